@@ -0,0 +1,56 @@
+//! Times the consensus of many small UMI families, each with a fresh `try_poa_consensus` call and
+//! with one reused `ConsensusEngine`.
+//!
+//! Run with `cargo bench --bench consensus_engine`; the number of families can be given as an
+//! argument.
+
+use std::time::{Duration, Instant};
+
+use rust_spoa::simulate::{random_sequence, ReadSimulator};
+use rust_spoa::{try_poa_consensus, ConsensusEngine, PoaParams};
+
+// the families of 2 to 8 reads of a 150 base template, as short reads of UMI families are
+fn families(count: usize) -> Vec<Vec<Vec<u8>>> {
+    let mut simulator = ReadSimulator::new(1).error_rates(0.005, 0.001, 0.001);
+    (0..count)
+        .map(|family| {
+            let template = random_sequence(150, family as u64);
+            simulator.reads(&template, 2 + family % 7).into_iter().map(|read| [read, vec![0]].concat()).collect()
+        })
+        .collect()
+}
+
+// the best of three runs of f, as the time of one run
+fn time<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    let mut best = None;
+    let mut bases = 0;
+    for _ in 0..3 {
+        let start = Instant::now();
+        bases = f();
+        let elapsed = start.elapsed();
+        best = Some(best.map_or(elapsed, |best: Duration| best.min(elapsed)));
+    }
+    (best.unwrap_or_default(), bases)
+}
+
+fn main() {
+    // cargo bench passes --bench, which is not a count
+    let count = std::env::args().skip(1).find_map(|arg| arg.parse().ok()).unwrap_or(20_000);
+    let families = families(count);
+    let params = PoaParams::new(1, 5, -4, -8, -6);
+
+    let (one_shot, one_shot_bases) = time(|| {
+        families.iter().map(|reads| try_poa_consensus(reads, 0, &params).expect("the reads are valid").len()).sum()
+    });
+    let (engine, engine_bases) = time(|| {
+        let mut engine = ConsensusEngine::new(&params);
+        families.iter().map(|reads| engine.consensus(reads).len()).sum()
+    });
+    assert_eq!(one_shot_bases, engine_bases, "the engine and one-shot calls disagree");
+
+    let per_family = |elapsed: Duration| elapsed.as_secs_f64() * 1e6 / count as f64;
+    println!("{} families", count);
+    println!("try_poa_consensus  {:>10.3?}  {:>8.2} us/family", one_shot, per_family(one_shot));
+    println!("ConsensusEngine    {:>10.3?}  {:>8.2} us/family", engine, per_family(engine));
+    println!("speedup            {:>10.2}x", one_shot.as_secs_f64() / engine.as_secs_f64());
+}
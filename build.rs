@@ -1,35 +1,200 @@
 // build.rs
 
 extern crate cc;
-extern crate cmake;
 
-use cmake::Config;
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// the version of the spoa submodule, reported by the library
+const SPOA_VERSION: &str = "4.1.4";
+
+// the standard headers of spoa and poa_func.cpp, included before `throw` is redefined for builds
+// without exceptions
+const STD_HEADERS: [&str; 19] = [
+    "algorithm", "atomic", "cassert", "cctype", "cmath", "cstdint", "cstdlib", "exception", "fstream", "iostream", "limits", "map",
+    "memory", "stack", "stdexcept", "string", "unordered_set", "utility", "vector",
+];
 
 fn main() {
 
-    let dst = Config::new("src/spoa")
-           .define("CMAKE_BUILD_TYPE","Release")
-           .build();
+    // the pure-rust feature replaces the native code, so there is nothing to build
+    if env::var_os("CARGO_FEATURE_PURE_RUST").is_some() {
+        println!("cargo:rerun-if-changed=build.rs");
+        return;
+    }
+
+    // cc emits rerun-if-env-changed lines, which disable cargo's default change detection, so the
+    // native sources have to be listed explicitly
+    println!("cargo:rerun-if-changed=src/poa_func.cpp");
+    println!("cargo:rerun-if-changed=src/poa_func.h");
 
-    println!("cargo:rustc-link-search=native={}", dst.display());
-    println!("cargo:rustc-link-lib=static=spoa");
+    if env::var_os("CARGO_FEATURE_SYSTEM_SPOA").is_some() {
+        return build_with_system_spoa();
+    }
+    println!("cargo:rerun-if-changed=src/spoa");
 
+    // spoa generates its version header with CMake, which is replaced by the cc builds below
     let out_dir = env::var("OUT_DIR").unwrap();
-    println!("cargo:rustc-flags=-L {}/lib64/ -L {}/lib/", &out_dir, &out_dir);
+    fs::write(
+        Path::new(&out_dir).join("spoa_config.h"),
+        format!(
+            "#ifndef SPOA_CONFIG_H_\n#define SPOA_CONFIG_H_\n\nnamespace spoa {{\n\nconstexpr char SPOA_VERSION[] = \"{}\";\n\n}}  // namespace spoa\n\n#endif  // SPOA_CONFIG_H_\n",
+            SPOA_VERSION
+        ),
+    )
+    .unwrap();
+
+    // the library is built for the baseline of the target, so binaries run on any CPU of it. on
+    // x86 the SIMD alignment engine is compiled once more for each instruction set it supports,
+    // and poa_func.cpp picks one of them at runtime (see PoaParams::simd). the wasm feature
+    // builds the scalar engine alone, as WebAssembly needs
+    let target_arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap();
+    let wasm = env::var_os("CARGO_FEATURE_WASM").is_some();
+    if target_arch == "wasm32" && !wasm {
+        panic!("building rust-spoa for WebAssembly needs the `wasm` feature");
+    }
+    let x86_dispatch = !wasm && (target_arch == "x86_64" || target_arch == "x86");
+    let no_exceptions = if wasm { Some(no_exceptions_header(&out_dir)) } else { None };
+    let native = |build: &mut cc::Build| {
+        if let Some(header) = &no_exceptions {
+            build.flag("-fno-exceptions").flag("-include").flag(header);
+        }
+        if target_arch == "wasm32" {
+            wasm_sysroot(build);
+        }
+    };
+
+    let spoa = |name: &str, files: &[&str], arch_flag: Option<&str>| {
+        let mut build = cc::Build::new();
+        native(&mut build);
+        build
+            .cpp(true)
+            .warnings(false)
+            .flag_if_supported("-O3")
+            .flag_if_supported("-std=c++17")
+            .include("src/spoa/include")
+            .include("src/spoa/src")
+            .include(&out_dir);
+        // each instruction set defines its own spoa::Storage, a register, so it is renamed for
+        // the std::vector<Storage> code of the two SIMD builds not to be merged by the linker
+        if let Some(arch_flag) = arch_flag {
+            build.flag(arch_flag).define("Storage", Some(format!("Storage_{}", name).as_str()));
+        }
+        for file in files {
+            build.file(Path::new("src/spoa/src").join(file));
+        }
+        build.compile(name);
+    };
+
+    spoa(
+        "spoa",
+        &["alignment_engine.cpp", "graph.cpp", "simd_alignment_engine_dispatcher.cpp", "sisd_alignment_engine.cpp", "version.cpp"],
+        None,
+    );
+    if x86_dispatch {
+        spoa("spoa_avx2", &["simd_alignment_engine_dispatch.cpp"], Some("-mavx2"));
+        spoa("spoa_sse4_1", &["simd_alignment_engine_dispatch.cpp"], Some("-msse4.1"));
+    }
 
-    cc::Build::new()
+    let mut poa_func = cc::Build::new();
+    native(&mut poa_func);
+    poa_func
         .cpp(true)
-        .shared_flag(false)
-        .static_flag(true)
         .flag_if_supported("-O3")
         .flag_if_supported("-D_GNU_SOURCE")
         .flag_if_supported("-Wall")
-        .flag_if_supported("-std=c++11")
-        .flag_if_supported("-Isrc/spoa/include")
-        .flag_if_supported(&format!("-L{}/lib64 -L{}/lib", &out_dir, &out_dir))
-        .flag_if_supported("-lspoa")
-        .file("src/poa_func.cpp")
-        .compile("poa_func");
+        .flag_if_supported("-std=c++17")
+        .include("src/spoa/include")
+        .include("src/spoa/src");
+    if x86_dispatch {
+        poa_func.define("POA_X86_DISPATCH", None);
+    }
+    poa_func.file("src/poa_func.cpp").compile("poa_func");
+
+}
+
+// writes the header included first by builds without C++ exceptions, as WebAssembly has none, and
+// returns its path. a throw aborts there, as an exception escaping into Rust does otherwise, and
+// the handlers of spoa, which only turn a failed allocation into another exception, are dropped.
+fn no_exceptions_header(out_dir: &str) -> String {
+    let path = Path::new(out_dir).join("poa_no_exceptions.h");
+    let includes: String = STD_HEADERS.iter().map(|header| format!("#include <{}>\n", header)).collect();
+    fs::write(&path, format!("#ifndef POA_NO_EXCEPTIONS_H_\n#define POA_NO_EXCEPTIONS_H_\n\n{}\n#define throw std::abort(),\n#define try if (true)\n#define catch(...) else if (false)\n\n#endif  // POA_NO_EXCEPTIONS_H_\n", includes))
+        .unwrap();
+    path.display().to_string()
+}
+
+// compiles for WebAssembly against the C++ standard library of the WASI sysroot in WASI_SYSROOT,
+// which cc links for the wasi targets. wasm32-unknown-unknown has no C library of its own, so it
+// is built as for WASI and links the libraries of the sysroot here; the module then imports the
+// few WASI functions they call, which a browser provides with a WASI shim.
+fn wasm_sysroot(build: &mut cc::Build) {
+    println!("cargo:rerun-if-env-changed=WASI_SYSROOT");
+    if env::var("CARGO_CFG_TARGET_OS").unwrap() == "wasi" {
+        return;
+    }
+    let sysroot = env::var("WASI_SYSROOT").expect("building for wasm32-unknown-unknown needs a WASI sysroot in WASI_SYSROOT");
+    build.cpp_link_stdlib(None).flag("--target=wasm32-wasip1").flag(format!("--sysroot={}", sysroot));
+    println!("cargo:rustc-link-search=native={}/lib/wasm32-wasip1", sysroot);
+    for library in ["c++", "c++abi", "c"].iter() {
+        println!("cargo:rustc-link-lib=static={}", library);
+    }
+}
 
+// finds an installed spoa library, from SPOA_INCLUDE_DIR and SPOA_LIB_DIR if set or else through
+// pkg-config, emitting the lines linking it. returns its include directories.
+fn system_spoa() -> Vec<PathBuf> {
+    println!("cargo:rerun-if-env-changed=SPOA_INCLUDE_DIR");
+    println!("cargo:rerun-if-env-changed=SPOA_LIB_DIR");
+
+    if let (Some(include_dir), Some(lib_dir)) = (env::var_os("SPOA_INCLUDE_DIR"), env::var_os("SPOA_LIB_DIR")) {
+        println!("cargo:rustc-link-search=native={}", Path::new(&lib_dir).display());
+        println!("cargo:rustc-link-lib=spoa");
+        return vec![PathBuf::from(include_dir)];
+    }
+    pkg_config_spoa()
+}
+
+#[cfg(feature = "system-spoa")]
+fn pkg_config_spoa() -> Vec<PathBuf> {
+    match pkg_config::Config::new().range_version("4.0.0".."5.0.0").probe("spoa") {
+        Ok(library) => library.include_paths,
+        Err(error) => panic!(
+            "the `system-spoa` feature needs spoa 4.x, found by pkg-config or given by SPOA_INCLUDE_DIR and SPOA_LIB_DIR: {}",
+            error
+        ),
+    }
+}
+
+#[cfg(not(feature = "system-spoa"))]
+fn pkg_config_spoa() -> Vec<PathBuf> {
+    unreachable!("the system library is only looked for with the `system-spoa` feature")
+}
+
+// builds poa_func.cpp against an installed spoa library instead of the bundled copy. an installed
+// library does not ship the headers of its alignment engines, so it picks their instruction set
+// itself, and poa_func.cpp only uses its public API.
+fn build_with_system_spoa() {
+    let include_dirs = system_spoa();
+
+    let mut poa_func = cc::Build::new();
+    poa_func
+        .cpp(true)
+        .flag_if_supported("-O3")
+        .flag_if_supported("-D_GNU_SOURCE")
+        .flag_if_supported("-Wall")
+        .flag_if_supported("-std=c++17")
+        .includes(&include_dirs)
+        .define("POA_SYSTEM_SPOA", None)
+        .file("src/poa_func.cpp");
+    // a header of another spoa version fails to compile against the calls of poa_func.cpp, which
+    // the FFI of the crate is declared for
+    if let Err(error) = poa_func.try_compile("poa_func") {
+        panic!(
+            "the spoa headers in {:?} do not match the spoa 4 API rust-spoa is built against ({}); install spoa 4.x or \
+             build the bundled copy without the `system-spoa` feature",
+            include_dirs, error
+        );
+    }
 }
@@ -0,0 +1,151 @@
+//! Unit-cost global pairwise alignment, used to compare consensus sequences with each other
+//! and with known truth sequences.
+
+/// A single column of a pairwise alignment of `a` against `b`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AlignOp {
+    /// a base of `a` aligned to an identical base of `b`
+    Match,
+    /// a base of `a` aligned to a different base of `b`
+    Mismatch,
+    /// a base of `a` with no counterpart in `b`
+    Insertion,
+    /// a base of `b` with no counterpart in `a`
+    Deletion,
+}
+
+/// Computes a global alignment of `a` against `b` minimizing the edit distance.
+///
+/// Ties are broken in favour of matches and mismatches, then insertions, so the result is
+/// deterministic.
+pub(crate) fn align(a: &[u8], b: &[u8]) -> Vec<AlignOp> {
+    let cols = b.len() + 1;
+    let mut dp = vec![0usize; (a.len() + 1) * cols];
+
+    for i in 0..=a.len() {
+        dp[i * cols] = i;
+    }
+    for (j, cell) in dp.iter_mut().enumerate().take(cols) {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let diagonal = dp[(i - 1) * cols + j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            let insertion = dp[(i - 1) * cols + j] + 1;
+            let deletion = dp[i * cols + j - 1] + 1;
+            dp[i * cols + j] = diagonal.min(insertion).min(deletion);
+        }
+    }
+
+    let mut ops = Vec::with_capacity(a.len().max(b.len()));
+    let (mut i, mut j) = (a.len(), b.len());
+    while i > 0 || j > 0 {
+        let score = dp[i * cols + j];
+        if i > 0 && j > 0 && score == dp[(i - 1) * cols + j - 1] + (a[i - 1] != b[j - 1]) as usize {
+            ops.push(if a[i - 1] == b[j - 1] { AlignOp::Match } else { AlignOp::Mismatch });
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && score == dp[(i - 1) * cols + j] + 1 {
+            ops.push(AlignOp::Insertion);
+            i -= 1;
+        } else {
+            ops.push(AlignOp::Deletion);
+            j -= 1;
+        }
+    }
+
+    ops.reverse();
+    ops
+}
+
+/// Computes an alignment of all of `a` against the part of `b` it fits best, minimizing the edit distance.
+///
+/// Returns the offset in `b` where the aligned part starts, and the alignment of `a` against
+/// that part. Ties are broken as in `align`, and in favour of the leftmost end in `b`.
+pub(crate) fn fit(a: &[u8], b: &[u8]) -> (usize, Vec<AlignOp>) {
+    let cols = b.len() + 1;
+    let mut dp = vec![0usize; (a.len() + 1) * cols];
+
+    // the first row is zero, so the alignment may start anywhere in b
+    for i in 0..=a.len() {
+        dp[i * cols] = i;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let diagonal = dp[(i - 1) * cols + j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            let insertion = dp[(i - 1) * cols + j] + 1;
+            let deletion = dp[i * cols + j - 1] + 1;
+            dp[i * cols + j] = diagonal.min(insertion).min(deletion);
+        }
+    }
+
+    let last_row = &dp[a.len() * cols..];
+    let mut j = last_row.iter().enumerate().min_by_key(|&(j, &score)| (score, j)).map_or(0, |(j, _)| j);
+    let mut i = a.len();
+    let mut ops = Vec::with_capacity(a.len());
+    while i > 0 {
+        let score = dp[i * cols + j];
+        if j > 0 && score == dp[(i - 1) * cols + j - 1] + (a[i - 1] != b[j - 1]) as usize {
+            ops.push(if a[i - 1] == b[j - 1] { AlignOp::Match } else { AlignOp::Mismatch });
+            i -= 1;
+            j -= 1;
+        } else if score == dp[(i - 1) * cols + j] + 1 {
+            ops.push(AlignOp::Insertion);
+            i -= 1;
+        } else {
+            ops.push(AlignOp::Deletion);
+            j -= 1;
+        }
+    }
+
+    ops.reverse();
+    (j, ops)
+}
+
+/// Returns the number of mismatches, insertions and deletions in `ops`.
+pub(crate) fn edit_distance(ops: &[AlignOp]) -> usize {
+    ops.iter().filter(|&&op| op != AlignOp::Match).count()
+}
+
+/// Returns the fraction of alignment columns that are matches, or 1 for an empty alignment.
+pub(crate) fn identity(ops: &[AlignOp]) -> f64 {
+    if ops.is_empty() {
+        return 1.0;
+    }
+    (ops.len() - edit_distance(ops)) as f64 / ops.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::AlignOp::*;
+
+    #[test]
+    fn test_align() {
+        assert_eq!(align(b"ACGT", b"ACGT"), vec![Match, Match, Match, Match]);
+        assert_eq!(align(b"ACGT", b"AGGT"), vec![Match, Mismatch, Match, Match]);
+        assert_eq!(align(b"ACGGT", b"ACGT"), vec![Match, Match, Insertion, Match, Match]);
+        assert_eq!(align(b"ACT", b"ACGT"), vec![Match, Match, Deletion, Match]);
+        assert_eq!(align(b"", b"AC"), vec![Deletion, Deletion]);
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit(b"CGT", b"AACGTAA"), (2, vec![Match, Match, Match]));
+        assert_eq!(fit(b"CGGT", b"AACGTAA"), (2, vec![Match, Insertion, Match, Match]));
+        assert_eq!(fit(b"ACGTAC", b"TTACGGTACTT"), (2, vec![Match, Match, Deletion, Match, Match, Match, Match]));
+        // of equally good fits, the one ending leftmost in b is chosen
+        assert_eq!(fit(b"CT", b"AACGTAA"), (2, vec![Match, Insertion]));
+        assert_eq!(fit(b"", b"AC"), (0, vec![]));
+    }
+
+    #[test]
+    fn test_edit_distance_and_identity() {
+        let ops = align(b"AATGCCCGTT", b"AATGCCGTT");
+        assert_eq!(edit_distance(&ops), 1);
+        assert!((identity(&ops) - 0.9).abs() < 1e-9);
+
+        assert_eq!(edit_distance(&align(b"AATGCCCGTT", b"ATTGCCCGAT")), 2);
+        assert_eq!(identity(&align(b"", b"")), 1.0);
+    }
+}
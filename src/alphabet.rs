@@ -0,0 +1,162 @@
+//! Checking the bytes of reads before they reach the alignment engine.
+
+use std::fmt;
+
+use crate::{PoaError, RejectReason, RejectedRead};
+
+/// A set of bytes reads may hold.
+///
+/// The null byte is never part of an alphabet, as it terminates reads.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Alphabet {
+    // one bit per byte value
+    allowed: [u64; 4],
+}
+
+impl Alphabet {
+    /// Returns the alphabet of the given bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::Alphabet;
+    ///
+    ///     let alphabet = Alphabet::new(b"ACGT");
+    ///     assert!(alphabet.contains(b'A'));
+    ///     assert!(!alphabet.contains(b'a'));
+    ///     assert_eq!(alphabet.first_invalid(b"ACGNT"), Some((3, b'N')));
+    /// ```
+    pub fn new(bytes: &[u8]) -> Alphabet {
+        let mut alphabet = Alphabet { allowed: [0; 4] };
+        for &byte in bytes.iter().filter(|&&byte| byte != 0) {
+            alphabet.allowed[usize::from(byte / 64)] |= 1 << (byte % 64);
+        }
+        alphabet
+    }
+
+    /// Returns the printable ASCII characters other than space, the alphabet of `try_poa_consensus`.
+    pub fn ascii() -> Alphabet {
+        Alphabet::new(&(b'!'..=b'~').collect::<Vec<u8>>())
+    }
+
+    /// Returns the bases `ACGT` and `N`, in upper and lower case.
+    pub fn dna() -> Alphabet {
+        Alphabet::new(b"ACGTNacgtn")
+    }
+
+    /// Returns the IUPAC nucleotide codes, including `U`, in upper and lower case.
+    pub fn iupac() -> Alphabet {
+        Alphabet::new(b"ACGTURYSWKMBDHVNacgturyswkmbdhvn")
+    }
+
+    /// Returns the one letter amino acid codes, including the ambiguity codes `BJZX`, selenocysteine
+    /// `U`, pyrrolysine `O` and the stop `*`, in upper and lower case.
+    pub fn protein() -> Alphabet {
+        Alphabet::new(b"ACDEFGHIKLMNOPQRSTUVWYBJZX*acdefghiklmnopqrstuvwybjzx")
+    }
+
+    /// Returns the 20 standard amino acids, the ambiguity codes `BZX` and the stop `*`, in upper and
+    /// lower case: the symbols BLOSUM62 scores, which `try_poa_consensus_protein` accepts.
+    pub fn amino_acids() -> Alphabet {
+        Alphabet::new(b"ACDEFGHIKLMNPQRSTVWYBZX*acdefghiklmnpqrstvwybzx")
+    }
+
+    /// Returns whether the alphabet holds the byte.
+    pub fn contains(&self, byte: u8) -> bool {
+        self.allowed[usize::from(byte / 64)] & (1 << (byte % 64)) != 0
+    }
+
+    /// Returns the position and value of the first byte of the read outside the alphabet, if any.
+    ///
+    /// A trailing null terminator is not checked.
+    pub fn first_invalid(&self, seq: &[u8]) -> Option<(usize, u8)> {
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        seq.iter().position(|&byte| !self.contains(byte)).map(|pos| (pos, seq[pos]))
+    }
+}
+
+impl Default for Alphabet {
+    fn default() -> Alphabet {
+        Alphabet::ascii()
+    }
+}
+
+impl fmt::Debug for Alphabet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<u8> = (0..=255).filter(|&byte| self.contains(byte)).collect();
+        write!(f, "Alphabet({:?})", String::from_utf8_lossy(&bytes))
+    }
+}
+
+/// Checks reads before aligning them, so no read reaches the alignment engine with bytes it cannot handle.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `alphabet` - the bytes the reads may hold
+///
+/// # Returns
+/// * returns `PoaError::InvalidRead` with `RejectReason::Empty` for the first read without bases,
+///   `PoaError::InvalidByte` for the first byte outside the alphabet, such as a null byte inside a
+///   read, and nothing if all reads are valid
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{validate_sequences, Alphabet, PoaError};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), "AATGCCGTTé".as_bytes().to_vec()];
+///     assert_eq!(validate_sequences(&seqs[..1], &Alphabet::dna()), Ok(()));
+///     assert_eq!(
+///         validate_sequences(&seqs, &Alphabet::dna()),
+///         Err(PoaError::InvalidByte { seq_index: 1, pos: 9, byte: 0xc3 })
+///     );
+/// ```
+pub fn validate_sequences<T: AsRef<[u8]>>(seqs: &[T], alphabet: &Alphabet) -> Result<(), PoaError> {
+    for (index, seq) in seqs.iter().enumerate() {
+        let seq = seq.as_ref();
+        if seq.strip_suffix(b"\0").unwrap_or(seq).is_empty() {
+            return Err(PoaError::InvalidRead(RejectedRead { index, reason: RejectReason::Empty }));
+        }
+        if let Some((pos, byte)) = alphabet.first_invalid(seq) {
+            return Err(PoaError::InvalidByte { seq_index: index, pos, byte });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alphabets() {
+        let ascii = Alphabet::default();
+        assert!((b'!'..=b'~').all(|byte| ascii.contains(byte)));
+        assert!(!ascii.contains(b' ') && !ascii.contains(0) && !ascii.contains(0x80) && !ascii.contains(0xff));
+
+        assert!(!Alphabet::new(b"\0A").contains(0));
+        assert!(Alphabet::new(&[0xff]).contains(0xff));
+        assert!(b"ACGTNacgtn".iter().all(|&byte| Alphabet::iupac().contains(byte)));
+        assert!(!Alphabet::dna().contains(b'R') && Alphabet::iupac().contains(b'R'));
+        assert!(Alphabet::protein().contains(b'W') && Alphabet::protein().contains(b'*'));
+        assert!(!Alphabet::amino_acids().contains(b'U') && Alphabet::protein().contains(b'U'));
+        let blosum62 = crate::Matrix::Blosum62.scores();
+        let symbols: Vec<u8> = blosum62.alphabet().iter().flat_map(|&symbol| vec![symbol, symbol.to_ascii_lowercase()]).collect();
+        assert_eq!(Alphabet::amino_acids(), Alphabet::new(&symbols));
+        assert_eq!(format!("{:?}", Alphabet::new(b"TGCA")), "Alphabet(\"ACGT\")");
+    }
+
+    #[test]
+    fn test_validate_sequences() {
+        let dna = Alphabet::dna();
+        assert_eq!(validate_sequences(&["ACGT", "acgtn"], &dna), Ok(()));
+        assert_eq!(validate_sequences(&Vec::<Vec<u8>>::new(), &dna), Ok(()));
+
+        assert_eq!(validate_sequences(&[&b"AC\0GT\0"[..]], &dna), Err(PoaError::InvalidByte { seq_index: 0, pos: 2, byte: 0 }));
+        assert_eq!(validate_sequences(&["ACGT", "ACXT"], &dna), Err(PoaError::InvalidByte { seq_index: 1, pos: 2, byte: b'X' }));
+        assert_eq!(
+            validate_sequences(&["ACGT", "\0"], &dna),
+            Err(PoaError::InvalidRead(RejectedRead { index: 1, reason: RejectReason::Empty }))
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! How ambiguous input bases, such as the `N` of a low-quality base call, are scored when they are
+//! aligned.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{PoaError, PoaParams};
+
+/// How pairs of bases with an IUPAC ambiguity code (`N`, `R`, `Y`, `S`, `W`, `K`, `M`, `B`, `D`, `H`
+/// or `V`) on either side are scored, as set by `PoaParams::ambiguous_bases`.
+///
+/// SPOA compares bases as bytes, so an `N` only matches another `N` and mismatches every base it
+/// stands for: a read with many `N`s aligns poorly and pulls the graph apart. The codes are those
+/// of nucleotides, so a policy other than `Literal` suits nucleotide reads only; a protein
+/// alphabet, whose letters include most of the codes, is scored as given with `Literal`. Codes
+/// are matched in the case of the bases they are paired with, as the case policy leaves them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AmbiguousBases {
+    /// codes are compared as any other symbol, matching only themselves
+    #[default]
+    Literal,
+    /// a pair with a code scores 0, so it neither supports nor penalises an alignment
+    Neutral,
+    /// a pair with a code scores as the best pair of the bases the codes stand for, so `N` matches
+    /// any base and `R` matches `A` and `G`
+    Wildcard,
+    /// a pair with a code scores the lowest score of the engine, the mismatch score or the lowest
+    /// score of a substitution matrix, so even `N` against `N` is a mismatch
+    Mismatch,
+}
+
+impl AmbiguousBases {
+    /// Returns a short lower-case name for the policy, as `str::parse` reads it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AmbiguousBases::Literal => "literal",
+            AmbiguousBases::Neutral => "neutral",
+            AmbiguousBases::Wildcard => "wildcard",
+            AmbiguousBases::Mismatch => "mismatch",
+        }
+    }
+
+    // the POA_AMBIGUITY_ value of poa_func.h
+    pub(crate) fn code(self) -> i32 {
+        match self {
+            AmbiguousBases::Literal => 0,
+            AmbiguousBases::Neutral => 1,
+            AmbiguousBases::Wildcard => 2,
+            AmbiguousBases::Mismatch => 3,
+        }
+    }
+
+    // the score of aligning the graph base a with the sequence base b with the match and mismatch
+    // scores of params, as the native engine scores it
+    pub(crate) fn score(self, params: &PoaParams, a: u8, b: u8) -> i32 {
+        let literal = if a == b { params.match_score } else { params.mismatch_score };
+        let ((a_set, a_code), (b_set, b_code)) = (nucleotides(a), nucleotides(b));
+        if !a_code && !b_code {
+            return literal;
+        }
+        match self {
+            AmbiguousBases::Literal => literal,
+            AmbiguousBases::Neutral => 0,
+            AmbiguousBases::Wildcard if a_set & b_set != 0 && a.is_ascii_lowercase() == b.is_ascii_lowercase() => params.match_score,
+            AmbiguousBases::Wildcard => params.mismatch_score,
+            AmbiguousBases::Mismatch => params.match_score.min(params.mismatch_score),
+        }
+    }
+}
+
+// the nucleotides a symbol stands for, with A, C, G and T as bits 0 to 3, and whether it is an
+// ambiguity code
+fn nucleotides(symbol: u8) -> (u8, bool) {
+    let set = match symbol.to_ascii_uppercase() {
+        b'A' => 0b0001,
+        b'C' => 0b0010,
+        b'G' => 0b0100,
+        b'T' => 0b1000,
+        b'M' => 0b0011,
+        b'R' => 0b0101,
+        b'W' => 0b1001,
+        b'S' => 0b0110,
+        b'Y' => 0b1010,
+        b'K' => 0b1100,
+        b'V' => 0b0111,
+        b'H' => 0b1011,
+        b'D' => 0b1101,
+        b'B' => 0b1110,
+        b'N' => 0b1111,
+        _ => 0,
+    };
+    (set, set.count_ones() > 1)
+}
+
+impl fmt::Display for AmbiguousBases {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AmbiguousBases {
+    type Err = PoaError;
+
+    fn from_str(s: &str) -> Result<AmbiguousBases, PoaError> {
+        match s {
+            "literal" => Ok(AmbiguousBases::Literal),
+            "neutral" => Ok(AmbiguousBases::Neutral),
+            "wildcard" => Ok(AmbiguousBases::Wildcard),
+            "mismatch" => Ok(AmbiguousBases::Mismatch),
+            _ => Err(PoaError::InvalidPreset(format!("unknown ambiguous base scoring {}", s))),
+        }
+    }
+}
+
+impl PoaParams {
+    /// Sets how pairs with an ambiguous base are scored.
+    ///
+    /// Every scoring but `AmbiguousBases::Literal` aligns with the scalar engine, whatever `simd`
+    /// asks for, and does not support convex gaps, which `validate` reports.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{AmbiguousBases, PoaGraph, PoaParams};
+    ///
+    ///     let params = PoaParams::dna().ambiguous_bases(AmbiguousBases::Wildcard);
+    ///     assert_eq!(params.to_string().parse::<PoaParams>(), Ok(params));
+    ///
+    ///     let mut graph = PoaGraph::new(&params);
+    ///     graph.add_sequence(b"GATTACAGGC");
+    ///     // the two Ns align to the T and G they stand in for
+    ///     assert_eq!(graph.alignment_score(b"GATNACAGNC"), 50);
+    /// ```
+    pub fn ambiguous_bases(mut self, ambiguous_bases: AmbiguousBases) -> PoaParams {
+        self.ambiguous_bases = ambiguous_bases;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{poa_consensus_unbounded, ConvexGaps, PoaGraph};
+
+    #[test]
+    fn test_scores() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        assert_eq!(AmbiguousBases::Literal.score(&params, b'N', b'N'), 5);
+        assert_eq!(AmbiguousBases::Literal.score(&params, b'N', b'A'), -4);
+        assert_eq!(AmbiguousBases::Neutral.score(&params, b'A', b'N'), 0);
+        assert_eq!(AmbiguousBases::Neutral.score(&params, b'A', b'A'), 5);
+        assert_eq!(AmbiguousBases::Wildcard.score(&params, b'R', b'G'), 5);
+        assert_eq!(AmbiguousBases::Wildcard.score(&params, b'R', b'Y'), -4);
+        assert_eq!(AmbiguousBases::Wildcard.score(&params, b'n', b'a'), 5);
+        assert_eq!(AmbiguousBases::Wildcard.score(&params, b'N', b'a'), -4);
+        assert_eq!(AmbiguousBases::Mismatch.score(&params, b'N', b'N'), -4);
+        for policy in [AmbiguousBases::Literal, AmbiguousBases::Neutral, AmbiguousBases::Wildcard, AmbiguousBases::Mismatch].iter() {
+            assert_eq!(policy.to_string().parse::<AmbiguousBases>(), Ok(*policy));
+        }
+        assert!("wild".parse::<AmbiguousBases>().is_err());
+    }
+
+    #[test]
+    fn test_rust_alignment_agrees() {
+        let reads: [&[u8]; 4] = [b"GATTACAGGCTTACCG", b"GATNACAGGCNTACCG", b"GATTACAGRCTTACCG", b"GANNNNAGGCTTACCG"];
+        for policy in [AmbiguousBases::Literal, AmbiguousBases::Neutral, AmbiguousBases::Wildcard, AmbiguousBases::Mismatch].iter() {
+            let params = PoaParams::new(1, 5, -4, -8, -6).ambiguous_bases(*policy);
+            // a band as wide as the reads aligns in Rust as the native engine does
+            let mut native = PoaGraph::new(&params);
+            let mut banded = PoaGraph::new(&params.band_width(crate::BandWidth::Fixed(32)));
+            for read in reads.iter() {
+                native.add_sequence(read);
+                banded.add_sequence(read);
+            }
+            assert_eq!(native.multiple_sequence_alignment(true), banded.multiple_sequence_alignment(true), "{}", policy);
+        }
+    }
+
+    #[test]
+    fn test_wildcard_consensus() {
+        // aligned literally, each N of a read costs it a mismatch
+        let reads = ["GATTACAGGCTTACCG\0", "GATTACNNNNNNACCG\0", "GATTACAGGCTTACCG\0"];
+        let scores = |policy: AmbiguousBases| {
+            let mut graph = PoaGraph::new(&PoaParams::dna().ambiguous_bases(policy));
+            graph.add_sequence(b"GATTACAGGCTTACCG");
+            [b"GATTACNNNNNNACCG".as_ref(), b"GATTRCAGGCTTACCG"].iter().map(|read| graph.alignment_score(read)).collect::<Vec<_>>()
+        };
+        assert_eq!(scores(AmbiguousBases::Literal), vec![26, 71]);
+        assert_eq!(scores(AmbiguousBases::Neutral), vec![50, 75]);
+        assert_eq!(scores(AmbiguousBases::Wildcard), vec![80, 80]);
+        assert_eq!(scores(AmbiguousBases::Mismatch), vec![26, 71]);
+        let wildcard = poa_consensus_unbounded(&reads, &PoaParams::dna().ambiguous_bases(AmbiguousBases::Wildcard));
+        assert_eq!(wildcard, b"GATTACAGGCTTACCG".to_vec());
+
+        // a matrix is rescored the same way
+        let matrix = crate::SubstitutionMatrix::uniform(b"ACGT", 5, -4).unwrap();
+        let mut graph = PoaGraph::with_matrix(&PoaParams::dna().ambiguous_bases(AmbiguousBases::Wildcard), &matrix);
+        graph.add_sequence(b"GATTACAGGCTTACCG");
+        assert_eq!(graph.alignment_score(b"GATTACNNNNNNACCG"), 80);
+
+        let convex = PoaParams::dna().convex_gaps(ConvexGaps::new(-10, -1)).ambiguous_bases(AmbiguousBases::Neutral);
+        assert!(matches!(convex.validate(), Err(PoaError::InvalidScores(_))));
+        assert!(PoaGraph::new(&PoaParams::dna().ambiguous_bases(AmbiguousBases::Mismatch)).simd() == crate::SimdBackend::Scalar);
+    }
+}
@@ -0,0 +1,204 @@
+//! Consensus jobs for async services, run on the blocking thread pool of tokio so the native
+//! alignments do not stall the threads driving other tasks.
+
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+use tokio::task;
+
+use crate::{Consensus, ConsensusExt, PoaError, PoaParams};
+
+/// Generates the consensus of the reads on the blocking thread pool of the current tokio runtime,
+/// as `ConsensusExt::consensus` does.
+///
+/// Reads may carry a null terminator or not; empty reads are left out. Every call runs at once, so
+/// a burst of requests can occupy as many blocking threads as there are calls; `ConsensusJobs`
+/// bounds the number of jobs running together.
+/// # Arguments
+///
+/// * `seqs` - the reads, moved to the blocking thread
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus, `PoaError::EmptyInput` if no read has bases, and the error of
+///   `params.validate()` if it fails
+///
+/// # Panics
+/// Panics outside a tokio runtime, and with the panic of the job if it panicked.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_async, PoaParams};
+///
+///     let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+///     let reads = vec!["AATGCCCGTT", "AATGCCGTT", "AATGCCCGTT"];
+///     let consensus = runtime.block_on(poa_consensus_async(reads, PoaParams::new(1, 5, -4, -3, -1))).unwrap();
+///     assert_eq!(consensus.as_str().unwrap(), "AATGCCCGTT");
+/// ```
+pub async fn poa_consensus_async<T>(seqs: Vec<T>, params: PoaParams) -> Result<Consensus, PoaError>
+where
+    T: AsRef<[u8]> + Send + 'static,
+{
+    params.validate()?;
+    blocking(move || seqs.consensus(&params)).await
+}
+
+// runs a job on the blocking thread pool, resuming its panic if it panicked
+async fn blocking<F>(job: F) -> Result<Consensus, PoaError>
+where
+    F: FnOnce() -> Result<Consensus, PoaError> + Send + 'static,
+{
+    match task::spawn_blocking(job).await {
+        Ok(consensus) => consensus,
+        Err(error) => std::panic::resume_unwind(error.into_panic()),
+    }
+}
+
+/// A bound on the number of consensus jobs running on the blocking thread pool together, shared by
+/// its clones, e.g. by the handlers of a web service.
+///
+/// Jobs beyond the bound wait, without holding a thread, until a running job finishes. A job
+/// counts towards the bound until its alignments finish, even if the future awaiting it is
+/// dropped, e.g. when a client disconnects or a timeout expires, as the blocking thread cannot be
+/// stopped.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{ConsensusJobs, PoaParams};
+///
+///     let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+///     let jobs = ConsensusJobs::new(2);
+///     let params = PoaParams::new(1, 5, -4, -3, -1);
+///
+///     let consensuses = runtime.block_on(async {
+///         let first = jobs.consensus(vec!["AATGCCCGTT", "AATGCCCGTT"], params);
+///         let second = jobs.consensus(vec!["GATTACA", "GATTACA"], params);
+///         (first.await, second.await)
+///     });
+///     assert_eq!(consensuses.1.unwrap().as_str().unwrap(), "GATTACA");
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConsensusJobs {
+    permits: Arc<Semaphore>,
+}
+
+impl ConsensusJobs {
+    /// Creates a bound of `max_jobs` jobs running together.
+    ///
+    /// # Panics
+    /// Panics if `max_jobs` is 0.
+    pub fn new(max_jobs: usize) -> ConsensusJobs {
+        assert!(max_jobs > 0, "at least one consensus job must be able to run");
+        ConsensusJobs { permits: Arc::new(Semaphore::new(max_jobs)) }
+    }
+
+    /// Returns the number of jobs that can start without waiting.
+    pub fn available(&self) -> usize {
+        self.permits.available_permits()
+    }
+
+    /// Generates the consensus of the reads as `poa_consensus_async` does, once fewer than the
+    /// bound of jobs are running.
+    pub async fn consensus<T>(&self, seqs: Vec<T>, params: PoaParams) -> Result<Consensus, PoaError>
+    where
+        T: AsRef<[u8]> + Send + 'static,
+    {
+        params.validate()?;
+        // the semaphore is never closed, so acquiring only waits. The permit moves into the job,
+        // so it is released when the job finishes rather than when this future is dropped
+        let permit = self.permits.clone().acquire_owned().await.expect("the semaphore of the jobs was closed");
+        blocking(move || {
+            let _permit = permit;
+            seqs.consensus(&params)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Condvar, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use tokio::runtime::Builder;
+
+    // a read whose bases can only be read once its gate is opened, holding a job on its thread
+    #[derive(Clone)]
+    struct GatedRead(Arc<(Mutex<bool>, Condvar)>, Vec<u8>);
+
+    impl AsRef<[u8]> for GatedRead {
+        fn as_ref(&self) -> &[u8] {
+            let (open, opened) = &*self.0;
+            let _open = opened.wait_while(open.lock().unwrap(), |open| !*open).unwrap();
+            &self.1
+        }
+    }
+
+    #[test]
+    fn test_consensus_async() {
+        let runtime = Builder::new_multi_thread().worker_threads(2).build().unwrap();
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let jobs = ConsensusJobs::new(2);
+
+        let results = runtime.block_on(async {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let jobs = jobs.clone();
+                    let reads = if i % 2 == 0 { vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCGTT\0".to_vec()] } else { vec![b"GATTACA".to_vec()] };
+                    tokio::spawn(async move { jobs.consensus(reads, params).await })
+                })
+                .collect();
+            let mut results = vec![];
+            for handle in handles {
+                results.push(handle.await.unwrap().unwrap());
+            }
+            results
+        });
+        for (i, consensus) in results.iter().enumerate() {
+            assert_eq!(consensus.as_str().unwrap(), if i % 2 == 0 { "AATGCCCGTT" } else { "GATTACA" });
+        }
+        assert_eq!(jobs.available(), 2);
+    }
+
+    #[test]
+    fn test_dropped_job_keeps_its_permit() {
+        let runtime = Builder::new_multi_thread().worker_threads(1).build().unwrap();
+        let jobs = ConsensusJobs::new(1);
+        let gate = Arc::new((Mutex::new(false), Condvar::new()));
+        let reads = vec![GatedRead(gate.clone(), b"GATTACA".to_vec()); 2];
+
+        runtime.block_on(async {
+            let job = tokio::spawn({
+                let jobs = jobs.clone();
+                async move { jobs.consensus(reads, PoaParams::dna()).await }
+            });
+            while jobs.available() > 0 {
+                task::yield_now().await;
+            }
+            job.abort();
+            assert!(job.await.unwrap_err().is_cancelled());
+        });
+
+        // the job is still running on its blocking thread, so it keeps its permit until it finishes
+        let available = jobs.available();
+        *gate.0.lock().unwrap() = true;
+        gate.1.notify_all();
+        assert_eq!(available, 0);
+        while jobs.available() == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(jobs.available(), 1);
+    }
+
+    #[test]
+    fn test_errors() {
+        let runtime = Builder::new_current_thread().build().unwrap();
+        let empty: Vec<&str> = vec!["", "\0"];
+        assert_eq!(runtime.block_on(poa_consensus_async(empty, PoaParams::dna())), Err(PoaError::EmptyInput));
+        let invalid = runtime.block_on(poa_consensus_async(vec!["ACGT"], PoaParams::new(3, 5, -4, -3, -1)));
+        assert_eq!(invalid, Err(PoaError::InvalidAlignmentType(3)));
+    }
+}
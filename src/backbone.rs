@@ -0,0 +1,674 @@
+//! Building a consensus on a known backbone sequence, such as the target of a capture panel, and
+//! correcting a backbone by the reads aligned to it.
+
+use std::ops::Range;
+
+use crate::align::{fit, AlignOp};
+use crate::graph::{row_alignment, Topology};
+use crate::reject::{validate_read, ConsensusReport, RejectedRead};
+use crate::{PoaGraph, PoaParams, ReadAlignment};
+
+/// Generates a consensus sequence seeded with a backbone, excluding reads too different from it.
+///
+/// The backbone is added to the graph first and so takes part in the consensus; each read is then
+/// added only if its identity to the backbone, measured on its graph alignment, is at least
+/// `min_identity`. Invalid reads are rejected as by `poa_consensus_with_report`.
+/// # Arguments
+///
+/// * `backbone` - the expected sequence, without a null terminator
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `min_identity` - the lowest identity to the backbone, between 0 and 1, of the reads to keep
+///
+/// # Returns
+/// * returns the consensus of the backbone and the accepted reads, with the rejected reads and their reasons
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_on_backbone, PoaParams, RejectReason};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"GGCATTAGAC\0".to_vec(), b"AATGCCGTT\0".to_vec()];
+///     let report = poa_consensus_on_backbone(b"AATGCCCGTT", &seqs, 20, &PoaParams::new(1, 5, -4, -3, -1), 0.8);
+///
+///     assert_eq!(report.consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(report.reads_used, 2);
+///     assert_eq!(report.rejected[0].index, 1);
+///     assert_eq!(report.rejected[0].reason, RejectReason::LowIdentity);
+/// ```
+pub fn poa_consensus_on_backbone(
+    backbone: &[u8],
+    seqs: &[Vec<u8>],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    min_identity: f64
+) -> ConsensusReport {
+
+    let mut graph = PoaGraph::new(params);
+    graph.add_sequence(backbone);
+    let mut rejected = vec![];
+
+    for (index, seq) in seqs.iter().enumerate() {
+        let result = match validate_read(seq) {
+            Some(reason) => Err(reason),
+            None => graph.add_sequence_min_identity(&seq[..seq.len() - 1], min_identity).map(|_| ()),
+        };
+        if let Err(reason) = result {
+            rejected.push(RejectedRead { index, reason });
+        }
+    }
+
+    let mut consensus = graph.consensus();
+    if consensus_max_length > 0 {
+        consensus.truncate(consensus_max_length);
+    }
+
+    ConsensusReport { consensus, reads_used: graph.num_sequences() - 1, rejected }
+}
+
+/// Generates a consensus sequence seeded with a backbone, aligning each read only around its known position on the backbone.
+///
+/// `offsets` holds the approximate backbone position of the first base of each read, e.g. from a
+/// read mapper; each read is aligned to the graph between `slack` bases before its offset and
+/// `slack` bases after its end, as by `PoaGraph::add_sequence_anchored`. Reads tiling a long
+/// amplicon at different positions are then aligned in time proportional to their own length,
+/// and a read cannot be placed on a similar stretch elsewhere on the backbone. Semi-global
+/// alignment is the mode to use. Invalid reads are rejected as by `poa_consensus_with_report`.
+/// # Arguments
+///
+/// * `backbone` - the expected sequence, without a null terminator
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `offsets` - the backbone position of the start of each sequence
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `slack` - how far past its offset and end each sequence may align
+///
+/// # Returns
+/// * returns the consensus of the backbone and the reads, with the rejected reads and their reasons
+///
+/// # Panics
+/// Panics if `offsets` and `seqs` differ in length.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_on_backbone_anchored, PoaParams};
+///
+///     let backbone = b"AATGCCCGTTAGGCATTACGTTGACCAGTA";
+///     let seqs = vec![b"AATGCCCGATAGGC\0".to_vec(), b"CCGATAGGCATTACG\0".to_vec(), b"ATTACGTTGACCAGTA\0".to_vec()];
+///     let report = poa_consensus_on_backbone_anchored(backbone, &seqs, &[0, 5, 14], 40, &PoaParams::new(2, 5, -4, -3, -1), 5);
+///
+///     assert_eq!(report.consensus, b"AATGCCCGATAGGCATTACGTTGACCAGTA".to_vec());
+///     assert_eq!(report.reads_used, 3);
+/// ```
+pub fn poa_consensus_on_backbone_anchored(
+    backbone: &[u8],
+    seqs: &[Vec<u8>],
+    offsets: &[usize],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    slack: usize
+) -> ConsensusReport {
+    assert_eq!(seqs.len(), offsets.len(), "one offset is needed per sequence");
+
+    let mut graph = PoaGraph::new(params);
+    graph.add_sequence(backbone);
+    let mut rejected = vec![];
+
+    for (index, (seq, &offset)) in seqs.iter().zip(offsets).enumerate() {
+        match validate_read(seq) {
+            Some(reason) => rejected.push(RejectedRead { index, reason }),
+            None => graph.add_sequence_anchored(&seq[..seq.len() - 1], offset, slack),
+        }
+    }
+
+    let mut consensus = graph.consensus();
+    if consensus_max_length > 0 {
+        consensus.truncate(consensus_max_length);
+    }
+
+    ConsensusReport { consensus, reads_used: graph.num_sequences() - 1, rejected }
+}
+
+// the fewest matching bases, and the lowest identity, of the part of a read fitting a region for
+// the read to count as overlapping the region rather than matching by chance
+const MIN_REGION_MATCHES: usize = 5;
+const MIN_REGION_IDENTITY: f64 = 0.75;
+
+// returns the part of the read between its first and last base matching the region in the best
+// fit of the region against the read, if it overlaps the region
+fn region_segment<'a>(window: &[u8], read: &'a [u8]) -> Option<&'a [u8]> {
+    let (start, ops) = fit(window, read);
+    let first = ops.iter().position(|&op| op == AlignOp::Match)?;
+    let last = ops.iter().rposition(|&op| op == AlignOp::Match)?;
+    let core = &ops[first..=last];
+
+    let matches = core.iter().filter(|&&op| op == AlignOp::Match).count();
+    if matches < MIN_REGION_MATCHES || (matches as f64) < MIN_REGION_IDENTITY * core.len() as f64 {
+        return None;
+    }
+    let skipped = ops[..first].iter().filter(|&&op| op != AlignOp::Insertion).count();
+    let len = core.iter().filter(|&&op| op != AlignOp::Insertion).count();
+    Some(&read[start + skipped..start + skipped + len])
+}
+
+/// The consensus of a region of a backbone.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionConsensus {
+    /// the consensus of the region
+    pub consensus: Vec<u8>,
+    /// the number of reads supporting each consensus base, not counting the backbone
+    pub depth: Vec<u32>,
+    /// the number of reads overlapping the region
+    pub reads_used: usize,
+    /// the excluded reads, in input order
+    pub rejected: Vec<RejectedRead>,
+}
+
+/// Generates the consensus of one region of a backbone, from just the parts of the reads within it.
+///
+/// Each read is clipped to the part that best fits the region, by a unit-cost alignment of the
+/// region against the read, and only the clipped parts are aligned to a graph seeded with the
+/// region. This is much cheaper than building the graph of whole reads when the region is short.
+/// Reads whose fitting part has fewer than five matching bases or less than 75% identity are
+/// taken not to overlap the region and are left out without being rejected; invalid reads are
+/// rejected as by `poa_consensus_with_report`.
+/// # Arguments
+///
+/// * `backbone` - the expected sequence, without a null terminator
+/// * `region` - the range of backbone positions to build the consensus of
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the region with the depth of each of its bases
+///
+/// # Panics
+/// Panics if the region is not within the backbone.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_on_backbone_region, PoaParams};
+///
+///     let seqs = vec![b"TTGACAATGCCCGTTGCAT\0".to_vec(), b"TTGACAATGCCGTTGCAT\0".to_vec(), b"ACAATGCCCGTTGC\0".to_vec()];
+///     let region = poa_consensus_on_backbone_region(b"TTGACAATGCCCGTTGCAT", 5..15, &seqs, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(region.consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(region.reads_used, 3);
+/// ```
+pub fn poa_consensus_on_backbone_region(
+    backbone: &[u8],
+    region: Range<usize>,
+    seqs: &[Vec<u8>],
+    params: &PoaParams
+) -> RegionConsensus {
+    assert!(region.start <= region.end && region.end <= backbone.len(), "region outside the backbone");
+    let window = &backbone[region];
+
+    let mut graph = PoaGraph::new(params);
+    graph.add_sequence(window);
+    let mut rejected = vec![];
+
+    for (index, seq) in seqs.iter().enumerate() {
+        if let Some(reason) = validate_read(seq) {
+            rejected.push(RejectedRead { index, reason });
+            continue;
+        }
+        if let Some(segment) = region_segment(window, &seq[..seq.len() - 1]) {
+            graph.add_sequence(segment);
+        }
+    }
+
+    let nodes = graph.nodes();
+    let on_backbone = if window.is_empty() { vec![] } else { graph.sequence_path(0) };
+    let path = graph.consensus_path();
+    let consensus = path.iter().map(|&id| nodes[id as usize].base).collect();
+    let depth = path.iter().map(|&id| nodes[id as usize].coverage - on_backbone.contains(&id) as u32).collect();
+
+    RegionConsensus { consensus, depth, reads_used: graph.num_sequences().saturating_sub(1), rejected }
+}
+
+/// A change the consensus makes to the backbone, in the manner of a VCF record: the backbone bases
+/// from `backbone_pos` on are replaced by the consensus bases.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Correction {
+    /// the position on the backbone of the first replaced base, or of the base an insertion comes before
+    pub backbone_pos: usize,
+    /// the replaced backbone bases, empty for an insertion
+    pub backbone: Vec<u8>,
+    /// the consensus bases replacing them, empty for a deletion
+    pub consensus: Vec<u8>,
+}
+
+/// A backbone corrected by the reads aligned to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolishedBackbone {
+    /// the corrected backbone
+    pub consensus: Vec<u8>,
+    /// the changes from the backbone to the consensus, in backbone order
+    pub corrections: Vec<Correction>,
+    /// the number of reads aligned to the backbone, not counting the backbone
+    pub reads_used: usize,
+}
+
+impl PoaGraph {
+    /// Returns the consensus of the graph between the first and the last base of the sequence at
+    /// `backbone`, with the changes it makes to that sequence.
+    ///
+    /// The consensus is the heaviest path from the first to the last node of the backbone, chosen
+    /// as SPOA chooses the heaviest path of the whole graph, so it neither extends past the ends of
+    /// the backbone nor is cut short by reads covering only part of it. Changes are found by the
+    /// alignment columns of the graph, so a substitution replaces the base aligned to it.
+    ///
+    /// # Panics
+    /// Panics if `backbone` is not less than `num_sequences()`.
+    pub fn polish_backbone(&self, backbone: usize) -> PolishedBackbone {
+        let reads_used = self.num_sequences() - 1;
+        let backbone_path = self.sequence_path(backbone);
+        let (first, last) = match (backbone_path.first(), backbone_path.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return PolishedBackbone { consensus: vec![], corrections: vec![], reads_used },
+        };
+
+        // the heaviest in-edge of each node reachable from the first backbone node, ties going to
+        // the predecessor of the higher score, as in SPOA
+        let nodes = self.nodes();
+        let topology = Topology::new(nodes.len(), &self.edges());
+        let mut scores: Vec<Option<u64>> = vec![None; nodes.len()];
+        let mut predecessors: Vec<Option<u32>> = vec![None; nodes.len()];
+        scores[first as usize] = Some(0);
+        for &v in &topology.order {
+            if v == first {
+                continue;
+            }
+            let mut best: Option<(u64, u64, u32)> = None;
+            for &(u, weight) in &topology.in_edges[v as usize] {
+                if let Some(score) = scores[u as usize] {
+                    if !matches!(best, Some((best_weight, best_score, _)) if (best_weight, best_score) >= (weight, score)) {
+                        best = Some((weight, score, u));
+                    }
+                }
+            }
+            if let Some((weight, score, u)) = best {
+                scores[v as usize] = Some(score + weight);
+                predecessors[v as usize] = Some(u);
+            }
+        }
+        let mut path = vec![last];
+        while let Some(u) = predecessors[*path.last().unwrap() as usize] {
+            path.push(u);
+        }
+        path.reverse();
+        let consensus = path.iter().map(|&id| nodes[id as usize].base).collect();
+
+        PolishedBackbone { consensus, corrections: self.corrections(&backbone_path, &path), reads_used }
+    }
+
+    // the column of each node in the multiple sequence alignment msa of the graph, without the consensus
+    fn node_columns(&self, msa: &[Vec<u8>]) -> Vec<usize> {
+        let mut node_columns = vec![0; self.num_nodes()];
+        for (i, row) in msa.iter().enumerate() {
+            let columns = row.iter().enumerate().filter(|&(_, &base)| base != b'-').map(|(column, _)| column);
+            for (id, column) in self.sequence_path(i).into_iter().zip(columns) {
+                node_columns[id as usize] = column;
+            }
+        }
+        node_columns
+    }
+
+    // the changes from the bases along one path of the graph to those along another, by the
+    // alignment columns of their nodes
+    fn corrections(&self, from: &[u32], to: &[u32]) -> Vec<Correction> {
+        let node_columns = self.node_columns(&self.multiple_sequence_alignment(false));
+        let nodes = self.nodes();
+
+        let mut corrections = vec![];
+        let mut current: Option<Correction> = None;
+        let (mut i, mut j) = (0, 0);
+        while i < from.len() || j < to.len() {
+            let column = |path: &[u32], k: usize| path.get(k).map_or(usize::MAX, |&id| node_columns[id as usize]);
+            let (from_column, to_column) = (column(from, i), column(to, j));
+            if from_column == to_column && from[i] == to[j] {
+                corrections.extend(current.take());
+                i += 1;
+                j += 1;
+                continue;
+            }
+            let correction = current.get_or_insert_with(|| Correction { backbone_pos: i, backbone: vec![], consensus: vec![] });
+            if from_column <= to_column {
+                correction.backbone.push(nodes[from[i] as usize].base);
+                i += 1;
+            }
+            if to_column <= from_column {
+                correction.consensus.push(nodes[to[j] as usize].base);
+                j += 1;
+            }
+        }
+        corrections.extend(current);
+        corrections
+    }
+}
+
+/// A consensus guided by a reference, as returned by `poa_consensus_guided`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuidedConsensus {
+    /// the consensus of the graph seeded with the reference
+    pub consensus: Vec<u8>,
+    /// how the reference aligns to the consensus, as by `PoaGraph::read_alignments`: `I` marks
+    /// reference bases the consensus lacks and `D` consensus bases the reference lacks
+    pub reference_alignment: ReadAlignment,
+    /// the changes from the reference to the consensus, in reference order; insertions before
+    /// and after the reference are at positions 0 and its length
+    pub corrections: Vec<Correction>,
+    /// the number of reads added, not counting the reference
+    pub reads_used: usize,
+}
+
+impl PoaGraph {
+    /// Creates a graph seeded with a trusted reference, so the reads added to it are aligned to the
+    /// reference rather than to each other and the consensus is reference-guided.
+    ///
+    /// The reference is the first sequence of the graph and its bases weigh `weight` each. A
+    /// weight of 1 counts it as one more read; a weight of 0 only lays out the graph, so the reads
+    /// alone choose the consensus where they cover the reference, though the heaviest path still
+    /// runs on along the reference past them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::with_reference(&PoaParams::new(1, 5, -4, -8, -6), b"GATTACAGGCTTACCG", 0);
+    ///     graph.add_sequence(b"GATTACTGGCTTACCG");
+    ///     graph.add_sequence(b"GATTACTGGCTTACCG");
+    ///     assert_eq!(graph.num_sequences(), 3);
+    ///     assert_eq!(graph.consensus(), b"GATTACTGGCTTACCG".to_vec());
+    /// ```
+    pub fn with_reference(params: &PoaParams, reference: &[u8], weight: u32) -> PoaGraph {
+        let mut graph = PoaGraph::new(params);
+        graph.add_sequence_with_weight(reference, weight);
+        graph
+    }
+}
+
+/// Generates a reference-guided consensus: the graph is seeded with the reference, weighing
+/// `reference_weight` per base as by `PoaGraph::with_reference`, and the reads are added after it.
+///
+/// With a weight of 0 the consensus is cut to the bases some read covers, so the ends of the
+/// reference no read reaches are left out. Empty reads and trailing null terminators are ignored.
+/// # Arguments
+///
+/// * `reference` - the trusted reference, without a null terminator
+/// * `seqs` - the reads, with or without null terminators
+/// * `params` - the alignment and scoring parameters
+/// * `reference_weight` - the weight of each reference base, 0 for the reference to only lay out the graph
+///
+/// # Returns
+/// * returns the consensus with the alignment of the reference to it and the changes it makes to the reference
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_guided, Correction, PoaParams};
+///
+///     let reads = ["GATTACTGGCTTACCG\0", "GATTACTGGCTTACCG\0", "GATTACAGGCTTACCG\0"];
+///     let guided = poa_consensus_guided(b"GATTACAGGCTTACCG", &reads, &PoaParams::new(1, 5, -4, -8, -6), 1);
+///
+///     assert_eq!(guided.consensus, b"GATTACTGGCTTACCG".to_vec());
+///     assert_eq!(guided.reference_alignment.cigar, "6=1X9=");
+///     assert_eq!(guided.corrections, vec![Correction { backbone_pos: 6, backbone: b"A".to_vec(), consensus: b"T".to_vec() }]);
+///     assert_eq!(guided.reads_used, 3);
+/// ```
+pub fn poa_consensus_guided<T: AsRef<[u8]>>(reference: &[u8], seqs: &[T], params: &PoaParams, reference_weight: u32) -> GuidedConsensus {
+    let mut graph = PoaGraph::with_reference(params, reference, reference_weight);
+    for seq in seqs {
+        let seq = seq.as_ref();
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        if !seq.is_empty() {
+            graph.add_sequence(seq);
+        }
+    }
+
+    let mut path = graph.consensus_path();
+    let nodes = graph.nodes();
+    let on_reference = graph.sequence_path(0);
+    if reference_weight == 0 {
+        let covered = |id: &u32| nodes[*id as usize].coverage > on_reference.contains(id) as u32;
+        let first = path.iter().position(covered).unwrap_or(path.len());
+        let last = path.iter().rposition(covered).map_or(first, |last| last + 1);
+        path = path[first..last].to_vec();
+    }
+    let consensus = path.iter().map(|&id| nodes[id as usize].base).collect();
+
+    // the reference row of the msa against a row of the consensus bases in their columns
+    let msa = graph.multiple_sequence_alignment(false);
+    let columns = graph.node_columns(&msa);
+    let mut consensus_row = vec![b'-'; msa[0].len()];
+    for &id in &path {
+        consensus_row[columns[id as usize]] = nodes[id as usize].base;
+    }
+    let reference_alignment = row_alignment(&msa[0], &consensus_row, graph.sequence_score(0));
+    let corrections = graph.corrections(&on_reference, &path);
+    GuidedConsensus { consensus, reference_alignment, corrections, reads_used: graph.num_sequences() - 1 }
+}
+
+/// Corrects one of a set of reads, or any other draft sequence given with them, by the consensus
+/// of the others: SPOA as a polisher.
+///
+/// The sequence at `backbone` is added to the graph first and the other reads after it, in order;
+/// the consensus is then taken along the backbone, from its first to its last base, as by
+/// `PoaGraph::polish_backbone`. Empty reads and trailing null terminators are ignored, as by
+/// `poa_consensus_with_matrix`, and an empty backbone is returned unchanged.
+/// # Arguments
+///
+/// * `seqs` - the sequences, with or without null terminators
+/// * `backbone` - the index in `seqs` of the sequence to correct, e.g. 0 for the first
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the corrected backbone with the changes made to it
+///
+/// # Panics
+/// Panics if `backbone` is not less than `seqs.len()`.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_polish_backbone, Correction, PoaParams};
+///
+///     let seqs = vec!["AATGCGCGTT", "AATGCCCGTTAC", "ATGCCCGTT", "AATGCCCGTT"];
+///     let polished = poa_polish_backbone(&seqs, 0, &PoaParams::new(1, 5, -4, -8, -6));
+///
+///     assert_eq!(polished.consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(polished.corrections, vec![Correction { backbone_pos: 5, backbone: b"G".to_vec(), consensus: b"C".to_vec() }]);
+///     assert_eq!(polished.reads_used, 3);
+/// ```
+pub fn poa_polish_backbone<T: AsRef<[u8]>>(seqs: &[T], backbone: usize, params: &PoaParams) -> PolishedBackbone {
+    let strip = |seq: &T| {
+        let seq = seq.as_ref();
+        seq.strip_suffix(b"\0").unwrap_or(seq).to_vec()
+    };
+    let draft = strip(&seqs[backbone]);
+    if draft.is_empty() {
+        return PolishedBackbone { consensus: vec![], corrections: vec![], reads_used: 0 };
+    }
+
+    let mut graph = PoaGraph::new(params);
+    graph.add_sequence(&draft);
+    for (i, seq) in seqs.iter().enumerate() {
+        let seq = strip(seq);
+        if i != backbone && !seq.is_empty() {
+            graph.add_sequence(&seq);
+        }
+    }
+    graph.polish_backbone(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::{random_sequence, ReadSimulator};
+    use crate::RejectReason;
+
+    fn params() -> PoaParams {
+        PoaParams::new(1, 5, -4, -3, -1)
+    }
+
+    #[test]
+    fn test_identity_to_backbone() {
+        let mut graph = PoaGraph::new(&params());
+        assert_eq!(graph.add_sequence_min_identity(b"AATGCCCGTT", 0.9), Ok(1.0));
+        assert_eq!(graph.add_sequence_min_identity(b"AATGCCCGTT", 0.9), Ok(1.0));
+        // one mismatch in ten columns
+        assert_eq!(graph.add_sequence_min_identity(b"AATGCGCGTT", 0.9), Ok(0.9));
+        // a read matching the mismatch above is still a mismatch against the backbone
+        assert_eq!(graph.add_sequence_min_identity(b"AATGCGCGTA", 0.9), Err(RejectReason::LowIdentity));
+        assert_eq!(graph.num_sequences(), 3);
+    }
+
+    #[test]
+    fn test_local_alignment_counts_clipped_bases() {
+        let mut graph = PoaGraph::new(&PoaParams::new(0, 5, -4, -8, -6));
+        graph.add_sequence(b"AATGCCCGTT");
+
+        let identity = graph.add_sequence_min_identity(b"AATGCCCGTTGGGGGGGGGG", 0.0).unwrap();
+        assert!((identity - 0.5).abs() < 1e-9, "identity {}", identity);
+    }
+
+    #[test]
+    fn test_zero_minimum_keeps_every_read() {
+        let seqs: Vec<Vec<u8>> = ["AATGCCCGTT\0", "GGCATTAGAC\0", "AATGCCGTT\0"].iter().map(|seq| seq.bytes().collect()).collect();
+        let report = poa_consensus_on_backbone(b"AATGCCCGTT", &seqs, 0, &params(), 0.0);
+
+        assert!(report.rejected.is_empty());
+        assert_eq!(report.reads_used, 3);
+        assert_eq!(report.consensus, poa_consensus_on_backbone(b"AATGCCCGTT", &seqs, 20, &params(), 0.0).consensus);
+        assert!(!report.consensus.is_empty());
+    }
+
+    #[test]
+    fn test_anchor_picks_the_repeat_copy() {
+        // the read matches both copies of the repeat equally well
+        let mut graph = PoaGraph::new(&PoaParams::new(2, 5, -4, -8, -6));
+        graph.add_sequence(b"AATGCCCGTTGGGGGGGGGGAATGCCCGTT");
+
+        graph.add_sequence_anchored(b"AATGCCCGTT", 20, 3);
+        assert_eq!(graph.sequence_path(1), (20..30).collect::<Vec<u32>>());
+        graph.add_sequence_anchored(b"AATGCCCGTT", 0, 3);
+        assert_eq!(graph.sequence_path(2), (0..10).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_anchored_reads_tile_a_long_backbone() {
+        let truth = random_sequence(2000, 4);
+        let mut backbone = truth.clone();
+        for i in (100..2000).step_by(300) {
+            backbone[i] = if backbone[i] == b'A' { b'C' } else { b'A' };
+        }
+
+        // three reads of 300 bases every 50 bases, so each position is covered by reads starting
+        // at several offsets
+        let mut simulator = ReadSimulator::new(5).error_rates(0.02, 0.01, 0.01);
+        let mut seqs = vec![];
+        let mut offsets = vec![];
+        for start in (0..=1700).step_by(50) {
+            for read in simulator.reads(&truth[start..start + 300], 3) {
+                seqs.push([read.as_slice(), b"\0"].concat());
+                offsets.push(start);
+            }
+        }
+
+        let report = poa_consensus_on_backbone_anchored(&backbone, &seqs, &offsets, 3000, &PoaParams::new(2, 5, -4, -8, -6), 20);
+        assert_eq!(report.reads_used, seqs.len());
+        assert_eq!(report.consensus, truth);
+    }
+
+    #[test]
+    fn test_region_depth() {
+        let backbone = b"GGCATAATGCCCGTTCAGGA";
+        let seqs: Vec<Vec<u8>> = ["GGCATAATGCCCGTTCAGGA\0", "AATGCCGTTCAGGA\0", "GGCATAATGCCC\0", "GGCATAATGC\0", "CAGGATTTT\0", "\0"]
+            .iter().map(|seq| seq.bytes().collect()).collect();
+        let region = poa_consensus_on_backbone_region(backbone, 6..15, &seqs, &params());
+
+        assert_eq!(region.consensus, b"ATGCCCGTT".to_vec());
+        // too short an overlap, or chance matches, do not count
+        assert_eq!(region.reads_used, 3);
+        assert_eq!(region.depth[0], 3);
+        assert_eq!(region.depth[8], 2);
+        assert_eq!(region.rejected, vec![RejectedRead { index: 5, reason: RejectReason::Empty }]);
+    }
+
+    #[test]
+    fn test_polish_backbone() {
+        // a draft with a substitution, a base too many in a run of Gs, and a deletion against the
+        // reads; the first base of the run is taken as the extra one
+        let truth = b"GATTACAGGCTTACCGATCGATTGCAGCTAGG".to_vec();
+        let draft = b"GATTACAGGATTACCGATCGGATTGCAGCAGG".to_vec();
+        let mut seqs = vec![truth.clone(); 5];
+        seqs.insert(2, draft.clone());
+
+        for alignment_type in 0..3 {
+            let polished = poa_polish_backbone(&seqs, 2, &PoaParams::new(alignment_type, 5, -4, -8, -6));
+            assert_eq!(polished.consensus, truth, "{}", alignment_type);
+            assert_eq!(polished.reads_used, 5);
+            assert_eq!(
+                polished.corrections,
+                vec![
+                    Correction { backbone_pos: 9, backbone: b"A".to_vec(), consensus: b"C".to_vec() },
+                    Correction { backbone_pos: 19, backbone: b"G".to_vec(), consensus: vec![] },
+                    Correction { backbone_pos: 29, backbone: vec![], consensus: b"T".to_vec() },
+                ],
+                "{}",
+                alignment_type
+            );
+        }
+    }
+
+    #[test]
+    fn test_polish_backbone_ends() {
+        let params = params();
+        // reads extending past the backbone do not extend the consensus, and reads covering part
+        // of it do not shorten it
+        let seqs = vec!["ATGCCCGTT", "GGAATGCCCGTTAA\0", "GGAATGCCCGTTAA", "ATGCC", "", "ATGCCCG"];
+        let polished = poa_polish_backbone(&seqs, 0, &params);
+        assert_eq!(polished.consensus, b"ATGCCCGTT".to_vec());
+        assert!(polished.corrections.is_empty());
+        assert_eq!(polished.reads_used, 4);
+
+        assert_eq!(poa_polish_backbone(&["", "ACGT"], 0, &params), PolishedBackbone { consensus: vec![], corrections: vec![], reads_used: 0 });
+        assert_eq!(poa_polish_backbone(&["ACGT"], 0, &params).consensus, b"ACGT".to_vec());
+    }
+
+    #[test]
+    fn test_guided_consensus_weights() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let reference = b"TTGACAGATTACAGGCTTACCG";
+        // the reads cover the reference from its seventh base on and disagree with it on one base
+        let reads = vec!["GATTACTGGCTTACCG"; 2];
+
+        let topology = poa_consensus_guided(reference, &reads, &params, 0);
+        assert_eq!(topology.consensus, b"GATTACTGGCTTACCG".to_vec());
+        assert_eq!(topology.reference_alignment.consensus_start, 0);
+        assert_eq!(topology.reference_alignment.cigar, "6I6=1X9=");
+        assert_eq!(
+            topology.corrections,
+            vec![
+                Correction { backbone_pos: 0, backbone: b"TTGACA".to_vec(), consensus: vec![] },
+                Correction { backbone_pos: 12, backbone: b"A".to_vec(), consensus: b"T".to_vec() },
+            ]
+        );
+
+        // a reference weighing more than the reads outvotes them
+        let trusted = poa_consensus_guided(reference, &reads, &params, 3);
+        assert_eq!(trusted.consensus, reference.to_vec());
+        assert_eq!(trusted.reference_alignment.cigar, "22=");
+        assert!(trusted.corrections.is_empty());
+        assert_eq!(trusted.reads_used, 2);
+
+        let empty = poa_consensus_guided(b"", &["", "ACGT\0"], &params, 1);
+        assert_eq!((empty.consensus, empty.reads_used), (b"ACGT".to_vec(), 1));
+        assert!(empty.reference_alignment.cigar.is_empty());
+    }
+}
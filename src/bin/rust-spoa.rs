@@ -0,0 +1,468 @@
+//! A command line tool printing the consensus of the reads of a FASTA or FASTQ file, with the
+//! options and defaults of the spoa executable, so parameters can be tried out quickly and results
+//! compared against upstream spoa. It also builds the consensus of groups of records of an indexed
+//! FASTA file, and polishes a draft assembly with the `polish` subcommand.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::process;
+use std::str::FromStr;
+
+use rust_spoa::{
+    poa_consensus_of_groups, polish_from_paths, read_seq_records, read_seq_records_from, write_fasta_record, ConvexGaps, GroupConsensus,
+    GroupOptions, GroupSummary, IndexedFasta, PoaGraph, PoaParams, PolishOptions, SeqRecord, SmallGroups,
+};
+
+const USAGE: &str = "usage: rust-spoa [options ...] [<sequences>]
+       rust-spoa polish [polish options ...] <draft> <reads> <mappings>
+
+    <sequences>
+        input file in FASTA or FASTQ format, which may be gzip-compressed with
+        the gzip feature; standard input if missing or -
+
+    options:
+        -m, --match <int>            default: 5
+            score for matching bases
+        -n, --mismatch <int>         default: -4
+            score for mismatching bases
+        -g, --gap-open <int>         default: -8
+            gap opening score (non-positive)
+        -e, --gap-extend <int>       default: -6
+            gap extension score (non-positive)
+        -q, --gap-open2 <int>        default: -10
+            second gap opening score, for convex gap scores (non-positive)
+        -c, --gap-extend2 <int>      default: -4
+            second gap extension score, for convex gap scores (non-positive)
+        -l, --algorithm <int>        default: 0
+            alignment mode: 0 - local, 1 - global, 2 - semi-global
+        -r, --result <int>           default: 0
+            result mode: 0 - consensus (FASTA), 1 - multiple sequence alignment
+            (FASTA), 2 - 0 & 1 (FASTA), 3 - partial order graph (GFA)
+        --regions <file>
+            builds a consensus of each group of reads listed in the file, one
+            group per line: its name followed by the record names or
+            name:start-end regions of its reads, separated by whitespace; the
+            reads are fetched from <sequences>, a FASTA file indexed by
+            <sequences>.fai, and result mode 0 is the only one supported
+        --min-reads <int>            default: 1
+            minimum number of non-empty reads a group needs for a consensus;
+            smaller groups are left out, and the counts of how the groups were
+            handled are printed to standard error
+        --pass-through
+            outputs the first read of a group below --min-reads, flagged with
+            PT:i:1, instead of leaving the group out
+        -t, --threads <int>          default: 1
+            number of threads the groups of --regions are spread across; the
+            output is the same for any number
+        --version
+            prints the version number
+        -h, --help
+            prints the usage
+
+    polish:
+        polishes <draft>, a FASTA file, with <reads>, a FASTA or FASTQ file,
+        mapped to it in <mappings>, a PAF file, by the consensus of each draft
+        window, and prints the polished contigs (FASTA)
+
+    polish options:
+        -m, -n, -g, -e, -l           as above, with -l 2 (semi-global) as the
+            default and no second gap scores
+        -w, --window-length <int>    default: 500
+            length of the draft windows polished independently
+        --min-mapping-quality <int>  default: 0
+            minimum mapping quality of the mappings used
+";
+
+// what is printed, numbered as the result modes of spoa
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Output {
+    Consensus,
+    Msa,
+    MsaAndConsensus,
+    Gfa,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Options {
+    params: PoaParams,
+    output: Output,
+    // None for standard input
+    input: Option<String>,
+    // the file listing the groups of reads of an indexed input, if any
+    regions: Option<String>,
+    // the minimum number of reads of a group and the threads groups are spread across
+    groups: GroupOptions,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct PolishArgs {
+    options: PolishOptions,
+    draft: String,
+    reads: String,
+    mappings: String,
+}
+
+// what the command line asks for: a run, polishing, or only the usage or version
+#[derive(Clone, Debug, PartialEq)]
+enum Command {
+    Run(Options),
+    Polish(PolishArgs),
+    Help,
+    Version,
+}
+
+// the value following the option arg
+fn value<I: Iterator<Item = String>>(arg: &str, args: &mut I) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("option {} needs a value", arg))
+}
+
+// the integer following the option arg
+fn integer<T: FromStr, I: Iterator<Item = String>>(arg: &str, args: &mut I) -> Result<T, String> {
+    let text = value(arg, args)?;
+    text.parse().map_err(|_| format!("option {} needs an integer, not {}", arg, text))
+}
+
+// parses the arguments after the program name
+fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let (mut match_score, mut mismatch_score, mut gap_open, mut gap_extend) = (5, -4, -8, -6);
+    let (mut gap_open2, mut gap_extend2, mut algorithm, mut result) = (-10, -4, 0, 0);
+    let (mut input, mut regions) = (None, None);
+    let mut groups = GroupOptions::new();
+
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(String::as_str) == Some("polish") {
+        args.next();
+        return parse_polish_args(args);
+    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--version" => return Ok(Command::Version),
+            "-m" | "--match" => match_score = integer(&arg, &mut args)?,
+            "-n" | "--mismatch" => mismatch_score = integer(&arg, &mut args)?,
+            "-g" | "--gap-open" => gap_open = integer(&arg, &mut args)?,
+            "-e" | "--gap-extend" => gap_extend = integer(&arg, &mut args)?,
+            "-q" | "--gap-open2" => gap_open2 = integer(&arg, &mut args)?,
+            "-c" | "--gap-extend2" => gap_extend2 = integer(&arg, &mut args)?,
+            "-l" | "--algorithm" => algorithm = integer(&arg, &mut args)?,
+            "-r" | "--result" => result = integer(&arg, &mut args)?,
+            "--regions" => regions = Some(value(&arg, &mut args)?),
+            "--min-reads" => groups.min_reads = integer(&arg, &mut args)?,
+            "--pass-through" => groups.small_groups = SmallGroups::PassThrough,
+            "-t" | "--threads" => groups.threads = integer(&arg, &mut args)?,
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option {}", arg)),
+            _ if input.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.filter(|input| input != "-");
+
+    let output = match result {
+        0 => Output::Consensus,
+        1 => Output::Msa,
+        2 => Output::MsaAndConsensus,
+        3 => Output::Gfa,
+        _ => return Err(format!("unknown result mode {}", result)),
+    };
+    // spoa scores gaps with both pairs only where they make the scores convex, as the graph does
+    let params = PoaParams::new(algorithm, match_score, mismatch_score, gap_open, gap_extend)
+        .convex_gaps(ConvexGaps::new(gap_open2, gap_extend2));
+    params.validate().map_err(|error| error.to_string())?;
+    if regions.is_some() && input.is_none() {
+        return Err("--regions needs an indexed FASTA file, not standard input".to_string());
+    }
+    if regions.is_some() && output != Output::Consensus {
+        return Err("--regions only supports result mode 0".to_string());
+    }
+
+    Ok(Command::Run(Options { params, output, input, regions, groups }))
+}
+
+// parses the arguments after polish
+fn parse_polish_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let defaults = PolishOptions::default();
+    let (mut match_score, mut mismatch_score) = (defaults.params.match_score, defaults.params.mismatch_score);
+    let (mut gap_open, mut gap_extend, mut algorithm) = (defaults.params.gap_open, defaults.params.gap_extend, defaults.params.alignment_type);
+    let (mut window_length, mut min_mapping_quality) = (defaults.window_length, defaults.min_mapping_quality);
+    let mut paths = vec![];
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-m" | "--match" => match_score = integer(&arg, &mut args)?,
+            "-n" | "--mismatch" => mismatch_score = integer(&arg, &mut args)?,
+            "-g" | "--gap-open" => gap_open = integer(&arg, &mut args)?,
+            "-e" | "--gap-extend" => gap_extend = integer(&arg, &mut args)?,
+            "-l" | "--algorithm" => algorithm = integer(&arg, &mut args)?,
+            "-w" | "--window-length" => window_length = integer(&arg, &mut args)?,
+            "--min-mapping-quality" => min_mapping_quality = integer(&arg, &mut args)?,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => paths.push(arg),
+        }
+    }
+
+    let params = PoaParams::new(algorithm, match_score, mismatch_score, gap_open, gap_extend);
+    params.validate().map_err(|error| error.to_string())?;
+    if window_length == 0 {
+        return Err("option --window-length needs a positive length".to_string());
+    }
+    let mut paths = paths.into_iter();
+    match (paths.next(), paths.next(), paths.next(), paths.next()) {
+        (Some(draft), Some(reads), Some(mappings), None) => {
+            let options = defaults.params(params).window_length(window_length).min_mapping_quality(min_mapping_quality);
+            Ok(Command::Polish(PolishArgs { options, draft, reads, mappings }))
+        }
+        (_, _, _, Some(arg)) => Err(format!("unexpected argument {}", arg)),
+        _ => Err("polish needs a draft, reads and mappings".to_string()),
+    }
+}
+
+// writes the result of a graph built from records, in that order, flagging a consensus that is
+// the first read of a group passed through
+fn write_output<W: Write>(mut writer: W, graph: &PoaGraph, records: &[SeqRecord], output: Output, passed_through: bool) -> io::Result<()> {
+    match output {
+        Output::Consensus => {
+            let consensus = graph.consensus();
+            writeln!(writer, ">Consensus LN:i:{}{}", consensus.len(), if passed_through { " PT:i:1" } else { "" })?;
+            writer.write_all(&consensus)?;
+            writeln!(writer)
+        }
+        Output::Msa | Output::MsaAndConsensus => {
+            let rows = graph.multiple_sequence_alignment(output == Output::MsaAndConsensus);
+            let names = records.iter().map(|record| record.name.as_str()).chain(Some("Consensus"));
+            for (name, row) in names.zip(&rows) {
+                writeln!(writer, ">{}", name)?;
+                writer.write_all(row)?;
+                writeln!(writer)?;
+            }
+            Ok(())
+        }
+        Output::Gfa => graph.write_gfa(writer),
+    }
+}
+
+// writes the consensus of each group, flagging groups passed through
+fn write_groups<W: Write>(mut writer: W, records: &[GroupConsensus]) -> io::Result<()> {
+    for record in records {
+        writeln!(writer, ">{} LN:i:{}{}", record.name, record.consensus.len(), if record.passed_through { " PT:i:1" } else { "" })?;
+        writer.write_all(&record.consensus)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+// reads the groups listed in regions, one per line, fetching their reads from an indexed FASTA
+fn read_groups<R: BufRead, F: Read + Seek>(regions: R, fasta: &mut IndexedFasta<F>) -> io::Result<Vec<(String, Vec<Vec<u8>>)>> {
+    let mut groups = vec![];
+    for line in regions.lines() {
+        let line = line?;
+        let mut fields = line.split_whitespace();
+        if let Some(name) = fields.next() {
+            let reads = fields.map(|spec| fasta.fetch_spec(spec)).collect::<io::Result<_>>()?;
+            groups.push((name.to_string(), reads));
+        }
+    }
+    Ok(groups)
+}
+
+fn print_summary(summary: &GroupSummary) {
+    eprintln!(
+        "rust-spoa: {} groups: {} built, {} skipped, {} passed through",
+        summary.groups, summary.built, summary.skipped, summary.passed_through
+    );
+}
+
+fn run(options: &Options) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+
+    if let (Some(regions), Some(input)) = (&options.regions, &options.input) {
+        let mut fasta = IndexedFasta::open(input)?;
+        let groups = read_groups(BufReader::new(File::open(regions)?), &mut fasta)?;
+        let (records, summary) = poa_consensus_of_groups(groups, &options.params, &options.groups);
+        write_groups(&mut writer, &records)?;
+        print_summary(&summary);
+        return writer.flush();
+    }
+
+    let mut records = match &options.input {
+        Some(path) => read_seq_records(path)?,
+        None => read_seq_records_from(io::stdin())?,
+    };
+
+    // the whole input is one group, left out or passed through as its first read if too small
+    records.retain(|record| !record.seq.is_empty());
+    let mut summary = GroupSummary { groups: 1, ..GroupSummary::default() };
+    let small = records.len() < options.groups.min_reads;
+    if records.is_empty() || (small && options.groups.small_groups == SmallGroups::Skip) {
+        summary.skipped = 1;
+    } else {
+        if small {
+            summary.passed_through = 1;
+            records.truncate(1);
+        } else {
+            summary.built = 1;
+        }
+
+        // the bases of FASTQ records are weighted by quality, as spoa does
+        let mut graph = PoaGraph::new(&options.params);
+        for record in &records {
+            graph.add_record(record);
+        }
+        write_output(&mut writer, &graph, &records, options.output, small)?;
+    }
+    if options.groups.min_reads > 1 {
+        print_summary(&summary);
+    }
+    writer.flush()
+}
+
+fn run_polish(args: &PolishArgs) -> io::Result<()> {
+    let polished = polish_from_paths(&args.draft, &args.reads, &args.mappings, &args.options)?;
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    for (name, seq) in &polished {
+        write_fasta_record(&mut writer, name, seq, None)?;
+    }
+    writer.flush()
+}
+
+fn main() {
+    let result = match parse_args(env::args().skip(1)) {
+        Ok(Command::Run(options)) => run(&options),
+        Ok(Command::Polish(args)) => run_polish(&args),
+        Ok(Command::Help) => return print!("{}", USAGE),
+        Ok(Command::Version) => return println!("rust-spoa {}", env!("CARGO_PKG_VERSION")),
+        Err(message) => {
+            eprintln!("rust-spoa: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(error) = result {
+        eprintln!("rust-spoa: {}", error);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_spoa::FastaIndex;
+    use std::io::Cursor;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let defaults = PoaParams::new(0, 5, -4, -8, -6).convex_gaps(ConvexGaps::new(-10, -4));
+        let options = Options { params: defaults, output: Output::Consensus, input: None, regions: None, groups: GroupOptions::new() };
+        assert_eq!(parse(&[]), Ok(Command::Run(options.clone())));
+        assert_eq!(parse(&["-"]), parse(&[]));
+
+        let expected = PoaParams::new(1, 2, -3, -4, -1).convex_gaps(ConvexGaps::new(-10, -4));
+        assert_eq!(
+            parse(&["-l", "1", "reads.fq", "--match", "2", "-n", "-3", "-g", "-4", "-e", "-1", "-r", "2"]),
+            Ok(Command::Run(Options { params: expected, output: Output::MsaAndConsensus, input: Some("reads.fq".to_string()), ..options.clone() }))
+        );
+        assert_eq!(
+            parse(&["--regions", "families.txt", "families.fa", "--min-reads", "3", "--pass-through", "-t", "4"]),
+            Ok(Command::Run(Options {
+                input: Some("families.fa".to_string()),
+                regions: Some("families.txt".to_string()),
+                groups: GroupOptions::new().min_reads(3).small_groups(SmallGroups::PassThrough).threads(4),
+                ..options
+            }))
+        );
+        assert_eq!(parse(&["-m", "5", "--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["--version"]), Ok(Command::Version));
+
+        let polish = PolishOptions::default().params(PoaParams::new(1, 3, -5, -4, -2)).window_length(200).min_mapping_quality(20);
+        assert_eq!(
+            parse(&["polish", "-l", "1", "-m", "3", "-n", "-5", "-g", "-4", "-e", "-2", "draft.fa", "reads.fq", "-w", "200", "--min-mapping-quality", "20", "reads.paf"]),
+            Ok(Command::Polish(PolishArgs {
+                options: polish,
+                draft: "draft.fa".to_string(),
+                reads: "reads.fq".to_string(),
+                mappings: "reads.paf".to_string(),
+            }))
+        );
+        assert!(matches!(parse(&["polish", "a.fa", "b.fq", "c.paf"]), Ok(Command::Polish(args)) if args.options == PolishOptions::default()));
+    }
+
+    #[test]
+    fn test_invalid_args() {
+        assert!(parse(&["-x"]).unwrap_err().contains("unknown option"));
+        assert!(parse(&["-m"]).unwrap_err().contains("needs a value"));
+        assert!(parse(&["-m", "five"]).unwrap_err().contains("needs an integer"));
+        assert!(parse(&["a.fa", "b.fa"]).unwrap_err().contains("unexpected argument"));
+        assert!(parse(&["-r", "5"]).is_err());
+        assert!(parse(&["-l", "3"]).is_err());
+        assert!(parse(&["-g", "4"]).is_err());
+        assert!(parse(&["--min-reads", "-1"]).unwrap_err().contains("needs an integer"));
+        assert!(parse(&["--regions", "families.txt"]).unwrap_err().contains("standard input"));
+        assert!(parse(&["--regions", "families.txt", "-r", "1", "families.fa"]).unwrap_err().contains("result mode 0"));
+
+        assert!(parse(&["polish", "a.fa", "b.fq"]).unwrap_err().contains("needs a draft"));
+        assert!(parse(&["polish", "a.fa", "b.fq", "c.paf", "d"]).unwrap_err().contains("unexpected argument"));
+        assert!(parse(&["polish", "-q", "-10", "a.fa", "b.fq", "c.paf"]).unwrap_err().contains("unknown option"));
+        assert!(parse(&["polish", "-w", "0", "a.fa", "b.fq", "c.paf"]).is_err());
+    }
+
+    #[test]
+    fn test_write_output() {
+        let records: Vec<SeqRecord> = ["AACGT", "AACGT", "ACGT"]
+            .iter()
+            .enumerate()
+            .map(|(i, seq)| SeqRecord::new(format!("r{}", i), seq.as_bytes().to_vec()))
+            .collect();
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+        for record in &records {
+            graph.add_record(record);
+        }
+        let output = |output: Output| {
+            let mut bytes = vec![];
+            write_output(&mut bytes, &graph, &records, output, false).unwrap();
+            String::from_utf8(bytes).unwrap()
+        };
+
+        assert_eq!(output(Output::Consensus), ">Consensus LN:i:5\nAACGT\n");
+        assert_eq!(output(Output::Msa), ">r0\nAACGT\n>r1\nAACGT\n>r2\n-ACGT\n");
+        assert!(output(Output::MsaAndConsensus).ends_with(">r2\n-ACGT\n>Consensus\nAACGT\n"));
+        assert!(output(Output::Gfa).starts_with("H\tVN:Z:1.0\n"));
+
+        let mut bytes = vec![];
+        write_output(&mut bytes, &graph, &records, Output::Consensus, true).unwrap();
+        assert_eq!(bytes, b">Consensus LN:i:5 PT:i:1\nAACGT\n");
+    }
+
+    #[test]
+    fn test_groups() {
+        let fasta = b">f1/r1\nAATGCCCGTT\n>f1/r2\nAATGCCGTT\n>f1/r3\nAATGCC\nCGTT\n>f2/r1\nGATTACA\n";
+        let index = FastaIndex::build(&fasta[..]).unwrap();
+        let mut fasta = IndexedFasta::new(Cursor::new(&fasta[..]), index);
+        let regions = "f1 f1/r1 f1/r2 f1/r3\n\nf2 f2/r1:2-5\nf3\n";
+        let groups = read_groups(regions.as_bytes(), &mut fasta).unwrap();
+        assert_eq!(groups, vec![
+            ("f1".to_string(), vec![b"AATGCCCGTT".to_vec(), b"AATGCCGTT".to_vec(), b"AATGCCCGTT".to_vec()]),
+            ("f2".to_string(), vec![b"ATTA".to_vec()]),
+            ("f3".to_string(), vec![]),
+        ]);
+        assert!(read_groups("f4 f4/r1\n".as_bytes(), &mut fasta).is_err());
+
+        // the output is the same for any number of threads
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let options = GroupOptions::new().min_reads(2).small_groups(SmallGroups::PassThrough);
+        let (records, summary) = poa_consensus_of_groups(groups.clone(), &params, &options);
+        assert_eq!(poa_consensus_of_groups(groups, &params, &options.threads(3)), (records.clone(), summary));
+        assert_eq!(summary, GroupSummary { groups: 3, built: 1, skipped: 1, passed_through: 1 });
+
+        let mut bytes = vec![];
+        write_groups(&mut bytes, &records).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), ">f1 LN:i:10\nAATGCCCGTT\n>f2 LN:i:4 PT:i:1\nATTA\n");
+    }
+}
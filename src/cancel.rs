@@ -0,0 +1,165 @@
+//! Stopping a long consensus job from another thread or after a timeout.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{try_consensus_until, Alphabet, PoaError, PoaGraph, PoaParams};
+
+/// A flag shared by the clones of a token, checked between the alignments of a consensus job, and
+/// optionally a deadline after which the token counts as cancelled.
+///
+/// A single alignment runs to completion, so a job stops at most one alignment after the token is
+/// cancelled.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::CancelToken;
+///
+///     let token = CancelToken::new();
+///     let handle = token.clone();
+///     assert!(!token.is_cancelled());
+///     handle.cancel();
+///     assert!(token.is_cancelled());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancelToken {
+    /// Creates a token that is cancelled only by `cancel`.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Creates a token that is cancelled by `cancel` or once `timeout` has passed from now.
+    pub fn with_timeout(timeout: Duration) -> CancelToken {
+        CancelToken { cancelled: Arc::new(AtomicBool::new(false)), deadline: Instant::now().checked_add(timeout) }
+    }
+
+    /// Cancels the token and all its clones.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the token was cancelled or its timeout has passed.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || matches!(self.deadline, Some(deadline) if Instant::now() >= deadline)
+    }
+}
+
+impl PoaGraph {
+    /// Aligns the sequences to the graph and adds them in order, checking `token` before each one.
+    ///
+    /// # Returns
+    /// * returns `PoaError::Cancelled` with the number of sequences added if the token is cancelled
+    ///   before the last one is added; the graph keeps them, so the consensus of the reads so far
+    ///   can still be taken
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{CancelToken, PoaError, PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     let token = CancelToken::new();
+    ///     graph.add_sequences_cancellable(&["AATGCCCGTT", "AATGCCGTT"], &token).unwrap();
+    ///
+    ///     token.cancel();
+    ///     assert_eq!(graph.add_sequences_cancellable(&["AATGCCCGTT"], &token), Err(PoaError::Cancelled { reads_added: 0 }));
+    ///     assert_eq!(graph.num_sequences(), 2);
+    /// ```
+    pub fn add_sequences_cancellable<T: AsRef<[u8]>>(&mut self, seqs: &[T], token: &CancelToken) -> Result<(), PoaError> {
+        for (reads_added, seq) in seqs.iter().enumerate() {
+            if token.is_cancelled() {
+                return Err(PoaError::Cancelled { reads_added });
+            }
+            self.add_sequence(seq.as_ref());
+        }
+        Ok(())
+    }
+}
+
+/// Generates a consensus sequence like `try_poa_consensus`, giving up once `token` is cancelled.
+///
+/// The token is checked before each read is aligned. To keep the reads aligned before a
+/// cancellation, add them to a `PoaGraph` with `add_sequences_cancellable` instead.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `token` - the token stopping the job, e.g. `CancelToken::with_timeout`
+///
+/// # Returns
+/// * returns the consensus of the reads, the errors of `try_poa_consensus`, or
+///   `PoaError::Cancelled` with the number of reads added if the token was cancelled first
+///
+/// # Examples
+///
+/// ```
+///     use std::time::Duration;
+///     use rust_spoa::{try_poa_consensus_cancellable, CancelToken, PoaError, PoaParams};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCGTT\0".to_vec(), b"AATGCCCGAT\0".to_vec()];
+///     let params = PoaParams::new(1, 5, -4, -3, -1);
+///
+///     let token = CancelToken::with_timeout(Duration::from_secs(60));
+///     assert_eq!(try_poa_consensus_cancellable(&seqs, 0, &params, &token), Ok(b"AATGCCCGTT".to_vec()));
+///
+///     let expired = CancelToken::with_timeout(Duration::from_secs(0));
+///     assert_eq!(try_poa_consensus_cancellable(&seqs, 0, &params, &expired), Err(PoaError::Cancelled { reads_added: 0 }));
+/// ```
+pub fn try_poa_consensus_cancellable<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    token: &CancelToken,
+) -> Result<Vec<u8>, PoaError> {
+    try_consensus_until(seqs, consensus_max_length, params, &Alphabet::default(), None, Some(token), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_cancelled_from_another_thread() {
+        let reads: Vec<Vec<u8>> = (0..2000).map(|i| format!("GATTACAGGC{}TTACCGATCG\0", "A".repeat(i % 7)).into_bytes()).collect();
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let token = CancelToken::new();
+
+        let worker = {
+            let token = token.clone();
+            thread::spawn(move || try_poa_consensus_cancellable(&reads, 0, &params, &token))
+        };
+        token.cancel();
+        match worker.join().unwrap() {
+            Err(PoaError::Cancelled { reads_added }) => assert!(reads_added < 2000),
+            result => panic!("not cancelled: {:?}", result),
+        }
+        assert_eq!(PoaError::Cancelled { reads_added: 3 }.to_string(), "cancelled after adding 3 reads");
+    }
+
+    #[test]
+    fn test_timeout_and_other_errors() {
+        assert!(!CancelToken::with_timeout(Duration::from_secs(3600)).is_cancelled());
+        assert!(!CancelToken::with_timeout(Duration::MAX).is_cancelled());
+        assert!(CancelToken::with_timeout(Duration::from_millis(0)).is_cancelled());
+
+        // a token that is not cancelled leaves the errors of try_poa_consensus
+        let token = CancelToken::new();
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        assert_eq!(try_poa_consensus_cancellable(&[b"ACGT\0"], 0, &PoaParams::new(3, 5, -4, -3, -1), &token), Err(PoaError::InvalidAlignmentType(3)));
+        assert_eq!(try_poa_consensus_cancellable(&[b"\0"], 0, &params, &token), Err(PoaError::EmptyInput));
+
+        let mut graph = PoaGraph::new(&params);
+        let seqs = ["AATGCCCGTT", "AATGCCGTT", "AATGCCCGAT"];
+        assert_eq!(graph.add_sequences_cancellable(&seqs, &token), Ok(()));
+        assert_eq!(graph.consensus(), b"AATGCCCGTT".to_vec());
+    }
+}
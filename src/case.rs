@@ -0,0 +1,156 @@
+//! How lower-case bases of the reads, e.g. soft-masked repeats, are aligned.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{PoaError, PoaParams, RejectReason, RejectedRead};
+
+/// How reads with lower-case bases are treated, as set by `PoaParams::case_policy`.
+///
+/// SPOA compares bases as bytes, so `a` and `A` would be different symbols that never match: a
+/// soft-masked repeat would align as a run of mismatches and split the graph.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CasePolicy {
+    /// bases are aligned in upper case, so the consensus is in upper case
+    #[default]
+    Uppercase,
+    /// bases are aligned as given, so the consensus keeps the case of the reads, and bases of
+    /// either case do not match each other; suits reads in one case, or a substitution matrix with
+    /// a lower-case alphabet
+    Preserve,
+    /// bases are aligned as given, and reads mixing lower- and upper-case bases are rejected
+    Reject,
+}
+
+impl CasePolicy {
+    /// Returns a short lower-case name for the policy, as `str::parse` reads it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CasePolicy::Uppercase => "uppercase",
+            CasePolicy::Preserve => "preserve",
+            CasePolicy::Reject => "reject",
+        }
+    }
+
+    // the bases of seq as they are aligned under the policy, or why seq is rejected
+    pub(crate) fn apply(self, seq: &[u8]) -> Result<Cow<'_, [u8]>, RejectReason> {
+        match self {
+            CasePolicy::Uppercase if seq.iter().any(u8::is_ascii_lowercase) => Ok(Cow::Owned(seq.to_ascii_uppercase())),
+            CasePolicy::Reject if seq.iter().any(u8::is_ascii_lowercase) && seq.iter().any(u8::is_ascii_uppercase) => {
+                Err(RejectReason::MixedCase)
+            }
+            _ => Ok(Cow::Borrowed(seq)),
+        }
+    }
+
+    // the reads as they are aligned under the policy, for the native consensus functions
+    pub(crate) fn apply_all<T: AsRef<[u8]>>(self, seqs: &[T]) -> Vec<Cow<'_, [u8]>> {
+        seqs.iter()
+            .enumerate()
+            .map(|(index, seq)| {
+                self.apply(seq.as_ref()).unwrap_or_else(|reason| panic!("{}", PoaError::InvalidRead(RejectedRead { index, reason })))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for CasePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CasePolicy {
+    type Err = PoaError;
+
+    fn from_str(s: &str) -> Result<CasePolicy, PoaError> {
+        match s {
+            "uppercase" => Ok(CasePolicy::Uppercase),
+            "preserve" => Ok(CasePolicy::Preserve),
+            "reject" => Ok(CasePolicy::Reject),
+            _ => Err(PoaError::InvalidPreset(format!("unknown case policy {}", s))),
+        }
+    }
+}
+
+impl PoaParams {
+    /// Sets how reads with lower-case bases are aligned.
+    ///
+    /// Reads are turned to upper case by default. Under `CasePolicy::Reject`, methods adding a
+    /// read mixing cases to a graph panic, the `try_` functions return `PoaError::InvalidRead`,
+    /// and the functions reporting rejected reads report `RejectReason::MixedCase`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{poa_consensus_unbounded, CasePolicy, PoaParams};
+    ///
+    ///     // the second read has a soft-masked repeat
+    ///     let reads = ["AATGCCCGTT\0", "AATgcccGTT\0", "AATGCCCGTT\0"];
+    ///     assert_eq!(poa_consensus_unbounded(&reads, &PoaParams::dna()), b"AATGCCCGTT".to_vec());
+    ///
+    ///     let masked = ["aatgcccgtt\0", "aatgcccgtt\0"];
+    ///     let params = PoaParams::dna().case_policy(CasePolicy::Preserve);
+    ///     assert_eq!(poa_consensus_unbounded(&masked, &params), b"aatgcccgtt".to_vec());
+    /// ```
+    pub fn case_policy(mut self, case_policy: CasePolicy) -> PoaParams {
+        self.case_policy = case_policy;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{try_poa_consensus, PoaGraph};
+
+    #[test]
+    fn test_policies() {
+        let masked = b"ACgtN-";
+        assert_eq!(CasePolicy::Uppercase.apply(masked).unwrap(), Cow::<[u8]>::Owned(b"ACGTN-".to_vec()));
+        assert!(matches!(CasePolicy::Uppercase.apply(b"ACGT").unwrap(), Cow::Borrowed(_)));
+        assert_eq!(CasePolicy::Preserve.apply(masked).unwrap().as_ref(), masked);
+        assert_eq!(CasePolicy::Reject.apply(masked), Err(RejectReason::MixedCase));
+        assert_eq!(CasePolicy::Reject.apply(b"acgt-").unwrap().as_ref(), b"acgt-");
+        for policy in [CasePolicy::Uppercase, CasePolicy::Preserve, CasePolicy::Reject].iter() {
+            assert_eq!(policy.to_string().parse::<CasePolicy>(), Ok(*policy));
+        }
+        assert!("lower".parse::<CasePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_soft_masked_reads() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let reads = ["GATTACAGGCTTACCG", "GATTacaggcTTACCG", "gattacaggcttaccg"];
+
+        let mut graph = PoaGraph::new(&params);
+        let mut preserved = PoaGraph::new(&params.case_policy(CasePolicy::Preserve));
+        for read in reads.iter() {
+            graph.add_sequence(read.as_bytes());
+            preserved.add_sequence(read.as_bytes());
+        }
+        assert_eq!(graph.num_nodes(), 16);
+        assert_eq!(graph.consensus(), b"GATTACAGGCTTACCG".to_vec());
+        assert!(preserved.num_nodes() > 16);
+        assert_eq!(graph.alignment_score(b"gattacaggcttaccg"), 80);
+
+        let terminated = ["GATTACAGGCTTACCG\0", "GATTacaggcTTACCG\0"];
+        let rejecting = params.case_policy(CasePolicy::Reject);
+        assert_eq!(
+            try_poa_consensus(&terminated, 0, &rejecting),
+            Err(PoaError::InvalidRead(RejectedRead { index: 1, reason: RejectReason::MixedCase }))
+        );
+        assert_eq!(
+            PoaGraph::new(&rejecting).try_add_sequence(b"acGT"),
+            Err(PoaError::InvalidRead(RejectedRead { index: 0, reason: RejectReason::MixedCase }))
+        );
+        assert_eq!(RejectReason::MixedCase.to_string(), "mixed_case");
+    }
+
+    #[test]
+    #[should_panic(expected = "read 0 is invalid: mixed_case")]
+    fn test_native_consensus_rejects() {
+        crate::poa_consensus_unbounded(&["acGT\0"], &PoaParams::dna().case_policy(CasePolicy::Reject));
+    }
+}
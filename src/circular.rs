@@ -0,0 +1,279 @@
+//! Consensus sequences of circular molecules, e.g. plasmids and viral genomes, whose reads start
+//! at arbitrary positions around the circle.
+
+use std::collections::HashMap;
+
+use crate::seq::reverse_complement;
+use crate::{PoaGraph, PoaParams, Strand};
+
+// the length of the k-mers matching reads to the seed read
+const SEED_K: usize = 11;
+
+/// The common origin every read of a circular molecule is rotated to before it is aligned.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CircularOrigin {
+    /// the start of the first non-empty read: every other read is rotated, and oriented, to the
+    /// offset most of its k-mers share with that read
+    Seed,
+    /// the first occurrence of a sequence, e.g. a k-mer known to occur once in the molecule; reads
+    /// without it on either strand are left out
+    Anchor(Vec<u8>),
+}
+
+/// How a read of a circular molecule was turned before it was added to the graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rotation {
+    /// the strand the read was taken from
+    pub strand: Strand,
+    /// the number of bases moved from the start to the end of the read, on that strand
+    pub offset: usize,
+}
+
+impl Rotation {
+    /// Returns the read on `strand`, rotated left by `offset`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{Rotation, Strand};
+    ///
+    ///     assert_eq!(Rotation { strand: Strand::Forward, offset: 2 }.apply(b"AACGT"), b"CGTAA".to_vec());
+    ///     assert_eq!(Rotation { strand: Strand::Reverse, offset: 1 }.apply(b"AACGT"), b"CGTTA".to_vec());
+    /// ```
+    pub fn apply(&self, seq: &[u8]) -> Vec<u8> {
+        let mut seq = match self.strand {
+            Strand::Forward => seq.to_vec(),
+            Strand::Reverse => reverse_complement(seq),
+        };
+        if !seq.is_empty() {
+            let offset = self.offset % seq.len();
+            seq.rotate_left(offset);
+        }
+        seq
+    }
+}
+
+/// The consensus of the reads of a circular molecule, with the rotation of each read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CircularConsensus {
+    /// the consensus, starting at the origin, with the bases the reads overran the origin by
+    /// removed so that it closes into the circle
+    pub consensus: Vec<u8>,
+    /// how each read was turned before it was added, in input order; `None` for the reads left
+    /// out, as empty reads and reads that could not be placed relative to the origin
+    pub rotations: Vec<Option<Rotation>>,
+}
+
+// the first occurrence of anchor in seq read around the circle, i.e. allowing it to wrap past the end
+fn find_circular(seq: &[u8], anchor: &[u8]) -> Option<usize> {
+    if seq.len() < anchor.len() {
+        return None;
+    }
+    let doubled: Vec<u8> = seq.iter().chain(&seq[..anchor.len() - 1]).cloned().collect();
+    doubled.windows(anchor.len()).position(|window| window == anchor)
+}
+
+// the k-mers occurring exactly once around the circle of seed, with their positions
+fn unique_kmers(seed: &[u8]) -> HashMap<Vec<u8>, usize> {
+    let mut kmers: HashMap<Vec<u8>, Option<usize>> = HashMap::new();
+    if seed.len() >= SEED_K {
+        let doubled: Vec<u8> = seed.iter().chain(&seed[..SEED_K - 1]).cloned().collect();
+        for (pos, kmer) in doubled.windows(SEED_K).enumerate() {
+            kmers.entry(kmer.to_vec()).and_modify(|first| *first = None).or_insert(Some(pos));
+        }
+    }
+    kmers.into_iter().filter_map(|(kmer, pos)| Some((kmer, pos?))).collect()
+}
+
+// the offset of seq that rotates the start of the seed, of seed_len bases, to its start, as voted
+// for by the k-mers it shares with the seed, and the number of votes; the smallest offset among
+// equals. a read ending before the start of the seed comes around is not rotated.
+fn seed_offset(kmers: &HashMap<Vec<u8>, usize>, seed_len: usize, seq: &[u8]) -> Option<(usize, usize)> {
+    if seq.len() < SEED_K || seed_len == 0 {
+        return None;
+    }
+    // the position of the start of the seed in the read, around the circle of the seed
+    let mut votes = vec![0usize; seed_len];
+    let doubled: Vec<u8> = seq.iter().chain(&seq[..SEED_K - 1]).cloned().collect();
+    for (pos, kmer) in doubled.windows(SEED_K).enumerate() {
+        if let Some(&seed_pos) = kmers.get(kmer) {
+            votes[(pos as i64 - seed_pos as i64).rem_euclid(seed_len as i64) as usize] += 1;
+        }
+    }
+    let origin = (0..votes.len()).fold(0, |best, origin| if votes[origin] > votes[best] { origin } else { best });
+    match votes[origin] {
+        0 => None,
+        count if origin < seq.len() => Some((origin, count)),
+        count => Some((0, count)),
+    }
+}
+
+// the consensus without its longest suffix of at least min_overlap bases, and at most half its
+// length, that repeats its prefix
+fn close_circle(mut consensus: Vec<u8>, min_overlap: usize) -> Vec<u8> {
+    let overlap = (min_overlap.max(1)..=consensus.len() / 2).rev().find(|&len| consensus[consensus.len() - len..] == consensus[..len]);
+    if let Some(overlap) = overlap {
+        consensus.truncate(consensus.len() - overlap);
+    }
+    consensus
+}
+
+/// Finds how to turn each read of a circular molecule so that all of them start at a common
+/// origin.
+///
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `origin` - the origin to rotate the reads to
+///
+/// # Returns
+/// * returns the rotation of each read, in input order; `None` for the reads that cannot be
+///   placed, such as empty reads, reads without the anchor, or reads sharing no k-mer with the seed
+///
+/// # Panics
+/// Panics if the anchor of `CircularOrigin::Anchor` is empty.
+pub fn circular_rotations<T: AsRef<[u8]>>(seqs: &[T], origin: &CircularOrigin) -> Vec<Option<Rotation>> {
+    let seqs: Vec<&[u8]> = seqs.iter().map(|seq| seq.as_ref()).map(|seq| seq.strip_suffix(b"\0").unwrap_or(seq)).collect();
+    match origin {
+        CircularOrigin::Anchor(anchor) => {
+            assert!(!anchor.is_empty(), "the anchor of a circular origin is empty");
+            let reverse_anchor = reverse_complement(anchor);
+            seqs.iter()
+                .map(|seq| match find_circular(seq, anchor) {
+                    Some(offset) => Some(Rotation { strand: Strand::Forward, offset }),
+                    // the anchor on the reverse strand, read on that strand, starts where its
+                    // reverse complement ends on this one
+                    None => find_circular(seq, &reverse_anchor)
+                        .map(|pos| Rotation { strand: Strand::Reverse, offset: (2 * seq.len() - pos - anchor.len()) % seq.len() }),
+                })
+                .collect()
+        }
+        CircularOrigin::Seed => {
+            let seed = match seqs.iter().position(|seq| !seq.is_empty()) {
+                Some(seed) => seed,
+                None => return vec![None; seqs.len()],
+            };
+            let kmers = unique_kmers(seqs[seed]);
+            let seed_len = seqs[seed].len();
+            seqs.iter()
+                .enumerate()
+                .map(|(i, seq)| {
+                    if i == seed {
+                        return Some(Rotation { strand: Strand::Forward, offset: 0 });
+                    }
+                    let forward = seed_offset(&kmers, seed_len, seq);
+                    let reverse = seed_offset(&kmers, seed_len, &reverse_complement(seq));
+                    match (forward, reverse) {
+                        (Some((_, forward_votes)), Some((offset, reverse_votes))) if reverse_votes > forward_votes => {
+                            Some(Rotation { strand: Strand::Reverse, offset })
+                        }
+                        (None, Some((offset, _))) => Some(Rotation { strand: Strand::Reverse, offset }),
+                        (forward, _) => forward.map(|(offset, _)| Rotation { strand: Strand::Forward, offset }),
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// Generates the consensus of the reads of a circular molecule, rotating each read to a common
+/// origin before it is added.
+///
+/// The reads are turned as `circular_rotations` finds, so the consensus starts at the origin, and
+/// bases of reads overrunning the origin that repeat the start of the consensus at its end are
+/// removed. Global or semi-global alignment keeps the ends of the rotated reads together best.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `params` - the alignment and scoring parameters
+/// * `origin` - the origin to rotate the reads to
+///
+/// # Returns
+/// * returns the closed consensus starting at the origin, empty if no read could be placed, and
+///   the rotation of each read
+///
+/// # Panics
+/// Panics if the anchor of `CircularOrigin::Anchor` is empty.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_circular, CircularOrigin, PoaParams, Strand};
+///
+///     let molecule = "GATTACAGGCTTACCGATCGTTAGC";
+///     // reads of the molecule starting at different positions, one from the reverse strand
+///     let seqs = vec!["GATTACAGGCTTACCGATCGTTAGC", "CCGATCGTTAGCGATTACAGGCTTA", "CTGTAATCGCTAACGATCGGTAAGC"];
+///     let params = PoaParams::new(1, 5, -4, -8, -6);
+///
+///     let circular = poa_consensus_circular(&seqs, &params, &CircularOrigin::Anchor(b"GATTACA".to_vec()));
+///     assert_eq!(circular.consensus, molecule.as_bytes().to_vec());
+///     assert_eq!(circular.rotations[1].unwrap().offset, 12);
+///     assert_eq!(circular.rotations[2].unwrap().strand, Strand::Reverse);
+/// ```
+pub fn poa_consensus_circular<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams, origin: &CircularOrigin) -> CircularConsensus {
+    let rotations = circular_rotations(seqs, origin);
+    let mut graph = PoaGraph::new(params);
+    for (seq, rotation) in seqs.iter().zip(&rotations) {
+        if let Some(rotation) = rotation {
+            let seq = seq.as_ref();
+            graph.add_sequence(&rotation.apply(seq.strip_suffix(b"\0").unwrap_or(seq)));
+        }
+    }
+
+    let min_overlap = match origin {
+        CircularOrigin::Anchor(anchor) => anchor.len(),
+        CircularOrigin::Seed => SEED_K,
+    };
+    CircularConsensus { consensus: close_circle(graph.consensus(), min_overlap), rotations }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the read of length len starting at start around the circle of molecule
+    fn circular_read(molecule: &str, start: usize, len: usize) -> Vec<u8> {
+        molecule.bytes().cycle().skip(start).take(len).collect()
+    }
+
+    #[test]
+    fn test_seed_origin() {
+        let molecule = "ATGCGTACGTTAGCCTAGGATCCGATCGATTTAGGCATGCAAGTCCGATAGCTTGACCA";
+        let len = molecule.len();
+        let mut seqs: Vec<Vec<u8>> = [0, 17, 33, 48].iter().map(|&start| circular_read(molecule, start, len)).collect();
+        seqs.push(reverse_complement(&circular_read(molecule, 25, len)));
+        // a read with an error, one ending before the origin and an empty one
+        seqs[2][10] = b'T';
+        seqs.push(circular_read(molecule, 5, len - 8));
+        seqs.push(vec![]);
+
+        let circular = poa_consensus_circular(&seqs, &PoaParams::new(1, 5, -4, -8, -6), &CircularOrigin::Seed);
+        assert_eq!(circular.consensus, molecule.as_bytes().to_vec());
+        assert_eq!(circular.rotations[1], Some(Rotation { strand: Strand::Forward, offset: len - 17 }));
+        assert_eq!(circular.rotations[4].unwrap().strand, Strand::Reverse);
+        assert_eq!(circular.rotations[5], Some(Rotation { strand: Strand::Forward, offset: 0 }));
+        assert_eq!(circular.rotations[6], None);
+
+        // the rotated reads all start at the origin
+        for (seq, rotation) in seqs.iter().zip(&circular.rotations).take(5) {
+            assert_eq!(&rotation.unwrap().apply(seq)[..20], &molecule.as_bytes()[..20]);
+        }
+    }
+
+    #[test]
+    fn test_anchor_origin() {
+        assert_eq!(find_circular(b"ACGTTG", b"TGA"), Some(4));
+        assert_eq!(find_circular(b"AC", b"ACG"), None);
+        assert_eq!(close_circle(b"ACGTTTACG".to_vec(), 3), b"ACGTTT".to_vec());
+        assert_eq!(close_circle(b"ACGTTTAC".to_vec(), 3), b"ACGTTTAC".to_vec());
+
+        // the anchor wrapping around the end, on either strand
+        let seqs = ["ACAGGCTTAGATT", "AATCTAAGCCTGT", "GGCTTAC"];
+        let rotations = circular_rotations(&seqs, &CircularOrigin::Anchor(b"GATTACA".to_vec()));
+        assert_eq!(rotations[0], Some(Rotation { strand: Strand::Forward, offset: 9 }));
+        assert_eq!(rotations[0].unwrap().apply(seqs[0].as_bytes()), b"GATTACAGGCTTA".to_vec());
+        assert_eq!(rotations[1].unwrap().apply(seqs[1].as_bytes()), b"GATTACAGGCTTA".to_vec());
+        assert_eq!(rotations[2], None);
+        assert_eq!(circular_rotations(&[""], &CircularOrigin::Seed), vec![None]);
+    }
+}
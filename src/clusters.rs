@@ -0,0 +1,257 @@
+//! Splitting the reads of a graph into clusters with one consensus each, for reads of mixed
+//! haplotypes or isoforms, whose single consensus would be a chimera.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::graph::Topology;
+use crate::{Edge, PoaGraph, PoaParams};
+
+/// The consensus of one cluster of the reads of a graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClusterConsensus {
+    /// the consensus of the reads of the cluster
+    pub consensus: Vec<u8>,
+    /// the indices of the reads of the cluster, increasing
+    pub reads: Vec<usize>,
+}
+
+// the path and base weights of each sequence of a graph
+type Paths = [(Vec<u32>, Vec<u32>)];
+
+// the edges along the paths of the given sequences, weighted as in SPOA by the summed weight of
+// the bases at both ends, with the number of the sequences passing along each
+fn read_edges(paths: &Paths, reads: &[usize]) -> HashMap<(u32, u32), (u64, usize)> {
+    let mut edges = HashMap::new();
+    for &read in reads {
+        let (path, weights) = &paths[read];
+        for i in 1..path.len() {
+            let edge = edges.entry((path[i - 1], path[i])).or_insert((0, 0));
+            edge.0 += u64::from(weights[i - 1]) + u64::from(weights[i]);
+            edge.1 += 1;
+        }
+    }
+    edges
+}
+
+// the heaviest path along the edges of the given sequences, the first one in topological order
+// among equals
+fn heaviest_path(num_nodes: usize, paths: &Paths, reads: &[usize]) -> Vec<u32> {
+    let mut edges: Vec<Edge> = read_edges(paths, reads)
+        .into_iter()
+        .map(|((from, to), (weight, _))| Edge { from, to, weight, labels: vec![] })
+        .collect();
+    edges.sort_by_key(|edge| (edge.from, edge.to));
+    let topology = Topology::new(num_nodes, &edges);
+
+    let mut scores = vec![0u64; num_nodes];
+    let mut predecessors: Vec<Option<u32>> = vec![None; num_nodes];
+    let mut end: Option<u32> = None;
+    for &v in &topology.order {
+        for &(u, weight) in &topology.in_edges[v as usize] {
+            if predecessors[v as usize].is_none() || scores[u as usize] + weight > scores[v as usize] {
+                scores[v as usize] = scores[u as usize] + weight;
+                predecessors[v as usize] = Some(u);
+            }
+        }
+        let heavier = match end {
+            Some(end) => scores[v as usize] > scores[end as usize],
+            None => true,
+        };
+        if heavier && predecessors[v as usize].is_some() {
+            end = Some(v);
+        }
+    }
+    // reads of a single base have no edges
+    let mut end = match end {
+        Some(end) => end,
+        None => return reads.iter().filter_map(|&read| paths[read].0.first().copied()).take(1).collect(),
+    };
+
+    let mut path = vec![end];
+    while let Some(u) = predecessors[end as usize] {
+        path.push(u);
+        end = u;
+    }
+    path.reverse();
+    path
+}
+
+// whether a sequence path passes along an edge
+fn follows(path: &[u32], edge: (u32, u32)) -> bool {
+    path.windows(2).any(|pair| (pair[0], pair[1]) == edge)
+}
+
+// the edge off the heaviest path of the given sequences that the most of them follow, at least
+// min_reads but not all of them, the first one among equals
+fn strongest_variant(num_nodes: usize, paths: &Paths, reads: &[usize], min_reads: usize) -> Option<(u32, u32)> {
+    let path = heaviest_path(num_nodes, paths, reads);
+    let on_path: HashMap<u32, usize> = path.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+    let leaves_path = |from: u32, to: u32| match (on_path.get(&from), on_path.get(&to)) {
+        (Some(&i), Some(&j)) => j != i + 1,
+        _ => true,
+    };
+
+    let mut variants: Vec<((u32, u32), usize)> = read_edges(paths, reads)
+        .into_iter()
+        .filter(|&((from, to), (_, count))| count >= min_reads.max(1) && count < reads.len() && leaves_path(from, to))
+        .map(|(edge, (_, count))| (edge, count))
+        .collect();
+    variants.sort_unstable_by_key(|&(edge, count)| (Reverse(count), edge));
+    variants.first().map(|&(edge, _)| edge)
+}
+
+impl PoaGraph {
+    /// Splits the reads of the graph into up to `max_clusters` clusters, e.g. by haplotype or
+    /// isoform, and returns the consensus of each, the cluster of the most reads first.
+    ///
+    /// Each cluster starts out with the reads not yet clustered. An edge leaving the heaviest path
+    /// along their edges that at least `min_reads` of them follow marks a variant of another
+    /// cluster, such as the other allele of a SNP or an exon the path skips, so the reads following
+    /// the most followed such edge are left for later clusters, until the reads of the cluster
+    /// share no such variant; its consensus is the heaviest path along their edges. The last
+    /// cluster takes all remaining reads. `min_reads` should be above the number of reads
+    /// expected to share a sequencing error, or errors split clusters too.
+    ///
+    /// Empty reads belong to no cluster.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+    ///     for seq in ["ACGTTGCAAC", "ACGTAGCATC", "ACGTTGCAAC", "ACGTAGCATC", "ACGTTGCAAC"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     let clusters = graph.consensus_clusters(2, 2);
+    ///     assert_eq!(clusters[0].consensus, b"ACGTTGCAAC".to_vec());
+    ///     assert_eq!(clusters[0].reads, vec![0, 2, 4]);
+    ///     assert_eq!(clusters[1].consensus, b"ACGTAGCATC".to_vec());
+    ///     assert_eq!(clusters[1].reads, vec![1, 3]);
+    /// ```
+    pub fn consensus_clusters(&self, max_clusters: usize, min_reads: usize) -> Vec<ClusterConsensus> {
+        let nodes = self.nodes();
+        let paths: Vec<(Vec<u32>, Vec<u32>)> =
+            (0..self.num_sequences()).map(|read| (self.sequence_path(read), self.sequence_weights(read))).collect();
+        let mut remaining: Vec<usize> = (0..paths.len()).filter(|&read| !paths[read].0.is_empty()).collect();
+
+        let mut clusters = vec![];
+        while !remaining.is_empty() && clusters.len() < max_clusters {
+            let mut reads = std::mem::take(&mut remaining);
+            if clusters.len() + 1 < max_clusters {
+                while let Some(variant) = strongest_variant(nodes.len(), &paths, &reads, min_reads) {
+                    let (carried, kept) = reads.iter().partition(|&&read| follows(&paths[read].0, variant));
+                    reads = kept;
+                    remaining.extend::<Vec<usize>>(carried);
+                }
+                remaining.sort_unstable();
+            }
+
+            let consensus = heaviest_path(nodes.len(), &paths, &reads).iter().map(|&id| nodes[id as usize].base).collect();
+            clusters.push(ClusterConsensus { consensus, reads });
+        }
+        clusters.sort_by_key(|cluster| Reverse(cluster.reads.len()));
+        clusters
+    }
+}
+
+/// Generates one consensus per cluster of reads, for reads of mixed haplotypes or isoforms.
+///
+/// Empty reads and trailing null terminators are ignored, as by `poa_consensus_with_matrix`; the
+/// clusters are found as by `PoaGraph::consensus_clusters`.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `params` - the alignment and scoring parameters
+/// * `max_clusters` - the maximum number of clusters
+/// * `min_reads` - the number of reads that must share a variant to split them off into another cluster
+///
+/// # Returns
+/// * returns the consensus of each cluster, the one of the most reads first, with the indices of its
+///   reads in `seqs`
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_clusters, PoaParams};
+///
+///     let mut reads = vec!["ACGTTGCAACGGTA"; 4];
+///     reads.extend(vec!["ACGTTGCGTCAACGGTA"; 3]);
+///     let clusters = poa_consensus_clusters(&reads, &PoaParams::new(1, 5, -4, -8, -6), 3, 2);
+///
+///     assert_eq!(clusters.len(), 2);
+///     assert_eq!(clusters[1].consensus, b"ACGTTGCGTCAACGGTA".to_vec());
+///     assert_eq!(clusters[1].reads, vec![4, 5, 6]);
+/// ```
+pub fn poa_consensus_clusters<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams, max_clusters: usize, min_reads: usize) -> Vec<ClusterConsensus> {
+    let mut graph = PoaGraph::new(params);
+    let mut indices = vec![];
+    for (i, seq) in seqs.iter().enumerate() {
+        let seq = seq.as_ref();
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        if !seq.is_empty() {
+            graph.add_sequence(seq);
+            indices.push(i);
+        }
+    }
+
+    let mut clusters = graph.consensus_clusters(max_clusters, min_reads);
+    for cluster in &mut clusters {
+        for read in &mut cluster.reads {
+            *read = indices[*read];
+        }
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two haplotypes differing at a SNP near each end, and by a deletion in the middle
+    const HAPLOTYPES: [&[u8]; 2] = [b"GATTACAGGCTTACCGATCGATTGCAGCTAGGCATCAAGT", b"GATTACAGGCGTACCGATCGATTGCTAGGCATCAATGT"];
+
+    fn reads() -> Vec<Vec<u8>> {
+        (0..11)
+            .map(|i| {
+                let mut read = HAPLOTYPES[i % 2].to_vec();
+                // a sequencing error in each read, at a different position
+                let pos = (3 * i + 2) % read.len();
+                read[pos] = if read[pos] == b'A' { b'C' } else { b'A' };
+                read
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_haplotypes() {
+        for alignment_type in 0..3 {
+            let clusters = poa_consensus_clusters(&reads(), &PoaParams::new(alignment_type, 5, -4, -8, -6), 4, 3);
+            assert_eq!(clusters.len(), 2, "{}", alignment_type);
+            assert_eq!(clusters[0].consensus, HAPLOTYPES[0].to_vec(), "{}", alignment_type);
+            assert_eq!(clusters[0].reads, vec![0, 2, 4, 6, 8, 10]);
+            assert_eq!(clusters[1].consensus, HAPLOTYPES[1].to_vec(), "{}", alignment_type);
+            assert_eq!(clusters[1].reads, vec![1, 3, 5, 7, 9]);
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+
+        // one cluster holds every read, and errors shared by fewer than min_reads reads split nothing
+        let one = poa_consensus_clusters(&reads(), &params, 1, 3);
+        assert_eq!(one.len(), 1);
+        assert_eq!(one[0].reads, (0..11).collect::<Vec<_>>());
+        let same: Vec<&[u8]> = vec![HAPLOTYPES[0]; 5];
+        assert_eq!(poa_consensus_clusters(&same, &params, 3, 2).len(), 1);
+
+        // empty reads are skipped, and indices refer to the input
+        let clusters = poa_consensus_clusters(&["", "A\0", "A"], &params, 2, 1);
+        assert_eq!(clusters, vec![ClusterConsensus { consensus: b"A".to_vec(), reads: vec![1, 2] }]);
+        assert!(poa_consensus_clusters(&Vec::<Vec<u8>>::new(), &params, 2, 1).is_empty());
+        assert!(PoaGraph::new(&params).consensus_clusters(0, 1).is_empty());
+    }
+}
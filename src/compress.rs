@@ -0,0 +1,165 @@
+//! Consensus of homopolymer-compressed reads, for nanopore data whose errors are mostly in the
+//! lengths of homopolymer runs.
+//!
+//! Each read is collapsed to one base per run before it is added to the graph, so the alignment
+//! only has to get the order of the bases right; the length of each run of the consensus is then
+//! taken from the reads supporting it.
+
+use crate::{PoaGraph, PoaParams};
+
+/// Collapses each run of equal bases of a sequence to one base.
+///
+/// Bases are compared as given, so `aA` is two runs.
+///
+/// # Returns
+/// * returns the compressed sequence and the length of the run of each of its bases
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::homopolymer_compress;
+///
+///     assert_eq!(homopolymer_compress(b"AATGCCCGTT"), (b"ATGCGT".to_vec(), vec![2, 1, 1, 3, 1, 2]));
+/// ```
+pub fn homopolymer_compress(seq: &[u8]) -> (Vec<u8>, Vec<u32>) {
+    let mut bases: Vec<u8> = vec![];
+    let mut runs: Vec<u32> = vec![];
+    for &base in seq {
+        match (bases.last(), runs.last_mut()) {
+            (Some(&last), Some(run)) if last == base => *run += 1,
+            _ => {
+                bases.push(base);
+                runs.push(1);
+            }
+        }
+    }
+    (bases, runs)
+}
+
+/// A consensus generated from homopolymer-compressed reads, as returned by
+/// `poa_consensus_compressed`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompressedConsensus {
+    /// the consensus with each run expanded to its estimated length
+    pub consensus: Vec<u8>,
+    /// the consensus of the compressed reads, one base per run
+    pub compressed: Vec<u8>,
+    /// the estimated length of each run of `compressed`
+    pub run_lengths: Vec<u32>,
+    /// the number of reads supporting each run of `compressed`, which its length was estimated from
+    pub support: Vec<u32>,
+}
+
+/// Generates a consensus sequence from homopolymer-compressed reads and expands each of its runs to
+/// the median length of the runs of the reads supporting it.
+///
+/// A read supports a run of the consensus if it has the same base in its alignment column. The
+/// median of an even number of lengths is the higher of the middle two; a run no read supports
+/// keeps length 1. Empty reads and trailing null terminators are ignored.
+/// # Arguments
+///
+/// * `seqs` - the reads, uncompressed, with or without null terminators
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the expanded consensus, with the compressed consensus and its run lengths
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_compressed, PoaParams};
+///
+///     // the reads disagree only on the lengths of the runs of C and T
+///     let reads = ["AATGCCCCGTT", "AATGCCCGTTT", "AATGCCCCGTT", "AATGCCGTT"];
+///     let result = poa_consensus_compressed(&reads, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(result.compressed, b"ATGCGT".to_vec());
+///     assert_eq!(result.run_lengths, vec![2, 1, 1, 4, 1, 2]);
+///     assert_eq!(result.consensus, b"AATGCCCCGTT".to_vec());
+/// ```
+pub fn poa_consensus_compressed<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams) -> CompressedConsensus {
+    let mut graph = PoaGraph::new(params);
+    let mut read_runs = vec![];
+    for seq in seqs {
+        let seq = seq.as_ref();
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        if !seq.is_empty() {
+            let (bases, runs) = homopolymer_compress(seq);
+            graph.add_sequence(&bases);
+            read_runs.push(runs);
+        }
+    }
+
+    let msa = graph.multiple_sequence_alignment(true);
+    let (consensus, rows) = match msa.split_last() {
+        Some(rows) => rows,
+        None => return CompressedConsensus::default(),
+    };
+    let columns: Vec<usize> = (0..consensus.len()).filter(|&c| consensus[c] != b'-').collect();
+
+    // the run lengths of the reads supporting each consensus column
+    let mut supporting = vec![vec![]; consensus.len()];
+    for (row, runs) in rows.iter().zip(&read_runs) {
+        let mut runs = runs.iter();
+        for (c, &base) in row.iter().enumerate().filter(|&(_, &base)| base != b'-') {
+            let run = *runs.next().expect("msa row has more bases than its read");
+            if base == consensus[c] {
+                supporting[c].push(run);
+            }
+        }
+    }
+
+    let mut result = CompressedConsensus::default();
+    for c in columns {
+        let lengths = &mut supporting[c];
+        lengths.sort_unstable();
+        let length = lengths.get(lengths.len() / 2).copied().unwrap_or(1);
+        let expanded = result.consensus.len() + length as usize;
+        result.consensus.resize(expanded, consensus[c]);
+        result.compressed.push(consensus[c]);
+        result.run_lengths.push(length);
+        result.support.push(lengths.len() as u32);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compress() {
+        assert_eq!(homopolymer_compress(b""), (vec![], vec![]));
+        assert_eq!(homopolymer_compress(b"aAAAcN"), (b"aAcN".to_vec(), vec![1, 3, 1, 1]));
+    }
+
+    #[test]
+    fn test_homopolymer_errors() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let truth = b"GATTTTACAGGGGCTTACCGAAAAAT";
+        // each read gets one homopolymer run a base too short or too long
+        let reads: Vec<&[u8]> = vec![
+            b"GATTTACAGGGGCTTACCGAAAAAT\0",
+            b"GATTTTTACAGGGGCTTACCGAAAAAT",
+            b"GATTTTACAGGGCTTACCGAAAAAT",
+            b"GATTTTACAGGGGGCTTACCGAAAAAT",
+            b"GATTTTACAGGGGCTTACCGAAAAT",
+            b"GATTTTACAGGGGCTTACCGAAAAAAT",
+            b"GATTTTACAGGGGCTTACCGAAAAAT",
+            b"",
+        ];
+        let result = poa_consensus_compressed(&reads, &params);
+        assert_eq!(result.consensus, truth.to_vec());
+        assert_eq!(result.compressed, homopolymer_compress(truth).0);
+        assert_eq!(result.run_lengths, homopolymer_compress(truth).1);
+        assert!(result.support.iter().all(|&support| support == 7));
+    }
+
+    #[test]
+    fn test_single_read() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        assert_eq!(poa_consensus_compressed::<&str>(&[], &params), CompressedConsensus::default());
+        let result = poa_consensus_compressed(&["AAACGT"], &params);
+        assert_eq!((result.consensus, result.support), (b"AAACGT".to_vec(), vec![1; 4]));
+    }
+}
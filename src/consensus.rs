@@ -0,0 +1,131 @@
+//! Generating consensus sequences from sets of reads, and reporting on how they were built.
+//!
+//! The plain entry points are [`try_poa_consensus`] and [`poa_consensus_with_support`]; the other
+//! functions add validation, growth limits, quality values, resampling, retries or batching on top.
+
+use crate::PoaGraph;
+
+mod algorithm;
+mod sequence;
+
+pub use self::algorithm::ConsensusAlgorithm;
+pub use self::sequence::Consensus;
+pub use crate::alphabet::{validate_sequences, Alphabet};
+#[cfg(feature = "async")]
+pub use crate::asynchronous::{poa_consensus_async, ConsensusJobs};
+pub use crate::backbone::{
+    poa_consensus_guided, poa_consensus_on_backbone, poa_consensus_on_backbone_anchored, poa_consensus_on_backbone_region, poa_polish_backbone,
+    Correction, GuidedConsensus, PolishedBackbone, RegionConsensus,
+};
+pub use crate::cancel::{try_poa_consensus_cancellable, CancelToken};
+pub use crate::circular::{circular_rotations, poa_consensus_circular, CircularConsensus, CircularOrigin, Rotation};
+pub use crate::clusters::{poa_consensus_clusters, ClusterConsensus};
+pub use crate::compress::{homopolymer_compress, poa_consensus_compressed, CompressedConsensus};
+pub use crate::coverage::{mask_low_coverage, poa_consensus_trimmed, poa_consensus_with_min_coverage, trim_low_coverage_ends, LowCoverage};
+pub use crate::engine::ConsensusEngine;
+pub use crate::fallback::{poa_consensus_with_fallback, FallbackConsensus, FallbackPolicy};
+pub use crate::fast::small_group_consensus;
+pub use crate::filter::{poa_consensus_filtered, ReadFilter};
+pub use crate::groups::{
+    group_consensus_by, poa_consensus_batch, poa_consensus_batch_with_threads, poa_consensus_groups, poa_consensus_groups_with_options,
+    poa_consensus_of_groups, GroupBy, GroupConsensus, GroupFlush, GroupOptions, GroupSummary, KeyedConsensus, SmallGroups,
+};
+pub use crate::hierarchical::poa_consensus_hierarchical;
+pub use crate::hmm::{hmm_polish, HmmPolishOptions, HmmPolished};
+pub use crate::limits::{poa_consensus_with_limits, GrowthLimits};
+pub use crate::matrix::{poa_consensus_with_matrix, Matrix, SubstitutionMatrix};
+pub use crate::memory::estimate_memory;
+pub use crate::mode::{choose_alignment_type, AlignmentType};
+pub use crate::msa::{poa_consensus_with_ambiguity, poa_consensus_with_variants};
+pub use crate::ordering::{poa_consensus_ordered, OrderedConsensus, ReadOrder};
+pub use crate::orient::{poa_consensus_oriented, OrientedConsensus, Strand};
+pub use crate::polish::{polish, polish_contig, polish_from_paths, read_paf, PafRecord, PolishOptions};
+pub use crate::progress::{try_poa_consensus_with_progress, ProgressEvent};
+pub use crate::protein::try_poa_consensus_protein;
+pub use crate::qc::{poa_consensus_with_read_qc, ReadQc};
+pub use crate::quality::{calibration_samples, poa_consensus_with_quality, CalibrationSample, Platform, QualityModel};
+pub use crate::record::{poa_consensus_of_records, poa_consensus_with_qualities, poa_consensus_with_read_weights, SeqRecord};
+pub use crate::reject::{poa_consensus_outcome, poa_consensus_with_report, ConsensusOutcome, ConsensusReport, RejectReason, RejectedRead, RejectionLog};
+pub use crate::resample::{poa_concordance, poa_consensus_bootstrap, BootstrapConsensus, Concordance};
+pub use crate::streaming::{poa_consensus_until_stable, StableConsensus, StreamingConsensus};
+pub use crate::train::{consensus_accuracy, train_params, ParameterGrid, ParameterSearch, TrainedParams, TrainingGroup};
+pub use crate::windowed::{poa_consensus_windowed, WindowedOptions};
+#[allow(deprecated)]
+pub use crate::{
+    estimate_consensus_len, estimate_consensus_len_with_margin, poa_consensus, poa_consensus_unbounded, poa_consensus_with_coverage,
+    poa_consensus_with_support, try_poa_consensus, try_poa_consensus_with_alphabet, ConvexGaps, CoverageHistogram, GapModel, HomopolymerGaps,
+    PoaError, PoaParams, SimdBackend,
+};
+
+/// Generating the consensus of a collection of reads with a method call, e.g. `reads.consensus(&params)?`.
+///
+/// This is implemented for slices (and so for vectors) of sequences, with or without null
+/// terminators, and of `SeqRecord`s, whose qualities and weights are used. Empty reads are left
+/// out. For limits, reports or other options, use the functions of this module.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{ConsensusExt, PoaError, PoaParams};
+///
+///     let params = PoaParams::new(1, 5, -4, -3, -1);
+///     let reads = vec!["AATGCCCGTT", "AATGCCGTT", "AATGCCCGTT"];
+///     let consensus = reads.consensus(&params)?;
+///     assert_eq!(consensus.as_str()?, "AATGCCCGTT");
+///
+///     let none: Vec<Vec<u8>> = vec![vec![]];
+///     assert_eq!(none.consensus(&params), Err(PoaError::EmptyInput));
+///     # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub trait ConsensusExt {
+    /// Generates the consensus of the reads, or fails if there are no non-empty reads.
+    fn consensus(&self, params: &PoaParams) -> Result<Consensus, PoaError>;
+}
+
+impl<T: AsRef<[u8]>> ConsensusExt for [T] {
+    fn consensus(&self, params: &PoaParams) -> Result<Consensus, PoaError> {
+        let mut graph = PoaGraph::new(params);
+        for read in self {
+            let read = read.as_ref();
+            let read = read.strip_suffix(b"\0").unwrap_or(read);
+            if !read.is_empty() {
+                graph.add_sequence(read);
+            }
+        }
+        graph_consensus(&graph)
+    }
+}
+
+impl ConsensusExt for [SeqRecord] {
+    fn consensus(&self, params: &PoaParams) -> Result<Consensus, PoaError> {
+        let mut graph = PoaGraph::new(params);
+        for record in self.iter().filter(|record| !record.seq.is_empty()) {
+            graph.add_record(record);
+        }
+        graph_consensus(&graph)
+    }
+}
+
+fn graph_consensus(graph: &PoaGraph) -> Result<Consensus, PoaError> {
+    if graph.num_sequences() == 0 {
+        return Err(PoaError::EmptyInput);
+    }
+    Ok(Consensus::new(graph.consensus()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consensus_ext() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let seqs: Vec<Vec<u8>> = vec![b"AACGT\0".to_vec(), b"AACGT".to_vec(), b"ACCGT\0".to_vec()];
+        assert_eq!(seqs.consensus(&params).unwrap(), b"AACGT".to_vec());
+        assert_eq!(seqs[..2].consensus(&params).unwrap(), b"AACGT".to_vec());
+
+        let records = [SeqRecord::new("r1", b"AACGT".to_vec()), SeqRecord::new("r2", b"ACCGT".to_vec()).weight(2.0)];
+        assert_eq!(records.consensus(&params).unwrap(), b"ACCGT".to_vec());
+        assert_eq!(records[..0].consensus(&params), Err(PoaError::EmptyInput));
+    }
+}
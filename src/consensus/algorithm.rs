@@ -0,0 +1,101 @@
+//! The ways a consensus can be read off a built graph.
+
+use std::fmt;
+
+use crate::msa::Majority;
+use crate::PoaGraph;
+
+/// How the consensus is extracted from a graph once all reads are added.
+///
+/// The algorithms agree on well-covered groups with few errors, but differ where coverage is low
+/// or indels are common: the heaviest bundle follows the heaviest edge into each node, the
+/// maximum-weight path maximizes the summed weight of all its edges, which favours longer paths
+/// through insertions, and the column majority lets every read spanning an MSA column vote on it.
+/// Each breaks ties by fixed rules, listed by `PoaParams::reproducible`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ConsensusAlgorithm {
+    /// the heaviest bundle traversal of SPOA, as used by `PoaGraph::consensus`
+    #[default]
+    HeaviestBundle,
+    /// the source-to-sink path with the largest summed edge weight
+    MaxWeightPath,
+    /// the most common symbol of each column of the multiple sequence alignment, gaps left out
+    ColumnMajority,
+}
+
+impl ConsensusAlgorithm {
+    /// Every algorithm, for comparing them on the same graph.
+    pub const ALL: [ConsensusAlgorithm; 3] =
+        [ConsensusAlgorithm::HeaviestBundle, ConsensusAlgorithm::MaxWeightPath, ConsensusAlgorithm::ColumnMajority];
+
+    /// Returns a short snake_case name for the algorithm, e.g. for report columns.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConsensusAlgorithm::HeaviestBundle => "heaviest_bundle",
+            ConsensusAlgorithm::MaxWeightPath => "max_weight_path",
+            ConsensusAlgorithm::ColumnMajority => "column_majority",
+        }
+    }
+}
+
+impl fmt::Display for ConsensusAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl PoaGraph {
+    /// Returns the consensus of the graph extracted with `algorithm`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{ConsensusAlgorithm, PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     for seq in ["AATGCCCGTT", "AATGCCGTT", "AATGCCCGTT"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     for &algorithm in ConsensusAlgorithm::ALL.iter() {
+    ///         assert_eq!(graph.consensus_by(algorithm), b"AATGCCCGTT".to_vec(), "{}", algorithm);
+    ///     }
+    /// ```
+    pub fn consensus_by(&self, algorithm: ConsensusAlgorithm) -> Vec<u8> {
+        match algorithm {
+            ConsensusAlgorithm::HeaviestBundle => self.consensus(),
+            ConsensusAlgorithm::MaxWeightPath => self.heaviest_paths(1).pop().map_or_else(Vec::new, |path| path.sequence),
+            ConsensusAlgorithm::ColumnMajority => self.column_consensus(&Majority),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PoaParams;
+
+    #[test]
+    fn test_algorithms_differ_on_insertions() {
+        // one read in three carries an insertion; the path through it sums more edge weight
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        for seq in ["AATGCCGTT", "AATGCCGTT", "AATGCTTTTCGTT"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        let consensus: Vec<Vec<u8>> = ConsensusAlgorithm::ALL.iter().map(|&algorithm| graph.consensus_by(algorithm)).collect();
+
+        assert_eq!(consensus[0], b"AATGCCGTT".to_vec());
+        assert_eq!(consensus[2], b"AATGCCGTT".to_vec());
+        assert_eq!(consensus[1].len(), 13);
+    }
+
+    #[test]
+    fn test_empty_graph_and_names() {
+        let graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        for &algorithm in ConsensusAlgorithm::ALL.iter() {
+            assert!(graph.consensus_by(algorithm).is_empty());
+        }
+        assert_eq!(ConsensusAlgorithm::default().to_string(), "heaviest_bundle");
+        assert_eq!(ConsensusAlgorithm::ColumnMajority.as_str(), "column_majority");
+    }
+}
@@ -0,0 +1,135 @@
+//! A consensus sequence with the helpers callers commonly need.
+
+use std::ops::Deref;
+use std::str::{self, Utf8Error};
+
+use crate::seq::reverse_complement;
+use crate::SeqRecord;
+
+/// The bases of a consensus sequence, without a null terminator.
+///
+/// It dereferences to `[u8]`, so it can be used wherever a byte slice is expected.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Consensus(Vec<u8>);
+
+impl Consensus {
+    /// Wraps the bases of a consensus.
+    pub fn new(bases: Vec<u8>) -> Consensus {
+        Consensus(bases)
+    }
+
+    /// Returns the bases as a byte slice.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the bases, consuming the consensus.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    /// Returns the bases as a string slice, or an error if they are not valid UTF-8.
+    pub fn as_str(&self) -> Result<&str, Utf8Error> {
+        str::from_utf8(&self.0)
+    }
+
+    /// Returns the bases as an owned string, or an error if they are not valid UTF-8.
+    #[allow(clippy::inherent_to_string)]
+    pub fn to_string(&self) -> Result<String, Utf8Error> {
+        self.as_str().map(str::to_string)
+    }
+
+    /// Returns the number of bases.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns whether the consensus has no bases.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the fraction of bases that are G or C, in either case, or 0 for an empty consensus.
+    pub fn gc_content(&self) -> f64 {
+        if self.0.is_empty() {
+            return 0.0;
+        }
+        let gc = self.0.iter().filter(|&&base| matches!(base, b'G' | b'C' | b'g' | b'c')).count();
+        gc as f64 / self.0.len() as f64
+    }
+
+    /// Returns the reverse complement of the consensus, complementing IUPAC ambiguity codes too.
+    pub fn reverse_complement(&self) -> Consensus {
+        Consensus(reverse_complement(&self.0))
+    }
+
+    /// Returns the consensus as a record named `name`, without qualities.
+    pub fn into_record<S: Into<String>>(self, name: S) -> SeqRecord {
+        SeqRecord::new(name, self.0)
+    }
+}
+
+impl Deref for Consensus {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Consensus {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Consensus {
+    fn from(bases: Vec<u8>) -> Consensus {
+        Consensus(bases)
+    }
+}
+
+impl From<Consensus> for Vec<u8> {
+    fn from(consensus: Consensus) -> Vec<u8> {
+        consensus.0
+    }
+}
+
+impl PartialEq<[u8]> for Consensus {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&[u8]> for Consensus {
+    fn eq(&self, other: &&[u8]) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialEq<Vec<u8>> for Consensus {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        &self.0 == other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_helpers() {
+        let consensus = Consensus::new(b"AACGTg".to_vec());
+
+        assert_eq!(consensus.len(), 6);
+        assert_eq!(consensus.as_str(), Ok("AACGTg"));
+        assert_eq!(consensus.to_string().unwrap(), "AACGTg".to_string());
+        assert!((consensus.gc_content() - 0.5).abs() < 1e-12);
+        assert_eq!(consensus.reverse_complement(), b"cACGTT".to_vec());
+        assert_eq!(&consensus[1..3], b"AC");
+        assert_eq!(consensus.clone().into_record("c1"), SeqRecord::new("c1", b"AACGTg".to_vec()));
+
+        assert!(Consensus::new(vec![0xff]).as_str().is_err());
+        assert_eq!(Consensus::default().gc_content(), 0.0);
+    }
+}
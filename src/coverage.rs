@@ -0,0 +1,333 @@
+//! Summaries of the per-position depth of a consensus, and masking or trimming of the positions
+//! few reads support.
+
+use std::ops::Range;
+
+use crate::{poa_consensus_with_coverage, PoaGraph, PoaParams};
+
+/// The distribution of depths over the positions of a consensus.
+///
+/// QC thresholds such as "at least 90% of positions at depth 10 or more" read as
+/// `histogram.fraction_at_least(10) >= 0.9`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoverageHistogram {
+    // counts[d] is the number of positions with depth d
+    counts: Vec<usize>,
+    positions: usize,
+}
+
+impl CoverageHistogram {
+    /// Builds the histogram of a per-position depth vector.
+    pub fn from_depths(depths: &[u32]) -> CoverageHistogram {
+        let max_depth = depths.iter().copied().max().unwrap_or(0) as usize;
+        let mut counts = vec![0; if depths.is_empty() { 0 } else { max_depth + 1 }];
+
+        for &depth in depths {
+            counts[depth as usize] += 1;
+        }
+
+        CoverageHistogram { counts, positions: depths.len() }
+    }
+
+    /// Returns the number of positions with each depth, indexed by depth.
+    pub fn counts(&self) -> &[usize] {
+        &self.counts
+    }
+
+    /// Returns the number of positions with exactly `depth`.
+    pub fn count(&self, depth: u32) -> usize {
+        self.counts.get(depth as usize).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of positions in the histogram.
+    pub fn positions(&self) -> usize {
+        self.positions
+    }
+
+    /// Returns the fraction of positions with a depth of at least `depth`, or 0 if there are none.
+    pub fn fraction_at_least(&self, depth: u32) -> f64 {
+        if self.positions == 0 {
+            return 0.0;
+        }
+        let covered: usize = self.counts.iter().skip(depth as usize).sum();
+        covered as f64 / self.positions as f64
+    }
+
+    /// Returns the `q` quantile of the depths (nearest rank), for `q` between 0 and 1.
+    ///
+    /// This is the smallest depth such that at least a fraction `q` of the positions have this
+    /// depth or less. An empty histogram has all quantiles 0.
+    pub fn quantile(&self, q: f64) -> u32 {
+        let rank = ((q.clamp(0.0, 1.0) * self.positions as f64).ceil() as usize).max(1);
+
+        let mut cumulative = 0;
+        for (depth, &count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= rank {
+                return depth as u32;
+            }
+        }
+        0
+    }
+
+    /// Returns the median depth.
+    pub fn median(&self) -> u32 {
+        self.quantile(0.5)
+    }
+
+    /// Returns the smallest depth, or 0 for an empty histogram.
+    pub fn min(&self) -> u32 {
+        self.quantile(0.0)
+    }
+
+    /// Returns the largest depth, or 0 for an empty histogram.
+    pub fn max(&self) -> u32 {
+        self.counts.len().saturating_sub(1) as u32
+    }
+
+    /// Returns the mean depth, or 0 for an empty histogram.
+    pub fn mean(&self) -> f64 {
+        if self.positions == 0 {
+            return 0.0;
+        }
+        let total: usize = self.counts.iter().enumerate().map(|(depth, &count)| depth * count).sum();
+        total as f64 / self.positions as f64
+    }
+}
+
+/// What to do with the consensus positions supported by too few reads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LowCoverage {
+    /// replace the base with the given symbol, e.g. `N` for nucleotides or `X` for proteins
+    Mask(u8),
+    /// leave the base out of the consensus
+    Drop,
+}
+
+/// Masks or drops the bases of a consensus supported by fewer than `min_coverage` reads.
+///
+/// # Panics
+/// Panics if `coverage` does not have the length of `consensus`.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{mask_low_coverage, LowCoverage};
+///
+///     let coverage = [1, 3, 3, 3, 2];
+///     assert_eq!(mask_low_coverage(b"GACGT", &coverage, 3, LowCoverage::Mask(b'N')), b"NACGN".to_vec());
+///     assert_eq!(mask_low_coverage(b"GACGT", &coverage, 3, LowCoverage::Drop), b"ACG".to_vec());
+/// ```
+pub fn mask_low_coverage(consensus: &[u8], coverage: &[u32], min_coverage: u32, low_coverage: LowCoverage) -> Vec<u8> {
+    assert_eq!(consensus.len(), coverage.len(), "one coverage value is needed per consensus base");
+    consensus
+        .iter()
+        .zip(coverage)
+        .filter_map(|(&base, &coverage)| match low_coverage {
+            _ if coverage >= min_coverage => Some(base),
+            LowCoverage::Mask(symbol) => Some(symbol),
+            LowCoverage::Drop => None,
+        })
+        .collect()
+}
+
+/// Generates a consensus sequence, masking or dropping the bases supported by fewer than `min_coverage` reads.
+///
+/// A read supports a consensus base as counted by `poa_consensus_with_coverage`, so a stray read
+/// extending past the others at either end leaves its bases masked rather than in the consensus.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `min_coverage` - the number of reads a base needs to be kept as it is
+/// * `low_coverage` - whether to mask or drop the other bases
+///
+/// # Returns
+/// * returns the consensus, with the bases below `min_coverage` masked or left out
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_min_coverage, LowCoverage, PoaParams};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCCGTT\0".to_vec(), b"AATGCCCGTTACGT\0".to_vec()];
+///     let params = PoaParams::new(2, 5, -4, -8, -6);
+///
+///     let masked = poa_consensus_with_min_coverage(&seqs, 0, &params, 2, LowCoverage::Mask(b'N'));
+///     assert_eq!(masked, b"AATGCCCGTTNNNN".to_vec());
+///     let dropped = poa_consensus_with_min_coverage(&seqs, 0, &params, 2, LowCoverage::Drop);
+///     assert_eq!(dropped, b"AATGCCCGTT".to_vec());
+/// ```
+pub fn poa_consensus_with_min_coverage<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    min_coverage: u32,
+    low_coverage: LowCoverage,
+) -> Vec<u8> {
+    let (consensus, coverage) = poa_consensus_with_coverage(seqs, consensus_max_length, params);
+    mask_low_coverage(&consensus, &coverage, min_coverage, low_coverage)
+}
+
+/// Returns the range of consensus positions left after trimming the bases supported by fewer
+/// than `min_support` reads off either end.
+///
+/// Reads rarely all start and end at the same position, so the bases at the ends of a consensus
+/// often rest on one or two reads. Only the ends are trimmed; poorly supported bases between
+/// better supported ones are kept, as `mask_low_coverage` can mask them.
+///
+/// # Returns
+/// * returns the trimmed range, empty (`0..0`) if no position has the support
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::trim_low_coverage_ends;
+///
+///     assert_eq!(trim_low_coverage_ends(&[1, 2, 5, 1, 6, 2], 3), 2..5);
+///     assert_eq!(trim_low_coverage_ends(&[1, 2], 3), 0..0);
+/// ```
+pub fn trim_low_coverage_ends(coverage: &[u32], min_support: u32) -> Range<usize> {
+    match coverage.iter().position(|&depth| depth >= min_support) {
+        Some(start) => {
+            let end = coverage.iter().rposition(|&depth| depth >= min_support).map_or(start, |last| last + 1);
+            start..end
+        }
+        None => 0..0,
+    }
+}
+
+impl PoaGraph {
+    /// Generates the consensus with the bases covered by fewer than `min_support` sequences
+    /// trimmed off either end, as by `trim_low_coverage_ends`.
+    ///
+    /// Coverage is counted as by `consensus_masked`.
+    ///
+    /// # Returns
+    /// * returns the trimmed consensus and its range in the untrimmed one
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(2, 5, -4, -8, -6));
+    ///     for seq in ["GGAATGCCCGTT", "AATGCCCGTT", "AATGCCCGTTACGT"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     let (consensus, range) = graph.consensus_trimmed(2);
+    ///     assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+    ///     assert_eq!(range, 2..12);
+    /// ```
+    pub fn consensus_trimmed(&self, min_support: u32) -> (Vec<u8>, Range<usize>) {
+        let nodes = self.nodes();
+        let path = self.consensus_path();
+        let coverage: Vec<u32> = path.iter().map(|&id| nodes[id as usize].coverage).collect();
+        let range = trim_low_coverage_ends(&coverage, min_support);
+        (path[range.clone()].iter().map(|&id| nodes[id as usize].base).collect(), range)
+    }
+}
+
+/// Generates a consensus sequence with the bases supported by fewer than `min_support` reads
+/// trimmed off either end.
+///
+/// A read supports a consensus base as counted by `poa_consensus_with_coverage`.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the untrimmed consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `min_support` - the number of reads the first and last base of the trimmed consensus need
+///
+/// # Returns
+/// * returns the trimmed consensus and its range in the untrimmed one
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_trimmed, PoaParams};
+///
+///     let seqs = vec![b"GGAATGCCCGTT\0".to_vec(), b"AATGCCCGTT\0".to_vec(), b"AATGCCCGTTACGT\0".to_vec()];
+///     let (consensus, range) = poa_consensus_trimmed(&seqs, 0, &PoaParams::new(2, 5, -4, -8, -6), 2);
+///
+///     assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(range, 2..12);
+/// ```
+pub fn poa_consensus_trimmed<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    min_support: u32,
+) -> (Vec<u8>, Range<usize>) {
+    let (consensus, coverage) = poa_consensus_with_coverage(seqs, consensus_max_length, params);
+    let range = trim_low_coverage_ends(&coverage, min_support);
+    (consensus[range.clone()].to_vec(), range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let histogram = CoverageHistogram::from_depths(&[3, 10, 12, 12, 10, 11, 12, 12, 9, 4]);
+
+        assert_eq!(histogram.positions(), 10);
+        assert_eq!(histogram.count(12), 4);
+        assert_eq!(histogram.count(5), 0);
+        assert_eq!(histogram.count(100), 0);
+        assert_eq!(histogram.min(), 3);
+        assert_eq!(histogram.max(), 12);
+        assert_eq!(histogram.median(), 10);
+        assert_eq!(histogram.quantile(0.9), 12);
+        assert!((histogram.mean() - 9.5).abs() < 1e-9);
+        assert!((histogram.fraction_at_least(10) - 0.7).abs() < 1e-9);
+        assert_eq!(histogram.fraction_at_least(0), 1.0);
+    }
+
+    #[test]
+    fn test_mask_low_coverage() {
+        assert_eq!(mask_low_coverage(b"ACGT", &[0, 1, 2, 3], 0, LowCoverage::Drop), b"ACGT".to_vec());
+        assert_eq!(mask_low_coverage(b"ACGT", &[0, 1, 2, 3], 4, LowCoverage::Mask(b'X')), b"XXXX".to_vec());
+        assert!(mask_low_coverage(b"", &[], 4, LowCoverage::Drop).is_empty());
+
+        // a single read at the start of the reads is masked
+        let seqs = vec![b"GGGAATGCCCGTT\0".to_vec(), b"AATGCCCGTT\0".to_vec(), b"AATGCACGTT\0".to_vec()];
+        let params = PoaParams::new(2, 5, -4, -8, -6);
+        let masked = poa_consensus_with_min_coverage(&seqs, 0, &params, 2, LowCoverage::Mask(b'N'));
+        assert_eq!(masked, b"NNNAATGCCCGTT".to_vec());
+        assert_eq!(poa_consensus_with_min_coverage(&seqs, 0, &params, 1, LowCoverage::Drop), b"GGGAATGCCCGTT".to_vec());
+    }
+
+    #[test]
+    fn test_trim_ends() {
+        assert_eq!(trim_low_coverage_ends(&[], 1), 0..0);
+        assert_eq!(trim_low_coverage_ends(&[0, 1, 0], 0), 0..3);
+        assert_eq!(trim_low_coverage_ends(&[4], 4), 0..1);
+        // a dip inside the consensus is kept
+        assert_eq!(trim_low_coverage_ends(&[1, 3, 1, 3, 1], 2), 1..4);
+
+        let params = PoaParams::new(2, 5, -4, -8, -6);
+        let mut graph = PoaGraph::new(&params);
+        for seq in ["AATGCCCGTT", "AATGCCCGTT", "AATGCCCGTTACGT"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        assert_eq!(graph.consensus_trimmed(1), (b"AATGCCCGTTACGT".to_vec(), 0..14));
+        assert_eq!(graph.consensus_trimmed(3), (b"AATGCCCGTT".to_vec(), 0..10));
+        assert_eq!(graph.consensus_trimmed(4), (vec![], 0..0));
+        assert_eq!(PoaGraph::new(&params).consensus_trimmed(1), (vec![], 0..0));
+    }
+
+    #[test]
+    fn test_empty_histogram() {
+        let histogram = CoverageHistogram::from_depths(&[]);
+
+        assert_eq!(histogram.positions(), 0);
+        assert_eq!(histogram.median(), 0);
+        assert_eq!(histogram.max(), 0);
+        assert_eq!(histogram.mean(), 0.0);
+        assert_eq!(histogram.fraction_at_least(1), 0.0);
+    }
+}
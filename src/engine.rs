@@ -0,0 +1,129 @@
+//! A consensus engine reused across many small groups of reads, keeping its buffers between them.
+
+use crate::{PoaError, PoaGraph, PoaParams};
+
+/// Generates the consensus of one group of reads after another, reusing one graph and one
+/// consensus buffer for all of them.
+///
+/// Each call of `poa_consensus` builds a graph, and with it the dynamic programming buffers of
+/// its alignment engine, and a consensus buffer from scratch, which for the tiny groups of UMI
+/// families takes longer than aligning their reads. An engine clears its graph between groups,
+/// keeping the buffers of the alignment engine, and writes every consensus into the same buffer, so
+/// after the first few groups a call allocates little beyond the nodes of the graph.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{ConsensusEngine, PoaParams};
+///
+///     let mut engine = ConsensusEngine::new(&PoaParams::new(1, 5, -4, -3, -1));
+///     let families = vec![
+///         vec!["AATGCCCGTT", "AATGCCGTT", "AATGCCCGAT"],
+///         vec!["GATTACAGG\0", "", "GATTACAGG\0", "GATACAGG\0"],
+///     ];
+///
+///     assert_eq!(engine.consensus(&families[0]), b"AATGCCCGTT");
+///     assert_eq!(engine.consensus(&families[1]), b"GATTACAGG");
+///     assert_eq!(engine.graph().num_sequences(), 3);
+/// ```
+pub struct ConsensusEngine {
+    graph: PoaGraph,
+    consensus: Vec<u8>,
+}
+
+impl ConsensusEngine {
+    /// Creates an engine aligning reads with the given parameters.
+    ///
+    /// # Panics
+    /// Panics if `params.validate()` fails or the alignment engine rejects the parameters, as
+    /// `try_new` reports.
+    pub fn new(params: &PoaParams) -> ConsensusEngine {
+        ConsensusEngine::try_new(params).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Creates an engine aligning reads with the given parameters, returning the error of
+    /// `params.validate()` if it fails, or `PoaError::Native` if the native library does.
+    pub fn try_new(params: &PoaParams) -> Result<ConsensusEngine, PoaError> {
+        params.validate()?;
+        Ok(ConsensusEngine { graph: PoaGraph::try_new(params)?, consensus: vec![] })
+    }
+
+    /// Returns the parameters reads are aligned with.
+    pub fn params(&self) -> PoaParams {
+        self.graph.params()
+    }
+
+    /// Returns the graph of the reads of the last group, e.g. for their multiple sequence alignment.
+    pub fn graph(&self) -> &PoaGraph {
+        &self.graph
+    }
+
+    /// Generates the consensus of a group of reads, replacing the group before it in the graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `seqs` - the reads, with or without null terminators; empty reads are left out
+    ///
+    /// # Returns
+    /// * returns the consensus, borrowed from the engine until its next call; empty if every read
+    ///   is empty
+    ///
+    /// # Panics
+    /// Panics if the native library fails or the case policy of the parameters rejects a read, as
+    /// `PoaGraph::add_sequence` does.
+    pub fn consensus<T: AsRef<[u8]>>(&mut self, seqs: &[T]) -> &[u8] {
+        match self.try_consensus(seqs) {
+            Ok(_) | Err(PoaError::EmptyInput) => &self.consensus,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Generates the consensus of a group of reads as `consensus` does.
+    ///
+    /// # Returns
+    /// * returns the consensus, `PoaError::EmptyInput` if every read is empty, or the error of the
+    ///   first read the graph fails to add, as `PoaGraph::try_add_sequence` gives it
+    pub fn try_consensus<T: AsRef<[u8]>>(&mut self, seqs: &[T]) -> Result<&[u8], PoaError> {
+        self.graph.clear();
+        self.consensus.clear();
+        for seq in seqs {
+            let seq = seq.as_ref();
+            let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+            if !seq.is_empty() {
+                self.graph.try_add_sequence(seq)?;
+            }
+        }
+        if self.graph.num_sequences() == 0 {
+            return Err(PoaError::EmptyInput);
+        }
+
+        self.graph.try_consensus_into(&mut self.consensus)?;
+        Ok(&self.consensus)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::{random_sequence, ReadSimulator};
+    use crate::try_poa_consensus;
+
+    #[test]
+    fn test_engine_matches_one_shot() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let mut engine = ConsensusEngine::new(&params);
+        let mut simulator = ReadSimulator::new(7).error_rates(0.02, 0.01, 0.01);
+        for family in 0..20 {
+            let template = random_sequence(60 + family, family as u64);
+            let reads: Vec<Vec<u8>> = simulator.reads(&template, 1 + family % 6).into_iter().map(|read| [read, vec![0]].concat()).collect();
+            assert_eq!(engine.try_consensus(&reads).map(<[u8]>::to_vec), try_poa_consensus(&reads, 0, &params), "{}", family);
+        }
+
+        let empty: [&[u8]; 2] = [b"", b"\0"];
+        assert_eq!(engine.try_consensus(&empty), Err(PoaError::EmptyInput));
+        assert_eq!(engine.consensus(&empty), b"");
+        assert_eq!(engine.graph().num_sequences(), 0);
+        assert_eq!(engine.params(), params);
+        assert!(matches!(ConsensusEngine::try_new(&PoaParams::new(3, 5, -4, -8, -6)), Err(PoaError::InvalidAlignmentType(3))));
+    }
+}
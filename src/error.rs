@@ -0,0 +1,105 @@
+//! The errors of the fallible consensus APIs.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::RejectedRead;
+
+/// The reason a consensus could not be generated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PoaError {
+    /// there were no non-empty reads to build a consensus from
+    EmptyInput,
+    /// a parameter preset could not be parsed
+    InvalidPreset(String),
+    /// the alignment type is not 0 (local), 1 (global) or 2 (semi-global)
+    InvalidAlignmentType(i32),
+    /// `AlignmentType::Auto` was given where a single mode is needed; it is chosen per group of
+    /// reads with `PoaParams::auto_alignment_type`
+    UnresolvedAlignmentType,
+    /// the scores cannot be used by the alignment engine, for the given reason
+    InvalidScores(String),
+    /// a read is malformed, e.g. not null terminated
+    InvalidRead(RejectedRead),
+    /// a read holds a byte outside the alphabet
+    InvalidByte {
+        /// the index of the read in the input
+        seq_index: usize,
+        /// the position of the byte in the read
+        pos: usize,
+        /// the byte
+        byte: u8,
+    },
+    /// the consensus is longer than the maximum length asked for
+    Truncated {
+        /// the length of the full consensus
+        len: usize,
+        /// the maximum length asked for
+        max_len: usize,
+    },
+    /// bytes could not be read back as a graph written by `PoaGraph::to_bytes`, for the given reason
+    InvalidGraph(String),
+    /// a `CancelToken` was cancelled or timed out before every read was added
+    Cancelled {
+        /// the number of reads added before
+        reads_added: usize,
+    },
+    /// the native library failed, e.g. ran out of memory, with its message
+    Native(String),
+}
+
+impl fmt::Display for PoaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PoaError::EmptyInput => f.write_str("no non-empty reads to build a consensus from"),
+            PoaError::InvalidPreset(message) => write!(f, "invalid parameter preset: {}", message),
+            PoaError::InvalidAlignmentType(code) => write!(f, "invalid alignment type {}", code),
+            PoaError::UnresolvedAlignmentType => f.write_str("AlignmentType::Auto is chosen per group of reads with auto_alignment_type"),
+            PoaError::InvalidScores(message) => write!(f, "invalid scores: {}", message),
+            PoaError::InvalidRead(read) => write!(f, "read {} is invalid: {}", read.index, read.reason),
+            PoaError::InvalidByte { seq_index, pos, byte } => {
+                write!(f, "read {} holds the byte 0x{:02x} outside the alphabet at position {}", seq_index, byte, pos)
+            }
+            PoaError::Truncated { len, max_len } => {
+                write!(f, "the consensus of length {} exceeds the maximum length {}", len, max_len)
+            }
+            PoaError::InvalidGraph(message) => write!(f, "invalid serialized graph: {}", message),
+            PoaError::Cancelled { reads_added } => write!(f, "cancelled after adding {} reads", reads_added),
+            PoaError::Native(message) => write!(f, "native error: {}", message),
+        }
+    }
+}
+
+impl Error for PoaError {}
+
+#[cfg(feature = "pure-rust")]
+use crate::pure::poa_take_error;
+
+#[cfg(not(feature = "pure-rust"))]
+extern "C" {
+    fn poa_take_error(message: *mut u8, message_len: usize) -> i32;
+}
+
+// the longest native error message kept, in bytes
+const NATIVE_MESSAGE_LEN: usize = 1024;
+
+// takes the C++ exception the last native call on this thread caught, if it caught one
+pub(crate) fn native_result() -> Result<(), PoaError> {
+    let mut message = [0u8; NATIVE_MESSAGE_LEN];
+    match unsafe { poa_take_error(message.as_mut_ptr(), message.len()) } {
+        0 => Ok(()),
+        _ => {
+            let len = message.iter().position(|&byte| byte == 0).unwrap_or(message.len());
+            Err(PoaError::Native(String::from_utf8_lossy(&message[..len]).into_owned()))
+        }
+    }
+}
+
+// panics with the C++ exception the last native call on this thread caught, for the methods that
+// do not return errors
+pub(crate) fn check_native() {
+    if let Err(error) = native_result() {
+        panic!("{}", error);
+    }
+}
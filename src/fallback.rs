@@ -0,0 +1,161 @@
+//! Retrying consensus generation with relaxed parameters when the first attempt fails.
+
+use crate::{native_consensus, PoaParams};
+
+/// Describes when a consensus counts as failed and which parameter sets to retry with.
+///
+/// A consensus is considered failed if it is empty, if it reaches the maximum consensus length
+/// given (and was therefore probably truncated), or if it is shorter than `min_length_fraction`
+/// times the median input sequence length.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FallbackPolicy {
+    /// parameter sets tried in order after the primary parameters have failed
+    pub alternatives: Vec<PoaParams>,
+    /// the minimum consensus length, as a fraction of the median input sequence length
+    pub min_length_fraction: f64,
+}
+
+impl FallbackPolicy {
+    /// Creates a policy retrying with `alternatives` in order, accepting a consensus of at
+    /// least half the median input length.
+    pub fn new(alternatives: Vec<PoaParams>) -> FallbackPolicy {
+        FallbackPolicy {
+            alternatives,
+            min_length_fraction: 0.5,
+        }
+    }
+
+    /// Sets the minimum accepted consensus length as a fraction of the median input length.
+    pub fn min_length_fraction(mut self, min_length_fraction: f64) -> FallbackPolicy {
+        self.min_length_fraction = min_length_fraction;
+        self
+    }
+
+    fn accepts(&self, consensus: &[u8], consensus_max_length: usize, median_length: usize) -> bool {
+        !consensus.is_empty()
+            && (consensus_max_length == 0 || consensus.len() < consensus_max_length)
+            && consensus.len() as f64 >= self.min_length_fraction * median_length as f64
+    }
+}
+
+/// The result of `poa_consensus_with_fallback`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FallbackConsensus {
+    /// the accepted consensus, or the longest consensus produced if no attempt was accepted
+    pub consensus: Vec<u8>,
+    /// the attempt that produced `consensus`: 0 for the primary parameters, `i` for `alternatives[i - 1]`
+    pub attempt: usize,
+    /// the parameters that produced `consensus`
+    pub params: PoaParams,
+    /// whether `consensus` passed the policy's checks
+    pub accepted: bool,
+}
+
+/// Generates a consensus sequence, retrying with alternative parameters if the result is empty or heavily truncated.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, shared by all attempts, or 0 for no maximum
+/// * `params` - the primary alignment and scoring parameters, tried first
+/// * `policy` - the acceptance checks and the alternative parameter sets to fall back to
+///
+/// # Returns
+/// * returns the first accepted consensus together with the attempt that produced it
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_fallback, FallbackPolicy, PoaParams};
+///
+///     let seqs: Vec<Vec<u8>> = ["AATGCCCGTT\0", "AATGCCGTT\0", "AACGCCCGTC\0"]
+///         .iter()
+///         .map(|seq| seq.bytes().collect())
+///         .collect();
+///
+///     let policy = FallbackPolicy::new(vec![PoaParams::new(0, 5, -4, -8, -6)]);
+///     let result = poa_consensus_with_fallback(&seqs, 20, &PoaParams::new(1, 5, -4, -3, -1), &policy);
+///
+///     assert!(result.accepted);
+///     assert_eq!(result.attempt, 0);
+/// ```
+pub fn poa_consensus_with_fallback<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    policy: &FallbackPolicy
+) -> FallbackConsensus {
+
+    let mut lengths: Vec<usize> = seqs.iter().map(|seq| seq.as_ref().len().saturating_sub(1)).collect();
+    lengths.sort_unstable();
+    let median_length = if lengths.is_empty() { 0 } else { lengths[lengths.len() / 2] };
+
+    let mut best: Option<FallbackConsensus> = None;
+
+    for (attempt, p) in std::iter::once(params).chain(policy.alternatives.iter()).enumerate() {
+        let consensus = native_consensus(seqs, consensus_max_length, p);
+
+        let accepted = policy.accepts(&consensus, consensus_max_length, median_length);
+        let result = FallbackConsensus { consensus, attempt, params: *p, accepted };
+
+        if accepted {
+            return result;
+        }
+
+        if !matches!(&best, Some(b) if b.consensus.len() >= result.consensus.len()) {
+            best = Some(result);
+        }
+    }
+
+    best.expect("at least the primary attempt is always made")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dna_seqs() -> Vec<Vec<u8>> {
+        ["ATTGCCCGTT\0",
+            "AATGCCGTT\0",
+            "AATGCCCGAT\0",
+            "AACGCCCGTC\0",
+            "AGTGCTCGTT\0",
+            "AATGCTCGTT\0"].iter().map(|seq| seq.bytes().collect()).collect()
+    }
+
+    #[test]
+    fn test_primary_accepted() {
+        let policy = FallbackPolicy::new(vec![PoaParams::new(0, 5, -4, -8, -6)]);
+        let result = poa_consensus_with_fallback(&dna_seqs(), 20, &PoaParams::new(1, 5, -4, -3, -1), &policy);
+
+        assert!(result.accepted);
+        assert_eq!(result.attempt, 0);
+        assert_eq!(result.consensus, b"AATGCCCGTT".to_vec());
+
+        // without a maximum length the consensus cannot be truncated
+        assert_eq!(poa_consensus_with_fallback(&dna_seqs(), 0, &PoaParams::new(1, 5, -4, -3, -1), &policy), result);
+    }
+
+    #[test]
+    fn test_falls_back_on_short_consensus() {
+        // the harsh local parameters drop a base from the consensus
+        let primary = PoaParams::new(0, 1, -100, -100, -100);
+        let relaxed = PoaParams::new(1, 5, -4, -3, -1);
+        let policy = FallbackPolicy::new(vec![relaxed]).min_length_fraction(1.0);
+
+        let result = poa_consensus_with_fallback(&dna_seqs(), 20, &primary, &policy);
+
+        assert!(result.accepted);
+        assert_eq!(result.attempt, 1);
+        assert_eq!(result.params, relaxed);
+        assert_eq!(result.consensus, b"AATGCCCGTT".to_vec());
+    }
+
+    #[test]
+    fn test_truncated_consensus_rejected() {
+        let policy = FallbackPolicy::new(vec![PoaParams::new(0, 5, -4, -8, -6)]);
+        let result = poa_consensus_with_fallback(&dna_seqs(), 5, &PoaParams::new(1, 5, -4, -3, -1), &policy);
+
+        assert!(!result.accepted);
+        assert_eq!(result.consensus.len(), 5);
+    }
+}
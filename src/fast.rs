@@ -0,0 +1,192 @@
+//! A consensus of two or three reads without building a graph, for the tiny groups that dominate
+//! UMI data.
+
+use crate::align::{align, AlignOp};
+use crate::SeqRecord;
+
+// the votes of one read against the first read of the group, which the others are aligned to
+struct ReadVotes {
+    // the base aligned to each position of the first read, or None if it is deleted, with its weight
+    columns: Vec<(Option<u8>, f64)>,
+    // the bases inserted before each position of the first read (and after the last), with their
+    // mean weight, or no bases and the weight of the flanking bases
+    insertions: Vec<(Vec<u8>, f64)>,
+}
+
+// the mean weight of the bases on either side of position `i` of a read
+fn flank_weight(weights: &[f64], i: usize) -> f64 {
+    let flanks: Vec<f64> = [i.checked_sub(1), Some(i)].iter().flatten().filter_map(|&k| weights.get(k)).cloned().collect();
+    if flanks.is_empty() {
+        1.0
+    } else {
+        flanks.iter().sum::<f64>() / flanks.len() as f64
+    }
+}
+
+fn self_votes(seq: &[u8], weights: &[f64]) -> ReadVotes {
+    ReadVotes {
+        columns: seq.iter().zip(weights).map(|(&base, &weight)| (Some(base), weight)).collect(),
+        insertions: (0..=seq.len()).map(|j| (vec![], flank_weight(weights, j))).collect(),
+    }
+}
+
+fn aligned_votes(seq: &[u8], weights: &[f64], first: &[u8]) -> ReadVotes {
+    let mut columns = Vec::with_capacity(first.len());
+    let mut insertions: Vec<(Vec<u8>, f64)> = vec![(vec![], 0.0); first.len() + 1];
+    let (mut i, mut j) = (0, 0);
+
+    for op in align(seq, first) {
+        if op != AlignOp::Insertion && insertions[j].0.is_empty() {
+            // no bases were inserted before this position, and the gap weighs as much as its flanks
+            insertions[j].1 = flank_weight(weights, i);
+        }
+        match op {
+            AlignOp::Match | AlignOp::Mismatch => {
+                columns.push((Some(seq[i]), weights[i]));
+                i += 1;
+                j += 1;
+            }
+            AlignOp::Deletion => {
+                columns.push((None, flank_weight(weights, i)));
+                j += 1;
+            }
+            AlignOp::Insertion => {
+                insertions[j].0.push(seq[i]);
+                insertions[j].1 += weights[i];
+                i += 1;
+            }
+        }
+    }
+    if insertions[first.len()].0.is_empty() {
+        insertions[first.len()].1 = flank_weight(weights, seq.len());
+    }
+    for insertion in insertions.iter_mut().filter(|insertion| !insertion.0.is_empty()) {
+        insertion.1 /= insertion.0.len() as f64;
+    }
+
+    ReadVotes { columns, insertions }
+}
+
+// returns the candidate with the highest summed weight, the first one reached among equals
+fn elect<T: PartialEq + Clone>(votes: &[(T, f64)]) -> T {
+    let mut totals: Vec<(T, f64)> = vec![];
+    for (candidate, weight) in votes {
+        match totals.iter_mut().find(|(other, _)| other == candidate) {
+            Some((_, total)) => *total += weight,
+            None => totals.push((candidate.clone(), *weight)),
+        }
+    }
+    let mut best = 0;
+    for (i, (_, total)) in totals.iter().enumerate() {
+        if *total > totals[best].1 {
+            best = i;
+        }
+    }
+    totals.swap_remove(best).0
+}
+
+/// Generates the consensus of one to three reads directly from their pairwise alignments, without building a graph.
+///
+/// The other reads are aligned globally to the first and each position, as well as each gap
+/// between positions, is decided by a vote weighted as the bases are in a graph (see
+/// `SeqRecord`). A deleted base or a missing insertion weighs as much as the bases flanking the
+/// gap. With two reads this arbitrates every difference by quality; ties go to the first read.
+/// # Arguments
+///
+/// * `records` - the reads to form a consensus from
+///
+/// # Returns
+/// * returns the consensus, or `None` for an empty slice or more than three reads
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{small_group_consensus, SeqRecord};
+///
+///     let records = vec![
+///         SeqRecord::new("r1", b"AATGCCCGTT".to_vec()).qual(b"IIII#IIIII".to_vec()),
+///         SeqRecord::new("r2", b"AATGACCGTT".to_vec()).qual(b"IIIIIIIIII".to_vec()),
+///     ];
+///
+///     assert_eq!(small_group_consensus(&records), Some(b"AATGACCGTT".to_vec()));
+/// ```
+pub fn small_group_consensus(records: &[SeqRecord]) -> Option<Vec<u8>> {
+    if records.is_empty() || records.len() > 3 {
+        return None;
+    }
+
+    let reads: Vec<(&[u8], Vec<f64>)> = records
+        .iter()
+        .map(|record| {
+            let weights = match record.base_weights() {
+                Some(weights) => weights.into_iter().map(f64::from).collect(),
+                None => vec![1.0; record.seq.len()],
+            };
+            (record.seq.as_slice(), weights)
+        })
+        .collect();
+    Some(consensus_of_few(&reads))
+}
+
+// the consensus of one to three reads with the given weight for each base
+pub(crate) fn consensus_of_few(reads: &[(&[u8], Vec<f64>)]) -> Vec<u8> {
+    let (first, first_weights) = (reads[0].0, &reads[0].1);
+    let mut votes = vec![self_votes(first, first_weights)];
+    for (seq, weights) in &reads[1..] {
+        votes.push(aligned_votes(seq, weights, first));
+    }
+
+    let mut consensus = Vec::with_capacity(first.len());
+    for j in 0..=first.len() {
+        let inserted: Vec<(Vec<u8>, f64)> = votes.iter().map(|read| read.insertions[j].clone()).collect();
+        consensus.extend(elect(&inserted));
+        if j < first.len() {
+            let column: Vec<(Option<u8>, f64)> = votes.iter().map(|read| read.columns[j]).collect();
+            consensus.extend(elect(&column));
+        }
+    }
+    consensus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(seqs: &[&str]) -> Vec<SeqRecord> {
+        seqs.iter().map(|seq| SeqRecord::from(seq.as_bytes())).collect()
+    }
+
+    #[test]
+    fn test_majority_of_three() {
+        // a mismatch of the first read is outvoted like the errors of the others
+        let reads = records(&["AATGCGCGTT", "AATGCCCGTT", "AATGCCCGTT"]);
+        assert_eq!(small_group_consensus(&reads), Some(b"AATGCCCGTT".to_vec()));
+
+        // a deletion and an insertion, each in one read only
+        let reads = records(&["AATGCCCGTT", "AATGCCGTT", "AATGCCCGGTT"]);
+        assert_eq!(small_group_consensus(&reads), Some(b"AATGCCCGTT".to_vec()));
+
+        // an insertion shared by two reads is kept
+        let reads = records(&["AATGCCCGTT", "AATGCCCAGTT", "AATGCCCAGTT"]);
+        assert_eq!(small_group_consensus(&reads), Some(b"AATGCCCAGTT".to_vec()));
+    }
+
+    #[test]
+    fn test_quality_decides_between_two() {
+        let reads = vec![
+            SeqRecord::new("r1", b"AATGCCCGTT".to_vec()).qual(b"IIIIIIIIII".to_vec()),
+            SeqRecord::new("r2", b"AATGCCCAGTT".to_vec()).qual(b"IIIIIII#III".to_vec()),
+        ];
+        assert_eq!(small_group_consensus(&reads), Some(b"AATGCCCGTT".to_vec()));
+
+        let reads = vec![reads[0].clone().qual(b"IIIIIII###".to_vec()), reads[1].clone().qual(b"IIIIIIIIIII".to_vec())];
+        assert_eq!(small_group_consensus(&reads), Some(b"AATGCCCAGTT".to_vec()));
+    }
+
+    #[test]
+    fn test_group_sizes() {
+        assert_eq!(small_group_consensus(&[]), None);
+        assert_eq!(small_group_consensus(&records(&["ACGT"])), Some(b"ACGT".to_vec()));
+        assert_eq!(small_group_consensus(&records(&["ACGT"; 4])), None);
+    }
+}
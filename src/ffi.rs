@@ -0,0 +1,484 @@
+//! Safe bindings to the alignment engine and graph classes of SPOA on their own, for composing
+//! what `PoaGraph` does not offer, e.g. aligning sequences again and again without adding them,
+//! aligning to one graph with several engines, or walking the graph in topological order.
+//!
+//! `PoaGraph` bundles a graph with its engine and keeps the path, weights and clips of each
+//! sequence; these types keep nothing beyond what SPOA does. An `AlignmentEngine` aligns a
+//! sequence to a `Graph`, and `Graph::add_alignment` adds the sequence along an `Alignment`, which
+//! may also come from elsewhere.
+//!
+//! # Examples
+//!
+//! ```
+//!     use rust_spoa::ffi::{AlignmentEngine, Graph};
+//!     use rust_spoa::PoaParams;
+//!
+//!     let mut engine = AlignmentEngine::new(&PoaParams::new(1, 5, -4, -3, -1)).unwrap();
+//!     let mut graph = Graph::new();
+//!     for seq in ["AATGCCCGTT", "AATGCCGTT", "AATGCCCGTT"].iter() {
+//!         let alignment = engine.align(seq.as_bytes(), &graph);
+//!         graph.add_alignment(&alignment, seq.as_bytes());
+//!     }
+//!
+//!     assert_eq!(graph.consensus(), b"AATGCCCGTT".to_vec());
+//!     let order = graph.topological_order();
+//!     assert_eq!(graph.base(order[0]), b'A');
+//! ```
+
+use std::convert::TryFrom;
+use std::ptr::NonNull;
+
+use crate::error::{check_native, native_result};
+use crate::{PoaError, PoaParams, SimdBackend};
+
+#[cfg(not(feature = "pure-rust"))]
+#[repr(C)]
+struct RawEngine {
+    _private: [u8; 0],
+}
+
+#[cfg(not(feature = "pure-rust"))]
+#[repr(C)]
+struct RawGraph {
+    _private: [u8; 0],
+}
+
+// the links of a node poa_raw_graph_node_links lists, POA_RAW_ in poa_func.h
+const OUT_EDGES: i32 = 0;
+const IN_EDGES: i32 = 1;
+const ALIGNED: i32 = 2;
+
+#[cfg(feature = "pure-rust")]
+use crate::pure::raw::*;
+
+#[cfg(not(feature = "pure-rust"))]
+extern "C" {
+    fn poa_raw_engine_new(
+        alignment_type: i32,
+        match_score: i32,
+        mismatch_score: i32,
+        gap_open: i32,
+        gap_extend: i32,
+        gap_open2: i32,
+        gap_extend2: i32,
+        simd: i32,
+        ambiguity: i32,
+    ) -> *mut RawEngine;
+    fn poa_raw_engine_free(e: *mut RawEngine);
+    fn poa_raw_engine_simd(e: *const RawEngine) -> i32;
+    fn poa_raw_engine_prealloc(e: *mut RawEngine, max_seq_len: u32, alphabet_size: u32);
+    fn poa_raw_engine_align(
+        e: *mut RawEngine,
+        g: *const RawGraph,
+        seq: *const u8,
+        seq_len: u32,
+        node_ids: *mut i32,
+        seq_positions: *mut i32,
+        score: *mut i32,
+    ) -> u32;
+    fn poa_raw_graph_new() -> *mut RawGraph;
+    fn poa_raw_graph_free(g: *mut RawGraph);
+    fn poa_raw_graph_clear(g: *mut RawGraph);
+    fn poa_raw_graph_add_alignment(
+        g: *mut RawGraph,
+        seq: *const u8,
+        seq_len: u32,
+        weights: *const u32,
+        node_ids: *const i32,
+        seq_positions: *const i32,
+        alignment_len: u32,
+    );
+    fn poa_raw_graph_num_nodes(g: *const RawGraph) -> u32;
+    fn poa_raw_graph_num_edges(g: *const RawGraph) -> u32;
+    fn poa_raw_graph_num_sequences(g: *const RawGraph) -> u32;
+    fn poa_raw_graph_sequence_start(g: *const RawGraph, seq_index: u32) -> u32;
+    fn poa_raw_graph_node_base(g: *const RawGraph, node_id: u32) -> u8;
+    fn poa_raw_graph_node_coverage(g: *const RawGraph, node_id: u32) -> u32;
+    fn poa_raw_graph_node_links(g: *const RawGraph, node_id: u32, kind: i32, ids: *mut u32, weights: *mut i64, len: u32) -> u32;
+    fn poa_raw_graph_order(g: *const RawGraph, node_ids: *mut u32);
+    fn poa_raw_graph_consensus(g: *mut RawGraph, node_ids: *mut u32) -> u32;
+    fn poa_raw_graph_msa(g: *mut RawGraph, include_consensus: i32, rows: *mut u8, rows_len: usize) -> u32;
+}
+
+// the length of a sequence as SPOA takes it, which numbers bases with 32 bits
+fn native_len(len: usize) -> u32 {
+    u32::try_from(len).expect("sequences longer than u32::MAX bases are not supported")
+}
+
+/// An alignment of a sequence to a graph, as `spoa::Alignment`: the node and sequence position of
+/// each column, in the order SPOA gives them, `None` marking a gap.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Alignment {
+    /// the node id and sequence position of each column
+    pub columns: Vec<(Option<u32>, Option<u32>)>,
+    /// the score of the alignment, 0 for one not computed by an engine
+    pub score: i32,
+}
+
+impl Alignment {
+    /// Creates an alignment from its columns, e.g. one computed outside SPOA, with score 0.
+    pub fn new(columns: Vec<(Option<u32>, Option<u32>)>) -> Alignment {
+        Alignment { columns, score: 0 }
+    }
+
+    /// Returns the number of columns.
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Returns whether the alignment has no columns, as for the first sequence of a graph.
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+}
+
+/// An alignment engine of SPOA, which keeps its dynamic programming buffers between alignments.
+pub struct AlignmentEngine {
+    raw: NonNull<RawEngine>,
+}
+
+impl AlignmentEngine {
+    /// Creates an engine aligning with the alignment type, scores and instruction set of `params`.
+    ///
+    /// The homopolymer gap scores, band and case policy of `params` are not used, as they are
+    /// applied by `PoaGraph` in Rust; sequences are aligned as given.
+    ///
+    /// # Returns
+    /// * returns the engine, the error of `params.validate()` if it fails, or `PoaError::Native`
+    ///   if SPOA fails
+    pub fn new(params: &PoaParams) -> Result<AlignmentEngine, PoaError> {
+        params.validate()?;
+        let (gap_open2, gap_extend2) = params.second_gaps();
+        let raw = unsafe {
+            poa_raw_engine_new(
+                params.alignment_type,
+                params.match_score,
+                params.mismatch_score,
+                params.gap_open,
+                params.gap_extend,
+                gap_open2,
+                gap_extend2,
+                params.simd.code(),
+                params.ambiguous_bases.code(),
+            )
+        };
+        native_result()?;
+        Ok(AlignmentEngine { raw: NonNull::new(raw).expect("failed to allocate the native engine") })
+    }
+
+    /// Returns the instruction set the engine uses.
+    pub fn simd(&self) -> SimdBackend {
+        SimdBackend::from_code(unsafe { poa_raw_engine_simd(self.raw.as_ptr()) }).unwrap_or(SimdBackend::Scalar)
+    }
+
+    /// Allocates the buffers for sequences of up to `max_seq_len` bases over `alphabet_size`
+    /// symbols up front, rather than growing them as longer sequences come.
+    pub fn prealloc(&mut self, max_seq_len: usize, alphabet_size: usize) {
+        unsafe { poa_raw_engine_prealloc(self.raw.as_ptr(), native_len(max_seq_len), alphabet_size.min(u32::MAX as usize) as u32) }
+        check_native();
+    }
+
+    /// Aligns a sequence to the graph without adding it.
+    ///
+    /// The alignment is empty, with score 0, if the graph or the sequence is.
+    ///
+    /// # Panics
+    /// Panics with the message of SPOA if it fails, e.g. runs out of memory.
+    pub fn align(&mut self, seq: &[u8], graph: &Graph) -> Alignment {
+        let capacity = graph.num_nodes() + seq.len();
+        let (mut node_ids, mut positions) = (vec![-1i32; capacity], vec![-1i32; capacity]);
+        let mut score = 0;
+        let len = unsafe {
+            poa_raw_engine_align(
+                self.raw.as_ptr(),
+                graph.raw.as_ptr(),
+                seq.as_ptr(),
+                native_len(seq.len()),
+                node_ids.as_mut_ptr(),
+                positions.as_mut_ptr(),
+                &mut score,
+            )
+        } as usize;
+        check_native();
+
+        let gap = |value: i32| u32::try_from(value).ok();
+        let columns = node_ids.into_iter().zip(positions).take(len).map(|(id, pos)| (gap(id), gap(pos))).collect();
+        Alignment { columns, score }
+    }
+}
+
+impl Drop for AlignmentEngine {
+    fn drop(&mut self) {
+        unsafe { poa_raw_engine_free(self.raw.as_ptr()) }
+    }
+}
+
+// the engine and its buffers are owned by this value alone, so it can move to another thread
+unsafe impl Send for AlignmentEngine {}
+
+/// A partial order graph of SPOA, holding only what SPOA itself keeps.
+pub struct Graph {
+    raw: NonNull<RawGraph>,
+}
+
+impl Graph {
+    /// Creates an empty graph.
+    pub fn new() -> Graph {
+        let raw = unsafe { poa_raw_graph_new() };
+        check_native();
+        Graph { raw: NonNull::new(raw).expect("failed to allocate the native graph") }
+    }
+
+    /// Removes all nodes and sequences.
+    pub fn clear(&mut self) {
+        unsafe { poa_raw_graph_clear(self.raw.as_ptr()) }
+    }
+
+    /// Adds a sequence along an alignment to the graph, such as one from `AlignmentEngine::align`
+    /// on this graph, every base weighing 1. An empty sequence is not added.
+    ///
+    /// # Panics
+    /// Panics if a column refers to a node or sequence position that does not exist.
+    pub fn add_alignment(&mut self, alignment: &Alignment, seq: &[u8]) {
+        self.add(alignment, seq, None)
+    }
+
+    /// Adds a sequence along an alignment as `add_alignment` does, giving each base the weight at
+    /// the same position of `weights`.
+    ///
+    /// # Panics
+    /// Panics if `weights` does not have the length of `seq`, or as `add_alignment` does.
+    pub fn add_alignment_weighted(&mut self, alignment: &Alignment, seq: &[u8], weights: &[u32]) {
+        assert_eq!(seq.len(), weights.len(), "weights length differs from sequence length");
+        self.add(alignment, seq, Some(weights))
+    }
+
+    fn add(&mut self, alignment: &Alignment, seq: &[u8], weights: Option<&[u32]>) {
+        let num_nodes = self.num_nodes();
+        let mut node_ids = Vec::with_capacity(alignment.len());
+        let mut positions = Vec::with_capacity(alignment.len());
+        for &(id, pos) in &alignment.columns {
+            // SPOA indexes its nodes and the sequence with the columns unchecked
+            assert!(!matches!(id, Some(id) if id as usize >= num_nodes), "alignment column refers to node {:?} of {}", id, num_nodes);
+            assert!(!matches!(pos, Some(pos) if pos as usize >= seq.len()), "alignment column refers to position {:?} past the sequence", pos);
+            node_ids.push(id.map_or(-1, |id| id as i32));
+            positions.push(pos.map_or(-1, |pos| pos as i32));
+        }
+        unsafe {
+            poa_raw_graph_add_alignment(
+                self.raw.as_ptr(),
+                seq.as_ptr(),
+                native_len(seq.len()),
+                weights.map_or(std::ptr::null(), |weights| weights.as_ptr()),
+                node_ids.as_ptr(),
+                positions.as_ptr(),
+                native_len(node_ids.len()),
+            );
+        }
+        check_native();
+    }
+
+    /// Returns the number of nodes.
+    pub fn num_nodes(&self) -> usize {
+        unsafe { poa_raw_graph_num_nodes(self.raw.as_ptr()) as usize }
+    }
+
+    /// Returns the number of edges.
+    pub fn num_edges(&self) -> usize {
+        unsafe { poa_raw_graph_num_edges(self.raw.as_ptr()) as usize }
+    }
+
+    /// Returns the number of sequences added, leaving out empty ones.
+    pub fn num_sequences(&self) -> usize {
+        unsafe { poa_raw_graph_num_sequences(self.raw.as_ptr()) as usize }
+    }
+
+    /// Returns the id of the node of the first base of the sequence at `seq_index`.
+    ///
+    /// # Panics
+    /// Panics if `seq_index` is not less than `num_sequences()`.
+    pub fn sequence_start(&self, seq_index: usize) -> u32 {
+        assert!(seq_index < self.num_sequences(), "sequence index out of range");
+        unsafe { poa_raw_graph_sequence_start(self.raw.as_ptr(), seq_index as u32) }
+    }
+
+    /// Returns the base of a node.
+    ///
+    /// # Panics
+    /// Panics if `node_id` is not less than `num_nodes()`, as do the other methods taking a node.
+    pub fn base(&self, node_id: u32) -> u8 {
+        self.check_node(node_id);
+        unsafe { poa_raw_graph_node_base(self.raw.as_ptr(), node_id) }
+    }
+
+    /// Returns the number of sequences passing through a node.
+    pub fn coverage(&self, node_id: u32) -> u32 {
+        self.check_node(node_id);
+        unsafe { poa_raw_graph_node_coverage(self.raw.as_ptr(), node_id) }
+    }
+
+    /// Returns the node each outgoing edge of a node enters, with the weight of the edge.
+    pub fn successors(&self, node_id: u32) -> Vec<(u32, i64)> {
+        self.edges(node_id, OUT_EDGES)
+    }
+
+    /// Returns the node each incoming edge of a node leaves, with the weight of the edge.
+    pub fn predecessors(&self, node_id: u32) -> Vec<(u32, i64)> {
+        self.edges(node_id, IN_EDGES)
+    }
+
+    /// Returns the nodes aligned to a node, i.e. those of other bases in its alignment column.
+    pub fn aligned_nodes(&self, node_id: u32) -> Vec<u32> {
+        self.links(node_id, ALIGNED).0
+    }
+
+    fn edges(&self, node_id: u32, kind: i32) -> Vec<(u32, i64)> {
+        let (ids, weights) = self.links(node_id, kind);
+        ids.into_iter().zip(weights).collect()
+    }
+
+    // the links of a node of the given kind, first counted and then written
+    fn links(&self, node_id: u32, kind: i32) -> (Vec<u32>, Vec<i64>) {
+        self.check_node(node_id);
+        let raw = self.raw.as_ptr();
+        let len = unsafe { poa_raw_graph_node_links(raw, node_id, kind, std::ptr::null_mut(), std::ptr::null_mut(), 0) };
+        let (mut ids, mut weights) = (vec![0u32; len as usize], vec![0i64; len as usize]);
+        unsafe { poa_raw_graph_node_links(raw, node_id, kind, ids.as_mut_ptr(), weights.as_mut_ptr(), len) };
+        (ids, weights)
+    }
+
+    fn check_node(&self, node_id: u32) {
+        assert!((node_id as usize) < self.num_nodes(), "node id out of range");
+    }
+
+    /// Returns the node ids in the topological order SPOA aligns in.
+    pub fn topological_order(&self) -> Vec<u32> {
+        let mut order = vec![0u32; self.num_nodes()];
+        unsafe { poa_raw_graph_order(self.raw.as_ptr(), order.as_mut_ptr()) }
+        order
+    }
+
+    /// Returns the ids of the nodes of the heaviest bundle consensus, in order.
+    ///
+    /// Takes `&mut self` as SPOA keeps the path in the graph.
+    pub fn consensus_path(&mut self) -> Vec<u32> {
+        let mut node_ids = vec![0u32; self.num_nodes()];
+        let len = unsafe { poa_raw_graph_consensus(self.raw.as_ptr(), node_ids.as_mut_ptr()) };
+        check_native();
+        node_ids.truncate(len as usize);
+        node_ids
+    }
+
+    /// Generates the heaviest bundle consensus.
+    pub fn consensus(&mut self) -> Vec<u8> {
+        self.consensus_path().into_iter().map(|id| self.base(id)).collect()
+    }
+
+    /// Generates the multiple sequence alignment, with a row per sequence in the order they were
+    /// added and the consensus last if `include_consensus` is set, using `-` for gaps.
+    pub fn multiple_sequence_alignment(&mut self, include_consensus: bool) -> Vec<Vec<u8>> {
+        let raw = self.raw.as_ptr();
+        let len = unsafe { poa_raw_graph_msa(raw, include_consensus as i32, std::ptr::null_mut(), 0) } as usize;
+        check_native();
+        if len == 0 {
+            return vec![];
+        }
+        let mut rows = vec![0u8; len * (self.num_sequences() + include_consensus as usize)];
+        unsafe { poa_raw_graph_msa(raw, include_consensus as i32, rows.as_mut_ptr(), rows.len()) };
+        check_native();
+        rows.chunks(len).map(|row| row.to_vec()).collect()
+    }
+}
+
+impl Default for Graph {
+    fn default() -> Graph {
+        Graph::new()
+    }
+}
+
+impl Drop for Graph {
+    fn drop(&mut self) {
+        unsafe { poa_raw_graph_free(self.raw.as_ptr()) }
+    }
+}
+
+// the native graph is owned by this value alone and keeps no state tied to the thread that made
+// it, so it can move to another thread. it is not Sync, as consensus and
+// multiple_sequence_alignment update the internal state of SPOA's graph.
+unsafe impl Send for Graph {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PoaGraph;
+
+    #[test]
+    fn test_matches_poa_graph() {
+        let params = PoaParams::new(1, 5, -4, -8, -6).simd(SimdBackend::Scalar);
+        let reads = ["GATTACAGGCTTACCG", "GATTACAGGCATACCG", "GATTAAAGGCTTACCG", "GATTACAGGCTACCG", "GATTCAGGCTTTACCG"];
+
+        let mut engine = AlignmentEngine::new(&params).unwrap();
+        engine.prealloc(16, 4);
+        assert_eq!(engine.simd(), SimdBackend::Scalar);
+        let mut graph = Graph::new();
+        let mut poa_graph = PoaGraph::new(&params);
+        for read in reads.iter() {
+            let alignment = engine.align(read.as_bytes(), &graph);
+            assert_eq!(alignment.score, if graph.num_nodes() == 0 { 0 } else { poa_graph.alignment_score(read.as_bytes()) });
+            graph.add_alignment(&alignment, read.as_bytes());
+            poa_graph.add_sequence(read.as_bytes());
+        }
+
+        assert_eq!((graph.num_nodes(), graph.num_edges(), graph.num_sequences()), (poa_graph.num_nodes(), poa_graph.edges().len(), 5));
+        assert_eq!(graph.consensus(), poa_graph.consensus());
+        assert_eq!(graph.consensus_path(), poa_graph.consensus_path());
+        assert_eq!(graph.multiple_sequence_alignment(true), poa_graph.multiple_sequence_alignment(true));
+        assert_eq!(graph.sequence_start(0), 0);
+
+        let nodes = poa_graph.nodes();
+        let order = graph.topological_order();
+        let mut rank = vec![0; order.len()];
+        for (i, &id) in order.iter().enumerate() {
+            rank[id as usize] = i;
+        }
+        for id in 0..graph.num_nodes() as u32 {
+            assert_eq!((graph.base(id), graph.coverage(id)), (nodes[id as usize].base, nodes[id as usize].coverage));
+            for (head, weight) in graph.successors(id) {
+                assert!(rank[head as usize] > rank[id as usize]);
+                assert!(graph.predecessors(head).contains(&(id, weight)));
+            }
+            for aligned in graph.aligned_nodes(id) {
+                assert!(graph.aligned_nodes(aligned).contains(&id));
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_alignment() {
+        let mut graph = Graph::default();
+        graph.add_alignment(&Alignment::default(), b"ACGT");
+        // the second read substitutes its third base and shares the rest
+        let columns = vec![(Some(0), Some(0)), (Some(1), Some(1)), (Some(2), Some(2)), (Some(3), Some(3))];
+        graph.add_alignment_weighted(&Alignment::new(columns), b"ACTT", &[1, 1, 3, 1]);
+        assert_eq!((graph.num_nodes(), graph.num_sequences()), (5, 2));
+        assert_eq!(graph.aligned_nodes(2), vec![4]);
+        assert_eq!(graph.consensus(), b"ACTT".to_vec());
+
+        graph.add_alignment(&Alignment::default(), b"");
+        assert_eq!(graph.num_sequences(), 2);
+        graph.clear();
+        assert!(graph.multiple_sequence_alignment(false).is_empty());
+        assert!(Graph::new().consensus().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "refers to node")]
+    fn test_alignment_out_of_range() {
+        Graph::new().add_alignment(&Alignment::new(vec![(Some(0), Some(0))]), b"A");
+    }
+
+    #[test]
+    fn test_invalid_engine() {
+        assert_eq!(AlignmentEngine::new(&PoaParams::new(3, 5, -4, -8, -6)).err(), Some(PoaError::InvalidAlignmentType(3)));
+    }
+}
@@ -0,0 +1,217 @@
+//! Dropping reads by length, base quality and N content before they are added to a graph.
+
+use crate::reject::{ConsensusReport, RejectedRead};
+use crate::{PoaGraph, PoaParams, RejectReason, SeqRecord};
+
+/// Criteria a read must meet to be added to a graph, checked before it is aligned.
+///
+/// Very short or long reads, reads of low quality and reads mostly of N bases distort the graph
+/// for the reads after them, so dropping them first is cheaper than correcting for them later.
+/// Each criterion is unset by default.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{ReadFilter, RejectReason};
+///
+///     let filter = ReadFilter::new().min_length(5).max_n_fraction(0.2);
+///     assert_eq!(filter.check(b"AATGCCCGTT", None), Ok(()));
+///     assert_eq!(filter.check(b"AATG", None), Err(RejectReason::TooShort));
+///     assert_eq!(filter.check(b"AANNNCCGTT", None), Err(RejectReason::TooManyN));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReadFilter {
+    /// the minimum mean Phred quality, checked only for reads with qualities
+    pub min_mean_quality: Option<f64>,
+    /// the minimum number of bases
+    pub min_length: Option<usize>,
+    /// the maximum number of bases
+    pub max_length: Option<usize>,
+    /// the maximum fraction of the bases that are N, in either case
+    pub max_n_fraction: Option<f64>,
+}
+
+impl ReadFilter {
+    /// Creates a filter accepting every non-empty read.
+    pub fn new() -> ReadFilter {
+        ReadFilter::default()
+    }
+
+    /// Sets the minimum mean Phred quality of a read.
+    ///
+    /// The mean is taken over the error probabilities of the bases and turned back into a Phred
+    /// quality, so a few bad bases lower it more than they lower the mean of the qualities.
+    ///
+    /// # Panics
+    /// Panics if `min_mean_quality` is negative or not a number.
+    pub fn min_mean_quality(mut self, min_mean_quality: f64) -> ReadFilter {
+        assert!(min_mean_quality >= 0.0, "the minimum mean quality must not be negative");
+        self.min_mean_quality = Some(min_mean_quality);
+        self
+    }
+
+    /// Sets the minimum number of bases of a read.
+    pub fn min_length(mut self, min_length: usize) -> ReadFilter {
+        self.min_length = Some(min_length);
+        self
+    }
+
+    /// Sets the maximum number of bases of a read.
+    pub fn max_length(mut self, max_length: usize) -> ReadFilter {
+        self.max_length = Some(max_length);
+        self
+    }
+
+    /// Sets the maximum fraction of the bases of a read that are N.
+    ///
+    /// # Panics
+    /// Panics if `max_n_fraction` is not between 0 and 1.
+    pub fn max_n_fraction(mut self, max_n_fraction: f64) -> ReadFilter {
+        assert!((0.0..=1.0).contains(&max_n_fraction), "the maximum N fraction must be between 0 and 1");
+        self.max_n_fraction = Some(max_n_fraction);
+        self
+    }
+
+    /// Checks a read, without a null terminator, against the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `seq` - the bases of the read
+    /// * `qual` - the Phred+33 quality of each base, if known
+    ///
+    /// # Returns
+    /// * returns the first criterion the read fails, checking emptiness, length, N content and
+    ///   quality in that order
+    ///
+    /// # Panics
+    /// Panics if `qual` does not have the length of `seq`.
+    pub fn check(&self, seq: &[u8], qual: Option<&[u8]>) -> Result<(), RejectReason> {
+        if seq.is_empty() {
+            return Err(RejectReason::Empty);
+        }
+        if matches!(self.min_length, Some(min_length) if seq.len() < min_length) {
+            return Err(RejectReason::TooShort);
+        }
+        if matches!(self.max_length, Some(max_length) if seq.len() > max_length) {
+            return Err(RejectReason::TooLong);
+        }
+        if let Some(max_n_fraction) = self.max_n_fraction {
+            let n = seq.iter().filter(|&&base| base == b'N' || base == b'n').count();
+            if n as f64 > max_n_fraction * seq.len() as f64 {
+                return Err(RejectReason::TooManyN);
+            }
+        }
+        if let (Some(min_mean_quality), Some(qual)) = (self.min_mean_quality, qual) {
+            assert_eq!(qual.len(), seq.len(), "quality string length differs from sequence length");
+            if mean_quality(qual) < min_mean_quality {
+                return Err(RejectReason::LowQuality);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks a record against the filter, using its qualities if it has them.
+    pub fn check_record(&self, record: &SeqRecord) -> Result<(), RejectReason> {
+        self.check(&record.seq, record.qual.as_deref())
+    }
+}
+
+// the Phred quality of the mean error probability of the bases of a Phred+33 quality string
+pub(crate) fn mean_quality(qual: &[u8]) -> f64 {
+    let error: f64 = qual.iter().map(|&q| 10f64.powf(-f64::from(q.saturating_sub(33)) / 10.0)).sum::<f64>() / qual.len() as f64;
+    -10.0 * error.log10()
+}
+
+impl PoaGraph {
+    /// Aligns a record to the graph and adds it as `add_record` does, unless it fails `filter`.
+    ///
+    /// A rejected record is not aligned and leaves the graph unchanged.
+    pub fn add_record_filtered(&mut self, record: &SeqRecord, filter: &ReadFilter) -> Result<(), RejectReason> {
+        filter.check_record(record)?;
+        self.add_record(record);
+        Ok(())
+    }
+}
+
+/// Generates the consensus of a set of records, leaving out the reads that fail a filter.
+///
+/// The bases of the kept records are weighted by quality and read weight as by
+/// `poa_consensus_of_records`.
+/// # Arguments
+///
+/// * `records` - the reads to form a consensus from
+/// * `params` - the alignment and scoring parameters
+/// * `filter` - the length, quality and N content criteria each read must meet
+///
+/// # Returns
+/// * returns the consensus of the kept reads, with the dropped reads and the criterion each failed
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_filtered, PoaParams, ReadFilter, RejectReason, SeqRecord};
+///
+///     let records = vec![
+///         SeqRecord::new("r1", b"AATGCCCGTT".to_vec()).qual(vec![b'I'; 10]),
+///         SeqRecord::new("r2", b"AATGCCCGTT".to_vec()).qual(vec![b'I'; 10]),
+///         SeqRecord::new("r3", b"GGCCAATTGG".to_vec()).qual(vec![b'#'; 10]),
+///         SeqRecord::new("r4", b"AATG".to_vec()),
+///     ];
+///     let filter = ReadFilter::new().min_length(8).min_mean_quality(10.0);
+///     let report = poa_consensus_filtered(&records, &PoaParams::new(1, 5, -4, -3, -1), &filter);
+///
+///     assert_eq!(report.consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(report.reads_used, 2);
+///     assert_eq!(report.reason_counts(), vec![(RejectReason::LowQuality, 1), (RejectReason::TooShort, 1)]);
+/// ```
+pub fn poa_consensus_filtered(records: &[SeqRecord], params: &PoaParams, filter: &ReadFilter) -> ConsensusReport {
+    let mut graph = PoaGraph::new(params);
+    let mut rejected = vec![];
+    for (index, record) in records.iter().enumerate() {
+        if let Err(reason) = graph.add_record_filtered(record, filter) {
+            rejected.push(RejectedRead { index, reason });
+        }
+    }
+    ConsensusReport { consensus: graph.consensus(), reads_used: graph.num_sequences(), rejected }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_criteria() {
+        let filter = ReadFilter::new().min_length(4).max_length(8).max_n_fraction(0.25).min_mean_quality(20.0);
+        assert_eq!(filter.check(b"", None), Err(RejectReason::Empty));
+        assert_eq!(filter.check(b"ACG", None), Err(RejectReason::TooShort));
+        assert_eq!(filter.check(b"ACGTACGTA", None), Err(RejectReason::TooLong));
+        assert_eq!(filter.check(b"ACnN", None), Err(RejectReason::TooManyN));
+        assert_eq!(filter.check(b"ACGN", None), Ok(()));
+        // reads without qualities pass the quality criterion
+        assert_eq!(filter.check(b"ACGT", None), Ok(()));
+        assert_eq!(filter.check(b"ACGT", Some(b"IIII")), Ok(()));
+        // one base of quality 0 among three of 40 brings the mean near 6
+        assert_eq!(filter.check(b"ACGT", Some(b"III!")), Err(RejectReason::LowQuality));
+        assert!((mean_quality(b"5555") - 20.0).abs() < 1e-9);
+        assert_eq!(RejectReason::TooManyN.to_string(), "too_many_n");
+    }
+
+    #[test]
+    fn test_filtered_graph() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let mut graph = PoaGraph::new(&params);
+        let filter = ReadFilter::new().max_length(12);
+
+        assert_eq!(graph.add_record_filtered(&SeqRecord::new("r1", b"AATGCCCGTT".to_vec()), &filter), Ok(()));
+        let long = SeqRecord::new("r2", b"AATGCCCGTTAAAAAAAA".to_vec());
+        assert_eq!(graph.add_record_filtered(&long, &filter), Err(RejectReason::TooLong));
+        assert_eq!((graph.num_sequences(), graph.num_nodes()), (1, 10));
+
+        let records = vec![SeqRecord::new("r1", vec![]), long, SeqRecord::new("r3", b"AATGCCCGTT".to_vec())];
+        let report = poa_consensus_filtered(&records, &params, &filter);
+        let rejected: Vec<(usize, RejectReason)> = report.rejected.iter().map(|read| (read.index, read.reason)).collect();
+        assert_eq!(rejected, vec![(0, RejectReason::Empty), (1, RejectReason::TooLong)]);
+        assert_eq!(report.consensus, b"AATGCCCGTT".to_vec());
+        assert_eq!(poa_consensus_filtered(&records, &params, &ReadFilter::new()).reads_used, 2);
+    }
+}
@@ -0,0 +1,1350 @@
+//! A partial order graph that sequences are added to one at a time, and its export.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt::Write;
+use std::ops::Range;
+use std::os::raw::c_void;
+use std::ptr::NonNull;
+
+use crate::error::{check_native, native_result};
+use crate::{mask_low_coverage, reserve_support, GrowthLimits, LowCoverage, PoaError, PoaParams, RejectReason, RejectedRead, SeqRecord, SimdBackend, SubstitutionMatrix, SupportBuffers};
+
+#[cfg(feature = "debug-dp")]
+mod dp;
+mod cigar;
+mod dot;
+mod gfa;
+mod homopolymer;
+mod paths;
+mod query;
+mod serialize;
+mod stats;
+mod svg;
+
+#[cfg(feature = "debug-dp")]
+pub use self::dp::DpMatrix;
+pub use self::cigar::{ReadAlignment, Realignment};
+pub use self::paths::WeightedPath;
+pub use self::query::GraphAlignment;
+pub use self::stats::GraphStats;
+pub(crate) use self::cigar::row_alignment;
+pub(crate) use self::paths::Topology;
+
+#[cfg(not(feature = "pure-rust"))]
+#[repr(C)]
+struct RawGraph {
+    _private: [u8; 0],
+}
+
+#[cfg(feature = "pure-rust")]
+use crate::pure::reads::{ReadGraph as RawGraph, *};
+
+#[cfg(not(feature = "pure-rust"))]
+extern "C" {
+    fn poa_graph_new(
+        alignment_type: i32,
+        match_score: i32,
+        mismatch_score: i32,
+        gap_open: i32,
+        gap_extend: i32,
+        gap_open2: i32,
+        gap_extend2: i32,
+        simd: i32,
+        ambiguity: i32,
+    ) -> *mut RawGraph;
+    fn poa_graph_new_matrix(
+        alignment_type: i32,
+        gap_open: i32,
+        gap_extend: i32,
+        alphabet: *const u8,
+        alphabet_len: u32,
+        scores: *const i32,
+        ambiguity: i32,
+    ) -> *mut RawGraph;
+    fn poa_graph_free(g: *mut RawGraph);
+    fn poa_graph_simd(g: *const RawGraph) -> i32;
+    fn poa_graph_clear(g: *mut RawGraph);
+    fn poa_graph_add(g: *mut RawGraph, seq: *const u8, seq_len: u32);
+    fn poa_graph_add_limited(g: *mut RawGraph, seq: *const u8, seq_len: u32, max_nodes: u32, max_inserted: u32) -> i32;
+    fn poa_graph_add_weighted(
+        g: *mut RawGraph,
+        seq: *const u8,
+        seq_len: u32,
+        weights: *const u32,
+        max_nodes: u32,
+        max_inserted: u32,
+    ) -> i32;
+    fn poa_graph_add_checked(
+        g: *mut RawGraph,
+        seq: *const u8,
+        seq_len: u32,
+        weights: *const u32,
+        max_nodes: u32,
+        max_inserted: u32,
+        min_identity: f64,
+        identity: *mut f64,
+    ) -> i32;
+    fn poa_graph_alignment_score(g: *mut RawGraph, seq: *const u8, seq_len: u32) -> i32;
+    fn poa_graph_align(g: *mut RawGraph, seq: *const u8, seq_len: u32, node_ids: *mut i32, seq_positions: *mut i32, score: *mut i32) -> u32;
+    fn poa_graph_add_anchored(g: *mut RawGraph, seq: *const u8, seq_len: u32, weights: *const u32, begin: u32, end: u32) -> i32;
+    fn poa_graph_add_alignment(
+        g: *mut RawGraph,
+        seq: *const u8,
+        seq_len: u32,
+        weights: *const u32,
+        node_ids: *const i32,
+        seq_positions: *const i32,
+        alignment_len: u32,
+        score: i32,
+    );
+    fn poa_graph_add_alignment_checked(
+        g: *mut RawGraph,
+        seq: *const u8,
+        seq_len: u32,
+        weights: *const u32,
+        node_ids: *const i32,
+        seq_positions: *const i32,
+        alignment_len: u32,
+        score: i32,
+        max_nodes: u32,
+        max_inserted: u32,
+        min_identity: f64,
+        identity: *mut f64,
+    ) -> i32;
+    fn poa_graph_add_subgraph(dst: *mut RawGraph, src: *const RawGraph, from_node: u32, to_node: u32);
+    fn poa_graph_add_reverse_complement(dst: *mut RawGraph, src: *const RawGraph);
+    fn poa_graph_add_pruned(dst: *mut RawGraph, src: *const RawGraph, min_reads: u32);
+    fn poa_graph_num_sequences(g: *const RawGraph) -> u32;
+    fn poa_graph_consensus(g: *mut RawGraph, consensus: *mut u8, consensus_len: u32) -> u32;
+    fn poa_graph_consensus_support(g: *mut RawGraph, buffers: *mut c_void, reserve: crate::ReserveSupport) -> usize;
+    fn poa_graph_consensus_path(g: *mut RawGraph, node_ids: *mut u32, node_ids_len: u32) -> u32;
+    fn poa_graph_msa_len(g: *mut RawGraph) -> u32;
+    fn poa_graph_msa(g: *mut RawGraph, rows: *mut u8, include_consensus: i32);
+    fn poa_graph_num_nodes(g: *const RawGraph) -> u32;
+    fn poa_graph_nodes(g: *const RawGraph, bases: *mut u8, weights: *mut u64);
+    fn poa_graph_num_edges(g: *const RawGraph) -> u32;
+    fn poa_graph_edges(g: *const RawGraph, tails: *mut u32, heads: *mut u32, weights: *mut u64);
+    fn poa_graph_sequence_len(g: *const RawGraph, seq_index: u32) -> u32;
+    fn poa_graph_sequence_path(g: *const RawGraph, seq_index: u32, node_ids: *mut u32);
+    fn poa_graph_sequence_weights(g: *const RawGraph, seq_index: u32, weights: *mut u32);
+    fn poa_graph_aligned_partners(g: *const RawGraph, partners: *mut i32);
+    fn poa_graph_sequence_clips(g: *const RawGraph, seq_index: u32, leading: *mut u32, trailing: *mut u32);
+    fn poa_graph_sequence_score(g: *const RawGraph, seq_index: u32) -> i32;
+}
+
+// the score of a sequence added without an alignment, POA_NO_SCORE in poa_func.h
+const NO_SCORE: i32 = i32::MIN;
+
+// the length of a sequence as the native graph takes it. SPOA numbers nodes and bases with 32 bits,
+// so a longer sequence panics instead of being cut short.
+fn native_len(len: usize) -> u32 {
+    u32::try_from(len).expect("sequences longer than u32::MAX bases are not supported")
+}
+
+// the index of a sequence as the native graph takes it. callers check it against num_sequences,
+// which the native graph counts with 32 bits, so a valid index always fits.
+fn native_index(seq_index: usize) -> u32 {
+    u32::try_from(seq_index).expect("sequence index out of range")
+}
+
+/// A node of the partial order graph, representing one base of one or more sequences.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    /// the node id, which is also its index in `PoaGraph::nodes`
+    pub id: u32,
+    /// the base (symbol) of the node
+    pub base: u8,
+    /// the summed weight of the sequence bases represented by the node
+    pub weight: u64,
+    /// the number of sequences passing through the node
+    pub coverage: u32,
+}
+
+/// A directed edge between two consecutive bases of one or more sequences.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    /// the id of the node the edge leaves
+    pub from: u32,
+    /// the id of the node the edge enters
+    pub to: u32,
+    /// the summed weight of the sequences passing along the edge
+    pub weight: u64,
+    /// the indices of the sequences passing along the edge, in increasing order
+    pub labels: Vec<u32>,
+}
+
+/// The bases at either end of a sequence that were not aligned to the graph when it was added.
+///
+/// In local mode these are the soft-clipped bases; in the other modes they are the bases inserted
+/// before the first and after the last base aligned to an existing node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SoftClips {
+    /// the number of unaligned bases at the start of the sequence
+    pub leading: usize,
+    /// the number of unaligned bases at the end of the sequence
+    pub trailing: usize,
+}
+
+impl SoftClips {
+    /// Splits `seq`, the sequence the clips belong to, into its leading clip, its aligned part and its trailing clip.
+    ///
+    /// # Panics
+    /// Panics if `seq` is shorter than the two clips together.
+    pub fn split<'a>(&self, seq: &'a [u8]) -> (&'a [u8], &'a [u8], &'a [u8]) {
+        let (leading, rest) = seq.split_at(self.leading);
+        let (aligned, trailing) = rest.split_at(rest.len() - self.trailing);
+        (leading, aligned, trailing)
+    }
+}
+
+/// A partial order graph built by aligning sequences to it one at a time.
+///
+/// Unlike `poa_consensus`, sequences added to a graph are passed without a null terminator.
+/// Building the graph and generating outputs from it are separate steps, so one graph can
+/// produce several outputs, such as consensuses with different coverage thresholds and the
+/// multiple sequence alignment, without aligning the sequences again.
+///
+/// Each sequence is aligned and added when it is passed, so reads arriving from a stream can be
+/// added as they come, by `add_sequence` or through `Extend`, without collecting them first. The
+/// graph does not keep the sequences themselves, only the node path and base weights of each, so
+/// its memory grows with the bases added but not with copies of the reads.
+///
+/// SPOA counts nodes, sequences and bases with 32 bits, so a graph holds at most `u32::MAX` of
+/// each; adding a sequence longer than that panics rather than cutting it short.
+///
+/// A graph is `Send`, so it can be built on one thread and handed to another, e.g. through a
+/// channel, to generate its outputs. It is not `Sync`, as generating outputs updates caches of the
+/// native graph, so it cannot be shared between threads without a lock:
+///
+/// ```compile_fail
+///     fn shared<T: Sync>(_: &T) {}
+///
+///     shared(&rust_spoa::PoaGraph::new(&rust_spoa::PoaParams::dna()));
+/// ```
+///
+/// A C++ exception inside SPOA, such as running out of memory for the alignment matrices, is
+/// caught before it reaches Rust. The `try_` methods return it as `PoaError::Native`; the others
+/// panic with its message.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{PoaGraph, PoaParams};
+///
+///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+///     for seq in ["AATGCCCGTT", "AATGCCGTT", "AACGCCCGTC"].iter() {
+///         graph.add_sequence(seq.as_bytes());
+///     }
+///
+///     assert_eq!(graph.num_sequences(), 3);
+///     assert_eq!(graph.consensus(), b"AATGCCCGTT".to_vec());
+/// ```
+pub struct PoaGraph {
+    raw: NonNull<RawGraph>,
+    params: PoaParams,
+    matrix: Option<SubstitutionMatrix>,
+}
+
+impl PoaGraph {
+    /// Creates an empty graph aligning sequences with the given parameters.
+    ///
+    /// # Panics
+    /// Panics if the alignment engine rejects the parameters, as `try_new` reports.
+    pub fn new(params: &PoaParams) -> PoaGraph {
+        PoaGraph::try_new(params).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Creates an empty graph aligning sequences with the given parameters, returning
+    /// `PoaError::Native` if the native library fails, e.g. rejects parameters `validate` was not
+    /// asked about.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaError, PoaGraph, PoaParams};
+    ///
+    ///     assert!(PoaGraph::try_new(&PoaParams::dna()).is_ok());
+    ///     // positive gap scores are rejected inside SPOA
+    ///     assert!(matches!(PoaGraph::try_new(&PoaParams::new(1, 5, -4, 3, -6)), Err(PoaError::Native(_))));
+    /// ```
+    pub fn try_new(params: &PoaParams) -> Result<PoaGraph, PoaError> {
+        let (gap_open2, gap_extend2) = params.second_gaps();
+        let raw = unsafe {
+            poa_graph_new(
+                params.alignment_type,
+                params.match_score,
+                params.mismatch_score,
+                params.gap_open,
+                params.gap_extend,
+                gap_open2,
+                gap_extend2,
+                params.simd.code(),
+                params.ambiguous_bases.code(),
+            )
+        };
+
+        native_result()?;
+        Ok(PoaGraph {
+            raw: NonNull::new(raw).expect("failed to allocate the native graph"),
+            params: *params,
+            matrix: None,
+        })
+    }
+
+    /// Creates an empty graph aligning sequences with a substitution matrix in place of the match
+    /// and mismatch scores of `params`.
+    ///
+    /// The alignment type and gap scores of `params` are used as by `new`. The matrix is used by a
+    /// scalar alignment engine, so `params.simd` has no effect, and sequences are aligned without
+    /// the convex and homopolymer gap scores and band of `params`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{Matrix, PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::with_matrix(&PoaParams::protein(), &Matrix::Blosum62.into());
+    ///     for seq in ["MKTAYIAKQR", "MKTAYIAKQR", "MKSAYIAKQR"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///     assert_eq!(graph.consensus(), b"MKTAYIAKQR".to_vec());
+    /// ```
+    pub fn with_matrix(params: &PoaParams, matrix: &SubstitutionMatrix) -> PoaGraph {
+        PoaGraph::try_with_matrix(params, matrix).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Creates an empty graph aligning sequences with a substitution matrix as `with_matrix` does,
+    /// returning `PoaError::Native` if the native library fails.
+    pub fn try_with_matrix(params: &PoaParams, matrix: &SubstitutionMatrix) -> Result<PoaGraph, PoaError> {
+        let raw = unsafe {
+            poa_graph_new_matrix(
+                params.alignment_type,
+                params.gap_open,
+                params.gap_extend,
+                matrix.alphabet().as_ptr(),
+                native_len(matrix.alphabet().len()),
+                matrix.raw_scores().as_ptr(),
+                params.ambiguous_bases.code(),
+            )
+        };
+
+        native_result()?;
+        Ok(PoaGraph {
+            raw: NonNull::new(raw).expect("failed to allocate the native graph"),
+            params: PoaParams { convex_gaps: None, homopolymer_gaps: None, band_width: None, end_gaps: None, ..*params },
+            matrix: Some(matrix.clone()),
+        })
+    }
+
+    // an empty graph aligning sequences as this one does
+    fn empty_like(&self) -> PoaGraph {
+        match &self.matrix {
+            Some(matrix) => PoaGraph::with_matrix(&self.params, matrix),
+            None => PoaGraph::new(&self.params),
+        }
+    }
+
+    /// Removes all sequences from the graph, keeping its parameters.
+    ///
+    /// The buffers used for alignment are kept, so processing many groups with one graph, clearing
+    /// it between groups, avoids reallocating them for every group.
+    pub fn clear(&mut self) {
+        unsafe { poa_graph_clear(self.raw.as_ptr()) }
+    }
+
+    /// Returns the score of the best alignment of a sequence to the graph, without adding it; 0 if
+    /// the graph is empty.
+    ///
+    /// The sequence is scored as `add_sequence` would align it, with the homopolymer gap scores,
+    /// band and end gaps of the parameters if they set them.
+    pub fn alignment_score(&self, seq: &[u8]) -> i32 {
+        let seq = self.query_bases(seq);
+        if self.params.is_scalar() {
+            return self.scalar_alignment(&seq, self.params.homopolymer_gaps.as_ref(), self.params.band_width).1.unwrap_or(0);
+        }
+        let score = unsafe { poa_graph_alignment_score(self.raw.as_ptr(), seq.as_ptr(), native_len(seq.len())) };
+        check_native();
+        score
+    }
+
+    // the bases of seq as the case policy of the graph aligns them, or the error rejecting it as
+    // the next sequence of the graph
+    fn cased<'a>(&self, seq: &'a [u8]) -> Result<Cow<'a, [u8]>, PoaError> {
+        let index = self.num_sequences();
+        self.params.case_policy.apply(seq).map_err(|reason| PoaError::InvalidRead(RejectedRead { index, reason }))
+    }
+
+    // the bases of a query as the case policy of the graph aligns them; a query the policy would
+    // reject is aligned as given, as it is not added
+    pub(crate) fn query_bases<'a>(&self, seq: &'a [u8]) -> Cow<'a, [u8]> {
+        self.params.case_policy.apply(seq).unwrap_or(Cow::Borrowed(seq))
+    }
+
+    /// Aligns a sequence to the graph and adds it.
+    ///
+    /// A graph takes sequences of up to `u32::MAX` bases, as SPOA numbers nodes and bases with 32
+    /// bits; adding a longer one panics rather than truncating it, as do the other methods adding
+    /// sequences.
+    ///
+    /// If the parameters of the graph set `homopolymer_gaps`, the sequence is aligned with those gap
+    /// scores inside long homopolymer runs, which is slower than the native alignment engine. If
+    /// they set `band_width`, only the cells of the band are computed, and if they set `end_gaps`,
+    /// the ends of the alignment are scored as those say. Either way the sequence is aligned in
+    /// Rust, with affine gap scores. Lower-case bases are aligned as their
+    /// `case_policy` says, in upper case by default, as by the other methods adding sequences.
+    pub fn add_sequence(&mut self, seq: &[u8]) {
+        self.try_add_sequence(seq).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Aligns a sequence to the graph and adds it as `add_sequence` does, returning
+    /// `PoaError::Native` if the native library fails, e.g. runs out of memory. A failed alignment
+    /// leaves the graph unchanged.
+    pub fn try_add_sequence(&mut self, seq: &[u8]) -> Result<(), PoaError> {
+        let seq = self.cased(seq)?;
+        let seq = seq.as_ref();
+        if self.params.is_scalar() {
+            self.add_sequence_scalar(seq, None);
+        } else {
+            unsafe { poa_graph_add(self.raw.as_ptr(), seq.as_ptr(), native_len(seq.len())) }
+        }
+        native_result()
+    }
+
+    /// Aligns a sequence to the graph and adds it, giving each base the weight at the same position
+    /// of `weights` instead of 1.
+    ///
+    /// Heavier bases count for more when the consensus is chosen; Phred qualities are a common choice.
+    /// Homopolymer gap scores and the band are used as by `add_sequence`.
+    ///
+    /// # Panics
+    /// Panics if `weights` does not have the length of `seq`.
+    pub fn add_sequence_weighted(&mut self, seq: &[u8], weights: &[u32]) {
+        assert_eq!(seq.len(), weights.len(), "weights length differs from sequence length");
+        let seq = self.cased(seq).unwrap_or_else(|error| panic!("{}", error));
+        let seq = seq.as_ref();
+
+        if self.params.is_scalar() {
+            return self.add_sequence_scalar(seq, Some(weights));
+        }
+        unsafe {
+            poa_graph_add_weighted(self.raw.as_ptr(), seq.as_ptr(), native_len(seq.len()), weights.as_ptr(), u32::MAX, u32::MAX);
+        }
+        check_native();
+    }
+
+    /// Aligns a sequence to the graph and adds it with every base weighing `weight`, e.g. for a read
+    /// that is itself the consensus of `weight` molecules.
+    ///
+    /// The bases, and so the edges along the sequence, count as if `weight` copies of the sequence
+    /// had been added, while node coverage still counts the sequence once.
+    pub fn add_sequence_with_weight(&mut self, seq: &[u8], weight: u32) {
+        self.add_sequence_weighted(seq, &vec![weight; seq.len()]);
+    }
+
+    /// Aligns a record to the graph and adds it, weighting its bases as described for `SeqRecord`.
+    ///
+    /// # Panics
+    /// Panics if the quality string of the record does not have the length of its sequence.
+    pub fn add_record(&mut self, record: &SeqRecord) {
+        match record.base_weights() {
+            Some(weights) => self.add_sequence_weighted(&record.seq, &weights),
+            None => self.add_sequence(&record.seq),
+        }
+    }
+
+    /// Aligns a sequence to the graph and adds it, unless this would exceed one of the `limits`.
+    ///
+    /// The sequence is aligned as by `add_sequence`. A rejected sequence leaves the graph unchanged.
+    pub fn add_sequence_limited(&mut self, seq: &[u8], limits: &GrowthLimits) -> Result<(), RejectReason> {
+        let seq = self.params.case_policy.apply(seq)?;
+        let seq = seq.as_ref();
+        if let Some(max_memory) = limits.max_memory {
+            if self.memory_usage().saturating_add(self.alignment_memory(seq.len())) > max_memory {
+                return Err(RejectReason::MemoryLimit);
+            }
+        }
+        let max_nodes = limits.max_nodes.map_or(u32::MAX, |n| n.min(u32::MAX as usize) as u32);
+        let max_inserted = limits.max_read_insertions.map_or(u32::MAX, |n| n.min(u32::MAX as usize) as u32);
+
+        let result = if self.params.is_scalar() {
+            self.add_sequence_scalar_checked(seq, None, max_nodes, max_inserted, 0.0, &mut 0.0)
+        } else {
+            unsafe { poa_graph_add_limited(self.raw.as_ptr(), seq.as_ptr(), native_len(seq.len()), max_nodes, max_inserted) }
+        };
+        check_native();
+        match result {
+            0 => Ok(()),
+            1 => Err(RejectReason::NodeLimit),
+            _ => Err(RejectReason::InsertionLimit),
+        }
+    }
+
+    /// Aligns a sequence to the graph and adds it, unless its identity to the backbone, the first
+    /// sequence added to the graph, is below `min_identity`.
+    ///
+    /// The identity is measured on the alignment used to add the sequence, which is that of
+    /// `add_sequence`: it is the fraction of alignment columns where the base matches the backbone,
+    /// counting the bases a local alignment leaves out as columns. A rejected sequence leaves the
+    /// graph unchanged; the backbone itself is always added, with identity 1.
+    ///
+    /// # Returns
+    /// * returns the identity of the added sequence to the backbone
+    pub fn add_sequence_min_identity(&mut self, seq: &[u8], min_identity: f64) -> Result<f64, RejectReason> {
+        let seq = self.params.case_policy.apply(seq)?;
+        let seq = seq.as_ref();
+        let mut identity = 0.0;
+        let result = if self.params.is_scalar() {
+            self.add_sequence_scalar_checked(seq, None, u32::MAX, u32::MAX, min_identity, &mut identity)
+        } else {
+            unsafe {
+                poa_graph_add_checked(
+                    self.raw.as_ptr(),
+                    seq.as_ptr(),
+                    native_len(seq.len()),
+                    std::ptr::null(),
+                    u32::MAX,
+                    u32::MAX,
+                    min_identity,
+                    &mut identity,
+                )
+            }
+        };
+        check_native();
+        match result {
+            0 => Ok(identity),
+            _ => Err(RejectReason::LowIdentity),
+        }
+    }
+
+    /// Aligns a sequence to the part of the graph around a known position on the backbone, the
+    /// first sequence added to the graph, and adds it.
+    ///
+    /// `offset` is the approximate backbone position of the first base of `seq`, e.g. from a read
+    /// mapper. The sequence is aligned only to the nodes between backbone positions
+    /// `offset - slack` and `offset + seq.len() + slack`, with the branches added between them, so
+    /// a read tiling a small part of a long backbone costs no more to align than the part itself
+    /// and cannot be misplaced elsewhere along it. Semi-global alignment suits such reads best, as
+    /// the window is wider than the read. The sequence is aligned to the whole graph if the graph
+    /// is empty or the offset is past the end of the backbone. Homopolymer gap scores, the band and
+    /// end gaps are used as by `add_sequence`, within the window.
+    pub fn add_sequence_anchored(&mut self, seq: &[u8], offset: usize, slack: usize) {
+        let seq = self.cased(seq).unwrap_or_else(|error| panic!("{}", error));
+        let seq = seq.as_ref();
+        let begin = offset.saturating_sub(slack).min(u32::MAX as usize) as u32;
+        let end = offset.saturating_add(seq.len()).saturating_add(slack).min(u32::MAX as usize) as u32;
+
+        if self.params.is_scalar() {
+            let region = self.anchored_region(begin as usize, end as usize);
+            self.add_sequence_scalar_checked(seq, region.as_deref(), u32::MAX, u32::MAX, 0.0, &mut 0.0);
+        } else {
+            unsafe {
+                poa_graph_add_anchored(self.raw.as_ptr(), seq.as_ptr(), native_len(seq.len()), std::ptr::null(), begin, end);
+            }
+        }
+        check_native();
+    }
+
+    /// Extracts the region of the graph between two nodes, as a new graph with the same parameters.
+    ///
+    /// The region holds every node on a path from `from_node` to `to_node`, both included, and the
+    /// edges between them; it is empty if `to_node` cannot be reached from `from_node`. Each
+    /// sequence passing through the region becomes a sequence of the new graph holding just its
+    /// bases inside the region, so the sequences are renumbered in the order they were added.
+    ///
+    /// # Panics
+    /// Panics if either node id is not less than `num_nodes()`.
+    pub fn subgraph(&self, from_node: u32, to_node: u32) -> PoaGraph {
+        let num_nodes = self.num_nodes();
+        assert!((from_node as usize) < num_nodes && (to_node as usize) < num_nodes, "node id out of range");
+
+        let subgraph = self.empty_like();
+        unsafe { poa_graph_add_subgraph(subgraph.raw.as_ptr(), self.raw.as_ptr(), from_node, to_node) }
+        check_native();
+        subgraph
+    }
+
+    /// Extracts the region of the graph spanned by the bases `range` of the sequence at
+    /// `seq_index`, as by `subgraph` from the node of its first base to the node of its last one.
+    ///
+    /// The region is empty if `range` is.
+    ///
+    /// # Panics
+    /// Panics if `seq_index` is not less than `num_sequences()`, or `range` ends past the sequence.
+    pub fn sequence_subgraph(&self, seq_index: usize, range: Range<usize>) -> PoaGraph {
+        let path = self.sequence_path(seq_index);
+        assert!(range.end <= path.len(), "range {:?} ends past the {} bases of sequence {}", range, path.len(), seq_index);
+        if range.start >= range.end {
+            return self.empty_like();
+        }
+        self.subgraph(path[range.start], path[range.end - 1])
+    }
+
+    /// Returns the consensus of the region of the graph spanned by the bases `range` of the
+    /// sequence at `seq_index`, e.g. of bases 2000 to 3000 of the first read, without rebuilding
+    /// the graph from the parts of the reads in the region.
+    ///
+    /// The consensus is that of `sequence_subgraph`, so it starts and ends where the sequence does
+    /// in the region, and reads only partly overlapping the region count as far as they do.
+    ///
+    /// # Panics
+    /// Panics if `seq_index` is not less than `num_sequences()`, or `range` ends past the sequence.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+    ///     for seq in ["GATTACAGGCTTACCGATCG", "GATTACAGGATTACCGATCG", "GATTACAGGCTTACCGATCG"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     // bases 5 to 14 of the second read, whose A at 9 the other reads correct
+    ///     assert_eq!(graph.region_consensus(1, 5..15), b"CAGGCTTACC".to_vec());
+    /// ```
+    pub fn region_consensus(&self, seq_index: usize, range: Range<usize>) -> Vec<u8> {
+        self.sequence_subgraph(seq_index, range).consensus()
+    }
+
+    /// Returns the reverse complement of the graph, as a new graph with the same parameters.
+    ///
+    /// Every node holds the complement of its base (IUPAC ambiguity codes included, other symbols
+    /// are kept) and every edge is reversed, with node and edge weights preserved. Each sequence
+    /// becomes its reverse complement under the same index, but node ids are renumbered.
+    ///
+    /// # Panics
+    /// Panics if the graph aligns protein reads, as shown by `is_protein`.
+    pub fn reverse_complement(&self) -> PoaGraph {
+        assert!(!self.is_protein(), "protein reads have no reverse complement");
+        let graph = self.empty_like();
+        unsafe { poa_graph_add_reverse_complement(graph.raw.as_ptr(), self.raw.as_ptr()) }
+        check_native();
+        graph
+    }
+
+    /// Removes the nodes and edges fewer than `min_reads` sequences pass through, such as the
+    /// branches of sequencing errors, and returns the number of nodes removed.
+    ///
+    /// The graph stays consistent with its sequences, which keep their indices: the base of a read
+    /// on a removed node moves to the kept node aligned to it with the most reads, as for a
+    /// substitution, or is dropped if there is none, as for an insertion; a read skipping kept
+    /// nodes along a removed edge, as for a deletion, passes through them again along the
+    /// strongest path of kept edges. So the sequences spell the reads corrected by the others, and
+    /// later reads align to a smaller graph. A removed edge stays only where no path of kept edges
+    /// joins its ends. Node ids are renumbered, and the pruned sequences have no alignment score.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+    ///     for seq in ["GATTACAGGC", "GATTACAGGC", "GATAACAGGC", "GATTACAGGC"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     assert_eq!(graph.prune(2), 1);
+    ///     assert_eq!(graph.num_nodes(), 10);
+    ///     assert_eq!(graph.multiple_sequence_alignment(false)[2], b"GATTACAGGC".to_vec());
+    /// ```
+    pub fn prune(&mut self, min_reads: usize) -> usize {
+        let num_nodes = self.num_nodes();
+        let pruned = self.empty_like();
+        unsafe { poa_graph_add_pruned(pruned.raw.as_ptr(), self.raw.as_ptr(), min_reads.min(u32::MAX as usize) as u32) }
+        check_native();
+        *self = pruned;
+        num_nodes - self.num_nodes()
+    }
+
+    /// Returns the parameters sequences are aligned with.
+    pub fn params(&self) -> PoaParams {
+        self.params
+    }
+
+    /// Returns the substitution matrix sequences are aligned with, if the graph was created by `with_matrix`.
+    pub fn matrix(&self) -> Option<&SubstitutionMatrix> {
+        self.matrix.as_ref()
+    }
+
+    /// Returns the instruction set the alignment engine of the graph uses, never `SimdBackend::Auto`.
+    pub fn simd(&self) -> SimdBackend {
+        SimdBackend::from_code(unsafe { poa_graph_simd(self.raw.as_ptr()) }).unwrap_or(SimdBackend::Scalar)
+    }
+
+    /// Returns the number of sequences added to the graph.
+    pub fn num_sequences(&self) -> usize {
+        unsafe { poa_graph_num_sequences(self.raw.as_ptr()) as usize }
+    }
+
+    /// Returns the number of nodes in the graph.
+    pub fn num_nodes(&self) -> usize {
+        unsafe { poa_graph_num_nodes(self.raw.as_ptr()) as usize }
+    }
+
+    /// Generates the consensus of the sequences added so far.
+    pub fn consensus(&self) -> Vec<u8> {
+        self.try_consensus().unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    // the consensus, or the native error generating it
+    pub(crate) fn try_consensus(&self) -> Result<Vec<u8>, PoaError> {
+        let mut consensus = vec![];
+        self.try_consensus_into(&mut consensus).map(|()| consensus)
+    }
+
+    // the consensus written into consensus, replacing its contents and reusing its allocation
+    pub(crate) fn try_consensus_into(&self, consensus: &mut Vec<u8>) -> Result<(), PoaError> {
+        // the consensus is a path through the graph, so it cannot be longer than the node count
+        consensus.clear();
+        consensus.resize(self.num_nodes(), 0);
+
+        unsafe {
+            let len = poa_graph_consensus(self.raw.as_ptr(), consensus.as_mut_ptr(), native_len(consensus.len()));
+            consensus.truncate(len as usize);
+        }
+
+        native_result()
+    }
+
+    // the consensus with the agreeing fraction, coverage and agreeing reads of each base, as
+    // poa_func_support gives them
+    pub(crate) fn try_consensus_support(&self) -> Result<SupportBuffers, PoaError> {
+        let mut buffers = SupportBuffers::default();
+        unsafe {
+            poa_graph_consensus_support(self.raw.as_ptr(), &mut buffers as *mut SupportBuffers as *mut c_void, reserve_support);
+        }
+        native_result().map(|()| buffers)
+    }
+
+    /// Generates the consensus, leaving out the consensus bases covered by fewer than `min_coverage` sequences.
+    ///
+    /// With a `min_coverage` of 0 or 1 this is the same as `consensus`.
+    pub fn consensus_with_min_coverage(&self, min_coverage: u32) -> Vec<u8> {
+        self.consensus_masked(min_coverage, LowCoverage::Drop)
+    }
+
+    /// Generates the consensus, masking or leaving out the consensus bases covered by fewer than
+    /// `min_coverage` sequences.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{LowCoverage, PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(2, 5, -4, -8, -6));
+    ///     graph.add_sequence(b"AATGCCCGTT");
+    ///     graph.add_sequence(b"AATGCCCGTT");
+    ///     graph.add_sequence(b"AATGCCCGTTACGT");
+    ///
+    ///     assert_eq!(graph.consensus_masked(2, LowCoverage::Mask(b'N')), b"AATGCCCGTTNNNN".to_vec());
+    /// ```
+    pub fn consensus_masked(&self, min_coverage: u32, low_coverage: LowCoverage) -> Vec<u8> {
+        let nodes = self.nodes();
+        let path = self.consensus_path();
+        let bases: Vec<u8> = path.iter().map(|&id| nodes[id as usize].base).collect();
+        let coverage: Vec<u32> = path.iter().map(|&id| nodes[id as usize].coverage).collect();
+        mask_low_coverage(&bases, &coverage, min_coverage, low_coverage)
+    }
+
+    /// Generates the multiple sequence alignment of the sequences in the graph, using `-` for gaps.
+    ///
+    /// The rows hold the sequences in the order they were added, followed by the aligned
+    /// consensus if `include_consensus` is set. All rows have the same length.
+    pub fn multiple_sequence_alignment(&self, include_consensus: bool) -> Vec<Vec<u8>> {
+        if self.num_sequences() == 0 {
+            return vec![];
+        }
+
+        let num_rows = self.num_sequences() + include_consensus as usize;
+        unsafe {
+            let len = poa_graph_msa_len(self.raw.as_ptr()) as usize;
+            let mut rows: Vec<u8> = vec![0; num_rows * len];
+            poa_graph_msa(self.raw.as_ptr(), rows.as_mut_ptr(), include_consensus as i32);
+            check_native();
+            rows.chunks(len.max(1)).map(|row| row.to_vec()).collect()
+        }
+    }
+
+    /// Returns the ids of the nodes spelling the consensus, in order.
+    ///
+    /// This joins each consensus position to the graph, e.g. to inspect the alternatives to a
+    /// consensus base through the edges of its predecessor.
+    pub fn consensus_path(&self) -> Vec<u32> {
+        let mut node_ids: Vec<u32> = vec![0; self.num_nodes()];
+
+        unsafe {
+            let len = poa_graph_consensus_path(self.raw.as_ptr(), node_ids.as_mut_ptr(), native_len(node_ids.len()));
+            node_ids.truncate(len as usize);
+        }
+        check_native();
+
+        node_ids
+    }
+
+    /// Returns the nodes of the graph, indexed by node id.
+    pub fn nodes(&self) -> Vec<Node> {
+        let num_nodes = self.num_nodes();
+        let mut bases: Vec<u8> = vec![0; num_nodes];
+        let mut weights: Vec<u64> = vec![0; num_nodes];
+
+        unsafe { poa_graph_nodes(self.raw.as_ptr(), bases.as_mut_ptr(), weights.as_mut_ptr()) }
+
+        // a sequence passes through a node at most once
+        let mut coverage: Vec<u32> = vec![0; num_nodes];
+        for label in 0..self.num_sequences() {
+            for id in self.sequence_path(label) {
+                coverage[id as usize] += 1;
+            }
+        }
+
+        bases
+            .into_iter()
+            .zip(weights)
+            .zip(coverage)
+            .enumerate()
+            .map(|(id, ((base, weight), coverage))| Node { id: id as u32, base, weight, coverage })
+            .collect()
+    }
+
+    /// Returns the edges of the graph, ordered by the node they leave and then the node they enter.
+    pub fn edges(&self) -> Vec<Edge> {
+        let num_edges = unsafe { poa_graph_num_edges(self.raw.as_ptr()) as usize };
+        let mut tails: Vec<u32> = vec![0; num_edges];
+        let mut heads: Vec<u32> = vec![0; num_edges];
+        let mut weights: Vec<u64> = vec![0; num_edges];
+
+        unsafe {
+            poa_graph_edges(self.raw.as_ptr(), tails.as_mut_ptr(), heads.as_mut_ptr(), weights.as_mut_ptr())
+        }
+        check_native();
+
+        let mut edges: Vec<Edge> = tails
+            .into_iter()
+            .zip(heads)
+            .zip(weights)
+            .map(|((from, to), weight)| Edge { from, to, weight, labels: vec![] })
+            .collect();
+
+        for label in 0..self.num_sequences() {
+            for pair in self.sequence_path(label).windows(2) {
+                let i = edges
+                    .binary_search_by_key(&(pair[0], pair[1]), |e| (e.from, e.to))
+                    .expect("every step of a sequence path is an edge");
+                edges[i].labels.push(label as u32);
+            }
+        }
+
+        edges
+    }
+
+    /// Returns how many bases at each end of the sequence added at `seq_index` were left unaligned.
+    ///
+    /// The clips are measured against the graph as it was when the sequence was added, so the
+    /// first sequence of a graph has none. A sequence without any aligned base is clipped entirely
+    /// at its start.
+    ///
+    /// # Panics
+    /// Panics if `seq_index` is not less than `num_sequences()`.
+    pub fn soft_clips(&self, seq_index: usize) -> SoftClips {
+        assert!(seq_index < self.num_sequences(), "sequence index out of range");
+
+        let (mut leading, mut trailing) = (0, 0);
+        unsafe { poa_graph_sequence_clips(self.raw.as_ptr(), native_index(seq_index), &mut leading, &mut trailing) }
+        SoftClips { leading: leading as usize, trailing: trailing as usize }
+    }
+
+    /// Returns the score of the alignment to the graph the sequence added at `seq_index` was added
+    /// along, by the scores of the graph.
+    ///
+    /// The score is measured against the graph as it was when the sequence was added, so it tells
+    /// how well the sequence fit the sequences before it. The first sequence of a graph, which is
+    /// not aligned to anything, has none, as do the fragments of sequences in a `subgraph`.
+    ///
+    /// # Panics
+    /// Panics if `seq_index` is not less than `num_sequences()`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     graph.add_sequence(b"ACGTACGT");
+    ///     graph.add_sequence(b"ACGTACGT");
+    ///     graph.add_sequence(b"ACGAACGT");
+    ///
+    ///     assert_eq!(graph.sequence_score(0), None);
+    ///     assert_eq!(graph.sequence_score(1), Some(40));
+    ///     assert_eq!(graph.sequence_score(2), Some(31));
+    /// ```
+    pub fn sequence_score(&self, seq_index: usize) -> Option<i32> {
+        assert!(seq_index < self.num_sequences(), "sequence index out of range");
+
+        match unsafe { poa_graph_sequence_score(self.raw.as_ptr(), native_index(seq_index)) } {
+            NO_SCORE => None,
+            score => Some(score),
+        }
+    }
+
+    /// Returns the id of the node representing each base of the sequence added at `seq_index`.
+    ///
+    /// # Panics
+    /// Panics if `seq_index` is not less than `num_sequences()`.
+    pub fn sequence_path(&self, seq_index: usize) -> Vec<u32> {
+        assert!(seq_index < self.num_sequences(), "sequence index out of range");
+
+        unsafe {
+            let len = poa_graph_sequence_len(self.raw.as_ptr(), native_index(seq_index)) as usize;
+            let mut node_ids: Vec<u32> = vec![0; len];
+            poa_graph_sequence_path(self.raw.as_ptr(), native_index(seq_index), node_ids.as_mut_ptr());
+            node_ids
+        }
+    }
+
+    // the summed length of the sequences of the graph
+    pub(crate) fn num_bases(&self) -> usize {
+        (0..self.num_sequences()).map(|i| unsafe { poa_graph_sequence_len(self.raw.as_ptr(), native_index(i)) as usize }).sum()
+    }
+
+    /// Returns the weight of each base of the sequence added at `seq_index`, as given when it was
+    /// added; the bases of unweighted sequences weigh 1.
+    ///
+    /// # Panics
+    /// Panics if `seq_index` is not less than `num_sequences()`.
+    pub fn sequence_weights(&self, seq_index: usize) -> Vec<u32> {
+        assert!(seq_index < self.num_sequences(), "sequence index out of range");
+
+        unsafe {
+            let len = poa_graph_sequence_len(self.raw.as_ptr(), native_index(seq_index)) as usize;
+            let mut weights: Vec<u32> = vec![0; len];
+            poa_graph_sequence_weights(self.raw.as_ptr(), native_index(seq_index), weights.as_mut_ptr());
+            weights
+        }
+    }
+
+    /// Exports the graph as JSON, for web visualizers such as Cytoscape.js.
+    ///
+    /// The output holds a `nodes` array of `{"id", "symbol", "weight"}` objects and an `edges`
+    /// array of `{"from", "to", "weight", "labels"}` objects, where `labels` lists the indices of
+    /// the sequences passing along the edge.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"nodes\":[");
+
+        for (i, node) in self.nodes().iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write!(json, "{{\"id\":{},\"symbol\":", node.id).unwrap();
+            write_json_symbol(&mut json, node.base);
+            write!(json, ",\"weight\":{}}}", node.weight).unwrap();
+        }
+
+        json.push_str("],\"edges\":[");
+
+        for (i, edge) in self.edges().iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write!(json, "{{\"from\":{},\"to\":{},\"weight\":{},\"labels\":[", edge.from, edge.to, edge.weight).unwrap();
+            for (j, label) in edge.labels.iter().enumerate() {
+                if j > 0 {
+                    json.push(',');
+                }
+                write!(json, "{}", label).unwrap();
+            }
+            json.push_str("]}");
+        }
+
+        json.push_str("]}");
+        json
+    }
+}
+
+/// Adds each sequence of an iterator in turn, as by `add_sequence`, consuming it lazily.
+///
+/// # Examples
+///
+/// ```
+///     use std::io::{BufRead, Cursor};
+///     use rust_spoa::{PoaGraph, PoaParams};
+///
+///     let stream = Cursor::new("AATGCCCGTT\nAATGCCGTT\nAACGCCCGTC\n");
+///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+///     graph.extend(stream.lines().map(|line| line.unwrap()));
+///
+///     assert_eq!(graph.num_sequences(), 3);
+///     assert_eq!(graph.consensus(), b"AATGCCCGTT".to_vec());
+/// ```
+impl<T: AsRef<[u8]>> Extend<T> for PoaGraph {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, seqs: I) {
+        for seq in seqs {
+            self.add_sequence(seq.as_ref());
+        }
+    }
+}
+
+impl Drop for PoaGraph {
+    fn drop(&mut self) {
+        unsafe { poa_graph_free(self.raw.as_ptr()) }
+    }
+}
+
+// the native graph and its alignment engine are owned by this value alone, and SPOA keeps no
+// global state; the C++ shim keeps only the error of the last call on each thread, which is taken
+// on the same thread right after the call, so a graph can move to another thread. it is not Sync:
+// methods taking &self, such as consensus, update caches of the native graph.
+unsafe impl Send for PoaGraph {}
+
+fn write_json_symbol(json: &mut String, base: u8) {
+    match base {
+        b'"' => json.push_str("\"\\\"\""),
+        b'\\' => json.push_str("\"\\\\\""),
+        0x20..=0x7e => write!(json, "\"{}\"", base as char).unwrap(),
+        _ => write!(json, "\"\\u{:04x}\"", base).unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dna_graph() -> PoaGraph {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        for seq in ["ATTGCCCGTT",
+            "AATGCCGTT",
+            "AATGCCCGAT",
+            "AACGCCCGTC",
+            "AGTGCTCGTT",
+            "AATGCTCGTT"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        graph
+    }
+
+    #[test]
+    fn test_graph_consensus() {
+        let graph = dna_graph();
+
+        assert_eq!(graph.num_sequences(), 6);
+        assert_eq!(graph.consensus(), b"AATGCCCGTT".to_vec());
+    }
+
+    #[test]
+    fn test_consensus_path() {
+        let graph = dna_graph();
+        let nodes = graph.nodes();
+        let edges = graph.edges();
+        let path = graph.consensus_path();
+
+        let seq: Vec<u8> = path.iter().map(|&id| nodes[id as usize].base).collect();
+        assert_eq!(seq, graph.consensus());
+        assert!(path.windows(2).all(|pair| edges.iter().any(|e| e.from == pair[0] && e.to == pair[1])));
+    }
+
+    #[test]
+    fn test_soft_clips() {
+        let mut graph = PoaGraph::new(&PoaParams::new(0, 5, -4, -8, -6));
+        graph.add_sequence(b"AATGCCCGTTAGCA");
+        graph.add_sequence(b"CCCAATGCCCGTTAGCATT");
+
+        assert_eq!(graph.soft_clips(0), SoftClips { leading: 0, trailing: 0 });
+        let clips = graph.soft_clips(1);
+        assert_eq!(clips, SoftClips { leading: 3, trailing: 2 });
+        assert_eq!(clips.split(b"CCCAATGCCCGTTAGCATT"), (&b"CCC"[..], &b"AATGCCCGTTAGCA"[..], &b"TT"[..]));
+
+        // the ends swap in the reverse complement
+        assert_eq!(graph.reverse_complement().soft_clips(1), SoftClips { leading: 2, trailing: 3 });
+        graph.clear();
+        graph.add_sequence(b"AATGCCCGTT");
+        assert_eq!(graph.soft_clips(0), SoftClips::default());
+    }
+
+    #[test]
+    fn test_subgraph() {
+        let graph = dna_graph();
+        let path = graph.consensus_path();
+
+        // the whole consensus span keeps every sequence and reproduces the consensus
+        let whole = graph.subgraph(path[0], path[path.len() - 1]);
+        assert_eq!(whole.num_sequences(), 6);
+        assert_eq!(whole.consensus(), graph.consensus());
+
+        let region = graph.subgraph(path[3], path[6]);
+        assert_eq!(region.consensus(), b"GCCC".to_vec());
+        assert!(region.num_nodes() < graph.num_nodes());
+        // unit base weights give edges twice the weight of the sequences along them
+        assert!(region.edges().iter().all(|e| e.weight == 2 * e.labels.len() as u64));
+        assert_eq!(graph.subgraph(path[6], path[3]).num_nodes(), 0);
+    }
+
+    #[test]
+    fn test_region_consensus() {
+        let graph = dna_graph();
+        // the whole of a sequence spans the consensus, which it starts and ends with
+        assert_eq!(graph.region_consensus(0, 0..10), graph.consensus());
+        // bases 1 to 6 of AACGCCCGTC, whose C at 2 the other reads correct; the consensus starts
+        // and ends with the nodes of the bases at the ends of the range
+        assert_eq!(graph.region_consensus(3, 1..7), b"ATGCCC".to_vec());
+        assert_eq!(graph.region_consensus(3, 2..7), b"CGCCC".to_vec());
+        assert_eq!(graph.sequence_subgraph(3, 1..7).num_sequences(), 6);
+        assert_eq!(graph.sequence_subgraph(1, 4..4).num_nodes(), 0);
+        assert!(std::panic::catch_unwind(|| dna_graph().region_consensus(1, 0..10)).is_err());
+    }
+
+    #[test]
+    fn test_reverse_complement() {
+        let graph = dna_graph();
+        let rc = graph.reverse_complement();
+        let nodes = rc.nodes();
+
+        assert_eq!(rc.num_sequences(), graph.num_sequences());
+        assert_eq!(rc.num_nodes(), graph.num_nodes());
+        assert_eq!(rc.edges().len(), graph.edges().len());
+
+        let seq: Vec<u8> = rc.sequence_path(1).iter().map(|&id| nodes[id as usize].base).collect();
+        assert_eq!(seq, b"AACGGCATT".to_vec());
+
+        let mut weights: Vec<u64> = graph.edges().iter().map(|e| e.weight).collect();
+        let mut rc_weights: Vec<u64> = rc.edges().iter().map(|e| e.weight).collect();
+        weights.sort_unstable();
+        rc_weights.sort_unstable();
+        assert_eq!(weights, rc_weights);
+
+        // complementing twice restores the graph up to node ids
+        assert_eq!(rc.reverse_complement().consensus(), graph.consensus());
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut graph = dna_graph();
+        let consensus = graph.consensus();
+        assert_eq!(graph.prune(1), 0);
+        assert_eq!(graph.consensus(), consensus);
+
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+        // a substitution, an insertion, a deletion and an empty read among copies of one sequence
+        for seq in ["GATTACAGGCTTACCGATCG", "GATTACAGGCTTACCGATCG", "GATTACACGCTTACCGATCG", "GATTACAGGCTTAACCGATCG", "",
+            "GATTACAGGCTTACCGTCG", "GATTACAGGCTTACCGATCG"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        let num_nodes = graph.num_nodes();
+        assert_eq!(graph.prune(2), num_nodes - 20);
+        assert_eq!(graph.num_nodes(), 20);
+        assert_eq!(graph.num_sequences(), 7);
+        assert_eq!(graph.consensus(), b"GATTACAGGCTTACCGATCG".to_vec());
+        // unit base weights give edges twice the weight of the sequences along them
+        assert!(graph.edges().iter().all(|edge| edge.weight >= 4));
+
+        let nodes = graph.nodes();
+        for read in [2, 3, 5].iter() {
+            let seq: Vec<u8> = graph.sequence_path(*read).iter().map(|&id| nodes[id as usize].base).collect();
+            assert_eq!(seq, b"GATTACAGGCTTACCGATCG".to_vec(), "{}", read);
+            assert_eq!(graph.sequence_score(*read), None);
+        }
+        assert!(graph.sequence_path(4).is_empty());
+
+        // nodes of fewer reads than the threshold cannot be kept, so no graph is left
+        assert_eq!(graph.prune(7), 20);
+        assert_eq!(graph.num_sequences(), 7);
+        assert!(graph.consensus().is_empty());
+    }
+
+    #[test]
+    fn test_outputs_from_one_graph() {
+        let graph = dna_graph();
+
+        assert_eq!(graph.consensus_with_min_coverage(0), graph.consensus());
+        assert_eq!(graph.consensus_with_min_coverage(3), graph.consensus());
+        // the fifth consensus base is supported by only three reads
+        assert_eq!(graph.consensus_with_min_coverage(4), b"AATGCCGTT".to_vec());
+        assert!(graph.consensus_with_min_coverage(7).is_empty());
+
+        let msa = graph.multiple_sequence_alignment(true);
+        assert_eq!(msa.len(), 7);
+        assert!(msa.iter().all(|row| row.len() == msa[0].len()));
+        let ungapped: Vec<u8> = msa[1].iter().copied().filter(|&c| c != b'-').collect();
+        assert_eq!(ungapped, b"AATGCCGTT".to_vec());
+        let consensus: Vec<u8> = msa[6].iter().copied().filter(|&c| c != b'-').collect();
+        assert_eq!(consensus, graph.consensus());
+        assert_eq!(graph.multiple_sequence_alignment(false), msa[..6].to_vec());
+    }
+
+    #[test]
+    fn test_node_coverage() {
+        let graph = dna_graph();
+        let nodes = graph.nodes();
+
+        // with unit base weights, the weight of a node is its coverage
+        assert!(nodes.iter().all(|n| n.weight == n.coverage as u64));
+        assert_eq!(nodes[graph.consensus_path()[0] as usize].coverage, 6);
+    }
+
+    #[test]
+    fn test_native_lengths() {
+        // lengths past the range of i32 reach the native graph intact
+        assert_eq!(native_len((1 << 31) + 1), (1 << 31) + 1);
+        assert_eq!(native_len(u32::MAX as usize), u32::MAX);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    #[should_panic(expected = "longer than u32::MAX bases")]
+    fn test_native_length_beyond_u32() {
+        native_len(1 << 32);
+    }
+
+    #[test]
+    fn test_extend_in_chunks() {
+        let seqs = ["ATTGCCCGTT", "AATGCCGTT", "AATGCCCGAT", "AACGCCCGTC", "AGTGCTCGTT", "AATGCTCGTT"];
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        graph.extend(&seqs[..2]);
+        graph.extend(seqs[2..].iter().map(|seq| seq.as_bytes().to_vec()));
+
+        let expected = dna_graph();
+        assert_eq!(graph.num_sequences(), 6);
+        assert_eq!(graph.nodes(), expected.nodes());
+        assert_eq!(graph.consensus(), expected.consensus());
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut graph = dna_graph();
+        graph.clear();
+
+        assert_eq!(graph.num_sequences(), 0);
+        assert_eq!(graph.num_nodes(), 0);
+        assert!(graph.consensus_path().is_empty());
+
+        for seq in ["AACGT", "AACGT", "ACCGT"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        assert_eq!(graph.num_sequences(), 3);
+        assert_eq!(graph.consensus(), b"AACGT".to_vec());
+        assert_eq!(graph.consensus_path().len(), 5);
+    }
+
+    #[test]
+    fn test_graph_moves_between_threads() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        graph.add_sequence(b"AATGCCCGTT");
+        let mut graph = std::thread::spawn(move || {
+            graph.add_sequence(b"AATGCCCGTT");
+            graph
+        })
+        .join()
+        .unwrap();
+        graph.add_sequence(b"AATGCCGTT");
+        assert_eq!((graph.num_sequences(), graph.consensus()), (3, b"AATGCCCGTT".to_vec()));
+    }
+
+    #[test]
+    fn test_graph_handed_between_workers() {
+        let (built, finalize) = std::sync::mpsc::channel::<PoaGraph>();
+        let (done, results) = std::sync::mpsc::channel();
+        let finisher = std::thread::spawn(move || {
+            for graph in finalize {
+                done.send((graph.num_sequences(), graph.consensus())).unwrap();
+            }
+        });
+        let builder = std::thread::spawn(move || {
+            for seqs in [["AATGCCCGTT", "AATGCCGTT", "AATGCCCGTT"], ["AACGT", "AACGT", "ACCGT"]].iter() {
+                let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+                graph.extend(seqs);
+                built.send(graph).unwrap();
+            }
+        });
+
+        builder.join().unwrap();
+        finisher.join().unwrap();
+        let results: Vec<(usize, Vec<u8>)> = results.iter().collect();
+        assert_eq!(results, vec![(3, b"AATGCCCGTT".to_vec()), (3, b"AACGT".to_vec())]);
+    }
+
+    #[test]
+    fn test_consensus_between_additions() {
+        let seqs = ["ATTGCCCGTT", "AATGCCGTT", "AATGCCCGAT", "AACGCCCGTC", "AGTGCTCGTT", "AATGCTCGTT"];
+        let mut incremental = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        for (i, seq) in seqs.iter().enumerate() {
+            incremental.add_sequence(seq.as_bytes());
+            assert_eq!(incremental.num_sequences(), i + 1);
+            assert!(!incremental.consensus().is_empty());
+        }
+
+        // querying the consensus on the way does not change the graph
+        assert_eq!(incremental.consensus(), dna_graph().consensus());
+        assert_eq!(incremental.edges(), dna_graph().edges());
+    }
+
+    #[test]
+    fn test_empty_graph() {
+        let graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+
+        assert_eq!(graph.num_nodes(), 0);
+        assert!(graph.consensus().is_empty());
+        assert!(graph.consensus_path().is_empty());
+        assert!(graph.multiple_sequence_alignment(true).is_empty());
+        assert_eq!(graph.to_json(), "{\"nodes\":[],\"edges\":[]}");
+    }
+
+    #[test]
+    fn test_sequence_paths_spell_sequences() {
+        let graph = dna_graph();
+        let nodes = graph.nodes();
+
+        let seq: Vec<u8> = graph.sequence_path(1).iter().map(|&id| nodes[id as usize].base).collect();
+        assert_eq!(seq, b"AATGCCGTT".to_vec());
+
+        for label in 0..graph.num_sequences() {
+            assert!(graph.sequence_path(label).iter().all(|&id| (id as usize) < nodes.len()));
+        }
+    }
+
+    #[test]
+    fn test_node_and_edge_weights() {
+        let graph = dna_graph();
+        let nodes = graph.nodes();
+        let edges = graph.edges();
+
+        // every base of every sequence is represented by exactly one node
+        assert_eq!(nodes.iter().map(|n| n.weight).sum::<u64>(), 59);
+        // with unit base weights, an edge weighs twice the number of sequences along it
+        assert!(edges.iter().all(|e| e.weight == 2 * e.labels.len() as u64));
+        assert_eq!(edges.iter().map(|e| e.labels.len()).sum::<usize>(), 59 - 6);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        graph.add_sequence(b"AC");
+        graph.add_sequence(b"AC");
+
+        assert_eq!(
+            graph.to_json(),
+            "{\"nodes\":[{\"id\":0,\"symbol\":\"A\",\"weight\":2},{\"id\":1,\"symbol\":\"C\",\"weight\":2}],\
+             \"edges\":[{\"from\":0,\"to\":1,\"weight\":4,\"labels\":[0,1]}]}"
+        );
+    }
+
+    #[test]
+    fn test_native_errors() {
+        // a positive gap score passes the native entry point but makes SPOA throw
+        let invalid = PoaParams::new(1, 5, -4, 3, -6);
+        match PoaGraph::try_new(&invalid) {
+            Err(PoaError::Native(message)) => assert!(!message.is_empty()),
+            _ => panic!("expected a native error"),
+        }
+
+        // the error is taken, so it does not leak into the next call
+        let mut graph = PoaGraph::try_new(&PoaParams::new(1, 5, -4, -3, -1)).unwrap();
+        assert_eq!(graph.try_add_sequence(b"AATGCCCGTT"), Ok(()));
+        assert_eq!(graph.try_consensus(), Ok(b"AATGCCCGTT".to_vec()));
+        assert!(PoaError::Native("std::bad_alloc".to_string()).to_string().contains("std::bad_alloc"));
+    }
+
+    #[test]
+    #[should_panic(expected = "native error")]
+    fn test_native_error_panics() {
+        PoaGraph::new(&PoaParams::new(1, 5, -4, 3, -6));
+    }
+}
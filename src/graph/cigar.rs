@@ -0,0 +1,298 @@
+//! Describing how each sequence of a graph aligns to the consensus, e.g. to filter poor reads.
+
+use std::fmt::Write;
+
+use super::PoaGraph;
+
+/// How a sequence of a graph aligns to the consensus, with the score it was added with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReadAlignment {
+    /// the score of the alignment to the graph the sequence was added along, as by `PoaGraph::sequence_score`
+    pub score: Option<i32>,
+    /// the position of the consensus the alignment starts at
+    pub consensus_start: usize,
+    /// the alignment as an extended CIGAR string from `consensus_start` on, with `=` for matches, `X`
+    /// for mismatches, `I` for bases missing from the consensus and `D` for consensus bases missing
+    /// from the sequence; empty for a sequence without bases
+    pub cigar: String,
+}
+
+impl ReadAlignment {
+    /// Returns the number of matches, mismatches, insertions and deletions of the alignment.
+    pub fn counts(&self) -> (usize, usize, usize, usize) {
+        let mut counts = (0, 0, 0, 0);
+        let mut len = 0;
+        for c in self.cigar.chars() {
+            match c {
+                '0'..='9' => len = len * 10 + c.to_digit(10).unwrap() as usize,
+                '=' => counts.0 += len,
+                'X' => counts.1 += len,
+                'I' => counts.2 += len,
+                _ => counts.3 += len,
+            }
+            if !c.is_ascii_digit() {
+                len = 0;
+            }
+        }
+        counts
+    }
+
+    /// Returns the fraction of alignment columns that are matches, 0 for a sequence without bases.
+    pub fn identity(&self) -> f64 {
+        let (matches, mismatches, insertions, deletions) = self.counts();
+        let columns = matches + mismatches + insertions + deletions;
+        if columns == 0 {
+            0.0
+        } else {
+            matches as f64 / columns as f64
+        }
+    }
+}
+
+// the CIGAR operation of an alignment column holding base of the sequence and consensus of the
+// consensus, '-' marking a gap, if either holds a base
+fn column_op(base: u8, consensus: u8) -> Option<u8> {
+    match (base, consensus) {
+        (b'-', b'-') => None,
+        (_, b'-') => Some(b'I'),
+        (b'-', _) => Some(b'D'),
+        _ if base == consensus => Some(b'='),
+        _ => Some(b'X'),
+    }
+}
+
+impl PoaGraph {
+    /// Returns how each sequence of the graph aligns to the consensus, in the order they were added.
+    ///
+    /// The alignments are read off the multiple sequence alignment, so they span a sequence from
+    /// its first to its last base; consensus bases before and after it are not part of its
+    /// alignment. Together with the scores, they tell reads that fit the graph poorly apart.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     for seq in ["AATGCCCGTT", "AATGCCCGTT", "AATGCCGTT", "AATCCCCGTT"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     let alignments = graph.read_alignments();
+    ///     assert_eq!(alignments[0].cigar, "10=");
+    ///     assert_eq!(alignments[2].cigar, "4=1D5=");
+    ///     assert_eq!(alignments[3].cigar, "3=1X6=");
+    ///     assert!(alignments[3].score < alignments[1].score);
+    /// ```
+    pub fn read_alignments(&self) -> Vec<ReadAlignment> {
+        let mut rows = self.multiple_sequence_alignment(true);
+        let consensus = rows.pop().unwrap_or_default();
+
+        rows.iter().enumerate().map(|(i, row)| row_alignment(row, &consensus, self.sequence_score(i))).collect()
+    }
+
+    /// Aligns each sequence of the graph to the consensus alone and returns where it starts on the
+    /// consensus and its CIGAR string, in the order the sequences were added, e.g. to write a BAM
+    /// file of the reads against the consensus for a genome browser.
+    ///
+    /// Unlike `read_alignments`, which reads the alignments off the graph, each sequence is
+    /// aligned pairwise to the consensus with the parameters of the graph, so the alignments are
+    /// those of a read mapper given the consensus as the reference. Consensus bases before or
+    /// after the aligned part of a sequence are not part of its alignment, and bases of the
+    /// sequence before and after it are soft-clipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+    ///     for seq in ["GATTACAGGCTTACCG", "GATTACAGGCTTACCG", "GATTACAGCTTACCG", "TACAGGCTTACCG"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     let realigned = graph.realign_to_consensus();
+    ///     assert_eq!(realigned[0].cigar, "16M");
+    ///     assert_eq!(realigned[2].cigar, "7M1D8M");
+    ///     assert_eq!((realigned[3].consensus_start, realigned[3].cigar.as_str()), (3, "13M"));
+    /// ```
+    pub fn realign_to_consensus(&self) -> Vec<Realignment> {
+        let consensus = self.consensus();
+        let mut reference = self.empty_like();
+        reference.add_sequence(&consensus);
+        let mut positions = vec![0; reference.num_nodes()];
+        for (position, id) in reference.sequence_path(0).into_iter().enumerate() {
+            positions[id as usize] = position;
+        }
+        let nodes = self.nodes();
+
+        (0..self.num_sequences())
+            .map(|i| {
+                let seq: Vec<u8> = self.sequence_path(i).iter().map(|&id| nodes[id as usize].base).collect();
+                let alignment = reference.align(&seq);
+                // the columns from the first to the last with both a consensus base and a base
+                let columns = &alignment.alignment;
+                let paired = |&(node, pos): &(Option<u32>, Option<usize>)| node.is_some() && pos.is_some();
+                let (first, last) = match (columns.iter().position(paired), columns.iter().rposition(paired)) {
+                    (Some(first), Some(last)) => (first, last),
+                    _ => {
+                        let cigar = if seq.is_empty() { String::new() } else { format!("{}S", seq.len()) };
+                        return Realignment { consensus_start: 0, cigar, score: alignment.score };
+                    }
+                };
+                let start = columns[first].1.unwrap();
+                let end = columns[last].1.unwrap() + 1;
+
+                let mut ops = vec![b'S'; start];
+                ops.extend(columns[first..=last].iter().map(|&(node, pos)| match (node, pos) {
+                    (Some(_), Some(_)) => b'M',
+                    (None, _) => b'I',
+                    (_, None) => b'D',
+                }));
+                ops.resize(ops.len() + seq.len() - end, b'S');
+                let consensus_start = positions[columns[first].0.unwrap() as usize];
+                Realignment { consensus_start, cigar: run_lengths(ops), score: alignment.score }
+            })
+            .collect()
+    }
+}
+
+// the alignment of an msa row to the consensus row of the same msa, from the first to the last
+// base of the row
+pub(crate) fn row_alignment(row: &[u8], consensus: &[u8], score: Option<i32>) -> ReadAlignment {
+    let first = match row.iter().position(|&base| base != b'-') {
+        Some(first) => first,
+        None => return ReadAlignment { score, consensus_start: 0, cigar: String::new() },
+    };
+    let last = row.iter().rposition(|&base| base != b'-').unwrap();
+    let consensus_start = consensus[..first].iter().filter(|&&base| base != b'-').count();
+    let cigar = run_lengths((first..=last).filter_map(|column| column_op(row[column], consensus[column])));
+    ReadAlignment { score, consensus_start, cigar }
+}
+
+// a CIGAR string of operations, each run of one operation written as its length and the operation
+fn run_lengths<I: IntoIterator<Item = u8>>(ops: I) -> String {
+    let mut cigar = String::new();
+    let mut run: Option<(u8, usize)> = None;
+    for op in ops {
+        run = match run {
+            Some((run_op, len)) if run_op == op => Some((op, len + 1)),
+            Some((run_op, len)) => {
+                write!(cigar, "{}{}", len, run_op as char).unwrap();
+                Some((op, 1))
+            }
+            None => Some((op, 1)),
+        };
+    }
+    if let Some((op, len)) = run {
+        write!(cigar, "{}{}", len, op as char).unwrap();
+    }
+    cigar
+}
+
+/// A sequence of a graph aligned afresh to the consensus, as returned by
+/// `PoaGraph::realign_to_consensus`, in the form of a SAM record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Realignment {
+    /// the 0-based position of the consensus the first aligned base of the sequence is on, 0 if no
+    /// base is aligned
+    pub consensus_start: usize,
+    /// the alignment as a SAM CIGAR string, with `M` for bases aligned to consensus bases, `I` and
+    /// `D` for bases missing from the consensus and from the sequence, and `S` for the bases at
+    /// either end of the sequence left out of the alignment; empty for a sequence without bases
+    pub cigar: String,
+    /// the score of the alignment to the consensus
+    pub score: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HomopolymerGaps, PoaParams};
+
+    #[test]
+    fn test_alignments_to_consensus() {
+        let mut graph = PoaGraph::new(&PoaParams::new(2, 5, -4, -8, -6));
+        for seq in ["GATTACAGGC", "GATTACAGGC", "TACAGGC", "GATTAGGAAACAGGC", "GATTACAGGC", ""].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        let alignments = graph.read_alignments();
+        assert_eq!(alignments.len(), 6);
+
+        // the first sequence is not aligned to anything, each match adds 5
+        assert_eq!(alignments[0].score, None);
+        assert_eq!(alignments[1].score, Some(50));
+        assert_eq!(alignments[2], ReadAlignment { score: Some(35), consensus_start: 3, cigar: "7=".to_string() });
+        assert_eq!(alignments[3].cigar, "4=5I6=");
+        assert_eq!(alignments[3].counts(), (10, 0, 5, 0));
+        assert!((alignments[3].identity() - 10.0 / 15.0).abs() < 1e-9);
+        assert_eq!(alignments[5].cigar, "");
+        assert_eq!(alignments[5].identity(), 0.0);
+
+        // every base of a sequence is in its alignment
+        for (alignment, seq) in alignments.iter().zip(&[10, 10, 7, 15, 10, 0]) {
+            let (matches, mismatches, insertions, _) = alignment.counts();
+            assert_eq!(matches + mismatches + insertions, *seq);
+        }
+        assert!(PoaGraph::new(&PoaParams::new(0, 5, -4, -8, -6)).read_alignments().is_empty());
+    }
+
+    #[test]
+    fn test_realign_to_consensus() {
+        let mut graph = PoaGraph::new(&PoaParams::new(0, 5, -4, -8, -6));
+        // the third read has an insertion, the fourth ends in an adapter the others outweigh
+        for seq in ["GATTACAGGCTTACCG", "GATTACAGGCTTACCG", "GATTACAGGAAACTTACCG", "GATTACAGGCTTAAAAAA", ""].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        assert_eq!(graph.consensus(), b"GATTACAGGCTTACCG".to_vec());
+        let realigned = graph.realign_to_consensus();
+        assert_eq!(realigned.len(), 5);
+        assert_eq!(realigned[1], Realignment { consensus_start: 0, cigar: "16M".to_string(), score: 80 });
+        assert_eq!(realigned[2].cigar, "9M3I7M");
+        assert_eq!((realigned[3].consensus_start, realigned[3].cigar.as_str()), (0, "13M5S"));
+        assert_eq!(realigned[4], Realignment { consensus_start: 0, cigar: String::new(), score: 0 });
+
+        // every base of a sequence is in its cigar
+        let lengths = realigned.iter().map(|realignment| {
+            let mut len = 0;
+            let mut total = 0;
+            for c in realignment.cigar.chars() {
+                match c.to_digit(10) {
+                    Some(digit) => len = len * 10 + digit as usize,
+                    None => {
+                        total += if c == 'D' { 0 } else { len };
+                        len = 0;
+                    }
+                }
+            }
+            total
+        });
+        assert_eq!(lengths.collect::<Vec<_>>(), vec![16, 16, 19, 18, 0]);
+    }
+
+    #[test]
+    fn test_scores_of_derived_graphs() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let mut graph = PoaGraph::new(&params);
+        for seq in ["ACGTACGTAC", "ACGTACGTAC", "ACGAACGTAC"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        let scores: Vec<Option<i32>> = (0..3).map(|i| graph.sequence_score(i)).collect();
+        assert_eq!(scores, vec![None, Some(50), Some(41)]);
+
+        let reverse = graph.reverse_complement();
+        assert_eq!((0..3).map(|i| reverse.sequence_score(i)).collect::<Vec<_>>(), scores);
+        let subgraph = graph.subgraph(1, 8);
+        assert!((0..subgraph.num_sequences()).all(|i| subgraph.sequence_score(i).is_none()));
+
+        // sequences aligned with homopolymer gap scores have scores too
+        let mut homopolymer = PoaGraph::new(&params.homopolymer_gaps(HomopolymerGaps::new(4, -2, -1)));
+        homopolymer.add_sequence(b"ACGTAAAAAACGT");
+        homopolymer.add_sequence(b"ACGTAAAAAACGT");
+        assert_eq!(homopolymer.sequence_score(1), Some(65));
+
+        graph.clear();
+        graph.add_sequence(b"ACGT");
+        assert_eq!(graph.sequence_score(0), None);
+    }
+}
@@ -0,0 +1,101 @@
+//! Exporting the graph in the DOT language of Graphviz.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use super::PoaGraph;
+
+const CONSENSUS_COLOR: &str = "red";
+
+fn write_dot_symbol(dot: &mut String, base: u8) {
+    match base {
+        b'"' => dot.push_str("\\\""),
+        b'\\' => dot.push_str("\\\\"),
+        0x20..=0x7e => dot.push(base as char),
+        _ => dot.push('?'),
+    }
+}
+
+impl PoaGraph {
+    /// Exports the graph as a Graphviz digraph, e.g. for `dot -Tpng`.
+    ///
+    /// Nodes are labelled with their base and laid out from left to right; the tooltip of a node
+    /// holds its id, weight and coverage. Edges are labelled with the number of sequences passing
+    /// along them, and their tooltip holds their weight, the summed weight of the bases at both
+    /// ends in SPOA. If `highlight_consensus` is set, the nodes and edges of the consensus path are
+    /// drawn in red.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     graph.add_sequence(b"ACGT");
+    ///     graph.add_sequence(b"ACGT");
+    ///     graph.add_sequence(b"ACTT");
+    ///
+    ///     let dot = graph.to_dot(true);
+    ///     assert!(dot.starts_with("digraph poa {"));
+    ///     assert!(dot.contains("1 -> 2 [label=\"2\""));
+    /// ```
+    pub fn to_dot(&self, highlight_consensus: bool) -> String {
+        let consensus = if highlight_consensus { self.consensus_path() } else { Vec::new() };
+        let on_path: HashSet<u32> = consensus.iter().copied().collect();
+        let path_edges: HashSet<(u32, u32)> = consensus.windows(2).map(|pair| (pair[0], pair[1])).collect();
+
+        let mut dot = String::from("digraph poa {\n    rankdir=LR;\n    node [shape=circle];\n");
+        for node in self.nodes() {
+            write!(dot, "    {} [label=\"", node.id).unwrap();
+            write_dot_symbol(&mut dot, node.base);
+            write!(dot, "\", tooltip=\"id {} weight {} coverage {}\"", node.id, node.weight, node.coverage).unwrap();
+            if on_path.contains(&node.id) {
+                write!(dot, ", color={}, penwidth=2", CONSENSUS_COLOR).unwrap();
+            }
+            dot.push_str("];\n");
+        }
+        for edge in self.edges() {
+            write!(dot, "    {} -> {} [label=\"{}\", tooltip=\"weight {}\"", edge.from, edge.to, edge.labels.len(), edge.weight).unwrap();
+            if path_edges.contains(&(edge.from, edge.to)) {
+                write!(dot, ", color={}, penwidth=2", CONSENSUS_COLOR).unwrap();
+            }
+            dot.push_str("];\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PoaGraph, PoaParams};
+
+    #[test]
+    fn test_dot_highlights_consensus() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        for seq in ["AATGCCCGTT", "AATGCCGTT", "AATGCCCGTT"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        let path = graph.consensus_path();
+
+        let plain = graph.to_dot(false);
+        assert_eq!(plain.matches("label=").count(), graph.num_nodes() + graph.edges().len());
+        assert!(!plain.contains("color="));
+
+        let highlighted = graph.to_dot(true);
+        assert_eq!(highlighted.matches("color=red").count(), 2 * path.len() - 1);
+        let first_edge = format!("    {} -> {} [label=\"3\", tooltip=\"weight 6\", color=red", path[0], path[1]);
+        assert!(highlighted.contains(&first_edge));
+    }
+
+    #[test]
+    fn test_empty_graph_and_escaping() {
+        let graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        assert_eq!(graph.to_dot(true), "digraph poa {\n    rankdir=LR;\n    node [shape=circle];\n}\n");
+
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        graph.add_sequence(b"A\"\\");
+        let dot = graph.to_dot(false);
+        assert!(dot.contains("[label=\"\\\"\"") && dot.contains("[label=\"\\\\\""));
+    }
+}
@@ -0,0 +1,262 @@
+//! Recomputing the dynamic-programming matrix of aligning one sequence to a graph, for inspecting
+//! alignments under unusual scoring parameters.
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use super::PoaGraph;
+
+// the score of unreachable cells, as in the native alignment engine
+const NEGATIVE_INFINITY: i32 = i32::MIN + 1024;
+
+/// The score matrix of aligning a sequence to a graph, filled as by the native alignment engine.
+///
+/// Row 0 and column 0 hold the initial conditions of the alignment mode; row `r` for `r > 0`
+/// belongs to node `node_ids[r - 1]` and column `c > 0` to base `c - 1` of the sequence. The rows
+/// follow a topological order of the nodes, which may differ from the order used natively, but
+/// every score only depends on the scores of predecessor nodes, so the values are the same. With
+/// affine gaps (a gap open score below the gap extend score) the matrix holds the best score of
+/// each cell over the match, insertion and deletion states.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DpMatrix {
+    /// the node of each row after the first
+    pub node_ids: Vec<u32>,
+    /// the base of each row after the first
+    pub bases: Vec<u8>,
+    /// the aligned sequence
+    pub seq: Vec<u8>,
+    /// the scores, row by row, with `seq.len() + 1` columns
+    pub scores: Vec<i32>,
+    /// the row and column of the cell the alignment is traced back from, if any
+    pub best: Option<(usize, usize)>,
+}
+
+impl DpMatrix {
+    /// Returns the number of columns, one more than the length of the sequence.
+    pub fn width(&self) -> usize {
+        self.seq.len() + 1
+    }
+
+    /// Returns the score of the cell at `row` and `column`.
+    ///
+    /// # Panics
+    /// Panics if the cell is outside the matrix.
+    pub fn score(&self, row: usize, column: usize) -> i32 {
+        assert!(column < self.width(), "column out of range");
+        self.scores[row * self.width() + column]
+    }
+
+    /// Returns the score of the alignment, at the cell it is traced back from, if any.
+    pub fn best_score(&self) -> Option<i32> {
+        self.best.map(|(row, column)| self.score(row, column))
+    }
+
+    /// Writes the matrix as tab-separated values, restricted to a band of columns if given.
+    ///
+    /// The header line holds the sequence base of each column, and every other line starts with
+    /// the node id and base of its row ("-" for row 0). Unreachable cells are written as "-inf".
+    pub fn write_tsv<W: Write>(&self, mut writer: W, columns: Option<Range<usize>>) -> io::Result<()> {
+        let columns = columns.unwrap_or(0..self.width());
+        let columns = columns.start.min(self.width())..columns.end.min(self.width());
+
+        write!(writer, "node\tbase")?;
+        for column in columns.clone() {
+            match column {
+                0 => write!(writer, "\t-")?,
+                _ => write!(writer, "\t{}", self.seq[column - 1] as char)?,
+            }
+        }
+        writeln!(writer)?;
+
+        for row in 0..=self.node_ids.len() {
+            match row {
+                0 => write!(writer, "-\t-")?,
+                _ => write!(writer, "{}\t{}", self.node_ids[row - 1], self.bases[row - 1] as char)?,
+            }
+            for column in columns.clone() {
+                match self.score(row, column) {
+                    score if score <= NEGATIVE_INFINITY / 2 => write!(writer, "\t-inf")?,
+                    score => write!(writer, "\t{}", score)?,
+                }
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the matrix to the file at `path`, as by `write_tsv`.
+    pub fn save<P: AsRef<Path>>(&self, path: P, columns: Option<Range<usize>>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_tsv(&mut writer, columns)?;
+        writer.flush()
+    }
+}
+
+impl PoaGraph {
+    /// Fills the alignment score matrix of `seq` against the graph, without adding `seq`.
+    ///
+    /// The matrix is recomputed with the scoring parameters and alignment mode of the graph,
+    /// following the recurrences of the native alignment engine.
+    pub fn dp_matrix(&self, seq: &[u8]) -> DpMatrix {
+        let params = self.params();
+        let nodes = self.nodes();
+        let edges = self.edges();
+        let (local, global) = (params.alignment_type == 0, params.alignment_type == 1);
+        let gap_open = params.gap_open;
+        let affine = params.gap_open < params.gap_extend;
+        let gap_extend = if affine { params.gap_extend } else { params.gap_open };
+
+        // a topological order, smallest node id first among the available ones
+        let mut predecessors: Vec<Vec<usize>> = vec![vec![]; nodes.len()];
+        let mut successors: Vec<Vec<usize>> = vec![vec![]; nodes.len()];
+        for edge in &edges {
+            predecessors[edge.to as usize].push(edge.from as usize);
+            successors[edge.from as usize].push(edge.to as usize);
+        }
+        let mut in_degree: Vec<usize> = predecessors.iter().map(Vec::len).collect();
+        let mut available: BTreeSet<usize> = (0..nodes.len()).filter(|&id| in_degree[id] == 0).collect();
+        let mut order = Vec::with_capacity(nodes.len());
+        while let Some(id) = available.iter().next().cloned() {
+            available.remove(&id);
+            order.push(id);
+            for &next in &successors[id] {
+                in_degree[next] -= 1;
+                if in_degree[next] == 0 {
+                    available.insert(next);
+                }
+            }
+        }
+        let mut row_of = vec![0; nodes.len()];
+        for (rank, &id) in order.iter().enumerate() {
+            row_of[id] = rank + 1;
+        }
+
+        let width = seq.len() + 1;
+        let height = nodes.len() + 1;
+        let mut h = vec![0; width * height];
+        let mut f = vec![NEGATIVE_INFINITY; width * height];
+        let mut e = vec![NEGATIVE_INFINITY; width * height];
+
+        // the initial conditions
+        if global {
+            for &id in &order {
+                let row = row_of[id];
+                h[row * width] = match predecessors[id].iter().map(|&p| h[row_of[p] * width]).max() {
+                    None => gap_open,
+                    Some(best) => best + gap_extend,
+                };
+            }
+        }
+        if !local {
+            for (j, cell) in h.iter_mut().enumerate().take(width).skip(1) {
+                *cell = gap_open + (j as i32 - 1) * gap_extend;
+            }
+        }
+        for row in 1..height {
+            f[row * width] = h[row * width];
+        }
+        e[..width].copy_from_slice(&h[..width]);
+
+        let mut best: Option<(usize, usize, i32)> = None;
+        for &id in &order {
+            let row = row_of[id];
+            let preds: Vec<usize> = if predecessors[id].is_empty() {
+                vec![0]
+            } else {
+                predecessors[id].iter().map(|&p| row_of[p]).collect()
+            };
+            for j in 1..width {
+                let profile = params.ambiguous_bases.score(&params, nodes[id].base, seq[j - 1]);
+                let mut score = NEGATIVE_INFINITY;
+                let mut up = NEGATIVE_INFINITY;
+                for &pred in &preds {
+                    score = score.max(h[pred * width + j - 1] + profile);
+                    up = up.max(h[pred * width + j] + gap_open);
+                    if affine {
+                        up = up.max(f[pred * width + j] + gap_extend);
+                    }
+                }
+                f[row * width + j] = up;
+                h[row * width + j] = score.max(up);
+            }
+            for j in 1..width {
+                let left = if affine {
+                    (h[row * width + j - 1] + gap_open).max(e[row * width + j - 1] + gap_extend)
+                } else {
+                    h[row * width + j - 1] + gap_open
+                };
+                e[row * width + j] = left;
+                let mut score = h[row * width + j].max(left);
+                if local {
+                    score = score.max(0);
+                }
+                h[row * width + j] = score;
+
+                let candidate = if local {
+                    true
+                } else if global {
+                    j == width - 1 && successors[id].is_empty()
+                } else {
+                    successors[id].is_empty()
+                };
+                let floor = if local { 0 } else { NEGATIVE_INFINITY };
+                if candidate && score > best.map_or(floor, |(_, _, best)| best) {
+                    best = Some((row, j, score));
+                }
+            }
+        }
+
+        DpMatrix {
+            node_ids: order.iter().map(|&id| id as u32).collect(),
+            bases: order.iter().map(|&id| nodes[id].base).collect(),
+            seq: seq.to_vec(),
+            scores: h,
+            best: best.map(|(row, column, _)| (row, column)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PoaGraph, PoaParams};
+
+    #[test]
+    fn test_linear_global_matrix() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -3));
+        graph.add_sequence(b"ACGT");
+        let matrix = graph.dp_matrix(b"AGT");
+
+        assert_eq!(matrix.node_ids, vec![0, 1, 2, 3]);
+        assert_eq!((0..4).map(|c| matrix.score(0, c)).collect::<Vec<_>>(), vec![0, -3, -6, -9]);
+        // A matches, C is deleted, G and T match
+        assert_eq!(matrix.best, Some((4, 3)));
+        assert_eq!(matrix.best_score(), Some(5 - 3 + 5 + 5));
+    }
+
+    #[test]
+    fn test_affine_and_local_scores() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 2, -4, -8, -1));
+        graph.add_sequence(b"AACCCCGG");
+        // one gap of four bases costs -8 - 1 - 1 - 1
+        assert_eq!(graph.dp_matrix(b"AAGG").best_score(), Some(8 - 11));
+
+        let mut graph = PoaGraph::new(&PoaParams::new(0, 2, -4, -8, -6));
+        graph.add_sequence(b"TTTACGTTTT");
+        let matrix = graph.dp_matrix(b"GGACGTGG");
+        assert_eq!(matrix.best_score(), Some(8));
+        assert!(matrix.scores.iter().all(|&score| score >= 0));
+    }
+
+    #[test]
+    fn test_write_band() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -3));
+        graph.add_sequence(b"AC");
+        let mut tsv = vec![];
+        graph.dp_matrix(b"AC").write_tsv(&mut tsv, Some(1..3)).unwrap();
+
+        assert_eq!(String::from_utf8(tsv).unwrap(), "node\tbase\tA\tC\n-\t-\t-3\t-6\n0\tA\t5\t2\n1\tC\t2\t10\n");
+    }
+}
@@ -0,0 +1,103 @@
+//! Exporting the graph as GFA1, for graph tools such as Bandage and vg.
+
+use std::io::{self, Write};
+
+use super::PoaGraph;
+
+impl PoaGraph {
+    /// Writes the graph as GFA1.
+    ///
+    /// Every node becomes a segment holding its base, named by its id plus one since some tools
+    /// reject a segment 0, with its weight as the `RC` (read count) tag, so Bandage shows it as
+    /// depth. Every edge becomes a link without overlap, tagged with its weight. Each sequence
+    /// becomes a path `seq<i>`, by its index, through the nodes of its bases, and a last path
+    /// `consensus` follows the consensus.
+    pub fn write_gfa<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "H\tVN:Z:1.0")?;
+        for node in self.nodes() {
+            writeln!(writer, "S\t{}\t{}\tRC:i:{}", node.id + 1, node.base as char, node.weight)?;
+        }
+        for edge in self.edges() {
+            writeln!(writer, "L\t{}\t+\t{}\t+\t0M\tRC:i:{}", edge.from + 1, edge.to + 1, edge.weight)?;
+        }
+
+        let mut write_path = |name: &str, path: &[u32]| -> io::Result<()> {
+            if path.is_empty() {
+                return Ok(());
+            }
+            write!(writer, "P\t{}\t", name)?;
+            for (i, id) in path.iter().enumerate() {
+                let separator = if i > 0 { "," } else { "" };
+                write!(writer, "{}{}+", separator, id + 1)?;
+            }
+            writeln!(writer, "\t*")
+        };
+        for i in 0..self.num_sequences() {
+            write_path(&format!("seq{}", i), &self.sequence_path(i))?;
+        }
+        write_path("consensus", &self.consensus_path())
+    }
+
+    /// Returns the graph as GFA1, as written by `write_gfa`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     graph.add_sequence(b"ACGT");
+    ///     graph.add_sequence(b"ACTT");
+    ///
+    ///     let gfa = graph.to_gfa();
+    ///     assert!(gfa.starts_with("H\tVN:Z:1.0\n"));
+    ///     assert_eq!(gfa.lines().filter(|line| line.starts_with("S\t")).count(), 5);
+    ///     assert!(gfa.contains("P\tseq1\t1+,2+,5+,4+\t*\n"));
+    /// ```
+    pub fn to_gfa(&self) -> String {
+        let mut gfa = Vec::new();
+        self.write_gfa(&mut gfa).expect("writing to a vector cannot fail");
+        String::from_utf8_lossy(&gfa).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PoaGraph, PoaParams};
+
+    #[test]
+    fn test_gfa_records() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        for seq in ["AATGCCCGTT", "AATGCCGTT", "AATGCCCGTT"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        let gfa = graph.to_gfa();
+        let records = |kind: &str| gfa.lines().filter(|line| line.starts_with(kind)).count();
+
+        assert_eq!(records("S\t"), graph.num_nodes());
+        assert_eq!(records("L\t"), graph.edges().len());
+        assert_eq!(records("P\t"), 4);
+
+        // the path of each sequence spells it out
+        let bases: Vec<char> = graph.nodes().iter().map(|node| node.base as char).collect();
+        for line in gfa.lines().filter(|line| line.starts_with("P\t")) {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let seq: String =
+                fields[2].split(',').map(|step| bases[step.trim_end_matches('+').parse::<usize>().unwrap() - 1]).collect();
+            let expected = match fields[1] {
+                "consensus" => "AATGCCCGTT",
+                "seq1" => "AATGCCGTT",
+                _ => "AATGCCCGTT",
+            };
+            assert_eq!(seq, expected, "{}", line);
+        }
+        // the first base is shared by all three sequences
+        assert!(gfa.lines().any(|line| line.starts_with("S\t1\tA\tRC:i:3")));
+    }
+
+    #[test]
+    fn test_empty_graph() {
+        let graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        assert_eq!(graph.to_gfa(), "H\tVN:Z:1.0\n");
+    }
+}
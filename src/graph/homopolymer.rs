@@ -0,0 +1,549 @@
+//! Aligning sequences to a graph in Rust, with cheaper gaps inside long homopolymer runs, in a
+//! band or with free end gaps of its own choosing, which the native alignment engine does not
+//! support.
+
+use super::paths::Topology;
+use super::{native_len, poa_graph_add_alignment, poa_graph_add_alignment_checked, poa_graph_aligned_partners, PoaGraph, NO_SCORE};
+use crate::{AlignmentType, BandWidth, EndGaps, HomopolymerGaps};
+
+// the score of unreachable cells, as in the native alignment engine
+const NEGATIVE_INFINITY: i32 = i32::MIN + 1024;
+
+// the cell of the matrices a traceback step is in: the best score, or an insertion or deletion
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Best,
+    Insertion,
+    Deletion,
+}
+
+// returns the length of the run of equal bases each base of seq is part of
+fn sequence_runs(seq: &[u8]) -> Vec<usize> {
+    let mut runs = vec![0; seq.len()];
+    let mut start = 0;
+    for end in 1..=seq.len() {
+        if end == seq.len() || seq[end] != seq[start] {
+            for run in &mut runs[start..end] {
+                *run = end - start;
+            }
+            start = end;
+        }
+    }
+    runs
+}
+
+impl PoaGraph {
+    // returns the length of the longest run of equal bases through each node, following edges
+    fn node_runs(&self, topology: &Topology) -> Vec<usize> {
+        let bases: Vec<u8> = self.nodes().iter().map(|node| node.base).collect();
+        let mut before = vec![1; bases.len()];
+        let mut after = vec![1; bases.len()];
+        for &v in &topology.order {
+            let v = v as usize;
+            for &(u, _) in &topology.in_edges[v] {
+                if bases[u as usize] == bases[v] {
+                    before[v] = before[v].max(before[u as usize] + 1);
+                }
+            }
+        }
+        for &v in topology.order.iter().rev() {
+            let v = v as usize;
+            for &(w, _) in &topology.out_edges[v] {
+                if bases[w as usize] == bases[v] {
+                    after[v] = after[v].max(after[w as usize] + 1);
+                }
+            }
+        }
+        before.iter().zip(&after).map(|(before, after)| before + after - 1).collect()
+    }
+
+    // the number of bases from the start of the graph to each node, inclusive, following the
+    // heaviest edge into each node, which the band of a banded alignment follows
+    fn node_depths(&self, topology: &Topology) -> Vec<usize> {
+        let mut depths = vec![1; self.num_nodes()];
+        for &v in &topology.order {
+            let heaviest = topology.in_edges[v as usize].iter().fold(None, |best: Option<(u32, u64)>, &(u, weight)| match best {
+                Some((_, best_weight)) if best_weight >= weight => best,
+                _ => Some((u, weight)),
+            });
+            if let Some((u, _)) = heaviest {
+                depths[v as usize] = depths[u as usize] + 1;
+            }
+        }
+        depths
+    }
+
+    // aligns seq to the graph with the recurrences of the native alignment engine, but scoring the
+    // gap of a base inside a run of at least gaps.min_run equal bases, in seq or along the graph,
+    // with the homopolymer gap scores, with only the cells of the band computed if one is given,
+    // and with the end gaps of the parameters free if they set them. returns the node id and sequence position of each alignment column, -1 marking a gap,
+    // in the form the native graph adds alignments in, and its score.
+    pub(super) fn scalar_alignment(&self, seq: &[u8], gaps: Option<&HomopolymerGaps>, band: Option<BandWidth>) -> (Vec<(i32, i32)>, Option<i32>) {
+        self.scalar_alignment_in(seq, gaps, band, None)
+    }
+
+    // aligns seq as scalar_alignment does, but only to the nodes of region if one is given, as if
+    // the rest of the graph and the edges leaving the region were not there
+    fn scalar_alignment_in(
+        &self,
+        seq: &[u8],
+        gaps: Option<&HomopolymerGaps>,
+        band: Option<BandWidth>,
+        region: Option<&[bool]>
+    ) -> (Vec<(i32, i32)>, Option<i32>) {
+        let params = self.params();
+        let nodes = self.nodes();
+        if nodes.is_empty() || seq.is_empty() {
+            return (vec![], None);
+        }
+        let mut edges = self.edges();
+        if let Some(region) = region {
+            edges.retain(|edge| region[edge.from as usize] && region[edge.to as usize]);
+        }
+        let mut topology = Topology::new(nodes.len(), &edges);
+        if let Some(region) = region {
+            topology.order.retain(|&id| region[id as usize]);
+        }
+        let local = params.alignment_type == 0;
+        let ends = params.end_gaps.or_else(|| AlignmentType::from_code(params.alignment_type).and_then(EndGaps::of)).unwrap_or_default();
+        let gap_open = params.gap_open;
+        let gap_extend = if params.gap_open < params.gap_extend { params.gap_extend } else { params.gap_open };
+
+        let gap_scores = |run: usize| match gaps {
+            Some(gaps) if run >= gaps.min_run => (gaps.gap_open, gaps.gap_extend),
+            _ => (gap_open, gap_extend),
+        };
+        let deletion: Vec<(i32, i32)> = match gaps {
+            Some(_) => self.node_runs(&topology).into_iter().map(gap_scores).collect(),
+            None => vec![(gap_open, gap_extend); nodes.len()],
+        };
+        let insertion: Vec<(i32, i32)> = sequence_runs(seq).into_iter().map(gap_scores).collect();
+
+        let mut row_of = vec![0; nodes.len()];
+        for (rank, &id) in topology.order.iter().enumerate() {
+            row_of[id as usize] = rank + 1;
+        }
+        // the rows of the predecessors of each node, row 0 for a source
+        let preds: Vec<Vec<usize>> = topology
+            .in_edges
+            .iter()
+            .map(|in_edges| if in_edges.is_empty() { vec![0] } else { in_edges.iter().map(|&(u, _)| row_of[u as usize]).collect() })
+            .collect();
+
+        // the first and last column of each row that is computed, and where the row is stored
+        let width = seq.len() + 1;
+        let height = nodes.len() + 1;
+        let mut columns = vec![(0, width - 1); height];
+        if let Some(band) = band {
+            let depths = self.node_depths(&topology);
+            let band = band.width(seq.len(), depths.iter().copied().max().unwrap_or(0));
+            columns[0] = (0, band.min(width - 1));
+            for &id in &topology.order {
+                let depth = depths[id as usize];
+                columns[row_of[id as usize]] = (depth.saturating_sub(band).min(width - 1), (depth + band).min(width - 1));
+            }
+        }
+        let mut starts = Vec::with_capacity(height + 1);
+        starts.push(0);
+        for &(first, last) in &columns {
+            starts.push(starts[starts.len() - 1] + last - first + 1);
+        }
+        let cells = starts[height];
+        let index = |row: usize, j: usize| {
+            let (first, last) = columns[row];
+            if j >= first && j <= last {
+                Some(starts[row] + j - first)
+            } else {
+                None
+            }
+        };
+        let get = |matrix: &[i32], row: usize, j: usize| index(row, j).map_or(NEGATIVE_INFINITY, |cell| matrix[cell]);
+
+        let mut h = vec![0; cells];
+        let mut e = vec![NEGATIVE_INFINITY; cells];
+        let mut f = vec![NEGATIVE_INFINITY; cells];
+
+        // the initial conditions, with the regular gap scores
+        if !local && !ends.graph_start {
+            for &id in &topology.order {
+                let id = id as usize;
+                if let Some(cell) = index(row_of[id], 0) {
+                    h[cell] = match preds[id].iter().map(|&p| get(&h, p, 0)).max() {
+                        Some(best) if !topology.in_edges[id].is_empty() => (best + gap_extend).max(NEGATIVE_INFINITY),
+                        _ => gap_open,
+                    };
+                }
+            }
+        }
+        if !local && !ends.query_start {
+            for (j, cell) in h.iter_mut().enumerate().take(columns[0].1 + 1).skip(1) {
+                *cell = gap_open + (j as i32 - 1) * gap_extend;
+            }
+        }
+
+        let profile = |id: usize, j: usize| params.ambiguous_bases.score(&params, nodes[id].base, seq[j - 1]);
+        let mut best: Option<(usize, usize, i32)> = None;
+        for &id in &topology.order {
+            let id = id as usize;
+            let row = row_of[id];
+            let (first, last) = columns[row];
+            let (open, extend) = deletion[id];
+            for j in first.max(1)..=last {
+                let mut score = NEGATIVE_INFINITY;
+                let mut up = NEGATIVE_INFINITY;
+                for &p in &preds[id] {
+                    score = score.max(get(&h, p, j - 1) + profile(id, j));
+                    up = up.max(get(&h, p, j) + open).max(get(&f, p, j) + extend);
+                }
+                let cell = starts[row] + j - first;
+                f[cell] = up.max(NEGATIVE_INFINITY);
+                h[cell] = score.max(up).max(NEGATIVE_INFINITY);
+            }
+            for j in first.max(1)..=last {
+                let (open, extend) = insertion[j - 1];
+                let cell = starts[row] + j - first;
+                let left = (get(&h, row, j - 1) + open).max(get(&e, row, j - 1) + extend).max(NEGATIVE_INFINITY);
+                e[cell] = left;
+                let mut score = h[cell].max(left);
+                if local {
+                    score = score.max(0);
+                }
+                h[cell] = score;
+
+                let sink = topology.out_edges[id].is_empty();
+                let candidate = local || ((sink || ends.graph_end) && (ends.query_end || j == width - 1));
+                let floor = if local { 0 } else { NEGATIVE_INFINITY };
+                if candidate && score > best.map_or(floor, |(_, _, best)| best) {
+                    best = Some((row, j, score));
+                }
+            }
+        }
+
+        let (mut row, mut j, score) = match best {
+            Some(best) => best,
+            None => return (vec![], None),
+        };
+        let mut state = State::Best;
+        let mut alignment = vec![];
+        loop {
+            if row == 0 || j == 0 {
+                if local {
+                    break;
+                }
+                // the penalized leading gaps are completed along the initial conditions
+                while j > 0 && row == 0 && !ends.query_start {
+                    j -= 1;
+                    alignment.push((-1, j as i32));
+                }
+                while row > 0 && !ends.graph_start {
+                    let id = topology.order[row - 1] as usize;
+                    alignment.push((id as i32, -1));
+                    row = preds[id].iter().copied().max_by_key(|&p| get(&h, p, 0)).unwrap_or(0);
+                }
+                break;
+            }
+
+            let id = topology.order[row - 1] as usize;
+            let (h_cell, f_cell, e_cell) = (get(&h, row, j), get(&f, row, j), get(&e, row, j));
+            match state {
+                State::Best => {
+                    if local && h_cell == 0 {
+                        break;
+                    }
+                    if let Some(&p) = preds[id].iter().find(|&&p| get(&h, p, j - 1) + profile(id, j) == h_cell) {
+                        alignment.push((id as i32, j as i32 - 1));
+                        row = p;
+                        j -= 1;
+                    } else if h_cell == f_cell {
+                        state = State::Deletion;
+                    } else {
+                        state = State::Insertion;
+                    }
+                }
+                State::Deletion => {
+                    let (open, extend) = deletion[id];
+                    alignment.push((id as i32, -1));
+                    if let Some(&p) = preds[id].iter().find(|&&p| get(&h, p, j) + open == f_cell) {
+                        row = p;
+                        state = State::Best;
+                    } else {
+                        row = preds[id].iter().copied().find(|&p| get(&f, p, j) + extend == f_cell).unwrap_or(0);
+                    }
+                }
+                State::Insertion => {
+                    let (open, _) = insertion[j - 1];
+                    alignment.push((-1, j as i32 - 1));
+                    if get(&h, row, j - 1) + open == e_cell {
+                        state = State::Best;
+                    }
+                    j -= 1;
+                }
+            }
+        }
+
+        alignment.reverse();
+        (alignment, Some(score))
+    }
+
+    // adds seq along its alignment by scalar_alignment, with the homopolymer gap scores and band of
+    // the parameters of the graph
+    pub(super) fn add_sequence_scalar(&mut self, seq: &[u8], weights: Option<&[u32]>) {
+        let params = self.params();
+        let (alignment, score) = self.scalar_alignment(seq, params.homopolymer_gaps.as_ref(), params.band_width);
+        let (node_ids, positions): (Vec<i32>, Vec<i32>) = alignment.into_iter().unzip();
+        unsafe {
+            poa_graph_add_alignment(
+                self.raw.as_ptr(),
+                seq.as_ptr(),
+                native_len(seq.len()),
+                weights.map_or(std::ptr::null(), |weights| weights.as_ptr()),
+                node_ids.as_ptr(),
+                positions.as_ptr(),
+                native_len(node_ids.len()),
+                score.unwrap_or(NO_SCORE),
+            );
+        }
+    }
+
+    // the nodes poa_graph_add_anchored aligns to between the backbone positions begin and end, or
+    // None if it aligns to the whole graph: those reached from the backbone node at end - 1 back
+    // along edges and across aligned nodes, without going below the id of the one at begin
+    pub(super) fn anchored_region(&self, begin: usize, end: usize) -> Option<Vec<bool>> {
+        if self.num_sequences() == 0 || begin >= end {
+            return None;
+        }
+        let backbone = self.sequence_path(0);
+        if begin >= backbone.len() {
+            return None;
+        }
+        let (begin_node, end_node) = (backbone[begin], backbone[end.min(backbone.len()) - 1]);
+
+        let num_nodes = self.num_nodes();
+        let mut in_edges = vec![vec![]; num_nodes];
+        for edge in self.edges() {
+            in_edges[edge.to as usize].push(edge.from);
+        }
+        // the nodes aligned to each other share the root their chains of aligned partners end at
+        let mut partners: Vec<i32> = vec![-1; num_nodes];
+        unsafe { poa_graph_aligned_partners(self.raw.as_ptr(), partners.as_mut_ptr()) }
+        let root = |mut id: usize| {
+            while partners[id] >= 0 {
+                id = partners[id] as usize;
+            }
+            id
+        };
+        let mut aligned = vec![vec![]; num_nodes];
+        for id in 0..num_nodes {
+            aligned[root(id)].push(id as u32);
+        }
+
+        let mut region = vec![false; num_nodes];
+        let mut stack = vec![end_node];
+        while let Some(id) = stack.pop() {
+            if !region[id as usize] && id >= begin_node {
+                region[id as usize] = true;
+                stack.extend(&in_edges[id as usize]);
+                stack.extend(&aligned[root(id as usize)]);
+            }
+        }
+        Some(region)
+    }
+
+    // adds seq along its alignment by scalar_alignment, to region if one is given, under the
+    // limits of poa_graph_add_checked, returning its code and leaving the graph unchanged if a
+    // limit is exceeded
+    pub(super) fn add_sequence_scalar_checked(
+        &mut self,
+        seq: &[u8],
+        region: Option<&[bool]>,
+        max_nodes: u32,
+        max_inserted: u32,
+        min_identity: f64,
+        identity: &mut f64
+    ) -> i32 {
+        let params = self.params();
+        let (alignment, score) = self.scalar_alignment_in(seq, params.homopolymer_gaps.as_ref(), params.band_width, region);
+        let (node_ids, positions): (Vec<i32>, Vec<i32>) = alignment.into_iter().unzip();
+        unsafe {
+            poa_graph_add_alignment_checked(
+                self.raw.as_ptr(),
+                seq.as_ptr(),
+                native_len(seq.len()),
+                std::ptr::null(),
+                node_ids.as_ptr(),
+                positions.as_ptr(),
+                native_len(node_ids.len()),
+                score.unwrap_or(NO_SCORE),
+                max_nodes,
+                max_inserted,
+                min_identity,
+                identity,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{native_consensus, poa_consensus_unbounded, poa_consensus_with_support, GrowthLimits, PoaParams, RejectReason};
+
+    // reads of ACGTCGAAAAAAACTGACTTTTTTGCAGCCCCCCCCTGATGGGGGACTG, most with a run one base too short or long
+    const READS: [&[u8]; 8] = [
+        b"ACGTCGAAAAAACTGACTTTTTTGCAGCCCCCCCCTGATGGGGACTG",
+        b"ACGTCGAAAAACTGACTTTTTTGCAGCCCCCCCCCTGATGGGGGACTG",
+        b"ACGTCGAAAAAACTGACTTTTTTTGCAGCCCCCCCTGATGGGGGACTG",
+        b"ACGTCGAAAAAAAACTGACTTTTGCAGCCCCCCCCTGATGGGGGACTG",
+        b"ACGTCGAAAAACTGACTTTTTGCAGCCCCCCCTGATGGGGGACTG",
+        b"ACGTCGAAAAAAACTGACTTTTTTGCAGCCCCCCCCTGATGGGGGACTG",
+        b"ACGTCGAAAAAACTGACTTTTGCAGCCCCCCCCTGATGGGGACTG",
+        b"ACGTCGAAAAAAACTGACTTTTTTGCAGCCCCCCCCTGATGGGGGGACTG",
+    ];
+
+    fn consensus(params: &PoaParams) -> Vec<u8> {
+        let mut graph = PoaGraph::new(params);
+        for read in READS.iter() {
+            graph.add_sequence(read);
+        }
+        graph.consensus()
+    }
+
+    #[test]
+    fn test_run_lengths_called_correctly() {
+        // with expensive gaps a local alignment clips a shortened run rather than open a gap in it
+        let params = PoaParams::new(0, 5, -2, -10, -10);
+        assert_eq!(consensus(&params), b"ACGTCGAAAAAACTGACTTTTTTGCAGCCCCCCCCTGATGGGGGACTG".to_vec());
+
+        let params = params.homopolymer_gaps(HomopolymerGaps::new(4, -2, -2));
+        assert_eq!(consensus(&params), b"ACGTCGAAAAAAACTGACTTTTTTGCAGCCCCCCCCTGATGGGGGACTG".to_vec());
+    }
+
+    #[test]
+    fn test_regular_scores_align_as_natively() {
+        for alignment_type in 0..3 {
+            for &(gap_open, gap_extend) in [(-8, -6), (-4, -2), (-12, -4)].iter() {
+                let params = PoaParams::new(alignment_type, 5, -4, gap_open, gap_extend);
+                let unused = params.homopolymer_gaps(HomopolymerGaps::new(usize::MAX, -1, -1));
+                assert_eq!(consensus(&unused), consensus(&params));
+            }
+        }
+    }
+
+    #[test]
+    fn test_band_wide_enough_aligns_as_unbanded() {
+        for alignment_type in 0..3 {
+            let params = PoaParams::new(alignment_type, 5, -4, -8, -6);
+            let unused = params.homopolymer_gaps(HomopolymerGaps::new(usize::MAX, -1, -1));
+            assert_eq!(consensus(&params.band_width(BandWidth::Fixed(64))), consensus(&unused), "{}", alignment_type);
+            assert_eq!(consensus(&params.band_width(BandWidth::Auto)), consensus(&unused), "{}", alignment_type);
+
+            let gaps = params.homopolymer_gaps(HomopolymerGaps::new(4, -2, -2));
+            assert_eq!(consensus(&gaps.band_width(BandWidth::Auto)), consensus(&gaps), "{}", alignment_type);
+        }
+    }
+
+    #[test]
+    fn test_end_gaps() {
+        // the end gaps of each mode align as the mode does natively
+        for alignment_type in 0..3 {
+            let params = PoaParams::new(alignment_type, 5, -4, -8, -6);
+            let ends = AlignmentType::from_code(alignment_type).and_then(EndGaps::of).unwrap();
+            assert_eq!(consensus(&params.end_gaps(ends)), consensus(&params), "{}", alignment_type);
+        }
+
+        // only the free ends of the read and the graph are left unscored
+        let score = |ends: EndGaps, query: &[u8]| {
+            let mut graph = PoaGraph::new(&PoaParams::dna().end_gaps(ends));
+            graph.add_sequence(b"GATTACAGGCTTACCGATCG");
+            graph.align(query).score
+        };
+        let query_end = EndGaps { query_end: true, ..EndGaps::global() };
+        assert_eq!(score(query_end, b"GATTACAGGCTTACCGATCGAAAA"), 100);
+        assert_eq!(score(query_end, b"AAAAGATTACAGGCTTACCGATCG"), 100 - 8 - 3 * 6);
+        assert_eq!(score(EndGaps { query_start: true, ..query_end }, b"AAAAGATTACAGGCTTACCGATCG"), 100);
+        assert_eq!(score(query_end, b"GATTACAGGCTTACC"), 75 - 8 - 4 * 6);
+        assert_eq!(score(EndGaps { graph_end: true, ..query_end }, b"GATTACAGGCTTACC"), 75);
+        assert_eq!(score(EndGaps { graph_start: true, ..EndGaps::global() }, b"GGCTTACCGATCG"), 65);
+    }
+
+    #[test]
+    fn test_every_entry_point_aligns_as_add_sequence() {
+        let params = PoaParams::new(0, 5, -2, -10, -10).homopolymer_gaps(HomopolymerGaps::new(4, -2, -2));
+        let expected = consensus(&params);
+        let seqs: Vec<Vec<u8>> = READS.iter().map(|read| [read, &b"\0"[..]].concat()).collect();
+        assert_eq!(poa_consensus_unbounded(&seqs, &params), expected);
+        assert_eq!(native_consensus(&seqs, 10, &params), expected[..10].to_vec());
+        let (consensus_with_support, support) = poa_consensus_with_support(&seqs, 0, &params);
+        assert_eq!((consensus_with_support, support.len()), (expected.clone(), expected.len()));
+
+        let mut limited = PoaGraph::new(&params);
+        let mut min_identity = PoaGraph::new(&params);
+        let mut anchored = PoaGraph::new(&params);
+        for read in READS.iter() {
+            assert_eq!(limited.add_sequence_limited(read, &GrowthLimits::default()), Ok(()));
+            assert!(min_identity.add_sequence_min_identity(read, 0.0).is_ok());
+            anchored.add_sequence_anchored(read, 0, 100);
+        }
+        assert_eq!(limited.consensus(), expected);
+        assert_eq!(min_identity.consensus(), expected);
+        assert_eq!(anchored.consensus(), expected);
+
+        // a band wide enough aligns as the native engine, with the limits and the anchor window
+        let native = PoaParams::new(2, 5, -4, -8, -6);
+        let banded = native.band_width(BandWidth::Fixed(64));
+        let backbone = b"GATTACAGGCTTACCGATCGATTGCAGCTAGGCATCAAGTGATTACAGGCTTACCGATCG";
+        let reads: [&[u8]; 3] = [b"GATTACAGGCTTACC", b"GATTACAGCTTACCG", b"GGCAAGTGATAACAG"];
+        let add = |params: &PoaParams| {
+            let mut anchored = PoaGraph::new(params);
+            let mut checked = PoaGraph::new(params);
+            anchored.add_sequence(backbone);
+            checked.add_sequence(backbone);
+            let mut identities = vec![];
+            for (offset, read) in [40, 0, 32].iter().zip(reads.iter()) {
+                anchored.add_sequence_anchored(read, *offset, 4);
+                identities.push(checked.add_sequence_min_identity(read, 0.9).is_ok());
+            }
+            let limits = GrowthLimits { max_read_insertions: Some(8), ..GrowthLimits::default() };
+            let rejected = checked.add_sequence_limited(b"CCCCCCCCCCCCCCCCCCCC", &limits);
+            (anchored.multiple_sequence_alignment(false), checked.multiple_sequence_alignment(false), identities, rejected)
+        };
+        let banded = add(&banded);
+        assert_eq!(banded, add(&native));
+        assert_eq!(banded.3, Err(RejectReason::InsertionLimit));
+        assert!(banded.2.contains(&false));
+    }
+
+    #[test]
+    fn test_narrow_band() {
+        let template = b"GATTACAGGCTTACCGATCGATTGCAGCTAGGCATCAAGT";
+        let deleted = [&template[..12], &template[16..]].concat();
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let msa = |params: &PoaParams| {
+            let mut graph = PoaGraph::new(params);
+            graph.add_sequence(template);
+            graph.add_sequence(&deleted);
+            graph.multiple_sequence_alignment(false)
+        };
+
+        // a deletion of four bases fits in a band of four, but not of two
+        assert_eq!(msa(&params.band_width(BandWidth::Fixed(4))), msa(&params));
+        assert_ne!(msa(&params.band_width(BandWidth::Fixed(2))), msa(&params));
+        assert_eq!(BandWidth::Auto.width(36, 40), 14);
+        assert_eq!(BandWidth::Auto.width(5000, 4990), 120);
+        assert_eq!(BandWidth::Fixed(7).width(5000, 10), 7);
+    }
+
+    #[test]
+    fn test_runs() {
+        assert_eq!(sequence_runs(b"AAACGGT"), vec![3, 3, 3, 1, 2, 2, 1]);
+        assert!(sequence_runs(b"").is_empty());
+
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+        graph.add_sequence(b"CAAAG");
+        graph.add_sequence(b"CAAAAG");
+        let topology = Topology::new(graph.num_nodes(), &graph.edges());
+        let runs = graph.node_runs(&topology);
+        // the inserted A lengthens the run through every A node to four
+        assert_eq!(graph.nodes().iter().zip(runs).filter(|(node, _)| node.base == b'A').map(|(_, run)| run).collect::<Vec<_>>(), vec![4; 4]);
+    }
+}
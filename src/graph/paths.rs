@@ -0,0 +1,222 @@
+//! Enumeration of heavy source-to-sink paths through a graph.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use super::{Edge, PoaGraph};
+
+/// A path from a source node (without incoming edges) to a sink node (without outgoing edges).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WeightedPath {
+    /// the ids of the nodes along the path
+    pub nodes: Vec<u32>,
+    /// the bases of the nodes along the path
+    pub sequence: Vec<u8>,
+    /// the summed weight of the edges along the path
+    pub weight: u64,
+}
+
+// a partial path ending at a node: its weight, and the predecessor node with the index of the
+// partial path ending there that it extends
+type PartialPath = (u64, Option<(u32, usize)>);
+
+// the edges entering and leaving each node, and a topological order of the nodes
+pub(crate) struct Topology {
+    pub(crate) out_edges: Vec<Vec<(u32, u64)>>,
+    pub(crate) in_edges: Vec<Vec<(u32, u64)>>,
+    pub(crate) order: Vec<u32>,
+}
+
+impl Topology {
+    pub(crate) fn new(num_nodes: usize, edges: &[Edge]) -> Topology {
+        let mut out_edges = vec![vec![]; num_nodes];
+        let mut in_edges = vec![vec![]; num_nodes];
+        for edge in edges {
+            out_edges[edge.from as usize].push((edge.to, edge.weight));
+            in_edges[edge.to as usize].push((edge.from, edge.weight));
+        }
+
+        // Kahn's algorithm, taking the smallest ready node id first
+        let mut in_degree: Vec<usize> = in_edges.iter().map(|e| e.len()).collect();
+        let mut ready: BinaryHeap<Reverse<u32>> =
+            (0..num_nodes as u32).filter(|&v| in_degree[v as usize] == 0).map(Reverse).collect();
+        let mut order = Vec::with_capacity(num_nodes);
+        while let Some(Reverse(v)) = ready.pop() {
+            order.push(v);
+            for &(w, _) in &out_edges[v as usize] {
+                in_degree[w as usize] -= 1;
+                if in_degree[w as usize] == 0 {
+                    ready.push(Reverse(w));
+                }
+            }
+        }
+
+        Topology { out_edges, in_edges, order }
+    }
+}
+
+impl PoaGraph {
+    /// Returns up to `n` source-to-sink paths with the largest total edge weight, heaviest first.
+    ///
+    /// Paths of equal weight are ordered by their node ids.
+    pub fn heaviest_paths(&self, n: usize) -> Vec<WeightedPath> {
+        let nodes = self.nodes();
+        let topology = Topology::new(nodes.len(), &self.edges());
+
+        // best[v] holds the up to n heaviest partial paths ending at v, heaviest first
+        let mut best: Vec<Vec<PartialPath>> = vec![vec![]; nodes.len()];
+        for &v in &topology.order {
+            let in_edges = &topology.in_edges[v as usize];
+            if in_edges.is_empty() {
+                best[v as usize].push((0, None));
+                continue;
+            }
+
+            let mut candidates = vec![];
+            for &(u, weight) in in_edges {
+                for (i, &(w, _)) in best[u as usize].iter().enumerate() {
+                    candidates.push((w + weight, Some((u, i))));
+                }
+            }
+            candidates.sort_by_key(|&(w, pred)| (Reverse(w), pred));
+            candidates.truncate(n);
+            best[v as usize] = candidates;
+        }
+
+        let mut ends = vec![];
+        for &v in &topology.order {
+            if topology.out_edges[v as usize].is_empty() {
+                for (i, &(w, _)) in best[v as usize].iter().enumerate() {
+                    ends.push((w, v, i));
+                }
+            }
+        }
+        ends.sort_by_key(|&(w, v, i)| (Reverse(w), v, i));
+        ends.truncate(n);
+
+        ends.into_iter()
+            .map(|(weight, v, i)| {
+                let mut path = vec![v];
+                let mut entry = best[v as usize][i].1;
+                while let Some((u, j)) = entry {
+                    path.push(u);
+                    entry = best[u as usize][j].1;
+                }
+                path.reverse();
+
+                let sequence = path.iter().map(|&id| nodes[id as usize].base).collect();
+                WeightedPath { nodes: path, sequence, weight }
+            })
+            .collect()
+    }
+
+    /// Returns every source-to-sink path with a total edge weight of at least `min_weight`, heaviest first.
+    ///
+    /// The number of paths can grow exponentially as `min_weight` decreases, so prefer
+    /// `heaviest_paths` unless the threshold is known to be selective.
+    pub fn paths_above(&self, min_weight: u64) -> Vec<WeightedPath> {
+        let nodes = self.nodes();
+        let topology = Topology::new(nodes.len(), &self.edges());
+
+        // the heaviest weight still reachable from each node
+        let mut best_suffix = vec![0u64; nodes.len()];
+        for &v in topology.order.iter().rev() {
+            best_suffix[v as usize] = topology.out_edges[v as usize]
+                .iter()
+                .map(|&(w, weight)| weight + best_suffix[w as usize])
+                .max()
+                .unwrap_or(0);
+        }
+
+        let mut paths = vec![];
+        for &source in &topology.order {
+            if !topology.in_edges[source as usize].is_empty() || best_suffix[source as usize] < min_weight {
+                continue;
+            }
+
+            // depth-first search, pruning branches that cannot reach the threshold
+            let mut path = vec![source];
+            let mut weights = vec![0u64];
+            let mut next_edge = vec![0usize];
+            while let Some(&v) = path.last() {
+                let depth = path.len() - 1;
+                let out_edges = &topology.out_edges[v as usize];
+
+                if out_edges.is_empty() {
+                    let sequence = path.iter().map(|&id| nodes[id as usize].base).collect();
+                    paths.push(WeightedPath { nodes: path.clone(), sequence, weight: weights[depth] });
+                }
+
+                let mut descended = false;
+                while next_edge[depth] < out_edges.len() {
+                    let (w, weight) = out_edges[next_edge[depth]];
+                    next_edge[depth] += 1;
+                    if weights[depth] + weight + best_suffix[w as usize] >= min_weight {
+                        path.push(w);
+                        weights.push(weights[depth] + weight);
+                        next_edge.push(0);
+                        descended = true;
+                        break;
+                    }
+                }
+                if !descended {
+                    path.pop();
+                    weights.pop();
+                    next_edge.pop();
+                }
+            }
+        }
+
+        paths.sort_by(|a, b| b.weight.cmp(&a.weight).then_with(|| a.nodes.cmp(&b.nodes)));
+        paths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PoaGraph, PoaParams};
+
+    fn two_allele_graph() -> PoaGraph {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        for seq in ["AATGCCCGTT", "AATGCCCGTT", "AATGCCCGTT", "AATGACCGTT", "AATGACCGTT"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        graph
+    }
+
+    #[test]
+    fn test_heaviest_paths() {
+        let graph = two_allele_graph();
+        let paths = graph.heaviest_paths(5);
+
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].sequence, b"AATGCCCGTT".to_vec());
+        assert_eq!(paths[1].sequence, b"AATGACCGTT".to_vec());
+        assert!(paths[0].weight > paths[1].weight);
+        assert_eq!(paths[0].sequence, graph.consensus());
+        assert_eq!(graph.heaviest_paths(1), paths[..1].to_vec());
+    }
+
+    #[test]
+    fn test_paths_above() {
+        let graph = two_allele_graph();
+        let all = graph.paths_above(0);
+
+        assert_eq!(all, graph.heaviest_paths(10));
+        assert_eq!(graph.paths_above(all[0].weight), all[..1].to_vec());
+        assert!(graph.paths_above(all[0].weight + 1).is_empty());
+    }
+
+    #[test]
+    fn test_path_weights_sum_edges() {
+        let graph = two_allele_graph();
+        let edges = graph.edges();
+
+        for path in graph.heaviest_paths(3) {
+            let weight: u64 = path.nodes.windows(2)
+                .map(|pair| edges.iter().find(|e| e.from == pair[0] && e.to == pair[1]).unwrap().weight)
+                .sum();
+            assert_eq!(weight, path.weight);
+        }
+    }
+}
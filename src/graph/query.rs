@@ -0,0 +1,145 @@
+//! Aligning a sequence that is not part of a graph to it, e.g. to place a primer or probe.
+
+use std::convert::TryFrom;
+
+use super::{native_len, poa_graph_align, PoaGraph};
+use crate::error::check_native;
+
+/// The alignment of a query sequence to a graph, which the query was not added to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GraphAlignment {
+    /// the score of the alignment
+    pub score: i32,
+    /// the ids of the nodes the alignment passes through, in order
+    pub path: Vec<u32>,
+    /// the node and query position of each alignment column, in order; `None` marks a gap
+    pub alignment: Vec<(Option<u32>, Option<usize>)>,
+    /// the first position of the consensus spanned by the alignment
+    pub consensus_start: usize,
+    /// the position after the last position of the consensus spanned by the alignment
+    pub consensus_end: usize,
+}
+
+impl PoaGraph {
+    /// Aligns a query sequence to the graph without adding it, e.g. to find where a primer or probe
+    /// lies on the reads.
+    ///
+    /// The query is aligned with the parameters of the graph, as by `add_sequence`, so a query
+    /// shorter than the reads needs a graph of local alignment; semi-global alignment in SPOA ends
+    /// at the end of the graph. Its position on the consensus
+    /// spans the consensus bases in the alignment columns from the first node of the path to the
+    /// last one, so a query aligned to a branch off the consensus still gets the consensus interval
+    /// around the branch. The alignment is empty, with score 0, if the graph or the query is.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(0, 5, -4, -8, -6));
+    ///     for seq in ["GATTACAGGCTTACCGATCG", "GATTACAGGCTTACCGATCG", "GATTACAGGATTACCGATCG"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     let alignment = graph.align(b"AGGCTTACC");
+    ///     assert_eq!(alignment.score, 45);
+    ///     assert_eq!((alignment.consensus_start, alignment.consensus_end), (6, 15));
+    ///     assert_eq!(graph.num_sequences(), 3);
+    /// ```
+    pub fn align(&self, query: &[u8]) -> GraphAlignment {
+        let query = self.query_bases(query);
+        let query = query.as_ref();
+        let params = self.params();
+        let (pairs, score) = if params.is_scalar() {
+            let (pairs, score) = self.scalar_alignment(query, params.homopolymer_gaps.as_ref(), params.band_width);
+            (pairs, score.unwrap_or(0))
+        } else {
+            self.native_alignment(query)
+        };
+
+        let alignment: Vec<(Option<u32>, Option<usize>)> =
+            pairs.iter().map(|&(node, pos)| (u32::try_from(node).ok(), usize::try_from(pos).ok())).collect();
+        let path: Vec<u32> = alignment.iter().filter_map(|&(node, _)| node).collect();
+        let (consensus_start, consensus_end) = self.consensus_span(&path);
+        GraphAlignment { score, path, alignment, consensus_start, consensus_end }
+    }
+
+    // aligns seq to the graph with the native alignment engine, returning the node id and sequence
+    // position of each alignment column, -1 marking a gap, and the score
+    fn native_alignment(&self, seq: &[u8]) -> (Vec<(i32, i32)>, i32) {
+        let capacity = self.num_nodes() + seq.len();
+        let (mut node_ids, mut positions) = (vec![-1i32; capacity], vec![-1i32; capacity]);
+        let mut score = 0;
+        let len = unsafe {
+            poa_graph_align(self.raw.as_ptr(), seq.as_ptr(), native_len(seq.len()), node_ids.as_mut_ptr(), positions.as_mut_ptr(), &mut score)
+        } as usize;
+        check_native();
+        (node_ids.into_iter().zip(positions).take(len).collect(), score)
+    }
+
+    // the consensus positions from the alignment column of the first node of a path to that of its
+    // last node
+    fn consensus_span(&self, path: &[u32]) -> (usize, usize) {
+        let (first, last) = match (path.first(), path.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return (0, 0),
+        };
+        let mut node_columns = vec![0; self.num_nodes()];
+        for (i, row) in self.multiple_sequence_alignment(false).iter().enumerate() {
+            let columns = row.iter().enumerate().filter(|&(_, &base)| base != b'-').map(|(column, _)| column);
+            for (id, column) in self.sequence_path(i).into_iter().zip(columns) {
+                node_columns[id as usize] = column;
+            }
+        }
+
+        let columns: Vec<usize> = self.consensus_path().iter().map(|&id| node_columns[id as usize]).collect();
+        let start = columns.iter().take_while(|&&column| column < node_columns[first as usize]).count();
+        let end = columns.iter().take_while(|&&column| column <= node_columns[last as usize]).count();
+        (start, end.max(start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BandWidth, PoaParams};
+
+    fn reads_graph(params: &PoaParams) -> PoaGraph {
+        let mut graph = PoaGraph::new(params);
+        for seq in ["GATTACAGGCTTACCGATCGTTGCA", "GATTACAGGCTTACCGATCGTTGCA", "GATTACAGGATTACCGATCGTTGCA", "GATTACAGGCTTACCGTCGTTGCA"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        graph
+    }
+
+    #[test]
+    fn test_align_query() {
+        let graph = reads_graph(&PoaParams::new(0, 5, -4, -8, -6));
+        let num_nodes = graph.num_nodes();
+        let consensus = graph.consensus();
+
+        // a query matching the consensus exactly spans its bases
+        let alignment = graph.align(&consensus[5..15]);
+        assert_eq!(alignment.score, 50);
+        assert_eq!((alignment.consensus_start, alignment.consensus_end), (5, 15));
+        assert_eq!(alignment.path, graph.consensus_path()[5..15].to_vec());
+        assert_eq!(alignment.alignment.len(), 10);
+        assert_eq!(graph.num_nodes(), num_nodes);
+        assert_eq!(graph.num_sequences(), 4);
+
+        // a query along the branch of the third read spans the consensus around it
+        let alignment = graph.align(b"CAGGATTAC");
+        assert_eq!(alignment.score, 45);
+        assert_eq!((alignment.consensus_start, alignment.consensus_end), (5, 14));
+        assert!(!alignment.path.iter().all(|id| graph.consensus_path().contains(id)));
+
+        // the banded aligner of the graph aligns queries too
+        let banded = reads_graph(&PoaParams::new(0, 5, -4, -8, -6).band_width(BandWidth::Fixed(8)));
+        assert_eq!(banded.align(&consensus[5..15]).consensus_start, 5);
+        assert_eq!(banded.align(&consensus[5..15]).score, 50);
+
+        let empty = PoaGraph::new(&PoaParams::new(0, 5, -4, -8, -6));
+        assert_eq!(empty.align(b"ACGT"), GraphAlignment { score: 0, path: vec![], alignment: vec![], consensus_start: 0, consensus_end: 0 });
+        assert_eq!(graph.align(b"").path, Vec::<u32>::new());
+    }
+}
@@ -0,0 +1,315 @@
+//! Saving a graph to bytes and restoring it, e.g. to checkpoint long-running jobs.
+
+use std::convert::TryInto;
+
+use super::{native_len, poa_graph_add_alignment, poa_graph_aligned_partners, PoaGraph, NO_SCORE};
+use crate::error::native_result;
+use crate::{PoaError, PoaParams, SubstitutionMatrix};
+
+// the start of every serialized graph, followed by the format version
+const MAGIC: &[u8; 4] = b"POAG";
+const VERSION: u8 = 1;
+
+fn put_u32(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+// reads the fields of a serialized graph in order, failing on truncated input
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PoaError> {
+        if self.bytes.len() < len {
+            return Err(invalid("the bytes end early"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, PoaError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, PoaError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    // a length followed by that many values, checking the length against the remaining bytes
+    // before allocating for it
+    fn u32s(&mut self, len: usize) -> Result<Vec<u32>, PoaError> {
+        let bytes = self.take(len.checked_mul(4).ok_or_else(|| invalid("a length overflows"))?)?;
+        Ok(bytes.chunks_exact(4).map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap())).collect())
+    }
+}
+
+fn invalid(message: &str) -> PoaError {
+    PoaError::InvalidGraph(message.to_string())
+}
+
+impl PoaGraph {
+    /// Serializes the graph, with its parameters and substitution matrix, to a compact binary
+    /// format that `from_bytes` restores.
+    ///
+    /// The bytes hold the parameters as their preset string, the base of every node and every
+    /// sequence as its node path, base weights and alignment score, with integers in little-endian order. The
+    /// format carries a version number, so a later version of the crate rejects bytes it cannot
+    /// read rather than misreading them.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+
+        let params = self.params.to_string();
+        put_u32(&mut bytes, params.len() as u32);
+        bytes.extend_from_slice(params.as_bytes());
+        match &self.matrix {
+            Some(matrix) => {
+                bytes.push(1);
+                put_u32(&mut bytes, matrix.alphabet().len() as u32);
+                bytes.extend_from_slice(matrix.alphabet());
+                for &score in matrix.raw_scores() {
+                    bytes.extend_from_slice(&score.to_le_bytes());
+                }
+            }
+            None => bytes.push(0),
+        }
+
+        let nodes = self.nodes();
+        let mut partners: Vec<i32> = vec![-1; nodes.len()];
+        unsafe { poa_graph_aligned_partners(self.raw.as_ptr(), partners.as_mut_ptr()) }
+        put_u32(&mut bytes, nodes.len() as u32);
+        bytes.extend(nodes.iter().map(|node| node.base));
+        for partner in partners {
+            bytes.extend_from_slice(&partner.to_le_bytes());
+        }
+
+        put_u32(&mut bytes, self.num_sequences() as u32);
+        for i in 0..self.num_sequences() {
+            let path = self.sequence_path(i);
+            put_u32(&mut bytes, path.len() as u32);
+            for value in path.into_iter().chain(self.sequence_weights(i)) {
+                put_u32(&mut bytes, value);
+            }
+            bytes.extend_from_slice(&self.sequence_score(i).unwrap_or(NO_SCORE).to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Restores a graph serialized by `to_bytes`, so sequences can be added to it where they left off.
+    ///
+    /// The sequences are added again in order, each aligned along its path, which rebuilds the
+    /// same nodes, edges and alignments between nodes under the same ids. Returns
+    /// `PoaError::InvalidGraph` if the bytes were not written by `to_bytes`, are truncated, or
+    /// describe a graph that cannot be rebuilt, and the errors of parsing the parameters.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     graph.add_sequence(b"AATGCCCGTT");
+    ///     graph.add_sequence(b"AATGCCGTT");
+    ///
+    ///     let mut restored = PoaGraph::from_bytes(&graph.to_bytes()).unwrap();
+    ///     restored.add_sequence(b"AATGCCCGTT");
+    ///     assert_eq!(restored.num_sequences(), 3);
+    ///     assert_eq!(restored.consensus(), b"AATGCCCGTT".to_vec());
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<PoaGraph, PoaError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(invalid("the bytes are not a serialized graph"));
+        }
+        if reader.take(1)?[0] != VERSION {
+            return Err(invalid("unsupported format version"));
+        }
+
+        let len = reader.u32()? as usize;
+        let params: PoaParams = std::str::from_utf8(reader.take(len)?).map_err(|_| invalid("the parameters are not text"))?.parse()?;
+        let graph = match reader.take(1)?[0] {
+            0 => PoaGraph::try_new(&params)?,
+            1 => {
+                let len = reader.u32()? as usize;
+                let alphabet = reader.take(len)?;
+                let mut rows = vec![Vec::with_capacity(len); len];
+                for row in rows.iter_mut() {
+                    for _ in 0..len {
+                        row.push(reader.i32()?);
+                    }
+                }
+                PoaGraph::try_with_matrix(&params, &SubstitutionMatrix::new(alphabet, &rows)?)?
+            }
+            _ => return Err(invalid("unknown substitution matrix flag")),
+        };
+
+        let num_nodes = reader.u32()? as usize;
+        let bases = reader.take(num_nodes)?;
+        let mut partners = Vec::with_capacity(num_nodes);
+        for id in 0..num_nodes {
+            let partner = reader.i32()?;
+            if partner < -1 || partner >= id as i32 {
+                return Err(invalid("a node is aligned to a later node"));
+            }
+            partners.push(partner);
+        }
+
+        for _ in 0..reader.u32()? {
+            let len = reader.u32()? as usize;
+            let path = reader.u32s(len)?;
+            let weights = reader.u32s(len)?;
+            let score = reader.i32()?;
+            if path.iter().any(|&id| id as usize >= num_nodes) {
+                return Err(invalid("a sequence passes through an unknown node"));
+            }
+            let seq: Vec<u8> = path.iter().map(|&id| bases[id as usize]).collect();
+            let (node_ids, positions) = replay_alignment(&path, &partners, graph.num_nodes() as u32);
+            unsafe {
+                poa_graph_add_alignment(
+                    graph.raw.as_ptr(),
+                    seq.as_ptr(),
+                    native_len(len),
+                    weights.as_ptr(),
+                    node_ids.as_ptr(),
+                    positions.as_ptr(),
+                    native_len(node_ids.len()),
+                    score,
+                );
+            }
+            native_result()?;
+            if graph.sequence_path(graph.num_sequences() - 1) != path {
+                return Err(invalid("a sequence does not follow its path"));
+            }
+        }
+        if graph.num_nodes() != num_nodes || !reader.bytes.is_empty() {
+            return Err(invalid("the graph does not match its nodes"));
+        }
+        Ok(graph)
+    }
+}
+
+// the alignment adding a sequence again along its path to a graph of num_nodes nodes, as the
+// columns of node ids and sequence positions. spoa creates the nodes of the bases before and after
+// the aligned part of a sequence first, then those of unaligned and mismatched bases inside it,
+// so the aligned part is the one leaving the new nodes numbered as along the path. nodes before
+// and after it are aligned to no other node, and bases of new nodes inside it are aligned to the
+// node their node was created as a mismatch of, if any.
+fn replay_alignment(path: &[u32], partners: &[i32], num_nodes: u32) -> (Vec<i32>, Vec<i32>) {
+    let len = path.len();
+    let unaligned = |id: u32| partners[id as usize] < 0;
+    let prefix = path.iter().zip(num_nodes..).take_while(|&(&id, next)| id == next && unaligned(id)).count();
+    // the suffix runs on from the prefix, numbered up to the id of the last base
+    let next = num_nodes + prefix as u32;
+    let suffix = match path.last() {
+        Some(&last) if last >= next && ((last - next) as usize) < len - prefix => {
+            let suffix = (last - next) as usize + 1;
+            let numbered = path[len - suffix..].iter().zip(next..).all(|(&id, next)| id == next && unaligned(id));
+            if numbered {
+                suffix
+            } else {
+                0
+            }
+        }
+        _ => 0,
+    };
+
+    (prefix..len - suffix)
+        .map(|i| {
+            let id = path[i];
+            let node = if id < num_nodes {
+                id as i32
+            } else {
+                match partners[id as usize] {
+                    partner if partner >= 0 && (partner as u32) < num_nodes => partner,
+                    _ => -1,
+                }
+            };
+            (node, i as i32)
+        })
+        .unzip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HomopolymerGaps, Matrix, PoaParams};
+
+    fn assert_same(graph: &PoaGraph, restored: &PoaGraph) {
+        assert_eq!(restored.params(), graph.params());
+        assert_eq!(restored.nodes(), graph.nodes());
+        assert_eq!(restored.edges(), graph.edges());
+        assert_eq!(restored.consensus(), graph.consensus());
+        assert_eq!(restored.multiple_sequence_alignment(true), graph.multiple_sequence_alignment(true));
+        for i in 0..graph.num_sequences() {
+            assert_eq!(restored.soft_clips(i), graph.soft_clips(i));
+            assert_eq!(restored.sequence_score(i), graph.sequence_score(i));
+        }
+        assert_eq!(restored.to_bytes(), graph.to_bytes());
+    }
+
+    #[test]
+    fn test_round_trip_in_every_mode() {
+        let reads = ["ATTGCCCGTT", "AATGCCGTT", "GGAATGCCCGATCC", "AACGCCCGTC", "TTTTAGTGCTCGTT", "AATGCTCGTTAAAA", "CCCC"];
+        for alignment_type in 0..3 {
+            let params = PoaParams::new(alignment_type, 5, -4, -8, -6);
+            let mut graph = PoaGraph::new(&params);
+            let mut continued = PoaGraph::new(&params);
+            for (i, read) in reads.iter().enumerate() {
+                let weights: Vec<u32> = (0..read.len() as u32).map(|k| 1 + (k + i as u32) % 4).collect();
+                graph.add_sequence_weighted(read.as_bytes(), &weights);
+                continued.add_sequence_weighted(read.as_bytes(), &weights);
+                assert_same(&graph, &PoaGraph::from_bytes(&graph.to_bytes()).unwrap());
+            }
+
+            // sequences added after restoring align as to the original graph
+            let mut restored = PoaGraph::from_bytes(&graph.to_bytes()).unwrap();
+            restored.add_sequence(b"AATGCCCGTA");
+            continued.add_sequence(b"AATGCCCGTA");
+            assert_same(&continued, &restored);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_of_derived_graphs() {
+        let mut graph = PoaGraph::with_matrix(&PoaParams::protein(), &Matrix::Blosum62.into());
+        for seq in ["MKTAYIAKQR", "MKSAYIAKQR", "MKTAYLAKQRQ", ""].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        assert_same(&graph, &PoaGraph::from_bytes(&graph.to_bytes()).unwrap());
+        assert!(PoaGraph::from_bytes(&graph.to_bytes()).unwrap().matrix().is_some());
+
+        let mut dna = PoaGraph::new(&PoaParams::dna().homopolymer_gaps(HomopolymerGaps::new(4, -2, -1)));
+        for seq in ["ACGTAAAAAACGT", "ACGTAAAAACGT", "ACGAAAAAAACGT"].iter() {
+            dna.add_sequence(seq.as_bytes());
+        }
+        for graph in [dna.reverse_complement(), dna.subgraph(2, 10), dna].iter() {
+            assert_same(graph, &PoaGraph::from_bytes(&graph.to_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        let mut graph = PoaGraph::new(&PoaParams::dna());
+        graph.add_sequence(b"ACGT");
+        graph.add_sequence(b"AGGT");
+        let bytes = graph.to_bytes();
+
+        assert!(matches!(PoaGraph::from_bytes(b"ACGT"), Err(PoaError::InvalidGraph(_))));
+        for len in 0..bytes.len() {
+            assert!(PoaGraph::from_bytes(&bytes[..len]).is_err(), "{}", len);
+        }
+        let mut version = bytes.clone();
+        version[4] = 2;
+        assert!(PoaGraph::from_bytes(&version).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(PoaGraph::from_bytes(&trailing).is_err());
+        // a path through a node created by a later sequence
+        let mut path = bytes;
+        let last = path.len() - 4 - 8 * 4;
+        path[last..last + 4].copy_from_slice(&7u32.to_le_bytes());
+        assert!(PoaGraph::from_bytes(&path).is_err());
+    }
+}
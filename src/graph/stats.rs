@@ -0,0 +1,124 @@
+//! Cheap summaries of the shape of a graph, e.g. to tell a clean read bundle from one that likely
+//! holds several haplotypes before looking closer.
+
+use super::paths::Topology;
+use super::PoaGraph;
+use crate::CoverageHistogram;
+
+/// The size and shape of a graph, as returned by `PoaGraph::stats`.
+///
+/// A bundle of reads of one sequence with few errors gives a graph barely longer than its
+/// consensus, with branch points spread thin and carried by single reads; several haplotypes give
+/// more branch points and consensus nodes only part of the reads pass through.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphStats {
+    /// the number of nodes
+    pub num_nodes: usize,
+    /// the number of edges
+    pub num_edges: usize,
+    /// the number of sequences added
+    pub num_sequences: usize,
+    /// the number of nodes with more than one outgoing edge
+    pub branch_points: usize,
+    /// the mean number of outgoing edges of the nodes with any, 0 for a graph without edges
+    pub branching_factor: f64,
+    /// the number of nodes on the longest path, i.e. the number of topological ranks
+    pub depth: usize,
+    /// the number of nodes on the consensus path
+    pub consensus_len: usize,
+    /// the distribution of the number of sequences passing through each consensus node
+    pub consensus_coverage: CoverageHistogram,
+}
+
+impl PoaGraph {
+    /// Returns the node and edge counts, branching and depth of the graph, and the coverage of its
+    /// consensus path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+    ///     for seq in ["AATGCCCGTT", "AATGCCCGTT", "AATGACCGTT"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     let stats = graph.stats();
+    ///     assert_eq!((stats.num_nodes, stats.num_edges, stats.branch_points), (11, 11, 1));
+    ///     assert_eq!((stats.depth, stats.consensus_len), (10, 10));
+    ///     // one consensus base is carried by two of the three reads
+    ///     assert_eq!(stats.consensus_coverage.count(2), 1);
+    /// ```
+    pub fn stats(&self) -> GraphStats {
+        let nodes = self.nodes();
+        let edges = self.edges();
+        let topology = Topology::new(nodes.len(), &edges);
+
+        let mut branching_nodes = 0;
+        let mut branch_points = 0;
+        for out_edges in &topology.out_edges {
+            branching_nodes += !out_edges.is_empty() as usize;
+            branch_points += (out_edges.len() > 1) as usize;
+        }
+        let branching_factor = if branching_nodes == 0 { 0.0 } else { edges.len() as f64 / branching_nodes as f64 };
+
+        // the number of nodes on the longest path ending at each node
+        let mut longest = vec![0usize; nodes.len()];
+        for &v in &topology.order {
+            longest[v as usize] = 1 + topology.in_edges[v as usize].iter().map(|&(u, _)| longest[u as usize]).max().unwrap_or(0);
+        }
+
+        let consensus = self.consensus_path();
+        let coverage: Vec<u32> = consensus.iter().map(|&id| nodes[id as usize].coverage).collect();
+        GraphStats {
+            num_nodes: nodes.len(),
+            num_edges: edges.len(),
+            num_sequences: self.num_sequences(),
+            branch_points,
+            branching_factor,
+            depth: longest.into_iter().max().unwrap_or(0),
+            consensus_len: consensus.len(),
+            consensus_coverage: CoverageHistogram::from_depths(&coverage),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PoaParams;
+
+    #[test]
+    fn test_haplotypes_branch_more() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let haplotypes: [&[u8]; 2] = [b"GATTACAGGCTTACCGATCGATTGCAGCT", b"GATTACTGGCTTACCGAACGATTGAAGCT"];
+
+        let mut clean = PoaGraph::new(&params);
+        let mut mixed = PoaGraph::new(&params);
+        for i in 0..6 {
+            clean.add_sequence(haplotypes[0]);
+            mixed.add_sequence(haplotypes[i % 2]);
+        }
+
+        let clean = clean.stats();
+        assert_eq!((clean.num_nodes, clean.num_edges, clean.num_sequences), (29, 28, 6));
+        assert_eq!((clean.branch_points, clean.branching_factor), (0, 1.0));
+        assert_eq!((clean.depth, clean.consensus_len), (29, 29));
+        assert_eq!(clean.consensus_coverage.count(6), 29);
+
+        let mixed = mixed.stats();
+        assert_eq!((mixed.num_nodes, mixed.branch_points), (32, 3));
+        assert!(mixed.branching_factor > 1.0);
+        assert_eq!(mixed.consensus_coverage.count(3), 3);
+        assert_eq!(mixed.consensus_coverage.count(6), 26);
+    }
+
+    #[test]
+    fn test_empty_graph() {
+        let stats = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6)).stats();
+        assert_eq!((stats.num_nodes, stats.num_edges, stats.depth, stats.consensus_len), (0, 0, 0, 0));
+        assert_eq!(stats.branching_factor, 0.0);
+        assert_eq!(stats.consensus_coverage.positions(), 0);
+    }
+}
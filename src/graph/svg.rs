@@ -0,0 +1,191 @@
+//! Rendering small graphs as SVG, without an external layout tool.
+
+use std::fmt::Write;
+
+use super::paths::Topology;
+use super::PoaGraph;
+
+// the layout grid, in pixels
+const MARGIN: f64 = 30.0;
+const LAYER_SPACING: f64 = 50.0;
+const ROW_SPACING: f64 = 45.0;
+const RADIUS: f64 = 14.0;
+
+const CONSENSUS_COLOR: &str = "#d62728";
+
+// returns a fill color from white for weight 0 to a saturated blue for the heaviest node
+fn weight_color(weight: u64, max_weight: u64) -> String {
+    let t = if max_weight == 0 { 0.0 } else { weight as f64 / max_weight as f64 };
+    let (r, g) = (255.0 - 215.0 * t, 255.0 - 135.0 * t);
+    format!("rgb({},{},255)", r.round() as u8, g.round() as u8)
+}
+
+fn write_svg_symbol(svg: &mut String, base: u8) {
+    match base {
+        b'&' => svg.push_str("&amp;"),
+        b'<' => svg.push_str("&lt;"),
+        b'>' => svg.push_str("&gt;"),
+        0x21..=0x7e => svg.push(base as char),
+        _ => svg.push('?'),
+    }
+}
+
+impl PoaGraph {
+    /// Renders the graph as a standalone SVG image, for figures and debugging of small graphs.
+    ///
+    /// Nodes are laid out in layers from left to right, each node one layer after the furthest
+    /// of its predecessors, with the consensus path kept on the top row. Nodes are filled from
+    /// white to blue by their weight relative to the heaviest node and labelled with their base;
+    /// edges are drawn wider the heavier they are, and the consensus path is outlined in red.
+    /// Hovering a node shows its id, weight and coverage.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     graph.add_sequence(b"AATGCCCGTT");
+    ///     graph.add_sequence(b"AATGCACGTT");
+    ///
+    ///     let svg = graph.to_svg();
+    ///     assert!(svg.starts_with("<svg"));
+    ///     assert_eq!(svg.matches("<circle").count(), 11);
+    /// ```
+    pub fn to_svg(&self) -> String {
+        let nodes = self.nodes();
+        let edges = self.edges();
+        let topology = Topology::new(nodes.len(), &edges);
+        let consensus = self.consensus_path();
+
+        let mut on_consensus = vec![false; nodes.len()];
+        for &id in &consensus {
+            on_consensus[id as usize] = true;
+        }
+
+        // each node sits one layer after the furthest of its predecessors
+        let mut layer = vec![0usize; nodes.len()];
+        for &v in &topology.order {
+            layer[v as usize] = topology.in_edges[v as usize].iter().map(|&(u, _)| layer[u as usize] + 1).max().unwrap_or(0);
+        }
+        let num_layers = layer.iter().max().map_or(0, |&max| max + 1);
+
+        // the consensus node of a layer takes row 0 and the others follow by id
+        let mut next_row = vec![1usize; num_layers];
+        let mut row = vec![0usize; nodes.len()];
+        for node in &nodes {
+            let id = node.id as usize;
+            if !on_consensus[id] {
+                row[id] = next_row[layer[id]];
+                next_row[layer[id]] += 1;
+            }
+        }
+        let num_rows = next_row.iter().max().copied().unwrap_or(1);
+
+        let position = |id: u32| {
+            let id = id as usize;
+            (MARGIN + RADIUS + layer[id] as f64 * LAYER_SPACING, MARGIN + RADIUS + row[id] as f64 * ROW_SPACING)
+        };
+        let width = 2.0 * (MARGIN + RADIUS) + num_layers.saturating_sub(1) as f64 * LAYER_SPACING;
+        let height = 2.0 * (MARGIN + RADIUS) + (num_rows - 1) as f64 * ROW_SPACING;
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">",
+            width, height
+        )
+        .unwrap();
+        // the arrow heads end at the rim of the node they point to
+        writeln!(
+            svg,
+            "<defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"{:.1}\" refY=\"5\" markerWidth=\"6\" markerHeight=\"6\" \
+             orient=\"auto-start-reverse\" markerUnits=\"userSpaceOnUse\"><path d=\"M0,0L10,5L0,10z\" fill=\"#555\"/></marker></defs>",
+            10.0 + RADIUS * 10.0 / 6.0
+        )
+        .unwrap();
+
+        let max_edge_weight = edges.iter().map(|edge| edge.weight).max().unwrap_or(0).max(1);
+        for edge in &edges {
+            let ((x1, y1), (x2, y2)) = (position(edge.from), position(edge.to));
+            let consensus_edge = consensus.windows(2).any(|pair| pair[0] == edge.from && pair[1] == edge.to);
+            let color = if consensus_edge { CONSENSUS_COLOR } else { "#999" };
+            writeln!(
+                svg,
+                "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\" stroke-width=\"{:.2}\" marker-end=\"url(#arrow)\"/>",
+                x1,
+                y1,
+                x2,
+                y2,
+                color,
+                1.0 + 3.0 * edge.weight as f64 / max_edge_weight as f64
+            )
+            .unwrap();
+        }
+
+        let max_node_weight = nodes.iter().map(|node| node.weight).max().unwrap_or(0);
+        for node in &nodes {
+            let (x, y) = position(node.id);
+            let (stroke, stroke_width) = if on_consensus[node.id as usize] { (CONSENSUS_COLOR, 3) } else { ("#555", 1) };
+            write!(
+                svg,
+                "<g><title>node {} weight {} coverage {}</title><circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" stroke=\"{}\" \
+                 stroke-width=\"{}\"/><text x=\"{}\" y=\"{}\" text-anchor=\"middle\" dominant-baseline=\"central\" \
+                 font-family=\"monospace\" font-size=\"14\">",
+                node.id,
+                node.weight,
+                node.coverage,
+                x,
+                y,
+                RADIUS,
+                weight_color(node.weight, max_node_weight),
+                stroke,
+                stroke_width,
+                x,
+                y
+            )
+            .unwrap();
+            write_svg_symbol(&mut svg, node.base);
+            svg.push_str("</text></g>\n");
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PoaGraph, PoaParams};
+
+    #[test]
+    fn test_layers_and_highlighting() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        graph.add_sequence(b"ACGT");
+        graph.add_sequence(b"ACGT");
+        graph.add_sequence(b"AGT");
+        let svg = graph.to_svg();
+
+        // four layers on the consensus row, the shortcut edge skipping C has no node of its own
+        assert!(svg.contains("width=\"238\" height=\"88\""));
+        assert_eq!(svg.matches("<circle").count(), 4);
+        assert_eq!(svg.matches("<line").count(), 4);
+        // every node is on the consensus, and all edges but the shortcut
+        assert_eq!(svg.matches("stroke=\"#d62728\" stroke-width=\"3\"").count(), 4);
+        assert_eq!(svg.matches("<line").count() - svg.matches("stroke=\"#999\"").count(), 3);
+        assert!(svg.contains("<title>node 0 weight 3 coverage 3</title>"));
+        assert!(svg.contains("fill=\"rgb(40,120,255)\""));
+    }
+
+    #[test]
+    fn test_empty_graph_and_escaping() {
+        let graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        assert!(graph.to_svg().ends_with("</svg>\n"));
+
+        let mut svg = String::new();
+        for &base in b"A<&>\n" {
+            super::write_svg_symbol(&mut svg, base);
+        }
+        assert_eq!(svg, "A&lt;&amp;&gt;?");
+    }
+}
@@ -0,0 +1,776 @@
+//! Generating one consensus per read group, such as the reads sharing a UMI.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use crate::fast::consensus_of_few;
+use crate::{small_group_consensus, ConsensusAnnotations, PoaGraph, PoaParams, SeqRecord};
+
+/// What to do with a group holding fewer reads than the minimum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmallGroups {
+    /// leave the group out of the output
+    Skip,
+    /// output the first read of the group as is, flagged as passed through
+    PassThrough,
+}
+
+/// Options for generating the consensuses of many read groups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GroupOptions {
+    /// the minimum number of non-empty reads a group needs for a consensus to be built
+    pub min_reads: usize,
+    /// what to do with groups below `min_reads`
+    pub small_groups: SmallGroups,
+    /// the number of threads groups are spread across
+    pub threads: usize,
+    /// whether to choose the alignment type of each group from its reads, overriding the parameters
+    pub auto_alignment: bool,
+    /// whether to build the consensus of groups of two or three reads with `small_group_consensus`
+    /// instead of a graph
+    pub fast_small_groups: bool,
+}
+
+impl Default for GroupOptions {
+    fn default() -> GroupOptions {
+        GroupOptions { min_reads: 1, small_groups: SmallGroups::Skip, threads: 1, auto_alignment: false, fast_small_groups: false }
+    }
+}
+
+impl GroupOptions {
+    /// Creates options building a consensus for every group with at least one read.
+    pub fn new() -> GroupOptions {
+        GroupOptions::default()
+    }
+
+    /// Sets the minimum number of reads a group needs for a consensus to be built.
+    pub fn min_reads(mut self, min_reads: usize) -> GroupOptions {
+        self.min_reads = min_reads;
+        self
+    }
+
+    /// Sets what to do with groups below the minimum number of reads.
+    pub fn small_groups(mut self, small_groups: SmallGroups) -> GroupOptions {
+        self.small_groups = small_groups;
+        self
+    }
+
+    /// Sets the number of threads groups are spread across. The output is the same for any number.
+    pub fn threads(mut self, threads: usize) -> GroupOptions {
+        self.threads = threads;
+        self
+    }
+
+    /// Sets whether to choose the alignment type of each group with `choose_alignment_type`.
+    pub fn auto_alignment(mut self, auto_alignment: bool) -> GroupOptions {
+        self.auto_alignment = auto_alignment;
+        self
+    }
+
+    /// Sets whether groups of two or three reads skip graph construction, which is much faster for
+    /// such groups but does not always give the same consensus.
+    pub fn fast_small_groups(mut self, fast_small_groups: bool) -> GroupOptions {
+        self.fast_small_groups = fast_small_groups;
+        self
+    }
+
+    fn use_fast_path(&self, reads: usize) -> bool {
+        self.fast_small_groups && (2..=3).contains(&reads)
+    }
+}
+
+/// The output record of one read group.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupConsensus {
+    /// the name of the group
+    pub name: String,
+    /// the consensus of the group, or its first read if it was passed through
+    pub consensus: Vec<u8>,
+    /// the number of non-empty reads in the group
+    pub reads: usize,
+    /// whether the group had too few reads and its first read was output instead of a consensus
+    pub passed_through: bool,
+}
+
+/// Counts of how the read groups were handled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GroupSummary {
+    /// the number of groups processed
+    pub groups: usize,
+    /// the number of groups a consensus was built for
+    pub built: usize,
+    /// the number of groups below the minimum number of reads that were left out
+    pub skipped: usize,
+    /// the number of groups below the minimum number of reads whose first read was output
+    pub passed_through: usize,
+}
+
+/// Generates the consensus of each read group, handling groups with too few reads as set in `options`.
+///
+/// Each thread reuses one graph for all the groups it processes. Empty reads are ignored and do
+/// not count towards the minimum; a group without any non-empty read is always skipped.
+///
+/// The groups are independent of each other and each consensus depends only on its own group, so
+/// the records and the summary are byte-identical for any number of threads.
+/// # Arguments
+///
+/// * `groups` - the name and the reads (without null terminators) of each group
+/// * `params` - the alignment and scoring parameters
+/// * `options` - the minimum number of reads and what to do with smaller groups
+///
+/// # Returns
+/// * returns the output record of each group that was not skipped, in input order, and the counts of how groups were handled
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_of_groups, GroupOptions, PoaParams, SmallGroups};
+///
+///     let groups = vec![
+///         ("umi_1".to_string(), vec![b"AATGCCCGTT".to_vec(), b"AATGCCCGTT".to_vec(), b"AATGCCGTT".to_vec()]),
+///         ("umi_2".to_string(), vec![b"ACGTTA".to_vec()]),
+///     ];
+///     let options = GroupOptions::new().min_reads(2).small_groups(SmallGroups::PassThrough);
+///     let (records, summary) = poa_consensus_of_groups(groups, &PoaParams::new(1, 5, -4, -3, -1), &options);
+///
+///     assert_eq!(records[0].consensus, b"AATGCCCGTT".to_vec());
+///     assert!(records[1].passed_through);
+///     assert_eq!(summary.passed_through, 1);
+/// ```
+pub fn poa_consensus_of_groups<I>(
+    groups: I,
+    params: &PoaParams,
+    options: &GroupOptions
+) -> (Vec<GroupConsensus>, GroupSummary)
+where
+    I: IntoIterator<Item = (String, Vec<Vec<u8>>)>,
+{
+    let groups: Vec<(String, Vec<Vec<u8>>)> = groups.into_iter().collect();
+
+    // each group is processed on its own, with a graph private to its thread, and its result is
+    // stored under its input index, so the output does not depend on the number of threads
+    let outcomes = parallel_map(&groups, options.threads, params, |graph, (_, reads)| process_group(graph, reads, options));
+
+    let mut records = vec![];
+    let mut summary = GroupSummary { groups: groups.len(), ..GroupSummary::default() };
+    for ((name, _), outcome) in groups.iter().zip(outcomes) {
+        match outcome {
+            Outcome::Skipped => summary.skipped += 1,
+            Outcome::Record { consensus, reads, passed_through } => {
+                if passed_through {
+                    summary.passed_through += 1;
+                } else {
+                    summary.built += 1;
+                }
+                records.push(GroupConsensus { name: name.clone(), consensus, reads, passed_through });
+            }
+        }
+    }
+
+    (records, summary)
+}
+
+/// Generates the consensus of each read group keyed by its barcode or UMI, spread across all
+/// available threads.
+///
+/// This is `poa_consensus_groups_with_options` with the default options and the number of threads
+/// the system reports as available.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_groups, PoaParams};
+///
+///     let groups = vec![
+///         ("ACGTACGT", vec![b"AATGCCCGTT".to_vec(), b"AATGCCGTT".to_vec(), b"AATGCCCGTT".to_vec()]),
+///         ("TTGCAAGC", vec![b"GATTACA".to_vec()]),
+///         ("ACGTACGT", vec![b"AATGCCCGTT".to_vec()]),
+///     ];
+///     let consensus = poa_consensus_groups(groups, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(consensus.len(), 2);
+///     assert_eq!(consensus["ACGTACGT"], b"AATGCCCGTT".to_vec());
+///     assert_eq!(consensus["TTGCAAGC"], b"GATTACA".to_vec());
+/// ```
+pub fn poa_consensus_groups<K, I>(groups: I, params: &PoaParams) -> HashMap<K, Vec<u8>>
+where
+    K: Eq + Hash + Sync,
+    I: IntoIterator<Item = (K, Vec<Vec<u8>>)>,
+{
+    poa_consensus_groups_with_options(groups, params, &GroupOptions::new().threads(available_threads()))
+}
+
+/// Generates the consensus of each read group keyed by its barcode or UMI, handling groups with
+/// too few reads as set in `options`.
+///
+/// The reads of groups given under the same key are pooled into one group first, so the input
+/// can hold a key more than once, e.g. when reads come from several files. A group of a single
+/// read gets the read as its consensus without building a graph. Groups are otherwise handled as
+/// by `poa_consensus_of_groups`; skipped groups, including those without a non-empty read, are
+/// left out of the map, and passed-through groups map to their first read.
+/// # Arguments
+///
+/// * `groups` - the key and the reads (without null terminators) of each group
+/// * `params` - the alignment and scoring parameters
+/// * `options` - the minimum number of reads, what to do with smaller groups, and the threads
+///
+/// # Returns
+/// * returns the consensus of each group that was not skipped, by key
+pub fn poa_consensus_groups_with_options<K, I>(groups: I, params: &PoaParams, options: &GroupOptions) -> HashMap<K, Vec<u8>>
+where
+    K: Eq + Hash + Sync,
+    I: IntoIterator<Item = (K, Vec<Vec<u8>>)>,
+{
+    let mut pooled: HashMap<K, Vec<Vec<u8>>> = HashMap::new();
+    for (key, reads) in groups {
+        pooled.entry(key).or_default().extend(reads);
+    }
+    let pooled: Vec<(K, Vec<Vec<u8>>)> = pooled.into_iter().collect();
+
+    let outcomes = parallel_map(&pooled, options.threads, params, |graph, (_, reads)| process_group(graph, reads, options));
+    pooled
+        .into_iter()
+        .zip(outcomes)
+        .filter_map(|((key, _), outcome)| match outcome {
+            Outcome::Skipped => None,
+            Outcome::Record { consensus, .. } => Some((key, consensus)),
+        })
+        .collect()
+}
+
+// the number of threads the system reports as available, 1 if it does not
+pub(crate) fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+/// Generates the consensus of each of many independent sets of reads, spread across all
+/// available threads.
+///
+/// This is `poa_consensus_batch_with_threads` with the number of threads the system reports as
+/// available.
+/// # Arguments
+///
+/// * `jobs` - the reads of each consensus, with or without null terminators
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of each job, in input order, empty for a job without a non-empty read
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_batch, PoaParams};
+///
+///     let jobs = vec![
+///         vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCGTT\0".to_vec(), b"AATGCCCGTT\0".to_vec()],
+///         vec![b"ACGTTA".to_vec(), b"ACGTTA".to_vec()],
+///         vec![],
+///     ];
+///     let consensus = poa_consensus_batch(&jobs, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(consensus, vec![b"AATGCCCGTT".to_vec(), b"ACGTTA".to_vec(), vec![]]);
+/// ```
+pub fn poa_consensus_batch(jobs: &[Vec<Vec<u8>>], params: &PoaParams) -> Vec<Vec<u8>> {
+    poa_consensus_batch_with_threads(jobs, params, available_threads())
+}
+
+/// Generates the consensus of each of many independent sets of reads, such as UMI families,
+/// spread across `threads` threads.
+///
+/// Each thread builds the consensus of the jobs it takes with a graph of its own, which it clears
+/// between jobs, so no native state is shared between threads and the output is the same for
+/// any number of threads. Empty reads and trailing null terminators are ignored.
+/// # Arguments
+///
+/// * `jobs` - the reads of each consensus, with or without null terminators
+/// * `params` - the alignment and scoring parameters
+/// * `threads` - the number of threads to spread the jobs across
+///
+/// # Returns
+/// * returns the consensus of each job, in input order, empty for a job without a non-empty read
+pub fn poa_consensus_batch_with_threads(jobs: &[Vec<Vec<u8>>], params: &PoaParams, threads: usize) -> Vec<Vec<u8>> {
+    parallel_map(jobs, threads, params, |graph, reads| {
+        graph.clear();
+        for read in reads {
+            let read = read.strip_suffix(b"\0").unwrap_or(read);
+            if !read.is_empty() {
+                graph.add_sequence(read);
+            }
+        }
+        graph.consensus()
+    })
+}
+
+// applies f to every item with a graph private to the thread it runs on, across at most threads
+// threads, returning the results in the order of the items. WebAssembly runs them all on the
+// calling thread, as it cannot start threads
+pub(crate) fn parallel_map<T, R, F>(items: &[T], threads: usize, params: &PoaParams, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&mut PoaGraph, &T) -> R + Sync,
+{
+    let threads = threads.max(1).min(items.len().max(1));
+    if threads == 1 || cfg!(target_family = "wasm") {
+        let mut graph = PoaGraph::new(params);
+        return items.iter().map(|item| f(&mut graph, item)).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let finished: Vec<Vec<(usize, R)>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut graph = PoaGraph::new(params);
+                    let mut finished = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= items.len() {
+                            return finished;
+                        }
+                        finished.push((i, f(&mut graph, &items[i])));
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().map(|worker| worker.join().expect("a consensus thread panicked")).collect()
+    });
+
+    let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+    for (i, result) in finished.into_iter().flatten() {
+        results[i] = Some(result);
+    }
+    results.into_iter().map(|result| result.expect("every item is processed")).collect()
+}
+
+// empties the graph for the next group, replacing it if the group needs another alignment type
+fn prepare_graph<T: AsRef<[u8]>>(graph: &mut PoaGraph, reads: &[T], options: &GroupOptions) {
+    if options.auto_alignment {
+        let params = graph.params().auto_alignment_type(reads);
+        if params != graph.params() {
+            *graph = PoaGraph::new(&params);
+            return;
+        }
+    }
+    graph.clear();
+}
+
+enum Outcome {
+    Skipped,
+    Record { consensus: Vec<u8>, reads: usize, passed_through: bool },
+}
+
+fn process_group(graph: &mut PoaGraph, reads: &[Vec<u8>], options: &GroupOptions) -> Outcome {
+    let reads: Vec<&Vec<u8>> = reads.iter().filter(|read| !read.is_empty()).collect();
+
+    if reads.is_empty() || (reads.len() < options.min_reads && options.small_groups == SmallGroups::Skip) {
+        return Outcome::Skipped;
+    }
+
+    if reads.len() < options.min_reads {
+        return Outcome::Record { consensus: reads[0].clone(), reads: reads.len(), passed_through: true };
+    }
+
+    // the graph of a single read spells it, so it is its own consensus
+    let consensus = if reads.len() == 1 {
+        reads[0].clone()
+    } else if options.use_fast_path(reads.len()) {
+        let weighted: Vec<(&[u8], Vec<f64>)> = reads.iter().map(|read| (read.as_slice(), vec![1.0; read.len()])).collect();
+        consensus_of_few(&weighted)
+    } else {
+        prepare_graph(graph, &reads, options);
+        for read in &reads {
+            graph.add_sequence(read);
+        }
+        graph.consensus()
+    };
+    Outcome::Record { consensus, reads: reads.len(), passed_through: false }
+}
+
+/// When `group_consensus_by` considers a buffered group complete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupFlush {
+    /// the records are sorted by key, so a group is complete as soon as a record with another key
+    /// arrives; a key seen again later starts a new group
+    Sorted,
+    /// a group is complete once this many records with other keys have followed its last record
+    Window(usize),
+}
+
+/// The output record of one group of `group_consensus_by`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyedConsensus<K> {
+    /// the key shared by the records of the group
+    pub key: K,
+    /// the consensus of the group, or its first read if it was passed through
+    pub consensus: Vec<u8>,
+    /// the number of non-empty reads in the group
+    pub reads: usize,
+    /// whether the group had too few reads and its first read was output instead of a consensus
+    pub passed_through: bool,
+    /// the depth and identity of the reads behind the output sequence
+    pub annotations: ConsensusAnnotations,
+}
+
+/// An iterator over the consensuses of groups of records sharing a key, created by `group_consensus_by`.
+pub struct GroupBy<I, K, F> {
+    records: I,
+    key: F,
+    flush: GroupFlush,
+    options: GroupOptions,
+    graph: PoaGraph,
+    // the records of each open group and the input index of its last record
+    buffers: HashMap<K, (Vec<SeqRecord>, usize)>,
+    // the key and input index of each record, oldest first, for finding groups outside the window
+    recent: VecDeque<(K, usize)>,
+    ready: VecDeque<KeyedConsensus<K>>,
+    seen: usize,
+    summary: GroupSummary,
+    finished: bool,
+}
+
+/// Groups records by a key and generates the consensus of each group as soon as it is complete.
+///
+/// Only the open groups are buffered, so the input can be streamed. Groups are output in the
+/// order they are completed; groups still open at the end of the input are output in the order
+/// of their last record. Empty reads and small groups are handled as in `poa_consensus_of_groups`,
+/// with the records weighted by their qualities and weights; the `threads` option is not used.
+/// # Arguments
+///
+/// * `records` - the reads, with the information the key is taken from
+/// * `key` - returns the group key of a record, such as the UMI in its name
+/// * `flush` - when a group is complete
+/// * `params` - the alignment and scoring parameters
+/// * `options` - the minimum number of reads and what to do with smaller groups
+///
+/// # Returns
+/// * returns an iterator over the output record of each group that was not skipped
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{group_consensus_by, GroupFlush, GroupOptions, PoaParams, SeqRecord};
+///
+///     let records = vec![
+///         SeqRecord::new("umi_1:r1", b"AATGCCCGTT".to_vec()),
+///         SeqRecord::new("umi_1:r2", b"AATGCCGTT".to_vec()),
+///         SeqRecord::new("umi_1:r3", b"AATGCCCGTT".to_vec()),
+///         SeqRecord::new("umi_2:r1", b"ACGTTA".to_vec()),
+///     ];
+///     let umi = |record: &SeqRecord| record.name.split(':').next().unwrap().to_string();
+///     let params = PoaParams::new(1, 5, -4, -3, -1);
+///     let mut groups = group_consensus_by(records, umi, GroupFlush::Sorted, &params, &GroupOptions::new());
+///
+///     let first = groups.next().unwrap();
+///     assert_eq!((first.key.as_str(), first.consensus.as_slice(), first.reads), ("umi_1", &b"AATGCCCGTT"[..], 3));
+///     assert_eq!(groups.next().unwrap().key, "umi_2");
+///     assert!(groups.next().is_none());
+///     assert_eq!(groups.summary().built, 2);
+/// ```
+pub fn group_consensus_by<I, K, F>(
+    records: I,
+    key: F,
+    flush: GroupFlush,
+    params: &PoaParams,
+    options: &GroupOptions
+) -> GroupBy<I::IntoIter, K, F>
+where
+    I: IntoIterator<Item = SeqRecord>,
+    K: Eq + Hash + Clone,
+    F: FnMut(&SeqRecord) -> K,
+{
+    GroupBy {
+        records: records.into_iter(),
+        key,
+        flush,
+        options: *options,
+        graph: PoaGraph::new(params),
+        buffers: HashMap::new(),
+        recent: VecDeque::new(),
+        ready: VecDeque::new(),
+        seen: 0,
+        summary: GroupSummary::default(),
+        finished: false,
+    }
+}
+
+impl<I, K, F> GroupBy<I, K, F>
+where
+    I: Iterator<Item = SeqRecord>,
+    K: Eq + Hash + Clone,
+    F: FnMut(&SeqRecord) -> K,
+{
+    /// Returns the counts of how the groups completed so far were handled.
+    pub fn summary(&self) -> GroupSummary {
+        self.summary
+    }
+
+    fn complete(&mut self, key: K) {
+        let records = match self.buffers.remove(&key) {
+            Some((records, _)) => records,
+            None => return,
+        };
+        let records: Vec<SeqRecord> = records.into_iter().filter(|record| !record.seq.is_empty()).collect();
+        let options = self.options;
+        self.summary.groups += 1;
+
+        if records.is_empty() || (records.len() < options.min_reads && options.small_groups == SmallGroups::Skip) {
+            self.summary.skipped += 1;
+            return;
+        }
+
+        let passed_through = records.len() < options.min_reads;
+        let (consensus, annotations) = if passed_through {
+            self.summary.passed_through += 1;
+            let seq = records[0].seq.clone();
+            let annotations = ConsensusAnnotations::from_reads(&seq, &[&seq]);
+            (seq, annotations)
+        } else if options.use_fast_path(records.len()) {
+            self.summary.built += 1;
+            let consensus = small_group_consensus(&records).expect("the group has two or three reads");
+            let reads: Vec<&[u8]> = records.iter().map(|record| record.seq.as_slice()).collect();
+            let annotations = ConsensusAnnotations::from_reads(&consensus, &reads);
+            (consensus, annotations)
+        } else {
+            self.summary.built += 1;
+            let reads: Vec<&[u8]> = records.iter().map(|record| record.seq.as_slice()).collect();
+            prepare_graph(&mut self.graph, &reads, &options);
+            for record in &records {
+                self.graph.add_record(record);
+            }
+            (self.graph.consensus(), ConsensusAnnotations::from_graph(&self.graph))
+        };
+
+        self.ready.push_back(KeyedConsensus { key, consensus, reads: records.len(), passed_through, annotations });
+    }
+
+    fn add(&mut self, record: SeqRecord) {
+        let key = (self.key)(&record);
+        let index = self.seen;
+        self.seen += 1;
+
+        if self.flush == GroupFlush::Sorted && !self.buffers.contains_key(&key) {
+            let open: Vec<K> = self.recent.drain(..).map(|(key, _)| key).collect();
+            for key in open {
+                self.complete(key);
+            }
+        }
+
+        let buffer = self.buffers.entry(key.clone()).or_insert_with(|| (vec![], index));
+        buffer.0.push(record);
+        buffer.1 = index;
+        if self.flush == GroupFlush::Sorted {
+            if self.recent.is_empty() {
+                self.recent.push_back((key, index));
+            }
+            return;
+        }
+        self.recent.push_back((key, index));
+
+        if let GroupFlush::Window(window) = self.flush {
+            while matches!(self.recent.front(), Some(&(_, last)) if index - last >= window) {
+                let (key, last) = self.recent.pop_front().expect("the front was just checked");
+                if matches!(self.buffers.get(&key), Some(&(_, group_last)) if group_last == last) {
+                    self.complete(key);
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        while let Some((key, last)) = self.recent.pop_front() {
+            if self.flush == GroupFlush::Sorted || matches!(self.buffers.get(&key), Some(&(_, group_last)) if group_last == last) {
+                self.complete(key);
+            }
+        }
+    }
+}
+
+impl<I, K, F> Iterator for GroupBy<I, K, F>
+where
+    I: Iterator<Item = SeqRecord>,
+    K: Eq + Hash + Clone,
+    F: FnMut(&SeqRecord) -> K,
+{
+    type Item = KeyedConsensus<K>;
+
+    fn next(&mut self) -> Option<KeyedConsensus<K>> {
+        loop {
+            if let Some(group) = self.ready.pop_front() {
+                return Some(group);
+            }
+            if self.finished {
+                return None;
+            }
+            match self.records.next() {
+                Some(record) => self.add(record),
+                None => self.finish(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups() -> Vec<(String, Vec<Vec<u8>>)> {
+        vec![
+            ("a".to_string(), vec![b"AACGT".to_vec(), b"AACGT".to_vec(), b"ACCGT".to_vec()]),
+            ("b".to_string(), vec![b"GGTCA".to_vec(), vec![]]),
+            ("c".to_string(), vec![]),
+            ("d".to_string(), vec![b"TTGCA".to_vec(), b"TTGCA".to_vec()]),
+        ]
+    }
+
+    #[test]
+    fn test_batch_is_independent_of_threads() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let jobs: Vec<Vec<Vec<u8>>> =
+            (0..40).map(|i| groups().swap_remove(i % 4).1.into_iter().map(|read| [&read[..], b"\0"].concat()).collect()).collect();
+
+        let serial = poa_consensus_batch_with_threads(&jobs, &params, 1);
+        assert_eq!(serial[0], b"AACGT".to_vec());
+        assert_eq!(serial[1], b"GGTCA".to_vec());
+        assert!(serial[2].is_empty());
+        for &threads in [2, 3, 8, 100].iter() {
+            assert_eq!(poa_consensus_batch_with_threads(&jobs, &params, threads), serial);
+        }
+        assert_eq!(poa_consensus_batch(&jobs, &params), serial);
+        assert!(poa_consensus_batch(&[], &params).is_empty());
+    }
+
+    #[test]
+    fn test_keyed_groups() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let mut split = groups();
+        split.push(("a".to_string(), vec![b"AACGT".to_vec()]));
+        split.push(("b".to_string(), vec![b"GGTCA".to_vec()]));
+
+        let consensus = poa_consensus_groups(split.clone(), &params);
+        assert_eq!(consensus.len(), 3);
+        assert_eq!(consensus["a"], b"AACGT".to_vec());
+        assert_eq!(consensus["b"], b"GGTCA".to_vec());
+        assert!(!consensus.contains_key("c"));
+
+        // the pooled second group reaches the minimum, the single read of the last does not
+        let options = GroupOptions::new().min_reads(2).threads(3);
+        let consensus = poa_consensus_groups_with_options(split, &params, &options);
+        let mut keys: Vec<&String> = consensus.keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["a", "b", "d"]);
+
+        let single = poa_consensus_of_groups(vec![("x".to_string(), vec![b"GATTACA".to_vec()])], &params, &GroupOptions::new());
+        let mut graph = PoaGraph::new(&params);
+        graph.add_sequence(b"GATTACA");
+        assert_eq!(single.0[0].consensus, graph.consensus());
+    }
+
+    #[test]
+    fn test_min_reads_skips_small_groups() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let (records, summary) = poa_consensus_of_groups(groups(), &params, &GroupOptions::new().min_reads(2));
+
+        let names: Vec<&str> = records.iter().map(|record| record.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "d"]);
+        assert_eq!(records[0].consensus, b"AACGT".to_vec());
+        assert_eq!(records[0].reads, 3);
+        assert_eq!(summary, GroupSummary { groups: 4, built: 2, skipped: 2, passed_through: 0 });
+    }
+
+    #[test]
+    fn test_min_reads_passes_small_groups_through() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let options = GroupOptions::new().min_reads(3).small_groups(SmallGroups::PassThrough);
+        let (records, summary) = poa_consensus_of_groups(groups(), &params, &options);
+
+        assert_eq!(records.len(), 3);
+        assert!(!records[0].passed_through);
+        assert_eq!(records[1], GroupConsensus { name: "b".to_string(), consensus: b"GGTCA".to_vec(), reads: 1, passed_through: true });
+        assert!(records[2].passed_through);
+        assert_eq!(summary, GroupSummary { groups: 4, built: 1, skipped: 1, passed_through: 2 });
+    }
+
+    #[test]
+    fn test_output_does_not_depend_on_threads() {
+        use crate::simulate::{random_sequence, ReadSimulator};
+
+        let mut simulator = ReadSimulator::new(3).error_rates(0.05, 0.03, 0.03);
+        let groups: Vec<(String, Vec<Vec<u8>>)> = (0..40)
+            .map(|g| {
+                let truth = random_sequence(60 + g, g as u64);
+                (format!("umi_{}", g), simulator.reads(&truth, g % 7))
+            })
+            .collect();
+
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let options = GroupOptions::new().min_reads(3).small_groups(SmallGroups::PassThrough);
+        let expected = poa_consensus_of_groups(groups.clone(), &params, &options);
+        assert_eq!(expected.1.groups, 40);
+
+        for &threads in &[2, 3, 8, 64] {
+            assert_eq!(poa_consensus_of_groups(groups.clone(), &params, &options.threads(threads)), expected);
+        }
+
+        let auto = options.auto_alignment(true);
+        let expected = poa_consensus_of_groups(groups.clone(), &params, &auto);
+        assert_eq!(poa_consensus_of_groups(groups, &params, &auto.threads(4)), expected);
+    }
+
+    #[test]
+    fn test_fast_small_groups() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let options = GroupOptions::new().fast_small_groups(true);
+        let (records, summary) = poa_consensus_of_groups(groups(), &params, &options);
+
+        assert_eq!(records, poa_consensus_of_groups(groups(), &params, &GroupOptions::new()).0);
+        assert_eq!(summary.built, 3);
+
+        let mut keyed = keyed(&["a:1", "a:2", "a:3"]);
+        keyed[0].seq = b"AAGT".to_vec();
+        let group = group_consensus_by(keyed, umi, GroupFlush::Sorted, &params, &options).next().unwrap();
+        assert_eq!(group.consensus, b"AACGT".to_vec());
+        assert_eq!(group.annotations.reads_used, 3);
+        assert!((group.annotations.mean_depth - 14.0 / 5.0).abs() < 1e-9);
+    }
+
+    fn keyed(names: &[&str]) -> Vec<SeqRecord> {
+        names.iter().map(|name| SeqRecord::new(*name, b"AACGT".to_vec())).collect()
+    }
+
+    fn umi(record: &SeqRecord) -> String {
+        record.name.split(':').next().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_group_by_sorted() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let mut records = keyed(&["a:1", "a:2", "b:1", "c:1", "c:2", "a:3"]);
+        records[1].seq = b"AAGT".to_vec();
+        records[3].seq = vec![];
+
+        let options = GroupOptions::new().min_reads(2).small_groups(SmallGroups::PassThrough);
+        let mut groups = group_consensus_by(records, umi, GroupFlush::Sorted, &params, &options);
+        let output: Vec<KeyedConsensus<String>> = groups.by_ref().collect();
+
+        let keys: Vec<(&str, usize, bool)> = output.iter().map(|g| (g.key.as_str(), g.reads, g.passed_through)).collect();
+        assert_eq!(keys, vec![("a", 2, false), ("b", 1, true), ("c", 1, true), ("a", 1, true)]);
+        assert_eq!(output[0].annotations.reads_used, 2);
+        assert_eq!(groups.summary(), GroupSummary { groups: 4, built: 1, skipped: 0, passed_through: 3 });
+    }
+
+    #[test]
+    fn test_group_by_window() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let records = keyed(&["a:1", "b:1", "a:2", "c:1", "b:2", "d:1", "e:1", "a:3"]);
+        let groups = group_consensus_by(records, umi, GroupFlush::Window(2), &params, &GroupOptions::new());
+
+        // a group closes once two records of other keys follow its last one, so "b:2" starts a new group
+        let keys: Vec<(String, usize)> = groups.map(|g| (g.key, g.reads)).collect();
+        let expected = vec![("b", 1), ("a", 2), ("c", 1), ("b", 1), ("d", 1), ("e", 1), ("a", 1)];
+        assert_eq!(keys, expected.into_iter().map(|(k, n)| (k.to_string(), n)).collect::<Vec<_>>());
+    }
+}
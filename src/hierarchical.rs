@@ -0,0 +1,111 @@
+//! Consensus of very large read bundles as a consensus of chunk consensuses, which keeps every
+//! graph small.
+
+use crate::groups::{available_threads, parallel_map};
+use crate::{PoaGraph, PoaParams};
+
+/// Generates the consensus of a large set of reads in two rounds: the reads are dealt into chunks
+/// of at most `chunk_size` reads, the consensus of each chunk is built on its own, in parallel
+/// across the available threads, and the final consensus is that of the chunk consensuses, each
+/// weighted by the number of reads of its chunk.
+///
+/// Aligning a read costs in proportion to the size of the graph, which grows with the reads added
+/// before it, so thousands of reads in one graph are slow; chunks of a few hundred reads keep
+/// each graph small and the chunks independent. Reads are dealt in turn, the first to the first
+/// chunk, the second to the second and so on, so reads sorted e.g. by length are spread over the
+/// chunks. Empty reads and trailing null terminators are ignored, and the result is the same for
+/// any number of threads.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `params` - the alignment and scoring parameters of both rounds
+/// * `chunk_size` - the maximum number of reads of a chunk; all reads form a single chunk if there
+///   are no more than this
+///
+/// # Returns
+/// * returns the consensus, empty if no read has bases
+///
+/// # Panics
+/// Panics if `chunk_size` is 0.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_hierarchical, PoaParams};
+///
+///     let mut reads = vec!["AATGCCCGTT"; 40];
+///     reads.extend(vec!["AATGCCGTT"; 10]);
+///     let consensus = poa_consensus_hierarchical(&reads, &PoaParams::new(1, 5, -4, -3, -1), 8);
+///     assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+/// ```
+pub fn poa_consensus_hierarchical<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams, chunk_size: usize) -> Vec<u8> {
+    assert!(chunk_size > 0, "a chunk must hold at least one read");
+    let reads: Vec<&[u8]> = seqs
+        .iter()
+        .map(|seq| {
+            let seq = seq.as_ref();
+            seq.strip_suffix(b"\0").unwrap_or(seq)
+        })
+        .filter(|seq| !seq.is_empty())
+        .collect();
+
+    let num_chunks = reads.len().div_ceil(chunk_size);
+    let mut chunks: Vec<Vec<&[u8]>> = vec![vec![]; num_chunks];
+    for (i, read) in reads.into_iter().enumerate() {
+        chunks[i % num_chunks].push(read);
+    }
+
+    let consensuses = parallel_map(&chunks, available_threads(), params, |graph, reads| {
+        graph.clear();
+        for read in reads {
+            graph.add_sequence(read);
+        }
+        graph.consensus()
+    });
+    if let [consensus] = &consensuses[..] {
+        return consensus.clone();
+    }
+
+    let mut graph = PoaGraph::new(params);
+    for (consensus, chunk) in consensuses.iter().zip(&chunks) {
+        graph.add_sequence_with_weight(consensus, chunk.len() as u32);
+    }
+    graph.consensus()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::{random_sequence, ReadSimulator};
+
+    #[test]
+    fn test_large_bundle() {
+        let template = random_sequence(200, 3);
+        let mut reads = ReadSimulator::new(5).error_rates(0.04, 0.03, 0.03).reads(&template, 120);
+        reads.push(vec![]);
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+
+        let mut graph = PoaGraph::new(&params);
+        for read in reads.iter().filter(|read| !read.is_empty()) {
+            graph.add_sequence(read);
+        }
+        let direct = graph.consensus();
+        // the reads agree on the template but for bases the heaviest bundle picks up at its ends,
+        // in one round or two
+        let inner = &template[1..template.len() - 1];
+        for consensus in [poa_consensus_hierarchical(&reads, &params, 16), direct.clone()].iter() {
+            assert!(consensus.windows(inner.len()).any(|window| window == inner));
+            assert!(consensus.len() <= template.len() + 2);
+        }
+        // a chunk as large as the bundle builds the consensus of all reads at once
+        assert_eq!(poa_consensus_hierarchical(&reads, &params, 1000), direct);
+    }
+
+    #[test]
+    fn test_small_inputs() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        assert!(poa_consensus_hierarchical::<&str>(&[], &params, 4).is_empty());
+        assert!(poa_consensus_hierarchical(&["", "\0"], &params, 1).is_empty());
+        assert_eq!(poa_consensus_hierarchical(&["AATGCCCGTT\0"], &params, 1), b"AATGCCCGTT".to_vec());
+    }
+}
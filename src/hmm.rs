@@ -0,0 +1,445 @@
+//! Polishing a consensus with a pair hidden Markov model of the reads.
+//!
+//! Each read is modelled as emitted from the consensus by a pair HMM whose match emissions follow
+//! the base qualities of the read. The consensus is changed by single-base substitutions,
+//! deletions and insertions for as long as they raise the summed likelihood of the reads, with
+//! every candidate edit scored from the forward and backward matrices of the current consensus.
+
+use crate::SeqRecord;
+
+const BASES: &[u8] = b"ACGT";
+
+/// Options for `hmm_polish`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HmmPolishOptions {
+    /// the probability of a read base inserted relative to the consensus, at each step
+    pub insertion_rate: f64,
+    /// the probability of a consensus base missing from a read, at each step
+    pub deletion_rate: f64,
+    /// the error probability of read bases without a quality
+    pub default_error_rate: f64,
+    /// the maximum number of rounds of edits
+    pub max_rounds: usize,
+    /// the minimum distance between consensus positions edited in the same round
+    pub min_edit_spacing: usize,
+}
+
+impl Default for HmmPolishOptions {
+    fn default() -> HmmPolishOptions {
+        HmmPolishOptions {
+            insertion_rate: 0.05,
+            deletion_rate: 0.05,
+            default_error_rate: 0.05,
+            max_rounds: 10,
+            min_edit_spacing: 4,
+        }
+    }
+}
+
+impl HmmPolishOptions {
+    /// Creates the default options, suited to reads with a few percent of errors.
+    pub fn new() -> HmmPolishOptions {
+        HmmPolishOptions::default()
+    }
+
+    /// Sets the probabilities of inserted and deleted read bases.
+    pub fn indel_rates(mut self, insertion_rate: f64, deletion_rate: f64) -> HmmPolishOptions {
+        self.insertion_rate = insertion_rate;
+        self.deletion_rate = deletion_rate;
+        self
+    }
+
+    /// Sets the error probability of read bases without a quality.
+    pub fn default_error_rate(mut self, default_error_rate: f64) -> HmmPolishOptions {
+        self.default_error_rate = default_error_rate;
+        self
+    }
+
+    /// Sets the maximum number of rounds of edits.
+    pub fn max_rounds(mut self, max_rounds: usize) -> HmmPolishOptions {
+        self.max_rounds = max_rounds;
+        self
+    }
+}
+
+/// The outcome of `hmm_polish`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HmmPolished {
+    /// the polished consensus
+    pub consensus: Vec<u8>,
+    /// the number of single-base edits applied
+    pub edits: usize,
+    /// the number of rounds run
+    pub rounds: usize,
+    /// the summed log-likelihood of the reads given the polished consensus
+    pub log_likelihood: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Edit {
+    Substitute(usize, u8),
+    Delete(usize),
+    Insert(usize, u8),
+}
+
+impl Edit {
+    fn position(&self) -> usize {
+        match *self {
+            Edit::Substitute(j, _) | Edit::Delete(j) | Edit::Insert(j, _) => j,
+        }
+    }
+
+    fn apply(&self, template: &mut Vec<u8>) {
+        match *self {
+            Edit::Substitute(j, base) => template[j] = base,
+            Edit::Delete(j) => {
+                template.remove(j);
+            }
+            Edit::Insert(j, base) => template.insert(j, base),
+        }
+    }
+}
+
+// a read prepared for the model: its bases and the error probability of each
+struct ModelRead {
+    bases: Vec<u8>,
+    errors: Vec<f64>,
+    weight: f64,
+}
+
+struct Model {
+    insertion: f64,
+    deletion: f64,
+    diagonal: f64,
+}
+
+impl Model {
+    // the probability of emitting read base i through a match with template base t
+    fn emit(&self, read: &ModelRead, i: usize, t: u8) -> f64 {
+        let error = read.errors[i];
+        self.diagonal * if read.bases[i] == t { 1.0 - error } else { error / 3.0 }
+    }
+}
+
+// the forward and backward matrices of one read against the template, stored by template column
+// and scaled per column; the natural log of each column's scale is kept alongside
+struct Matrices {
+    rows: usize,
+    forward: Vec<f64>,
+    forward_scale: Vec<f64>,
+    backward: Vec<f64>,
+    backward_scale: Vec<f64>,
+}
+
+fn normalize(column: &mut [f64]) -> f64 {
+    let max = column.iter().cloned().fold(0.0, f64::max);
+    if max > 0.0 {
+        column.iter_mut().for_each(|x| *x /= max);
+        max.ln()
+    } else {
+        0.0
+    }
+}
+
+impl Matrices {
+    fn column(&self, k: usize) -> &[f64] {
+        &self.forward[k * self.rows..(k + 1) * self.rows]
+    }
+
+    fn backward_column(&self, k: usize) -> &[f64] {
+        &self.backward[k * self.rows..(k + 1) * self.rows]
+    }
+
+    fn log_likelihood(&self, len: usize) -> f64 {
+        self.column(len)[self.rows - 1].ln() + self.forward_scale[len]
+    }
+}
+
+// computes the next forward column from the scaled column `previous` and template base `t`
+fn next_column(model: &Model, read: &ModelRead, previous: &[f64], t: u8) -> Vec<f64> {
+    let mut column = vec![0.0; previous.len()];
+    column[0] = previous[0] * model.deletion;
+    for i in 1..previous.len() {
+        column[i] = previous[i - 1] * model.emit(read, i - 1, t) + previous[i] * model.deletion + column[i - 1] * model.insertion;
+    }
+    column
+}
+
+fn forward(model: &Model, read: &ModelRead, template: &[u8]) -> (Vec<f64>, Vec<f64>) {
+    let rows = read.bases.len() + 1;
+    let mut forward = Vec::with_capacity(rows * (template.len() + 1));
+    let mut scale = Vec::with_capacity(template.len() + 1);
+
+    let mut column = vec![1.0; rows];
+    for i in 1..rows {
+        column[i] = column[i - 1] * model.insertion;
+    }
+    scale.push(normalize(&mut column));
+    forward.extend_from_slice(&column);
+
+    for (k, &t) in template.iter().enumerate() {
+        let mut column = next_column(model, read, &forward[k * rows..(k + 1) * rows], t);
+        scale.push(scale[k] + normalize(&mut column));
+        forward.extend_from_slice(&column);
+    }
+
+    (forward, scale)
+}
+
+fn matrices(model: &Model, read: &ModelRead, template: &[u8]) -> Matrices {
+    let rows = read.bases.len() + 1;
+    let len = template.len();
+    let (forward, forward_scale) = forward(model, read, template);
+
+    let mut backward = vec![0.0; rows * (len + 1)];
+    let mut backward_scale = vec![0.0; len + 1];
+
+    let last = &mut backward[len * rows..];
+    last[rows - 1] = 1.0;
+    for i in (0..rows - 1).rev() {
+        last[i] = last[i + 1] * model.insertion;
+    }
+    backward_scale[len] = normalize(last);
+
+    for k in (0..len).rev() {
+        let (current, next) = backward[k * rows..].split_at_mut(rows);
+        current[rows - 1] = next[rows - 1] * model.deletion;
+        for i in (0..rows - 1).rev() {
+            current[i] = current[i + 1] * model.insertion + next[i] * model.deletion + next[i + 1] * model.emit(read, i, template[k]);
+        }
+        backward_scale[k] = backward_scale[k + 1] + normalize(current);
+    }
+
+    Matrices { rows, forward, forward_scale, backward, backward_scale }
+}
+
+// the log-likelihood of the read, given a forward column (scaled by `log_scale`) that ends just
+// before template base `k`, followed by the unchanged template from `k` on
+fn join(model: &Model, read: &ModelRead, matrices: &Matrices, template: &[u8], column: &[f64], log_scale: f64, k: usize) -> f64 {
+    let rows = matrices.rows;
+    if k == template.len() {
+        return column[rows - 1].ln() + log_scale;
+    }
+
+    let next = matrices.backward_column(k + 1);
+    let mut total = column[rows - 1] * model.deletion * next[rows - 1];
+    for i in 0..rows - 1 {
+        total += column[i] * (model.deletion * next[i] + model.emit(read, i, template[k]) * next[i + 1]);
+    }
+    total.ln() + log_scale + matrices.backward_scale[k + 1]
+}
+
+// the log-likelihood of the read given the template with `edit` applied
+fn edited_log_likelihood(model: &Model, read: &ModelRead, matrices: &Matrices, template: &[u8], edit: Edit) -> f64 {
+    match edit {
+        Edit::Substitute(j, base) => {
+            let column = next_column(model, read, matrices.column(j), base);
+            join(model, read, matrices, template, &column, matrices.forward_scale[j], j + 1)
+        }
+        Edit::Delete(j) => join(model, read, matrices, template, matrices.column(j), matrices.forward_scale[j], j + 1),
+        Edit::Insert(j, base) => {
+            let column = next_column(model, read, matrices.column(j), base);
+            join(model, read, matrices, template, &column, matrices.forward_scale[j], j)
+        }
+    }
+}
+
+fn candidate_edits(template: &[u8]) -> Vec<Edit> {
+    let mut edits = vec![];
+    for j in 0..=template.len() {
+        if j < template.len() {
+            edits.extend(BASES.iter().filter(|&&base| base != template[j]).map(|&base| Edit::Substitute(j, base)));
+            edits.push(Edit::Delete(j));
+        }
+        // inserting the base of the run that follows is the same as inserting it after the run
+        let follows = template.get(j).cloned();
+        edits.extend(BASES.iter().filter(|&&base| Some(base) != follows).map(|&base| Edit::Insert(j, base)));
+    }
+    edits
+}
+
+fn total_log_likelihood(model: &Model, reads: &[ModelRead], template: &[u8]) -> f64 {
+    reads
+        .iter()
+        .map(|read| {
+            let (forward, scale) = forward(model, read, template);
+            let rows = read.bases.len() + 1;
+            read.weight * (forward[template.len() * rows + rows - 1].ln() + scale[template.len()])
+        })
+        .sum()
+}
+
+/// Polishes a consensus with a pair HMM of the reads, correcting errors left by the partial order alignment.
+///
+/// The reads must span the whole consensus on its strand, as in global mode. Read qualities set
+/// the error probability of each read base, and read weights scale their contributions. The
+/// topology of the graph is not consulted: each round scores every single-base edit against
+/// every read in time proportional to the read length times the consensus length, applies the
+/// best well-separated edits that raise the summed likelihood, and stops when none does.
+/// Only the bases A, C, G and T are considered for substitutions and insertions.
+/// # Arguments
+///
+/// * `consensus` - the consensus to polish, e.g. from `PoaGraph::consensus`
+/// * `reads` - the reads the consensus was built from; empty reads are left out
+/// * `options` - the model parameters and the number of rounds
+///
+/// # Returns
+/// * returns the polished consensus with the number of edits applied
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{hmm_polish, HmmPolishOptions, SeqRecord};
+///
+///     let reads: Vec<SeqRecord> = ["AATGCCCGTT", "AATGCCCGTT", "AATGCCGTT", "AATGCCCGTT"]
+///         .iter()
+///         .map(|read| SeqRecord::from(read.as_bytes()))
+///         .collect();
+///     let polished = hmm_polish(b"AATGCCGTT", &reads, &HmmPolishOptions::new());
+///
+///     assert_eq!(polished.consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(polished.edits, 1);
+/// ```
+pub fn hmm_polish(consensus: &[u8], reads: &[SeqRecord], options: &HmmPolishOptions) -> HmmPolished {
+    let model = Model {
+        insertion: options.insertion_rate * 0.25,
+        deletion: options.deletion_rate,
+        diagonal: 1.0 - options.insertion_rate - options.deletion_rate,
+    };
+    let reads: Vec<ModelRead> = reads
+        .iter()
+        .filter(|read| !read.seq.is_empty())
+        .map(|read| {
+            let errors = match &read.qual {
+                Some(qual) => qual.iter().map(|&q| 10f64.powf(-f64::from(q.saturating_sub(33)) / 10.0)).collect(),
+                None => vec![options.default_error_rate; read.seq.len()],
+            };
+            ModelRead {
+                bases: read.seq.to_ascii_uppercase(),
+                errors: errors.into_iter().map(|e: f64| e.clamp(1e-5, 0.75)).collect(),
+                weight: f64::from(read.weight.unwrap_or(1.0)),
+            }
+        })
+        .collect();
+
+    let mut template = consensus.to_ascii_uppercase();
+    let mut log_likelihood = total_log_likelihood(&model, &reads, &template);
+    let (mut edits, mut rounds) = (0, 0);
+
+    while rounds < options.max_rounds && !template.is_empty() && !reads.is_empty() {
+        rounds += 1;
+
+        let candidates = candidate_edits(&template);
+        let mut gains = vec![0.0; candidates.len()];
+        for read in &reads {
+            let matrices = matrices(&model, read, &template);
+            let current = matrices.log_likelihood(template.len());
+            for (gain, &edit) in gains.iter_mut().zip(&candidates) {
+                *gain += read.weight * (edited_log_likelihood(&model, read, &matrices, &template, edit) - current);
+            }
+        }
+
+        let mut improving: Vec<(f64, Edit)> = gains.into_iter().zip(candidates).filter(|&(gain, _)| gain > 1e-9).collect();
+        if improving.is_empty() {
+            break;
+        }
+        improving.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then(a.1.position().cmp(&b.1.position())));
+
+        let mut chosen: Vec<Edit> = vec![];
+        for &(_, edit) in &improving {
+            if chosen.iter().all(|other| other.position().abs_diff(edit.position()) >= options.min_edit_spacing.max(1)) {
+                chosen.push(edit);
+            }
+        }
+        chosen.sort_by_key(|edit| std::cmp::Reverse(edit.position()));
+
+        let mut edited = template.clone();
+        chosen.iter().for_each(|edit| edit.apply(&mut edited));
+        let mut edited_log_likelihood = total_log_likelihood(&model, &reads, &edited);
+
+        // edits scored one at a time may not combine well; the best one alone always helps
+        if chosen.len() > 1 && edited_log_likelihood <= log_likelihood {
+            chosen.truncate(0);
+            chosen.push(improving[0].1);
+            edited = template.clone();
+            improving[0].1.apply(&mut edited);
+            edited_log_likelihood = total_log_likelihood(&model, &reads, &edited);
+        }
+
+        edits += chosen.len();
+        template = edited;
+        log_likelihood = edited_log_likelihood;
+    }
+
+    HmmPolished { consensus: template, edits, rounds, log_likelihood }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::align::{align, edit_distance};
+    use crate::rng::Rng;
+    use crate::{PoaGraph, PoaParams};
+
+    // introduces substitutions, insertions and deletions at the given rate
+    fn mutate(rng: &mut Rng, seq: &[u8], per_thousand: usize) -> Vec<u8> {
+        let mut mutated = vec![];
+        for &base in seq {
+            match rng.below(1000 * 3 / per_thousand.max(1)) {
+                0 => mutated.push(BASES[rng.below(4)]),
+                1 => {}
+                2 => mutated.extend_from_slice(&[base, BASES[rng.below(4)]]),
+                _ => mutated.push(base),
+            }
+        }
+        mutated
+    }
+
+    #[test]
+    fn test_edited_likelihoods_match_recomputation() {
+        let model = Model { insertion: 0.05 * 0.25, deletion: 0.05, diagonal: 0.9 };
+        let read = ModelRead { bases: b"ACGGTAC".to_vec(), errors: vec![0.05; 7], weight: 1.0 };
+        let template = b"ACGTTAC".to_vec();
+        let matrices = matrices(&model, &read, &template);
+
+        let reference = |template: &[u8]| total_log_likelihood(&model, std::slice::from_ref(&read), template);
+        assert!((matrices.log_likelihood(template.len()) - reference(&template)).abs() < 1e-9);
+
+        for edit in candidate_edits(&template) {
+            let mut edited = template.clone();
+            edit.apply(&mut edited);
+            let expected = reference(&edited);
+            assert!((edited_log_likelihood(&model, &read, &matrices, &template, edit) - expected).abs() < 1e-9, "{:?}", edit);
+        }
+    }
+
+    #[test]
+    fn test_qualities_decide_between_reads() {
+        let reads = vec![
+            SeqRecord::new("r1", b"AACGTCA".to_vec()).qual(b"IIIIIII".to_vec()),
+            SeqRecord::new("r2", b"AACTTCA".to_vec()).qual(b"III#III".to_vec()),
+        ];
+        let polished = hmm_polish(b"AACTTCA", &reads, &HmmPolishOptions::new());
+
+        assert_eq!(polished.consensus, b"AACGTCA".to_vec());
+        assert!(hmm_polish(b"", &reads, &HmmPolishOptions::new()).consensus.is_empty());
+    }
+
+    #[test]
+    fn test_polishing_reduces_errors() {
+        let mut rng = Rng::new(11);
+        let truth: Vec<u8> = (0..300).map(|_| BASES[rng.below(4)]).collect();
+        let reads: Vec<Vec<u8>> = (0..4).map(|_| mutate(&mut rng, &truth, 150)).collect();
+
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+        reads.iter().for_each(|read| graph.add_sequence(read));
+        let consensus = graph.consensus();
+
+        let records: Vec<SeqRecord> = reads.into_iter().map(SeqRecord::from).collect();
+        let polished = hmm_polish(&consensus, &records, &HmmPolishOptions::new());
+
+        let before = edit_distance(&align(&consensus, &truth));
+        let after = edit_distance(&align(&polished.consensus, &truth));
+        assert!(after < before, "{} errors after polishing, {} before", after, before);
+    }
+}
@@ -0,0 +1,421 @@
+//! Reading sequences from FASTA and FASTQ files or packed storage, and writing consensus records.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::{PoaGraph, PoaParams, SeqRecord};
+
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "bio")]
+mod bio;
+mod dedup;
+mod faidx;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod packed;
+mod sam;
+mod write;
+
+#[cfg(feature = "arrow")]
+pub use self::arrow::{arrow_groups, poa_consensus_of_arrow, ArrowColumns};
+#[cfg(feature = "bio")]
+pub use self::bio::{poa_consensus_of_fasta, poa_consensus_of_fastq};
+pub use self::dedup::{DedupFasta, DedupSize};
+pub use self::faidx::{FaiEntry, FastaIndex, IndexedFasta};
+#[cfg(feature = "mmap")]
+pub use self::mmap::{FastaRecord, MappedFasta};
+pub use self::packed::{try_poa_consensus_packed, PackedSeq, Packing};
+#[cfg(feature = "noodles")]
+pub use self::sam::write_bam;
+pub use self::sam::{write_sam, write_sam_alignments};
+pub use self::write::{write_fasta_record, write_fastq_record, ConsensusAnnotations};
+
+// the first bytes of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// buffers a reader, decompressing its input if it starts with the gzip magic bytes
+fn buffered<R: Read + 'static>(reader: R) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(reader);
+
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        return gzip_reader(reader);
+    }
+    Ok(Box::new(reader))
+}
+
+// opens a file for buffered reading, decompressing it if it is gzip-compressed
+fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    buffered(File::open(path)?)
+}
+
+#[cfg(feature = "gzip")]
+fn gzip_reader<R: Read + 'static>(reader: R) -> io::Result<Box<dyn BufRead>> {
+    Ok(Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(reader))))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip_reader<R: Read + 'static>(_reader: R) -> io::Result<Box<dyn BufRead>> {
+    Err(invalid_data("reading gzip-compressed input requires the `gzip` feature"))
+}
+
+// reads the next line without its line ending, returning false at the end of the input
+fn read_line<R: BufRead>(reader: &mut R, line: &mut Vec<u8>) -> io::Result<bool> {
+    line.clear();
+    if reader.read_until(b'\n', line)? == 0 {
+        return Ok(false);
+    }
+    while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(true)
+}
+
+// returns the record name of a header line without its leading '>' or '@', i.e. the header up to
+// the first whitespace
+fn record_name(header: &[u8]) -> String {
+    let name = header[1..].split(|c| c.is_ascii_whitespace()).next().unwrap_or(&[]);
+    String::from_utf8_lossy(name).into_owned()
+}
+
+/// Reads the records of a FASTA or FASTQ file, with the qualities of FASTQ records.
+///
+/// The format is detected from the first record. FASTA sequences may span several lines.
+fn parse_records<R: BufRead>(mut reader: R) -> io::Result<Vec<SeqRecord>> {
+    let mut seqs: Vec<SeqRecord> = vec![];
+    let mut line = vec![];
+
+    // skip leading blank lines to find the first header
+    loop {
+        if !read_line(&mut reader, &mut line)? {
+            return Ok(seqs);
+        }
+        if !line.is_empty() {
+            break;
+        }
+    }
+
+    match line[0] {
+        b'>' => {
+            seqs.push(SeqRecord::new(record_name(&line), vec![]));
+            while read_line(&mut reader, &mut line)? {
+                if line.first() == Some(&b'>') {
+                    seqs.push(SeqRecord::new(record_name(&line), vec![]));
+                } else {
+                    seqs.last_mut().unwrap().seq.extend_from_slice(&line);
+                }
+            }
+            Ok(seqs)
+        }
+        b'@' => {
+            loop {
+                let name = record_name(&line);
+                let mut seq = vec![];
+                loop {
+                    if !read_line(&mut reader, &mut line)? {
+                        return Err(invalid_data("truncated FASTQ record"));
+                    }
+                    if line.first() == Some(&b'+') {
+                        break;
+                    }
+                    seq.extend_from_slice(&line);
+                }
+
+                // the quality string has the length of the sequence, and may begin with '@'
+                let mut qual = vec![];
+                while qual.len() < seq.len() {
+                    if !read_line(&mut reader, &mut line)? {
+                        return Err(invalid_data("truncated FASTQ record"));
+                    }
+                    qual.extend_from_slice(&line);
+                }
+                if qual.len() != seq.len() {
+                    return Err(invalid_data("FASTQ quality length differs from sequence length"));
+                }
+                seqs.push(SeqRecord::new(name, seq).qual(qual));
+
+                // find the next header
+                loop {
+                    if !read_line(&mut reader, &mut line)? {
+                        return Ok(seqs);
+                    }
+                    if !line.is_empty() {
+                        break;
+                    }
+                }
+                if line[0] != b'@' {
+                    return Err(invalid_data("FASTQ record does not start with '@'"));
+                }
+            }
+        }
+        _ => Err(invalid_data("input is neither FASTA nor FASTQ")),
+    }
+}
+
+/// Reads the names and sequences of the records of a FASTA or FASTQ file, without null terminators.
+pub(crate) fn read_records<R: BufRead>(reader: R) -> io::Result<Vec<(String, Vec<u8>)>> {
+    Ok(parse_records(reader)?.into_iter().map(|record| (record.name, record.seq)).collect())
+}
+
+/// Reads the sequences of the records of a FASTA or FASTQ file, without null terminators.
+pub(crate) fn read_sequences<R: BufRead>(reader: R) -> io::Result<Vec<Vec<u8>>> {
+    Ok(read_records(reader)?.into_iter().map(|(_, seq)| seq).collect())
+}
+
+/// Reads the names and sequences of the records of a FASTA or FASTQ file, which may be gzip-compressed.
+pub(crate) fn read_records_from_path(path: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+    read_records(open(path)?)
+}
+
+/// Reads the records of a FASTA or FASTQ file, which may be gzip-compressed (with the `gzip` feature).
+///
+/// FASTQ records keep their quality strings, so they can be added to a graph weighted by quality.
+pub fn read_seq_records<P: AsRef<Path>>(path: P) -> io::Result<Vec<SeqRecord>> {
+    parse_records(open(path.as_ref())?)
+}
+
+/// Reads the records of FASTA or FASTQ input from a reader, such as standard input, as
+/// `read_seq_records` reads them from a file.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::read_seq_records_from;
+///
+///     let records = read_seq_records_from(&b"@r1\nACGT\n+\nIIII\n"[..]).unwrap();
+///     assert_eq!(records[0].name, "r1");
+///     assert_eq!(records[0].qual, Some(b"IIII".to_vec()));
+/// ```
+pub fn read_seq_records_from<R: Read + 'static>(reader: R) -> io::Result<Vec<SeqRecord>> {
+    parse_records(buffered(reader)?)
+}
+
+/// Generates the consensus of all records of a FASTA or FASTQ file.
+///
+/// The file may be gzip-compressed (with the `gzip` feature). Records are added to the
+/// graph in file order; quality strings are not used.
+/// # Arguments
+///
+/// * `path` - the path of the FASTA or FASTQ file
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the records, or an error if the file cannot be read or parsed
+///
+/// # Examples
+///
+/// ```no_run
+///     use rust_spoa::{poa_consensus_from_path, PoaParams};
+///
+///     let consensus = poa_consensus_from_path("family_1.fastq.gz", &PoaParams::new(1, 5, -4, -3, -1)).unwrap();
+/// ```
+pub fn poa_consensus_from_path<P: AsRef<Path>>(path: P, params: &PoaParams) -> io::Result<Vec<u8>> {
+    let seqs = read_sequences(open(path.as_ref())?)?;
+
+    let mut graph = PoaGraph::new(params);
+    for seq in seqs.iter().filter(|seq| !seq.is_empty()) {
+        graph.add_sequence(seq);
+    }
+
+    Ok(graph.consensus())
+}
+
+/// The consensus of the records of a file, with statistics on the records it was built from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileConsensus {
+    /// the consensus of the records
+    pub consensus: Vec<u8>,
+    /// the number of records added to the graph, i.e. those with bases
+    pub num_reads: usize,
+    /// the number of records without bases, which were skipped
+    pub num_empty: usize,
+    /// the summed length of the records added
+    pub num_bases: usize,
+    /// the mean number of records covering a consensus base, 0 for an empty consensus
+    pub mean_coverage: f64,
+}
+
+// adds the records of a FASTA or FASTQ file to a graph in file order, weighting the bases of
+// FASTQ records by quality if use_qualities is set, and failing if the file is not in the format
+// the caller expects
+fn file_consensus(path: &Path, params: &PoaParams, fastq: bool, use_qualities: bool) -> io::Result<FileConsensus> {
+    let records = parse_records(open(path)?)?;
+    if records.iter().any(|record| record.qual.is_some() != fastq) {
+        return Err(invalid_data(if fastq { "input is FASTA, not FASTQ" } else { "input is FASTQ, not FASTA" }));
+    }
+
+    let mut graph = PoaGraph::new(params);
+    let (mut num_empty, mut num_bases) = (0, 0);
+    for record in &records {
+        if record.seq.is_empty() {
+            num_empty += 1;
+        } else if use_qualities {
+            graph.add_record(record);
+        } else {
+            graph.add_sequence(&record.seq);
+        }
+        num_bases += record.seq.len();
+    }
+
+    let nodes = graph.nodes();
+    let path = graph.consensus_path();
+    let covered: u64 = path.iter().map(|&id| u64::from(nodes[id as usize].coverage)).sum();
+    Ok(FileConsensus {
+        consensus: graph.consensus(),
+        num_reads: records.len() - num_empty,
+        num_empty,
+        num_bases,
+        mean_coverage: if path.is_empty() { 0.0 } else { covered as f64 / path.len() as f64 },
+    })
+}
+
+/// Generates the consensus of all records of a FASTA file, with statistics on the records.
+///
+/// The file may be gzip-compressed (with the `gzip` feature). Records are added to the graph in
+/// file order, skipping those without bases.
+/// # Arguments
+///
+/// * `path` - the path of the FASTA file
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus and statistics, or an error if the file cannot be read or parsed, or is
+///   a FASTQ file
+///
+/// # Examples
+///
+/// ```no_run
+///     use rust_spoa::{poa_consensus_from_fasta, PoaParams};
+///
+///     let family = poa_consensus_from_fasta("family_1.fa", &PoaParams::new(1, 5, -4, -3, -1)).unwrap();
+///     println!("{} reads, mean coverage {:.1}", family.num_reads, family.mean_coverage);
+/// ```
+pub fn poa_consensus_from_fasta<P: AsRef<Path>>(path: P, params: &PoaParams) -> io::Result<FileConsensus> {
+    file_consensus(path.as_ref(), params, false, false)
+}
+
+/// Generates the consensus of all records of a FASTQ file, with statistics on the records.
+///
+/// The file may be gzip-compressed (with the `gzip` feature). Records are added to the graph in
+/// file order, skipping those without bases.
+/// # Arguments
+///
+/// * `path` - the path of the FASTQ file
+/// * `params` - the alignment and scoring parameters
+/// * `use_qualities` - whether to weight each base by its Phred quality, as `PoaGraph::add_record` does
+///
+/// # Returns
+/// * returns the consensus and statistics, or an error if the file cannot be read or parsed, or is
+///   a FASTA file
+///
+/// # Examples
+///
+/// ```no_run
+///     use rust_spoa::{poa_consensus_from_fastq, PoaParams};
+///
+///     let family = poa_consensus_from_fastq("family_1.fastq.gz", &PoaParams::new(1, 5, -4, -3, -1), true).unwrap();
+/// ```
+pub fn poa_consensus_from_fastq<P: AsRef<Path>>(path: P, params: &PoaParams, use_qualities: bool) -> io::Result<FileConsensus> {
+    file_consensus(path.as_ref(), params, true, use_qualities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("rust_spoa_{}_{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_fasta() {
+        let fasta = b">r1 first\nAATG\nCCCGTT\n\n>r2\r\nAATGCCGTT\r\n>r3\n";
+
+        assert_eq!(read_sequences(&fasta[..]).unwrap(), vec![
+            b"AATGCCCGTT".to_vec(),
+            b"AATGCCGTT".to_vec(),
+            vec![],
+        ]);
+        assert!(read_sequences(&b""[..]).unwrap().is_empty());
+        assert_eq!(read_records(&fasta[..]).unwrap()[0].0, "r1");
+    }
+
+    #[test]
+    fn test_read_fastq() {
+        let fastq = b"@r1\nAATGCCCGTT\n+\n@@@@@IIIII\n@r2\nAATGCCGTT\n+r2\nIIIIIIIII\n";
+
+        assert_eq!(read_sequences(&fastq[..]).unwrap(), vec![b"AATGCCCGTT".to_vec(), b"AATGCCGTT".to_vec()]);
+        assert!(read_sequences(&b"@r1\nACGT\n+\nII\n"[..]).is_err());
+        assert!(read_sequences(&b"ACGT\n"[..]).is_err());
+        assert_eq!(parse_records(&fastq[..]).unwrap()[0].qual, Some(b"@@@@@IIIII".to_vec()));
+    }
+
+    #[test]
+    fn test_consensus_from_path() {
+        let mut fasta = vec![];
+        for (i, seq) in ["ATTGCCCGTT", "AATGCCGTT", "AATGCCCGAT", "AACGCCCGTC", "AGTGCTCGTT", "AATGCTCGTT"].iter().enumerate() {
+            fasta.extend_from_slice(format!(">read{}\n{}\n", i, seq).as_bytes());
+        }
+        let path = write_temp("family.fa", &fasta);
+
+        let consensus = poa_consensus_from_path(&path, &PoaParams::new(1, 5, -4, -3, -1)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+    }
+
+    #[test]
+    fn test_consensus_from_fasta_and_fastq() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let fasta = write_temp("stats.fa", b">r1\nAACGT\n>r2\n\n>r3\nAACGT\n>r4\nACCGT\n");
+        let family = poa_consensus_from_fasta(&fasta, &params).unwrap();
+        assert_eq!(family.consensus, b"AACGT".to_vec());
+        assert_eq!((family.num_reads, family.num_empty, family.num_bases), (3, 1, 15));
+        // the second consensus base is not covered by the third read
+        assert!((family.mean_coverage - 2.8).abs() < 1e-9);
+        assert_eq!(poa_consensus_from_fastq(&fasta, &params, false).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // the high-quality minority wins when qualities weight the bases
+        let fastq = write_temp("stats.fq", b"@r1\nAACGT\n+\n#####\n@r2\nAACGT\n+\n#####\n@r3\nACCGT\n+\nIIIII\n");
+        assert_eq!(poa_consensus_from_fastq(&fastq, &params, false).unwrap().consensus, b"AACGT".to_vec());
+        assert_eq!(poa_consensus_from_fastq(&fastq, &params, true).unwrap().consensus, b"ACCGT".to_vec());
+        assert!(poa_consensus_from_fasta(&fastq, &params).is_err());
+
+        let empty = write_temp("stats_empty.fa", b"");
+        let family = poa_consensus_from_fasta(&empty, &params).unwrap();
+        assert!(family.consensus.is_empty() && family.num_reads == 0 && family.mean_coverage == 0.0);
+        for path in [fasta, fastq, empty].iter() {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_consensus_from_gzipped_path() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(b"@r1\nAACGT\n+\nIIIII\n@r2\nAACGT\n+\nIIIII\n@r3\nACCGT\n+\nIIIII\n").unwrap();
+        let path = write_temp("family.fq.gz", &encoder.finish().unwrap());
+
+        let consensus = poa_consensus_from_path(&path, &PoaParams::new(1, 5, -4, -3, -1)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(consensus, b"AACGT".to_vec());
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn test_gzipped_path_requires_feature() {
+        let path = write_temp("family_no_gzip.fa.gz", &[0x1f, 0x8b, 0x08, 0x00]);
+
+        let result = poa_consensus_from_path(&path, &PoaParams::new(1, 5, -4, -3, -1));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,243 @@
+//! Reading read groups from Apache Arrow record batches, as produced by Spark or Polars.
+
+use std::collections::HashMap;
+use std::io;
+
+use arrow_array::{
+    Array, ArrayRef, BinaryArray, Int32Array, Int64Array, LargeBinaryArray, LargeStringArray, RecordBatch, StringArray, UInt32Array,
+    UInt64Array,
+};
+
+use super::invalid_data;
+use crate::{poa_consensus_of_groups, GroupConsensus, GroupOptions, GroupSummary, PoaParams, SeqRecord};
+
+/// The names of the columns holding the group id, sequence and quality string of each read.
+///
+/// The group id column may hold strings or integers, and the sequence and quality columns strings
+/// or binary values. The quality column is optional; a null quality leaves that read unweighted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArrowColumns {
+    /// the column of group ids
+    pub group: String,
+    /// the column of sequences
+    pub sequence: String,
+    /// the column of Phred+33 quality strings, if any
+    pub quality: Option<String>,
+}
+
+impl Default for ArrowColumns {
+    fn default() -> ArrowColumns {
+        ArrowColumns { group: "group".to_string(), sequence: "sequence".to_string(), quality: Some("quality".to_string()) }
+    }
+}
+
+impl ArrowColumns {
+    /// Uses the columns `group`, `sequence` and, if present, `quality`.
+    pub fn new() -> ArrowColumns {
+        ArrowColumns::default()
+    }
+
+    /// Sets the column of group ids.
+    pub fn group(mut self, name: &str) -> ArrowColumns {
+        self.group = name.to_string();
+        self
+    }
+
+    /// Sets the column of sequences.
+    pub fn sequence(mut self, name: &str) -> ArrowColumns {
+        self.sequence = name.to_string();
+        self
+    }
+
+    /// Sets the column of quality strings, or `None` to read no qualities.
+    pub fn quality(mut self, name: Option<&str>) -> ArrowColumns {
+        self.quality = name.map(str::to_string);
+        self
+    }
+}
+
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> io::Result<&'a ArrayRef> {
+    batch.column_by_name(name).ok_or_else(|| invalid_data(&format!("no column named `{}`", name)))
+}
+
+// returns the bytes of each value of a string or binary column, None for nulls
+fn bytes_values(array: &ArrayRef, name: &str) -> io::Result<Vec<Option<Vec<u8>>>> {
+    fn collect<'a, A: Array, F: Fn(&'a A, usize) -> &'a [u8]>(array: &'a A, value: F) -> Vec<Option<Vec<u8>>> {
+        (0..array.len()).map(|i| if array.is_null(i) { None } else { Some(value(array, i).to_vec()) }).collect()
+    }
+
+    let any = array.as_any();
+    if let Some(array) = any.downcast_ref::<StringArray>() {
+        Ok(collect(array, |array, i| array.value(i).as_bytes()))
+    } else if let Some(array) = any.downcast_ref::<LargeStringArray>() {
+        Ok(collect(array, |array, i| array.value(i).as_bytes()))
+    } else if let Some(array) = any.downcast_ref::<BinaryArray>() {
+        Ok(collect(array, |array, i| array.value(i)))
+    } else if let Some(array) = any.downcast_ref::<LargeBinaryArray>() {
+        Ok(collect(array, |array, i| array.value(i)))
+    } else {
+        Err(invalid_data(&format!("column `{}` is neither a string nor a binary column", name)))
+    }
+}
+
+// returns each value of a string or integer column as a string, None for nulls
+fn group_values(array: &ArrayRef, name: &str) -> io::Result<Vec<Option<String>>> {
+    fn collect<A: Array, F: Fn(&A, usize) -> String>(array: &A, value: F) -> Vec<Option<String>> {
+        (0..array.len()).map(|i| if array.is_null(i) { None } else { Some(value(array, i)) }).collect()
+    }
+
+    let any = array.as_any();
+    if let Some(array) = any.downcast_ref::<Int64Array>() {
+        Ok(collect(array, |array, i| array.value(i).to_string()))
+    } else if let Some(array) = any.downcast_ref::<Int32Array>() {
+        Ok(collect(array, |array, i| array.value(i).to_string()))
+    } else if let Some(array) = any.downcast_ref::<UInt64Array>() {
+        Ok(collect(array, |array, i| array.value(i).to_string()))
+    } else if let Some(array) = any.downcast_ref::<UInt32Array>() {
+        Ok(collect(array, |array, i| array.value(i).to_string()))
+    } else {
+        let values = bytes_values(array, name)
+            .map_err(|_| invalid_data(&format!("column `{}` is neither a string nor an integer column", name)))?;
+        Ok(values.into_iter().map(|value| value.map(|value| String::from_utf8_lossy(&value).into_owned())).collect())
+    }
+}
+
+/// Gathers the reads of record batches into groups by their group id.
+///
+/// Groups are returned in the order their first read appears, across batches, and the reads of a
+/// group in input order, so the rows need not be sorted by group. Each read is named after its
+/// group. Rows with a null group id or sequence are an error.
+/// # Arguments
+///
+/// * `batches` - the record batches to read
+/// * `columns` - the names of the group id, sequence and quality columns
+///
+/// # Returns
+/// * returns the group id and the reads of each group
+///
+/// # Examples
+///
+/// ```
+///     use std::sync::Arc;
+///     use arrow_array::{ArrayRef, RecordBatch, StringArray, UInt32Array};
+///     use rust_spoa::{arrow_groups, ArrowColumns};
+///
+///     let batch = RecordBatch::try_from_iter(vec![
+///         ("group", Arc::new(UInt32Array::from(vec![7, 3, 7])) as ArrayRef),
+///         ("sequence", Arc::new(StringArray::from(vec!["ACGT", "TTGA", "ACGA"])) as ArrayRef),
+///     ])
+///     .unwrap();
+///     let groups = arrow_groups(&[batch], &ArrowColumns::new()).unwrap();
+///
+///     assert_eq!(groups[0].0, "7");
+///     assert_eq!(groups[0].1.len(), 2);
+///     assert_eq!(groups[1].1[0].seq, b"TTGA".to_vec());
+/// ```
+pub fn arrow_groups(batches: &[RecordBatch], columns: &ArrowColumns) -> io::Result<Vec<(String, Vec<SeqRecord>)>> {
+    let mut groups: Vec<(String, Vec<SeqRecord>)> = vec![];
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for batch in batches {
+        let ids = group_values(column(batch, &columns.group)?, &columns.group)?;
+        let seqs = bytes_values(column(batch, &columns.sequence)?, &columns.sequence)?;
+        // a missing quality column is the same as one holding only nulls
+        let quals = match &columns.quality {
+            Some(name) if batch.column_by_name(name).is_some() => bytes_values(column(batch, name)?, name)?,
+            _ => vec![None; batch.num_rows()],
+        };
+
+        for ((id, seq), qual) in ids.into_iter().zip(seqs).zip(quals) {
+            let id = id.ok_or_else(|| invalid_data(&format!("null value in column `{}`", columns.group)))?;
+            let seq = seq.ok_or_else(|| invalid_data(&format!("null value in column `{}`", columns.sequence)))?;
+            let mut record = SeqRecord::new(id.clone(), seq);
+            if let Some(qual) = qual {
+                if qual.len() != record.seq.len() {
+                    return Err(invalid_data(&format!("quality length differs from sequence length in group `{}`", id)));
+                }
+                record = record.qual(qual);
+            }
+
+            let i = *index.entry(id.clone()).or_insert_with(|| {
+                groups.push((id, vec![]));
+                groups.len() - 1
+            });
+            groups[i].1.push(record);
+        }
+    }
+    Ok(groups)
+}
+
+/// Generates the consensus of each read group of Arrow record batches, as `poa_consensus_of_groups` does.
+///
+/// The groups are gathered as by `arrow_groups`. Like `poa_consensus_of_groups` this aligns the
+/// reads unweighted; to weight bases by their qualities, pass the records of `arrow_groups` to
+/// `group_consensus_by` instead.
+/// # Arguments
+///
+/// * `batches` - the record batches to read
+/// * `columns` - the names of the group id, sequence and quality columns
+/// * `params` - the alignment and scoring parameters
+/// * `options` - the minimum number of reads and what to do with smaller groups
+///
+/// # Returns
+/// * returns the output record of each group that was not skipped, in order of first appearance, and the counts of how groups were handled
+pub fn poa_consensus_of_arrow(
+    batches: &[RecordBatch],
+    columns: &ArrowColumns,
+    params: &PoaParams,
+    options: &GroupOptions
+) -> io::Result<(Vec<GroupConsensus>, GroupSummary)> {
+    let groups = arrow_groups(batches, columns)?
+        .into_iter()
+        .map(|(id, records)| (id, records.into_iter().map(|record| record.seq).collect()));
+    Ok(poa_consensus_of_groups(groups, params, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn batch(groups: ArrayRef, seqs: Vec<&str>, quals: Vec<Option<&str>>) -> RecordBatch {
+        RecordBatch::try_from_iter(vec![
+            ("umi", groups),
+            ("seq", Arc::new(StringArray::from(seqs)) as ArrayRef),
+            ("qual", Arc::new(StringArray::from(quals)) as ArrayRef),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_groups_span_batches() {
+        let columns = ArrowColumns::new().group("umi").sequence("seq").quality(Some("qual"));
+        let first = batch(
+            Arc::new(StringArray::from(vec!["b", "a", "b"])),
+            vec!["AATGCCCGTT", "ACGT", "AATGCCCGTT"],
+            vec![None, Some("IIII"), Some("IIIIIIIIII")],
+        );
+        let second = batch(Arc::new(StringArray::from(vec!["b"])), vec!["AATGCCGTT"], vec![None]);
+
+        let groups = arrow_groups(&[first.clone(), second.clone()], &columns).unwrap();
+        assert_eq!(groups.iter().map(|(id, reads)| (id.as_str(), reads.len())).collect::<Vec<_>>(), vec![("b", 3), ("a", 1)]);
+        assert_eq!(groups[0].1[1].qual, Some(b"IIIIIIIIII".to_vec()));
+        assert_eq!(groups[0].1[0].qual, None);
+
+        let options = GroupOptions::new().min_reads(2);
+        let (records, summary) = poa_consensus_of_arrow(&[first, second], &columns, &PoaParams::new(1, 5, -4, -3, -1), &options).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].name.as_str(), records[0].consensus.as_slice()), ("b", &b"AATGCCCGTT"[..]));
+        assert_eq!(summary.skipped, 1);
+    }
+
+    #[test]
+    fn test_invalid_columns() {
+        let columns = ArrowColumns::new().group("umi").sequence("seq").quality(Some("qual"));
+        let ints = batch(Arc::new(Int64Array::from(vec![1])), vec!["ACGT"], vec![Some("III")]);
+        assert!(arrow_groups(&[ints], &columns).is_err());
+
+        let ints = batch(Arc::new(Int64Array::from(vec![Some(1), None])), vec!["ACGT", "ACGT"], vec![None, None]);
+        assert!(arrow_groups(std::slice::from_ref(&ints), &columns).is_err());
+        assert!(arrow_groups(&[ints], &columns.sequence("bases")).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Accepting and returning the FASTA and FASTQ records of rust-bio, for pipelines reading their
+//! input with it.
+//!
+//! Sequences as `bio::utils::TextSlice` are byte slices, which `ConsensusExt` and the functions
+//! not needing null terminators take as they are; records convert to and from `SeqRecord` and
+//! add to a graph directly.
+
+use bio::io::{fasta, fastq};
+
+use crate::{GroupConsensus, PoaGraph, PoaParams, SeqRecord};
+
+// the weight of each base of a FASTQ record, its Phred quality
+fn quality_weights(record: &fastq::Record) -> Vec<u32> {
+    record.qual().iter().map(|&q| u32::from(q.saturating_sub(33))).collect()
+}
+
+impl From<&fasta::Record> for SeqRecord {
+    fn from(record: &fasta::Record) -> SeqRecord {
+        SeqRecord::new(record.id(), record.seq().to_vec())
+    }
+}
+
+impl From<fasta::Record> for SeqRecord {
+    fn from(record: fasta::Record) -> SeqRecord {
+        SeqRecord::from(&record)
+    }
+}
+
+impl From<&fastq::Record> for SeqRecord {
+    fn from(record: &fastq::Record) -> SeqRecord {
+        SeqRecord::new(record.id(), record.seq().to_vec()).qual(record.qual().to_vec())
+    }
+}
+
+impl From<fastq::Record> for SeqRecord {
+    fn from(record: fastq::Record) -> SeqRecord {
+        SeqRecord::from(&record)
+    }
+}
+
+impl From<SeqRecord> for fasta::Record {
+    fn from(record: SeqRecord) -> fasta::Record {
+        fasta::Record::with_attrs(&record.name, None, &record.seq)
+    }
+}
+
+// a record without qualities gets quality `!` (Phred 0), unknown, at every base
+impl From<SeqRecord> for fastq::Record {
+    fn from(record: SeqRecord) -> fastq::Record {
+        let len = record.seq.len();
+        let qual = record.qual.unwrap_or_else(|| vec![b'!'; len]);
+        fastq::Record::with_attrs(&record.name, None, &record.seq, &qual)
+    }
+}
+
+impl From<GroupConsensus> for fasta::Record {
+    fn from(group: GroupConsensus) -> fasta::Record {
+        fasta::Record::with_attrs(&group.name, None, &group.consensus)
+    }
+}
+
+impl PoaGraph {
+    /// Aligns the sequence of a rust-bio FASTA record to the graph and adds it.
+    pub fn add_fasta_record(&mut self, record: &fasta::Record) {
+        self.add_sequence(record.seq());
+    }
+
+    /// Aligns the sequence of a rust-bio FASTQ record to the graph and adds it, weighting each base
+    /// by its Phred quality as for `SeqRecord`.
+    ///
+    /// # Panics
+    /// Panics if the quality string of the record does not have the length of its sequence.
+    pub fn add_fastq_record(&mut self, record: &fastq::Record) {
+        assert_eq!(record.qual().len(), record.seq().len(), "quality string length differs from sequence length");
+        self.add_sequence_weighted(record.seq(), &quality_weights(record));
+    }
+}
+
+/// Generates the consensus of a set of rust-bio FASTA records.
+///
+/// This is `poa_consensus_of_records` for reads read with rust-bio, without copying them first.
+/// # Arguments
+///
+/// * `records` - the reads to form a consensus from; empty reads are left out
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the records
+pub fn poa_consensus_of_fasta(records: &[fasta::Record], params: &PoaParams) -> Vec<u8> {
+    let mut graph = PoaGraph::new(params);
+    for record in records.iter().filter(|record| !record.seq().is_empty()) {
+        graph.add_fasta_record(record);
+    }
+    graph.consensus()
+}
+
+/// Generates the consensus of a set of rust-bio FASTQ records, weighting each base by its Phred
+/// quality.
+///
+/// This is `poa_consensus_with_qualities` for reads read with rust-bio.
+/// # Arguments
+///
+/// * `records` - the reads to form a consensus from; empty reads are left out
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the records
+///
+/// # Panics
+/// Panics if the quality string of a record does not have the length of its sequence.
+///
+/// # Examples
+///
+/// ```
+///     use bio::io::fastq;
+///     use rust_spoa::{poa_consensus_of_fastq, Consensus, PoaParams};
+///
+///     let reads = vec![
+///         fastq::Record::with_attrs("r1", None, b"AACGT", b"IIIII"),
+///         fastq::Record::with_attrs("r2", None, b"ACCGT", b"5!5II"),
+///         fastq::Record::with_attrs("r3", None, b"ACCGT", b"5!5II"),
+///     ];
+///     let consensus = poa_consensus_of_fastq(&reads, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     let record: fastq::Record = Consensus::new(consensus).into_record("consensus").into();
+///     assert_eq!(record.seq(), b"AACGT");
+///     assert_eq!(record.qual(), b"!!!!!");
+/// ```
+pub fn poa_consensus_of_fastq(records: &[fastq::Record], params: &PoaParams) -> Vec<u8> {
+    let mut graph = PoaGraph::new(params);
+    for record in records.iter().filter(|record| !record.seq().is_empty()) {
+        graph.add_fastq_record(record);
+    }
+    graph.consensus()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConsensusExt;
+
+    #[test]
+    fn test_conversions() {
+        let fasta = fasta::Record::with_attrs("r1", Some("sample=1"), b"AATGCCCGTT");
+        let record = SeqRecord::from(&fasta);
+        assert_eq!(record, SeqRecord::new("r1", b"AATGCCCGTT".to_vec()));
+        let back = fasta::Record::from(record.clone());
+        assert_eq!((back.id(), back.desc(), back.seq()), ("r1", None, &b"AATGCCCGTT"[..]));
+
+        let fastq = fastq::Record::with_attrs("r2", None, b"ACGT", b"II5!");
+        let record = SeqRecord::from(fastq.clone());
+        assert_eq!(record.base_weights(), Some(vec![40, 40, 20, 0]));
+        assert_eq!(fastq::Record::from(record), fastq);
+        assert_eq!(fastq::Record::from(SeqRecord::new("r3", b"AC".to_vec())).qual(), b"!!");
+
+        let group = GroupConsensus { name: "g".to_string(), consensus: b"ACGT".to_vec(), reads: 3, passed_through: false };
+        assert_eq!(fasta::Record::from(group).to_string(), ">g\nACGT\n");
+    }
+
+    #[test]
+    fn test_consensus_of_records() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let seqs = ["AATGCCCGTT", "AATGCCGTT", "", "AATGCCCGAT"];
+        let records: Vec<fasta::Record> = seqs.iter().enumerate().map(|(i, seq)| fasta::Record::with_attrs(&i.to_string(), None, seq.as_bytes())).collect();
+        assert_eq!(poa_consensus_of_fasta(&records, &params), b"AATGCCCGTT".to_vec());
+
+        // the sequences of records are text slices, which ConsensusExt takes directly
+        let slices: Vec<bio::utils::TextSlice> = records.iter().map(|record| record.seq()).collect();
+        assert_eq!(slices.consensus(&params).unwrap(), b"AATGCCCGTT".to_vec());
+        assert!(poa_consensus_of_fasta(&records[2..3], &params).is_empty());
+
+        // qualities outweigh the majority
+        let fastq: Vec<fastq::Record> =
+            vec![fastq::Record::with_attrs("a", None, b"AACGT", b"IIIII"), fastq::Record::with_attrs("b", None, b"ACCGT", b"5!5II"), fastq::Record::with_attrs("c", None, b"ACCGT", b"5!5II")];
+        assert_eq!(poa_consensus_of_fastq(&fastq, &params), b"AACGT".to_vec());
+        let mut graph = PoaGraph::new(&params);
+        graph.add_fasta_record(&records[0]);
+        graph.add_fastq_record(&fastq[0]);
+        assert_eq!(graph.num_sequences(), 2);
+    }
+}
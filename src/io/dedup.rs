@@ -0,0 +1,144 @@
+//! Writing each distinct consensus once, with the number of groups or reads behind it in the
+//! USEARCH `;size=N` annotation.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::GroupConsensus;
+
+/// What the size annotation of a deduplicated consensus counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DedupSize {
+    /// the number of groups with this consensus, e.g. the molecules of a UMI experiment
+    Groups,
+    /// the number of reads in those groups
+    Reads,
+}
+
+// a distinct consensus, named after the first group it was seen in
+struct DedupEntry {
+    name: String,
+    consensus: Vec<u8>,
+    groups: usize,
+    reads: usize,
+}
+
+/// Collects consensus sequences and writes each distinct one once, as FASTA with its abundance
+/// in the header, the way dereplicating tools such as USEARCH and VSEARCH do.
+///
+/// A record is named after the first group found with its sequence, followed by `;size=N`. Records
+/// are written by decreasing size, those of equal size in the order they were first seen, which is
+/// the order clustering tools reading size annotations expect.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{DedupFasta, DedupSize};
+///
+///     let mut dedup = DedupFasta::new(DedupSize::Groups);
+///     dedup.add("umi_1", b"ACGT", 5);
+///     dedup.add("umi_2", b"AAGT", 3);
+///     dedup.add("umi_3", b"AAGT", 2);
+///
+///     let mut fasta = vec![];
+///     dedup.write_to(&mut fasta).unwrap();
+///     assert_eq!(String::from_utf8(fasta).unwrap(), ">umi_2;size=2\nAAGT\n>umi_1;size=1\nACGT\n");
+/// ```
+pub struct DedupFasta {
+    size: DedupSize,
+    entries: Vec<DedupEntry>,
+    index: HashMap<Vec<u8>, usize>,
+}
+
+impl DedupFasta {
+    /// Creates an empty collection whose size annotations count `size`.
+    pub fn new(size: DedupSize) -> DedupFasta {
+        DedupFasta { size, entries: vec![], index: HashMap::new() }
+    }
+
+    /// Adds the consensus of the group `name`, built from `reads` reads.
+    pub fn add(&mut self, name: &str, consensus: &[u8], reads: usize) {
+        match self.index.get(consensus) {
+            Some(&i) => {
+                self.entries[i].groups += 1;
+                self.entries[i].reads += reads;
+            }
+            None => {
+                self.index.insert(consensus.to_vec(), self.entries.len());
+                self.entries.push(DedupEntry { name: name.to_string(), consensus: consensus.to_vec(), groups: 1, reads });
+            }
+        }
+    }
+
+    /// Adds the consensus of a group processed by `poa_consensus_of_groups`.
+    pub fn add_group(&mut self, group: &GroupConsensus) {
+        self.add(&group.name, &group.consensus, group.reads);
+    }
+
+    /// Returns the number of distinct consensus sequences.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns whether no consensus was added.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn entry_size(&self, entry: &DedupEntry) -> usize {
+        match self.size {
+            DedupSize::Groups => entry.groups,
+            DedupSize::Reads => entry.reads,
+        }
+    }
+
+    /// Writes the distinct consensus sequences as FASTA records on single sequence lines.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut order: Vec<&DedupEntry> = self.entries.iter().collect();
+        // a stable sort keeps the first-seen order among equal sizes
+        order.sort_by_key(|entry| std::cmp::Reverse(self.entry_size(entry)));
+
+        for entry in order {
+            writeln!(writer, ">{};size={}", entry.name, self.entry_size(entry))?;
+            writer.write_all(&entry.consensus)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes_count_groups_or_reads() {
+        let groups = [("a", &b"ACGT"[..], 2), ("b", b"AAGT", 7), ("c", b"ACGT", 3), ("d", b"TTTT", 1)];
+        let write = |size| {
+            let mut dedup = DedupFasta::new(size);
+            for &(name, consensus, reads) in groups.iter() {
+                dedup.add(name, consensus, reads);
+            }
+            assert_eq!(dedup.len(), 3);
+            let mut fasta = vec![];
+            dedup.write_to(&mut fasta).unwrap();
+            String::from_utf8(fasta).unwrap()
+        };
+
+        assert_eq!(write(DedupSize::Groups), ">a;size=2\nACGT\n>b;size=1\nAAGT\n>d;size=1\nTTTT\n");
+        assert_eq!(write(DedupSize::Reads), ">b;size=7\nAAGT\n>a;size=5\nACGT\n>d;size=1\nTTTT\n");
+    }
+
+    #[test]
+    fn test_groups_and_empty_output() {
+        let mut dedup = DedupFasta::new(DedupSize::Reads);
+        assert!(dedup.is_empty());
+
+        let group = GroupConsensus { name: "umi_9".to_string(), consensus: b"ACGT".to_vec(), reads: 4, passed_through: false };
+        dedup.add_group(&group);
+        dedup.add_group(&group);
+        let mut fasta = vec![];
+        dedup.write_to(&mut fasta).unwrap();
+        assert_eq!(fasta, b">umi_9;size=8\nACGT\n".to_vec());
+    }
+}
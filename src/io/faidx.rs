@@ -0,0 +1,274 @@
+//! Random access to the records of an indexed (`.fai`) FASTA file.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use super::invalid_data;
+
+/// The location of one record in a FASTA file, as stored in a line of its `.fai` index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FaiEntry {
+    /// the record name
+    pub name: String,
+    /// the number of bases of the record
+    pub length: u64,
+    /// the byte offset of the first base of the record in the file
+    pub offset: u64,
+    /// the number of bases on each full line
+    pub line_bases: u64,
+    /// the number of bytes of each full line, including the line ending
+    pub line_width: u64,
+}
+
+/// The `.fai` index of a FASTA file, as written by `samtools faidx`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FastaIndex {
+    entries: Vec<FaiEntry>,
+    by_name: HashMap<String, usize>,
+}
+
+impl FastaIndex {
+    /// Reads an index from its tab-separated `.fai` lines.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<FastaIndex> {
+        let mut index = FastaIndex::default();
+
+        for line in reader.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 5 {
+                return Err(invalid_data("a .fai line has fewer than 5 fields"));
+            }
+            let number = |field: &str| field.parse::<u64>().map_err(|_| invalid_data("a .fai field is not a number"));
+
+            index.push(FaiEntry {
+                name: fields[0].to_string(),
+                length: number(fields[1])?,
+                offset: number(fields[2])?,
+                line_bases: number(fields[3])?,
+                line_width: number(fields[4])?,
+            });
+        }
+
+        Ok(index)
+    }
+
+    /// Builds the index of a FASTA file by scanning it once.
+    ///
+    /// All lines of a record but its last must have the same length, as required by `samtools faidx`.
+    pub fn build<R: BufRead>(mut reader: R) -> io::Result<FastaIndex> {
+        let mut index = FastaIndex::default();
+        let mut line = vec![];
+        let mut position = 0u64;
+        // the entry being built, and whether a line shorter than the full lines was seen
+        let mut current: Option<(FaiEntry, bool)> = None;
+
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)? as u64;
+            if read == 0 {
+                break;
+            }
+            let bases = line.iter().take_while(|&&c| c != b'\n' && c != b'\r').count() as u64;
+
+            if line[0] == b'>' {
+                if let Some((entry, _)) = current.take() {
+                    index.push(entry);
+                }
+                let header = String::from_utf8_lossy(&line[1..bases as usize]);
+                let name = header.split_whitespace().next().unwrap_or("").to_string();
+                current = Some((FaiEntry { name, length: 0, offset: position + read, line_bases: 0, line_width: 0 }, false));
+            } else if let Some((entry, short_line_seen)) = current.as_mut() {
+                if bases > 0 {
+                    if entry.line_bases == 0 {
+                        entry.line_bases = bases;
+                        entry.line_width = read;
+                    } else if *short_line_seen || bases > entry.line_bases {
+                        return Err(invalid_data(&format!("record {} has lines of different lengths", entry.name)));
+                    }
+                    *short_line_seen = bases < entry.line_bases || read != entry.line_width;
+                    entry.length += bases;
+                }
+            } else if bases > 0 {
+                return Err(invalid_data("FASTA record does not start with '>'"));
+            }
+
+            position += read;
+        }
+
+        if let Some((entry, _)) = current {
+            index.push(entry);
+        }
+        Ok(index)
+    }
+
+    /// Writes the index as `.fai` lines.
+    pub fn write<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        for entry in &self.entries {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                entry.name, entry.length, entry.offset, entry.line_bases, entry.line_width
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns the entries of the index, in file order.
+    pub fn entries(&self) -> &[FaiEntry] {
+        &self.entries
+    }
+
+    /// Returns the entry of the record named `name`, if any.
+    pub fn get(&self, name: &str) -> Option<&FaiEntry> {
+        self.by_name.get(name).map(|&i| &self.entries[i])
+    }
+
+    fn push(&mut self, entry: FaiEntry) {
+        self.by_name.insert(entry.name.clone(), self.entries.len());
+        self.entries.push(entry);
+    }
+}
+
+/// A FASTA file with its index, reading only the records or regions asked for.
+///
+/// # Examples
+///
+/// ```no_run
+///     use rust_spoa::{IndexedFasta, PoaGraph, PoaParams};
+///
+///     // reads families.fa.fai next to families.fa
+///     let mut fasta = IndexedFasta::open("families.fa").unwrap();
+///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+///     for name in ["family_7/read_1", "family_7/read_2"].iter() {
+///         graph.add_sequence(&fasta.fetch(name).unwrap());
+///     }
+/// ```
+pub struct IndexedFasta<R> {
+    reader: R,
+    index: FastaIndex,
+}
+
+impl IndexedFasta<BufReader<File>> {
+    /// Opens the FASTA file at `path` with the index at the same path with `.fai` appended.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<IndexedFasta<BufReader<File>>> {
+        let path = path.as_ref();
+        let mut fai_path = PathBuf::from(path).into_os_string();
+        fai_path.push(".fai");
+
+        let index = FastaIndex::read(BufReader::new(File::open(fai_path)?))?;
+        Ok(IndexedFasta::new(BufReader::new(File::open(path)?), index))
+    }
+}
+
+impl<R: Read + Seek> IndexedFasta<R> {
+    /// Combines a reader of a FASTA file with the index of that file.
+    pub fn new(reader: R, index: FastaIndex) -> IndexedFasta<R> {
+        IndexedFasta { reader, index }
+    }
+
+    /// Returns the index of the file.
+    pub fn index(&self) -> &FastaIndex {
+        &self.index
+    }
+
+    /// Reads the whole sequence of the record named `name`.
+    pub fn fetch(&mut self, name: &str) -> io::Result<Vec<u8>> {
+        self.fetch_region(name, 0, u64::MAX)
+    }
+
+    /// Reads the bases from `start` (0-based, included) to `end` (excluded) of the record named `name`.
+    ///
+    /// The region is clipped to the record.
+    pub fn fetch_region(&mut self, name: &str, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        let entry = self.index.get(name).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("record {} is not in the index", name))
+        })?;
+        let end = end.min(entry.length);
+        if start >= end {
+            return Ok(vec![]);
+        }
+        if entry.line_bases == 0 {
+            return Err(invalid_data(&format!("record {} has no bases per line in the index", name)));
+        }
+
+        // byte position of a base within the record
+        let position = |base: u64| entry.offset + base / entry.line_bases * entry.line_width + base % entry.line_bases;
+        let first = position(start);
+        let last = position(end - 1);
+
+        let mut bytes = vec![0; (last - first + 1) as usize];
+        self.reader.seek(SeekFrom::Start(first))?;
+        self.reader.read_exact(&mut bytes)?;
+
+        bytes.retain(|&c| c != b'\n' && c != b'\r');
+        Ok(bytes)
+    }
+
+    /// Reads a region given as `name`, or as `name:start-end` with 1-based, inclusive coordinates
+    /// as used by `samtools faidx`.
+    pub fn fetch_spec(&mut self, spec: &str) -> io::Result<Vec<u8>> {
+        if self.index.get(spec).is_some() {
+            return self.fetch(spec);
+        }
+
+        let (name, range) = match spec.rsplit_once(':') {
+            Some(parts) => parts,
+            None => return self.fetch(spec),
+        };
+        let coordinate = |field: &str| {
+            field.replace(',', "").parse::<u64>().map_err(|_| invalid_data(&format!("invalid region {}", spec)))
+        };
+        let (start, end) = match range.split_once('-') {
+            Some((start, end)) => (coordinate(start)?, coordinate(end)?),
+            None => (coordinate(range)?, u64::MAX),
+        };
+
+        self.fetch_region(name, start.saturating_sub(1), end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const FASTA: &[u8] = b">r1 first\nAATGC\nCCGTT\nAC\n>r2\nGGGAA\r\nTT\r\n";
+
+    #[test]
+    fn test_build_and_round_trip() {
+        let index = FastaIndex::build(FASTA).unwrap();
+
+        assert_eq!(index.entries().len(), 2);
+        assert_eq!(index.get("r1"), Some(&FaiEntry { name: "r1".to_string(), length: 12, offset: 10, line_bases: 5, line_width: 6 }));
+        assert_eq!(index.get("r2").unwrap().line_width, 7);
+
+        let mut fai = vec![];
+        index.write(&mut fai).unwrap();
+        assert_eq!(FastaIndex::read(&fai[..]).unwrap(), index);
+    }
+
+    #[test]
+    fn test_fetch() {
+        let mut fasta = IndexedFasta::new(Cursor::new(FASTA), FastaIndex::build(FASTA).unwrap());
+
+        assert_eq!(fasta.fetch("r1").unwrap(), b"AATGCCCGTTAC".to_vec());
+        assert_eq!(fasta.fetch("r2").unwrap(), b"GGGAATT".to_vec());
+        assert_eq!(fasta.fetch_region("r1", 3, 8).unwrap(), b"GCCCG".to_vec());
+        assert_eq!(fasta.fetch_spec("r1:4-8").unwrap(), b"GCCCG".to_vec());
+        assert_eq!(fasta.fetch_spec("r2:6").unwrap(), b"TT".to_vec());
+        assert!(fasta.fetch_region("r1", 20, 30).unwrap().is_empty());
+        assert_eq!(fasta.fetch("r3").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_build_rejects_ragged_lines() {
+        assert!(FastaIndex::build(&b">r1\nAAT\nGCCCG\n"[..]).is_err());
+        assert!(FastaIndex::build(&b">r1\nAATGC\nCG\nTT\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Memory-mapped FASTA input, borrowing sequences from the mapping instead of copying them.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use super::{invalid_data, GZIP_MAGIC};
+use crate::{PoaGraph, PoaParams};
+
+/// A record of a memory-mapped FASTA file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FastaRecord<'a> {
+    /// the record name, i.e. the header up to the first whitespace
+    pub name: &'a [u8],
+    /// the sequence, borrowed from the mapping if it is stored on a single line
+    pub seq: Cow<'a, [u8]>,
+}
+
+/// A FASTA file mapped into memory.
+///
+/// Sequences stored on a single line are borrowed from the mapping, and graphs are built with
+/// the explicit sequence lengths, so neither copies nor null terminators are needed. Only line
+/// wrapped sequences are copied to join their lines.
+///
+/// The mapping reflects the file, so the file must not be modified while it is mapped; see
+/// `open`.
+pub struct MappedFasta {
+    // empty files cannot be mapped
+    map: Option<Mmap>,
+}
+
+impl MappedFasta {
+    /// Maps the FASTA file at `path` into memory.
+    ///
+    /// # Safety
+    /// The file must not be modified, truncated or replaced in place, by this process or another,
+    /// while the returned value or any record borrowed from it is alive. The records borrow the
+    /// mapped bytes, so a change to the file changes them behind the borrow, and truncation makes
+    /// reading them fault, which is undefined behavior, as for `memmap2::Mmap::map`.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> io::Result<MappedFasta> {
+        let file = File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Ok(MappedFasta { map: None });
+        }
+
+        // safety: the mapping is only read, and the caller keeps the file unmodified while it is mapped
+        let map = unsafe { Mmap::map(&file)? };
+        if map.starts_with(&GZIP_MAGIC) {
+            return Err(invalid_data("gzip-compressed input cannot be memory-mapped"));
+        }
+        Ok(MappedFasta { map: Some(map) })
+    }
+
+    /// Returns the mapped bytes of the file.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.map.as_deref().unwrap_or(&[])
+    }
+
+    /// Parses the records of the file.
+    pub fn records(&self) -> io::Result<Vec<FastaRecord<'_>>> {
+        parse_fasta(self.as_bytes())
+    }
+
+    /// Generates the consensus of all records of the file, in file order.
+    pub fn consensus(&self, params: &PoaParams) -> io::Result<Vec<u8>> {
+        let mut graph = PoaGraph::new(params);
+        for record in self.records()?.iter().filter(|record| !record.seq.is_empty()) {
+            graph.add_sequence(&record.seq);
+        }
+
+        Ok(graph.consensus())
+    }
+}
+
+// strips a trailing carriage return
+fn trim_cr(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+fn parse_fasta(data: &[u8]) -> io::Result<Vec<FastaRecord<'_>>> {
+    let mut records = vec![];
+    let mut lines = data.split(|&c| c == b'\n').map(trim_cr).filter(|line| !line.is_empty()).peekable();
+
+    while let Some(header) = lines.next() {
+        if header[0] != b'>' {
+            return Err(invalid_data("FASTA record does not start with '>'"));
+        }
+        let name = header[1..].split(|c| c.is_ascii_whitespace()).next().unwrap_or(&[]);
+
+        let mut seq: Cow<[u8]> = Cow::Borrowed(&[]);
+        while let Some(line) = lines.next_if(|line| line[0] != b'>') {
+            if seq.is_empty() {
+                seq = Cow::Borrowed(line);
+            } else {
+                seq.to_mut().extend_from_slice(line);
+            }
+        }
+
+        records.push(FastaRecord { name, seq });
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fasta() {
+        let records = parse_fasta(b">r1 first read\nAATGCCCGTT\n>r2\r\nAATG\r\nCCGTT\r\n\n>r3\n").unwrap();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].name, b"r1");
+        assert!(matches!(records[0].seq, Cow::Borrowed(b"AATGCCCGTT")));
+        assert_eq!(records[1].seq, Cow::<[u8]>::Owned(b"AATGCCGTT".to_vec()));
+        assert!(records[2].seq.is_empty());
+        assert!(parse_fasta(b"AATG\n").is_err());
+    }
+
+    #[test]
+    fn test_mapped_consensus() {
+        let path = std::env::temp_dir().join(format!("rust_spoa_{}_mapped.fa", std::process::id()));
+        std::fs::write(&path, b">a\nAACGT\n>b\nAACGT\n>c\nACCGT\n").unwrap();
+
+        // safety: the file is not written until the mapping is dropped
+        let fasta = unsafe { MappedFasta::open(&path) }.unwrap();
+        assert_eq!(fasta.records().unwrap().len(), 3);
+        assert_eq!(fasta.consensus(&PoaParams::new(1, 5, -4, -3, -1)).unwrap(), b"AACGT".to_vec());
+
+        drop(fasta);
+        std::fs::write(&path, b"").unwrap();
+        assert!(unsafe { MappedFasta::open(&path) }.unwrap().records().unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
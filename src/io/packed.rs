@@ -0,0 +1,259 @@
+//! Reads stored in 2-bit or 4-bit packed form, unpacked one at a time as they are added.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{PoaError, PoaGraph, PoaParams};
+
+// the bases of the 2-bit and 4-bit codes; the 4-bit codes are those of BAM records
+const TWO_BIT_BASES: &[u8; 4] = b"ACGT";
+const FOUR_BIT_BASES: &[u8; 16] = b"=ACMGRSVTWYHKDBN";
+
+/// The packing of the bases of a read into bytes, the first base in the most significant bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Packing {
+    /// four bases per byte, coded as A = 0, C = 1, G = 2 and T = 3
+    TwoBit,
+    /// two bases per byte, coded as in BAM records: `=ACMGRSVTWYHKDBN` from 0 to 15, so IUPAC
+    /// ambiguity codes and N are kept
+    FourBit,
+}
+
+impl Packing {
+    /// Returns the name of the packing, as `FromStr` parses it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Packing::TwoBit => "2bit",
+            Packing::FourBit => "4bit",
+        }
+    }
+
+    /// Returns the number of bases packed into each byte.
+    pub fn bases_per_byte(self) -> usize {
+        match self {
+            Packing::TwoBit => 4,
+            Packing::FourBit => 2,
+        }
+    }
+
+    /// Returns the number of bytes holding `len` bases.
+    pub fn packed_len(self, len: usize) -> usize {
+        len.div_ceil(self.bases_per_byte())
+    }
+}
+
+impl fmt::Display for Packing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Packing {
+    type Err = PoaError;
+
+    fn from_str(s: &str) -> Result<Packing, PoaError> {
+        match s {
+            "2bit" => Ok(Packing::TwoBit),
+            "4bit" => Ok(Packing::FourBit),
+            _ => Err(PoaError::InvalidPreset(format!("unknown packing {:?}, expected 2bit or 4bit", s))),
+        }
+    }
+}
+
+/// A read of `len` bases packed into bytes, borrowed from the caller's storage.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{PackedSeq, Packing};
+///
+///     let seq = PackedSeq::pack(Packing::TwoBit, b"AATGCCCGTT");
+///     assert_eq!(seq.data(), &[0b0000_1110, 0b0101_0110, 0b1111_0000]);
+///     assert_eq!(seq.unpack(), b"AATGCCCGTT".to_vec());
+///
+///     let borrowed = PackedSeq::new(Packing::TwoBit, seq.data(), 10);
+///     assert_eq!(borrowed.base(2), b'T');
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PackedSeq<'a> {
+    packing: Packing,
+    data: Cow<'a, [u8]>,
+    len: usize,
+}
+
+impl<'a> PackedSeq<'a> {
+    /// Wraps the first `len` bases packed in `data`; the bits after them are ignored.
+    ///
+    /// # Panics
+    /// * panics if `data` is shorter than `packing.packed_len(len)` bytes
+    pub fn new(packing: Packing, data: &'a [u8], len: usize) -> PackedSeq<'a> {
+        assert!(data.len() >= packing.packed_len(len), "{} bytes cannot hold {} bases packed as {}", data.len(), len, packing);
+        PackedSeq { packing, data: data.into(), len }
+    }
+
+    /// Packs a sequence, e.g. for tests or to convert reads once. With `Packing::TwoBit`, bases
+    /// other than A, C, G and T (in either case) are packed as A; with `Packing::FourBit`, other
+    /// bases than `=ACMGRSVTWYHKDBN` are packed as N.
+    pub fn pack(packing: Packing, seq: &[u8]) -> PackedSeq<'static> {
+        let mut data = vec![0u8; packing.packed_len(seq.len())];
+        let bits = 8 / packing.bases_per_byte();
+        for (i, &base) in seq.iter().enumerate() {
+            let base = base.to_ascii_uppercase();
+            let code = match packing {
+                Packing::TwoBit => TWO_BIT_BASES.iter().position(|&c| c == base).unwrap_or(0),
+                Packing::FourBit => FOUR_BIT_BASES.iter().position(|&c| c == base).unwrap_or(15),
+            } as u8;
+            let shift = 8 - bits * (i % packing.bases_per_byte() + 1);
+            data[i / packing.bases_per_byte()] |= code << shift;
+        }
+        PackedSeq { packing, data: data.into(), len: seq.len() }
+    }
+
+    /// Returns the packing of the bases.
+    pub fn packing(&self) -> Packing {
+        self.packing
+    }
+
+    /// Returns the packed bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the number of bases.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the read has no bases.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the base at position `i`, in upper case.
+    ///
+    /// # Panics
+    /// * panics if `i` is not below `len()`
+    pub fn base(&self, i: usize) -> u8 {
+        assert!(i < self.len, "base {} of a read of {} bases", i, self.len);
+        let per_byte = self.packing.bases_per_byte();
+        let bits = 8 / per_byte;
+        let code = (self.data[i / per_byte] >> (8 - bits * (i % per_byte + 1))) & ((1 << bits) - 1);
+        match self.packing {
+            Packing::TwoBit => TWO_BIT_BASES[code as usize],
+            Packing::FourBit => FOUR_BIT_BASES[code as usize],
+        }
+    }
+
+    /// Unpacks the bases into `buffer`, replacing its contents, so one buffer can serve every read.
+    pub fn unpack_into(&self, buffer: &mut Vec<u8>) {
+        buffer.clear();
+        buffer.extend((0..self.len).map(|i| self.base(i)));
+    }
+
+    /// Returns the bases as ASCII.
+    pub fn unpack(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(self.len);
+        self.unpack_into(&mut buffer);
+        buffer
+    }
+}
+
+impl PoaGraph {
+    /// Aligns a packed read to the graph and adds it as `try_add_sequence` does, unpacking it into
+    /// `buffer` first; reusing one buffer avoids an allocation for every read.
+    pub fn try_add_packed(&mut self, seq: &PackedSeq, buffer: &mut Vec<u8>) -> Result<(), PoaError> {
+        seq.unpack_into(buffer);
+        self.try_add_sequence(buffer)
+    }
+}
+
+/// Generates a consensus sequence like `try_poa_consensus` from packed reads.
+///
+/// The reads are unpacked one at a time into a single buffer as they are added, so at most one
+/// read is held as ASCII at any time. Empty reads are skipped.
+/// # Arguments
+///
+/// * `seqs` - the packed reads to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the reads, `PoaError::EmptyInput` if every read is empty, or the
+///   errors of `try_poa_consensus` for invalid parameters and a truncated consensus
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{try_poa_consensus_packed, PackedSeq, Packing, PoaParams};
+///
+///     let reads = ["AATGCCCGTT", "AATGCCGTT", "AATGCCCGAT"];
+///     let packed: Vec<PackedSeq> = reads.iter().map(|read| PackedSeq::pack(Packing::TwoBit, read.as_bytes())).collect();
+///
+///     let consensus = try_poa_consensus_packed(&packed, 0, &PoaParams::new(1, 5, -4, -3, -1));
+///     assert_eq!(consensus, Ok(b"AATGCCCGTT".to_vec()));
+/// ```
+pub fn try_poa_consensus_packed(seqs: &[PackedSeq], consensus_max_length: usize, params: &PoaParams) -> Result<Vec<u8>, PoaError> {
+    params.validate()?;
+
+    let mut graph = PoaGraph::try_new(params)?;
+    let mut buffer = vec![];
+    for seq in seqs.iter().filter(|seq| !seq.is_empty()) {
+        graph.try_add_packed(seq, &mut buffer)?;
+    }
+    if graph.num_sequences() == 0 {
+        return Err(PoaError::EmptyInput);
+    }
+
+    let consensus = graph.try_consensus()?;
+    if consensus_max_length > 0 && consensus.len() > consensus_max_length {
+        return Err(PoaError::Truncated { len: consensus.len(), max_len: consensus_max_length });
+    }
+    Ok(consensus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::try_poa_consensus;
+
+    #[test]
+    fn test_packing() {
+        let seq = PackedSeq::pack(Packing::FourBit, b"ACGTNRy=");
+        assert_eq!(seq.data(), &[0x12, 0x48, 0xf5, 0xa0]);
+        assert_eq!(seq.unpack(), b"ACGTNRY=".to_vec());
+        assert_eq!(PackedSeq::pack(Packing::FourBit, b"AXC").unpack(), b"ANC".to_vec());
+        assert_eq!(PackedSeq::pack(Packing::TwoBit, b"acgtN").unpack(), b"ACGTA".to_vec());
+
+        // trailing bits past the last base are ignored
+        let seq = PackedSeq::new(Packing::TwoBit, &[0b0001_1011, 0b1111_1111], 5);
+        assert_eq!(seq.unpack(), b"ACGTT".to_vec());
+        assert_eq!((seq.len(), Packing::TwoBit.packed_len(5), Packing::FourBit.packed_len(5)), (5, 2, 3));
+        assert!(PackedSeq::new(Packing::FourBit, &[], 0).is_empty());
+
+        assert_eq!("4bit".parse::<Packing>(), Ok(Packing::FourBit));
+        assert_eq!(Packing::TwoBit.to_string().parse::<Packing>(), Ok(Packing::TwoBit));
+        assert!("3bit".parse::<Packing>().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "1 bytes cannot hold 5 bases packed as 2bit")]
+    fn test_short_data() {
+        PackedSeq::new(Packing::TwoBit, &[0], 5);
+    }
+
+    #[test]
+    fn test_packed_consensus() {
+        let reads = ["AATGCCCGTT", "", "AATGCCGTT", "AATGCCCGAT", "AAGCCCGTT"];
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let terminated: Vec<String> = reads.iter().map(|read| format!("{}\0", read)).collect();
+        let expected = try_poa_consensus(&terminated, 0, &params);
+
+        for &packing in &[Packing::TwoBit, Packing::FourBit] {
+            let packed: Vec<PackedSeq> = reads.iter().map(|read| PackedSeq::pack(packing, read.as_bytes())).collect();
+            assert_eq!(try_poa_consensus_packed(&packed, 0, &params), expected);
+            assert_eq!(try_poa_consensus_packed(&packed, 5, &params), Err(PoaError::Truncated { len: 10, max_len: 5 }));
+            assert_eq!(try_poa_consensus_packed(&packed[1..2], 0, &params), Err(PoaError::EmptyInput));
+        }
+    }
+}
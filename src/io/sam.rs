@@ -0,0 +1,370 @@
+//! Writing the reads of a graph aligned to its consensus as SAM, or BAM with the `noodles` feature,
+//! to inspect a consensus in a genome browser.
+
+#[cfg(feature = "noodles")]
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+#[cfg(feature = "noodles")]
+use noodles_core::Position;
+#[cfg(feature = "noodles")]
+use noodles_sam::alignment::io::Write as _;
+#[cfg(feature = "noodles")]
+use noodles_sam::alignment::record::cigar::{op::Kind, Op};
+#[cfg(feature = "noodles")]
+use noodles_sam::alignment::record::data::field::Tag;
+#[cfg(feature = "noodles")]
+use noodles_sam::alignment::record::Flags;
+#[cfg(feature = "noodles")]
+use noodles_sam::alignment::record_buf::data::field::Value;
+#[cfg(feature = "noodles")]
+use noodles_sam::alignment::RecordBuf;
+#[cfg(feature = "noodles")]
+use noodles_sam::Header;
+
+use crate::{PoaGraph, Realignment, SeqRecord};
+
+// the SAM flag of a read without aligned bases
+const UNMAPPED: u16 = 4;
+// the mapping quality of the records, which SAM reads as not available
+const NO_MAPQ: u8 = 255;
+// the longest read name SAM and BAM allow
+const MAX_NAME_LEN: usize = 254;
+
+// one read of the output with its alignment, in the order the records are written
+struct SamRead<'a> {
+    name: String,
+    seq: &'a [u8],
+    qual: Option<&'a [u8]>,
+    realignment: &'a Realignment,
+}
+
+impl SamRead<'_> {
+    fn is_mapped(&self) -> bool {
+        self.realignment.cigar.contains('M')
+    }
+
+    fn flag(&self) -> u16 {
+        if self.is_mapped() {
+            0
+        } else {
+            UNMAPPED
+        }
+    }
+
+    // the length and operation of each run of the cigar string
+    #[cfg(feature = "noodles")]
+    fn cigar_ops(&self) -> Vec<(usize, u8)> {
+        let mut ops = vec![];
+        let mut len = 0;
+        for c in self.realignment.cigar.bytes() {
+            if c.is_ascii_digit() {
+                len = len * 10 + usize::from(c - b'0');
+            } else {
+                ops.push((len, c));
+                len = 0;
+            }
+        }
+        ops
+    }
+
+    // the read as a noodles record, with the qualities as Phred scores rather than characters
+    #[cfg(feature = "noodles")]
+    fn record_buf(&self) -> io::Result<RecordBuf> {
+        let data = std::iter::once((Tag::ALIGNMENT_SCORE, Value::from(self.realignment.score))).collect();
+        let mut builder = RecordBuf::builder()
+            .set_name(self.name.as_str())
+            .set_flags(Flags::from(self.flag()))
+            .set_sequence(self.seq.into())
+            .set_data(data);
+        if let Some(qual) = self.qual.filter(|qual| !qual.is_empty()) {
+            builder = builder.set_quality_scores(qual.iter().map(|&q| q.saturating_sub(33)).collect());
+        }
+        if self.is_mapped() {
+            let start = Position::try_from(self.realignment.consensus_start + 1)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+            let cigar = self
+                .cigar_ops()
+                .into_iter()
+                .map(|(len, op)| {
+                    let kind = match op {
+                        b'M' => Kind::Match,
+                        b'I' => Kind::Insertion,
+                        b'D' => Kind::Deletion,
+                        _ => Kind::SoftClip,
+                    };
+                    Op::new(kind, len)
+                })
+                .collect();
+            builder = builder.set_reference_sequence_id(0).set_alignment_start(start).set_cigar(cigar);
+        }
+        Ok(builder.build())
+    }
+}
+
+// the reads with their names, bases and qualities, mapped reads by consensus position and then
+// unmapped reads, as a coordinate-sorted file orders them, or an error if a name is too long
+fn sam_reads<'a>(
+    graph_seqs: &'a [Vec<u8>],
+    records: Option<&'a [SeqRecord]>,
+    realignments: &'a [Realignment]
+) -> io::Result<Vec<SamRead<'a>>> {
+    let mut reads: Vec<SamRead> = realignments
+        .iter()
+        .enumerate()
+        .map(|(i, realignment)| match records {
+            Some(records) => {
+                SamRead { name: records[i].name.clone(), seq: &records[i].seq, qual: records[i].qual.as_deref(), realignment }
+            }
+            None => SamRead { name: format!("read_{}", i), seq: &graph_seqs[i], qual: None, realignment },
+        })
+        .collect();
+    if let Some(read) = reads.iter().find(|read| read.name.len() > MAX_NAME_LEN) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("read name {} is longer than {} bytes", read.name, MAX_NAME_LEN),
+        ));
+    }
+    reads.sort_by_key(|read| (!read.is_mapped(), read.realignment.consensus_start));
+    Ok(reads)
+}
+
+// the bases of each sequence of the graph
+fn graph_sequences(graph: &PoaGraph) -> Vec<Vec<u8>> {
+    let nodes = graph.nodes();
+    (0..graph.num_sequences()).map(|i| graph.sequence_path(i).iter().map(|&id| nodes[id as usize].base).collect()).collect()
+}
+
+// the header of the file, with the consensus as its only reference sequence
+fn header(name: &str, consensus_len: usize) -> String {
+    format!(
+        "@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:{}\tLN:{}\n@PG\tID:rust-spoa\tPN:rust-spoa\tVN:{}\n",
+        name,
+        consensus_len,
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+/// Writes the sequences of a graph aligned to its consensus as a SAM file, with the consensus as
+/// the reference sequence `name`.
+///
+/// Each sequence is aligned to the consensus as by `PoaGraph::realign_to_consensus`, and written
+/// with the `AS:i` tag holding its alignment score; records are sorted by position, with the
+/// sequences without an aligned base last as unmapped records. `records` gives the names and
+/// qualities of the sequences, one per sequence of the graph in the order they were added,
+/// e.g. the records the graph was built from; without it the sequences are named `read_0`,
+/// `read_1` and so on and have no qualities. A name longer than the 254 bytes SAM allows is
+/// an `io::ErrorKind::InvalidInput` error, and nothing is written. The consensus itself is written with
+/// `write_fasta_record`, for the browser to load as the reference.
+///
+/// # Panics
+/// Panics if `records` does not hold one record per sequence of the graph, with the length of
+/// that sequence.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::io::write_sam;
+///     use rust_spoa::{PoaGraph, PoaParams};
+///
+///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+///     for seq in ["GATTACAGGCTTACCG", "GATTACAGGCTTACCG", "TACAGGCTTACCG"].iter() {
+///         graph.add_sequence(seq.as_bytes());
+///     }
+///
+///     let mut sam = vec![];
+///     write_sam(&mut sam, "consensus", &graph, None).unwrap();
+///     let sam = String::from_utf8(sam).unwrap();
+///     assert!(sam.starts_with("@HD\tVN:1.6\tSO:coordinate\n@SQ\tSN:consensus\tLN:16\n"));
+///     assert!(sam.ends_with("read_2\t0\tconsensus\t4\t255\t13M\t*\t0\t0\tTACAGGCTTACCG\t*\tAS:i:45\n"));
+/// ```
+pub fn write_sam<W: Write>(writer: W, name: &str, graph: &PoaGraph, records: Option<&[SeqRecord]>) -> io::Result<()> {
+    write_sam_alignments(writer, name, &graph.consensus(), &graph_sequences(graph), records, &graph.realign_to_consensus())
+}
+
+/// Writes the sequences of a graph aligned to its consensus as a BAM file, with the records of
+/// `write_sam` and the mapping qualities, qualities and tags of the SAM records.
+///
+/// The records are encoded and compressed in BGZF blocks by noodles, so samtools and genome
+/// browsers can index the output.
+/// A read name longer than 254 bytes is an error, as for `write_sam`.
+///
+/// # Panics
+/// Panics if `records` does not hold one record per sequence of the graph, with the length of
+/// that sequence.
+#[cfg(feature = "noodles")]
+pub fn write_bam<W: Write>(writer: W, name: &str, graph: &PoaGraph, records: Option<&[SeqRecord]>) -> io::Result<()> {
+    let seqs = graph_sequences(graph);
+    let realignments = graph.realign_to_consensus();
+    check_records(&seqs, records, &realignments);
+    let header: Header =
+        header(name, graph.consensus().len()).parse().map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?;
+
+    let reads = sam_reads(&seqs, records, &realignments)?;
+    let mut writer = noodles_bam::io::Writer::new(writer);
+    writer.write_header(&header)?;
+    for read in reads {
+        writer.write_alignment_record(&header, &read.record_buf()?)?;
+    }
+    writer.try_finish()
+}
+
+/// Writes sequences aligned to a consensus, as returned by `PoaGraph::realign_to_consensus`, as a
+/// SAM file in the manner of `write_sam`.
+///
+/// `seqs` holds the bases of each aligned sequence, which `records`, if given, names and gives the
+/// qualities of.
+///
+/// # Panics
+/// Panics if `seqs`, `records` and `realignments` differ in length, or a record differs in length
+/// from its sequence.
+pub fn write_sam_alignments<W: Write, T: AsRef<[u8]>>(
+    mut writer: W,
+    name: &str,
+    consensus: &[u8],
+    seqs: &[T],
+    records: Option<&[SeqRecord]>,
+    realignments: &[Realignment]
+) -> io::Result<()> {
+    let seqs: Vec<Vec<u8>> = seqs.iter().map(|seq| seq.as_ref().to_vec()).collect();
+    check_records(&seqs, records, realignments);
+
+    let reads = sam_reads(&seqs, records, realignments)?;
+    writer.write_all(header(name, consensus.len()).as_bytes())?;
+    for read in reads {
+        let seq = if read.seq.is_empty() { "*".to_string() } else { String::from_utf8_lossy(read.seq).into_owned() };
+        let qual = match read.qual {
+            Some(qual) if !qual.is_empty() => String::from_utf8_lossy(qual).into_owned(),
+            _ => "*".to_string(),
+        };
+        if read.is_mapped() {
+            write!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                read.name,
+                read.flag(),
+                name,
+                read.realignment.consensus_start + 1,
+                NO_MAPQ,
+                read.realignment.cigar
+            )?;
+        } else {
+            write!(writer, "{}\t{}\t*\t0\t0\t*", read.name, read.flag())?;
+        }
+        writeln!(writer, "\t*\t0\t0\t{}\t{}\tAS:i:{}", seq, qual, read.realignment.score)?;
+    }
+    Ok(())
+}
+
+// panics unless there is a realignment and, if given, a record of the same length per sequence
+fn check_records(seqs: &[Vec<u8>], records: Option<&[SeqRecord]>, realignments: &[Realignment]) {
+    assert_eq!(seqs.len(), realignments.len(), "one realignment is needed per sequence");
+    if let Some(records) = records {
+        assert_eq!(records.len(), seqs.len(), "one record is needed per sequence of the graph");
+        for (record, seq) in records.iter().zip(seqs) {
+            assert_eq!(record.seq.len(), seq.len(), "record {} differs in length from its sequence", record.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PoaParams;
+
+    fn graph() -> PoaGraph {
+        let mut graph = PoaGraph::new(&PoaParams::new(0, 5, -4, -8, -6));
+        for seq in ["GATTACAGGCTTACCG", "TACAGGCTTACCG", "GATTACAGGCTTACCG", ""].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        graph
+    }
+
+    #[test]
+    fn test_write_sam() {
+        let records = vec![
+            SeqRecord::new("a", b"GATTACAGGCTTACCG".to_vec()).qual(vec![b'I'; 16]),
+            SeqRecord::new("b", b"TACAGGCTTACCG".to_vec()),
+            SeqRecord::new("c", b"GATTACAGGCTTACCG".to_vec()),
+            SeqRecord::new("d", vec![]),
+        ];
+        let mut sam = vec![];
+        write_sam(&mut sam, "umi_1", &graph(), Some(&records)).unwrap();
+        let sam = String::from_utf8(sam).unwrap();
+        let lines: Vec<&str> = sam.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[1], "@SQ\tSN:umi_1\tLN:16");
+        assert_eq!(lines[3], "a\t0\tumi_1\t1\t255\t16M\t*\t0\t0\tGATTACAGGCTTACCG\tIIIIIIIIIIIIIIII\tAS:i:80");
+        assert!(lines[4].starts_with("c\t0\tumi_1\t1\t"));
+        assert_eq!(lines[5], "b\t0\tumi_1\t4\t255\t13M\t*\t0\t0\tTACAGGCTTACCG\t*\tAS:i:65");
+        assert_eq!(lines[6], "d\t4\t*\t0\t0\t*\t*\t0\t0\t*\t*\tAS:i:0");
+    }
+
+    #[test]
+    fn test_long_names() {
+        let name = |len: usize| "n".repeat(len);
+        let records = |len: usize| {
+            vec![
+                SeqRecord::new(name(len), b"GATTACAGGCTTACCG".to_vec()),
+                SeqRecord::new("b", b"TACAGGCTTACCG".to_vec()),
+                SeqRecord::new("c", b"GATTACAGGCTTACCG".to_vec()),
+                SeqRecord::new("d", vec![]),
+            ]
+        };
+        let mut sam = vec![];
+        write_sam(&mut sam, "umi_1", &graph(), Some(&records(254))).unwrap();
+        assert!(String::from_utf8(sam).unwrap().contains(&format!("\n{}\t0\t", name(254))));
+        for &len in [255, 300].iter() {
+            let mut sam = vec![];
+            let error = write_sam(&mut sam, "umi_1", &graph(), Some(&records(len))).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert!(sam.is_empty());
+            #[cfg(feature = "noodles")]
+            assert_eq!(write_bam(vec![], "umi_1", &graph(), Some(&records(len))).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "one record is needed per sequence of the graph")]
+    fn test_records_match_the_graph() {
+        write_sam(vec![], "umi_1", &graph(), Some(&[SeqRecord::new("a", b"ACGT".to_vec())])).unwrap();
+    }
+
+    #[cfg(feature = "noodles")]
+    #[test]
+    fn test_write_bam() {
+        let records = vec![
+            SeqRecord::new("a", b"GATTACAGGCTTACCG".to_vec()).qual(vec![b'I'; 16]),
+            SeqRecord::new("b", b"TACAGGCTTACCG".to_vec()),
+            SeqRecord::new("c", b"GATTACAGGCTTACCG".to_vec()),
+            SeqRecord::new("d", vec![]),
+        ];
+        let mut bam = vec![];
+        write_bam(&mut bam, "umi_1", &graph(), Some(&records)).unwrap();
+
+        // read back with the noodles reader, the records are those of write_sam
+        let mut reader = noodles_bam::io::Reader::new(&bam[..]);
+        let header = reader.read_header().unwrap();
+        let reference = header.reference_sequences().get(&b"umi_1"[..]).unwrap();
+        assert_eq!(usize::from(reference.length()), 16);
+        let records: Vec<RecordBuf> = reader.record_bufs(&header).collect::<io::Result<_>>().unwrap();
+        let names: Vec<&[u8]> = records.iter().map(|record| record.name().unwrap().as_ref()).collect();
+        assert_eq!(names, [&b"a"[..], b"c", b"b", b"d"]);
+
+        let score = |record: &RecordBuf| record.data().get(&Tag::ALIGNMENT_SCORE).and_then(Value::as_int);
+        assert_eq!(records[0].reference_sequence_id(), Some(0));
+        assert_eq!(records[0].alignment_start(), Position::new(1));
+        assert_eq!(records[0].mapping_quality(), None);
+        assert_eq!(records[0].cigar().as_ref(), [Op::new(Kind::Match, 16)]);
+        assert_eq!(records[0].sequence().as_ref(), b"GATTACAGGCTTACCG");
+        assert_eq!(records[0].quality_scores().as_ref(), [40; 16]);
+        assert_eq!(score(&records[0]), Some(80));
+        assert_eq!(records[2].alignment_start(), Position::new(4));
+        assert_eq!(records[2].cigar().as_ref(), [Op::new(Kind::Match, 13)]);
+        assert!(records[2].quality_scores().as_ref().is_empty());
+        assert_eq!(score(&records[2]), Some(65));
+        assert!(records[3].flags().is_unmapped());
+        assert_eq!((records[3].reference_sequence_id(), records[3].alignment_start()), (None, None));
+        assert!(records[3].sequence().as_ref().is_empty());
+    }
+}
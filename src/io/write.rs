@@ -0,0 +1,179 @@
+//! Writing consensus records with annotations of the reads behind them.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::align::{align, identity, AlignOp};
+use crate::PoaGraph;
+
+/// Summary statistics of the reads behind a consensus, written with the consensus record so
+/// downstream filtering does not need a separate table.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConsensusAnnotations {
+    /// the number of reads used to build the consensus
+    pub reads_used: usize,
+    /// the mean number of reads covering each consensus base
+    pub mean_depth: f64,
+    /// the mean identity of the reads to the consensus
+    pub mean_identity: f64,
+}
+
+impl ConsensusAnnotations {
+    /// Computes the annotations of the consensus of `graph`.
+    ///
+    /// The depth of a consensus base is the number of reads whose path passes through its node,
+    /// and the identity of a read is the fraction of matching columns in its global alignment
+    /// to the consensus. Both means are 0 for an empty graph.
+    pub fn from_graph(graph: &PoaGraph) -> ConsensusAnnotations {
+        let reads_used = graph.num_sequences();
+        let nodes = graph.nodes();
+
+        let consensus_path = graph.consensus_path();
+        let consensus: Vec<u8> = consensus_path.iter().map(|&id| nodes[id as usize].base).collect();
+
+        let mean_depth = if consensus_path.is_empty() {
+            0.0
+        } else {
+            let depth: u64 = consensus_path.iter().map(|&id| u64::from(nodes[id as usize].coverage)).sum();
+            depth as f64 / consensus_path.len() as f64
+        };
+
+        let mean_identity = if reads_used == 0 {
+            0.0
+        } else {
+            let total: f64 = (0..reads_used)
+                .map(|i| {
+                    let read: Vec<u8> = graph.sequence_path(i).iter().map(|&id| nodes[id as usize].base).collect();
+                    identity(&align(&read, &consensus))
+                })
+                .sum();
+            total / reads_used as f64
+        };
+
+        ConsensusAnnotations { reads_used, mean_depth, mean_identity }
+    }
+
+    /// Computes the annotations of a consensus built without a graph from `reads`.
+    ///
+    /// The depth of a consensus base is the number of reads with a matching base aligned to it in
+    /// their global alignment to the consensus, which is the same alignment the identity is
+    /// measured on.
+    pub fn from_reads<T: AsRef<[u8]>>(consensus: &[u8], reads: &[T]) -> ConsensusAnnotations {
+        let mut depth: u64 = 0;
+        let mut total_identity = 0.0;
+        for read in reads {
+            let ops = align(read.as_ref(), consensus);
+            depth += ops.iter().filter(|&&op| op == AlignOp::Match).count() as u64;
+            total_identity += identity(&ops);
+        }
+
+        ConsensusAnnotations {
+            reads_used: reads.len(),
+            mean_depth: if consensus.is_empty() { 0.0 } else { depth as f64 / consensus.len() as f64 },
+            mean_identity: if reads.is_empty() { 0.0 } else { total_identity / reads.len() as f64 },
+        }
+    }
+
+    /// Returns the annotations as SAM optional fields, for writing the consensus as SAM or BAM:
+    /// `cn:i` holds the read count, `cd:f` the mean depth and `ci:f` the mean identity.
+    pub fn sam_tags(&self) -> String {
+        format!("cn:i:{}\tcd:f:{:.2}\tci:f:{:.4}", self.reads_used, self.mean_depth, self.mean_identity)
+    }
+}
+
+impl fmt::Display for ConsensusAnnotations {
+    /// Formats the annotations as `key=value` fields of a FASTA or FASTQ description.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "reads={} depth={:.2} identity={:.4}", self.reads_used, self.mean_depth, self.mean_identity)
+    }
+}
+
+// writes a header line of `marker`, the name and the annotations, if any
+fn write_header<W: Write>(
+    writer: &mut W,
+    marker: char,
+    name: &str,
+    annotations: Option<&ConsensusAnnotations>
+) -> io::Result<()> {
+    match annotations {
+        Some(annotations) => writeln!(writer, "{}{} {}", marker, name, annotations),
+        None => writeln!(writer, "{}{}", marker, name),
+    }
+}
+
+/// Writes a consensus as a FASTA record on a single sequence line.
+///
+/// The annotations, if any, are written as the description of the record, e.g.
+/// `>umi_17 reads=6 depth=5.50 identity=0.9140`.
+pub fn write_fasta_record<W: Write>(
+    mut writer: W,
+    name: &str,
+    consensus: &[u8],
+    annotations: Option<&ConsensusAnnotations>
+) -> io::Result<()> {
+    write_header(&mut writer, '>', name, annotations)?;
+    writer.write_all(consensus)?;
+    writer.write_all(b"\n")
+}
+
+/// Writes a consensus and its Phred+33 quality string as a FASTQ record.
+///
+/// The annotations, if any, are written as the description of the record.
+///
+/// # Panics
+/// Panics if `qualities` does not have the length of `consensus`.
+pub fn write_fastq_record<W: Write>(
+    mut writer: W,
+    name: &str,
+    consensus: &[u8],
+    qualities: &[u8],
+    annotations: Option<&ConsensusAnnotations>
+) -> io::Result<()> {
+    assert_eq!(consensus.len(), qualities.len(), "quality string length differs from consensus length");
+
+    write_header(&mut writer, '@', name, annotations)?;
+    writer.write_all(consensus)?;
+    writer.write_all(b"\n+\n")?;
+    writer.write_all(qualities)?;
+    writer.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PoaParams;
+
+    #[test]
+    fn test_annotations_from_graph() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        assert_eq!(ConsensusAnnotations::from_graph(&graph).mean_depth, 0.0);
+
+        for seq in [&b"AATGCCCGTT"[..], b"AATGCCCGTT", b"AATGCCGTT"].iter() {
+            graph.add_sequence(seq);
+        }
+        let annotations = ConsensusAnnotations::from_graph(&graph);
+
+        assert_eq!(annotations.reads_used, 3);
+        assert!((annotations.mean_depth - 29.0 / 10.0).abs() < 1e-9);
+        assert!((annotations.mean_identity - (1.0 + 1.0 + 0.9) / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_write_records() {
+        let annotations = ConsensusAnnotations { reads_used: 6, mean_depth: 5.5, mean_identity: 0.914 };
+
+        let mut fasta = vec![];
+        write_fasta_record(&mut fasta, "umi_17", b"AATGCCCGTT", Some(&annotations)).unwrap();
+        write_fasta_record(&mut fasta, "umi_18", b"ACGT", None).unwrap();
+        assert_eq!(
+            String::from_utf8(fasta).unwrap(),
+            ">umi_17 reads=6 depth=5.50 identity=0.9140\nAATGCCCGTT\n>umi_18\nACGT\n"
+        );
+
+        let mut fastq = vec![];
+        write_fastq_record(&mut fastq, "umi_17", b"ACGT", b"II5I", Some(&annotations)).unwrap();
+        assert_eq!(String::from_utf8(fastq).unwrap(), "@umi_17 reads=6 depth=5.50 identity=0.9140\nACGT\n+\nII5I\n");
+
+        assert_eq!(annotations.sam_tags(), "cn:i:6\tcd:f:5.50\tci:f:0.9140");
+    }
+}
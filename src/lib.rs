@@ -5,26 +5,963 @@
 //! If you use this crate, please cite the original authors of SPOA:
 //!
 //! [Vaser, R., Sović, I., Nagarajan, N. and Šikić, M., 2017. Fast and accurate de novo genome assembly from long uncorrected reads. Genome research, 27(5), pp.737-746.](https://genome.cshlp.org/content/27/5/737)
+//!
+//! The API is grouped into the [`consensus`], [`msa`], [`graph`] and [`io`] modules, and
+//! everything is also available from the crate root. `use rust_spoa::prelude::*;` imports the
+//! items most programs need. The [`ffi`] module binds the graph and alignment engine of SPOA on
+//! their own, for building what `PoaGraph` does not offer.
+
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::fmt;
+use std::str::FromStr;
+
+mod align;
+mod alphabet;
+mod ambiguity;
+#[cfg(feature = "async")]
+mod asynchronous;
+mod backbone;
+mod cancel;
+mod case;
+mod circular;
+mod clusters;
+mod compress;
+pub mod consensus;
+mod coverage;
+mod engine;
+mod error;
+mod fallback;
+mod fast;
+pub mod ffi;
+mod filter;
+pub mod graph;
+mod groups;
+mod hierarchical;
+mod hmm;
+pub mod io;
+mod limits;
+mod matrix;
+mod memory;
+mod mode;
+pub mod msa;
+mod ordering;
+mod orient;
+mod polish;
+mod protein;
+#[cfg(feature = "pure-rust")]
+mod pure;
+mod qc;
+pub mod prelude;
+mod progress;
+mod quality;
+mod record;
+mod reproducible;
+mod reject;
+mod resample;
+mod rng;
+mod seq;
+mod simd;
+mod streaming;
+pub mod simulate;
+mod train;
+mod windowed;
 
+pub use alphabet::{validate_sequences, Alphabet};
+pub use ambiguity::AmbiguousBases;
+#[cfg(feature = "async")]
+pub use asynchronous::{poa_consensus_async, ConsensusJobs};
+pub use backbone::{
+    poa_consensus_guided, poa_consensus_on_backbone, poa_consensus_on_backbone_anchored, poa_consensus_on_backbone_region, poa_polish_backbone,
+    Correction, GuidedConsensus, PolishedBackbone, RegionConsensus,
+};
+pub use cancel::{try_poa_consensus_cancellable, CancelToken};
+pub use case::CasePolicy;
+pub use circular::{circular_rotations, poa_consensus_circular, CircularConsensus, CircularOrigin, Rotation};
+pub use clusters::{poa_consensus_clusters, ClusterConsensus};
+pub use compress::{homopolymer_compress, poa_consensus_compressed, CompressedConsensus};
+pub use consensus::{Consensus, ConsensusAlgorithm, ConsensusExt};
+pub use coverage::{mask_low_coverage, poa_consensus_trimmed, poa_consensus_with_min_coverage, trim_low_coverage_ends, CoverageHistogram, LowCoverage};
+pub use engine::ConsensusEngine;
+pub use error::PoaError;
+pub use fallback::{poa_consensus_with_fallback, FallbackConsensus, FallbackPolicy};
+pub use fast::small_group_consensus;
+pub use filter::{poa_consensus_filtered, ReadFilter};
+pub use graph::{Edge, GraphAlignment, GraphStats, Node, PoaGraph, ReadAlignment, Realignment, SoftClips, WeightedPath};
+#[cfg(feature = "debug-dp")]
+pub use graph::DpMatrix;
+pub use groups::{
+    group_consensus_by, poa_consensus_batch, poa_consensus_batch_with_threads, poa_consensus_groups, poa_consensus_groups_with_options,
+    poa_consensus_of_groups, GroupBy, GroupConsensus, GroupFlush, GroupOptions, GroupSummary, KeyedConsensus, SmallGroups,
+};
+pub use hierarchical::poa_consensus_hierarchical;
+pub use hmm::{hmm_polish, HmmPolishOptions, HmmPolished};
+pub use io::{
+    poa_consensus_from_fasta, poa_consensus_from_fastq, poa_consensus_from_path, read_seq_records, read_seq_records_from,
+    try_poa_consensus_packed, write_fasta_record, write_fastq_record, write_sam, write_sam_alignments, ConsensusAnnotations, DedupFasta,
+    DedupSize, FaiEntry, FastaIndex, FileConsensus, IndexedFasta, PackedSeq, Packing,
+};
+#[cfg(feature = "arrow")]
+pub use io::{arrow_groups, poa_consensus_of_arrow, ArrowColumns};
+#[cfg(feature = "bio")]
+pub use io::{poa_consensus_of_fasta, poa_consensus_of_fastq};
+#[cfg(feature = "mmap")]
+pub use io::{FastaRecord, MappedFasta};
+#[cfg(feature = "noodles")]
+pub use io::write_bam;
+pub use limits::{poa_consensus_with_limits, GrowthLimits};
+pub use matrix::{poa_consensus_with_matrix, Matrix, SubstitutionMatrix};
+pub use memory::estimate_memory;
+pub use mode::{choose_alignment_type, AlignmentType};
+pub use msa::{
+    base_counts, column_consensus, column_consensus_gapped, identity_matrix, poa_consensus_with_ambiguity, poa_consensus_with_variants,
+    poa_msa, poa_msa_with_qualities, variant_sites, write_clustal, write_fasta_msa, write_stockholm, Allele, AmbiguityCode, BaseCounts,
+    Column, ColumnStrategy, IndelStats, Majority, Profile, ProfileAlignment, QualityMsa, VariantSite, WeightedMajority, GAP_QUALITY,
+};
+pub use ordering::{poa_consensus_ordered, OrderedConsensus, ReadOrder};
+pub use orient::{poa_consensus_oriented, OrientedConsensus, Strand};
+pub use polish::{polish, polish_contig, polish_from_paths, read_paf, PafRecord, PolishOptions};
+pub use progress::{try_poa_consensus_with_progress, ProgressEvent};
+pub use protein::try_poa_consensus_protein;
+pub use qc::{poa_consensus_with_read_qc, ReadQc};
+pub use quality::{calibration_samples, poa_consensus_with_quality, CalibrationSample, Platform, QualityModel};
+pub use record::{poa_consensus_of_records, poa_consensus_with_qualities, poa_consensus_with_read_weights, SeqRecord};
+pub use reject::{poa_consensus_outcome, poa_consensus_with_report, ConsensusOutcome, ConsensusReport, RejectReason, RejectedRead, RejectionLog};
+pub use resample::{poa_concordance, poa_consensus_bootstrap, BootstrapConsensus, Concordance};
+pub use simd::SimdBackend;
+pub use streaming::{poa_consensus_until_stable, StableConsensus, StreamingConsensus};
+pub use train::{consensus_accuracy, train_params, ParameterGrid, ParameterSearch, TrainedParams, TrainingGroup};
+pub use windowed::{poa_consensus_windowed, WindowedOptions};
+
+#[cfg(feature = "pure-rust")]
+use pure::{poa_func, poa_func_support};
+
+#[cfg(not(feature = "pure-rust"))]
 extern "C" {
     fn poa_func(
         seqs: *const *const u8,
-        num_seqs: i32,
-        consensus: *const u8,
-        consensus_len: i32,
+        num_seqs: usize,
+        consensus: *mut c_void,
+        reserve: extern "C" fn(*mut c_void, usize) -> *mut u8,
+        alignment_type: i32, // 0 = local, 1 = global, 2 = gapped
+        match_score: i32,
+        mismatch_score: i32,
+        gap_open: i32,
+        gap_extend: i32,
+        gap_open2: i32,
+        gap_extend2: i32,
+        simd: i32,
+        ambiguity: i32,
+    ) -> usize;
+
+    fn poa_func_support(
+        seqs: *const *const u8,
+        num_seqs: usize,
+        buffers: *mut c_void,
+        reserve: ReserveSupport,
         alignment_type: i32, // 0 = local, 1 = global, 2 = gapped
         match_score: i32,
         mismatch_score: i32,
         gap_open: i32,
         gap_extend: i32,
-    ) -> u32;
+        gap_open2: i32,
+        gap_extend2: i32,
+        simd: i32,
+        ambiguity: i32,
+    ) -> usize;
+}
+
+/// Resizes the consensus buffer of `poa_func`, a `Vec<u8>`, to the length of the consensus.
+extern "C" fn reserve_consensus(consensus: *mut c_void, consensus_len: usize) -> *mut u8 {
+    let consensus = unsafe { &mut *(consensus as *mut Vec<u8>) };
+    consensus.resize(consensus_len, 0);
+    consensus.as_mut_ptr()
+}
+
+/// The consensus of `poa_func_support` with the agreeing fraction, the number of reads covering and
+/// the number of reads agreeing with each base.
+#[derive(Default)]
+pub(crate) struct SupportBuffers {
+    pub(crate) consensus: Vec<u8>,
+    pub(crate) support: Vec<f32>,
+    pub(crate) depth: Vec<u32>,
+    pub(crate) agreeing: Vec<u32>,
+}
+
+/// The callback sizing the buffers of `poa_func_support` for the consensus.
+pub(crate) type ReserveSupport = extern "C" fn(*mut c_void, usize, *mut *mut u8, *mut *mut f32, *mut *mut u32, *mut *mut u32);
+
+/// Resizes the buffers of `poa_func_support`, a `SupportBuffers`, to the length of the consensus.
+pub(crate) extern "C" fn reserve_support(
+    buffers: *mut c_void,
+    consensus_len: usize,
+    consensus: *mut *mut u8,
+    support: *mut *mut f32,
+    depth: *mut *mut u32,
+    agreeing: *mut *mut u32
+) {
+    let buffers = unsafe { &mut *(buffers as *mut SupportBuffers) };
+    buffers.consensus.resize(consensus_len, 0);
+    buffers.support.resize(consensus_len, 0.0);
+    buffers.depth.resize(consensus_len, 0);
+    buffers.agreeing.resize(consensus_len, 0);
+    unsafe {
+        *consensus = buffers.consensus.as_mut_ptr();
+        *support = buffers.support.as_mut_ptr();
+        *depth = buffers.depth.as_mut_ptr();
+        *agreeing = buffers.agreeing.as_mut_ptr();
+    }
+}
+
+/// Collects pointers to the input sequences, checking that each one is null terminated.
+fn seq_ptrs<T: AsRef<[u8]>>(seqs: &[T]) -> Vec<*const u8> {
+
+    let mut seq_ptrs: Vec<*const u8> = Vec::with_capacity(seqs.len());
+
+    for seq in seqs {
+        let seq = seq.as_ref();
+        if seq[seq.len()-1] != b'\0' {
+            panic!("Input sequences must be null terminated");
+        }
+        seq_ptrs.push(seq.as_ptr());
+    }
+
+    seq_ptrs
+}
+
+/// Aligns sequences, each up to its null terminator, to a `PoaGraph`, for the parameters
+/// `poa_func` does not take: homopolymer gaps, a band or end gaps.
+fn scalar_graph<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams) -> PoaGraph {
+    let mut graph = PoaGraph::new(params);
+    for seq in seqs {
+        let seq = seq.as_ref();
+        if seq.last() != Some(&b'\0') {
+            panic!("Input sequences must be null terminated");
+        }
+        let len = seq.iter().position(|&base| base == b'\0').unwrap_or(seq.len());
+        graph.try_add_sequence(&seq[..len]).unwrap_or_else(|error| panic!("{}", error));
+    }
+    graph
+}
+
+/// Gap scores used in place of the regular ones for gaps inside long homopolymer runs.
+///
+/// Nanopore reads mostly err by lengthening or shortening homopolymers, so with uniform gap scores
+/// the consensus length of a run is decided by a few reads that happen to align well rather than by
+/// the majority. Making a gap of a base inside a run of at least `min_run` equal bases, in the read
+/// or along the graph, cost less lets reads align their own run lengths instead, so the run length
+/// most reads agree on wins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HomopolymerGaps {
+    /// the shortest run of equal bases whose gaps use these scores
+    pub min_run: usize,
+    /// the gap open score inside a run
+    pub gap_open: i32,
+    /// the gap extend score inside a run
+    pub gap_extend: i32,
+}
+
+impl HomopolymerGaps {
+    pub fn new(min_run: usize, gap_open: i32, gap_extend: i32) -> HomopolymerGaps {
+        HomopolymerGaps { min_run, gap_open, gap_extend }
+    }
+}
+
+/// A second pair of gap scores making gap scores convex: a gap is scored with whichever pair
+/// scores it higher.
+///
+/// With an open score below `gap_open` and an extend score above `gap_extend`, short gaps are scored
+/// by the regular pair and long gaps by this one, so a long indel, common in nanopore reads, costs
+/// less than under affine scores without short gaps becoming cheap. A pair that does not open more
+/// expensively and extend more cheaply than the regular one has no effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConvexGaps {
+    /// the second gap open score
+    pub gap_open: i32,
+    /// the second gap extend score
+    pub gap_extend: i32,
+}
+
+impl ConvexGaps {
+    pub fn new(gap_open: i32, gap_extend: i32) -> ConvexGaps {
+        ConvexGaps { gap_open, gap_extend }
+    }
+}
+
+/// The gap scoring of an alignment, with the scores of each model.
+///
+/// SPOA picks the model from the gap scores: a gap open score no lower than the extend score makes
+/// gaps linear, and a second pair that does not open more expensively and extend more cheaply
+/// than the first leaves them affine. Giving the model explicitly with `PoaParams::gap_model`
+/// checks the scores against it instead, so a mistyped score cannot silently change the model.
+/// A gap of `k` bases scores `k * g` under linear scoring, `open + (k - 1) * extend` under affine
+/// scoring, and the higher of the two affine scores of its pairs under convex scoring.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GapModel {
+    /// every gap base scores the same
+    Linear(i32),
+    /// opening a gap scores `open`, each further base `extend`
+    Affine {
+        /// the score of the first base of a gap
+        open: i32,
+        /// the score of each further base of a gap
+        extend: i32,
+    },
+    /// the higher score of two affine pairs, the second for long gaps
+    Convex {
+        /// the score of the first base of a short gap
+        open1: i32,
+        /// the score of each further base of a short gap
+        extend1: i32,
+        /// the score of the first base of a long gap, below `open1`
+        open2: i32,
+        /// the score of each further base of a long gap, above `extend1`
+        extend2: i32,
+    },
+}
+
+impl GapModel {
+    /// Checks that the scores are negative or 0, fit in 8 bits, and give the model: an affine
+    /// pair must open more expensively than it extends, and the second pair of a convex model must
+    /// open more expensively and extend more cheaply than the first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{GapModel, PoaError};
+    ///
+    ///     assert_eq!(GapModel::Affine { open: -8, extend: -6 }.validate(), Ok(()));
+    ///     // gap scores are negative, and the pair would be linear
+    ///     assert!(matches!(GapModel::Affine { open: 8, extend: 6 }.validate(), Err(PoaError::InvalidScores(_))));
+    ///     assert!(GapModel::Affine { open: -6, extend: -8 }.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), PoaError> {
+        let invalid = |message: String| Err(PoaError::InvalidScores(message));
+        let scores = match *self {
+            GapModel::Linear(score) => vec![("gap", score)],
+            GapModel::Affine { open, extend } => vec![("gap_open", open), ("gap_extend", extend)],
+            GapModel::Convex { open1, extend1, open2, extend2 } => {
+                vec![("gap_open", open1), ("gap_extend", extend1), ("gap_open2", open2), ("gap_extend2", extend2)]
+            }
+        };
+        for &(name, score) in &scores {
+            if score > 0 || i8::try_from(score).is_err() {
+                return invalid(format!("{} {} is not in -128..=0", name, score));
+            }
+        }
+        match *self {
+            GapModel::Linear(_) => Ok(()),
+            GapModel::Affine { open, extend } | GapModel::Convex { open1: open, extend1: extend, .. } if open >= extend => {
+                invalid(format!("gap_open {} is not below gap_extend {}, which makes gaps linear", open, extend))
+            }
+            GapModel::Convex { open1, extend1, open2, extend2 } if open2 >= open1 || extend2 <= extend1 => invalid(format!(
+                "gap_open2 {} is not below gap_open {} or gap_extend2 {} is not above gap_extend {}, which makes gaps affine",
+                open2, open1, extend2, extend1
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The width of the band of a banded alignment: how far from the diagonal, in bases, the cells of
+/// the dynamic programming matrix are computed.
+///
+/// The band of a node is centred on its distance from the start of the graph along the heaviest
+/// edges into it, so it suits reads that span the region of the graph, differ from it in length
+/// by less than the width and are too similar to drift far from the diagonal, such as the reads of
+/// a long amplicon.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BandWidth {
+    /// the given number of bases on either side of the diagonal
+    Fixed(usize),
+    /// the difference in length between the read and the graph, plus 2% of the read length and 10
+    /// bases for the indels of reads over 98% identical
+    Auto,
+}
+
+impl BandWidth {
+    /// Returns the number of bases on either side of the diagonal to align a read of `read_len`
+    /// bases to a graph `graph_len` bases long in.
+    pub fn width(&self, read_len: usize, graph_len: usize) -> usize {
+        match *self {
+            BandWidth::Fixed(width) => width,
+            BandWidth::Auto => (read_len.max(graph_len) - read_len.min(graph_len)) + read_len / 50 + 10,
+        }
+    }
+}
+
+/// Which end gaps of an alignment are free, for the read and the graph independently.
+///
+/// A free leading gap of the read leaves the bases before its first aligned base unscored, and a
+/// free trailing gap those after its last; a free leading gap of the graph lets the read start at
+/// any node, and a free trailing one end at any node rather than at the end of the graph. Global
+/// alignment frees no end, and the semi-global mode of SPOA the start of the graph and the end of
+/// the read. Reads anchored by an adapter at their 5' end, for example, align with only the end
+/// of the read free, so gaps at the adapter end stay penalized.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{AlignmentType, EndGaps};
+///
+///     let anchored = EndGaps { query_end: true, ..EndGaps::global() };
+///     assert_eq!(anchored.to_string(), "query_end");
+///     assert_eq!("graph_start,query_end".parse().ok(), EndGaps::of(AlignmentType::SemiGlobal));
+///     assert_eq!("none".parse(), Ok(EndGaps::global()));
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct EndGaps {
+    /// whether the bases of the read before its alignment are free
+    pub query_start: bool,
+    /// whether the bases of the read after its alignment are free
+    pub query_end: bool,
+    /// whether the alignment can start past the start of the graph for free
+    pub graph_start: bool,
+    /// whether the alignment can end before the end of the graph for free
+    pub graph_end: bool,
+}
+
+impl EndGaps {
+    /// Returns the end gaps of global alignment, none of them free.
+    pub fn global() -> EndGaps {
+        EndGaps::default()
+    }
+
+    /// Returns the end gaps of an alignment mode: none free for global alignment, the start of the
+    /// graph and the end of the read for semi-global alignment, and every end for local alignment.
+    /// Returns `None` for `AlignmentType::Auto`, which is chosen per group of reads.
+    pub fn of(alignment_type: AlignmentType) -> Option<EndGaps> {
+        match alignment_type {
+            AlignmentType::Local => Some(EndGaps { query_start: true, query_end: true, graph_start: true, graph_end: true }),
+            AlignmentType::Global => Some(EndGaps::global()),
+            AlignmentType::SemiGlobal => Some(EndGaps { graph_start: true, query_end: true, ..EndGaps::global() }),
+            AlignmentType::Auto => None,
+        }
+    }
+
+    // the names of the ends, in the order they are formatted, and whether each is free
+    fn ends(&self) -> [(&'static str, bool); 4] {
+        [("query_start", self.query_start), ("query_end", self.query_end), ("graph_start", self.graph_start), ("graph_end", self.graph_end)]
+    }
+}
+
+impl fmt::Display for EndGaps {
+    /// Formats the free ends separated by commas, or `none`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let free: Vec<&str> = self.ends().iter().filter(|&&(_, free)| free).map(|&(name, _)| name).collect();
+        if free.is_empty() {
+            f.write_str("none")
+        } else {
+            f.write_str(&free.join(","))
+        }
+    }
+}
+
+impl FromStr for EndGaps {
+    type Err = PoaError;
+
+    fn from_str(s: &str) -> Result<EndGaps, PoaError> {
+        let mut gaps = EndGaps::global();
+        if s == "none" {
+            return Ok(gaps);
+        }
+        for end in s.split(',') {
+            let free = match end {
+                "query_start" => &mut gaps.query_start,
+                "query_end" => &mut gaps.query_end,
+                "graph_start" => &mut gaps.graph_start,
+                "graph_end" => &mut gaps.graph_end,
+                _ => return Err(PoaError::InvalidPreset(format!("unknown end gap {}", end))),
+            };
+            if *free {
+                return Err(PoaError::InvalidPreset(format!("end gap {} is given twice", end)));
+            }
+            *free = true;
+        }
+        Ok(gaps)
+    }
+}
+
+/// A set of alignment and scoring parameters for a single consensus run.
+///
+/// The fields have the same meaning as the corresponding arguments of `poa_consensus`, apart from
+/// `homopolymer_gaps`, `band_width` and `end_gaps`, which `poa_consensus` does not take: with any
+/// of them set, every consensus function and `PoaGraph` method aligning reads does so with a
+/// scalar engine honoring them, with the regular gap scores and without `convex_gaps` outside
+/// runs. Rather than passing every score to `new` by position, parameters can be built from
+/// `dna()` or `protein()` with the setters named after the fields; the default is `dna()`. `simd`
+/// only changes the speed of alignment, not its result.
+/// `case_policy` decides how lower-case bases are aligned, by every function taking parameters,
+/// and `ambiguous_bases` how IUPAC ambiguity codes such as `N` are scored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoaParams {
+    /// alignment mode: 0 = local, 1 = global, 2 = gapped
+    pub alignment_type: i32,
+    /// the match score for alignment
+    pub match_score: i32,
+    /// the mismatch score for alignment
+    pub mismatch_score: i32,
+    /// the gap open score for alignment
+    pub gap_open: i32,
+    /// the gap extend score for alignment
+    pub gap_extend: i32,
+    /// the second pair of gap scores of convex gap scoring, if gap scores are not simply affine
+    pub convex_gaps: Option<ConvexGaps>,
+    /// the gap scores inside long homopolymer runs, if they differ from the regular ones
+    pub homopolymer_gaps: Option<HomopolymerGaps>,
+    /// the band sequences are aligned in, if alignment is banded
+    pub band_width: Option<BandWidth>,
+    /// the free end gaps, if they differ from those of the alignment type
+    pub end_gaps: Option<EndGaps>,
+    /// the instruction set of the alignment engine
+    pub simd: SimdBackend,
+    /// how lower-case bases are aligned
+    pub case_policy: CasePolicy,
+    /// how pairs with an ambiguous base are scored
+    pub ambiguous_bases: AmbiguousBases,
+}
+
+impl PoaParams {
+    pub fn new(
+        alignment_type: i32,
+        match_score: i32,
+        mismatch_score: i32,
+        gap_open: i32,
+        gap_extend: i32
+    ) -> PoaParams {
+        PoaParams {
+            alignment_type,
+            match_score,
+            mismatch_score,
+            gap_open,
+            gap_extend,
+            convex_gaps: None,
+            homopolymer_gaps: None,
+            band_width: None,
+            end_gaps: None,
+            simd: SimdBackend::Auto,
+            case_policy: CasePolicy::Uppercase,
+            ambiguous_bases: AmbiguousBases::Literal,
+        }
+    }
+
+    /// Returns the default scores of SPOA for nucleotides, with global alignment: match 5,
+    /// mismatch -4, gap open -8 and gap extend -6.
+    ///
+    /// The other methods setting a single parameter start from these, so only the parameters that
+    /// differ need naming, and none can be passed in the wrong position.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{AlignmentType, PoaParams};
+    ///
+    ///     let params = PoaParams::dna().alignment_type(AlignmentType::SemiGlobal)?.gap_extend(-2);
+    ///     assert_eq!(params, PoaParams::new(2, 5, -4, -8, -2));
+    ///     # Ok::<(), rust_spoa::PoaError>(())
+    /// ```
+    pub fn dna() -> PoaParams {
+        PoaParams::new(1, 5, -4, -8, -6)
+    }
+
+    /// Returns scores suited to protein sequences, with global alignment: match 4, mismatch -2,
+    /// gap open -11 and gap extend -1, the affine gap scores commonly used with BLOSUM62.
+    pub fn protein() -> PoaParams {
+        PoaParams::new(1, 4, -2, -11, -1)
+    }
+
+    /// Sets the alignment mode.
+    ///
+    /// # Returns
+    /// * returns the parameters, or `PoaError::UnresolvedAlignmentType` for `AlignmentType::Auto`,
+    ///   which is chosen per group of reads with `auto_alignment_type`
+    pub fn alignment_type(mut self, alignment_type: AlignmentType) -> Result<PoaParams, PoaError> {
+        self.alignment_type = alignment_type.code().ok_or(PoaError::UnresolvedAlignmentType)?;
+        Ok(self)
+    }
+
+    /// Sets the match score.
+    ///
+    /// # Panics
+    /// Panics unless the score is positive and fits in 8 bits.
+    pub fn match_score(mut self, score: i32) -> PoaParams {
+        assert!(score > 0 && score <= i32::from(i8::MAX), "match score {} is not in 1..=127", score);
+        self.match_score = score;
+        self
+    }
+
+    /// Sets the mismatch score.
+    ///
+    /// # Panics
+    /// Panics unless the score fits in 8 bits.
+    pub fn mismatch_score(mut self, score: i32) -> PoaParams {
+        assert!(i8::try_from(score).is_ok(), "mismatch score {} does not fit in 8 bits", score);
+        self.mismatch_score = score;
+        self
+    }
+
+    /// Sets the gap open score.
+    ///
+    /// # Panics
+    /// Panics unless the score is not positive and fits in 8 bits.
+    pub fn gap_open(mut self, score: i32) -> PoaParams {
+        assert!(score <= 0 && score >= i32::from(i8::MIN), "gap open score {} is not in -128..=0", score);
+        self.gap_open = score;
+        self
+    }
+
+    /// Sets the gap extend score.
+    ///
+    /// # Panics
+    /// Panics unless the score is not positive and fits in 8 bits.
+    pub fn gap_extend(mut self, score: i32) -> PoaParams {
+        assert!(score <= 0 && score >= i32::from(i8::MIN), "gap extend score {} is not in -128..=0", score);
+        self.gap_extend = score;
+        self
+    }
+
+    /// Sets the gap scores of the given model, replacing the gap scores and any second pair.
+    ///
+    /// # Panics
+    /// Panics if `model.validate()` fails.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{GapModel, PoaParams};
+    ///
+    ///     let params = PoaParams::dna().gap_model(GapModel::Linear(-4));
+    ///     assert_eq!((params.gap_open, params.gap_extend, params.convex_gaps), (-4, -4, None));
+    ///     assert_eq!(params.gaps(), GapModel::Linear(-4));
+    ///
+    ///     let convex = GapModel::Convex { open1: -8, extend1: -6, open2: -24, extend2: -1 };
+    ///     assert_eq!(PoaParams::dna().gap_model(convex).gaps(), convex);
+    /// ```
+    pub fn gap_model(mut self, model: GapModel) -> PoaParams {
+        if let Err(error) = model.validate() {
+            panic!("{}", error);
+        }
+        let (gap_open, gap_extend, convex_gaps) = match model {
+            GapModel::Linear(score) => (score, score, None),
+            GapModel::Affine { open, extend } => (open, extend, None),
+            GapModel::Convex { open1, extend1, open2, extend2 } => (open1, extend1, Some(ConvexGaps::new(open2, extend2))),
+        };
+        self.gap_open = gap_open;
+        self.gap_extend = gap_extend;
+        self.convex_gaps = convex_gaps;
+        self
+    }
+
+    /// Returns the gap model the alignment engine picks from the gap scores, with the scores it uses.
+    pub fn gaps(&self) -> GapModel {
+        let (gap_open2, gap_extend2) = self.second_gaps();
+        if self.gap_open >= self.gap_extend {
+            GapModel::Linear(self.gap_open)
+        } else if self.gap_open <= gap_open2 || self.gap_extend >= gap_extend2 {
+            GapModel::Affine { open: self.gap_open, extend: self.gap_extend }
+        } else {
+            GapModel::Convex { open1: self.gap_open, extend1: self.gap_extend, open2: gap_open2, extend2: gap_extend2 }
+        }
+    }
+
+    /// Sets a second pair of gap scores, making gap scores convex.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{ConvexGaps, PoaParams};
+    ///
+    ///     let params = PoaParams::dna().convex_gaps(ConvexGaps::new(-24, -1));
+    ///     assert_eq!(params.to_string(), "alignment_type=1 match_score=5 mismatch_score=-4 gap_open=-8 gap_extend=-6 gap_open2=-24 gap_extend2=-1");
+    ///     assert_eq!(params.to_string().parse::<PoaParams>(), Ok(params));
+    /// ```
+    pub fn convex_gaps(mut self, gaps: ConvexGaps) -> PoaParams {
+        self.convex_gaps = Some(gaps);
+        self
+    }
+
+    // the second gap pair passed to the alignment engine, the regular pair for affine gap scores
+    pub(crate) fn second_gaps(&self) -> (i32, i32) {
+        match self.convex_gaps {
+            Some(gaps) => (gaps.gap_open, gaps.gap_extend),
+            None => (self.gap_open, self.gap_extend),
+        }
+    }
+
+    /// Sets the gap scores inside long homopolymer runs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{HomopolymerGaps, PoaParams};
+    ///
+    ///     let params = PoaParams::new(1, 5, -4, -8, -6).homopolymer_gaps(HomopolymerGaps::new(4, -3, -1));
+    ///     assert_eq!(params.to_string().parse::<PoaParams>(), Ok(params));
+    /// ```
+    pub fn homopolymer_gaps(mut self, gaps: HomopolymerGaps) -> PoaParams {
+        self.homopolymer_gaps = Some(gaps);
+        self
+    }
+
+    /// Sets the band sequences are aligned in.
+    ///
+    /// A banded alignment only computes the cells of the dynamic programming matrix near the
+    /// diagonal, so aligning a read takes time and memory proportional to its length times the band
+    /// rather than times the graph size. Reads that do not fit in the band align poorly.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{BandWidth, PoaGraph, PoaParams};
+    ///
+    ///     let params = PoaParams::dna().band_width(BandWidth::Fixed(8));
+    ///     assert_eq!(params.to_string().parse::<PoaParams>(), Ok(params));
+    ///
+    ///     let mut graph = PoaGraph::new(&params);
+    ///     for seq in ["GATTACAGGCTTACCGATCG", "GATTACAGGCTACCGATCG", "GATTACAGGCTTACCGATCG"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///     assert_eq!(graph.consensus(), b"GATTACAGGCTTACCGATCG".to_vec());
+    /// ```
+    pub fn band_width(mut self, band: BandWidth) -> PoaParams {
+        self.band_width = Some(band);
+        self
+    }
+
+    /// Sets which end gaps are free, in place of those of the alignment type; with local alignment
+    /// every end is free regardless.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{EndGaps, PoaGraph, PoaParams};
+    ///
+    ///     let params = PoaParams::dna().end_gaps(EndGaps { query_end: true, ..EndGaps::global() });
+    ///     assert_eq!(params.to_string().parse::<PoaParams>(), Ok(params));
+    ///
+    ///     let mut graph = PoaGraph::new(&params);
+    ///     for seq in ["GATTACAGGCTTACCGATCG", "GATTACAGGCTACCGATCG", "GATTACAGGCTTACCGATCG"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///     // read bases past the end of the amplicon are free, before its start they are gaps
+    ///     assert_eq!(graph.align(b"GATTACAGGCTTACCGATCGAAAA").score, 100);
+    ///     assert_eq!(graph.align(b"AAAAGATTACAGGCTTACCGATCG").score, 100 - 8 - 3 * 6);
+    /// ```
+    pub fn end_gaps(mut self, gaps: EndGaps) -> PoaParams {
+        self.end_gaps = Some(gaps);
+        self
+    }
+
+    // whether sequences are aligned in Rust rather than by the native alignment engine, for the
+    // parameters it does not support
+    pub(crate) fn is_scalar(&self) -> bool {
+        self.homopolymer_gaps.is_some() || self.band_width.is_some() || self.end_gaps.is_some()
+    }
+
+    /// Checks that the alignment engine accepts the parameters.
+    ///
+    /// The alignment type must be 0, 1 or 2, the scores must fit in 8 bits as the engine stores
+    /// them, the gap scores must not be positive and a match must score more than a mismatch.
+    /// Ambiguous bases can only be scored other than literally without convex gaps.
+    pub fn validate(&self) -> Result<(), PoaError> {
+        if AlignmentType::from_code(self.alignment_type).is_none() {
+            return Err(PoaError::InvalidAlignmentType(self.alignment_type));
+        }
+        let invalid = |message: String| Err(PoaError::InvalidScores(message));
+        let (gap_open2, gap_extend2) = self.second_gaps();
+        let scores = [
+            ("match_score", self.match_score),
+            ("mismatch_score", self.mismatch_score),
+            ("gap_open", self.gap_open),
+            ("gap_extend", self.gap_extend),
+            ("gap_open2", gap_open2),
+            ("gap_extend2", gap_extend2),
+        ];
+        for &(name, score) in scores.iter() {
+            if i8::try_from(score).is_err() {
+                return invalid(format!("{} {} does not fit in 8 bits", name, score));
+            }
+        }
+        let mut gaps = vec![
+            ("gap_open", self.gap_open),
+            ("gap_extend", self.gap_extend),
+            ("gap_open2", gap_open2),
+            ("gap_extend2", gap_extend2),
+        ];
+        if let Some(homopolymer) = self.homopolymer_gaps {
+            gaps.push(("homopolymer_gap_open", homopolymer.gap_open));
+            gaps.push(("homopolymer_gap_extend", homopolymer.gap_extend));
+        }
+        for &(name, score) in &gaps {
+            if score > 0 {
+                return invalid(format!("{} {} is positive", name, score));
+            }
+        }
+        if self.match_score <= self.mismatch_score {
+            return invalid(format!("match_score {} is not above mismatch_score {}", self.match_score, self.mismatch_score));
+        }
+        if self.convex_gaps.is_some() && self.ambiguous_bases != AmbiguousBases::Literal {
+            return invalid(format!("ambiguous_bases {} does not support convex gaps", self.ambiguous_bases));
+        }
+        Ok(())
+    }
+}
+
+impl Default for PoaParams {
+    fn default() -> PoaParams {
+        PoaParams::dna()
+    }
+}
+
+impl fmt::Display for PoaParams {
+    /// Formats the parameters as a preset of `key=value` fields, which `str::parse` reads back.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "alignment_type={} match_score={} mismatch_score={} gap_open={} gap_extend={}",
+            self.alignment_type, self.match_score, self.mismatch_score, self.gap_open, self.gap_extend
+        )?;
+        if let Some(gaps) = self.convex_gaps {
+            write!(f, " gap_open2={} gap_extend2={}", gaps.gap_open, gaps.gap_extend)?;
+        }
+        if let Some(gaps) = self.homopolymer_gaps {
+            write!(
+                f,
+                " homopolymer_run={} homopolymer_gap_open={} homopolymer_gap_extend={}",
+                gaps.min_run, gaps.gap_open, gaps.gap_extend
+            )?;
+        }
+        match self.band_width {
+            Some(BandWidth::Fixed(width)) => write!(f, " band_width={}", width)?,
+            Some(BandWidth::Auto) => write!(f, " band_width=auto")?,
+            None => {}
+        }
+        if let Some(gaps) = self.end_gaps {
+            write!(f, " end_gaps={}", gaps)?;
+        }
+        if self.simd != SimdBackend::Auto {
+            write!(f, " simd={}", self.simd)?;
+        }
+        if self.case_policy != CasePolicy::Uppercase {
+            write!(f, " case_policy={}", self.case_policy)?;
+        }
+        if self.ambiguous_bases != AmbiguousBases::Literal {
+            write!(f, " ambiguous_bases={}", self.ambiguous_bases)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PoaParams {
+    type Err = PoaError;
+
+    /// Parses a preset written by `Display`: whitespace-separated `key=value` fields naming every
+    /// parameter once, in any order. The two second gap fields and the three homopolymer gap fields
+    /// are each given all together or not at all, and `band_width`, a width or `auto`, `end_gaps`,
+    /// `simd`, `case_policy` and `ambiguous_bases` are optional.
+    fn from_str(s: &str) -> Result<PoaParams, PoaError> {
+        let invalid = |message: &str| PoaError::InvalidPreset(message.to_string());
+        let mut values: [Option<i32>; 10] = [None; 10];
+        let mut simd = None;
+        let mut band_width = None;
+        let mut end_gaps = None;
+        let mut case_policy = None;
+        let mut ambiguous_bases = None;
+
+        for field in s.split_whitespace() {
+            let (key, value) = field.split_once('=').ok_or_else(|| invalid(&format!("{} is not key=value", field)))?;
+            if key == "simd" {
+                if simd.is_some() {
+                    return Err(invalid("simd is given twice"));
+                }
+                simd = Some(value.parse()?);
+                continue;
+            }
+            if key == "case_policy" {
+                if case_policy.is_some() {
+                    return Err(invalid("case_policy is given twice"));
+                }
+                case_policy = Some(value.parse()?);
+                continue;
+            }
+            if key == "ambiguous_bases" {
+                if ambiguous_bases.is_some() {
+                    return Err(invalid("ambiguous_bases is given twice"));
+                }
+                ambiguous_bases = Some(value.parse()?);
+                continue;
+            }
+            if key == "band_width" {
+                if band_width.is_some() {
+                    return Err(invalid("band_width is given twice"));
+                }
+                band_width = Some(match value {
+                    "auto" => BandWidth::Auto,
+                    _ => BandWidth::Fixed(value.parse().map_err(|_| invalid(&format!("{} is not a width or auto", field)))?),
+                });
+                continue;
+            }
+            if key == "end_gaps" {
+                if end_gaps.is_some() {
+                    return Err(invalid("end_gaps is given twice"));
+                }
+                end_gaps = Some(value.parse()?);
+                continue;
+            }
+            let i = match key {
+                "alignment_type" => 0,
+                "match_score" => 1,
+                "mismatch_score" => 2,
+                "gap_open" => 3,
+                "gap_extend" => 4,
+                "homopolymer_run" => 5,
+                "homopolymer_gap_open" => 6,
+                "homopolymer_gap_extend" => 7,
+                "gap_open2" => 8,
+                "gap_extend2" => 9,
+                _ => return Err(invalid(&format!("unknown parameter {}", key))),
+            };
+            if values[i].is_some() {
+                return Err(invalid(&format!("{} is given twice", key)));
+            }
+            values[i] = Some(value.parse().map_err(|_| invalid(&format!("{} is not an integer", field)))?);
+        }
+
+        let mut params = match values[..5] {
+            [Some(alignment_type), Some(match_score), Some(mismatch_score), Some(gap_open), Some(gap_extend)] => {
+                PoaParams::new(alignment_type, match_score, mismatch_score, gap_open, gap_extend).simd(simd.unwrap_or_default())
+            }
+            _ => return Err(invalid("a parameter is missing")),
+        };
+        params.band_width = band_width;
+        params.end_gaps = end_gaps;
+        params.case_policy = case_policy.unwrap_or_default();
+        params.ambiguous_bases = ambiguous_bases.unwrap_or_default();
+        match values[8..] {
+            [None, None] => {}
+            [Some(gap_open), Some(gap_extend)] => params = params.convex_gaps(ConvexGaps::new(gap_open, gap_extend)),
+            _ => return Err(invalid("a second gap parameter is missing")),
+        }
+        match values[5..8] {
+            [None, None, None] => Ok(params),
+            [Some(min_run), Some(gap_open), Some(gap_extend)] if min_run >= 0 => {
+                Ok(params.homopolymer_gaps(HomopolymerGaps::new(min_run as usize, gap_open, gap_extend)))
+            }
+            [Some(_), Some(_), Some(_)] => Err(invalid("homopolymer_run is negative")),
+            _ => Err(invalid("a homopolymer gap parameter is missing")),
+        }
+    }
 }
 
 /// Generates a consensus sequence from a list of sequences.
+///
+/// This panics on reads without a null terminator, aborts on an invalid alignment type and
+/// silently truncates the consensus; `try_poa_consensus` reports these cases as errors instead,
+/// and `poa_consensus_unbounded` never truncates.
 /// # Arguments
 ///
-/// * `seqs` - a vector holding the sequences (each as a null-terminated vector of u8) to form a consensus from
-/// * `consensus_max_len` - The upper bound for the output consensus length. If the output consensus sequence is longer than this value, it will be truncated to this length. Setting a large value uses more memory and runtime, since a buffer of this size is allocated internally.
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_len` - The upper bound for the output consensus length. If the output consensus sequence is longer than this value, it will be truncated to this length. 0 keeps the whole consensus.
 /// * `alignment_type` - alignment mode: 0 = local, 1 = global, 2 = gapped
 /// * `match_score` - the match score for alignment
 /// * `mismatch_score` - the mismatch score for alignment
@@ -50,7 +987,7 @@ extern "C" {
 ///            "AACGCCCGTC\0",
 ///            "AGTGCTCGTT\0",
 ///            "AATGCTCGTT\0"].iter() {
-///            seqs.push((*seq).bytes().map(|x|{x as u8}).collect::<Vec<u8>>());
+///            seqs.push((*seq).bytes().collect::<Vec<u8>>());
 ///        }
 ///
 ///        // generate consensus sequence
@@ -60,9 +997,9 @@ extern "C" {
 ///        assert_eq!(consensus, expected);
 ///    }
 /// ```
-
-pub fn poa_consensus(
-    seqs: &Vec<Vec<u8>>,
+#[deprecated(note = "use try_poa_consensus, which reports invalid input and truncation as errors")]
+pub fn poa_consensus<T: AsRef<[u8]>>(
+    seqs: &[T],
     consensus_max_length: usize,
     alignment_type: i32,
     match_score: i32,
@@ -70,40 +1007,361 @@ pub fn poa_consensus(
     gap_open: i32,
     gap_extend: i32
 ) -> Vec<u8> {
+    let params = PoaParams::new(alignment_type, match_score, mismatch_score, gap_open, gap_extend);
+    native_consensus(seqs, consensus_max_length, &params)
+}
 
-    let mut consensus: Vec<u8> = vec![0; consensus_max_length];
-
-    let num_seqs = seqs.len() as i32;
-    let consensus_len = consensus.len() as i32;
+/// Generates a consensus sequence from a list of sequences, however long it is.
+///
+/// The consensus buffer is sized by the native side once the consensus is known, so there is no
+/// maximum length to guess and nothing is truncated.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the input sequences as a vector of u8
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_unbounded, PoaParams};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCGTT\0".to_vec(), b"AATGCCCGAT\0".to_vec()];
+///     let consensus = poa_consensus_unbounded(&seqs, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+///
+///     // borrowed reads are used in place
+///     let reads: [&[u8]; 3] = [b"AATGCCCGTT\0", b"AATGCCGTT\0", b"AATGCCCGAT\0"];
+///     assert_eq!(poa_consensus_unbounded(&reads, &PoaParams::new(1, 5, -4, -3, -1)), consensus);
+/// ```
+pub fn poa_consensus_unbounded<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams) -> Vec<u8> {
+    native_consensus(seqs, 0, params)
+}
 
-    let mut seq_ptrs: Vec<*const u8> = Vec::with_capacity(seqs.len());
+/// Generates a consensus sequence like `poa_consensus`, taking the parameters as `PoaParams`,
+/// for the functions layered on top of it.
+pub(crate) fn native_consensus<T: AsRef<[u8]>>(seqs: &[T], consensus_max_length: usize, params: &PoaParams) -> Vec<u8> {
 
-    for seq in seqs {
-        if !(seq[seq.len()-1] == '\0' as u8) {
-            panic!("Input sequences must be null terminated");
+    if params.is_scalar() {
+        let mut consensus = scalar_graph(seqs, params).consensus();
+        if consensus_max_length > 0 {
+            consensus.truncate(consensus_max_length);
         }
-        seq_ptrs.push(seq.as_ptr());
+        return consensus;
     }
 
+    let mut consensus: Vec<u8> = vec![];
+
+    let seqs = params.case_policy.apply_all(seqs);
+    let seq_ptrs = seq_ptrs(&seqs);
+    let (gap_open2, gap_extend2) = params.second_gaps();
+
     unsafe {
 
-        let len = poa_func(
+        poa_func(
             seq_ptrs.as_ptr(),
-            num_seqs,
-            consensus.as_ptr(),
-            consensus_len,
-            alignment_type,
-            match_score,
-            mismatch_score,
-            gap_open,
-            gap_extend
+            seqs.len(),
+            &mut consensus as *mut Vec<u8> as *mut c_void,
+            reserve_consensus,
+            params.alignment_type,
+            params.match_score,
+            params.mismatch_score,
+            params.gap_open,
+            params.gap_extend,
+            gap_open2,
+            gap_extend2,
+            params.simd.code(),
+            params.ambiguous_bases.code()
         );
+    }
+    error::check_native();
 
-        consensus.truncate(len as usize);
+    if consensus_max_length > 0 {
+        consensus.truncate(consensus_max_length);
     }
+    consensus
+}
+
+/// Generates a consensus sequence from a list of sequences, reporting invalid input instead of
+/// panicking or aborting, and a consensus longer than the maximum instead of truncating it.
+///
+/// Empty reads are left out. Reads are aligned to a `PoaGraph`, so the homopolymer gap scores of
+/// `params` are used as well. Reads may hold the printable ASCII characters of `Alphabet::ascii`;
+/// `try_poa_consensus_with_alphabet` takes another alphabet.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the reads, or `PoaError::EmptyInput` if no read has bases,
+///   `PoaError::InvalidAlignmentType` or `PoaError::InvalidScores` if `params.validate()` fails,
+///   `PoaError::InvalidRead` for the first read that is not null terminated or holds a null byte,
+///   `PoaError::InvalidByte` for the first byte outside the alphabet, and `PoaError::Truncated` if
+///   the consensus is longer than `consensus_max_length`
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{try_poa_consensus, PoaError, PoaParams};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCGTT\0".to_vec(), b"AATGCCCGAT\0".to_vec()];
+///     let params = PoaParams::new(1, 5, -4, -3, -1);
+///
+///     assert_eq!(try_poa_consensus(&seqs, 0, &params), Ok(b"AATGCCCGTT".to_vec()));
+///     assert_eq!(try_poa_consensus(&seqs, 8, &params), Err(PoaError::Truncated { len: 10, max_len: 8 }));
+///     assert_eq!(try_poa_consensus(&seqs, 0, &PoaParams::new(3, 5, -4, -3, -1)), Err(PoaError::InvalidAlignmentType(3)));
+/// ```
+pub fn try_poa_consensus<T: AsRef<[u8]>>(seqs: &[T], consensus_max_length: usize, params: &PoaParams) -> Result<Vec<u8>, PoaError> {
+    try_poa_consensus_with_alphabet(seqs, consensus_max_length, params, &Alphabet::default())
+}
 
+/// Generates a consensus sequence like `try_poa_consensus`, with reads restricted to the bytes of `alphabet`.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{try_poa_consensus_with_alphabet, Alphabet, PoaError, PoaParams};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCGTT\0".to_vec(), b"AATGXCCGAT\0".to_vec()];
+///     let params = PoaParams::new(1, 5, -4, -3, -1);
+///
+///     assert_eq!(
+///         try_poa_consensus_with_alphabet(&seqs, 0, &params, &Alphabet::dna()),
+///         Err(PoaError::InvalidByte { seq_index: 2, pos: 4, byte: b'X' })
+///     );
+///     assert!(try_poa_consensus_with_alphabet(&seqs, 0, &params, &Alphabet::ascii()).is_ok());
+/// ```
+pub fn try_poa_consensus_with_alphabet<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    alphabet: &Alphabet,
+) -> Result<Vec<u8>, PoaError> {
+    try_consensus_until(seqs, consensus_max_length, params, alphabet, None, None, None)
+}
 
-    consensus
+// the body of try_poa_consensus_with_alphabet, aligning with matrix if given, returning
+// PoaError::Cancelled once token is cancelled between two reads, and reporting each read processed
+// to progress
+pub(crate) fn try_consensus_until<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    alphabet: &Alphabet,
+    matrix: Option<&SubstitutionMatrix>,
+    token: Option<&CancelToken>,
+    mut progress: Option<&mut progress::Progress>,
+) -> Result<Vec<u8>, PoaError> {
+    params.validate()?;
+
+    let mut graph = match matrix {
+        Some(matrix) => PoaGraph::try_with_matrix(params, matrix)?,
+        None => PoaGraph::try_new(params)?,
+    };
+    for (index, seq) in seqs.iter().map(AsRef::as_ref).enumerate() {
+        if matches!(token, Some(token) if token.is_cancelled()) {
+            return Err(PoaError::Cancelled { reads_added: graph.num_sequences() });
+        }
+        match reject::validate_read(seq).map_or_else(|| params.case_policy.apply(seq).err(), Some) {
+            None => match alphabet.first_invalid(seq) {
+                None => graph.try_add_sequence(&seq[..seq.len() - 1])?,
+                Some((pos, byte)) => return Err(PoaError::InvalidByte { seq_index: index, pos, byte }),
+            },
+            Some(RejectReason::Empty) => {}
+            Some(reason) => return Err(PoaError::InvalidRead(RejectedRead { index, reason })),
+        }
+        if let Some(progress) = progress.as_mut() {
+            progress.report(index + 1, &graph);
+        }
+    }
+    if graph.num_sequences() == 0 {
+        return Err(PoaError::EmptyInput);
+    }
+
+    let consensus = graph.try_consensus()?;
+    if consensus_max_length > 0 && consensus.len() > consensus_max_length {
+        return Err(PoaError::Truncated { len: consensus.len(), max_len: consensus_max_length });
+    }
+    Ok(consensus)
+}
+
+// the margin of estimate_consensus_len over the median read length
+const CONSENSUS_LEN_MARGIN: f64 = 0.5;
+
+/// Estimates a bound on the consensus length of a set of reads, e.g. for choosing a
+/// `consensus_max_length` that cuts off a runaway consensus.
+///
+/// The bound is the median read length plus a margin of 50%, which leaves room for the consensus
+/// of fragments overlapping into a longer sequence without being raised by a single long read.
+/// No function uses it by default: a `consensus_max_length` of 0 keeps the whole consensus.
+/// A trailing null terminator of a read is ignored.
+/// # Arguments
+///
+/// * `seqs` - the reads to form a consensus from
+///
+/// # Returns
+/// * returns the bound, 0 if there are no reads
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::estimate_consensus_len;
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCGTT\0".to_vec(), b"AATGCCCGTTA\0".to_vec()];
+///     assert_eq!(estimate_consensus_len(&seqs), 15);
+/// ```
+pub fn estimate_consensus_len<T: AsRef<[u8]>>(seqs: &[T]) -> usize {
+    estimate_consensus_len_with_margin(seqs, CONSENSUS_LEN_MARGIN)
+}
+
+/// Estimates a bound on the consensus length of a set of reads as `estimate_consensus_len` does,
+/// with a margin over the median read length of `margin` times that length.
+pub fn estimate_consensus_len_with_margin<T: AsRef<[u8]>>(seqs: &[T], margin: f64) -> usize {
+    let mut lengths: Vec<usize> = seqs
+        .iter()
+        .map(|seq| {
+            let seq = seq.as_ref();
+            seq.strip_suffix(b"\0").unwrap_or(seq).len()
+        })
+        .collect();
+    if lengths.is_empty() {
+        return 0;
+    }
+    lengths.sort_unstable();
+    let median = lengths[lengths.len() / 2];
+    (median as f64 * (1.0 + margin.max(0.0))).ceil() as usize
+}
+
+/// Generates a consensus sequence together with the fraction of covering reads that agree with each consensus base.
+///
+/// A read covers a consensus base if it is aligned to that position, either with a base or with a
+/// deletion between two aligned bases; reads that start after or end before the position do not
+/// count. This is distinct from the raw depth, which also counts the disagreeing reads.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus and, for each consensus base, the agreeing fraction of the reads covering it
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_support, PoaParams};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCCGTT\0".to_vec(), b"AATGCACGTT\0".to_vec()];
+///     let (consensus, support) = poa_consensus_with_support(&seqs, 20, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(support[0], 1.0);
+///     assert!((support[5] - 2.0 / 3.0).abs() < 1e-6);
+/// ```
+pub fn poa_consensus_with_support<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams
+) -> (Vec<u8>, Vec<f32>) {
+
+    let (consensus, support, _, _) = consensus_support(seqs, consensus_max_length, params);
+    (consensus, support)
+}
+
+/// Generates a consensus sequence together with the number of reads supporting each consensus base.
+///
+/// A read supports a consensus base if it is aligned to that position with the same base, so the
+/// counts are those behind the agreeing fractions of `poa_consensus_with_support`, e.g. for
+/// masking positions only few reads back.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus and, for each consensus base, the number of reads supporting it
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_coverage, PoaParams};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCCGTT\0".to_vec(), b"AATGCACGTT\0".to_vec()];
+///     let (consensus, coverage) = poa_consensus_with_coverage(&seqs, 20, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(coverage, vec![3, 3, 3, 3, 3, 2, 3, 3, 3, 3]);
+/// ```
+pub fn poa_consensus_with_coverage<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams
+) -> (Vec<u8>, Vec<u32>) {
+
+    let (consensus, _, _, agreeing) = consensus_support(seqs, consensus_max_length, params);
+    (consensus, agreeing)
+}
+
+/// Generates a consensus with the agreeing fraction, the number of reads covering and the number
+/// of reads agreeing with each base.
+fn consensus_support<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams
+) -> (Vec<u8>, Vec<f32>, Vec<u32>, Vec<u32>) {
+
+    let buffers = if params.is_scalar() {
+        scalar_graph(seqs, params).try_consensus_support().unwrap_or_else(|error| panic!("{}", error))
+    } else {
+        native_support(seqs, params)
+    };
+
+    let SupportBuffers { mut consensus, mut support, mut depth, mut agreeing } = buffers;
+    if consensus_max_length > 0 {
+        consensus.truncate(consensus_max_length);
+        support.truncate(consensus_max_length);
+        depth.truncate(consensus_max_length);
+        agreeing.truncate(consensus_max_length);
+    }
+    (consensus, support, depth, agreeing)
+}
+
+// the consensus and support of poa_func_support
+fn native_support<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams) -> SupportBuffers {
+
+    let mut buffers = SupportBuffers::default();
+
+    let seqs = params.case_policy.apply_all(seqs);
+    let seq_ptrs = seq_ptrs(&seqs);
+    let (gap_open2, gap_extend2) = params.second_gaps();
+
+    unsafe {
+
+        poa_func_support(
+            seq_ptrs.as_ptr(),
+            seqs.len(),
+            &mut buffers as *mut SupportBuffers as *mut c_void,
+            reserve_support,
+            params.alignment_type,
+            params.match_score,
+            params.mismatch_score,
+            params.gap_open,
+            params.gap_extend,
+            gap_open2,
+            gap_extend2,
+            params.simd.code(),
+            params.ambiguous_bases.code()
+        );
+    }
+    error::check_native();
+
+    buffers
 }
 
 
@@ -112,6 +1370,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(deprecated)]
     fn test_dna_consensus() {
         let mut seqs = vec![];
 
@@ -122,7 +1381,7 @@ mod tests {
             "AACGCCCGTC\0",
             "AGTGCTCGTT\0",
             "AATGCTCGTT\0"].iter() {
-            seqs.push((*seq).bytes().map(|x|{x as u8}).collect::<Vec<u8>>());
+            seqs.push((*seq).bytes().collect::<Vec<u8>>());
         }
 
         let consensus = poa_consensus(&seqs, 20, 1, 5, -4, -3, -1);
@@ -133,6 +1392,7 @@ mod tests {
 
 
     #[test]
+    #[allow(deprecated)]
     fn test_protein_consensus() {
         let mut seqs = vec![];
         // expect consensus "FNLKPSWDDCQ"
@@ -142,7 +1402,7 @@ mod tests {
             "FNLKASWCQ\0".to_string(),
             "FLKPSWDDCQ\0".to_string(),
             "FNLKPSWDADCQ\0".to_string()].iter() {
-            seqs.push(seq.chars().into_iter().map(|x|{x as u8}).collect::<Vec<u8>>());
+            seqs.push(seq.chars().map(|x|{x as u8}).collect::<Vec<u8>>());
         }
 
         let consensus = poa_consensus(&seqs, 20, 1, 5, -4, -3, -1);
@@ -153,6 +1413,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     #[should_panic]
     fn test_not_null_terminated() {
         let mut seqs = vec![];
@@ -164,10 +1425,259 @@ mod tests {
             "AACGCCCGTC",
             "AGTGCTCGTT",
             "AATGCTCGTT"].iter() {
-            seqs.push((*seq).bytes().map(|x|{x as u8}).collect::<Vec<u8>>());
+            seqs.push((*seq).bytes().collect::<Vec<u8>>());
         }
 
         poa_consensus(&seqs, 20, 1, 5, -4, -3, -1);
 
     }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_invalid_alignment_type_unwinds() {
+        let seqs = vec![b"AATGCCCGTT\0".to_vec()];
+        assert!(std::panic::catch_unwind(|| poa_consensus(&seqs, 0, 5, 5, -4, -3, -1)).is_err());
+    }
+
+    #[test]
+    fn test_consensus_support() {
+        let mut seqs = vec![];
+
+        for seq in ["ATTGCCCGTT\0",
+            "AATGCCGTT\0",
+            "AATGCCCGAT\0",
+            "AACGCCCGTC\0",
+            "AGTGCTCGTT\0",
+            "AATGCTCGTT\0"].iter() {
+            seqs.push((*seq).bytes().collect::<Vec<u8>>());
+        }
+
+        let (consensus, support) = poa_consensus_with_support(&seqs, 20, &PoaParams::new(1, 5, -4, -3, -1));
+
+        assert_eq!(consensus, "AATGCCCGTT".to_string().into_bytes());
+        assert_eq!(support.len(), consensus.len());
+        assert_eq!(support[0], 1.0);
+        // the second position reads A in four out of six reads
+        assert!((support[1] - 4.0 / 6.0).abs() < 1e-6);
+        assert!(support.iter().all(|&s| s > 0.0 && s <= 1.0));
+    }
+
+    #[test]
+    fn test_consensus_coverage() {
+        let seqs: Vec<Vec<u8>> = ["ATTGCCCGTT\0", "AATGCCGTT\0", "AATGCCCGAT\0", "AACGCCCGTC\0", "AGTGCTCGTT\0", "AATGCTCGTT\0"]
+            .iter()
+            .map(|seq| seq.bytes().collect())
+            .collect();
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+
+        let (consensus, coverage) = poa_consensus_with_coverage(&seqs, 20, &params);
+        assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+        assert_eq!(coverage[1], 4);
+        // every read of a global alignment covers every base, so the counts are the fractions of six
+        let (_, support) = poa_consensus_with_support(&seqs, 20, &params);
+        for (&count, &fraction) in coverage.iter().zip(&support) {
+            assert!((count as f32 / 6.0 - fraction).abs() < 1e-6);
+        }
+        assert_eq!(poa_consensus_with_coverage(&seqs, 4, &params).1.len(), 4);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_estimated_consensus_len() {
+        let seqs: Vec<Vec<u8>> = ["AATGCCCGTT\0", "AATGCCGTT\0", "AATGCCCGTTAATGCCCGTT\0"].iter().map(|seq| seq.bytes().collect()).collect();
+        assert_eq!(estimate_consensus_len(&seqs), 15);
+        assert_eq!(estimate_consensus_len_with_margin(&seqs, 0.0), 10);
+        assert_eq!(estimate_consensus_len::<Vec<u8>>(&[]), 0);
+
+        // a maximum length of 0 keeps the whole consensus with support too
+        assert_eq!(poa_consensus(&seqs[..2], 0, 1, 5, -4, -3, -1), b"AATGCCCGTT".to_vec());
+        assert_eq!(poa_consensus_with_support(&seqs[..2], 0, &PoaParams::new(1, 5, -4, -3, -1)).0, b"AATGCCCGTT".to_vec());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_unbounded_consensus() {
+        // most reads cover only the start of the sequence, so its length is far above the estimate
+        let long = b"AATGCCCGTTACGGATCCATGACTTGGCAATCGTAGCTGA";
+        let mut seqs: Vec<Vec<u8>> = vec![[&long[..10], b"\0"].concat(); 3];
+        seqs.extend(vec![[&long[..], b"\0"].concat(); 2]);
+        let params = PoaParams::new(2, 5, -4, -8, -6);
+        assert_eq!(estimate_consensus_len(&seqs), 15);
+
+        assert_eq!(poa_consensus_unbounded(&seqs, &params), long.to_vec());
+        assert_eq!(poa_consensus(&seqs, 0, 2, 5, -4, -8, -6), long.to_vec());
+        assert_eq!(poa_consensus(&seqs, 12, 2, 5, -4, -8, -6), long[..12].to_vec());
+        assert_eq!(poa_consensus_with_support(&seqs, 0, &params).0, long.to_vec());
+        assert_eq!(poa_consensus_with_coverage(&seqs, 0, &params).1.len(), long.len());
+        assert!(poa_consensus_unbounded(&Vec::<Vec<u8>>::new(), &params).is_empty());
+    }
+
+    #[test]
+    fn test_try_poa_consensus() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"\0".to_vec(), b"AATGCCGTT\0".to_vec(), b"AATGCCCGTT\0".to_vec()];
+        assert_eq!(try_poa_consensus(&seqs, 10, &params), Ok(b"AATGCCCGTT".to_vec()));
+        assert_eq!(try_poa_consensus(&seqs, 9, &params), Err(PoaError::Truncated { len: 10, max_len: 9 }));
+
+        assert_eq!(try_poa_consensus(&seqs[1..2], 0, &params), Err(PoaError::EmptyInput));
+        assert_eq!(try_poa_consensus(&Vec::<Vec<u8>>::new(), 0, &params), Err(PoaError::EmptyInput));
+        let malformed = vec![b"AATG\0".to_vec(), b"AATG".to_vec(), b"AA\0TG\0".to_vec()];
+        assert_eq!(
+            try_poa_consensus(&malformed, 0, &params),
+            Err(PoaError::InvalidRead(RejectedRead { index: 1, reason: RejectReason::NotNullTerminated }))
+        );
+        let non_ascii = vec![b"AATG\0".to_vec(), b"AA\xffTG\0".to_vec(), b"AA TG\0".to_vec()];
+        assert_eq!(try_poa_consensus(&non_ascii, 0, &params), Err(PoaError::InvalidByte { seq_index: 1, pos: 2, byte: 0xff }));
+        assert_eq!(
+            try_poa_consensus_with_alphabet(&non_ascii, 0, &params, &Alphabet::new(b"AGT\xff")),
+            Err(PoaError::InvalidByte { seq_index: 2, pos: 2, byte: b' ' })
+        );
+    }
+
+    #[test]
+    fn test_borrowed_sequences() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let owned = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCGTT\0".to_vec(), b"AATGCCCGAT\0".to_vec()];
+        let expected = try_poa_consensus(&owned, 0, &params);
+
+        // slices of a single buffer holding all reads, and string literals, without copying them
+        let buffer = owned.concat();
+        let slices: Vec<&[u8]> = vec![&buffer[..11], &buffer[11..21], &buffer[21..]];
+        assert_eq!(try_poa_consensus(&slices, 0, &params), expected);
+        assert_eq!(try_poa_consensus(&[&b"AATGCCCGTT\0"[..], b"AATGCCGTT\0", b"AATGCCCGAT\0"], 0, &params), expected);
+        assert_eq!(poa_consensus_unbounded(&slices, &params), poa_consensus_unbounded(&owned, &params));
+        assert_eq!(poa_consensus_with_support(&slices, 0, &params), poa_consensus_with_support(&owned, 0, &params));
+    }
+
+    #[test]
+    fn test_params_builder() {
+        let params = PoaParams::protein().alignment_type(AlignmentType::Local).unwrap().match_score(6).mismatch_score(-3);
+        let params = params.gap_open(-9).gap_extend(-2);
+        assert_eq!(params, PoaParams::new(0, 6, -3, -9, -2));
+        assert_eq!(params.validate(), Ok(()));
+        assert_eq!(PoaParams::default(), PoaParams::dna());
+        assert_eq!(PoaParams::dna().validate(), Ok(()));
+        assert_eq!(PoaParams::protein().validate(), Ok(()));
+
+        assert!(std::panic::catch_unwind(|| PoaParams::dna().gap_open(5)).is_err());
+        assert!(std::panic::catch_unwind(|| PoaParams::dna().match_score(-5)).is_err());
+        assert_eq!(PoaParams::dna().alignment_type(AlignmentType::Auto), Err(PoaError::UnresolvedAlignmentType));
+        assert_eq!(EndGaps::of(AlignmentType::Auto), None);
+    }
+
+    #[test]
+    fn test_validate_params() {
+        assert_eq!(PoaParams::new(2, 5, -4, -8, -6).validate(), Ok(()));
+        assert_eq!(PoaParams::new(-1, 5, -4, -8, -6).validate(), Err(PoaError::InvalidAlignmentType(-1)));
+        for params in [
+            PoaParams::new(1, 200, -4, -8, -6),
+            PoaParams::new(1, 5, -4, 3, -6),
+            PoaParams::new(1, 5, 5, -8, -6),
+            PoaParams::new(1, 5, -4, -8, -6).homopolymer_gaps(HomopolymerGaps::new(3, 1, 0)),
+        ]
+        .iter()
+        {
+            assert!(matches!(params.validate(), Err(PoaError::InvalidScores(_))), "{}", params);
+        }
+    }
+
+    #[test]
+    fn test_params_preset_round_trip() {
+        let params = PoaParams::new(2, 3, -5, -8, -2);
+        assert_eq!(params.to_string(), "alignment_type=2 match_score=3 mismatch_score=-5 gap_open=-8 gap_extend=-2");
+        assert_eq!(params.to_string().parse::<PoaParams>(), Ok(params));
+        assert_eq!("gap_extend=-2 gap_open=-8 mismatch_score=-5 match_score=3 alignment_type=2".parse(), Ok(params));
+
+        assert!("alignment_type=2 match_score=3".parse::<PoaParams>().is_err());
+        assert!("alignment_type=x match_score=3 mismatch_score=-5 gap_open=-8 gap_extend=-2".parse::<PoaParams>().is_err());
+        assert!("alignment_type=2 alignment_type=2".parse::<PoaParams>().is_err());
+
+        let params = params.homopolymer_gaps(HomopolymerGaps::new(3, -4, -1));
+        assert_eq!(params.to_string().parse::<PoaParams>(), Ok(params));
+        let partial = "alignment_type=2 match_score=3 mismatch_score=-5 gap_open=-8 gap_extend=-2 homopolymer_run=3";
+        assert!(partial.parse::<PoaParams>().is_err());
+
+        let params = params.simd(SimdBackend::Sse41);
+        assert!(params.to_string().ends_with(" simd=sse4.1"));
+        assert_eq!(params.to_string().parse::<PoaParams>(), Ok(params));
+        assert!((params.to_string() + " simd=avx2").parse::<PoaParams>().is_err());
+
+        let params = params.band_width(BandWidth::Auto);
+        assert!(params.to_string().ends_with(" band_width=auto simd=sse4.1"));
+        assert_eq!(params.to_string().parse::<PoaParams>(), Ok(params));
+        assert!((params.to_string() + " band_width=3").parse::<PoaParams>().is_err());
+        assert!("alignment_type=2 match_score=3 mismatch_score=-5 gap_open=-8 gap_extend=-2 band_width=-3".parse::<PoaParams>().is_err());
+
+        let params = params.end_gaps(EndGaps { query_start: true, graph_end: true, ..EndGaps::global() });
+        assert!(params.to_string().ends_with(" band_width=auto end_gaps=query_start,graph_end simd=sse4.1"));
+        assert_eq!(params.to_string().parse::<PoaParams>(), Ok(params));
+        assert_eq!(params.end_gaps(EndGaps::global()).to_string().parse::<PoaParams>(), Ok(params.end_gaps(EndGaps::global())));
+        assert!(params.to_string().replace("graph_end", "graph_end,graph_end").parse::<PoaParams>().is_err());
+        assert!(params.to_string().replace("graph_end", "read_end").parse::<PoaParams>().is_err());
+    }
+
+    #[test]
+    fn test_convex_gaps() {
+        // a read missing twelve bases in the middle, which local alignment clips under affine gap scores
+        let reference = b"GATTACAGGCTTACCGATCGATTGCAGCTAGGCATCAAGT";
+        let deleted = [&reference[..12], &reference[24..]].concat();
+        let affine = PoaParams::new(0, 5, -4, -8, -6);
+        let convex = affine.convex_gaps(ConvexGaps::new(-20, -1));
+        let msa = |params: &PoaParams| {
+            let mut graph = PoaGraph::new(params);
+            graph.add_sequence(reference);
+            graph.add_sequence(&deleted);
+            graph.multiple_sequence_alignment(false)
+        };
+        assert_ne!(msa(&affine)[1], b"GATTACAGGCTT------------CAGCTAGGCATCAAGT".to_vec());
+        assert_eq!(msa(&convex)[1], b"GATTACAGGCTT------------CAGCTAGGCATCAAGT".to_vec());
+
+        // a second pair that does not open more expensively and extend more cheaply is ignored
+        assert_eq!(msa(&affine.convex_gaps(ConvexGaps::new(-4, -1))), msa(&affine));
+        let seqs = vec![[&reference[..], b"\0"].concat(), [&deleted[..], b"\0"].concat(), [&reference[..], b"\0"].concat()];
+        assert_eq!(poa_consensus_unbounded(&seqs, &convex), reference.to_vec());
+
+        assert_eq!(convex.to_string().parse::<PoaParams>(), Ok(convex));
+        assert!((affine.to_string() + " gap_open2=-20").parse::<PoaParams>().is_err());
+        assert!(affine.convex_gaps(ConvexGaps::new(-20, 1)).validate().is_err());
+        assert!(affine.convex_gaps(ConvexGaps::new(-200, -1)).validate().is_err());
+    }
+
+    #[test]
+    fn test_gap_models() {
+        let affine = PoaParams::new(0, 5, -4, -8, -6);
+        assert_eq!(affine.gaps(), GapModel::Affine { open: -8, extend: -6 });
+        assert_eq!(affine.gap_model(GapModel::Affine { open: -8, extend: -6 }), affine);
+        assert_eq!(PoaParams::new(0, 5, -4, -4, -6).gaps(), GapModel::Linear(-4));
+        // a second pair without effect leaves the gaps affine, as for the engine
+        assert_eq!(affine.convex_gaps(ConvexGaps::new(-4, -1)).gaps(), affine.gaps());
+
+        // each model aligns as the scores it sets
+        let convex = GapModel::Convex { open1: -8, extend1: -6, open2: -20, extend2: -1 };
+        assert_eq!(affine.gap_model(convex), affine.convex_gaps(ConvexGaps::new(-20, -1)));
+        assert_eq!(affine.convex_gaps(ConvexGaps::new(-20, -1)).gap_model(GapModel::Affine { open: -8, extend: -6 }), affine);
+        let linear = affine.gap_model(GapModel::Linear(-5));
+        assert_eq!((linear.gap_open, linear.gap_extend), (-5, -5));
+        let msa = |params: &PoaParams| {
+            let mut graph = PoaGraph::new(params);
+            graph.add_sequence(b"GATTACAGGCTTACCGATCG");
+            graph.add_sequence(b"GATTACAGCTTACGATCG");
+            graph.multiple_sequence_alignment(false)
+        };
+        assert_eq!(msa(&linear), msa(&PoaParams::new(0, 5, -4, -5, -5)));
+
+        for model in [
+            GapModel::Linear(2),
+            GapModel::Linear(-129),
+            GapModel::Affine { open: -6, extend: -6 },
+            GapModel::Affine { open: -8, extend: 1 },
+            GapModel::Convex { open1: -8, extend1: -6, open2: -4, extend2: -1 },
+            GapModel::Convex { open1: -8, extend1: -6, open2: -20, extend2: -6 },
+            GapModel::Convex { open1: -6, extend1: -8, open2: -20, extend2: -1 },
+        ]
+        .iter()
+        {
+            assert!(matches!(model.validate(), Err(PoaError::InvalidScores(_))), "{:?}", model);
+        }
+    }
 }
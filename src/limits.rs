@@ -0,0 +1,160 @@
+//! Caps on how much a single read may grow the graph.
+
+use crate::reject::{validate_read, ConsensusReport, RejectedRead};
+use crate::{PoaGraph, PoaParams};
+
+/// Limits on the growth of a graph, checked before each read is added.
+///
+/// A read exceeding a limit is rejected and leaves the graph unchanged, so that a single chimeric
+/// or otherwise unrelated read cannot inflate the graph and slow down every later alignment.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GrowthLimits {
+    /// the maximum number of nodes the graph may hold, or `None` for no limit
+    pub max_nodes: Option<usize>,
+    /// the maximum number of bases of a single read that may be inserted as new nodes, i.e. that
+    /// are not aligned to an existing node, or `None` for no limit; the first read added to an
+    /// empty graph is exempt, as all of its bases are new
+    pub max_read_insertions: Option<usize>,
+    /// the maximum memory in bytes the graph and the alignment of a read may take, as estimated by
+    /// `PoaGraph::memory_usage` and `PoaGraph::alignment_memory`, or `None` for no limit
+    pub max_memory: Option<usize>,
+}
+
+impl GrowthLimits {
+    /// Creates limits that accept every read.
+    pub fn new() -> GrowthLimits {
+        GrowthLimits::default()
+    }
+
+    /// Sets the maximum number of nodes the graph may hold.
+    pub fn max_nodes(mut self, max_nodes: usize) -> GrowthLimits {
+        self.max_nodes = Some(max_nodes);
+        self
+    }
+
+    /// Sets the maximum number of bases of a single read that may be inserted as new nodes.
+    pub fn max_read_insertions(mut self, max_read_insertions: usize) -> GrowthLimits {
+        self.max_read_insertions = Some(max_read_insertions);
+        self
+    }
+
+    /// Sets the maximum memory in bytes the graph and the alignment of a read may take, so that a
+    /// read that would exhaust the memory of a constrained process is rejected before its alignment
+    /// allocates anything.
+    pub fn max_memory(mut self, max_memory: usize) -> GrowthLimits {
+        self.max_memory = Some(max_memory);
+        self
+    }
+}
+
+/// Generates a consensus sequence, rejecting reads that would grow the graph beyond the given limits.
+///
+/// Reads are added in order, so whether a read exceeds a limit depends on the reads before it.
+/// Invalid reads are rejected as by `poa_consensus_with_report`.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `limits` - the caps on graph size and on the insertions of a single read
+///
+/// # Returns
+/// * returns the consensus of the accepted reads, with the rejected reads and their reasons
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_limits, GrowthLimits, PoaParams, RejectReason};
+///
+///     let seqs = vec![
+///         b"AATGCCCGTT\0".to_vec(),
+///         b"AATGCCCGTTGGGGGGGGGGGGGGGGGGGG\0".to_vec(),
+///         b"AATGCCCGTT\0".to_vec(),
+///     ];
+///     let limits = GrowthLimits::new().max_read_insertions(5);
+///     let report = poa_consensus_with_limits(&seqs, 40, &PoaParams::new(1, 5, -4, -3, -1), &limits);
+///
+///     assert_eq!(report.consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(report.rejected[0].index, 1);
+///     assert_eq!(report.rejected[0].reason, RejectReason::InsertionLimit);
+/// ```
+pub fn poa_consensus_with_limits<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    limits: &GrowthLimits
+) -> ConsensusReport {
+
+    let mut graph = PoaGraph::new(params);
+    let mut rejected = vec![];
+
+    for (index, seq) in seqs.iter().enumerate() {
+        let seq = seq.as_ref();
+        let result = match validate_read(seq) {
+            Some(reason) => Err(reason),
+            None => graph.add_sequence_limited(&seq[..seq.len() - 1], limits),
+        };
+        if let Err(reason) = result {
+            rejected.push(RejectedRead { index, reason });
+        }
+    }
+
+    let mut consensus = graph.consensus();
+    if consensus_max_length > 0 {
+        consensus.truncate(consensus_max_length);
+    }
+
+    ConsensusReport { consensus, reads_used: graph.num_sequences(), rejected }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RejectReason;
+
+    fn params() -> PoaParams {
+        PoaParams::new(1, 5, -4, -3, -1)
+    }
+
+    #[test]
+    fn test_node_limit() {
+        let mut graph = PoaGraph::new(&params());
+        let limits = GrowthLimits::new().max_nodes(12);
+
+        assert_eq!(graph.add_sequence_limited(b"AATGCCCGTT", &limits), Ok(()));
+        assert_eq!(graph.add_sequence_limited(b"AATGCCCGTT", &limits), Ok(()));
+        // two mismatches would add two nodes, the third read only one
+        assert_eq!(graph.add_sequence_limited(b"ACTGCCCGAT", &limits), Ok(()));
+        assert_eq!(graph.add_sequence_limited(b"AATGCGCGTC", &limits), Err(RejectReason::NodeLimit));
+        assert_eq!(graph.num_nodes(), 12);
+        assert_eq!(graph.num_sequences(), 3);
+    }
+
+    #[test]
+    fn test_unlimited_matches_plain_consensus() {
+        let seqs: Vec<Vec<u8>> = ["ATTGCCCGTT\0", "AATGCCGTT\0", "AATGCCCGAT\0", "AACGCCCGTC\0"]
+            .iter().map(|seq| seq.bytes().collect()).collect();
+        let report = poa_consensus_with_limits(&seqs, 20, &params(), &GrowthLimits::new());
+
+        assert!(report.rejected.is_empty());
+        assert_eq!(report.reads_used, 4);
+        assert_eq!(report.consensus, crate::native_consensus(&seqs, 20, &params()));
+        assert_eq!(poa_consensus_with_limits(&seqs, 0, &params(), &GrowthLimits::new()).consensus, report.consensus);
+    }
+
+    #[test]
+    fn test_rejected_read_leaves_graph_unchanged() {
+        let mut graph = PoaGraph::new(&params());
+        graph.add_sequence(b"AATGCCCGTT");
+        let nodes = graph.num_nodes();
+
+        let limits = GrowthLimits::new().max_read_insertions(3);
+        assert_eq!(
+            graph.add_sequence_limited(b"GGGGAATGCCCGTTCCCC", &limits),
+            Err(RejectReason::InsertionLimit)
+        );
+        assert_eq!(graph.num_nodes(), nodes);
+        assert_eq!(graph.num_sequences(), 1);
+        assert_eq!(graph.add_sequence_limited(b"AATGCCCGTTC", &limits), Ok(()));
+    }
+}
@@ -0,0 +1,395 @@
+//! Substitution matrices scoring every pair of symbols, e.g. BLOSUM62 for protein reads.
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use crate::{PoaError, PoaGraph, PoaParams};
+
+const BLOSUM62: &str = "\
+#  BLOSUM62, as distributed with NCBI BLAST
+   A  R  N  D  C  Q  E  G  H  I  L  K  M  F  P  S  T  W  Y  V  B  Z  X  *
+A  4 -1 -2 -2  0 -1 -1  0 -2 -1 -1 -1 -1 -2 -1  1  0 -3 -2  0 -2 -1  0 -4
+R -1  5  0 -2 -3  1  0 -2  0 -3 -2  2 -1 -3 -2 -1 -1 -3 -2 -3 -1  0 -1 -4
+N -2  0  6  1 -3  0  0  0  1 -3 -3  0 -2 -3 -2  1  0 -4 -2 -3  3  0 -1 -4
+D -2 -2  1  6 -3  0  2 -1 -1 -3 -4 -1 -3 -3 -1  0 -1 -4 -3 -3  4  1 -1 -4
+C  0 -3 -3 -3  9 -3 -4 -3 -3 -1 -1 -3 -1 -2 -3 -1 -1 -2 -2 -1 -3 -3 -2 -4
+Q -1  1  0  0 -3  5  2 -2  0 -3 -2  1  0 -3 -1  0 -1 -2 -1 -2  0  3 -1 -4
+E -1  0  0  2 -4  2  5 -2  0 -3 -3  1 -2 -3 -1  0 -1 -3 -2 -2  1  4 -1 -4
+G  0 -2  0 -1 -3 -2 -2  6 -2 -4 -4 -2 -3 -3 -2  0 -2 -2 -3 -3 -1 -2 -1 -4
+H -2  0  1 -1 -3  0  0 -2  8 -3 -3 -1 -2 -1 -2 -1 -2 -2  2 -3  0  0 -1 -4
+I -1 -3 -3 -3 -1 -3 -3 -4 -3  4  2 -3  1  0 -3 -2 -1 -3 -1  3 -3 -3 -1 -4
+L -1 -2 -3 -4 -1 -2 -3 -4 -3  2  4 -2  2  0 -3 -2 -1 -2 -1  1 -4 -3 -1 -4
+K -1  2  0 -1 -3  1  1 -2 -1 -3 -2  5 -1 -3 -1  0 -1 -3 -2 -2  0  1 -1 -4
+M -1 -1 -2 -3 -1  0 -2 -3 -2  1  2 -1  5  0 -2 -1 -1 -1 -1  1 -3 -1 -1 -4
+F -2 -3 -3 -3 -2 -3 -3 -3 -1  0  0 -3  0  6 -4 -2 -2  1  3 -1 -3 -3 -1 -4
+P -1 -2 -2 -1 -3 -1 -1 -2 -2 -3 -3 -1 -2 -4  7 -1 -1 -4 -3 -2 -2 -1 -2 -4
+S  1 -1  1  0 -1  0  0  0 -1 -2 -2  0 -1 -2 -1  4  1 -3 -2 -2  0  0  0 -4
+T  0 -1  0 -1 -1 -1 -1 -2 -2 -1 -1 -1 -1 -2 -1  1  5 -2 -2  0 -1 -1  0 -4
+W -3 -3 -4 -4 -2 -2 -3 -2 -2 -3 -2 -3 -1  1 -4 -3 -2 11  2 -3 -4 -3 -2 -4
+Y -2 -2 -2 -3 -2 -1 -2 -3  2 -1 -1 -2 -1  3 -3 -2 -2  2  7 -1 -3 -2 -1 -4
+V  0 -3 -3 -3 -1 -2 -2 -3 -3  3  1 -2  1 -1 -2 -2  0 -3 -1  4 -3 -2 -1 -4
+B -2 -1  3  4 -3  0  1 -1  0 -3 -4  0 -3 -3 -2  0 -1 -4 -3 -3  4  1 -1 -4
+Z -1  0  0  1 -3  3  4 -2  0 -3 -3  1 -1 -3 -1  0 -1 -3 -2 -2  1  4 -1 -4
+X  0 -1 -1 -1 -2 -1 -1 -1 -1 -1 -1 -1 -1 -1 -2  0  0 -2 -1 -1 -1 -1 -1 -4
+* -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4 -4  1
+";
+
+const PAM250: &str = "\
+#  PAM250, as distributed with NCBI BLAST
+   A  R  N  D  C  Q  E  G  H  I  L  K  M  F  P  S  T  W  Y  V  B  Z  X  *
+A  2 -2  0  0 -2  0  0  1 -1 -1 -2 -1 -1 -3  1  1  1 -6 -3  0  0  0  0 -8
+R -2  6  0 -1 -4  1 -1 -3  2 -2 -3  3  0 -4  0  0 -1  2 -4 -2 -1  0 -1 -8
+N  0  0  2  2 -4  1  1  0  2 -2 -3  1 -2 -3  0  1  0 -4 -2 -2  2  1  0 -8
+D  0 -1  2  4 -5  2  3  1  1 -2 -4  0 -3 -6 -1  0  0 -7 -4 -2  3  3 -1 -8
+C -2 -4 -4 -5 12 -5 -5 -3 -3 -2 -6 -5 -5 -4 -3  0 -2 -8  0 -2 -4 -5 -3 -8
+Q  0  1  1  2 -5  4  2 -1  3 -2 -2  1 -1 -5  0 -1 -1 -5 -4 -2  1  3 -1 -8
+E  0 -1  1  3 -5  2  4  0  1 -2 -3  0 -2 -5 -1  0  0 -7 -4 -2  3  3 -1 -8
+G  1 -3  0  1 -3 -1  0  5 -2 -3 -4 -2 -3 -5  0  1  0 -7 -5 -1  0  0 -1 -8
+H -1  2  2  1 -3  3  1 -2  6 -2 -2  0 -2 -2  0 -1 -1 -3  0 -2  1  2 -1 -8
+I -1 -2 -2 -2 -2 -2 -2 -3 -2  5  2 -2  2  1 -2 -1  0 -5 -1  4 -2 -2 -1 -8
+L -2 -3 -3 -4 -6 -2 -3 -4 -2  2  6 -3  4  2 -3 -3 -2 -2 -1  2 -3 -3 -1 -8
+K -1  3  1  0 -5  1  0 -2  0 -2 -3  5  0 -5 -1  0  0 -3 -4 -2  1  0 -1 -8
+M -1  0 -2 -3 -5 -1 -2 -3 -2  2  4  0  6  0 -2 -2 -1 -4 -2  2 -2 -2 -1 -8
+F -3 -4 -3 -6 -4 -5 -5 -5 -2  1  2 -5  0  9 -5 -3 -3  0  7 -1 -4 -5 -2 -8
+P  1  0  0 -1 -3  0 -1  0  0 -2 -3 -1 -2 -5  6  1  0 -6 -5 -1 -1  0 -1 -8
+S  1  0  1  0  0 -1  0  1 -1 -1 -3  0 -2 -3  1  2  1 -2 -3 -1  0  0  0 -8
+T  1 -1  0  0 -2 -1  0  0 -1  0 -2  0 -1 -3  0  1  3 -5 -3  0  0 -1  0 -8
+W -6  2 -4 -7 -8 -5 -7 -7 -3 -5 -2 -3 -4  0 -6 -2 -5 17  0 -6 -5 -6 -4 -8
+Y -3 -4 -2 -4  0 -4 -4 -5  0 -1 -1 -4 -2  7 -5 -3 -3  0 10 -2 -3 -4 -2 -8
+V  0 -2 -2 -2 -2 -2 -2 -1 -2  4  2 -2  2 -1 -1 -1  0 -6 -2  4 -2 -2 -1 -8
+B  0 -1  2  3 -4  1  3  0  1 -2 -3  1 -2 -4 -1  0  0 -5 -3 -2  3  2 -1 -8
+Z  0  0  1  3 -5  3  3  0  2 -2 -3  0 -2 -5  0  0 -1 -6 -4 -2  2  3 -1 -8
+X  0 -1  0 -1 -3 -1 -1 -1 -1 -1 -1 -1 -1 -2 -1  0  0 -4 -2 -1 -1 -1 -1 -8
+* -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8 -8  1
+";
+
+/// The built-in substitution matrices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Matrix {
+    /// BLOSUM62, the default of protein BLAST
+    Blosum62,
+    /// PAM250, for distantly related proteins
+    Pam250,
+}
+
+impl Matrix {
+    /// Every built-in matrix.
+    pub const ALL: [Matrix; 2] = [Matrix::Blosum62, Matrix::Pam250];
+
+    /// Returns the lower-case name of the matrix, as `str::parse` reads it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Matrix::Blosum62 => "blosum62",
+            Matrix::Pam250 => "pam250",
+        }
+    }
+
+    /// Returns the scores of the matrix.
+    pub fn scores(self) -> SubstitutionMatrix {
+        let text = match self {
+            Matrix::Blosum62 => BLOSUM62,
+            Matrix::Pam250 => PAM250,
+        };
+        SubstitutionMatrix::from_ncbi(text).expect("malformed built-in matrix")
+    }
+}
+
+impl FromStr for Matrix {
+    type Err = PoaError;
+
+    fn from_str(s: &str) -> Result<Matrix, PoaError> {
+        Matrix::ALL
+            .iter()
+            .copied()
+            .find(|matrix| matrix.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| PoaError::InvalidPreset(format!("unknown substitution matrix {}", s)))
+    }
+}
+
+/// The score of aligning each pair of symbols of an alphabet, replacing the single match and
+/// mismatch scores of `PoaParams`.
+///
+/// The rows belong to the symbol of the graph node and the columns to the symbol of the aligned
+/// read, so the matrix need not be symmetric. Symbols are matched ignoring case, and a pair with a
+/// symbol outside the alphabet, such as a gap character, scores the lowest score of the matrix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubstitutionMatrix {
+    alphabet: Vec<u8>,
+    scores: Vec<i32>,
+}
+
+impl SubstitutionMatrix {
+    /// Creates a matrix from the score of each pair of symbols of `alphabet`, with `scores[a][b]`
+    /// scoring symbol `alphabet[a]` of the graph against `alphabet[b]` of a read.
+    ///
+    /// Returns `PoaError::InvalidScores` if the alphabet is empty or repeats a symbol, ignoring
+    /// case, if `scores` is not a square matrix over the alphabet, or if a score does not fit in
+    /// 8 bits, as the scores of `PoaParams` must.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::SubstitutionMatrix;
+    ///
+    ///     // transitions (A-G, C-T) are penalized less than transversions
+    ///     let matrix = SubstitutionMatrix::new(
+    ///         b"ACGT",
+    ///         &[vec![5, -4, -1, -4], vec![-4, 5, -4, -1], vec![-1, -4, 5, -4], vec![-4, -1, -4, 5]],
+    ///     )
+    ///     .unwrap();
+    ///     assert_eq!(matrix.score(b'a', b'G'), -1);
+    /// ```
+    pub fn new(alphabet: &[u8], scores: &[Vec<i32>]) -> Result<SubstitutionMatrix, PoaError> {
+        let invalid = |message: String| Err(PoaError::InvalidScores(message));
+        if alphabet.is_empty() {
+            return invalid("the substitution matrix has no symbols".to_string());
+        }
+        for (i, &symbol) in alphabet.iter().enumerate() {
+            if alphabet[..i].iter().any(|other| other.eq_ignore_ascii_case(&symbol)) {
+                return invalid(format!("symbol {} occurs twice in the substitution matrix", symbol as char));
+            }
+        }
+        if scores.len() != alphabet.len() || scores.iter().any(|row| row.len() != alphabet.len()) {
+            return invalid(format!("the substitution matrix is not {0} by {0}", alphabet.len()));
+        }
+        if let Some(&score) = scores.iter().flatten().find(|&&score| i8::try_from(score).is_err()) {
+            return invalid(format!("substitution score {} does not fit in 8 bits", score));
+        }
+        Ok(SubstitutionMatrix { alphabet: alphabet.to_vec(), scores: scores.concat() })
+    }
+
+    /// Reads a matrix in the text format of NCBI BLAST, as in the files of its `data` directory.
+    ///
+    /// Lines starting with `#` are comments. The first other line lists the symbols of the
+    /// columns, and every further line a symbol followed by its scores against them; the rows may
+    /// come in any order, but every symbol must have exactly one.
+    pub fn from_ncbi(text: &str) -> Result<SubstitutionMatrix, PoaError> {
+        let invalid = |message: String| Err(PoaError::InvalidScores(message));
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let header = match lines.next() {
+            Some(header) => header,
+            None => return invalid("the substitution matrix has no symbols".to_string()),
+        };
+        let mut alphabet = Vec::new();
+        for symbol in header.split_whitespace() {
+            match symbol.as_bytes() {
+                &[symbol] => alphabet.push(symbol),
+                _ => return invalid(format!("symbol {} of the substitution matrix is not one character", symbol)),
+            }
+        }
+
+        let mut rows: Vec<Option<Vec<i32>>> = vec![None; alphabet.len()];
+        for line in lines {
+            let mut fields = line.split_whitespace();
+            let symbol = fields.next().unwrap_or_default().as_bytes();
+            let row = match alphabet.iter().position(|other| symbol.len() == 1 && other.eq_ignore_ascii_case(&symbol[0])) {
+                Some(row) if rows[row].is_none() => row,
+                _ => return invalid(format!("unexpected row {} of the substitution matrix", line)),
+            };
+            let scores: Result<Vec<i32>, _> = fields.map(str::parse).collect();
+            match scores {
+                Ok(scores) => rows[row] = Some(scores),
+                Err(_) => return invalid(format!("malformed row {} of the substitution matrix", line)),
+            }
+        }
+        if let Some(missing) = rows.iter().position(Option::is_none) {
+            return invalid(format!("the substitution matrix has no row for {}", alphabet[missing] as char));
+        }
+
+        let scores: Vec<Vec<i32>> = rows.into_iter().flatten().collect();
+        SubstitutionMatrix::new(&alphabet, &scores)
+    }
+
+    /// Creates a matrix scoring `match_score` for equal symbols of `alphabet` and `mismatch_score`
+    /// for all others, as `PoaParams` scores them, e.g. as a base to adjust single pairs of.
+    pub fn uniform(alphabet: &[u8], match_score: i32, mismatch_score: i32) -> Result<SubstitutionMatrix, PoaError> {
+        let scores: Vec<Vec<i32>> = (0..alphabet.len())
+            .map(|a| (0..alphabet.len()).map(|b| if a == b { match_score } else { mismatch_score }).collect())
+            .collect();
+        SubstitutionMatrix::new(alphabet, &scores)
+    }
+
+    /// Returns the symbols of the matrix, in the order of its rows and columns.
+    pub fn alphabet(&self) -> &[u8] {
+        &self.alphabet
+    }
+
+    /// Returns the score of aligning symbol `read` of a read to a node holding `graph`.
+    pub fn score(&self, graph: u8, read: u8) -> i32 {
+        match (self.index(graph), self.index(read)) {
+            (Some(a), Some(b)) => self.scores[a * self.alphabet.len() + b],
+            _ => self.lowest(),
+        }
+    }
+
+    /// Sets the score of aligning symbol `read` of a read to a node holding `graph`.
+    ///
+    /// # Panics
+    /// Panics if either symbol is not in the alphabet, or the score does not fit in 8 bits.
+    pub fn set_score(&mut self, graph: u8, read: u8, score: i32) {
+        assert!(i8::try_from(score).is_ok(), "substitution score {} does not fit in 8 bits", score);
+        let (a, b) = match (self.index(graph), self.index(read)) {
+            (Some(a), Some(b)) => (a, b),
+            _ => panic!("symbol not in the substitution matrix"),
+        };
+        self.scores[a * self.alphabet.len() + b] = score;
+    }
+
+    // the scores, row by row, as poa_graph_new_matrix takes them
+    pub(crate) fn raw_scores(&self) -> &[i32] {
+        &self.scores
+    }
+
+    // the lowest score of the matrix, which symbols outside the alphabet score
+    fn lowest(&self) -> i32 {
+        self.scores.iter().copied().min().unwrap_or(0).min(0)
+    }
+
+    fn index(&self, symbol: u8) -> Option<usize> {
+        self.alphabet.iter().position(|other| other.eq_ignore_ascii_case(&symbol))
+    }
+}
+
+impl From<Matrix> for SubstitutionMatrix {
+    fn from(matrix: Matrix) -> SubstitutionMatrix {
+        matrix.scores()
+    }
+}
+
+/// Generates the consensus of the reads, aligning them with a substitution matrix in place of the
+/// match and mismatch scores of `params`.
+///
+/// Empty reads and trailing null terminators are ignored, as by `poa_consensus_batch`.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment type and gap scores
+/// * `matrix` - the score of each pair of symbols
+///
+/// # Returns
+/// * returns the consensus sequence, empty if no read is non-empty
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_matrix, Matrix, PoaParams};
+///
+///     let seqs = vec![b"HEAGAWGHEE\0".to_vec(), b"HEAGAWGHEE\0".to_vec(), b"PAWHEAE\0".to_vec()];
+///     let consensus = poa_consensus_with_matrix(&seqs, 0, &PoaParams::protein(), &Matrix::Pam250.into());
+///
+///     assert_eq!(consensus, b"HEAGAWGHEE".to_vec());
+/// ```
+pub fn poa_consensus_with_matrix<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    matrix: &SubstitutionMatrix,
+) -> Vec<u8> {
+    let mut graph = PoaGraph::with_matrix(params, matrix);
+    for seq in seqs {
+        let seq = seq.as_ref();
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        if !seq.is_empty() {
+            graph.add_sequence(seq);
+        }
+    }
+    let mut consensus = graph.consensus();
+    if consensus_max_length > 0 {
+        consensus.truncate(consensus_max_length);
+    }
+    consensus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_matrices() {
+        let blosum62 = SubstitutionMatrix::from(Matrix::Blosum62);
+        assert_eq!(blosum62.alphabet(), b"ARNDCQEGHILKMFPSTWYVBZX*");
+        assert_eq!((blosum62.score(b'W', b'W'), blosum62.score(b'c', b'C'), blosum62.score(b'I', b'V')), (11, 9, 3));
+        let pam250 = Matrix::Pam250.scores();
+        assert_eq!((pam250.score(b'W', b'W'), pam250.score(b'C', b'W'), pam250.score(b'F', b'Y')), (17, -8, 7));
+
+        for &matrix in Matrix::ALL.iter() {
+            let scores = matrix.scores();
+            for &a in scores.alphabet() {
+                for &b in scores.alphabet() {
+                    assert_eq!(scores.score(a, b), scores.score(b, a), "{} {}{}", matrix.as_str(), a as char, b as char);
+                }
+            }
+            assert_eq!(matrix.as_str().to_uppercase().parse::<Matrix>(), Ok(matrix));
+        }
+        // symbols outside the alphabet score the lowest score
+        assert_eq!(blosum62.score(b'-', b'A'), -4);
+        assert!("blosum80".parse::<Matrix>().is_err());
+    }
+
+    #[test]
+    fn test_invalid_matrices() {
+        assert!(SubstitutionMatrix::new(b"", &[]).is_err());
+        assert!(SubstitutionMatrix::new(b"Aa", &[vec![1, 0], vec![0, 1]]).is_err());
+        assert!(SubstitutionMatrix::new(b"AC", &[vec![1, 0], vec![0]]).is_err());
+        assert!(SubstitutionMatrix::uniform(b"AC", 200, 0).is_err());
+        assert!(SubstitutionMatrix::from_ncbi("# no symbols\n").is_err());
+        assert!(SubstitutionMatrix::from_ncbi("  A C\nA 1 0\n").is_err());
+        assert!(SubstitutionMatrix::from_ncbi("  A C\nA 1 0\nC 0 x\n").is_err());
+        // rows may come in any order
+        let matrix = SubstitutionMatrix::from_ncbi("  A C\nC -1 2\nA 1 -1\n").unwrap();
+        assert_eq!(matrix, SubstitutionMatrix::new(b"AC", &[vec![1, -1], vec![-1, 2]]).unwrap());
+    }
+
+    #[test]
+    fn test_uniform_matrix_aligns_as_params() {
+        let reads = ["ATTGCCCGTT", "AATGCCGTT", "AATGCCCGAT", "AACGCCCGTC", "AGTGCTCGTT", "AATGCTCGTT"];
+        let matrix = SubstitutionMatrix::uniform(b"ACGT", 5, -4).unwrap();
+        for alignment_type in 0..3 {
+            for &(gap_open, gap_extend) in [(-8, -6), (-4, -2), (-3, -3)].iter() {
+                let params = PoaParams::new(alignment_type, 5, -4, gap_open, gap_extend);
+                let mut native = PoaGraph::new(&params);
+                let mut scored = PoaGraph::with_matrix(&params, &matrix);
+                for read in reads.iter() {
+                    native.add_sequence(read.as_bytes());
+                    scored.add_sequence(read.as_bytes());
+                }
+                assert_eq!(scored.simd(), crate::SimdBackend::Scalar);
+                assert_eq!(scored.consensus(), native.consensus(), "{:?}", params);
+                assert_eq!(scored.num_nodes(), native.num_nodes(), "{:?}", params);
+            }
+        }
+    }
+
+    #[test]
+    fn test_matrix_changes_the_alignment() {
+        // two gaps cost less than a mismatch, except for the transitions made cheap
+        let params = PoaParams::new(1, 5, -25, -10, -10);
+        let mut matrix = SubstitutionMatrix::uniform(b"ACGT", 5, -25).unwrap();
+        matrix.set_score(b'A', b'G', 2);
+        matrix.set_score(b'G', b'A', 2);
+
+        let msa_len = |matrix: &SubstitutionMatrix, variant: &[u8]| {
+            let mut graph = PoaGraph::with_matrix(&params, matrix);
+            graph.add_sequence(b"CCCACCC");
+            graph.add_sequence(variant);
+            graph.multiple_sequence_alignment(false)[0].len()
+        };
+        assert_eq!(msa_len(&matrix, b"CCCGCCC"), 7);
+        assert_eq!(msa_len(&matrix, b"CCCTCCC"), 8);
+
+        let mut graph = PoaGraph::with_matrix(&params, &matrix);
+        graph.add_sequence(b"CCCACCC");
+        let subgraph = graph.subgraph(0, 6);
+        assert_eq!(subgraph.matrix(), Some(&matrix));
+        assert_eq!(graph.reverse_complement().matrix(), Some(&matrix));
+
+        let consensus = poa_consensus_with_matrix(
+            &[b"CCCACCC\0".to_vec(), b"".to_vec(), b"CCCACCC".to_vec()],
+            4,
+            &params,
+            &matrix,
+        );
+        assert_eq!(consensus, b"CCCA".to_vec());
+    }
+}
@@ -0,0 +1,107 @@
+//! Estimating the memory a consensus takes, so that a job under a memory budget fails predictably
+//! instead of being killed.
+
+use crate::{GapModel, PoaGraph, PoaParams};
+
+// the estimated bytes of a node of the native graph, with its edges and aligned nodes
+const NODE_BYTES: usize = 160;
+// the estimated bytes each added base takes in edge labels and the stored sequences and weights
+const BASE_BYTES: usize = 16;
+// the fraction of the bases of each read after the first assumed to become new nodes, as for reads
+// of about 90% identity
+const NEW_NODE_FRACTION: f64 = 0.1;
+
+// the number of 32-bit matrices of the alignment engine for the gap model it picks
+fn dp_matrices(params: &PoaParams) -> usize {
+    match params.gaps() {
+        GapModel::Linear(_) => 1,
+        GapModel::Affine { .. } => 3,
+        GapModel::Convex { .. } => 5,
+    }
+}
+
+// the bytes of the matrices aligning a sequence of seq_len bases to a graph of num_nodes nodes
+fn alignment_bytes(num_nodes: usize, seq_len: usize, params: &PoaParams) -> usize {
+    let cells = num_nodes.saturating_add(1).saturating_mul(seq_len.saturating_add(1));
+    cells.saturating_mul(dp_matrices(params)).saturating_mul(4)
+}
+
+/// Estimates the peak memory in bytes of building the consensus of reads of the given lengths.
+///
+/// The estimate counts the graph, assuming every base of the first read and 10% of the bases of
+/// each later read become new nodes, and the matrices of the alignment engine, which keep the size
+/// of the largest alignment: the number of nodes times the read length, for one to five matrices
+/// depending on the gap scores. Banded alignment takes less than estimated. Reads far below 90%
+/// identity grow the graph faster; `GrowthLimits::max_memory` checks the actual graph instead.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{estimate_memory, PoaParams};
+///
+///     let params = PoaParams::dna();
+///     let small = estimate_memory(&[1000; 10], &params);
+///     assert!(small > 20_000_000 && small < 25_000_000);
+///     // longer reads cost quadratically more
+///     assert!(estimate_memory(&[10_000; 10], &params) > 90 * small);
+/// ```
+pub fn estimate_memory(seq_lengths: &[usize], params: &PoaParams) -> usize {
+    let mut num_nodes = 0usize;
+    let mut num_bases = 0usize;
+    let mut alignment = 0;
+    for &len in seq_lengths.iter().filter(|&&len| len > 0) {
+        alignment = alignment.max(alignment_bytes(num_nodes, len, params));
+        num_nodes = num_nodes.saturating_add(if num_nodes == 0 { len } else { (len as f64 * NEW_NODE_FRACTION).ceil() as usize });
+        num_bases = num_bases.saturating_add(len);
+    }
+    num_nodes.saturating_mul(NODE_BYTES).saturating_add(num_bases.saturating_mul(BASE_BYTES)).saturating_add(alignment)
+}
+
+impl PoaGraph {
+    /// Estimates the memory in bytes the graph holds, by its nodes and the bases of its sequences.
+    pub fn memory_usage(&self) -> usize {
+        self.num_nodes() * NODE_BYTES + self.num_bases() * BASE_BYTES
+    }
+
+    /// Estimates the memory in bytes of aligning a sequence of `seq_len` bases to the graph and
+    /// adding it: the matrices of the alignment engine, with the growth of the graph if every base
+    /// became a new node.
+    pub fn alignment_memory(&self, seq_len: usize) -> usize {
+        alignment_bytes(self.num_nodes(), seq_len, &self.params()).saturating_add(seq_len.saturating_mul(NODE_BYTES + BASE_BYTES))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConvexGaps, GrowthLimits, RejectReason};
+
+    #[test]
+    fn test_estimates() {
+        let affine = PoaParams::new(1, 5, -4, -8, -6);
+        assert_eq!(estimate_memory(&[], &affine), 0);
+        // the first read is aligned to nothing, the second to its 100 nodes
+        assert_eq!(estimate_memory(&[100, 0, 100], &affine), 110 * NODE_BYTES + 200 * BASE_BYTES + 101 * 101 * 3 * 4);
+
+        assert_eq!(dp_matrices(&affine), 3);
+        assert_eq!(dp_matrices(&PoaParams::new(1, 5, -4, -4, -4)), 1);
+        assert_eq!(dp_matrices(&affine.convex_gaps(ConvexGaps::new(-20, -1))), 5);
+        assert_eq!(dp_matrices(&affine.convex_gaps(ConvexGaps::new(-8, -1))), 3);
+        assert_eq!(estimate_memory(&[usize::MAX, usize::MAX], &affine), usize::MAX);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+        graph.add_sequence(b"AATGCCCGTT");
+        assert_eq!(graph.memory_usage(), 10 * NODE_BYTES + 10 * BASE_BYTES);
+        assert_eq!(graph.alignment_memory(10), 11 * 11 * 3 * 4 + 10 * (NODE_BYTES + BASE_BYTES));
+
+        let required = graph.memory_usage() + graph.alignment_memory(10);
+        let limits = GrowthLimits::new().max_memory(required - 1);
+        assert_eq!(graph.add_sequence_limited(b"AATGCCCGTT", &limits), Err(RejectReason::MemoryLimit));
+        assert_eq!(graph.num_sequences(), 1);
+        assert_eq!(graph.add_sequence_limited(b"AATGCCCGTT", &GrowthLimits::new().max_memory(required)), Ok(()));
+        assert_eq!(RejectReason::MemoryLimit.to_string(), "memory_limit");
+    }
+}
@@ -0,0 +1,199 @@
+//! Alignment modes, and choosing one from the structure of the reads.
+
+use crate::align::{edit_distance, fit, AlignOp};
+use crate::PoaParams;
+
+/// How reads are aligned to the graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AlignmentType {
+    /// local alignment (Smith-Waterman), for reads sharing only a core region
+    Local,
+    /// global alignment (Needleman-Wunsch), for reads spanning the same region end to end
+    Global,
+    /// semi-global alignment without penalties for end gaps, for fragments of a common region
+    SemiGlobal,
+    /// one of the other modes, chosen per group of reads by `choose_alignment_type`
+    Auto,
+}
+
+impl AlignmentType {
+    /// Returns the mode for the numeric `alignment_type` of `PoaParams`, if it is a valid one.
+    pub fn from_code(code: i32) -> Option<AlignmentType> {
+        match code {
+            0 => Some(AlignmentType::Local),
+            1 => Some(AlignmentType::Global),
+            2 => Some(AlignmentType::SemiGlobal),
+            _ => None,
+        }
+    }
+
+    /// Returns the numeric `alignment_type` of `PoaParams` for the mode, or `None` for `Auto`.
+    pub fn code(self) -> Option<i32> {
+        match self {
+            AlignmentType::Local => Some(0),
+            AlignmentType::Global => Some(1),
+            AlignmentType::SemiGlobal => Some(2),
+            AlignmentType::Auto => None,
+        }
+    }
+
+    /// Returns the mode itself, or for `Auto` the mode chosen for `reads`.
+    pub fn resolve<T: AsRef<[u8]>>(self, reads: &[T]) -> AlignmentType {
+        match self {
+            AlignmentType::Auto => choose_alignment_type(reads),
+            mode => mode,
+        }
+    }
+}
+
+impl PoaParams {
+    /// Sets the alignment type to the one chosen for `reads` by `choose_alignment_type`.
+    pub fn auto_alignment_type<T: AsRef<[u8]>>(mut self, reads: &[T]) -> PoaParams {
+        self.alignment_type = choose_alignment_type(reads).code().expect("a concrete mode is chosen");
+        self
+    }
+}
+
+// the length of the read ends compared against the reference read
+const END_LEN: usize = 15;
+
+// how a read relates to the reference read, judged from where its ends are found in it
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Ends {
+    // both ends are found at the ends of the reference
+    Spanning,
+    // at least one end is found, away from the end of the reference for the other
+    Fragment,
+    // neither end is found, as for reads with unrelated flanks around a shared core
+    Unanchored,
+}
+
+// returns the start and end in `reference` of the best fit of `end`, if it has few enough edits
+fn locate(end: &[u8], reference: &[u8]) -> Option<(usize, usize)> {
+    let (start, ops) = fit(end, reference);
+    if edit_distance(&ops) * 4 > end.len() {
+        return None;
+    }
+    let span = ops.iter().filter(|&&op| op != AlignOp::Insertion).count();
+    Some((start, start + span))
+}
+
+fn classify(read: &[u8], reference: &[u8]) -> Ends {
+    let k = END_LEN.min(read.len() / 3).min(reference.len());
+    let slack = (reference.len() / 50).max(3);
+    let prefix = locate(&read[..k], reference);
+    let suffix = locate(&read[read.len() - k..], reference);
+
+    match (prefix, suffix) {
+        (None, None) => Ends::Unanchored,
+        (Some((start, _)), Some((_, end))) if start <= slack && end + slack >= reference.len() => Ends::Spanning,
+        _ => Ends::Fragment,
+    }
+}
+
+/// Chooses the alignment mode suited to a group of reads, from their lengths and the way their ends overlap.
+///
+/// Each read is compared to the longest one. Reads whose two ends fall at the ends of the longest
+/// read, with similar lengths throughout the group, call for global alignment. If many reads have
+/// ends found nowhere in the longest read, the reads share only a core and local alignment is
+/// chosen. Otherwise the reads are treated as fragments of a common region, for semi-global
+/// alignment. Groups with fewer than two reads long enough to compare are aligned globally.
+/// A trailing null terminator of a read is ignored.
+/// # Arguments
+///
+/// * `reads` - the reads of the group
+///
+/// # Returns
+/// * returns `Local`, `Global` or `SemiGlobal`, never `Auto`
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{choose_alignment_type, AlignmentType};
+///
+///     let full = vec!["AATGCCCGTTAGGCATTACG", "AATGCCCGTTAGCATTACG", "AATGCCCGTTAGGCATTACG"];
+///     assert_eq!(choose_alignment_type(&full), AlignmentType::Global);
+///
+///     let fragments = vec!["AATGCCCGTTAGGCATTACGTTGACCAGTA", "CCGTTAGGCATTACGTTGAC", "AATGCCCGTTAGGCATTAC"];
+///     assert_eq!(choose_alignment_type(&fragments), AlignmentType::SemiGlobal);
+/// ```
+pub fn choose_alignment_type<T: AsRef<[u8]>>(reads: &[T]) -> AlignmentType {
+    let reads: Vec<&[u8]> = reads
+        .iter()
+        .map(|read| {
+            let read = read.as_ref();
+            read.strip_suffix(b"\0").unwrap_or(read)
+        })
+        .filter(|read| read.len() >= 12)
+        .collect();
+    if reads.len() < 2 {
+        return AlignmentType::Global;
+    }
+
+    let lengths: Vec<f64> = reads.iter().map(|read| read.len() as f64).collect();
+    let mean = lengths.iter().sum::<f64>() / lengths.len() as f64;
+    let sd = (lengths.iter().map(|len| (len - mean).powi(2)).sum::<f64>() / lengths.len() as f64).sqrt();
+
+    let reference = reads.iter().enumerate().max_by_key(|&(i, read)| (read.len(), std::cmp::Reverse(i))).map(|(i, _)| i).unwrap();
+    let ends: Vec<Ends> = reads
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != reference)
+        .map(|(_, read)| classify(read, reads[reference]))
+        .collect();
+    let fraction = |kind: Ends| ends.iter().filter(|&&e| e == kind).count() as f64 / ends.len() as f64;
+
+    if fraction(Ends::Unanchored) > 0.3 {
+        AlignmentType::Local
+    } else if fraction(Ends::Spanning) >= 0.8 && sd <= 0.1 * mean {
+        AlignmentType::Global
+    } else {
+        AlignmentType::SemiGlobal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::{random_sequence, LengthDistribution, ReadSimulator};
+
+    #[test]
+    fn test_codes() {
+        for code in 0..3 {
+            assert_eq!(AlignmentType::from_code(code).and_then(AlignmentType::code), Some(code));
+        }
+        assert_eq!(AlignmentType::from_code(3), None);
+        assert_eq!(AlignmentType::Auto.code(), None);
+        assert_eq!(AlignmentType::Local.resolve(&[b"ACGT"]), AlignmentType::Local);
+    }
+
+    #[test]
+    fn test_choices_on_simulated_reads() {
+        let truth = random_sequence(400, 8);
+
+        let full = ReadSimulator::new(1).error_rates(0.03, 0.02, 0.02).reads(&truth, 8);
+        assert_eq!(choose_alignment_type(&full), AlignmentType::Global);
+
+        let fragments = ReadSimulator::new(2)
+            .error_rates(0.03, 0.02, 0.02)
+            .length(LengthDistribution::Uniform { min: 150, max: 350 })
+            .reads(&truth, 8);
+        assert_eq!(choose_alignment_type(&fragments), AlignmentType::SemiGlobal);
+
+        let flanked: Vec<Vec<u8>> = full
+            .iter()
+            .enumerate()
+            .map(|(i, read)| [random_sequence(40, 100 + i as u64), read.clone(), random_sequence(40, 200 + i as u64)].concat())
+            .collect();
+        assert_eq!(choose_alignment_type(&flanked), AlignmentType::Local);
+
+        let params = PoaParams::new(1, 5, -4, -8, -6).auto_alignment_type(&fragments);
+        assert_eq!(params.alignment_type, 2);
+    }
+
+    #[test]
+    fn test_small_groups_are_global() {
+        assert_eq!(choose_alignment_type::<&[u8]>(&[]), AlignmentType::Global);
+        assert_eq!(choose_alignment_type(&[b"ACGTACGTACGTACGT\0"]), AlignmentType::Global);
+    }
+}
@@ -0,0 +1,268 @@
+//! Multiple sequence alignments of reads through their partial order graph, and statistics over
+//! their columns.
+
+use crate::{PoaGraph, PoaParams};
+
+mod column;
+mod counts;
+mod profile;
+mod qualities;
+mod variants;
+mod write;
+
+pub(crate) use self::column::alignment_columns;
+pub use self::column::{
+    column_consensus, column_consensus_gapped, poa_consensus_with_ambiguity, AmbiguityCode, Column, ColumnStrategy, Majority, WeightedMajority,
+};
+pub use self::counts::{base_counts, BaseCounts};
+pub use self::profile::{Profile, ProfileAlignment};
+pub use self::qualities::{poa_msa_with_qualities, QualityMsa, GAP_QUALITY};
+pub use self::variants::{poa_consensus_with_variants, variant_sites, Allele, VariantSite};
+pub use self::write::{write_clustal, write_fasta_msa, write_stockholm};
+
+/// Counts of the reads with indels at each consensus position.
+///
+/// A read counts at a position only if it spans it, i.e. has bases both at or before and at or
+/// after it; reads ending early, as in local or semi-global mode, are not counted as deletions.
+/// Bases before the first or after the last consensus base overhang the consensus and are not
+/// counted as insertions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndelStats {
+    /// the number of reads spanning each consensus position
+    pub depth: Vec<u32>,
+    /// the number of reads with bases between each consensus position and the next
+    pub insertions: Vec<u32>,
+    /// the number of spanning reads with a gap at each consensus position
+    pub deletions: Vec<u32>,
+}
+
+impl IndelStats {
+    /// Computes the statistics from a multiple sequence alignment whose last row is the consensus,
+    /// as returned by `PoaGraph::multiple_sequence_alignment(true)`.
+    ///
+    /// # Panics
+    /// Panics if the rows do not all have the same length.
+    pub fn from_msa(msa: &[Vec<u8>]) -> IndelStats {
+        let (consensus, reads) = match msa.split_last() {
+            Some(rows) => rows,
+            None => return IndelStats::default(),
+        };
+        assert!(reads.iter().all(|row| row.len() == consensus.len()), "msa rows differ in length");
+
+        let columns: Vec<usize> = (0..consensus.len()).filter(|&c| consensus[c] != b'-').collect();
+        let mut stats = IndelStats {
+            depth: vec![0; columns.len()],
+            insertions: vec![0; columns.len()],
+            deletions: vec![0; columns.len()],
+        };
+
+        for row in reads {
+            let first = match row.iter().position(|&c| c != b'-') {
+                Some(first) => first,
+                None => continue,
+            };
+            let last = row.iter().rposition(|&c| c != b'-').unwrap();
+
+            for (i, &column) in columns.iter().enumerate() {
+                if column < first || column > last {
+                    continue;
+                }
+                stats.depth[i] += 1;
+                if row[column] == b'-' {
+                    stats.deletions[i] += 1;
+                }
+                if let Some(&next) = columns.get(i + 1) {
+                    if next <= last && row[column + 1..next].iter().any(|&c| c != b'-') {
+                        stats.insertions[i] += 1;
+                    }
+                }
+            }
+        }
+
+        stats
+    }
+
+    /// Returns the consensus positions where at least `min_fraction` of the spanning reads have an
+    /// insertion after the position or a deletion at it.
+    pub fn hotspots(&self, min_fraction: f64) -> Vec<usize> {
+        (0..self.depth.len())
+            .filter(|&i| {
+                self.depth[i] > 0
+                    && f64::from(self.insertions[i] + self.deletions[i]) >= min_fraction * f64::from(self.depth[i])
+            })
+            .collect()
+    }
+}
+
+/// Computes the approximate pairwise identities of the rows of a multiple sequence alignment.
+///
+/// The identity of two rows is the fraction of matching bases among the columns of their overlap,
+/// from the later of their first bases to the earlier of their last bases, in which either row has
+/// a base. Rows without overlap have identity 0; each row has identity 1 to itself.
+///
+/// # Panics
+/// Panics if the rows do not all have the same length.
+pub fn identity_matrix(msa: &[Vec<u8>]) -> Vec<Vec<f64>> {
+    let len = msa.first().map_or(0, |row| row.len());
+    assert!(msa.iter().all(|row| row.len() == len), "msa rows differ in length");
+
+    // the first and last column holding a base of each row
+    let spans: Vec<Option<(usize, usize)>> = msa
+        .iter()
+        .map(|row| {
+            let first = row.iter().position(|&c| c != b'-')?;
+            Some((first, row.iter().rposition(|&c| c != b'-').unwrap()))
+        })
+        .collect();
+
+    let mut matrix = vec![vec![0.0; msa.len()]; msa.len()];
+    for i in 0..msa.len() {
+        matrix[i][i] = 1.0;
+        for j in i + 1..msa.len() {
+            let identity = match (spans[i], spans[j]) {
+                (Some((first_i, last_i)), Some((first_j, last_j))) if first_i.max(first_j) <= last_i.min(last_j) => {
+                    let overlap = first_i.max(first_j)..last_i.min(last_j) + 1;
+                    let (matches, columns) = msa[i][overlap.clone()]
+                        .iter()
+                        .zip(&msa[j][overlap])
+                        .filter(|&(&a, &b)| a != b'-' || b != b'-')
+                        .fold((0usize, 0usize), |(matches, columns), (a, b)| (matches + (a == b) as usize, columns + 1));
+                    matches as f64 / columns as f64
+                }
+                _ => 0.0,
+            };
+            matrix[i][j] = identity;
+            matrix[j][i] = identity;
+        }
+    }
+
+    matrix
+}
+
+impl PoaGraph {
+    /// Returns the counts of reads with insertions and deletions at each consensus position,
+    /// derived from the multiple sequence alignment of the graph.
+    pub fn indel_stats(&self) -> IndelStats {
+        IndelStats::from_msa(&self.multiple_sequence_alignment(true))
+    }
+
+    /// Returns the approximate identity of every pair of sequences in the graph, indexed by the
+    /// order they were added, as computed by `identity_matrix`.
+    ///
+    /// Every sequence is already aligned to the others through the graph, so this avoids a
+    /// pairwise alignment per pair. Two sequences aligned to the graph differently than they
+    /// would align to each other, e.g. across a shared insertion, may get a lower identity.
+    pub fn identity_matrix(&self) -> Vec<Vec<f64>> {
+        identity_matrix(&self.multiple_sequence_alignment(false))
+    }
+
+    /// Returns the consensus with gaps, aligned to the columns of the multiple sequence alignment.
+    ///
+    /// The result has the length of the rows of `multiple_sequence_alignment`, with `-` at the
+    /// columns the consensus skips, so column `c` of the consensus, of each row and of any
+    /// per-column statistic line up without bookkeeping. Removing the gaps gives `consensus()`.
+    pub fn gapped_consensus(&self) -> Vec<u8> {
+        self.multiple_sequence_alignment(true).pop().unwrap_or_default()
+    }
+}
+
+/// Aligns a set of sequences to each other through their partial order graph.
+/// # Arguments
+///
+/// * `seqs` - the sequences to align, without null terminators; empty sequences are left out
+/// * `params` - the alignment and scoring parameters
+/// * `include_consensus` - whether to add the consensus as a last row
+///
+/// # Returns
+/// * returns one gapped row per non-empty sequence, in input order, with `-` for gaps; all rows have the same length
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::msa::poa_msa;
+///     use rust_spoa::PoaParams;
+///
+///     let seqs = vec![b"AATGCCCGTT".to_vec(), b"AATGCCGTT".to_vec()];
+///     let rows = poa_msa(&seqs, &PoaParams::new(1, 5, -4, -3, -1), false);
+///
+///     assert_eq!(rows[0], b"AATGCCCGTT".to_vec());
+///     assert_eq!(rows[1].len(), 10);
+/// ```
+pub fn poa_msa<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams, include_consensus: bool) -> Vec<Vec<u8>> {
+    let mut graph = PoaGraph::new(params);
+    for seq in seqs.iter().map(AsRef::as_ref).filter(|seq| !seq.is_empty()) {
+        graph.add_sequence(seq);
+    }
+    graph.multiple_sequence_alignment(include_consensus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poa_msa() {
+        let seqs = vec![b"AACGT".to_vec(), b"ACGT".to_vec(), b"AACGT".to_vec()];
+        let rows = poa_msa(&seqs, &PoaParams::new(1, 5, -4, -3, -1), true);
+
+        assert_eq!(rows.len(), 4);
+        assert!(rows.iter().all(|row| row.len() == 5));
+        assert_eq!(rows[1].iter().filter(|&&c| c == b'-').count(), 1);
+        assert_eq!(rows[3], b"AACGT".to_vec());
+        assert!(poa_msa(&Vec::<Vec<u8>>::new(), &PoaParams::new(1, 5, -4, -3, -1), false).is_empty());
+    }
+
+    #[test]
+    fn test_indel_stats_from_msa() {
+        let msa: Vec<Vec<u8>> = ["AC-GT-", "A--GTA", "ACTGT-", "--TGT-", "AC-GT-"].iter().map(|row| row.bytes().collect()).collect();
+        let stats = IndelStats::from_msa(&msa);
+
+        // the first read overhangs nothing; the second deletes C and overhangs the end; the
+        // third inserts T after C; the fourth starts inside the gap after C
+        assert_eq!(stats.depth, vec![3, 3, 4, 4]);
+        assert_eq!(stats.insertions, vec![0, 1, 0, 0]);
+        assert_eq!(stats.deletions, vec![0, 1, 0, 0]);
+        assert_eq!(stats.hotspots(0.5), vec![1]);
+    }
+
+    #[test]
+    fn test_graph_indel_stats() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        for seq in [&b"AATGCCCGTT"[..], b"AATGCCCGTT", b"AATGCCGTT", b"AATGCCCGTT"].iter() {
+            graph.add_sequence(seq);
+        }
+        let stats = graph.indel_stats();
+
+        assert_eq!(stats.depth, vec![4; 10]);
+        assert_eq!(stats.deletions.iter().sum::<u32>(), 1);
+        assert_eq!(stats.insertions.iter().sum::<u32>(), 0);
+        assert!(PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1)).indel_stats().depth.is_empty());
+    }
+
+    #[test]
+    fn test_identity_matrix() {
+        let msa: Vec<Vec<u8>> = ["AACGT-", "AAGGTA", "--CG--", "A----A"].iter().map(|row| row.bytes().collect()).collect();
+        let matrix = identity_matrix(&msa);
+
+        assert_eq!(matrix[0][0], 1.0);
+        assert_eq!(matrix[0][1], 4.0 / 5.0);
+        assert_eq!(matrix[1][0], matrix[0][1]);
+        assert_eq!(matrix[0][2], 1.0);
+        assert_eq!(matrix[1][3], 2.0 / 6.0);
+        assert_eq!(matrix[2][3], 0.0);
+        assert!(identity_matrix(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_graph_identity_matrix() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        for seq in [&b"AATGCCCGTT"[..], b"AATGCCCGTT", b"AATGCCGTT"].iter() {
+            graph.add_sequence(seq);
+        }
+        let matrix = graph.identity_matrix();
+
+        assert_eq!(matrix.len(), 3);
+        assert_eq!(matrix[0][1], 1.0);
+        assert_eq!(matrix[0][2], 9.0 / 10.0);
+    }
+}
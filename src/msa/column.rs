@@ -0,0 +1,385 @@
+//! Calling the consensus of a multiple sequence alignment column by column, with a strategy
+//! deciding the symbol of each column.
+
+use std::cmp::Reverse;
+
+use crate::{PoaGraph, PoaParams};
+
+/// The symbols of the reads spanning one column of a multiple sequence alignment.
+///
+/// A read spans a column if it has bases both at or before and at or after it, so reads ending
+/// early do not vote for a gap where they have no bases.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Column {
+    /// the index of each spanning read, its symbol (`-` for a gap) and the weight of the symbol
+    pub calls: Vec<(usize, u8, f64)>,
+}
+
+impl Column {
+    /// Returns the number of reads spanning the column.
+    pub fn depth(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// Returns the number of reads with `symbol` in the column.
+    pub fn count(&self, symbol: u8) -> usize {
+        self.calls.iter().filter(|&&(_, s, _)| s == symbol).count()
+    }
+
+    /// Returns the summed weight of the reads with `symbol` in the column.
+    pub fn weight(&self, symbol: u8) -> f64 {
+        self.calls.iter().filter(|&&(_, s, _)| s == symbol).map(|&(_, _, weight)| weight).sum()
+    }
+
+    /// Returns the distinct symbols of the column, gaps included, in order of first appearance.
+    pub fn symbols(&self) -> Vec<u8> {
+        let mut symbols = vec![];
+        for &(_, symbol, _) in &self.calls {
+            if !symbols.contains(&symbol) {
+                symbols.push(symbol);
+            }
+        }
+        symbols
+    }
+
+    // returns the symbol with the highest score, the first one seen among equals, or None if there
+    // are no symbols or the gap wins
+    fn best_by<F: Fn(u8) -> f64>(&self, score: F) -> Option<u8> {
+        let mut best: Option<(u8, f64)> = None;
+        for symbol in self.symbols() {
+            let score = score(symbol);
+            if !matches!(best, Some((_, best)) if score <= best) {
+                best = Some((symbol, score));
+            }
+        }
+        best.map(|(symbol, _)| symbol).filter(|&symbol| symbol != b'-')
+    }
+}
+
+/// Decides the consensus symbol of an alignment column.
+///
+/// Implementations see every spanning read of the column, by index, so they can apply rules
+/// depending on the reads, e.g. requiring support from both strands. Closures taking a `&Column`
+/// are strategies too.
+pub trait ColumnStrategy {
+    /// Returns the symbol called at the column, or `None` to leave the column out of the consensus.
+    fn call(&self, column: &Column) -> Option<u8>;
+}
+
+impl<F: Fn(&Column) -> Option<u8>> ColumnStrategy for F {
+    fn call(&self, column: &Column) -> Option<u8> {
+        self(column)
+    }
+}
+
+/// Calls the symbol of most reads, or leaves the column out if most reads have a gap. Ties go to
+/// the symbol of the first read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Majority;
+
+impl ColumnStrategy for Majority {
+    fn call(&self, column: &Column) -> Option<u8> {
+        column.best_by(|symbol| column.count(symbol) as f64)
+    }
+}
+
+/// Calls the symbol with the highest summed weight, e.g. of base qualities, as `Majority` does for
+/// read counts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WeightedMajority;
+
+impl ColumnStrategy for WeightedMajority {
+    fn call(&self, column: &Column) -> Option<u8> {
+        column.best_by(|symbol| column.weight(symbol))
+    }
+}
+
+/// Calls the IUPAC code of every nucleotide held by at least a fraction of the reads with a base
+/// in the column, for consensus sequences of mixed samples.
+///
+/// The column is left out if most reads have a gap. Nucleotides are compared ignoring case and
+/// the code is upper case; symbols other than A, C, G and T never take part in a code, and the
+/// majority symbol is called if no nucleotide is frequent enough. With a minimum agreement, a
+/// column whose most common nucleotide is held by that fraction of the reads with a base calls
+/// it without a code, so only columns without a clear majority, such as heterozygous positions,
+/// are ambiguous.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::msa::{column_consensus, AmbiguityCode};
+///
+///     let mut msa = vec![b"ACGTA".to_vec(); 6];
+///     msa.extend(vec![b"ACTTC".to_vec(); 4]);
+///
+///     assert_eq!(column_consensus(&msa, None, &AmbiguityCode::new(0.3)), b"ACKTM".to_vec());
+///     // 60% agree on the last two columns, 70% are needed to call a base without a code
+///     assert_eq!(column_consensus(&msa, None, &AmbiguityCode::new(0.3).min_agreement(0.7)), b"ACKTM".to_vec());
+///     assert_eq!(column_consensus(&msa, None, &AmbiguityCode::new(0.3).min_agreement(0.6)), b"ACGTA".to_vec());
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AmbiguityCode {
+    min_fraction: f64,
+    min_agreement: Option<f64>,
+}
+
+impl AmbiguityCode {
+    /// Creates the strategy with the fraction of the reads with a base that a nucleotide must reach.
+    pub fn new(min_fraction: f64) -> AmbiguityCode {
+        AmbiguityCode { min_fraction, min_agreement: None }
+    }
+
+    /// Sets the fraction of the reads with a base that the most common nucleotide must reach to be
+    /// called without a code, e.g. 0.7.
+    pub fn min_agreement(mut self, min_agreement: f64) -> AmbiguityCode {
+        self.min_agreement = Some(min_agreement);
+        self
+    }
+}
+
+// the IUPAC code of each set of nucleotides, with A, C, G and T as bits 0 to 3
+const IUPAC_CODES: &[u8; 16] = b"-ACMGRSVTWYHKDBN";
+
+impl ColumnStrategy for AmbiguityCode {
+    fn call(&self, column: &Column) -> Option<u8> {
+        let majority = Majority.call(column)?;
+        let bases = column.depth() - column.count(b'-');
+
+        let counts: Vec<usize> = b"ACGT"
+            .iter()
+            .map(|&nucleotide| column.calls.iter().filter(|&&(_, s, _)| s.to_ascii_uppercase() == nucleotide).count())
+            .collect();
+        if let Some(min_agreement) = self.min_agreement {
+            // the most common nucleotide, the first one seen among equals
+            let nucleotides = column.symbols().into_iter().filter_map(|s| b"ACGT".iter().position(|&n| n == s.to_ascii_uppercase()));
+            if let Some(bit) = nucleotides.min_by_key(|&bit| Reverse(counts[bit])) {
+                if counts[bit] as f64 >= min_agreement * bases as f64 {
+                    return Some(b"ACGT"[bit]);
+                }
+            }
+        }
+
+        let mut set = 0;
+        for (bit, &count) in counts.iter().enumerate() {
+            if count > 0 && count as f64 >= self.min_fraction * bases as f64 {
+                set |= 1 << bit;
+            }
+        }
+        match set {
+            0 => Some(majority),
+            set => Some(IUPAC_CODES[set]),
+        }
+    }
+}
+
+// the columns of a multiple sequence alignment, with the weight of each read base, or 1 without
+// weights; a gap weighs as much as the bases on either side of it in its read
+pub(crate) fn alignment_columns(msa: &[Vec<u8>], weights: Option<&[Vec<f64>]>) -> Vec<Column> {
+    let width = msa.first().map_or(0, Vec::len);
+    assert!(msa.iter().all(|row| row.len() == width), "msa rows differ in length");
+    if let Some(weights) = weights {
+        assert_eq!(weights.len(), msa.len(), "one weight vector is needed per msa row");
+    }
+
+    let mut columns = vec![Column::default(); width];
+    for (read, row) in msa.iter().enumerate() {
+        let bases = row.iter().filter(|&&c| c != b'-').count();
+        let base_weight = |k: usize| match weights {
+            Some(weights) => {
+                assert_eq!(weights[read].len(), bases, "weights length differs from the bases of the msa row");
+                weights[read][k]
+            }
+            None => 1.0,
+        };
+        let (first, last) = match (row.iter().position(|&c| c != b'-'), row.iter().rposition(|&c| c != b'-')) {
+            (Some(first), Some(last)) => (first, last),
+            _ => continue,
+        };
+
+        // k counts the bases of the row before the current column
+        let mut k = 0;
+        for (c, &symbol) in row.iter().enumerate().take(last + 1).skip(first) {
+            if symbol == b'-' {
+                let weight = (base_weight(k - 1) + base_weight(k)) / 2.0;
+                columns[c].calls.push((read, symbol, weight));
+            } else {
+                columns[c].calls.push((read, symbol, base_weight(k)));
+                k += 1;
+            }
+        }
+    }
+    columns
+}
+
+/// Calls the consensus of a multiple sequence alignment, deciding each column with `strategy`.
+/// # Arguments
+///
+/// * `msa` - the gapped rows of the alignment, all of the same length, with `-` for gaps
+/// * `weights` - the weight of each base of each row, in order and without gaps, or `None` to weigh every base 1
+/// * `strategy` - the rule deciding the symbol of each column
+///
+/// # Returns
+/// * returns the symbols called at the columns that were not left out, in order
+///
+/// # Panics
+/// Panics if the rows differ in length, or the weights do not match the bases of the rows.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::msa::{column_consensus, AmbiguityCode, Majority};
+///
+///     let msa = vec![b"AATGC-CGTT".to_vec(), b"AATGCACGTT".to_vec(), b"AAGGC-CGTT".to_vec(), b"--TGC-CG--".to_vec()];
+///
+///     assert_eq!(column_consensus(&msa, None, &Majority), b"AATGCCGTT".to_vec());
+///     assert_eq!(column_consensus(&msa, None, &AmbiguityCode::new(0.25)), b"AAKGCCGTT".to_vec());
+/// ```
+pub fn column_consensus<S: ColumnStrategy + ?Sized>(msa: &[Vec<u8>], weights: Option<&[Vec<f64>]>, strategy: &S) -> Vec<u8> {
+    alignment_columns(msa, weights).iter().filter_map(|column| strategy.call(column)).collect()
+}
+
+/// Calls the consensus of a multiple sequence alignment as `column_consensus` does, but keeps the
+/// columns that were left out as gaps, so the consensus has the length of the rows.
+///
+/// # Panics
+/// Panics if the rows differ in length, or the weights do not match the bases of the rows.
+pub fn column_consensus_gapped<S: ColumnStrategy + ?Sized>(
+    msa: &[Vec<u8>],
+    weights: Option<&[Vec<f64>]>,
+    strategy: &S
+) -> Vec<u8> {
+    alignment_columns(msa, weights).iter().map(|column| strategy.call(column).unwrap_or(b'-')).collect()
+}
+
+impl PoaGraph {
+    /// Calls the consensus of the multiple sequence alignment of the graph column by column, as
+    /// `column_consensus` does, weighting each base as it was weighted when added.
+    pub fn column_consensus<S: ColumnStrategy + ?Sized>(&self, strategy: &S) -> Vec<u8> {
+        let msa = self.multiple_sequence_alignment(false);
+        let weights: Vec<Vec<f64>> =
+            (0..self.num_sequences()).map(|i| self.sequence_weights(i).into_iter().map(f64::from).collect()).collect();
+        column_consensus(&msa, Some(&weights), strategy)
+    }
+}
+
+/// Generates a consensus with IUPAC ambiguity codes where reads disagree, e.g. at the heterozygous
+/// positions of an amplicon of a diploid sample.
+///
+/// Each column of the multiple sequence alignment of the reads whose most common nucleotide is held
+/// by less than `min_agreement` of the reads with a base there calls the code of every nucleotide
+/// held by at least `1 - min_agreement` of them, as by `AmbiguityCode`; other columns call the most
+/// common nucleotide. Empty reads and trailing null terminators are ignored, as by
+/// `poa_consensus_with_matrix`.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `params` - the alignment and scoring parameters
+/// * `min_agreement` - the fraction of the reads at a column that must agree on a base to call it without a code, e.g. 0.7
+///
+/// # Returns
+/// * returns the consensus, with upper case codes at ambiguous columns
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_ambiguity, PoaParams};
+///
+///     let mut reads = vec!["GATTACAGGCTTACC"; 5];
+///     reads.extend(vec!["GATTACAGACTTACC"; 4]);
+///     reads.push("GATTACTGGCTTACC");
+///     let consensus = poa_consensus_with_ambiguity(&reads, &PoaParams::new(1, 5, -4, -8, -6), 0.7);
+///
+///     // a read with a T at position 6 is an error, 4 reads with an A at position 8 an allele
+///     assert_eq!(consensus, b"GATTACAGRCTTACC".to_vec());
+/// ```
+pub fn poa_consensus_with_ambiguity<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams, min_agreement: f64) -> Vec<u8> {
+    let mut graph = PoaGraph::new(params);
+    for seq in seqs {
+        let seq = seq.as_ref();
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        if !seq.is_empty() {
+            graph.add_sequence(seq);
+        }
+    }
+    graph.column_consensus(&AmbiguityCode::new(1.0 - min_agreement).min_agreement(min_agreement))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PoaParams;
+
+    fn rows(rows: &[&str]) -> Vec<Vec<u8>> {
+        rows.iter().map(|row| row.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_columns_count_spanning_reads() {
+        let columns = alignment_columns(&rows(&["AC-GT", "-CAG-"]), Some(&[vec![1.0, 3.0, 5.0, 7.0], vec![2.0, 4.0, 6.0]]));
+
+        assert_eq!(columns[0].calls, vec![(0, b'A', 1.0)]);
+        // the gap weighs as much as its flanks
+        assert_eq!(columns[2].calls, vec![(0, b'-', 4.0), (1, b'A', 4.0)]);
+        assert_eq!(columns[4].depth(), 1);
+        assert_eq!(columns[3].symbols(), vec![b'G']);
+    }
+
+    #[test]
+    fn test_weights_decide_between_two() {
+        let msa = rows(&["AATGCCCGTT", "AATGCACGTT"]);
+        let mut weights = vec![vec![1.0; 10], vec![1.0; 10]];
+        weights[1][5] = 30.0;
+
+        assert_eq!(column_consensus(&msa, Some(&weights), &Majority), b"AATGCCCGTT".to_vec());
+        assert_eq!(column_consensus(&msa, Some(&weights), &WeightedMajority), b"AATGCACGTT".to_vec());
+        assert_eq!(column_consensus(&msa, None, &AmbiguityCode::new(0.4)), b"AATGCMCGTT".to_vec());
+    }
+
+    #[test]
+    fn test_agreement_threshold() {
+        let mut msa = rows(&["AAC-T"; 7]);
+        msa.extend(rows(&["AGCAT", "AGNAT", "AG-AT"]));
+        // the insertion of three reads is left out, as by Majority
+        assert_eq!(column_consensus(&msa, None, &AmbiguityCode::new(0.25).min_agreement(0.7)), b"AACT".to_vec());
+        assert_eq!(column_consensus(&msa, None, &AmbiguityCode::new(0.25).min_agreement(0.75)), b"ARCT".to_vec());
+        // N counts among the reads with a base, so 8 in 9 agree on C, but takes part in no code
+        assert_eq!(column_consensus(&msa, None, &AmbiguityCode::new(0.1).min_agreement(0.9)), b"ARCT".to_vec());
+
+        let reads = ["AATGCCCGTT\0", "AATGCACGTT\0", "", "AATGCACGTT", "AATGCCCGTT"];
+        assert_eq!(poa_consensus_with_ambiguity(&reads, &PoaParams::new(1, 5, -4, -8, -6), 0.7), b"AATGCMCGTT".to_vec());
+        assert_eq!(poa_consensus_with_ambiguity(&reads[..2], &PoaParams::new(1, 5, -4, -8, -6), 0.5), b"AATGCCCGTT".to_vec());
+        assert!(poa_consensus_with_ambiguity(&Vec::<Vec<u8>>::new(), &PoaParams::new(1, 5, -4, -8, -6), 0.7).is_empty());
+    }
+
+    #[test]
+    fn test_gapped_consensus_keeps_columns() {
+        let msa = rows(&["AATGC-CGTT", "AATGCACGTT", "AATGC-CGTT"]);
+        assert_eq!(column_consensus_gapped(&msa, None, &Majority), b"AATGC-CGTT".to_vec());
+
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        for seq in [&b"AATGCCCGTT"[..], b"AATGCCCGTT", b"AATGCCGTT", b"AATGCACCGTT"].iter() {
+            graph.add_sequence(seq);
+        }
+        let gapped = graph.gapped_consensus();
+        let msa = graph.multiple_sequence_alignment(false);
+        assert_eq!(gapped.len(), msa[0].len());
+        assert_eq!(gapped.iter().copied().filter(|&c| c != b'-').collect::<Vec<u8>>(), graph.consensus());
+        assert_eq!(column_consensus_gapped(&msa, None, &Majority), gapped);
+        assert!(PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1)).gapped_consensus().is_empty());
+    }
+
+    #[test]
+    fn test_graph_and_custom_strategies() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+        graph.add_sequence_weighted(b"AATGCCCGTT", &[1; 10]);
+        graph.add_sequence_weighted(b"AATGCCCGTT", &[1; 10]);
+        graph.add_sequence_weighted(b"AATGCACGTT", &[1, 1, 1, 1, 1, 9, 1, 1, 1, 1]);
+
+        assert_eq!(graph.column_consensus(&Majority), b"AATGCCCGTT".to_vec());
+        assert_eq!(graph.column_consensus(&WeightedMajority), b"AATGCACGTT".to_vec());
+
+        // a rule trusting only the last read added
+        let last_read = |column: &Column| column.calls.iter().find(|call| call.0 == 2).map(|call| call.1).filter(|&s| s != b'-');
+        assert_eq!(graph.column_consensus(&last_read), b"AATGCACGTT".to_vec());
+    }
+}
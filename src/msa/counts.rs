@@ -0,0 +1,145 @@
+//! Counting the bases of each column of a multiple sequence alignment, without building the read
+//! calls of a `Column`.
+
+use crate::PoaGraph;
+
+/// The number of reads with each nucleotide, another symbol or a gap in one column of a multiple
+/// sequence alignment.
+///
+/// As for `Profile`, only the reads spanning the column count, and bases are counted ignoring
+/// case. `Profile` holds the counts of every symbol, e.g. of amino acids.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BaseCounts {
+    /// the number of reads with `A`
+    pub a: u32,
+    /// the number of reads with `C`
+    pub c: u32,
+    /// the number of reads with `G`
+    pub g: u32,
+    /// the number of reads with `T`
+    pub t: u32,
+    /// the number of reads with any other symbol, such as `N`
+    pub other: u32,
+    /// the number of reads with a gap
+    pub gap: u32,
+}
+
+impl BaseCounts {
+    /// Returns the number of reads spanning the column, gaps included.
+    pub fn depth(&self) -> u32 {
+        self.a + self.c + self.g + self.t + self.other + self.gap
+    }
+
+    /// Returns the number of reads with `symbol` in the column, ignoring case, with `-` for gaps.
+    /// Symbols other than `ACGT` and `-` share the count of `other`.
+    pub fn count(&self, symbol: u8) -> u32 {
+        match symbol.to_ascii_uppercase() {
+            b'A' => self.a,
+            b'C' => self.c,
+            b'G' => self.g,
+            b'T' => self.t,
+            b'-' => self.gap,
+            _ => self.other,
+        }
+    }
+
+    /// Returns the fractions of `A`, `C`, `G` and `T` among the nucleotides of the column, a column
+    /// of a position frequency matrix; all 0 if the column has none.
+    pub fn frequencies(&self) -> [f64; 4] {
+        let total = self.a + self.c + self.g + self.t;
+        if total == 0 {
+            return [0.0; 4];
+        }
+        let total = f64::from(total);
+        [f64::from(self.a) / total, f64::from(self.c) / total, f64::from(self.g) / total, f64::from(self.t) / total]
+    }
+
+    fn add(&mut self, symbol: u8) {
+        match symbol.to_ascii_uppercase() {
+            b'A' => self.a += 1,
+            b'C' => self.c += 1,
+            b'G' => self.g += 1,
+            b'T' => self.t += 1,
+            b'-' => self.gap += 1,
+            _ => self.other += 1,
+        }
+    }
+}
+
+/// Counts the bases of each column of a multiple sequence alignment.
+///
+/// # Panics
+/// Panics if the rows do not all have the same length.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::msa::base_counts;
+///
+///     let msa = vec![b"ACG-T".to_vec(), b"aCGAT".to_vec(), b"ANG-T".to_vec(), b"-TG--".to_vec()];
+///     let counts = base_counts(&msa);
+///
+///     assert_eq!((counts[0].a, counts[0].depth()), (3, 3));
+///     assert_eq!((counts[1].c, counts[1].other, counts[1].t), (2, 1, 1));
+///     // the last read ends at column 2, so it is not a gap after it
+///     assert_eq!((counts[3].a, counts[3].gap), (1, 2));
+/// ```
+pub fn base_counts(msa: &[Vec<u8>]) -> Vec<BaseCounts> {
+    let width = msa.first().map_or(0, Vec::len);
+    assert!(msa.iter().all(|row| row.len() == width), "msa rows differ in length");
+
+    let mut counts = vec![BaseCounts::default(); width];
+    for row in msa {
+        if let (Some(first), Some(last)) = (row.iter().position(|&c| c != b'-'), row.iter().rposition(|&c| c != b'-')) {
+            for (column, &symbol) in counts[first..=last].iter_mut().zip(&row[first..=last]) {
+                column.add(symbol);
+            }
+        }
+    }
+    counts
+}
+
+impl PoaGraph {
+    /// Returns the base counts of each column of the multiple sequence alignment of the graph, as
+    /// computed by `base_counts`, lining up with the rows of `multiple_sequence_alignment`.
+    pub fn base_counts(&self) -> Vec<BaseCounts> {
+        base_counts(&self.multiple_sequence_alignment(false))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msa::Profile;
+    use crate::PoaParams;
+
+    #[test]
+    fn test_counts_match_the_profile() {
+        let mut graph = PoaGraph::new(&PoaParams::new(2, 5, -4, -8, -6));
+        for seq in ["AATGCCCGTT", "AATGCCGTT", "AATNCCCGAT", "TGCCCGTTAA", "", "AATGCCCGTT"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        let counts = graph.base_counts();
+        let profile = Profile::from_msa(&graph.multiple_sequence_alignment(false), None);
+        assert_eq!(counts.len(), profile.len());
+
+        for (c, column) in counts.iter().enumerate() {
+            assert_eq!(f64::from(column.depth()), profile.depth(c), "{}", c);
+            assert_eq!(f64::from(column.gap), profile.gaps[c], "{}", c);
+            for (s, &symbol) in profile.symbols.iter().enumerate().filter(|&(_, &symbol)| symbol != b'N') {
+                assert_eq!(f64::from(column.count(symbol)), profile.counts[c][s], "{} {}", c, symbol as char);
+            }
+        }
+        assert_eq!(counts.iter().map(|column| column.other).sum::<u32>(), 1);
+    }
+
+    #[test]
+    fn test_frequencies() {
+        let column = BaseCounts { a: 3, c: 1, g: 0, t: 0, other: 2, gap: 4 };
+        assert_eq!(column.frequencies(), [0.75, 0.25, 0.0, 0.0]);
+        assert_eq!(column.count(b'n'), 2);
+        assert_eq!(column.depth(), 10);
+        assert_eq!(BaseCounts::default().frequencies(), [0.0; 4]);
+        assert!(base_counts(&[]).is_empty());
+    }
+}
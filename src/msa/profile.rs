@@ -0,0 +1,275 @@
+//! Per-column symbol counts of a multiple sequence alignment, and aligning them to a reference.
+
+use super::column::alignment_columns;
+use crate::{PoaGraph, PoaParams};
+
+/// The summed weight of each symbol in each column of a multiple sequence alignment, a position
+/// specific count matrix of the reads.
+///
+/// As for `column_consensus`, only the reads spanning a column count in it. Symbols are counted
+/// ignoring case, and without weights every read counts 1.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    /// the distinct symbols of the alignment other than the gap, upper case and sorted
+    pub symbols: Vec<u8>,
+    /// the weight of each symbol of `symbols` in each column
+    pub counts: Vec<Vec<f64>>,
+    /// the weight of the gaps in each column
+    pub gaps: Vec<f64>,
+}
+
+/// The placement of a profile on a reference sequence by `Profile::align_to`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProfileAlignment {
+    /// the score of the alignment
+    pub score: f64,
+    /// the first reference position covered by the alignment
+    pub reference_start: usize,
+    /// the position after the last reference position covered by the alignment
+    pub reference_end: usize,
+    /// the reference position aligned to each column of the profile, `None` for a deleted column
+    pub mapping: Vec<Option<usize>>,
+}
+
+// where the score of a cell came from, for the traceback
+const DIAGONAL: u8 = 0;
+const INSERTION: u8 = 1;
+const DELETION: u8 = 2;
+
+impl Profile {
+    /// Counts the symbols of a multiple sequence alignment, weighting each base by `weights` if given.
+    ///
+    /// # Panics
+    /// Panics if the rows differ in length, or the weights do not match the bases of the rows.
+    pub fn from_msa(msa: &[Vec<u8>], weights: Option<&[Vec<f64>]>) -> Profile {
+        let mut symbols: Vec<u8> =
+            msa.iter().flatten().filter(|&&symbol| symbol != b'-').map(|symbol| symbol.to_ascii_uppercase()).collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        let columns = alignment_columns(msa, weights);
+        let mut counts = vec![vec![0.0; symbols.len()]; columns.len()];
+        let mut gaps = vec![0.0; columns.len()];
+        for (c, column) in columns.iter().enumerate() {
+            for &(_, symbol, weight) in &column.calls {
+                match symbols.binary_search(&symbol.to_ascii_uppercase()) {
+                    Ok(s) if symbol != b'-' => counts[c][s] += weight,
+                    _ => gaps[c] += weight,
+                }
+            }
+        }
+        Profile { symbols, counts, gaps }
+    }
+
+    /// Returns the number of columns.
+    pub fn len(&self) -> usize {
+        self.gaps.len()
+    }
+
+    /// Returns whether the profile has no columns.
+    pub fn is_empty(&self) -> bool {
+        self.gaps.is_empty()
+    }
+
+    /// Returns the summed weight of the reads spanning `column`, gaps included.
+    pub fn depth(&self, column: usize) -> f64 {
+        self.counts[column].iter().sum::<f64>() + self.gaps[column]
+    }
+
+    /// Returns the fraction of the weight of `column` held by `symbol`, ignoring case, or by gaps for `-`.
+    pub fn frequency(&self, column: usize, symbol: u8) -> f64 {
+        let depth = self.depth(column);
+        if depth == 0.0 {
+            return 0.0;
+        }
+        let weight = match self.symbols.binary_search(&symbol.to_ascii_uppercase()) {
+            _ if symbol == b'-' => self.gaps[column],
+            Ok(s) => self.counts[column][s],
+            Err(_) => 0.0,
+        };
+        weight / depth
+    }
+
+    /// Aligns the profile end to end to a part of `reference`, e.g. to place the reads of a group
+    /// on a genome.
+    ///
+    /// Aligning a reference base to a column scores the match and mismatch scores of `params`,
+    /// averaged over the symbols of the column, with its gaps scoring a gap extension; deleting a
+    /// column costs the gap scores in proportion to the reads with a base there, so columns of
+    /// insertions by few reads are cheap to skip. Unaligned reference bases at either end are free.
+    /// A minority variant thus still counts towards the score, which makes the placement more
+    /// sensitive than aligning the consensus alone. Gaps are affine if the gap open score is below
+    /// the gap extend score, and linear with the gap open score otherwise, as in the native engine.
+    /// # Arguments
+    ///
+    /// * `reference` - the reference sequence to place the profile on
+    /// * `params` - the match, mismatch and gap scores; the alignment type is not used
+    ///
+    /// # Returns
+    /// * returns the score, the aligned reference range and the mapping of the columns to reference positions
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::msa::Profile;
+    ///     use rust_spoa::PoaParams;
+    ///
+    ///     let msa = vec![b"ACGTA".to_vec(), b"ACGTA".to_vec(), b"ACCTA".to_vec()];
+    ///     let profile = Profile::from_msa(&msa, None);
+    ///
+    ///     let alignment = profile.align_to(b"TTTTACCTATTTT", &PoaParams::new(1, 5, -4, -8, -6));
+    ///     assert_eq!((alignment.reference_start, alignment.reference_end), (4, 9));
+    ///     assert_eq!(alignment.mapping[2], Some(6));
+    /// ```
+    pub fn align_to(&self, reference: &[u8], params: &PoaParams) -> ProfileAlignment {
+        let gap_open = params.gap_open as f64;
+        let gap_extend = if params.gap_open < params.gap_extend { params.gap_extend } else { params.gap_open } as f64;
+        let (match_score, mismatch_score) = (params.match_score as f64, params.mismatch_score as f64);
+
+        let column_score = |c: usize, base: u8| {
+            let (base, gap) = (self.frequency(c, base), self.frequency(c, b'-'));
+            base * match_score + (1.0 - base - gap) * mismatch_score + gap * gap_extend
+        };
+        // the fraction of the column held by bases, which deleting it costs gap scores for
+        let bases = |c: usize| if self.depth(c) == 0.0 { 0.0 } else { 1.0 - self.frequency(c, b'-') };
+
+        let (height, width) = (self.len() + 1, reference.len() + 1);
+        let mut h = vec![0.0; height * width];
+        let mut e = vec![f64::NEG_INFINITY; height * width];
+        let mut f = vec![f64::NEG_INFINITY; height * width];
+        let mut h_from = vec![DIAGONAL; height * width];
+        let mut e_from = vec![INSERTION; height * width];
+        let mut f_from = vec![DELETION; height * width];
+
+        for i in 1..height {
+            let (c, bases) = (i - 1, bases(i - 1));
+            // deleting all columns up to this one, before the first reference base
+            let (open, extend) = (h[c * width] + bases * gap_open, f[c * width] + bases * gap_extend);
+            f[i * width] = open.max(extend);
+            f_from[i * width] = if open >= extend { DIAGONAL } else { DELETION };
+            h[i * width] = f[i * width];
+            h_from[i * width] = DELETION;
+
+            for j in 1..width {
+                let cell = i * width + j;
+                let (open, extend) = (h[cell - 1] + gap_open, e[cell - 1] + gap_extend);
+                e[cell] = open.max(extend);
+                e_from[cell] = if open >= extend { DIAGONAL } else { INSERTION };
+
+                let (open, extend) = (h[cell - width] + bases * gap_open, f[cell - width] + bases * gap_extend);
+                f[cell] = open.max(extend);
+                f_from[cell] = if open >= extend { DIAGONAL } else { DELETION };
+
+                let diagonal = h[cell - width - 1] + column_score(c, reference[j - 1]);
+                h[cell] = diagonal;
+                h_from[cell] = DIAGONAL;
+                if e[cell] > h[cell] {
+                    h[cell] = e[cell];
+                    h_from[cell] = INSERTION;
+                }
+                if f[cell] > h[cell] {
+                    h[cell] = f[cell];
+                    h_from[cell] = DELETION;
+                }
+            }
+        }
+
+        // the reference bases after the alignment are free, so it may end at any column of the last row
+        let last = (height - 1) * width;
+        let mut j = (0..width).fold(0, |best, j| if h[last + j] > h[last + best] { j } else { best });
+        let (score, reference_end) = (h[last + j], j);
+
+        let mut mapping = vec![None; self.len()];
+        let (mut i, mut state) = (height - 1, DIAGONAL);
+        while i > 0 {
+            let cell = i * width + j;
+            match state {
+                DIAGONAL => match h_from[cell] {
+                    DIAGONAL => {
+                        mapping[i - 1] = Some(j - 1);
+                        i -= 1;
+                        j -= 1;
+                    }
+                    from => state = from,
+                },
+                INSERTION => {
+                    state = e_from[cell];
+                    j -= 1;
+                }
+                _ => {
+                    state = f_from[cell];
+                    i -= 1;
+                }
+            }
+        }
+
+        ProfileAlignment { score, reference_start: j, reference_end, mapping }
+    }
+}
+
+impl PoaGraph {
+    /// Returns the profile of the multiple sequence alignment of the graph, weighting each base
+    /// as it was weighted when added.
+    pub fn profile(&self) -> Profile {
+        let msa = self.multiple_sequence_alignment(false);
+        let weights: Vec<Vec<f64>> =
+            (0..self.num_sequences()).map(|i| self.sequence_weights(i).into_iter().map(f64::from).collect()).collect();
+        Profile::from_msa(&msa, Some(&weights))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[&str]) -> Vec<Vec<u8>> {
+        rows.iter().map(|row| row.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_counts() {
+        let profile = Profile::from_msa(&rows(&["ACG-T", "aCGAT", "AC--T", "-TG--"]), None);
+
+        assert_eq!(profile.symbols, b"ACGT".to_vec());
+        assert_eq!(profile.len(), 5);
+        assert_eq!(profile.counts[0], vec![3.0, 0.0, 0.0, 0.0]);
+        // the last read ends at column 2 and does not count as a gap after it
+        assert_eq!((profile.gaps[3], profile.depth(3)), (2.0, 3.0));
+        assert_eq!(profile.frequency(1, b'c'), 0.75);
+        assert_eq!(profile.frequency(3, b'-'), 2.0 / 3.0);
+        assert_eq!(profile.frequency(0, b'N'), 0.0);
+    }
+
+    #[test]
+    fn test_minority_variant_counts_towards_the_score() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let profile = Profile::from_msa(&rows(&["ACGTACGT", "ACGTACGT", "ACGAACGT"]), None);
+
+        let reference = b"GGGGGGACGAACGTGGGGGG";
+        let alignment = profile.align_to(reference, &params);
+        assert_eq!((alignment.reference_start, alignment.reference_end), (6, 14));
+        assert_eq!(alignment.mapping, (6..14).map(Some).collect::<Vec<_>>());
+        // seven columns match every read, and the fourth matches one read in three
+        assert!((alignment.score - (7.0 * 5.0 + 5.0 / 3.0 - 8.0 / 3.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_skipped_columns_and_graph_profile() {
+        let params = PoaParams::new(1, 5, -4, -8, -8);
+        let mut graph = PoaGraph::new(&params);
+        for seq in ["AATGCCCGTT", "AATGCCCGTT", "AATGCCCGTT", "AATGCTTTCCCGTT"].iter() {
+            graph.add_sequence(seq.as_bytes());
+        }
+        let profile = graph.profile();
+        assert_eq!(profile.len(), 14);
+
+        // the columns of the insertion carried by one read in four are deleted at a quarter of the cost
+        let alignment = profile.align_to(b"CCAATGCCCGTTCC", &params);
+        assert_eq!((alignment.reference_start, alignment.reference_end), (2, 12));
+        assert_eq!(alignment.mapping.iter().filter(|position| position.is_none()).count(), 4);
+        assert!((alignment.score - (10.0 * 5.0 - 4.0 * 2.0)).abs() < 1e-9);
+
+        let empty = Profile::default().align_to(b"ACGT", &params);
+        assert_eq!((empty.score, empty.mapping.len()), (0.0, 0));
+    }
+}
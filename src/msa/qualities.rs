@@ -0,0 +1,163 @@
+//! Carrying the base qualities of the reads into the columns of a multiple sequence alignment.
+
+use crate::{PoaGraph, PoaParams, SeqRecord};
+
+/// The quality of a gap in the quality rows of a `QualityMsa`. No Phred+33 quality is 0, so a gap
+/// cannot be mistaken for a base.
+pub const GAP_QUALITY: u8 = 0;
+
+/// A multiple sequence alignment with the qualities of its bases, aligned to the same columns.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::msa::{QualityMsa, GAP_QUALITY};
+///
+///     let msa = vec![b"AC-GT".to_vec(), b"ACTGT".to_vec()];
+///     let aligned = QualityMsa::from_msa(msa, &[Some(&b"II5I"[..]), None]);
+///
+///     assert_eq!(aligned.quals[0], Some(vec![b'I', b'I', GAP_QUALITY, b'5', b'I']));
+///     assert_eq!(aligned.quals[1], None);
+///     assert_eq!(aligned.column_qualities(3), vec![Some(b'5'), None]);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QualityMsa {
+    /// the gapped rows, with `-` for gaps, as `PoaGraph::multiple_sequence_alignment` returns them
+    pub rows: Vec<Vec<u8>>,
+    /// the Phred+33 quality of each column of each row, `GAP_QUALITY` at its gaps; `None` for the
+    /// rows without qualities, such as the consensus
+    pub quals: Vec<Option<Vec<u8>>>,
+}
+
+impl QualityMsa {
+    /// Aligns the quality string of each row to its bases, the `i`th quality to the `i`th base
+    /// that is not a gap. Rows beyond the end of `quals`, e.g. a consensus row, get no qualities.
+    ///
+    /// # Panics
+    /// Panics if `quals` holds more strings than there are rows, or a quality string does not
+    /// have the length of its row without gaps.
+    pub fn from_msa(rows: Vec<Vec<u8>>, quals: &[Option<&[u8]>]) -> QualityMsa {
+        assert!(quals.len() <= rows.len(), "more quality strings than msa rows");
+        let quals = rows
+            .iter()
+            .enumerate()
+            .map(|(r, row)| {
+                let qual = (*quals.get(r)?)?;
+                let mut bases = qual.iter();
+                let aligned: Vec<u8> = row.iter().map(|&c| if c == b'-' { GAP_QUALITY } else { *bases.next().unwrap_or(&GAP_QUALITY) }).collect();
+                let len = row.iter().filter(|&&c| c != b'-').count();
+                assert_eq!(qual.len(), len, "quality string length differs from the bases of msa row {}", r);
+                Some(aligned)
+            })
+            .collect();
+        QualityMsa { rows, quals }
+    }
+
+    /// Returns the quality of each row in a column, `None` for a gap or a row without qualities.
+    ///
+    /// # Panics
+    /// Panics if `column` is not below the length of the rows.
+    pub fn column_qualities(&self, column: usize) -> Vec<Option<u8>> {
+        self.quals
+            .iter()
+            .map(|qual| match qual {
+                Some(qual) if qual[column] != GAP_QUALITY => Some(qual[column]),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl PoaGraph {
+    /// Generates the multiple sequence alignment of the sequences in the graph as
+    /// `multiple_sequence_alignment` does, with the given quality string of each sequence.
+    ///
+    /// # Panics
+    /// Panics if `quals` holds more strings than the graph sequences, or a quality string does not
+    /// have the length of its sequence.
+    pub fn multiple_sequence_alignment_with_qualities(&self, quals: &[Option<&[u8]>], include_consensus: bool) -> QualityMsa {
+        assert!(quals.len() <= self.num_sequences(), "more quality strings than sequences in the graph");
+        QualityMsa::from_msa(self.multiple_sequence_alignment(include_consensus), quals)
+    }
+}
+
+/// Aligns a set of records to each other through their partial order graph, keeping the quality
+/// of each base in its column.
+///
+/// Bases are weighted by quality and read weight, as by `poa_consensus_of_records`.
+/// # Arguments
+///
+/// * `records` - the reads to align; empty reads are left out
+/// * `params` - the alignment and scoring parameters
+/// * `include_consensus` - whether to add the consensus as a last row, without qualities
+///
+/// # Returns
+/// * returns one gapped row per non-empty record, in input order, and its qualities if the
+///   record has them
+///
+/// # Panics
+/// Panics if the quality string of a record does not have the length of its sequence.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::msa::{poa_msa_with_qualities, GAP_QUALITY};
+///     use rust_spoa::{PoaParams, SeqRecord};
+///
+///     let records = vec![
+///         SeqRecord::new("r1", b"AATGCCCGTT".to_vec()).qual(b"IIIIIIIIII".to_vec()),
+///         SeqRecord::new("r2", b"AATGCCGTT".to_vec()).qual(b"555555555".to_vec()),
+///     ];
+///     let aligned = poa_msa_with_qualities(&records, &PoaParams::new(1, 5, -4, -3, -1), true);
+///
+///     assert_eq!(aligned.rows.len(), 3);
+///     let qual = aligned.quals[1].as_ref().unwrap();
+///     assert_eq!(qual.iter().filter(|&&q| q == GAP_QUALITY).count(), 1);
+///     assert_eq!(aligned.quals[2], None);
+/// ```
+pub fn poa_msa_with_qualities(records: &[SeqRecord], params: &PoaParams, include_consensus: bool) -> QualityMsa {
+    let mut graph = PoaGraph::new(params);
+    let records: Vec<&SeqRecord> = records.iter().filter(|record| !record.seq.is_empty()).collect();
+    for record in &records {
+        graph.add_record(record);
+    }
+    let quals: Vec<Option<&[u8]>> = records.iter().map(|record| record.qual.as_deref()).collect();
+    graph.multiple_sequence_alignment_with_qualities(&quals, include_consensus)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qualities_follow_bases() {
+        let records = vec![
+            SeqRecord::new("r1", b"AATGCCCGTT".to_vec()).qual(b"ABCDEFGHIJ".to_vec()),
+            SeqRecord::new("empty", vec![]),
+            SeqRecord::new("r2", b"AATGCCGTT".to_vec()),
+            SeqRecord::new("r3", b"AATGCCCGTTAA".to_vec()).qual(b"!!!!!!!!!!#$".to_vec()),
+        ];
+        let aligned = poa_msa_with_qualities(&records, &PoaParams::new(1, 5, -4, -3, -1), false);
+        assert_eq!(aligned.rows.len(), 3);
+        assert_eq!(aligned.quals.len(), 3);
+        assert_eq!(aligned.quals[1], None);
+
+        // every quality lines up with its base, in order, and gaps get the sentinel
+        for (row, qual) in aligned.rows.iter().zip(&aligned.quals) {
+            if let Some(qual) = qual {
+                assert_eq!(qual.len(), row.len());
+                assert!(row.iter().zip(qual).all(|(&c, &q)| (c == b'-') == (q == GAP_QUALITY)));
+            }
+        }
+        let ungapped: Vec<u8> = aligned.quals[0].as_ref().unwrap().iter().cloned().filter(|&q| q != GAP_QUALITY).collect();
+        assert_eq!(ungapped, b"ABCDEFGHIJ".to_vec());
+        let last = aligned.rows[2].iter().rposition(|&c| c != b'-').unwrap();
+        assert_eq!(aligned.column_qualities(last), vec![None, None, Some(b'$')]);
+    }
+
+    #[test]
+    #[should_panic(expected = "quality string length differs from the bases of msa row 0")]
+    fn test_short_qualities() {
+        QualityMsa::from_msa(vec![b"AC-GT".to_vec()], &[Some(&b"IIIII"[..])]);
+    }
+}
@@ -0,0 +1,268 @@
+//! Alternative alleles at each consensus position and their read support, turning a bundle of
+//! amplicon reads into variant calls without exporting and parsing the alignment.
+
+use std::cmp::Reverse;
+use std::fmt;
+
+use crate::{PoaGraph, PoaParams};
+
+/// What a read has at a consensus position.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Allele {
+    /// a base, in upper case
+    Base(u8),
+    /// a gap, the read skipping the consensus base
+    Deletion,
+}
+
+impl fmt::Display for Allele {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Allele::Base(base) => write!(f, "{}", base as char),
+            Allele::Deletion => f.write_str("del"),
+        }
+    }
+}
+
+/// The alleles the reads spanning one consensus position have there and the bases they insert
+/// after it, as returned by `PoaGraph::variants`.
+///
+/// Only reads with bases both at or before and at or after a column span it, as for `IndelStats`,
+/// so reads ending early are not counted as deletions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VariantSite {
+    /// the 0-based position in the consensus
+    pub position: usize,
+    /// the consensus base, in upper case
+    pub consensus: u8,
+    /// the number of reads spanning the position
+    pub depth: u32,
+    /// the number of spanning reads with each allele, the consensus base included, most supported first
+    pub alleles: Vec<(Allele, u32)>,
+    /// the number of reads with each sequence inserted between the position and the next, most
+    /// supported first, counting only reads spanning both
+    pub insertions: Vec<(Vec<u8>, u32)>,
+}
+
+impl VariantSite {
+    /// Returns the number of spanning reads with the consensus base.
+    pub fn consensus_support(&self) -> u32 {
+        self.support(Allele::Base(self.consensus))
+    }
+
+    /// Returns the number of spanning reads with `allele`.
+    pub fn support(&self, allele: Allele) -> u32 {
+        self.alleles.iter().find(|&&(a, _)| a == allele).map_or(0, |&(_, count)| count)
+    }
+
+    /// Returns the alleles other than the consensus base, most supported first.
+    pub fn alternatives(&self) -> impl Iterator<Item = (Allele, u32)> + '_ {
+        let consensus = Allele::Base(self.consensus);
+        self.alleles.iter().copied().filter(move |&(allele, _)| allele != consensus)
+    }
+
+    /// Returns the fraction of the spanning reads with `allele`, 0 for a position no read spans.
+    pub fn fraction(&self, allele: Allele) -> f64 {
+        if self.depth == 0 {
+            return 0.0;
+        }
+        f64::from(self.support(allele)) / f64::from(self.depth)
+    }
+}
+
+impl fmt::Display for VariantSite {
+    /// Formats the site as its position, the alleles with their support and the insertions, e.g.
+    /// `153 A:42 G:17 del:2 +GT:3`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.position)?;
+        for (allele, count) in &self.alleles {
+            write!(f, " {}:{}", allele, count)?;
+        }
+        for (bases, count) in &self.insertions {
+            write!(f, " +{}:{}", String::from_utf8_lossy(bases), count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reports the consensus positions where reads disagree with the consensus, from a multiple
+/// sequence alignment whose last row is the consensus, as returned by
+/// `PoaGraph::multiple_sequence_alignment(true)`.
+///
+/// A position is reported if an allele other than the consensus base, or a sequence inserted after
+/// it, is carried by at least `min_support` reads. Bases are compared ignoring case.
+///
+/// # Panics
+/// Panics if `min_support` is 0 or the rows do not all have the same length.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::msa::{variant_sites, Allele};
+///
+///     let msa = vec![b"ACGT".to_vec(), b"ACAT".to_vec(), b"A-AT".to_vec(), b"ACGT".to_vec()];
+///     let sites = variant_sites(&msa, 1);
+///
+///     assert_eq!(sites.len(), 2);
+///     assert_eq!((sites[0].position, sites[0].support(Allele::Deletion)), (1, 1));
+///     assert_eq!(sites[1].to_string(), "2 A:2 G:1");
+/// ```
+pub fn variant_sites(msa: &[Vec<u8>], min_support: u32) -> Vec<VariantSite> {
+    assert!(min_support > 0, "the minimum support must be positive");
+    let (consensus, reads) = match msa.split_last() {
+        Some(rows) => rows,
+        None => return vec![],
+    };
+    assert!(reads.iter().all(|row| row.len() == consensus.len()), "msa rows differ in length");
+
+    let columns: Vec<usize> = (0..consensus.len()).filter(|&c| consensus[c] != b'-').collect();
+    let mut alleles = vec![vec![]; columns.len()];
+    let mut insertions = vec![vec![]; columns.len()];
+    for row in reads {
+        let first = match row.iter().position(|&c| c != b'-') {
+            Some(first) => first,
+            None => continue,
+        };
+        let last = row.iter().rposition(|&c| c != b'-').unwrap();
+
+        for (i, &column) in columns.iter().enumerate() {
+            if column < first || column > last {
+                continue;
+            }
+            let allele = match row[column] {
+                b'-' => Allele::Deletion,
+                base => Allele::Base(base.to_ascii_uppercase()),
+            };
+            alleles[i].push(allele);
+            if let Some(&next) = columns.get(i + 1) {
+                let inserted: Vec<u8> = row[column + 1..next].iter().filter(|&&c| c != b'-').map(u8::to_ascii_uppercase).collect();
+                if next <= last && !inserted.is_empty() {
+                    insertions[i].push(inserted);
+                }
+            }
+        }
+    }
+
+    let mut sites = vec![];
+    for (i, (alleles, insertions)) in alleles.into_iter().zip(insertions).enumerate() {
+        let site = VariantSite {
+            position: i,
+            consensus: consensus[columns[i]].to_ascii_uppercase(),
+            depth: alleles.len() as u32,
+            alleles: tally(alleles),
+            insertions: tally(insertions),
+        };
+        if site.alternatives().any(|(_, count)| count >= min_support) || site.insertions.iter().any(|&(_, count)| count >= min_support) {
+            sites.push(site);
+        }
+    }
+    sites
+}
+
+// the number of times each value occurs, most frequent first and ties in order of value
+fn tally<T: Ord>(mut values: Vec<T>) -> Vec<(T, u32)> {
+    values.sort();
+    let mut counts: Vec<(T, u32)> = vec![];
+    for value in values {
+        match counts.last_mut() {
+            Some((last, count)) if *last == value => *count += 1,
+            _ => counts.push((value, 1)),
+        }
+    }
+    counts.sort_by_key(|&(_, count)| Reverse(count));
+    counts
+}
+
+impl PoaGraph {
+    /// Reports the consensus positions where at least `min_support` reads have another allele or
+    /// an insertion, as computed by `variant_sites` from the multiple sequence alignment.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::msa::Allele;
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+    ///     for seq in ["AATGCCCGTT", "AATGCCCGTT", "AATGCCCGTT", "AATGACCGTT", "AATGACCGTT"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     let sites = graph.variants(2);
+    ///     assert_eq!(sites.len(), 1);
+    ///     assert_eq!((sites[0].position, sites[0].consensus, sites[0].depth), (4, b'C', 5));
+    ///     assert_eq!(sites[0].alternatives().collect::<Vec<_>>(), vec![(Allele::Base(b'A'), 2)]);
+    /// ```
+    pub fn variants(&self, min_support: u32) -> Vec<VariantSite> {
+        variant_sites(&self.multiple_sequence_alignment(true), min_support)
+    }
+}
+
+/// Generates a consensus sequence together with the positions where reads disagree with it.
+///
+/// Trailing null terminators are ignored and empty reads are left out.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `params` - the alignment and scoring parameters
+/// * `min_support` - the number of reads an alternative allele or insertion needs to be reported
+///
+/// # Returns
+/// * returns the consensus and the sites of `PoaGraph::variants`, in consensus order
+///
+/// # Panics
+/// Panics if `min_support` is 0.
+pub fn poa_consensus_with_variants<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams, min_support: u32) -> (Vec<u8>, Vec<VariantSite>) {
+    let mut graph = PoaGraph::new(params);
+    for seq in seqs {
+        let seq = seq.as_ref();
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        if !seq.is_empty() {
+            graph.add_sequence(seq);
+        }
+    }
+    (graph.consensus(), graph.variants(min_support))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amplicon_bundle() {
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let mut reads = vec!["GATTACAGGCTTACCGATCG\0"; 6];
+        reads.extend(vec!["GATTACAGGCATACCGATCG"; 3]);
+        reads.extend(vec!["GATTACAGGCTTACCGAGTTCG"; 2]);
+        reads.push("GATTACAGGCTACCGATCG");
+        reads.push("");
+        let (consensus, sites) = poa_consensus_with_variants(&reads, &params, 2);
+        assert_eq!(consensus, b"GATTACAGGCTTACCGATCG".to_vec());
+
+        assert_eq!(sites.len(), 2);
+        assert_eq!((sites[0].position, sites[0].depth, sites[0].consensus_support()), (10, 12, 8));
+        assert_eq!(sites[0].support(Allele::Base(b'A')), 3);
+        assert!((sites[0].fraction(Allele::Base(b'A')) - 0.25).abs() < 1e-9);
+        assert_eq!(sites[1].position, 16);
+        assert_eq!(sites[1].insertions, vec![(b"GT".to_vec(), 2)]);
+        assert!(sites[1].alternatives().next().is_none());
+
+        // the single read with a deletion is reported only without a minimum
+        let (_, sites) = poa_consensus_with_variants(&reads, &params, 1);
+        assert_eq!(sites.len(), 2);
+        assert_eq!(sites[0].alleles, vec![(Allele::Base(b'T'), 8), (Allele::Base(b'A'), 3), (Allele::Deletion, 1)]);
+        assert_eq!(sites[0].to_string(), "10 T:8 A:3 del:1");
+    }
+
+    #[test]
+    fn test_spanning_reads() {
+        // the third read ends early and the consensus is lower case
+        let msa = vec![b"ACGTA".to_vec(), b"ACTTA".to_vec(), b"AC---".to_vec(), b"acgta".to_vec()];
+        let sites = variant_sites(&msa, 1);
+        assert_eq!(sites.len(), 1);
+        assert_eq!((sites[0].position, sites[0].consensus, sites[0].depth), (2, b'G', 2));
+        assert_eq!(sites[0].to_string(), "2 G:1 T:1");
+        assert!(variant_sites(&[], 1).is_empty());
+        assert_eq!(VariantSite::default().fraction(Allele::Deletion), 0.0);
+    }
+}
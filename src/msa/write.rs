@@ -0,0 +1,178 @@
+//! Writing multiple sequence alignments in the formats of alignment viewers such as Jalview and
+//! AliView.
+
+use std::io::{self, Write};
+
+// the number of columns of each block of a CLUSTAL alignment
+const CLUSTAL_BLOCK: usize = 60;
+
+// checks that there is one name per row and the rows have the same length
+fn check_rows<S: AsRef<str>>(names: &[S], msa: &[Vec<u8>]) {
+    assert_eq!(names.len(), msa.len(), "one name is needed per msa row");
+    let width = msa.first().map_or(0, Vec::len);
+    assert!(msa.iter().all(|row| row.len() == width), "msa rows differ in length");
+}
+
+// the names with whitespace replaced by underscores, as the formats separate the name of a row
+// from its sequence by whitespace
+fn row_names<S: AsRef<str>>(names: &[S]) -> Vec<String> {
+    names.iter().map(|name| name.as_ref().chars().map(|c| if c.is_whitespace() { '_' } else { c }).collect()).collect()
+}
+
+/// Writes a multiple sequence alignment as aligned FASTA, each row as one record on a single line
+/// with gaps as `-`.
+///
+/// # Panics
+/// Panics if `names` does not hold one name per row, or the rows do not all have the same length.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::msa::write_fasta_msa;
+///
+///     let msa = vec![b"ACG-T".to_vec(), b"ACGAT".to_vec()];
+///     let mut out = vec![];
+///     write_fasta_msa(&mut out, &["read 1", "read 2"], &msa).unwrap();
+///     assert_eq!(String::from_utf8(out).unwrap(), ">read 1\nACG-T\n>read 2\nACGAT\n");
+/// ```
+pub fn write_fasta_msa<W: Write, S: AsRef<str>>(mut writer: W, names: &[S], msa: &[Vec<u8>]) -> io::Result<()> {
+    check_rows(names, msa);
+    for (name, row) in names.iter().zip(msa) {
+        writeln!(writer, ">{}", name.as_ref())?;
+        writer.write_all(row)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Writes a multiple sequence alignment in the CLUSTAL format, in blocks of 60 columns, each
+/// followed by a line marking the columns where every row holds the same base with `*`.
+///
+/// Whitespace in names is written as `_`.
+///
+/// # Panics
+/// Panics if `names` does not hold one name per row, or the rows do not all have the same length.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::msa::write_clustal;
+///
+///     let msa = vec![b"ACG-T".to_vec(), b"ACGAT".to_vec()];
+///     let mut out = vec![];
+///     write_clustal(&mut out, &["r1", "read_2"], &msa).unwrap();
+///     assert_eq!(
+///         String::from_utf8(out).unwrap(),
+///         "CLUSTAL W multiple sequence alignment\n\nr1        ACG-T\nread_2    ACGAT\n          *** *\n"
+///     );
+/// ```
+pub fn write_clustal<W: Write, S: AsRef<str>>(mut writer: W, names: &[S], msa: &[Vec<u8>]) -> io::Result<()> {
+    check_rows(names, msa);
+    let names = row_names(names);
+    let name_width = names.iter().map(String::len).max().unwrap_or(0) + 4;
+    let width = msa.first().map_or(0, Vec::len);
+
+    writeln!(writer, "CLUSTAL W multiple sequence alignment")?;
+    for start in (0..width).step_by(CLUSTAL_BLOCK) {
+        let end = (start + CLUSTAL_BLOCK).min(width);
+        writeln!(writer)?;
+        for (name, row) in names.iter().zip(msa) {
+            write!(writer, "{:<width$}", name, width = name_width)?;
+            writer.write_all(&row[start..end])?;
+            writer.write_all(b"\n")?;
+        }
+        let conserved: String = (start..end)
+            .map(|c| {
+                let base = msa[0][c].to_ascii_uppercase();
+                if base != b'-' && msa.iter().all(|row| row[c].to_ascii_uppercase() == base) {
+                    '*'
+                } else {
+                    ' '
+                }
+            })
+            .collect();
+        writeln!(writer, "{:<width$}{}", "", conserved.trim_end(), width = name_width)?;
+    }
+    Ok(())
+}
+
+/// Writes a multiple sequence alignment in the Stockholm format, as a single block of one line per
+/// row.
+///
+/// Whitespace in names is written as `_`.
+///
+/// # Panics
+/// Panics if `names` does not hold one name per row, or the rows do not all have the same length.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::msa::write_stockholm;
+///
+///     let msa = vec![b"ACG-T".to_vec(), b"ACGAT".to_vec()];
+///     let mut out = vec![];
+///     write_stockholm(&mut out, &["r1", "read 2"], &msa).unwrap();
+///     assert_eq!(String::from_utf8(out).unwrap(), "# STOCKHOLM 1.0\n\nr1     ACG-T\nread_2 ACGAT\n//\n");
+/// ```
+pub fn write_stockholm<W: Write, S: AsRef<str>>(mut writer: W, names: &[S], msa: &[Vec<u8>]) -> io::Result<()> {
+    check_rows(names, msa);
+    let names = row_names(names);
+    let name_width = names.iter().map(String::len).max().unwrap_or(0) + 1;
+
+    writeln!(writer, "# STOCKHOLM 1.0")?;
+    writeln!(writer)?;
+    for (name, row) in names.iter().zip(msa) {
+        write!(writer, "{:<width$}", name, width = name_width)?;
+        writer.write_all(row)?;
+        writer.write_all(b"\n")?;
+    }
+    writeln!(writer, "//")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PoaGraph, PoaParams};
+
+    #[test]
+    fn test_write_graph_msa() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+        let read = "GATTACAGGCTTACCGATCGATTGCAGCTAGGCATCAAGTGATTACAGGCTTACCGATCGATTGCAGCTAGG";
+        graph.add_sequence(read.as_bytes());
+        graph.add_sequence(read.replacen("GCAGC", "GCGC", 1).as_bytes());
+        let msa = graph.multiple_sequence_alignment(true);
+        let names = ["read1", "read2", "consensus"];
+
+        let mut clustal = vec![];
+        write_clustal(&mut clustal, &names, &msa).unwrap();
+        let clustal = String::from_utf8(clustal).unwrap();
+        let lines: Vec<&str> = clustal.lines().collect();
+        // two blocks of three rows and a conservation line, each after a blank line
+        assert_eq!(lines.len(), 1 + 2 * 5);
+        assert_eq!(lines[2], format!("read1        {}", &read[..60]));
+        assert_eq!(lines[7], format!("read1        {}", &read[60..]));
+        assert_eq!(lines[5].len(), 13 + 60);
+        assert_eq!(lines[5].matches(' ').count(), 13 + 1);
+
+        let mut fasta = vec![];
+        write_fasta_msa(&mut fasta, &names, &msa).unwrap();
+        assert_eq!(String::from_utf8(fasta).unwrap().lines().nth(5).unwrap().as_bytes(), &msa[2][..]);
+
+        let mut stockholm = vec![];
+        write_stockholm(&mut stockholm, &names, &msa).unwrap();
+        let stockholm = String::from_utf8(stockholm).unwrap();
+        assert!(stockholm.starts_with("# STOCKHOLM 1.0\n\nread1     GATTACA"));
+        assert!(stockholm.ends_with("\n//\n"));
+
+        // an empty alignment has no blocks
+        let mut empty = vec![];
+        write_clustal(&mut empty, &[] as &[&str], &[]).unwrap();
+        assert_eq!(empty, b"CLUSTAL W multiple sequence alignment\n".to_vec());
+    }
+
+    #[test]
+    #[should_panic(expected = "one name is needed per msa row")]
+    fn test_names_for_each_row() {
+        write_stockholm(vec![], &["r1"], &[b"AC".to_vec(), b"AC".to_vec()]).unwrap();
+    }
+}
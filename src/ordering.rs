@@ -0,0 +1,215 @@
+//! Choosing the order in which reads are added, which the graph and its consensus depend on.
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::filter::mean_quality;
+use crate::{PoaError, PoaGraph, PoaParams, SeqRecord};
+
+/// The order in which reads are added to a graph.
+///
+/// Each read is aligned to the graph of the reads before it, so a short or error-rich read added
+/// early misleads the alignment of every later one. Starting from long, accurate reads, or adding
+/// the read closest to the graph so far, makes the consensus less dependent on the input order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ReadOrder {
+    /// the reads are added in input order
+    #[default]
+    AsGiven,
+    /// the longest reads are added first, reads of equal length in input order
+    LongestFirst,
+    /// the reads of the highest mean quality (the Phred quality of their mean error probability)
+    /// are added first, reads without qualities last, and reads of equal quality in input order
+    HighestQualityFirst,
+    /// the longest read is added first, then again and again the read aligning best to the graph
+    /// so far, by alignment score per base, the first among equals; this aligns every remaining
+    /// read for each one added, so takes quadratic time in the number of reads
+    MostSimilar,
+}
+
+impl ReadOrder {
+    /// Returns a short lower-case name for the order, as `str::parse` reads it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ReadOrder::AsGiven => "as-given",
+            ReadOrder::LongestFirst => "longest-first",
+            ReadOrder::HighestQualityFirst => "quality-first",
+            ReadOrder::MostSimilar => "most-similar",
+        }
+    }
+}
+
+impl fmt::Display for ReadOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ReadOrder {
+    type Err = PoaError;
+
+    fn from_str(s: &str) -> Result<ReadOrder, PoaError> {
+        match s {
+            "as-given" => Ok(ReadOrder::AsGiven),
+            "longest-first" => Ok(ReadOrder::LongestFirst),
+            "quality-first" => Ok(ReadOrder::HighestQualityFirst),
+            "most-similar" => Ok(ReadOrder::MostSimilar),
+            _ => Err(PoaError::InvalidPreset(format!("unknown read order {}", s))),
+        }
+    }
+}
+
+/// A consensus of reads added in a chosen order, with that order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderedConsensus {
+    /// the consensus
+    pub consensus: Vec<u8>,
+    /// the input index of each read, in the order they were added; adding the reads again in this
+    /// order with `ReadOrder::AsGiven` gives the same graph
+    pub order: Vec<usize>,
+}
+
+impl PoaGraph {
+    /// Aligns the records to the graph and adds them in the given order, as `add_record` does.
+    ///
+    /// Empty records are left out. With `ReadOrder::MostSimilar`, the first record is the longest
+    /// only if the graph is empty; records are compared by alignment score as `alignment_score`
+    /// gives it.
+    ///
+    /// # Returns
+    /// * returns the input index of each record added, in the order they were added
+    ///
+    /// # Panics
+    /// Panics if the quality string of a record does not have the length of its sequence.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams, ReadOrder, SeqRecord};
+    ///
+    ///     let records: Vec<SeqRecord> = vec!["AATG", "AATGCCCGTT", "", "AATGCCG"].into_iter().map(|seq| seq.as_bytes().into()).collect();
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///
+    ///     assert_eq!(graph.add_records_ordered(&records, ReadOrder::LongestFirst), vec![1, 3, 0]);
+    ///     assert_eq!(graph.num_sequences(), 3);
+    /// ```
+    pub fn add_records_ordered(&mut self, records: &[SeqRecord], ordering: ReadOrder) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..records.len()).filter(|&i| !records[i].seq.is_empty()).collect();
+        match ordering {
+            ReadOrder::AsGiven => {}
+            ReadOrder::LongestFirst => order.sort_by_key(|&i| Reverse(records[i].seq.len())),
+            ReadOrder::HighestQualityFirst => {
+                let quality = |i: usize| records[i].qual.as_deref().map_or(f64::NEG_INFINITY, mean_quality);
+                order.sort_by(|&a, &b| quality(b).total_cmp(&quality(a)));
+            }
+            ReadOrder::MostSimilar => return self.add_most_similar(records, order),
+        }
+        for &i in &order {
+            self.add_record(&records[i]);
+        }
+        order
+    }
+
+    // adds the records at the remaining indices greedily, each time the one aligning best to the
+    // graph per base
+    fn add_most_similar(&mut self, records: &[SeqRecord], mut remaining: Vec<usize>) -> Vec<usize> {
+        let mut order = Vec::with_capacity(remaining.len());
+        while !remaining.is_empty() {
+            let next = if self.num_sequences() == 0 {
+                // the longest record, the first among equals
+                (0..remaining.len()).min_by_key(|&k| Reverse(records[remaining[k]].seq.len())).unwrap()
+            } else {
+                let similarity = |k: usize| {
+                    let seq = &records[remaining[k]].seq;
+                    f64::from(self.alignment_score(seq)) / seq.len() as f64
+                };
+                let similarities: Vec<f64> = (0..remaining.len()).map(similarity).collect();
+                (0..remaining.len()).fold(0, |best, k| if similarities[k] > similarities[best] { k } else { best })
+            };
+            let i = remaining.remove(next);
+            self.add_record(&records[i]);
+            order.push(i);
+        }
+        order
+    }
+}
+
+/// Generates the consensus of a set of records, adding them in the given order.
+///
+/// Bases are weighted by quality and read weight, as by `poa_consensus_of_records`.
+/// # Arguments
+///
+/// * `records` - the reads to form a consensus from; empty reads are left out
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `ordering` - the order in which the reads are added
+///
+/// # Returns
+/// * returns the consensus, and the input index of each read in the order they were added
+///
+/// # Panics
+/// Panics if the quality string of a record does not have the length of its sequence.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_ordered, PoaParams, ReadOrder, SeqRecord};
+///
+///     let records = vec![
+///         SeqRecord::new("short", b"AATGCC".to_vec()),
+///         SeqRecord::new("r1", b"AATGCCCGTT".to_vec()),
+///         SeqRecord::new("r2", b"AATGCCGTT".to_vec()),
+///         SeqRecord::new("r3", b"AATGCCCGTT".to_vec()),
+///     ];
+///     let ordered = poa_consensus_ordered(&records, 0, &PoaParams::new(1, 5, -4, -3, -1), ReadOrder::MostSimilar);
+///
+///     assert_eq!(ordered.consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(ordered.order, vec![1, 3, 2, 0]);
+/// ```
+pub fn poa_consensus_ordered(records: &[SeqRecord], consensus_max_length: usize, params: &PoaParams, ordering: ReadOrder) -> OrderedConsensus {
+    let mut graph = PoaGraph::new(params);
+    let order = graph.add_records_ordered(records, ordering);
+
+    let mut consensus = graph.consensus();
+    if consensus_max_length > 0 {
+        consensus.truncate(consensus_max_length);
+    }
+    OrderedConsensus { consensus, order }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_orders() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let records = vec![
+            SeqRecord::new("r0", b"AATGCCCGTT".to_vec()).qual(b"5555555555".to_vec()),
+            SeqRecord::new("r1", b"AATGCCCGTTAA".to_vec()),
+            SeqRecord::new("r2", b"AATGCCGTT".to_vec()).qual(b"IIIIIIIII".to_vec()),
+            SeqRecord::new("r3", vec![]),
+            SeqRecord::new("r4", b"AATGCCCGAT".to_vec()).qual(b"5555555555".to_vec()),
+        ];
+        let order = |ordering| poa_consensus_ordered(&records, 0, &params, ordering).order;
+        assert_eq!(order(ReadOrder::AsGiven), vec![0, 1, 2, 4]);
+        assert_eq!(order(ReadOrder::LongestFirst), vec![1, 0, 4, 2]);
+        assert_eq!(order(ReadOrder::HighestQualityFirst), vec![2, 0, 4, 1]);
+        assert_eq!(order(ReadOrder::MostSimilar)[0], 1);
+
+        // replaying the order as given gives the same consensus
+        for &ordering in &[ReadOrder::LongestFirst, ReadOrder::HighestQualityFirst, ReadOrder::MostSimilar] {
+            let ordered = poa_consensus_ordered(&records, 0, &params, ordering);
+            let replayed: Vec<SeqRecord> = ordered.order.iter().map(|&i| records[i].clone()).collect();
+            assert_eq!(poa_consensus_ordered(&replayed, 0, &params, ReadOrder::AsGiven).consensus, ordered.consensus);
+        }
+        assert_eq!(poa_consensus_ordered(&records, 4, &params, ReadOrder::AsGiven).consensus.len(), 4);
+        assert_eq!(poa_consensus_ordered(&records[3..4], 0, &params, ReadOrder::MostSimilar).order, Vec::<usize>::new());
+
+        for &ordering in &[ReadOrder::AsGiven, ReadOrder::LongestFirst, ReadOrder::HighestQualityFirst, ReadOrder::MostSimilar] {
+            assert_eq!(ordering.to_string().parse::<ReadOrder>(), Ok(ordering));
+        }
+        assert!("random".parse::<ReadOrder>().is_err());
+    }
+}
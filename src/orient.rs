@@ -0,0 +1,182 @@
+//! Orienting reads of mixed strands before adding them to a graph.
+
+use std::fmt;
+
+use crate::seq::reverse_complement;
+use crate::{PoaGraph, PoaParams};
+
+/// The orientation in which a read was added to a graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Strand {
+    /// the read was added as given
+    Forward,
+    /// the reverse complement of the read was added
+    Reverse,
+}
+
+impl Strand {
+    /// Returns the strand as `+` or `-`, as in SAM and PAF.
+    pub fn as_char(&self) -> char {
+        match self {
+            Strand::Forward => '+',
+            Strand::Reverse => '-',
+        }
+    }
+}
+
+impl fmt::Display for Strand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_char())
+    }
+}
+
+/// A consensus of reads of mixed strands, with the orientation each read was added in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrientedConsensus {
+    /// the consensus, along the strand of the first non-empty read
+    pub consensus: Vec<u8>,
+    /// the orientation of each read, in input order; empty reads are forward
+    pub strands: Vec<Strand>,
+}
+
+impl PoaGraph {
+    /// Aligns a read and its reverse complement to the graph and adds the one aligning with the
+    /// higher score, the read itself on a tie.
+    ///
+    /// The first read added to an empty graph is added as given, and sets the strand of the graph.
+    ///
+    /// # Returns
+    /// * returns the orientation in which the read was added
+    ///
+    /// # Panics
+    /// Panics if the graph aligns protein reads, as shown by `is_protein`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams, Strand};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     assert_eq!(graph.add_sequence_oriented(b"AATGCCCGTT"), Strand::Forward);
+    ///     assert_eq!(graph.add_sequence_oriented(b"AACGGGCATT"), Strand::Reverse);
+    ///     assert_eq!(graph.consensus(), b"AATGCCCGTT".to_vec());
+    /// ```
+    pub fn add_sequence_oriented(&mut self, seq: &[u8]) -> Strand {
+        assert!(!self.is_protein(), "protein reads have no reverse complement");
+        let reverse = reverse_complement(seq);
+        if self.num_sequences() > 0 && self.alignment_score(&reverse) > self.alignment_score(seq) {
+            self.add_sequence(&reverse);
+            Strand::Reverse
+        } else {
+            self.add_sequence(seq);
+            Strand::Forward
+        }
+    }
+}
+
+/// Generates the consensus of reads of mixed strands, adding each read in the orientation that
+/// aligns better to the graph of the reads before it.
+///
+/// Empty reads and trailing null terminators are ignored, as by `poa_consensus_with_matrix`.
+/// Global alignment tells the strands apart best, as a local alignment of the wrong strand can
+/// still find a short match.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus, along the strand of the first non-empty read, and the orientation of
+///   each read
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_oriented, PoaParams, Strand};
+///
+///     let seqs = vec!["AATGCCCGTT", "AACGGCATT", "AATGCCCGAT"];
+///     let oriented = poa_consensus_oriented(&seqs, 0, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(oriented.consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(oriented.strands, vec![Strand::Forward, Strand::Reverse, Strand::Forward]);
+/// ```
+pub fn poa_consensus_oriented<T: AsRef<[u8]>>(seqs: &[T], consensus_max_length: usize, params: &PoaParams) -> OrientedConsensus {
+    let mut graph = PoaGraph::new(params);
+    let strands = seqs
+        .iter()
+        .map(|seq| {
+            let seq = seq.as_ref();
+            let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+            if seq.is_empty() {
+                Strand::Forward
+            } else {
+                graph.add_sequence_oriented(seq)
+            }
+        })
+        .collect();
+
+    let mut consensus = graph.consensus();
+    if consensus_max_length > 0 {
+        consensus.truncate(consensus_max_length);
+    }
+    OrientedConsensus { consensus, strands }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SubstitutionMatrix;
+
+    #[test]
+    fn test_mixed_strands() {
+        let template = b"GATTACAGGCTTACCGATCGATTGCAGCTAGGCATCAAGT";
+        let reads: Vec<Vec<u8>> = (0..8)
+            .map(|i| {
+                let mut read = template.to_vec();
+                read[(5 * i + 3) % template.len()] = b'A';
+                if i % 3 == 1 {
+                    reverse_complement(&read)
+                } else {
+                    read
+                }
+            })
+            .collect();
+
+        for alignment_type in 0..3 {
+            let params = PoaParams::new(alignment_type, 5, -4, -8, -6);
+            let oriented = poa_consensus_oriented(&reads, 0, &params);
+            assert_eq!(oriented.consensus, template.to_vec(), "{}", alignment_type);
+            for (i, strand) in oriented.strands.iter().enumerate() {
+                let expected = if i % 3 == 1 { Strand::Reverse } else { Strand::Forward };
+                assert_eq!(*strand, expected, "{} {}", alignment_type, i);
+            }
+        }
+
+        // without orienting, the reverse reads branch off as new nodes
+        let params = PoaParams::new(1, 5, -4, -8, -6);
+        let (mut plain, mut oriented) = (PoaGraph::new(&params), PoaGraph::new(&params));
+        for read in &reads {
+            plain.add_sequence(read);
+            oriented.add_sequence_oriented(read);
+        }
+        assert!(plain.num_nodes() > oriented.num_nodes());
+    }
+
+    #[test]
+    fn test_first_read_and_empty_reads() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let oriented = poa_consensus_oriented(&["\0", "AACGGGCATT\0", "", "AATGCCCGTT"], 0, &params);
+        assert_eq!(oriented.consensus, b"AACGGGCATT".to_vec());
+        assert_eq!(oriented.strands, vec![Strand::Forward, Strand::Forward, Strand::Forward, Strand::Reverse]);
+        assert_eq!(poa_consensus_oriented(&["AATGCCCGTT"; 2], 4, &params).consensus, b"AATG".to_vec());
+
+        // the scores of a nucleotide substitution matrix orient reads too
+        let mut graph = PoaGraph::with_matrix(&params, &SubstitutionMatrix::uniform(b"ACGT", 5, -4).unwrap());
+        graph.add_sequence(b"AATGCCCGTT");
+        assert!(graph.alignment_score(b"AATGCCCGTT") > graph.alignment_score(b"AACGGGCATT"));
+        assert_eq!(graph.add_sequence_oriented(b"AACGGGCATT"), Strand::Reverse);
+        assert_eq!(Strand::Reverse.to_string(), "-");
+        assert_eq!(PoaGraph::new(&params).alignment_score(b"ACGT"), 0);
+    }
+}
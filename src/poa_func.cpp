@@ -1,50 +1,1417 @@
+#include <algorithm>
+#include <cctype>
+#include <cstdlib>
+#include <limits>
+#include <map>
+#include <memory>
+#include <new>
+#include <stdexcept>
+#include <string>
+#include <utility>
+#include <vector>
+
 #include "poa_func.h"
 #include "spoa/spoa.hpp"
+#if !defined(POA_SYSTEM_SPOA)
+// the alignment engines of each instruction set, which spoa only creates through its own dispatch
+#include "simd_alignment_engine.hpp"
+#include "sisd_alignment_engine.hpp"
+#endif
+
+// a graph together with the engine aligning sequences to it, and the node path of each sequence
+struct poa_graph {
+    std::unique_ptr<spoa::AlignmentEngine> alignment_engine;
+    // the instruction set of the alignment engine, one of the POA_SIMD_ values other than auto
+    int simd;
+    spoa::Graph graph;
+    std::vector<std::vector<uint32_t>> paths;
+    std::vector<std::vector<uint32_t>> weights;
+    // the number of leading and trailing bases of each sequence not aligned to an existing node
+    std::vector<std::pair<uint32_t, uint32_t>> clips;
+    // the score of the alignment each sequence was added along, or POA_NO_SCORE
+    std::vector<int32_t> scores;
+};
+
+// an alignment engine on its own, and the instruction set it uses
+struct poa_raw_engine {
+    std::unique_ptr<spoa::AlignmentEngine> engine;
+    int simd;
+};
+
+// a graph on its own, without the read bookkeeping of poa_graph
+struct poa_raw_graph {
+    spoa::Graph graph;
+};
+
+// the last exception caught by an entry point on this thread, as a POA_ERROR_ value and its message
+static thread_local int last_error = POA_OK;
+static thread_local std::string last_error_message;
+
+// only guarded sets errors, and only when exceptions are enabled
+#if defined(__cpp_exceptions)
+static void set_error(int error, const char* message) {
+    last_error = error;
+    last_error_message = message;
+}
+#endif
+
+// runs the body of an entry point, returning fallback if it throws, so no exception unwinds into
+// the caller. without exceptions, as for WebAssembly, a throw aborts instead.
+template <typename T, typename F>
+static T guarded(T fallback, F body) {
+#if defined(__cpp_exceptions)
+    try {
+        return body();
+    } catch (const std::bad_alloc& e) {
+        set_error(POA_ERROR_ALLOC, e.what());
+    } catch (const std::invalid_argument& e) {
+        set_error(POA_ERROR_INVALID, e.what());
+    } catch (const std::exception& e) {
+        set_error(POA_ERROR_OTHER, e.what());
+    } catch (...) {
+        set_error(POA_ERROR_OTHER, "unknown exception");
+    }
+    return fallback;
+#else
+    (void) fallback;
+    return body();
+#endif
+}
+
+template <typename F>
+static void guarded(F body) {
+    guarded<int>(0, [&]() -> int {
+        body();
+        return 0;
+    });
+}
+
+// returns the id of the graph node representing each base of a sequence that was just added to
+// the graph with the given alignment, when the graph held num_nodes nodes before. this follows
+// the order in which spoa::Graph::AddAlignment creates nodes: first the unaligned prefix, then
+// the unaligned suffix, then the aligned bases without an existing node for their letter.
+static std::vector<uint32_t> sequence_path(const spoa::Graph& graph, const spoa::Alignment& alignment,
+                                           const char* sequence, uint32_t sequence_len, uint32_t num_nodes) {
+
+    std::vector<uint32_t> path(sequence_len);
+    uint32_t next_node_id = num_nodes;
+
+    if (alignment.empty()) {
+        for (uint32_t i = 0; i < sequence_len; i++) {
+            path[i] = next_node_id++;
+        }
+        return path;
+    }
+
+    int32_t first = -1, last = -1;
+    for (const auto& it: alignment) {
+        if (it.second != -1) {
+            if (first == -1) {
+                first = it.second;
+            }
+            last = it.second;
+        }
+    }
+
+    for (int32_t i = 0; i < first; i++) {
+        path[i] = next_node_id++;
+    }
+    for (uint32_t i = last + 1; i < sequence_len; i++) {
+        path[i] = next_node_id++;
+    }
+
+    for (const auto& it: alignment) {
+        if (it.second == -1) {
+            continue;
+        }
+
+        uint8_t letter = sequence[it.second];
+        uint32_t node_id = 0;
+
+        if (it.first == -1) {
+            node_id = next_node_id++;
+        } else if (graph.decoder(graph.nodes()[it.first]->code) == letter) {
+            node_id = it.first;
+        } else {
+            // a mismatch joins (or creates) the node for its letter among the aligned nodes
+            for (const auto& aligned: graph.nodes()[it.first]->aligned_nodes) {
+                if (graph.decoder(aligned->code) == letter) {
+                    node_id = aligned->id;
+                    break;
+                }
+            }
+            if (node_id >= num_nodes) {
+                next_node_id++;
+            }
+        }
+
+        path[it.second] = node_id;
+    }
+
+    return path;
+}
+
+// sums the weights of the sequences passing along each edge, keyed by (tail, head)
+static std::map<std::pair<uint32_t, uint32_t>, uint64_t> edge_weights(const poa_graph* g) {
+
+    std::map<std::pair<uint32_t, uint32_t>, uint64_t> edges;
+    for (size_t s = 0; s < g->paths.size(); s++) {
+        const auto& path = g->paths[s];
+        const auto& weights = g->weights[s];
+        for (size_t i = 1; i < path.size(); i++) {
+            // both nodes contribute to edge weight, as in spoa
+            edges[std::make_pair(path[i - 1], path[i])] += weights[i - 1] + weights[i];
+        }
+    }
+    return edges;
+}
+
+// returns the complement of a nucleotide (including IUPAC ambiguity codes), or the symbol itself
+// for other symbols. this is an involution, so distinct symbols have distinct complements.
+static char complement(char c) {
+    switch (c) {
+        case 'A': return 'T'; case 'T': return 'A'; case 'C': return 'G'; case 'G': return 'C';
+        case 'a': return 't'; case 't': return 'a'; case 'c': return 'g'; case 'g': return 'c';
+        case 'R': return 'Y'; case 'Y': return 'R'; case 'K': return 'M'; case 'M': return 'K';
+        case 'B': return 'V'; case 'V': return 'B'; case 'D': return 'H'; case 'H': return 'D';
+        case 'r': return 'y'; case 'y': return 'r'; case 'k': return 'm'; case 'm': return 'k';
+        case 'b': return 'v'; case 'v': return 'b'; case 'd': return 'h'; case 'h': return 'd';
+        default: return c;
+    }
+}
+
+// adds to dst one sequence per non-empty path of src nodes (per path if keep_empty), spelling the bases of the nodes (or
+// their complements) with the given weights. every base is aligned to the dst node already
+// representing its src node, or else to a dst node representing a src node aligned to it, which
+// recreates the src nodes along the paths one by one together with their alignment groups.
+static void add_node_paths(poa_graph* dst, const poa_graph* src,
+                           const std::vector<std::vector<uint32_t>>& paths,
+                           const std::vector<std::vector<uint32_t>>& weights,
+                           const std::vector<std::pair<uint32_t, uint32_t>>& clips,
+                           const std::vector<int32_t>& scores, bool complemented, bool keep_empty) {
+
+    const auto& nodes = src->graph.nodes();
+    std::vector<int64_t> mapping(nodes.size(), -1);
+
+    for (size_t s = 0; s < paths.size(); s++) {
+        const auto& path = paths[s];
+        if (path.empty()) {
+            if (keep_empty) {
+                dst->paths.emplace_back();
+                dst->weights.emplace_back();
+                dst->clips.push_back(clips[s]);
+                dst->scores.push_back(scores[s]);
+            }
+            continue;
+        }
+
+        std::string sequence;
+        spoa::Alignment alignment;
+        for (size_t i = 0; i < path.size(); i++) {
+            char base = src->graph.decoder(nodes[path[i]]->code);
+            sequence.push_back(complemented ? complement(base) : base);
+
+            int32_t node_id = mapping[path[i]];
+            if (node_id == -1) {
+                for (const auto& aligned: nodes[path[i]]->aligned_nodes) {
+                    if (mapping[aligned->id] != -1) {
+                        node_id = mapping[aligned->id];
+                        break;
+                    }
+                }
+            }
+            alignment.emplace_back(node_id, (int32_t) i);
+        }
+
+        uint32_t num_nodes = dst->graph.nodes().size();
+        dst->graph.AddAlignment(alignment, sequence.c_str(), sequence.size(), weights[s]);
+        auto dst_path = sequence_path(dst->graph, alignment, sequence.c_str(), sequence.size(), num_nodes);
+
+        for (size_t i = 0; i < path.size(); i++) {
+            mapping[path[i]] = dst_path[i];
+        }
+        dst->paths.push_back(dst_path);
+        dst->weights.push_back(weights[s]);
+        dst->clips.push_back(clips[s]);
+        dst->scores.push_back(scores[s]);
+    }
+}
+
+// returns the best instruction set of the alignment engine the CPU supports
+static int supported_simd() {
+#if defined(POA_X86_DISPATCH)
+    __builtin_cpu_init();
+    if (__builtin_cpu_supports("avx2")) {
+        return POA_SIMD_AVX2;
+    }
+    if (__builtin_cpu_supports("sse4.1")) {
+        return POA_SIMD_SSE4_1;
+    }
+#endif
+    return POA_SIMD_SCALAR;
+}
+
+// the bases an IUPAC ambiguity code stands for, in its case, or null for any other symbol
+static const char* ambiguity_bases(unsigned char symbol) {
+
+    static const char* const upper[] = {"AG", "CT", "CG", "AT", "GT", "AC", "CGT", "AGT", "ACT", "ACG", "ACGT"};
+    static const char* const lower[] = {"ag", "ct", "cg", "at", "gt", "ac", "cgt", "agt", "act", "acg", "acgt"};
+    const char* codes = "RYSWKMBDHVN";
+    for (int i = 0; codes[i] != '\0'; i++) {
+        if (symbol == (unsigned char) codes[i]) {
+            return upper[i];
+        }
+        if (symbol == (unsigned char) std::tolower((unsigned char) codes[i])) {
+            return lower[i];
+        }
+    }
+    return nullptr;
+}
+
+// rescores the pairs of a 256 x 256 substitution table with an ambiguity code on either side: 0
+// for POA_AMBIGUITY_NEUTRAL, the best score of the bases the codes stand for for
+// POA_AMBIGUITY_WILDCARD, and the lowest score of the table for POA_AMBIGUITY_MISMATCH
+static void apply_ambiguity(std::vector<int32_t>& table, int ambiguity) {
+
+    if (ambiguity == POA_AMBIGUITY_LITERAL) {
+        return;
+    }
+    if (ambiguity < POA_AMBIGUITY_LITERAL || ambiguity > POA_AMBIGUITY_MISMATCH) {
+        throw std::invalid_argument("[poa_func] error: invalid ambiguous base scoring!");
+    }
+    std::vector<int32_t> scores = table;
+    int32_t lowest = *std::min_element(scores.begin(), scores.end());
+    for (int a = 0; a < 256; a++) {
+        for (int b = 0; b < 256; b++) {
+            const char* row = ambiguity_bases((unsigned char) a);
+            const char* column = ambiguity_bases((unsigned char) b);
+            if (row == nullptr && column == nullptr) {
+                continue;
+            }
+            int32_t score = ambiguity == POA_AMBIGUITY_NEUTRAL ? 0 : lowest;
+            if (ambiguity == POA_AMBIGUITY_WILDCARD) {
+                std::string rows = row == nullptr ? std::string(1, (char) a) : row;
+                std::string columns = column == nullptr ? std::string(1, (char) b) : column;
+                for (char r: rows) {
+                    for (char c: columns) {
+                        score = std::max(score, scores[(uint8_t) r * 256 + (uint8_t) c]);
+                    }
+                }
+            }
+            table[a * 256 + b] = score;
+        }
+    }
+}
+
+// a scalar alignment engine scoring each pair of symbols with a substitution matrix instead of one
+// match and one mismatch score. it fills the same matrices as spoa::SisdAlignmentEngine, with linear
+// gaps scored as affine gaps whose open and extend scores are equal, and traces the alignment back
+// through the match, insertion and deletion states
+class MatrixAlignmentEngine: public spoa::AlignmentEngine {
+public:
+    // table holds the score of each pair of bytes, with the graph symbol as the row and the
+    // sequence symbol as the column
+    MatrixAlignmentEngine(spoa::AlignmentType type, spoa::AlignmentSubtype subtype, int8_t g, int8_t e,
+                          std::vector<int32_t> table)
+            : spoa::AlignmentEngine(type, subtype, 0, 0, g, e, g, e), table(std::move(table)) {
+
+        max_magnitude = std::max(std::abs((int) g), std::abs((int) e));
+        for (int32_t score: this->table) {
+            max_magnitude = std::max(max_magnitude, std::abs(score));
+        }
+    }
+
+    // the table of a substitution matrix: scores holds the score of each pair of the alphabet_len
+    // symbols of alphabet, row by row. symbols are matched ignoring case, and pairs with a symbol
+    // outside the alphabet score the lowest score of the matrix.
+    static std::vector<int32_t> matrix_table(const char* alphabet, unsigned alphabet_len, const int* scores) {
+
+        int lowest = 0;
+        for (unsigned i = 0; i < alphabet_len * alphabet_len; i++) {
+            lowest = std::min(lowest, scores[i]);
+        }
+        std::vector<int32_t> table(256 * 256, lowest);
+        for (unsigned a = 0; a < alphabet_len; a++) {
+            for (unsigned b = 0; b < alphabet_len; b++) {
+                int score = scores[a * alphabet_len + b];
+                for (int row: {std::tolower((unsigned char) alphabet[a]), std::toupper((unsigned char) alphabet[a])}) {
+                    for (int column: {std::tolower((unsigned char) alphabet[b]), std::toupper((unsigned char) alphabet[b])}) {
+                        table[(uint8_t) row * 256 + (uint8_t) column] = score;
+                    }
+                }
+            }
+        }
+        return table;
+    }
+
+    // the table of a match and a mismatch score, comparing bytes as the native engines do
+    static std::vector<int32_t> score_table(int8_t m, int8_t n) {
+
+        std::vector<int32_t> table(256 * 256, n);
+        for (int a = 0; a < 256; a++) {
+            table[a * 256 + a] = m;
+        }
+        return table;
+    }
+
+    void Prealloc(uint32_t, uint8_t) override {
+    }
+
+    spoa::Alignment Align(const char* sequence, uint32_t sequence_len, const spoa::Graph& graph,
+                          int32_t* score) override {
+
+        const auto& rank_to_node = graph.rank_to_node();
+        if (rank_to_node.empty() || sequence_len == 0) {
+            return spoa::Alignment();
+        }
+        if ((int64_t) (sequence_len + rank_to_node.size() + 1) * max_magnitude >= (int64_t) 1 << 30) {
+            throw std::invalid_argument("[poa_func] error: possible overflow!");
+        }
+
+        uint64_t width = sequence_len + 1, height = rank_to_node.size() + 1;
+        H.assign(width * height, 0);
+        E.assign(width * height, kNegativeInfinity);
+        F.assign(width * height, kNegativeInfinity);
+        node_id_to_rank.resize(graph.nodes().size());
+        for (uint32_t i = 0; i < rank_to_node.size(); i++) {
+            node_id_to_rank[rank_to_node[i]->id] = i;
+        }
+        // the rows of the predecessors of the node in row i, or row 0 for a node without any
+        auto predecessors = [&](uint32_t i) {
+            std::vector<uint32_t> rows;
+            for (const auto& it: rank_to_node[i - 1]->inedges) {
+                rows.push_back(node_id_to_rank[it->tail->id] + 1);
+            }
+            if (rows.empty()) {
+                rows.push_back(0);
+            }
+            return rows;
+        };
+        auto substitution = [&](uint32_t i, uint64_t j) {
+            return table[graph.decoder(rank_to_node[i - 1]->code) * 256 + (uint8_t) sequence[j - 1]];
+        };
+
+        // the gaps before the first base and the first node, as in the native engine
+        E[0] = F[0] = 0;
+        for (uint64_t j = 1; j < width; j++) {
+            E[j] = g_ + (j - 1) * e_;
+            H[j] = type_ == spoa::AlignmentType::kSW ? 0 : E[j];
+        }
+        for (uint32_t i = 1; i < height; i++) {
+            const auto& edges = rank_to_node[i - 1]->inedges;
+            int32_t penalty = edges.empty() ? g_ - e_ : kNegativeInfinity;
+            for (const auto& it: edges) {
+                penalty = std::max(penalty, F[(node_id_to_rank[it->tail->id] + 1) * width]);
+            }
+            F[i * width] = penalty + e_;
+            H[i * width] = type_ == spoa::AlignmentType::kNW ? F[i * width] : 0;
+        }
+
+        int32_t max_score = type_ == spoa::AlignmentType::kSW ? 0 : kNegativeInfinity;
+        uint32_t max_i = 0;
+        uint64_t max_j = 0;
+        for (uint32_t i = 1; i < height; i++) {
+            auto rows = predecessors(i);
+            bool sink = rank_to_node[i - 1]->outedges.empty();
+            for (uint64_t j = 1; j < width; j++) {
+                uint64_t cell = i * width + j;
+                int32_t h = kNegativeInfinity;
+                for (uint32_t p: rows) {
+                    h = std::max(h, H[p * width + j - 1] + substitution(i, j));
+                    F[cell] = std::max(F[cell], std::max(H[p * width + j] + g_, F[p * width + j] + e_));
+                }
+                E[cell] = std::max(H[cell - 1] + g_, E[cell - 1] + e_);
+                H[cell] = std::max(h, std::max(E[cell], F[cell]));
+                if (type_ == spoa::AlignmentType::kSW) {
+                    H[cell] = std::max(H[cell], 0);
+                }
+
+                bool end = type_ == spoa::AlignmentType::kSW || (sink && (type_ == spoa::AlignmentType::kOV || j == width - 1));
+                if (end && H[cell] > max_score) {
+                    max_score = H[cell];
+                    max_i = i;
+                    max_j = j;
+                }
+            }
+        }
+
+        if (max_i == 0 && max_j == 0) {
+            return spoa::Alignment();
+        }
+        if (score) {
+            *score = max_score;
+        }
+
+        // the states of the traceback: a cell of H, or a gap in the sequence (F) or in the graph (E)
+        enum { kH, kE, kF } state = kH;
+        spoa::Alignment alignment;
+        uint32_t i = max_i;
+        uint64_t j = max_j;
+        while (true) {
+            uint64_t cell = i * width + j;
+            if (state == kH) {
+                bool done = type_ == spoa::AlignmentType::kSW ? H[cell] == 0 :
+                            type_ == spoa::AlignmentType::kNW ? i == 0 && j == 0 : i == 0 || j == 0;
+                if (done) {
+                    break;
+                }
+                bool matched = false;
+                if (i != 0 && j != 0) {
+                    for (uint32_t p: predecessors(i)) {
+                        if (H[cell] == H[p * width + j - 1] + substitution(i, j)) {
+                            alignment.emplace_back(rank_to_node[i - 1]->id, j - 1);
+                            i = p;
+                            j -= 1;
+                            matched = true;
+                            break;
+                        }
+                    }
+                }
+                if (!matched) {
+                    state = i != 0 && H[cell] == F[cell] ? kF : kE;
+                }
+            } else if (state == kF) {
+                bool extended = false;
+                uint32_t next = 0;
+                for (uint32_t p: predecessors(i)) {
+                    if (F[cell] == H[p * width + j] + g_) {
+                        next = p;
+                        break;
+                    }
+                    if (F[cell] == F[p * width + j] + e_) {
+                        next = p;
+                        extended = true;
+                        break;
+                    }
+                }
+                alignment.emplace_back(rank_to_node[i - 1]->id, -1);
+                i = next;
+                state = extended ? kF : kH;
+            } else {
+                bool extended = E[cell] != H[cell - 1] + g_;
+                alignment.emplace_back(-1, j - 1);
+                j -= 1;
+                state = extended ? kE : kH;
+            }
+        }
+
+        std::reverse(alignment.begin(), alignment.end());
+        return alignment;
+    }
+
+private:
+    static constexpr int32_t kNegativeInfinity = std::numeric_limits<int32_t>::min() + 1024;
+
+    std::vector<int32_t> table;
+    int max_magnitude;
+    std::vector<uint32_t> node_id_to_rank;
+    std::vector<int32_t> H, E, F;
+};
+
+// creates an alignment engine as spoa::AlignmentEngine::Create does, but with the instruction set
+// simd instead of the one spoa was compiled for. auto, or an instruction set the CPU does not
+// support, selects the best supported one. the selected instruction set is stored in selected.
+// scoring ambiguous bases other than as given needs a scalar engine with a substitution table
+static std::unique_ptr<spoa::AlignmentEngine> create_engine(int alignment_type, int match_score, int mismatch_score,
+                                                            int gap_open, int gap_extend, int gap_open2,
+                                                            int gap_extend2, int simd, int ambiguity, int* selected) {
+
+    if (alignment_type < 0 || alignment_type > 2) {
+        throw std::invalid_argument("[poa_func] error: invalid alignment type!");
+    }
+    if (gap_open > 0 || gap_extend > 0 || gap_open2 > 0 || gap_extend2 > 0) {
+        throw std::invalid_argument("[poa_func] error: gap penalties must be non-positive!");
+    }
+    auto type = static_cast<spoa::AlignmentType>(alignment_type);
+    int8_t m = (int8_t) match_score, n = (int8_t) mismatch_score, g = (int8_t) gap_open, e = (int8_t) gap_extend;
+    int8_t q = (int8_t) gap_open2, c = (int8_t) gap_extend2;
+    // as spoa::AlignmentEngine::Create, the second pair makes gaps convex only if it opens more
+    // expensively and extends more cheaply than the first
+    auto subtype = g >= e ? spoa::AlignmentSubtype::kLinear
+                          : (g <= q || e >= c ? spoa::AlignmentSubtype::kAffine : spoa::AlignmentSubtype::kConvex);
+    if (subtype == spoa::AlignmentSubtype::kLinear) {
+        e = g;
+    }
+    if (subtype != spoa::AlignmentSubtype::kConvex) {
+        q = g;
+        c = e;
+    }
+    if (ambiguity != POA_AMBIGUITY_LITERAL) {
+        if (subtype == spoa::AlignmentSubtype::kConvex) {
+            throw std::invalid_argument("[poa_func] error: ambiguous base scoring does not support convex gaps!");
+        }
+        auto table = MatrixAlignmentEngine::score_table(m, n);
+        apply_ambiguity(table, ambiguity);
+        *selected = POA_SIMD_SCALAR;
+        return std::unique_ptr<spoa::AlignmentEngine>(new MatrixAlignmentEngine(type, subtype, g, e, std::move(table)));
+    }
+
+#if defined(POA_SYSTEM_SPOA)
+    // an installed spoa library picks the instruction set of its engines itself
+    (void) simd;
+    (void) subtype;
+    *selected = POA_SIMD_SCALAR;
+    return spoa::AlignmentEngine::Create(type, m, n, g, e, q, c);
+#else
+    int supported = supported_simd();
+    *selected = simd == POA_SIMD_AUTO || simd > supported ? supported : simd;
+#if defined(POA_X86_DISPATCH)
+    if (*selected == POA_SIMD_AVX2) {
+        return spoa::SimdAlignmentEngine<spoa::Architecture::kAVX2>::Create(type, subtype, m, n, g, e, q, c);
+    }
+    if (*selected == POA_SIMD_SSE4_1) {
+        return spoa::SimdAlignmentEngine<spoa::Architecture::kSSE4_1>::Create(type, subtype, m, n, g, e, q, c);
+    }
+#endif
+    return spoa::SisdAlignmentEngine::Create(type, subtype, m, n, g, e, q, c);
+#endif
+}
+
+// aligns each of the sequences to a new graph, in order, and returns the graph
+static spoa::Graph build_graph(char** seqs, size_t num_seqs, int alignment_type, int match_score, int mismatch_score,
+                               int gap_open, int gap_extend, int gap_open2, int gap_extend2, int simd, int ambiguity) {
+
+    // populate the list of sequences
+    std::vector<std::string> sequences;
+    for (size_t i = 0; i < num_seqs; i++){
+        sequences.push_back((std::string) seqs[i]);
+    }
+
+    int selected;
+    auto alignment_engine = create_engine(alignment_type, match_score, mismatch_score, gap_open, gap_extend, gap_open2,
+                                          gap_extend2, simd, ambiguity, &selected);
+    spoa::Graph graph;
+
+    // add each of the real sequences (e.g. noisy sequence reads) to the graph
+    for (const auto& it: sequences) {
+        auto alignment = alignment_engine->Align(it, graph);
+        graph.AddAlignment(alignment, it);
+    }
+
+    return graph;
+}
+
+// copies at most consensus_len bases of cns to consensus, returning the number of bases copied
+static size_t copy_consensus(const std::string& cns, char* consensus, size_t consensus_len) {
+
+    size_t l = std::min(cns.length(), consensus_len);
+    std::copy(cns.begin(), cns.begin() + l, consensus);
+    return l;
+}
+
+// writes the consensus of the graph to the buffers reserve sizes for it, with the agreeing fraction,
+// the number of reads covering and the number of reads agreeing with each base, as poa_func_support
+// describes. returns the length of the consensus.
+static size_t consensus_support(spoa::Graph& graph, void* buffers, poa_reserve_support reserve) {
+
+    // the verbose summary holds, for each consensus column, the number of reads carrying each
+    // symbol code followed by the number of reads with a deletion at that column
+    std::vector<uint32_t> summary;
+    std::string cns = graph.GenerateConsensus(&summary, true);
+
+    // have the caller allocate the buffers of its length, and copy the consensus there
+    size_t l = cns.length();
+    char* consensus = nullptr;
+    float* support = nullptr;
+    unsigned* depth = nullptr;
+    unsigned* agreeing = nullptr;
+    reserve(buffers, l, &consensus, &support, &depth, &agreeing);
+    std::copy(cns.begin(), cns.end(), consensus);
+    uint32_t num_codes = graph.num_codes();
+
+    for (size_t i = 0; i < l; i++) {
+        uint32_t covering = 0;
+        for (uint32_t code = 0; code <= num_codes; code++) {
+            covering += summary[code * l + i];
+        }
+        uint32_t agreeing_reads = summary[graph.coder(cns[i]) * l + i];
+
+        support[i] = covering == 0 ? 0.0f : (float) agreeing_reads / (float) covering;
+        if (depth != nullptr) {
+            depth[i] = covering;
+        }
+        if (agreeing != nullptr) {
+            agreeing[i] = agreeing_reads;
+        }
+    }
+
+    return l;
+}
+
+// adds the sequence along an alignment with the given score to the graph, under the limits of
+// poa_graph_add_checked
+static int add_alignment_checked(poa_graph* g, const spoa::Alignment& alignment, int32_t score, const char* seq,
+                                 unsigned seq_len, const uint32_t* weights, unsigned max_nodes,
+                                 unsigned max_inserted, double min_identity, double* identity) {
+
+    uint32_t num_nodes = g->graph.nodes().size();
+    // the identity to the backbone is measured on this alignment: a base matches if it is
+    // aligned to a backbone node, or a node aligned to one, holding its letter. every alignment
+    // column and every base left out of a local alignment counts towards the length.
+    if (num_nodes > 0 && !g->paths.empty() && (min_identity > 0.0 || identity != nullptr)) {
+        const auto& nodes = g->graph.nodes();
+        std::vector<bool> backbone(num_nodes, false);
+        for (uint32_t id: g->paths[0]) {
+            backbone[id] = true;
+        }
+        uint64_t matches = 0, aligned_bases = 0;
+        for (const auto& it: alignment) {
+            if (it.second == -1) {
+                continue;
+            }
+            aligned_bases++;
+            if (it.first == -1) {
+                continue;
+            }
+            char letter = seq[it.second];
+            bool match = backbone[it.first] && g->graph.decoder(nodes[it.first]->code) == letter;
+            for (const auto& aligned: nodes[it.first]->aligned_nodes) {
+                match = match || (backbone[aligned->id] && g->graph.decoder(aligned->code) == letter);
+            }
+            if (match) {
+                matches++;
+            }
+        }
+        uint64_t columns = alignment.size() + (seq_len - aligned_bases);
+        double read_identity = columns == 0 ? 0.0 : (double) matches / columns;
+        if (identity != nullptr) {
+            *identity = read_identity;
+        }
+        if (read_identity < min_identity) {
+            return 3;
+        }
+    } else if (identity != nullptr) {
+        *identity = 1.0;
+    }
+
+    // the bases not aligned to any node are inserted as new nodes
+    uint64_t inserted = seq_len;
+    for (const auto& it: alignment) {
+        if (it.first != -1 && it.second != -1) {
+            inserted--;
+        }
+    }
+    // every base of the first sequence is new, so it is exempt from the insertion limit
+    if (num_nodes > 0 && inserted > max_inserted) {
+        return 2;
+    }
+
+    // so does each mismatch without a node for its letter among the aligned nodes
+    uint64_t new_nodes = inserted;
+    const auto& nodes = g->graph.nodes();
+    for (const auto& it: alignment) {
+        if (it.first == -1 || it.second == -1) {
+            continue;
+        }
+        char letter = seq[it.second];
+        bool has_node = g->graph.decoder(nodes[it.first]->code) == letter;
+        for (const auto& aligned: nodes[it.first]->aligned_nodes) {
+            has_node = has_node || g->graph.decoder(aligned->code) == letter;
+        }
+        if (!has_node) {
+            new_nodes++;
+        }
+    }
+    if (num_nodes + new_nodes > max_nodes) {
+        return 1;
+    }
+
+    std::vector<uint32_t> base_weights(seq_len, 1);
+    if (weights != nullptr) {
+        base_weights.assign(weights, weights + seq_len);
+    }
+    g->graph.AddAlignment(alignment, seq, seq_len, base_weights);
+    g->paths.push_back(sequence_path(g->graph, alignment, seq, seq_len, num_nodes));
+    g->weights.push_back(std::move(base_weights));
+
+    // the first sequence defines the graph, so none of its bases are clipped
+    uint32_t leading = 0, trailing = 0;
+    if (num_nodes > 0) {
+        int64_t first = -1, last = -1;
+        for (const auto& it: alignment) {
+            if (it.first != -1 && it.second != -1) {
+                if (first == -1) {
+                    first = it.second;
+                }
+                last = it.second;
+            }
+        }
+        leading = first == -1 ? seq_len : (uint32_t) first;
+        trailing = first == -1 ? 0 : seq_len - 1 - (uint32_t) last;
+    }
+    g->clips.emplace_back(leading, trailing);
+    // the first sequence is not aligned to anything
+    g->scores.push_back(num_nodes > 0 ? score : POA_NO_SCORE);
+    return 0;
+}
 
 extern "C" {
 
     // see the C header file (poa_func.h) for detailed descriptions of each argument
-    unsigned poa_func(char** seqs, int num_seqs,
-                      char* consensus, int consensus_len,
-                      int alignment_type, int match_score, int mismatch_score, int gap_open, int gap_extend) {
+    size_t poa_func(char** seqs, size_t num_seqs,
+                    void* consensus, poa_reserve reserve,
+                    int alignment_type, int match_score, int mismatch_score, int gap_open, int gap_extend,
+                    int gap_open2, int gap_extend2, int simd, int ambiguity) {
+
+        return guarded<size_t>(0, [&]() -> size_t {
+            if (num_seqs == 0) {
+                return 0;
+            }
+
+            auto graph = build_graph(seqs, num_seqs, alignment_type, match_score, mismatch_score, gap_open, gap_extend,
+                                     gap_open2, gap_extend2, simd, ambiguity);
+
+            // generate the consensus sequence, have the caller allocate memory of its length, and copy it there.
+            std::string cns = graph.GenerateConsensus();
+
+            size_t l = cns.length();
+            char* buffer = reserve(consensus, l);
+            std::copy(cns.begin(), cns.end(), buffer);
+
+            return l;
+        });
+    }
+
+    size_t poa_func_support(char** seqs, size_t num_seqs,
+                            void* buffers, poa_reserve_support reserve,
+                            int alignment_type, int match_score, int mismatch_score, int gap_open, int gap_extend,
+                            int gap_open2, int gap_extend2, int simd, int ambiguity) {
+
+        return guarded<size_t>(0, [&]() -> size_t {
+            if (num_seqs == 0) {
+                return 0;
+            }
+
+            auto graph = build_graph(seqs, num_seqs, alignment_type, match_score, mismatch_score, gap_open, gap_extend,
+                                     gap_open2, gap_extend2, simd, ambiguity);
+
+            return consensus_support(graph, buffers, reserve);
+        });
+    }
+
+    size_t poa_graph_consensus_support(poa_graph* g, void* buffers, poa_reserve_support reserve) {
+
+        return guarded<size_t>(0, [&]() -> size_t {
+            if (g->graph.nodes().empty()) {
+                return 0;
+            }
+            return consensus_support(g->graph, buffers, reserve);
+        });
+    }
+
+    int poa_take_error(char* message, size_t message_len) {
+        int error = last_error;
+        if (message_len > 0) {
+            size_t l = std::min(last_error_message.length(), message_len - 1);
+            std::copy(last_error_message.begin(), last_error_message.begin() + l, message);
+            message[l] = '\0';
+        }
+        last_error = POA_OK;
+        last_error_message.clear();
+        return error;
+    }
+
+    int poa_simd_supported(void) {
+        return supported_simd();
+    }
+
+    poa_graph* poa_graph_new(int alignment_type, int match_score, int mismatch_score, int gap_open, int gap_extend,
+                             int gap_open2, int gap_extend2, int simd, int ambiguity) {
+
+        return guarded<poa_graph*>(nullptr, [&]() -> poa_graph* {
+            std::unique_ptr<poa_graph> g(new poa_graph());
+            g->alignment_engine = create_engine(alignment_type, match_score, mismatch_score, gap_open, gap_extend, gap_open2,
+                                                gap_extend2, simd, ambiguity, &g->simd);
+            return g.release();
+        });
+    }
+
+    poa_graph* poa_graph_new_matrix(int alignment_type, int gap_open, int gap_extend, const char* alphabet,
+                                    unsigned alphabet_len, const int* scores, int ambiguity) {
+
+        return guarded<poa_graph*>(nullptr, [&]() -> poa_graph* {
+            // validates the alignment type and gap scores as poa_graph_new does
+            int selected;
+            create_engine(alignment_type, 0, 0, gap_open, gap_extend, gap_open, gap_extend, POA_SIMD_SCALAR,
+                          POA_AMBIGUITY_LITERAL, &selected);
+            int8_t open = (int8_t) gap_open, extend = (int8_t) gap_extend;
+            auto subtype = open >= extend ? spoa::AlignmentSubtype::kLinear : spoa::AlignmentSubtype::kAffine;
+            if (subtype == spoa::AlignmentSubtype::kLinear) {
+                extend = open;
+            }
+
+            auto table = MatrixAlignmentEngine::matrix_table(alphabet, alphabet_len, scores);
+            apply_ambiguity(table, ambiguity);
+            std::unique_ptr<poa_graph> g(new poa_graph());
+            g->alignment_engine.reset(new MatrixAlignmentEngine(static_cast<spoa::AlignmentType>(alignment_type), subtype,
+                                                                open, extend, std::move(table)));
+            g->simd = POA_SIMD_SCALAR;
+            return g.release();
+        });
+    }
+
+    int poa_graph_simd(const poa_graph* g) {
+        return g->simd;
+    }
+
+    void poa_graph_free(poa_graph* g) {
+        delete g;
+    }
+
+    void poa_graph_clear(poa_graph* g) {
+
+        // the alignment engine, which holds the large dynamic programming buffers, is kept
+        g->graph.Clear();
+        g->paths.clear();
+        g->weights.clear();
+        g->clips.clear();
+        g->scores.clear();
+    }
+
+    void poa_graph_add(poa_graph* g, const char* seq, unsigned seq_len) {
+        poa_graph_add_limited(g, seq, seq_len, UINT32_MAX, UINT32_MAX);
+    }
+
+    int32_t poa_graph_alignment_score(poa_graph* g, const char* seq, unsigned seq_len) {
+        return guarded<int32_t>(0, [&]() -> int32_t {
+            int32_t score = 0;
+            if (!g->graph.nodes().empty()) {
+                g->alignment_engine->Align(seq, seq_len, g->graph, &score);
+            }
+            return score;
+        });
+    }
+
+    unsigned poa_graph_align(poa_graph* g, const char* seq, unsigned seq_len, int32_t* node_ids, int32_t* seq_positions,
+                             int32_t* score) {
+        return guarded<unsigned>(0, [&]() -> unsigned {
+            *score = 0;
+            if (g->graph.nodes().empty()) {
+                return (unsigned) 0;
+            }
+
+            auto alignment = g->alignment_engine->Align(seq, seq_len, g->graph, score);
+            for (size_t i = 0; i < alignment.size(); i++) {
+                node_ids[i] = alignment[i].first;
+                seq_positions[i] = alignment[i].second;
+            }
+            return (unsigned) alignment.size();
+        });
+    }
+
+    int poa_graph_add_limited(poa_graph* g, const char* seq, unsigned seq_len, unsigned max_nodes, unsigned max_inserted) {
+        return poa_graph_add_weighted(g, seq, seq_len, nullptr, max_nodes, max_inserted);
+    }
+
+    int poa_graph_add_weighted(poa_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                               unsigned max_nodes, unsigned max_inserted) {
+        return poa_graph_add_checked(g, seq, seq_len, weights, max_nodes, max_inserted, 0.0, nullptr);
+    }
+
+    int poa_graph_add_checked(poa_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                              unsigned max_nodes, unsigned max_inserted, double min_identity, double* identity) {
+
+        return guarded<int>(POA_ADD_FAILED, [&]() -> int {
+            int32_t score = 0;
+            auto alignment = g->alignment_engine->Align(seq, seq_len, g->graph, &score);
+            return add_alignment_checked(g, alignment, score, seq, seq_len, weights, max_nodes, max_inserted, min_identity,
+                                         identity);
+        });
+    }
+
+    int poa_graph_add_anchored(poa_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                               uint32_t begin, uint32_t end) {
+
+        return guarded<int>(POA_ADD_FAILED, [&]() -> int {
+            if (g->paths.empty() || begin >= end || begin >= g->paths[0].size()) {
+                return poa_graph_add_checked(g, seq, seq_len, weights, UINT32_MAX, UINT32_MAX, 0.0, nullptr);
+            }
+            // the backbone is the first sequence, whose node ids increase along it as the subgraph
+            // extraction expects
+            const auto& backbone = g->paths[0];
+            uint32_t begin_node = backbone[begin];
+            uint32_t end_node = backbone[std::min<size_t>(end, backbone.size()) - 1];
+
+            std::vector<const spoa::Graph::Node*> mapping;
+            auto subgraph = g->graph.Subgraph(begin_node, end_node, &mapping);
+            int32_t score = 0;
+            auto alignment = g->alignment_engine->Align(seq, seq_len, subgraph, &score);
+            g->graph.UpdateAlignment(mapping, &alignment);
+            return add_alignment_checked(g, alignment, score, seq, seq_len, weights, UINT32_MAX, UINT32_MAX, 0.0, nullptr);
+        });
+    }
+
+    void poa_graph_add_alignment(poa_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                                 const int32_t* node_ids, const int32_t* seq_positions, unsigned alignment_len,
+                                 int32_t score) {
+
+        poa_graph_add_alignment_checked(g, seq, seq_len, weights, node_ids, seq_positions, alignment_len, score,
+                                        UINT32_MAX, UINT32_MAX, 0.0, nullptr);
+    }
+
+    int poa_graph_add_alignment_checked(poa_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                                        const int32_t* node_ids, const int32_t* seq_positions, unsigned alignment_len,
+                                        int32_t score, unsigned max_nodes, unsigned max_inserted, double min_identity,
+                                        double* identity) {
+
+        return guarded<int>(POA_ADD_FAILED, [&]() -> int {
+            spoa::Alignment alignment;
+            alignment.reserve(alignment_len);
+            for (unsigned i = 0; i < alignment_len; i++) {
+                alignment.emplace_back(node_ids[i], seq_positions[i]);
+            }
+            return add_alignment_checked(g, alignment, score, seq, seq_len, weights, max_nodes, max_inserted,
+                                         min_identity, identity);
+        });
+    }
+
+    void poa_graph_add_subgraph(poa_graph* dst, const poa_graph* src, uint32_t from_node, uint32_t to_node) {
+
+        guarded([&] {
+            // the region holds the nodes reachable from from_node that reach to_node
+            uint32_t num_nodes = src->graph.nodes().size();
+            std::vector<std::vector<uint32_t>> out_edges(num_nodes), in_edges(num_nodes);
+            for (const auto& it: edge_weights(src)) {
+                out_edges[it.first.first].push_back(it.first.second);
+                in_edges[it.first.second].push_back(it.first.first);
+            }
+
+            auto reachable = [num_nodes](uint32_t start, const std::vector<std::vector<uint32_t>>& edges) {
+                std::vector<bool> visited(num_nodes, false);
+                std::vector<uint32_t> stack(1, start);
+                visited[start] = true;
+                while (!stack.empty()) {
+                    uint32_t node_id = stack.back();
+                    stack.pop_back();
+                    for (const auto& next: edges[node_id]) {
+                        if (!visited[next]) {
+                            visited[next] = true;
+                            stack.push_back(next);
+                        }
+                    }
+                }
+                return visited;
+            };
+            auto after_from = reachable(from_node, out_edges);
+            auto before_to = reachable(to_node, in_edges);
+
+            // the nodes of a sequence path inside the region are consecutive, so each sequence adds
+            // at most one fragment
+            std::vector<std::vector<uint32_t>> fragments(src->paths.size()), weights(src->paths.size());
+            for (size_t s = 0; s < src->paths.size(); s++) {
+                for (size_t i = 0; i < src->paths[s].size(); i++) {
+                    uint32_t node_id = src->paths[s][i];
+                    if (after_from[node_id] && before_to[node_id]) {
+                        fragments[s].push_back(node_id);
+                        weights[s].push_back(src->weights[s][i]);
+                    }
+                }
+            }
+
+            // the fragments are parts of the recreated graph, not clipped against it, and were never
+            // aligned on their own
+            std::vector<std::pair<uint32_t, uint32_t>> clips(src->paths.size(), std::make_pair(0, 0));
+            std::vector<int32_t> scores(src->paths.size(), POA_NO_SCORE);
+            add_node_paths(dst, src, fragments, weights, clips, scores, false, false);
+        });
+    }
+
+    void poa_graph_add_reverse_complement(poa_graph* dst, const poa_graph* src) {
+
+        guarded([&] {
+            std::vector<std::vector<uint32_t>> paths(src->paths), weights(src->weights);
+            std::vector<std::pair<uint32_t, uint32_t>> clips(src->clips);
+            for (size_t s = 0; s < paths.size(); s++) {
+                std::reverse(paths[s].begin(), paths[s].end());
+                std::reverse(weights[s].begin(), weights[s].end());
+                std::swap(clips[s].first, clips[s].second);
+            }
+
+            // the reverse complement of an alignment scores as the alignment itself
+            add_node_paths(dst, src, paths, weights, clips, src->scores, true, false);
+        });
+    }
+
+    void poa_graph_add_pruned(poa_graph* dst, const poa_graph* src, uint32_t min_reads) {
+
+        guarded([&] {
+            // the number of sequences through each node and along each edge
+            const auto& nodes = src->graph.nodes();
+            uint32_t num_nodes = nodes.size();
+            std::vector<uint32_t> node_reads(num_nodes, 0);
+            std::map<std::pair<uint32_t, uint32_t>, uint32_t> edge_reads;
+            for (const auto& path: src->paths) {
+                for (size_t i = 0; i < path.size(); i++) {
+                    node_reads[path[i]]++;
+                    if (i > 0) {
+                        edge_reads[std::make_pair(path[i - 1], path[i])]++;
+                    }
+                }
+            }
+            auto strong_edge = [&](uint32_t from, uint32_t to) {
+                auto it = edge_reads.find(std::make_pair(from, to));
+                return it != edge_reads.end() && it->second >= min_reads;
+            };
+
+            // a weak node folds into the kept node aligned to it with the most reads, the first among
+            // equals, and is dropped if there is none
+            std::vector<int64_t> folded(num_nodes, -1);
+            for (uint32_t id = 0; id < num_nodes; id++) {
+                if (node_reads[id] >= min_reads) {
+                    folded[id] = id;
+                    continue;
+                }
+                for (const auto& aligned: nodes[id]->aligned_nodes) {
+                    if (node_reads[aligned->id] >= min_reads && (folded[id] == -1 || node_reads[aligned->id] > node_reads[folded[id]])) {
+                        folded[id] = aligned->id;
+                    }
+                }
+            }
+
+            // the tail of the strong edge into each node along which the most sequences pass
+            std::vector<int64_t> strongest_in(num_nodes, -1);
+            for (const auto& it: edge_reads) {
+                uint32_t from = it.first.first, to = it.first.second;
+                if (it.second >= min_reads && (strongest_in[to] == -1 || it.second > edge_reads[std::make_pair((uint32_t) strongest_in[to], to)])) {
+                    strongest_in[to] = from;
+                }
+            }
+            std::vector<uint32_t> rank(num_nodes, 0);
+            for (uint32_t r = 0; r < src->graph.rank_to_node().size(); r++) {
+                rank[src->graph.rank_to_node()[r]->id] = r;
+            }
+
+            std::vector<std::vector<uint32_t>> paths(src->paths.size()), weights(src->paths.size());
+            for (size_t s = 0; s < src->paths.size(); s++) {
+                auto& path = paths[s];
+                for (size_t i = 0; i < src->paths[s].size(); i++) {
+                    if (folded[src->paths[s][i]] == -1) {
+                        continue;
+                    }
+                    uint32_t node_id = folded[src->paths[s][i]];
+                    uint32_t weight = src->weights[s][i];
+
+                    // a sequence skipping kept nodes along a weak edge takes the strongest path of strong
+                    // edges between its ends instead, with the weight of the base after it
+                    if (!path.empty() && !strong_edge(path.back(), node_id)) {
+                        std::vector<uint32_t> detour;
+                        int64_t prev = strongest_in[node_id];
+                        while (prev != -1 && rank[prev] > rank[path.back()]) {
+                            detour.push_back(prev);
+                            prev = strongest_in[prev];
+                        }
+                        if (prev == path.back()) {
+                            path.insert(path.end(), detour.rbegin(), detour.rend());
+                            weights[s].insert(weights[s].end(), detour.size(), weight);
+                        }
+                    }
+                    path.push_back(node_id);
+                    weights[s].push_back(weight);
+                }
+            }
+
+            // the pruned sequences are parts of the recreated graph, not clipped against it, and were
+            // never aligned as they are
+            std::vector<std::pair<uint32_t, uint32_t>> clips(src->paths.size(), std::make_pair(0, 0));
+            std::vector<int32_t> scores(src->paths.size(), POA_NO_SCORE);
+            add_node_paths(dst, src, paths, weights, clips, scores, false, true);
+        });
+    }
+
+    unsigned poa_graph_num_sequences(const poa_graph* g) {
+        return (unsigned) g->paths.size();
+    }
+
+    unsigned poa_graph_consensus(poa_graph* g, char* consensus, unsigned consensus_len) {
 
-        if (num_seqs == 0) {
-            return (unsigned) 0;
+        return guarded<unsigned>(0, [&]() -> unsigned {
+            if (g->graph.nodes().empty()) {
+                return (unsigned) 0;
+            }
+
+            std::string cns = g->graph.GenerateConsensus();
+            return copy_consensus(cns, consensus, consensus_len);
+        });
+    }
+
+    unsigned poa_graph_consensus_path(poa_graph* g, uint32_t* node_ids, unsigned node_ids_len) {
+
+        return guarded<unsigned>(0, [&]() -> unsigned {
+            if (g->graph.nodes().empty()) {
+                return (unsigned) 0;
+            }
+
+            g->graph.GenerateConsensus();
+            unsigned l = 0;
+            for (const auto& node: g->graph.consensus()) {
+                if (l == node_ids_len) {
+                    break;
+                }
+                node_ids[l++] = node->id;
+            }
+            return l;
+        });
+    }
+
+    unsigned poa_graph_msa_len(poa_graph* g) {
+
+        return guarded<unsigned>(0, [&]() -> unsigned {
+            if (g->graph.nodes().empty()) {
+                return (unsigned) 0;
+            }
+
+            auto msa = g->graph.GenerateMultipleSequenceAlignment(false);
+            return msa.empty() ? (unsigned) 0 : (unsigned) msa[0].size();
+        });
+    }
+
+    void poa_graph_msa(poa_graph* g, char* rows, int include_consensus) {
+
+        guarded([&] {
+            if (g->graph.nodes().empty()) {
+                return;
+            }
+
+            // spoa leaves empty sequences out of the alignment, so they are written as rows of gaps
+            auto msa = g->graph.GenerateMultipleSequenceAlignment(include_consensus != 0);
+            size_t len = msa[0].size(), next = 0;
+            for (const auto& path: g->paths) {
+                const std::string& row = path.empty() ? std::string(len, '-') : msa[next++];
+                rows = std::copy(row.begin(), row.end(), rows);
+            }
+            if (include_consensus != 0) {
+                std::copy(msa.back().begin(), msa.back().end(), rows);
+            }
+        });
+    }
+
+    unsigned poa_graph_num_nodes(const poa_graph* g) {
+        return (unsigned) g->graph.nodes().size();
+    }
+
+    void poa_graph_nodes(const poa_graph* g, uint8_t* bases, uint64_t* weights) {
+
+        const auto& nodes = g->graph.nodes();
+        for (size_t i = 0; i < nodes.size(); i++) {
+            bases[i] = g->graph.decoder(nodes[i]->code);
+            weights[i] = 0;
+        }
+        for (size_t s = 0; s < g->paths.size(); s++) {
+            for (size_t i = 0; i < g->paths[s].size(); i++) {
+                weights[g->paths[s][i]] += g->weights[s][i];
+            }
+        }
+    }
+
+    unsigned poa_graph_num_edges(const poa_graph* g) {
+        return guarded<unsigned>(0, [&]() -> unsigned {
+            return (unsigned) edge_weights(g).size();
+        });
+    }
+
+    void poa_graph_edges(const poa_graph* g, uint32_t* tails, uint32_t* heads, uint64_t* weights) {
+
+        guarded([&] {
+            size_t i = 0;
+            for (const auto& it: edge_weights(g)) {
+                tails[i] = it.first.first;
+                heads[i] = it.first.second;
+                weights[i] = it.second;
+                i++;
+            }
+        });
+    }
+
+    unsigned poa_graph_sequence_len(const poa_graph* g, unsigned seq_index) {
+        return (unsigned) g->paths[seq_index].size();
+    }
+
+    void poa_graph_sequence_path(const poa_graph* g, unsigned seq_index, uint32_t* node_ids) {
+
+        const auto& path = g->paths[seq_index];
+        for (size_t i = 0; i < path.size(); i++) {
+            node_ids[i] = path[i];
         }
+    }
+
+    void poa_graph_sequence_weights(const poa_graph* g, unsigned seq_index, uint32_t* weights) {
 
-        // populate the list of sequences
-        std::vector<std::string> sequences;
-        for (int i = 0; i < num_seqs; i++){
-            sequences.push_back((std::string) seqs[i]);
+        const auto& base_weights = g->weights[seq_index];
+        for (size_t i = 0; i < base_weights.size(); i++) {
+            weights[i] = base_weights[i];
         }
+    }
+
+    void poa_graph_sequence_clips(const poa_graph* g, unsigned seq_index, uint32_t* leading, uint32_t* trailing) {
+        *leading = g->clips[seq_index].first;
+        *trailing = g->clips[seq_index].second;
+    }
 
-        auto alignment_engine = spoa::createAlignmentEngine(static_cast<spoa::AlignmentType>(alignment_type),
-                                                            (int8_t) match_score,
-                                                            (int8_t) mismatch_score,
-                                                            (int8_t) gap_open,
-                                                            (int8_t) gap_extend);
-        auto graph = spoa::createGraph();
+    int32_t poa_graph_sequence_score(const poa_graph* g, unsigned seq_index) {
+        return g->scores[seq_index];
+    }
+
+    void poa_graph_aligned_partners(const poa_graph* g, int32_t* partners) {
 
-        // add each of the real sequences (e.g. noisy sequence reads) to the graph
-        for (const auto& it: sequences) {
-            auto alignment = (*alignment_engine)(it, graph);
-            graph->add_alignment(alignment, it);
+        for (const auto& node: g->graph.nodes()) {
+            partners[node->id] = -1;
+            for (const auto& aligned: node->aligned_nodes) {
+                if (aligned->id < node->id) {
+                    partners[node->id] = (int32_t) aligned->id;
+                }
+            }
         }
+    }
+
+    // spoa's classes on their own, for composing what poa_graph does not offer
+
+    poa_raw_engine* poa_raw_engine_new(int alignment_type, int match_score, int mismatch_score, int gap_open,
+                                       int gap_extend, int gap_open2, int gap_extend2, int simd, int ambiguity) {
 
-        // generate the consensus sequence, assign it to the allocated memory block, and return the consensus length.
-        std::string cns = graph->generate_consensus();
+        return guarded<poa_raw_engine*>(nullptr, [&]() -> poa_raw_engine* {
+            std::unique_ptr<poa_raw_engine> e(new poa_raw_engine());
+            e->engine = create_engine(alignment_type, match_score, mismatch_score, gap_open, gap_extend, gap_open2,
+                                      gap_extend2, simd, ambiguity, &e->simd);
+            return e.release();
+        });
+    }
+
+    void poa_raw_engine_free(poa_raw_engine* e) {
+        delete e;
+    }
+
+    int poa_raw_engine_simd(const poa_raw_engine* e) {
+        return e->simd;
+    }
+
+    void poa_raw_engine_prealloc(poa_raw_engine* e, unsigned max_seq_len, unsigned alphabet_size) {
+
+        guarded([&] {
+            e->engine->Prealloc(max_seq_len, (uint8_t) std::min(alphabet_size, 255u));
+        });
+    }
+
+    unsigned poa_raw_engine_align(poa_raw_engine* e, const poa_raw_graph* g, const char* seq, unsigned seq_len,
+                                  int32_t* node_ids, int32_t* seq_positions, int32_t* score) {
+
+        return guarded<unsigned>(0, [&]() -> unsigned {
+            *score = 0;
+            auto alignment = e->engine->Align(seq, seq_len, g->graph, score);
+            for (size_t i = 0; i < alignment.size(); i++) {
+                node_ids[i] = alignment[i].first;
+                seq_positions[i] = alignment[i].second;
+            }
+            return (unsigned) alignment.size();
+        });
+    }
+
+    poa_raw_graph* poa_raw_graph_new(void) {
+        return guarded<poa_raw_graph*>(nullptr, [&]() -> poa_raw_graph* {
+            return new poa_raw_graph();
+        });
+    }
+
+    void poa_raw_graph_free(poa_raw_graph* g) {
+        delete g;
+    }
+
+    void poa_raw_graph_clear(poa_raw_graph* g) {
+        g->graph.Clear();
+    }
+
+    void poa_raw_graph_add_alignment(poa_raw_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                                     const int32_t* node_ids, const int32_t* seq_positions, unsigned alignment_len) {
+
+        guarded([&] {
+            spoa::Alignment alignment;
+            alignment.reserve(alignment_len);
+            for (unsigned i = 0; i < alignment_len; i++) {
+                alignment.emplace_back(node_ids[i], seq_positions[i]);
+            }
+            if (weights == nullptr) {
+                g->graph.AddAlignment(alignment, seq, seq_len);
+            } else {
+                g->graph.AddAlignment(alignment, seq, seq_len, std::vector<uint32_t>(weights, weights + seq_len));
+            }
+        });
+    }
 
-        int l = cns.length();
-        if (l > consensus_len) {
-            l = consensus_len;
+    unsigned poa_raw_graph_num_nodes(const poa_raw_graph* g) {
+        return (unsigned) g->graph.nodes().size();
+    }
+
+    unsigned poa_raw_graph_num_edges(const poa_raw_graph* g) {
+        return (unsigned) g->graph.edges().size();
+    }
+
+    unsigned poa_raw_graph_num_sequences(const poa_raw_graph* g) {
+        return (unsigned) g->graph.sequences().size();
+    }
+
+    uint32_t poa_raw_graph_sequence_start(const poa_raw_graph* g, unsigned seq_index) {
+        return g->graph.sequences()[seq_index]->id;
+    }
+
+    char poa_raw_graph_node_base(const poa_raw_graph* g, unsigned node_id) {
+        return (char) g->graph.decoder(g->graph.nodes()[node_id]->code);
+    }
+
+    unsigned poa_raw_graph_node_coverage(const poa_raw_graph* g, unsigned node_id) {
+        return g->graph.nodes()[node_id]->Coverage();
+    }
+
+    unsigned poa_raw_graph_node_links(const poa_raw_graph* g, unsigned node_id, int kind, uint32_t* ids, int64_t* weights,
+                                      unsigned len) {
+
+        const auto& node = g->graph.nodes()[node_id];
+        if (kind == POA_RAW_ALIGNED) {
+            for (unsigned i = 0; i < len && i < node->aligned_nodes.size(); i++) {
+                ids[i] = node->aligned_nodes[i]->id;
+            }
+            return (unsigned) node->aligned_nodes.size();
         }
+        const auto& edges = kind == POA_RAW_OUT_EDGES ? node->outedges : node->inedges;
+        for (unsigned i = 0; i < len && i < edges.size(); i++) {
+            ids[i] = kind == POA_RAW_OUT_EDGES ? edges[i]->head->id : edges[i]->tail->id;
+            weights[i] = edges[i]->weight;
+        }
+        return (unsigned) edges.size();
+    }
 
-        for (int i = 0; i < l; i++){
-            consensus[i] = cns[i];
+    void poa_raw_graph_order(const poa_raw_graph* g, uint32_t* node_ids) {
+
+        const auto& order = g->graph.rank_to_node();
+        for (size_t i = 0; i < order.size(); i++) {
+            node_ids[i] = order[i]->id;
         }
+    }
+
+    unsigned poa_raw_graph_consensus(poa_raw_graph* g, uint32_t* node_ids) {
+
+        return guarded<unsigned>(0, [&]() -> unsigned {
+            g->graph.GenerateConsensus();
+            const auto& consensus = g->graph.consensus();
+            for (size_t i = 0; i < consensus.size(); i++) {
+                node_ids[i] = consensus[i]->id;
+            }
+            return (unsigned) consensus.size();
+        });
+    }
 
-        sequences.clear();
+    unsigned poa_raw_graph_msa(poa_raw_graph* g, int include_consensus, char* rows, size_t rows_len) {
 
-        return (unsigned) l;
+        return guarded<unsigned>(0, [&]() -> unsigned {
+            if (g->graph.nodes().empty()) {
+                return (unsigned) 0;
+            }
+            auto msa = g->graph.GenerateMultipleSequenceAlignment(include_consensus != 0);
+            size_t len = msa[0].size();
+            if (rows_len >= len * msa.size()) {
+                for (const auto& row: msa) {
+                    rows = std::copy(row.begin(), row.end(), rows);
+                }
+            }
+            return (unsigned) len;
+        });
     }
 }
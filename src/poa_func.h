@@ -1,18 +1,311 @@
 #ifndef POA_FUNC_H
 #define POA_FUNC_H
 
+#include <stddef.h>
+#include <stdint.h>
+
 #ifdef __cplusplus
 extern "C" {
 #endif
-unsigned poa_func(char** seqs,        // the sequences (null-terminated) to perform multiple-sequence-alignment with.
-                  int num_seqs,       // the number of sequences being multiply aligned
-                  char* consensus,    // this chunk of memory will hold the return value, the consensus of the multiple alignment
-                  int consensus_len,  // the amount of memory allocated to consensus, i.e. use the MAXIMUM length of the consensus
-                  int alignment_type, // the alignment type: 0 = local align, 1 = global align, 2 = semi-global
-                  int match_score,    // the score to give a sequence match in alignment, e.g. 5
-                  int mismatch_score, // the score to give a sequence mismatch in alignment, e.g. -4
-                  int gap_open,
-                  int gap_extend);     // the score to give a sequence gap in alignment, e.g. -8
+
+// the instruction sets the alignment engine can use. auto selects the best one the CPU supports,
+// as does any instruction set it does not support.
+#define POA_SIMD_AUTO 0
+#define POA_SIMD_SCALAR 1
+#define POA_SIMD_SSE4_1 2
+#define POA_SIMD_AVX2 3
+
+// how pairs with an IUPAC ambiguity code (N, R, Y, ...) on either side are scored. literal compares
+// the codes as any other symbol; neutral scores such pairs 0; wildcard scores them as the best of
+// the bases the codes stand for; mismatch scores them the lowest score of the engine. any but
+// literal makes the engine scalar and does not support convex gaps.
+#define POA_AMBIGUITY_LITERAL 0
+#define POA_AMBIGUITY_NEUTRAL 1
+#define POA_AMBIGUITY_WILDCARD 2
+#define POA_AMBIGUITY_MISMATCH 3
+
+// the score of a sequence that was not aligned when it was added, such as the first of a graph
+#define POA_NO_SCORE INT32_MIN
+
+// the kinds of C++ exception an entry point can catch. an entry point that caught one returns 0,
+// null or POA_ADD_FAILED, leaving the exception to be taken with poa_take_error.
+#define POA_OK 0
+#define POA_ERROR_ALLOC 1
+#define POA_ERROR_INVALID 2
+#define POA_ERROR_OTHER 3
+
+// returned by the poa_graph_add_ functions returning int when they caught an exception
+#define POA_ADD_FAILED -1
+
+// returns the kind of the last exception caught on the calling thread, POA_OK if there is none,
+// writing its message, null-terminated and cut to message_len bytes, to message. clears the error.
+int poa_take_error(char* message, size_t message_len);
+
+// the best instruction set of the alignment engine the CPU supports, never POA_SIMD_AUTO
+int poa_simd_supported(void);
+
+// called with the caller's consensus buffer and the length of the consensus once it is known,
+// returning memory for at least that many bases
+typedef char* (*poa_reserve)(void* consensus, size_t consensus_len);
+
+size_t poa_func(char** seqs,        // the sequences (null-terminated) to perform multiple-sequence-alignment with.
+                size_t num_seqs,    // the number of sequences being multiply aligned
+                void* consensus,    // the buffer passed to reserve, which will hold the return value, the consensus of the multiple alignment
+                poa_reserve reserve, // sizes consensus for the consensus; not called if there are no sequences
+                int alignment_type, // the alignment type: 0 = local align, 1 = global align, 2 = semi-global
+                int match_score,    // the score to give a sequence match in alignment, e.g. 5
+                int mismatch_score, // the score to give a sequence mismatch in alignment, e.g. -4
+                int gap_open,
+                int gap_extend,     // the score to give a sequence gap in alignment, e.g. -8
+                int gap_open2,      // the second gap pair of convex gap scores, which long gaps score by
+                int gap_extend2,    // when it scores them higher; equal to gap_open and gap_extend for affine gaps
+                int simd,           // the instruction set of the alignment engine, a POA_SIMD_ value
+                int ambiguity);     // how ambiguous bases are scored, a POA_AMBIGUITY_ value
+
+// called with the caller's buffers and the length of the consensus once it is known, pointing the
+// last four arguments at memory for at least that many bases, fractions and counts; depth and
+// agreeing may be left null
+typedef void (*poa_reserve_support)(void* buffers, size_t consensus_len, char** consensus, float** support,
+                                    unsigned** depth, unsigned** agreeing);
+
+// as poa_func, but additionally fills support with the fraction of the reads covering each
+// consensus base that agree with it, depth (unless it is null) with the number of covering reads,
+// and agreeing (unless it is null) with the number of covering reads that agree, all of them
+// sized by reserve. returns the length of the consensus.
+size_t poa_func_support(char** seqs,
+                        size_t num_seqs,
+                        void* buffers,                // the buffers passed to reserve, which will hold the consensus and its support
+                        poa_reserve_support reserve, // sizes the buffers for the consensus; not called if there are no sequences
+                        int alignment_type,
+                        int match_score,
+                        int mismatch_score,
+                        int gap_open,
+                        int gap_extend,
+                        int gap_open2,
+                        int gap_extend2,
+                        int simd,
+                        int ambiguity);
+
+// a partial order graph that sequences can be added to one at a time
+typedef struct poa_graph poa_graph;
+
+poa_graph* poa_graph_new(int alignment_type,  // the alignment type: 0 = local align, 1 = global align, 2 = semi-global
+                         int match_score,
+                         int mismatch_score,
+                         int gap_open,
+                         int gap_extend,
+                         int gap_open2,       // the second gap pair, as for poa_func
+                         int gap_extend2,
+                         int simd,            // the instruction set of the alignment engine, a POA_SIMD_ value
+                         int ambiguity);      // how ambiguous bases are scored, a POA_AMBIGUITY_ value
+
+// as poa_graph_new, but scores each pair of symbols with a substitution matrix: scores holds the
+// score of each pair of the alphabet_len symbols of alphabet, row by row, with the graph symbol as
+// the row and the sequence symbol as the column. symbols are matched ignoring case, and pairs with
+// a symbol outside the alphabet score the lowest score of the matrix, and ambiguous bases are then
+// rescored as ambiguity sets. the engine is scalar.
+poa_graph* poa_graph_new_matrix(int alignment_type,
+                                int gap_open,
+                                int gap_extend,
+                                const char* alphabet,
+                                unsigned alphabet_len,
+                                const int* scores,
+                                int ambiguity);
+
+void poa_graph_free(poa_graph* g);
+
+// the instruction set the alignment engine of the graph uses, never POA_SIMD_AUTO
+int poa_graph_simd(const poa_graph* g);
+
+// removes all sequences from the graph, keeping the buffers of its alignment engine
+void poa_graph_clear(poa_graph* g);
+
+// aligns a sequence (not necessarily null-terminated) to the graph and adds it
+void poa_graph_add(poa_graph* g, const char* seq, unsigned seq_len);
+
+// returns the score of the best alignment of a sequence to the graph under its scoring, without adding
+// the sequence; 0 if the graph is empty.
+int32_t poa_graph_alignment_score(poa_graph* g, const char* seq, unsigned seq_len);
+
+// aligns a sequence to the graph without adding it, writing the node id and sequence position of
+// each alignment column to node_ids and seq_positions, -1 marking a gap, in the order of
+// spoa::Alignment, and its score to score. both buffers must hold the number of nodes plus
+// seq_len values. returns the number of columns, 0 with score 0 if the graph is empty.
+unsigned poa_graph_align(poa_graph* g, const char* seq, unsigned seq_len, int32_t* node_ids, int32_t* seq_positions,
+                         int32_t* score);
+
+// as poa_graph_add, but leaves the graph unchanged if adding the sequence would make the graph hold
+// more than max_nodes nodes (returning 1), or if more than max_inserted of its bases would not be
+// aligned to an existing node (returning 2). the first sequence added to an empty graph is exempt
+// from max_inserted. returns 0 if the sequence was added.
+int poa_graph_add_limited(poa_graph* g, const char* seq, unsigned seq_len, unsigned max_nodes, unsigned max_inserted);
+
+// as poa_graph_add_limited, but gives each base of the sequence the weight at the same position of
+// weights (seq_len values) instead of 1, e.g. its Phred quality. weights may be null.
+int poa_graph_add_weighted(poa_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                           unsigned max_nodes, unsigned max_inserted);
+
+// as poa_graph_add_weighted, but also leaves the graph unchanged (returning 3) if the identity of the
+// sequence to the backbone, the first sequence added, is below min_identity. the identity is
+// measured on the alignment used to add the sequence and stored in identity, if not null; it is 1
+// for the backbone itself.
+int poa_graph_add_checked(poa_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                          unsigned max_nodes, unsigned max_inserted, double min_identity, double* identity);
+
+// as poa_graph_add_weighted without limits, but aligns the sequence only to the region of the graph
+// between the backbone positions begin and end (exclusive, clamped to the backbone length): the
+// nodes reachable backwards from the backbone node at end - 1 down to the one at begin. bases
+// outside the region are clipped or inserted as by the alignment type. the whole graph is used if
+// it is empty or the region is.
+int poa_graph_add_anchored(poa_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                           uint32_t begin, uint32_t end);
+
+// adds a sequence to the graph along an alignment computed by the caller, without limits: the
+// sequence position aligned to each node id, both given as alignment_len values where -1 marks a
+// gap, in the order of spoa::Alignment. weights may be null. score is recorded as the score of the
+// alignment, POA_NO_SCORE if there is none.
+void poa_graph_add_alignment(poa_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                             const int32_t* node_ids, const int32_t* seq_positions, unsigned alignment_len,
+                             int32_t score);
+
+// as poa_graph_add_alignment, but under the limits of poa_graph_add_checked, returning its codes
+int poa_graph_add_alignment_checked(poa_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                                    const int32_t* node_ids, const int32_t* seq_positions, unsigned alignment_len,
+                                    int32_t score, unsigned max_nodes, unsigned max_inserted, double min_identity,
+                                    double* identity);
+
+// adds to dst, which must be empty, the region of src between from_node and to_node: the nodes on
+// the paths from from_node to to_node with the edges between them. each sequence of src passing
+// through the region adds its fragment inside the region as a sequence of dst, in order.
+void poa_graph_add_subgraph(poa_graph* dst, const poa_graph* src, uint32_t from_node, uint32_t to_node);
+
+// adds to dst, which must be empty, the reverse complement of src: every node holds the complement
+// of its base and every edge is reversed, with weights preserved. each sequence of src is added to
+// dst as its reverse complement, in order, with the alignment score it was added with. the
+// fragments of poa_graph_add_subgraph have no score.
+void poa_graph_add_reverse_complement(poa_graph* dst, const poa_graph* src);
+
+// adds to dst, which must be empty, src without the nodes and edges fewer than min_reads sequences
+// pass through. each sequence of src is added to dst in order, with the bases of removed nodes
+// moved to the kept node aligned to them with the most reads, or dropped if there is none, and
+// with the kept nodes it skipped along a removed edge taken back in along the strongest path of
+// kept edges, if there is one. the pruned sequences have no score.
+void poa_graph_add_pruned(poa_graph* dst, const poa_graph* src, uint32_t min_reads);
+
+unsigned poa_graph_num_sequences(const poa_graph* g);
+
+// writes at most consensus_len bases of the consensus to consensus, returning the number written.
+// the consensus is never longer than the number of nodes.
+unsigned poa_graph_consensus(poa_graph* g, char* consensus, unsigned consensus_len);
+
+// writes the consensus with its support to the buffers reserve sizes, as poa_func_support does for
+// the sequences of the graph, returning the length of the consensus. reserve is not called if the
+// graph is empty.
+size_t poa_graph_consensus_support(poa_graph* g, void* buffers, poa_reserve_support reserve);
+
+// writes at most node_ids_len ids of the nodes along the consensus path to node_ids, in order,
+// returning the number written. the path never holds more ids than the number of nodes.
+unsigned poa_graph_consensus_path(poa_graph* g, uint32_t* node_ids, unsigned node_ids_len);
+
+// the number of columns of the multiple sequence alignment of the sequences in the graph
+unsigned poa_graph_msa_len(poa_graph* g);
+
+// fills rows with the rows of the multiple sequence alignment, one after another without
+// separators, using '-' for gaps. the rows are the sequences in the order they were added, followed
+// by the consensus if include_consensus is nonzero. rows must hold poa_graph_msa_len values per row.
+void poa_graph_msa(poa_graph* g, char* rows, int include_consensus);
+
+unsigned poa_graph_num_nodes(const poa_graph* g);
+
+// fills, for each node id, its base and the summed weight of the sequence bases it represents.
+// both arrays must hold poa_graph_num_nodes values.
+void poa_graph_nodes(const poa_graph* g, uint8_t* bases, uint64_t* weights);
+
+unsigned poa_graph_num_edges(const poa_graph* g);
+
+// fills the tail node, head node and weight of each edge, ordered by tail and then head.
+// each array must hold poa_graph_num_edges values.
+void poa_graph_edges(const poa_graph* g, uint32_t* tails, uint32_t* heads, uint64_t* weights);
+
+// the length of the sequence added at seq_index, i.e. the number of nodes on its path
+unsigned poa_graph_sequence_len(const poa_graph* g, unsigned seq_index);
+
+// fills node_ids with the node representing each base of the sequence added at seq_index
+void poa_graph_sequence_path(const poa_graph* g, unsigned seq_index, uint32_t* node_ids);
+
+// copies the weight of each base of the sequence added at seq_index, as given when it was added (1
+// for unweighted sequences), into weights (poa_graph_sequence_len values).
+void poa_graph_sequence_weights(const poa_graph* g, unsigned seq_index, uint32_t* weights);
+
+// the number of leading and trailing bases of the sequence added at seq_index that were not aligned
+// to a node already in the graph when it was added, e.g. those soft-clipped by a local alignment.
+// both are 0 for the first sequence of a graph.
+void poa_graph_sequence_clips(const poa_graph* g, unsigned seq_index, uint32_t* leading, uint32_t* trailing);
+
+// the score of the alignment to the graph the sequence at seq_index was added along, by the
+// scoring of the graph's alignment engine, or POA_NO_SCORE for the first sequence of a graph and
+// others added without an alignment.
+int32_t poa_graph_sequence_score(const poa_graph* g, unsigned seq_index);
+
+// writes for each node, by id, the node a base was aligned to when the node was created for it as
+// a mismatch, or -1 for a node created for an unaligned base. this is the last node aligned to it
+// with a smaller id, as spoa::Graph::AddAlignment appends the node it aligns a mismatch to after
+// that node's own aligned nodes. adding every sequence again along its path, with the bases of new
+// nodes aligned to these nodes, rebuilds the graph exactly. partners must hold poa_graph_num_nodes values.
+void poa_graph_aligned_partners(const poa_graph* g, int32_t* partners);
+
+// spoa's alignment engine and graph on their own, for callers composing their own pipelines.
+// alignments cross as node_ids and seq_positions arrays as for poa_graph_align. functions that can
+// throw return 0 or null as described for poa_take_error.
+typedef struct poa_raw_engine poa_raw_engine;
+typedef struct poa_raw_graph poa_raw_graph;
+
+// the links of a node poa_raw_graph_node_links lists
+#define POA_RAW_OUT_EDGES 0
+#define POA_RAW_IN_EDGES 1
+#define POA_RAW_ALIGNED 2
+
+// creates an alignment engine with the scores, instruction set and ambiguous base scoring of poa_graph_new
+poa_raw_engine* poa_raw_engine_new(int alignment_type, int match_score, int mismatch_score, int gap_open,
+                                   int gap_extend, int gap_open2, int gap_extend2, int simd, int ambiguity);
+void poa_raw_engine_free(poa_raw_engine* e);
+// the instruction set the engine uses, never POA_SIMD_AUTO
+int poa_raw_engine_simd(const poa_raw_engine* e);
+// allocates the buffers for sequences of up to max_seq_len bases over alphabet_size symbols
+void poa_raw_engine_prealloc(poa_raw_engine* e, unsigned max_seq_len, unsigned alphabet_size);
+// aligns a sequence to the graph, as poa_graph_align does
+unsigned poa_raw_engine_align(poa_raw_engine* e, const poa_raw_graph* g, const char* seq, unsigned seq_len,
+                              int32_t* node_ids, int32_t* seq_positions, int32_t* score);
+
+poa_raw_graph* poa_raw_graph_new(void);
+void poa_raw_graph_free(poa_raw_graph* g);
+void poa_raw_graph_clear(poa_raw_graph* g);
+// adds a sequence along an alignment, as spoa::Graph::AddAlignment does. weights (seq_len values)
+// may be null for weights of 1. an empty sequence is not added.
+void poa_raw_graph_add_alignment(poa_raw_graph* g, const char* seq, unsigned seq_len, const uint32_t* weights,
+                                 const int32_t* node_ids, const int32_t* seq_positions, unsigned alignment_len);
+unsigned poa_raw_graph_num_nodes(const poa_raw_graph* g);
+unsigned poa_raw_graph_num_edges(const poa_raw_graph* g);
+unsigned poa_raw_graph_num_sequences(const poa_raw_graph* g);
+// the id of the node of the first base of the sequence added at seq_index
+uint32_t poa_raw_graph_sequence_start(const poa_raw_graph* g, unsigned seq_index);
+char poa_raw_graph_node_base(const poa_raw_graph* g, unsigned node_id);
+// the number of sequences passing through the node
+unsigned poa_raw_graph_node_coverage(const poa_raw_graph* g, unsigned node_id);
+// writes up to len links of a node of the given POA_RAW_ kind, in the order spoa keeps them: the
+// node at the other end of each edge and its weight, or the ids of the aligned nodes, leaving
+// weights untouched. returns the number of links.
+unsigned poa_raw_graph_node_links(const poa_raw_graph* g, unsigned node_id, int kind, uint32_t* ids, int64_t* weights,
+                                  unsigned len);
+// writes the node ids in topological order, poa_raw_graph_num_nodes values
+void poa_raw_graph_order(const poa_raw_graph* g, uint32_t* node_ids);
+// generates the heaviest bundle consensus, writing its node ids (at most poa_raw_graph_num_nodes)
+// and returning their number
+unsigned poa_raw_graph_consensus(poa_raw_graph* g, uint32_t* node_ids);
+// generates the multiple sequence alignment, one row per sequence and the consensus last if
+// include_consensus is set, writing the rows one after another to rows if rows_len is enough for
+// them all. returns the row length.
+unsigned poa_raw_graph_msa(poa_raw_graph* g, int include_consensus, char* rows, size_t rows_len);
 
 #ifdef __cplusplus
 }
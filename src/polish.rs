@@ -0,0 +1,460 @@
+//! Polishing a draft assembly with windowed partial order alignment of the mapped reads.
+//!
+//! Each contig is cut into windows of `window_length` bases. Every read mapped to the contig
+//! contributes the fragment that the mapping places in each window it overlaps, and the polished
+//! window is the consensus of the draft window (added first, as a backbone) and the fragments.
+//! The window boundaries are placed in each read with the `cg:Z` CIGAR string of its mapping if
+//! present. Otherwise they are interpolated along the mapping, and refined by fitting the contig
+//! bases around each boundary to the read.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader};
+use std::fs::File;
+use std::path::Path;
+
+use crate::align::{fit, AlignOp};
+use crate::io::read_records_from_path;
+use crate::seq::reverse_complement;
+use crate::{PoaGraph, PoaParams};
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The first twelve columns of a PAF line, describing the mapping of a read to a contig.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PafRecord {
+    /// the read name
+    pub query_name: String,
+    /// the read length
+    pub query_length: usize,
+    /// the start of the mapped part of the read, 0-based, on its forward strand
+    pub query_start: usize,
+    /// the end of the mapped part of the read, excluded, on its forward strand
+    pub query_end: usize,
+    /// whether the read maps to the reverse strand of the contig
+    pub reverse: bool,
+    /// the contig name
+    pub target_name: String,
+    /// the contig length
+    pub target_length: usize,
+    /// the start of the mapped part of the contig, 0-based
+    pub target_start: usize,
+    /// the end of the mapped part of the contig, excluded
+    pub target_end: usize,
+    /// the number of matching bases in the mapping
+    pub matches: usize,
+    /// the number of aligned columns in the mapping
+    pub block_length: usize,
+    /// the mapping quality, 255 if missing
+    pub mapping_quality: u8,
+    /// the CIGAR string of the `cg:Z` tag, if present, aligning the read (reverse complemented
+    /// for reverse mappings) to the contig
+    pub cigar: Option<String>,
+}
+
+impl PafRecord {
+    /// Parses a line of a PAF file. Of the optional fields after the first twelve columns, only the
+    /// `cg:Z` CIGAR string is used.
+    pub fn parse(line: &str) -> io::Result<PafRecord> {
+        let fields: Vec<&str> = line.trim_end().split('\t').collect();
+        if fields.len() < 12 {
+            return Err(invalid_data(format!("PAF line has fewer than 12 fields: {}", line)));
+        }
+        let number = |i: usize| {
+            fields[i].parse::<usize>().map_err(|_| invalid_data(format!("PAF field {} is not a number: {}", i + 1, line)))
+        };
+        let reverse = match fields[4] {
+            "+" => false,
+            "-" => true,
+            _ => return Err(invalid_data(format!("PAF strand is neither + nor -: {}", line))),
+        };
+
+        let record = PafRecord {
+            query_name: fields[0].to_string(),
+            query_length: number(1)?,
+            query_start: number(2)?,
+            query_end: number(3)?,
+            reverse,
+            target_name: fields[5].to_string(),
+            target_length: number(6)?,
+            target_start: number(7)?,
+            target_end: number(8)?,
+            matches: number(9)?,
+            block_length: number(10)?,
+            mapping_quality: number(11)?.min(255) as u8,
+            cigar: fields[12..].iter().find_map(|field| field.strip_prefix("cg:Z:")).map(|cigar| cigar.to_string()),
+        };
+        if record.query_start > record.query_end || record.query_end > record.query_length
+            || record.target_start > record.target_end || record.target_end > record.target_length {
+            return Err(invalid_data(format!("PAF coordinates are out of range: {}", line)));
+        }
+        Ok(record)
+    }
+}
+
+/// Reads the records of a PAF file, skipping empty lines.
+pub fn read_paf<R: BufRead>(reader: R) -> io::Result<Vec<PafRecord>> {
+    let mut records = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            records.push(PafRecord::parse(&line)?);
+        }
+    }
+    Ok(records)
+}
+
+/// The settings of `polish`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PolishOptions {
+    /// the length of the contig windows polished independently
+    pub window_length: usize,
+    /// the alignment and scoring parameters of the window consensus
+    pub params: PoaParams,
+    /// the minimum mapping quality of the mappings used
+    pub min_mapping_quality: u8,
+}
+
+impl Default for PolishOptions {
+    /// Returns windows of 500 bases with semi-global alignment, as the fragments at read ends do not span their window.
+    fn default() -> PolishOptions {
+        PolishOptions {
+            window_length: 500,
+            params: PoaParams::new(2, 5, -4, -8, -6),
+            min_mapping_quality: 0,
+        }
+    }
+}
+
+impl PolishOptions {
+    /// Sets the length of the contig windows.
+    pub fn window_length(mut self, window_length: usize) -> PolishOptions {
+        self.window_length = window_length;
+        self
+    }
+
+    /// Sets the alignment and scoring parameters of the window consensus.
+    pub fn params(mut self, params: PoaParams) -> PolishOptions {
+        self.params = params;
+        self
+    }
+
+    /// Sets the minimum mapping quality of the mappings used.
+    pub fn min_mapping_quality(mut self, min_mapping_quality: u8) -> PolishOptions {
+        self.min_mapping_quality = min_mapping_quality;
+        self
+    }
+}
+
+// the number of contig bases on each side of a window boundary used to place it in a read
+const BOUNDARY_MARGIN: usize = 32;
+
+// a read oriented along the contig, with the read position of each window boundary in its mapping
+struct OrientedRead {
+    seq: Vec<u8>,
+    // (contig position, read position) pairs, increasing in both
+    points: Vec<(usize, usize)>,
+}
+
+impl OrientedRead {
+    fn new(read: &[u8], mapping: &PafRecord, draft: &[u8], window_length: usize) -> OrientedRead {
+        let seq = if mapping.reverse { reverse_complement(read) } else { read.to_vec() };
+        // reverse mappings start from the end of the read
+        let query_start = if mapping.reverse { mapping.query_length - mapping.query_end } else { mapping.query_start };
+        let query_end = query_start + mapping.query_end - mapping.query_start;
+
+        let first_boundary = (mapping.target_start / window_length + 1) * window_length;
+        let boundaries: Vec<usize> = (first_boundary..mapping.target_end).step_by(window_length).collect();
+
+        let mut points = vec![(mapping.target_start, query_start)];
+        match mapping.cigar.as_deref().and_then(|cigar| cigar_points(cigar, mapping.target_start, query_start, &boundaries)) {
+            Some(cigar_points) => points.extend(cigar_points),
+            None => {
+                for &boundary in &boundaries {
+                    let previous = points.last().unwrap().1;
+                    let q = place_boundary(&seq, draft, mapping, query_start, query_end, boundary);
+                    points.push((boundary, q.clamp(previous, query_end)));
+                }
+            }
+        }
+        points.push((mapping.target_end, query_end));
+
+        OrientedRead { seq, points }
+    }
+
+    // the part of the read the mapping places in [start, end), both of which are window boundaries
+    // or mapping ends
+    fn fragment(&self, start: usize, end: usize) -> &[u8] {
+        let position = |t: usize| self.points[self.points.binary_search_by_key(&t, |&(t, _)| t).unwrap()].1;
+        &self.seq[position(start)..position(end)]
+    }
+}
+
+// walks a CIGAR string from the mapping start, returning the read position of each boundary, or
+// None if the CIGAR string is malformed
+fn cigar_points(cigar: &str, target_start: usize, query_start: usize, boundaries: &[usize]) -> Option<Vec<(usize, usize)>> {
+    let mut points = Vec::with_capacity(boundaries.len());
+    let mut next = boundaries.iter().peekable();
+    let (mut t, mut q) = (target_start, query_start);
+    let mut length = 0usize;
+
+    for c in cigar.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            length = length.checked_mul(10)?.checked_add(digit as usize)?;
+            continue;
+        }
+        let (consumes_target, consumes_query) = match c {
+            'M' | '=' | 'X' => (true, true),
+            'I' | 'S' => (false, true),
+            'D' | 'N' => (true, false),
+            _ => return None,
+        };
+        for _ in 0..length {
+            while let Some(&boundary) = next.next_if(|&&boundary| boundary == t) {
+                points.push((boundary, q));
+            }
+            t += consumes_target as usize;
+            q += consumes_query as usize;
+        }
+        length = 0;
+    }
+    while let Some(&boundary) = next.next_if(|&&boundary| boundary == t) {
+        points.push((boundary, q));
+    }
+
+    if points.len() == boundaries.len() { Some(points) } else { None }
+}
+
+// places a window boundary in the read by interpolating along the mapping, then fitting the contig
+// bases around the boundary to the read bases around the interpolated position
+fn place_boundary(seq: &[u8], draft: &[u8], mapping: &PafRecord, query_start: usize, query_end: usize, boundary: usize) -> usize {
+    let target_span = (mapping.target_end - mapping.target_start).max(1);
+    let interpolated = query_start + (boundary - mapping.target_start) * (query_end - query_start) / target_span;
+
+    let pattern_start = boundary.saturating_sub(BOUNDARY_MARGIN).max(mapping.target_start);
+    let pattern_end = (boundary + BOUNDARY_MARGIN).min(mapping.target_end).min(draft.len());
+    let text_start = interpolated.saturating_sub(2 * BOUNDARY_MARGIN).max(query_start);
+    let text_end = (interpolated + 2 * BOUNDARY_MARGIN).min(query_end);
+    if pattern_start >= pattern_end || text_start >= text_end {
+        return interpolated;
+    }
+
+    let (offset, ops) = fit(&draft[pattern_start..pattern_end], &seq[text_start..text_end]);
+    let (mut t, mut q) = (pattern_start, text_start + offset);
+    for op in ops {
+        if t == boundary {
+            break;
+        }
+        match op {
+            AlignOp::Match | AlignOp::Mismatch => {
+                t += 1;
+                q += 1;
+            }
+            AlignOp::Insertion => t += 1,
+            AlignOp::Deletion => q += 1,
+        }
+    }
+    q
+}
+
+/// Polishes one contig with the reads mapped to it.
+///
+/// `mapped` pairs each mapping to the contig with the sequence of its read, as stored in the read
+/// file (i.e. on the forward strand of the read). Windows without mapped fragments are kept as
+/// they are.
+pub fn polish_contig(draft: &[u8], mapped: &[(&[u8], &PafRecord)], options: &PolishOptions) -> Vec<u8> {
+    let window_length = options.window_length.max(1);
+
+    // orient every read along the contig once
+    let oriented: Vec<(OrientedRead, &PafRecord)> = mapped
+        .iter()
+        .filter(|(read, mapping)| {
+            read.len() == mapping.query_length
+                && mapping.target_end <= draft.len()
+                && mapping.mapping_quality >= options.min_mapping_quality
+        })
+        .map(|&(read, mapping)| (OrientedRead::new(read, mapping, draft, window_length), mapping))
+        .collect();
+
+    let mut polished = Vec::with_capacity(draft.len());
+    let mut graph = PoaGraph::new(&options.params);
+
+    for window_start in (0..draft.len()).step_by(window_length) {
+        let window_end = (window_start + window_length).min(draft.len());
+
+        graph.clear();
+        graph.add_sequence(&draft[window_start..window_end]);
+        for (read, mapping) in &oriented {
+            let start = mapping.target_start.max(window_start);
+            let end = mapping.target_end.min(window_end);
+            if start < end {
+                let fragment = read.fragment(start, end);
+                if !fragment.is_empty() {
+                    graph.add_sequence(fragment);
+                }
+            }
+        }
+
+        if graph.num_sequences() == 1 {
+            polished.extend_from_slice(&draft[window_start..window_end]);
+        } else {
+            polished.extend(graph.consensus());
+        }
+    }
+
+    polished
+}
+
+/// Polishes every contig of a draft assembly with the reads mapped to it.
+///
+/// Mappings to unknown contigs, or of unknown reads, are ignored, and contigs without mappings
+/// are kept as they are.
+/// # Arguments
+///
+/// * `contigs` - the names and sequences of the draft contigs
+/// * `reads` - the names and sequences of the reads
+/// * `mappings` - the mappings of the reads to the contigs, e.g. from `read_paf`
+/// * `options` - the window length and consensus parameters
+///
+/// # Returns
+/// * returns the names and polished sequences of the contigs, in the order of `contigs`
+pub fn polish(
+    contigs: &[(String, Vec<u8>)],
+    reads: &[(String, Vec<u8>)],
+    mappings: &[PafRecord],
+    options: &PolishOptions
+) -> Vec<(String, Vec<u8>)> {
+
+    let reads_by_name: HashMap<&str, &[u8]> = reads.iter().map(|(name, seq)| (name.as_str(), seq.as_slice())).collect();
+
+    let mut mapped_by_contig: HashMap<&str, Vec<(&[u8], &PafRecord)>> = HashMap::new();
+    for mapping in mappings {
+        if let Some(&read) = reads_by_name.get(mapping.query_name.as_str()) {
+            mapped_by_contig.entry(mapping.target_name.as_str()).or_default().push((read, mapping));
+        }
+    }
+
+    contigs
+        .iter()
+        .map(|(name, draft)| {
+            let polished = match mapped_by_contig.get(name.as_str()) {
+                Some(mapped) => polish_contig(draft, mapped, options),
+                None => draft.clone(),
+            };
+            (name.clone(), polished)
+        })
+        .collect()
+}
+
+/// Polishes a draft assembly read from files: a FASTA draft, FASTA/FASTQ reads (both may be
+/// gzip-compressed) and a PAF of read-to-draft mappings.
+pub fn polish_from_paths<P: AsRef<Path>>(
+    draft_path: P,
+    reads_path: P,
+    paf_path: P,
+    options: &PolishOptions
+) -> io::Result<Vec<(String, Vec<u8>)>> {
+
+    let contigs = read_records_from_path(draft_path.as_ref())?;
+    let reads = read_records_from_path(reads_path.as_ref())?;
+    let mappings = read_paf(BufReader::new(File::open(paf_path)?))?;
+
+    Ok(polish(&contigs, &reads, &mappings, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::align::{align, edit_distance};
+    use crate::rng::Rng;
+
+    const BASES: &[u8] = b"ACGT";
+
+    fn random_seq(rng: &mut Rng, len: usize) -> Vec<u8> {
+        (0..len).map(|_| BASES[rng.below(4)]).collect()
+    }
+
+    // introduces substitutions, insertions and deletions at the given rate
+    fn mutate(rng: &mut Rng, seq: &[u8], per_thousand: usize) -> Vec<u8> {
+        let mut mutated = vec![];
+        for &base in seq {
+            match rng.below(1000 * 3 / per_thousand.max(1)) {
+                0 => mutated.push(BASES[rng.below(4)]),
+                1 => {}
+                2 => mutated.extend_from_slice(&[base, BASES[rng.below(4)]]),
+                _ => mutated.push(base),
+            }
+        }
+        mutated
+    }
+
+    #[test]
+    fn test_parse_paf() {
+        let line = "read1\t1000\t10\t990\t-\tctg1\t5000\t100\t1080\t900\t980\t60\ttp:A:P\tcm:i:100";
+        let record = PafRecord::parse(line).unwrap();
+
+        assert_eq!(record.query_name, "read1");
+        assert!(record.reverse);
+        assert_eq!((record.target_start, record.target_end), (100, 1080));
+        assert_eq!(record.mapping_quality, 60);
+        assert_eq!(read_paf(format!("{}\n\n{}\n", line, line).as_bytes()).unwrap().len(), 2);
+        assert!(PafRecord::parse("read1\t1000\t10").is_err());
+        assert!(PafRecord::parse(&line.replace("\t-\t", "\t*\t")).is_err());
+    }
+
+    #[test]
+    fn test_cigar_points() {
+        // 4 matches, 2 inserted read bases, 3 matches, 1 deleted contig base, 2 matches; bases
+        // inserted at a boundary go to the window after it
+        assert_eq!(cigar_points("4M2I3M1D2M", 10, 0, &[12, 14, 17, 18]), Some(vec![(12, 2), (14, 4), (17, 9), (18, 9)]));
+        assert_eq!(cigar_points("4M", 10, 0, &[20]), None);
+        assert_eq!(cigar_points("4Q", 10, 0, &[]), None);
+    }
+
+    #[test]
+    fn test_fragments_follow_orientation() {
+        let draft = b"GGGGGGGGGGAACCGGTTGG";
+        let forward = PafRecord::parse("r\t8\t0\t8\t+\tc\t20\t10\t18\t8\t8\t60\tcg:Z:8M").unwrap();
+        let reverse = PafRecord { reverse: true, ..forward.clone() };
+
+        let read = OrientedRead::new(b"AACCGGTT", &forward, draft, 4);
+        assert_eq!(read.fragment(12, 16), b"CCGG");
+        let read = OrientedRead::new(&reverse_complement(b"AACCGGTT"), &reverse, draft, 4);
+        assert_eq!(read.fragment(10, 12), b"AA");
+
+        // without a CIGAR string, the boundaries are found around the interpolated positions
+        let shifted = PafRecord { cigar: None, target_start: 8, target_end: 18, query_length: 10, query_end: 10, ..forward.clone() };
+        let read = OrientedRead::new(b"GGAACCGTTT", &shifted, draft, 4);
+        assert_eq!(read.fragment(12, 16), b"CCG");
+    }
+
+    #[test]
+    fn test_polishing_reduces_errors() {
+        let mut rng = Rng::new(5);
+        let truth = random_seq(&mut rng, 1200);
+        let draft = mutate(&mut rng, &truth, 30);
+
+        // reads with a low error rate from both strands, mapped along the whole draft
+        let mut reads = vec![];
+        let mut mappings = vec![];
+        for i in 0..12 {
+            let read = mutate(&mut rng, &truth, 5);
+            let reverse = i % 2 == 1;
+            let line = format!(
+                "read{}\t{}\t0\t{}\t{}\tctg\t{}\t0\t{}\t0\t0\t60",
+                i, read.len(), read.len(), if reverse { "-" } else { "+" }, draft.len(), draft.len()
+            );
+            mappings.push(PafRecord::parse(&line).unwrap());
+            reads.push((format!("read{}", i), if reverse { reverse_complement(&read) } else { read }));
+        }
+
+        let contigs = vec![("ctg".to_string(), draft.clone()), ("unmapped".to_string(), b"ACGT".to_vec())];
+        let polished = polish(&contigs, &reads, &mappings, &PolishOptions::default().window_length(300));
+
+        assert_eq!(polished[1], contigs[1]);
+        let before = edit_distance(&align(&draft, &truth));
+        let after = edit_distance(&align(&polished[0].1, &truth));
+        assert!(after * 4 < before, "polishing left {} of {} errors", after, before);
+    }
+}
@@ -0,0 +1,18 @@
+//! The types and functions most programs need, for glob import.
+//!
+//! ```
+//!     use rust_spoa::prelude::*;
+//!
+//!     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+//!     graph.add_sequence(b"AATGCCCGTT");
+//!     assert_eq!(graph.consensus(), b"AATGCCCGTT".to_vec());
+//! ```
+
+#[allow(deprecated)]
+pub use crate::consensus::{
+    poa_consensus, poa_consensus_unbounded, try_poa_consensus, AlignmentType, Consensus, ConsensusExt, PoaError, poa_consensus_of_groups, poa_consensus_outcome, poa_consensus_with_report, poa_consensus_with_support, ConsensusOutcome, ConsensusReport,
+    group_consensus_by, GroupFlush, GroupOptions, GrowthLimits, PoaParams, RejectReason, SeqRecord, SmallGroups,
+};
+pub use crate::graph::PoaGraph;
+pub use crate::io::{poa_consensus_from_path, write_fasta_record, write_fastq_record, ConsensusAnnotations};
+pub use crate::msa::poa_msa;
@@ -0,0 +1,169 @@
+//! Reporting the progress of building a graph, e.g. to drive a progress bar.
+
+use std::time::{Duration, Instant};
+
+use crate::{try_consensus_until, Alphabet, PoaError, PoaGraph, PoaParams};
+
+/// The progress of a job after one of its sequences was aligned and added to the graph.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{PoaGraph, PoaParams};
+///
+///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+///     let mut events = vec![];
+///     graph.add_sequences_with_progress(&["AATGCCCGTT", "AATGCCGTT"], |event| events.push(event));
+///
+///     assert_eq!(events.len(), 2);
+///     assert_eq!((events[1].sequences_processed, events[1].total_sequences), (2, 2));
+///     assert_eq!(events[1].num_nodes, graph.num_nodes());
+///     assert!(events[1].throughput() >= 0.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgressEvent {
+    /// the number of sequences processed so far, including the one just added
+    pub sequences_processed: usize,
+    /// the number of sequences of the job
+    pub total_sequences: usize,
+    /// the number of nodes of the graph after adding the sequence
+    pub num_nodes: usize,
+    /// the time since the job started
+    pub elapsed: Duration,
+}
+
+impl ProgressEvent {
+    /// Returns the fraction of the sequences processed, between 0 and 1 (1 for a job without any).
+    pub fn fraction(&self) -> f64 {
+        if self.total_sequences == 0 {
+            1.0
+        } else {
+            self.sequences_processed as f64 / self.total_sequences as f64
+        }
+    }
+
+    /// Returns the number of sequences processed per second, 0 before any time has passed.
+    pub fn throughput(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            0.0
+        } else {
+            self.sequences_processed as f64 / seconds
+        }
+    }
+}
+
+// calls a progress callback with the state of a graph, timing from the start of a job
+pub(crate) struct Progress<'a> {
+    callback: &'a mut dyn FnMut(ProgressEvent),
+    total_sequences: usize,
+    start: Instant,
+}
+
+impl<'a> Progress<'a> {
+    pub(crate) fn new(callback: &'a mut dyn FnMut(ProgressEvent), total_sequences: usize) -> Progress<'a> {
+        Progress { callback, total_sequences, start: Instant::now() }
+    }
+
+    // reports that sequences_processed sequences were processed, the last of them just now
+    pub(crate) fn report(&mut self, sequences_processed: usize, graph: &PoaGraph) {
+        (self.callback)(ProgressEvent {
+            sequences_processed,
+            total_sequences: self.total_sequences,
+            num_nodes: graph.num_nodes(),
+            elapsed: self.start.elapsed(),
+        });
+    }
+}
+
+impl PoaGraph {
+    /// Aligns the sequences to the graph and adds them in order, calling `progress` after each one.
+    ///
+    /// # Arguments
+    ///
+    /// * `seqs` - the sequences to add, without null terminators
+    /// * `progress` - called after each sequence is added, with the number added so far, the size
+    ///   of the graph and the time since the first one started
+    ///
+    /// # Panics
+    /// * panics if the native library fails, as `add_sequence` does
+    pub fn add_sequences_with_progress<T: AsRef<[u8]>, F: FnMut(ProgressEvent)>(&mut self, seqs: &[T], mut progress: F) {
+        let mut progress = Progress::new(&mut progress, seqs.len());
+        for (index, seq) in seqs.iter().enumerate() {
+            self.add_sequence(seq.as_ref());
+            progress.report(index + 1, self);
+        }
+    }
+}
+
+/// Generates a consensus sequence like `try_poa_consensus`, calling `progress` after each read.
+///
+/// Reads skipped for being empty are reported as processed too, so the last event has
+/// `sequences_processed == total_sequences` unless the job fails before.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `progress` - called after each read is aligned, e.g. to advance a progress bar or log
+///   throughput
+///
+/// # Returns
+/// * returns the consensus of the reads, or the errors of `try_poa_consensus`
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{try_poa_consensus_with_progress, PoaParams};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCGTT\0".to_vec(), b"AATGCCCGAT\0".to_vec()];
+///     let mut processed = vec![];
+///     let consensus = try_poa_consensus_with_progress(&seqs, 0, &PoaParams::new(1, 5, -4, -3, -1), |event| {
+///         processed.push(event.sequences_processed);
+///     });
+///
+///     assert_eq!(consensus, Ok(b"AATGCCCGTT".to_vec()));
+///     assert_eq!(processed, vec![1, 2, 3]);
+/// ```
+pub fn try_poa_consensus_with_progress<T: AsRef<[u8]>, F: FnMut(ProgressEvent)>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    mut progress: F,
+) -> Result<Vec<u8>, PoaError> {
+    let mut progress = Progress::new(&mut progress, seqs.len());
+    try_consensus_until(seqs, consensus_max_length, params, &Alphabet::default(), None, None, Some(&mut progress))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_events() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let seqs = [&b"AATGCCCGTT\0"[..], b"\0", b"AATGCCGTT\0", b"AATGCCCGAT\0"];
+        let mut events = vec![];
+        let consensus = try_poa_consensus_with_progress(&seqs, 0, &params, |event| events.push(event));
+        assert_eq!(consensus, crate::try_poa_consensus(&seqs, 0, &params));
+
+        // the empty read is processed without growing the graph
+        assert_eq!(events.iter().map(|event| event.sequences_processed).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert!(events.iter().all(|event| event.total_sequences == 4));
+        assert_eq!((events[0].num_nodes, events[1].num_nodes), (10, 10));
+        assert!(events[3].num_nodes > 10);
+        assert!(events.windows(2).all(|pair| pair[0].elapsed <= pair[1].elapsed));
+        assert_eq!(events[1].fraction(), 0.5);
+
+        // a failing read stops the events at the reads before it
+        events.clear();
+        let invalid = [&b"AATGCCCGTT\0"[..], b"AATG"];
+        assert!(try_poa_consensus_with_progress(&invalid, 0, &params, |event| events.push(event)).is_err());
+        assert_eq!(events.len(), 1);
+
+        let event = ProgressEvent { sequences_processed: 0, total_sequences: 0, num_nodes: 0, elapsed: Duration::from_secs(0) };
+        assert_eq!((event.fraction(), event.throughput()), (1.0, 0.0));
+        let event = ProgressEvent { sequences_processed: 30, total_sequences: 60, num_nodes: 100, elapsed: Duration::from_secs(2) };
+        assert_eq!((event.fraction(), event.throughput()), (0.5, 15.0));
+    }
+}
@@ -0,0 +1,117 @@
+//! Consensus of protein reads, with amino acids checked and scored by BLOSUM62, and the operations
+//! that only make sense for nucleotides refused.
+
+use crate::{try_consensus_until, Alphabet, Matrix, PoaError, PoaGraph, PoaParams};
+
+impl PoaGraph {
+    /// Creates an empty graph aligning protein reads, scoring amino acids by BLOSUM62 in place of
+    /// the match and mismatch scores of `params`, as by `with_matrix`.
+    ///
+    /// `PoaParams::protein` holds gap scores suited to BLOSUM62.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::protein(&PoaParams::protein());
+    ///     for seq in ["MKTAYIAKQR", "MKTAYIAKQR", "MKSAYIAKQR"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///     assert!(graph.is_protein());
+    ///     assert_eq!(graph.consensus(), b"MKTAYIAKQR".to_vec());
+    /// ```
+    pub fn protein(params: &PoaParams) -> PoaGraph {
+        PoaGraph::with_matrix(params, &Matrix::Blosum62.into())
+    }
+
+    /// Returns whether the graph aligns protein reads: whether it has a substitution matrix scoring
+    /// symbols other than the IUPAC nucleotide codes, as BLOSUM62 and PAM250 do.
+    ///
+    /// Protein graphs have no reverse complement, so `reverse_complement` and
+    /// `add_sequence_oriented` panic on them.
+    pub fn is_protein(&self) -> bool {
+        let nucleotides = Alphabet::iupac();
+        matches!(self.matrix(), Some(matrix) if matrix.alphabet().iter().any(|&symbol| !nucleotides.contains(symbol)))
+    }
+}
+
+/// Generates the consensus of protein reads, like `try_poa_consensus` but with reads restricted to
+/// `Alphabet::amino_acids` and aligned by BLOSUM62, as by `PoaGraph::protein`.
+///
+/// The match and mismatch scores of `params` are replaced by BLOSUM62; its homopolymer gap scores,
+/// meant for the runs of nucleotide reads, are refused rather than silently ignored.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and gap scores, e.g. `PoaParams::protein()`
+///
+/// # Returns
+/// * returns the consensus of the reads, the errors of `try_poa_consensus`, with
+///   `PoaError::InvalidByte` for the first byte that is not an amino acid, or
+///   `PoaError::InvalidScores` if `params` has homopolymer gap scores
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{try_poa_consensus_protein, PoaError, PoaParams};
+///
+///     let seqs = vec![b"MKTAYIAKQR\0".to_vec(), b"MKTAYIAKQR\0".to_vec(), b"MKSAYIAQR\0".to_vec()];
+///     assert_eq!(try_poa_consensus_protein(&seqs, 0, &PoaParams::protein()), Ok(b"MKTAYIAKQR".to_vec()));
+///
+///     let seqs = vec![b"MKTAYIAKQR\0".to_vec(), b"MKTAY1AKQR\0".to_vec()];
+///     assert_eq!(try_poa_consensus_protein(&seqs, 0, &PoaParams::protein()), Err(PoaError::InvalidByte { seq_index: 1, pos: 5, byte: b'1' }));
+/// ```
+pub fn try_poa_consensus_protein<T: AsRef<[u8]>>(seqs: &[T], consensus_max_length: usize, params: &PoaParams) -> Result<Vec<u8>, PoaError> {
+    if params.homopolymer_gaps.is_some() {
+        return Err(PoaError::InvalidScores("homopolymer gap scores apply to nucleotide reads only".to_string()));
+    }
+    try_consensus_until(seqs, consensus_max_length, params, &Alphabet::amino_acids(), Some(&Matrix::Blosum62.into()), None, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HomopolymerGaps, SubstitutionMatrix};
+
+    #[test]
+    fn test_protein_consensus() {
+        let params = PoaParams::protein();
+        let seqs = ["MKTAYIAKQRQISFVKSHFSRQ\0", "MKTAYIAKQRQISFVKSHFSRQ\0", "MKTAYLAKQRQISFVKSHFSRQ\0", "\0", "MKTAYIAKQRQISFVKSHFSRQ\0"];
+        assert_eq!(try_poa_consensus_protein(&seqs, 0, &params), Ok(b"MKTAYIAKQRQISFVKSHFSRQ".to_vec()));
+        assert_eq!(try_poa_consensus_protein(&seqs, 10, &params), Err(PoaError::Truncated { len: 22, max_len: 10 }));
+
+        // lower case and the ambiguity codes are amino acids, but selenocysteine is not in BLOSUM62
+        assert!(try_poa_consensus_protein(&["mktBZX*\0"], 0, &params).is_ok());
+        assert_eq!(try_poa_consensus_protein(&["MKUA\0"], 0, &params), Err(PoaError::InvalidByte { seq_index: 0, pos: 2, byte: b'U' }));
+        assert_eq!(try_poa_consensus_protein(&["\0"], 0, &params), Err(PoaError::EmptyInput));
+        assert!(matches!(
+            try_poa_consensus_protein(&["MKTA\0"], 0, &params.homopolymer_gaps(HomopolymerGaps::new(3, -2, -1))),
+            Err(PoaError::InvalidScores(_))
+        ));
+    }
+
+    #[test]
+    fn test_is_protein() {
+        assert!(PoaGraph::protein(&PoaParams::protein()).is_protein());
+        assert!(PoaGraph::with_matrix(&PoaParams::protein(), &Matrix::Pam250.into()).is_protein());
+        assert!(!PoaGraph::new(&PoaParams::protein()).is_protein());
+        let dna = SubstitutionMatrix::uniform(b"ACGTN", 5, -4).unwrap();
+        assert!(!PoaGraph::with_matrix(&PoaParams::dna(), &dna).is_protein());
+    }
+
+    #[test]
+    #[should_panic(expected = "protein reads have no reverse complement")]
+    fn test_no_reverse_complement() {
+        let mut graph = PoaGraph::protein(&PoaParams::protein());
+        graph.add_sequence(b"MKTAYIAKQR");
+        graph.reverse_complement();
+    }
+
+    #[test]
+    #[should_panic(expected = "protein reads have no reverse complement")]
+    fn test_no_orientation() {
+        PoaGraph::protein(&PoaParams::protein()).add_sequence_oriented(b"MKTAYIAKQR");
+    }
+}
@@ -0,0 +1,351 @@
+//! The native interface of poa_func.h implemented in Rust, for the `pure-rust` feature.
+//!
+//! The functions here take the place of the C++ entry points, with the same names and arguments,
+//! so the rest of the crate calls them just as it calls the C++ library. They port SPOA's graph,
+//! its scalar alignment engine and poa_func.cpp: the alignments, scores and consensuses are those
+//! of the scalar native engine, which the SIMD engines agree with, only slower.
+
+use std::cell::RefCell;
+use std::os::raw::c_void;
+
+use self::engine::{AlignmentType, Engine, Subtype};
+use self::graph::Graph;
+use crate::ReserveSupport;
+
+mod engine;
+mod graph;
+pub(crate) mod raw;
+pub(crate) mod reads;
+
+// POA_SIMD_SCALAR, the instruction set of the only engine
+const SIMD_SCALAR: i32 = 1;
+
+// the POA_AMBIGUITY_ values of poa_func.h
+const AMBIGUITY_LITERAL: i32 = 0;
+const AMBIGUITY_NEUTRAL: i32 = 1;
+const AMBIGUITY_WILDCARD: i32 = 2;
+const AMBIGUITY_MISMATCH: i32 = 3;
+
+// POA_ERROR_INVALID, the kind of error of an invalid argument
+const ERROR_INVALID: i32 = 2;
+
+// an error an entry point reports through poa_take_error, in place of a C++ exception: its
+// POA_ERROR_ kind and message
+#[derive(Clone, Debug)]
+pub(crate) struct Exception {
+    kind: i32,
+    message: String,
+}
+
+// an error of an invalid argument, as std::invalid_argument
+pub(crate) fn invalid(message: &str) -> Exception {
+    Exception { kind: ERROR_INVALID, message: message.to_string() }
+}
+
+thread_local! {
+    // the last error of an entry point on this thread, taken by poa_take_error
+    static LAST_ERROR: RefCell<Option<Exception>> = const { RefCell::new(None) };
+}
+
+// runs the body of an entry point, returning fallback and keeping the error if it fails
+fn guarded<T, F: FnOnce() -> Result<T, Exception>>(fallback: T, body: F) -> T {
+    body().unwrap_or_else(|error| {
+        LAST_ERROR.with(|last| *last.borrow_mut() = Some(error));
+        fallback
+    })
+}
+
+// the IUPAC ambiguity codes, and the bases each of them stands for in either case
+const AMBIGUITY_CODES: &[u8] = b"RYSWKMBDHVN";
+const UPPER_BASES: [&[u8]; 11] = [b"AG", b"CT", b"CG", b"AT", b"GT", b"AC", b"CGT", b"AGT", b"ACT", b"ACG", b"ACGT"];
+const LOWER_BASES: [&[u8]; 11] = [b"ag", b"ct", b"cg", b"at", b"gt", b"ac", b"cgt", b"agt", b"act", b"acg", b"acgt"];
+
+// the bases an IUPAC ambiguity code stands for, in its case, or None for any other symbol
+fn ambiguity_bases(symbol: u8) -> Option<&'static [u8]> {
+    let i = AMBIGUITY_CODES.iter().position(|&code| code == symbol.to_ascii_uppercase())?;
+    Some(if symbol.is_ascii_lowercase() { LOWER_BASES[i] } else { UPPER_BASES[i] })
+}
+
+// the table of a match and a mismatch score, comparing bytes as the native engines do
+fn score_table(m: i8, n: i8) -> Vec<i32> {
+    let mut table = vec![n as i32; 256 * 256];
+    for a in 0..256 {
+        table[a * 256 + a] = m as i32;
+    }
+    table
+}
+
+// the table of a substitution matrix: scores holds the score of each pair of the symbols of
+// alphabet, row by row. symbols are matched ignoring case, and pairs with a symbol outside the
+// alphabet score the lowest score of the matrix.
+fn matrix_table(alphabet: &[u8], scores: &[i32]) -> Vec<i32> {
+    let lowest = scores.iter().cloned().fold(0, i32::min);
+    let mut table = vec![lowest; 256 * 256];
+    for (a, &row) in alphabet.iter().enumerate() {
+        for (b, &column) in alphabet.iter().enumerate() {
+            let score = scores[a * alphabet.len() + b];
+            for row in [row.to_ascii_lowercase(), row.to_ascii_uppercase()].iter() {
+                for column in [column.to_ascii_lowercase(), column.to_ascii_uppercase()].iter() {
+                    table[*row as usize * 256 + *column as usize] = score;
+                }
+            }
+        }
+    }
+    table
+}
+
+// rescores the pairs of a substitution table with an ambiguity code on either side: 0 for
+// neutral, the best score of the bases the codes stand for for wildcard, and the lowest score of
+// the table for mismatch
+fn apply_ambiguity(table: &mut [i32], ambiguity: i32) -> Result<(), Exception> {
+    if ambiguity == AMBIGUITY_LITERAL {
+        return Ok(());
+    }
+    if !(AMBIGUITY_LITERAL..=AMBIGUITY_MISMATCH).contains(&ambiguity) {
+        return Err(invalid("[poa_func] error: invalid ambiguous base scoring!"));
+    }
+    let scores = table.to_vec();
+    let lowest = scores.iter().cloned().fold(i32::MAX, i32::min);
+    for a in 0..=u8::MAX {
+        for b in 0..=u8::MAX {
+            let (row, column) = (ambiguity_bases(a), ambiguity_bases(b));
+            if row.is_none() && column.is_none() {
+                continue;
+            }
+            let mut score = if ambiguity == AMBIGUITY_NEUTRAL { 0 } else { lowest };
+            if ambiguity == AMBIGUITY_WILDCARD {
+                for &r in row.unwrap_or(&[a]) {
+                    for &c in column.unwrap_or(&[b]) {
+                        score = score.max(scores[r as usize * 256 + c as usize]);
+                    }
+                }
+            }
+            table[a as usize * 256 + b as usize] = score;
+        }
+    }
+    Ok(())
+}
+
+// the alignment type of its POA value, checking it
+fn alignment_type(alignment_type: i32) -> Result<AlignmentType, Exception> {
+    match alignment_type {
+        0 => Ok(AlignmentType::Sw),
+        1 => Ok(AlignmentType::Nw),
+        2 => Ok(AlignmentType::Ov),
+        _ => Err(invalid("[poa_func] error: invalid alignment type!")),
+    }
+}
+
+// creates an alignment engine as spoa::AlignmentEngine::Create does. there is only the scalar
+// engine, so it is the instruction set selected for any simd, as the second value
+#[allow(clippy::too_many_arguments)]
+fn create_engine(
+    alignment_type: i32,
+    match_score: i32,
+    mismatch_score: i32,
+    gap_open: i32,
+    gap_extend: i32,
+    gap_open2: i32,
+    gap_extend2: i32,
+    _simd: i32,
+    ambiguity: i32,
+) -> Result<(Engine, i32), Exception> {
+    let alignment_type = self::alignment_type(alignment_type)?;
+    if gap_open > 0 || gap_extend > 0 || gap_open2 > 0 || gap_extend2 > 0 {
+        return Err(invalid("[poa_func] error: gap penalties must be non-positive!"));
+    }
+    let (m, n, g, mut e) = (match_score as i8, mismatch_score as i8, gap_open as i8, gap_extend as i8);
+    let (mut q, mut c) = (gap_open2 as i8, gap_extend2 as i8);
+    // as spoa::AlignmentEngine::Create, the second pair makes gaps convex only if it opens more
+    // expensively and extends more cheaply than the first
+    let subtype = if g >= e {
+        Subtype::Linear
+    } else if g <= q || e >= c {
+        Subtype::Affine
+    } else {
+        Subtype::Convex
+    };
+    if subtype == Subtype::Linear {
+        e = g;
+    }
+    if subtype != Subtype::Convex {
+        q = g;
+        c = e;
+    }
+    if ambiguity != AMBIGUITY_LITERAL {
+        if subtype == Subtype::Convex {
+            return Err(invalid("[poa_func] error: ambiguous base scoring does not support convex gaps!"));
+        }
+        let mut table = score_table(m, n);
+        apply_ambiguity(&mut table, ambiguity)?;
+        return Ok((Engine::matrix(alignment_type, subtype, g, e, table), SIMD_SCALAR));
+    }
+    Ok((Engine::sisd(alignment_type, subtype, m, n, g, e, q, c), SIMD_SCALAR))
+}
+
+// the sequence of the null-terminated string at sequence
+unsafe fn c_str<'a>(sequence: *const u8) -> &'a [u8] {
+    let mut len = 0;
+    while *sequence.add(len) != 0 {
+        len += 1;
+    }
+    std::slice::from_raw_parts(sequence, len)
+}
+
+// the bases of a sequence of seq_len bases at seq, which may be null if it is empty
+unsafe fn bases<'a>(seq: *const u8, seq_len: u32) -> &'a [u8] {
+    if seq_len == 0 {
+        return &[];
+    }
+    std::slice::from_raw_parts(seq, seq_len as usize)
+}
+
+// aligns each of the null-terminated sequences to a new graph, in order, and returns the graph
+#[allow(clippy::too_many_arguments)]
+unsafe fn build_graph(
+    seqs: *const *const u8,
+    num_seqs: usize,
+    alignment_type: i32,
+    match_score: i32,
+    mismatch_score: i32,
+    gap_open: i32,
+    gap_extend: i32,
+    gap_open2: i32,
+    gap_extend2: i32,
+    simd: i32,
+    ambiguity: i32,
+) -> Result<Graph, Exception> {
+    let (engine, _) = create_engine(alignment_type, match_score, mismatch_score, gap_open, gap_extend, gap_open2, gap_extend2, simd, ambiguity)?;
+    let mut graph = Graph::default();
+    for i in 0..num_seqs {
+        let sequence = c_str(*seqs.add(i));
+        let (alignment, _) = engine.align(sequence, &graph)?;
+        graph.add_alignment(&alignment, sequence, &vec![1; sequence.len()])?;
+    }
+    Ok(graph)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn poa_func(
+    seqs: *const *const u8,
+    num_seqs: usize,
+    consensus: *mut c_void,
+    reserve: extern "C" fn(*mut c_void, usize) -> *mut u8,
+    alignment_type: i32,
+    match_score: i32,
+    mismatch_score: i32,
+    gap_open: i32,
+    gap_extend: i32,
+    gap_open2: i32,
+    gap_extend2: i32,
+    simd: i32,
+    ambiguity: i32,
+) -> usize {
+    guarded(0, || {
+        if num_seqs == 0 {
+            return Ok(0);
+        }
+        let mut graph = build_graph(seqs, num_seqs, alignment_type, match_score, mismatch_score, gap_open, gap_extend, gap_open2, gap_extend2, simd, ambiguity)?;
+
+        // generate the consensus sequence, have the caller allocate memory of its length, and copy it there
+        let cns = graph.generate_consensus();
+        let buffer = reserve(consensus, cns.len());
+        std::ptr::copy_nonoverlapping(cns.as_ptr(), buffer, cns.len());
+        Ok(cns.len())
+    })
+}
+
+// writes the consensus of the graph to the buffers reserve sizes for it, with the agreeing fraction,
+// the number of reads covering and the number of reads agreeing with each base, as
+// poa_func_support does. returns the length of the consensus.
+unsafe fn consensus_support(graph: &mut Graph, buffers: *mut c_void, reserve: ReserveSupport) -> usize {
+    // the summary holds, for each consensus column, the number of reads carrying each symbol
+    // code followed by the number of reads with a deletion at that column
+    let (cns, summary) = graph.generate_consensus_summary();
+
+    // have the caller allocate the buffers of its length, and copy the consensus there
+    let len = cns.len();
+    let (mut consensus, mut support, mut depth, mut agreeing) =
+        (std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null_mut());
+    reserve(buffers, len, &mut consensus, &mut support, &mut depth, &mut agreeing);
+    std::ptr::copy_nonoverlapping(cns.as_ptr(), consensus, len);
+
+    for (i, &base) in cns.iter().enumerate() {
+        let covering: u32 = (0..=graph.num_codes() as usize).map(|code| summary[code * len + i]).sum();
+        let agreeing_reads = summary[graph.coder(base) as usize * len + i];
+
+        *support.add(i) = if covering == 0 { 0.0 } else { agreeing_reads as f32 / covering as f32 };
+        if !depth.is_null() {
+            *depth.add(i) = covering;
+        }
+        if !agreeing.is_null() {
+            *agreeing.add(i) = agreeing_reads;
+        }
+    }
+    len
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn poa_func_support(
+    seqs: *const *const u8,
+    num_seqs: usize,
+    buffers: *mut c_void,
+    reserve: ReserveSupport,
+    alignment_type: i32,
+    match_score: i32,
+    mismatch_score: i32,
+    gap_open: i32,
+    gap_extend: i32,
+    gap_open2: i32,
+    gap_extend2: i32,
+    simd: i32,
+    ambiguity: i32,
+) -> usize {
+    guarded(0, || {
+        if num_seqs == 0 {
+            return Ok(0);
+        }
+        let mut graph = build_graph(seqs, num_seqs, alignment_type, match_score, mismatch_score, gap_open, gap_extend, gap_open2, gap_extend2, simd, ambiguity)?;
+
+        Ok(consensus_support(&mut graph, buffers, reserve))
+    })
+}
+
+pub(crate) unsafe fn poa_take_error(message: *mut u8, message_len: usize) -> i32 {
+    let error = LAST_ERROR.with(|last| last.borrow_mut().take());
+    if message_len > 0 {
+        let text = error.as_ref().map_or(&b""[..], |error| error.message.as_bytes());
+        let len = text.len().min(message_len - 1);
+        std::ptr::copy_nonoverlapping(text.as_ptr(), message, len);
+        *message.add(len) = 0;
+    }
+    error.map_or(0, |error| error.kind)
+}
+
+pub(crate) unsafe fn poa_simd_supported() -> i32 {
+    SIMD_SCALAR
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{try_poa_consensus, PoaParams, SimdBackend};
+
+    #[test]
+    fn test_consensus() {
+        let reads = ["AATGCCCGTT\0", "AATGCCGTT\0", "AATGCCCGTT\0", "AAGCCCGTT\0"];
+        assert_eq!(try_poa_consensus(&reads, 0, &PoaParams::new(1, 5, -4, -3, -1)), Ok(b"AATGCCCGTT".to_vec()));
+        assert_eq!(SimdBackend::detect(), SimdBackend::Scalar);
+    }
+
+    #[test]
+    fn test_errors() {
+        // an invalid argument is reported as the C++ library reports the exception
+        let graph = unsafe { super::reads::poa_graph_new(3, 5, -4, -8, -6, -8, -6, 0, 0) };
+        assert!(graph.is_null());
+        let mut message = [0u8; 64];
+        assert_eq!(unsafe { super::poa_take_error(message.as_mut_ptr(), message.len()) }, super::ERROR_INVALID);
+        assert!(message.starts_with(b"[poa_func] error: invalid alignment type!\0"));
+        assert_eq!(unsafe { super::poa_take_error(message.as_mut_ptr(), message.len()) }, 0);
+        assert_eq!(message[0], 0);
+    }
+}
@@ -0,0 +1,720 @@
+//! Ports of the scalar alignment engines: `spoa::SisdAlignmentEngine`, scoring pairs of symbols with
+//! a match and a mismatch score, and the substitution matrix engine of poa_func.cpp.
+
+use super::graph::{Alignment, Graph};
+use super::{invalid, Exception};
+
+// the score of unreachable cells, as in the native alignment engine
+const NEGATIVE_INFINITY: i32 = i32::MIN + 1024;
+
+// the alignment modes of spoa::AlignmentType
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AlignmentType {
+    // Smith-Waterman
+    Sw,
+    // Needleman-Wunsch
+    Nw,
+    // overlap
+    Ov,
+}
+
+// the gap models of spoa::AlignmentSubtype
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Subtype {
+    // g * i
+    Linear,
+    // g + (i - 1) * e
+    Affine,
+    // min(g + (i - 1) * e, q + (i - 1) * c)
+    Convex,
+}
+
+// an alignment engine with its scores, which spoa keeps as 8-bit integers
+#[derive(Clone, Debug)]
+pub(crate) struct Engine {
+    alignment_type: AlignmentType,
+    subtype: Subtype,
+    m: i32,
+    n: i32,
+    g: i32,
+    e: i32,
+    q: i32,
+    c: i32,
+    // the score of each pair of bytes, the graph symbol as the row and the sequence symbol as the
+    // column, for the substitution matrix engine
+    table: Option<Vec<i32>>,
+}
+
+impl Engine {
+    // the engine of spoa::SisdAlignmentEngine::Create
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn sisd(alignment_type: AlignmentType, subtype: Subtype, m: i8, n: i8, g: i8, e: i8, q: i8, c: i8) -> Engine {
+        Engine {
+            alignment_type,
+            subtype,
+            m: m as i32,
+            n: n as i32,
+            g: g as i32,
+            e: e as i32,
+            q: q as i32,
+            c: c as i32,
+            table: None,
+        }
+    }
+
+    // the engine scoring pairs with a 256 x 256 table, with linear gaps scored as affine gaps whose
+    // open and extend scores are equal
+    pub(crate) fn matrix(alignment_type: AlignmentType, subtype: Subtype, g: i8, e: i8, table: Vec<i32>) -> Engine {
+        Engine { table: Some(table), ..Engine::sisd(alignment_type, subtype, 0, 0, g, e, g, e) }
+    }
+
+    // checks that sequences of max_sequence_len bases can be aligned; the buffers themselves are
+    // allocated for each alignment
+    pub(crate) fn prealloc(&self, max_sequence_len: u32) -> Result<(), Exception> {
+        if self.table.is_none() && max_sequence_len > i32::MAX as u32 {
+            return Err(invalid("[spoa::SisdAlignmentEngine::Prealloc] error: too large sequence!"));
+        }
+        Ok(())
+    }
+
+    // aligns a sequence to the graph, returning the alignment and its score, or an empty
+    // alignment and 0 if there is none
+    pub(crate) fn align(&self, sequence: &[u8], graph: &Graph) -> Result<(Alignment, i32), Exception> {
+        match &self.table {
+            Some(table) => self.align_matrix(sequence, graph, table),
+            None => self.align_sisd(sequence, graph),
+        }
+    }
+
+    // the lowest score an alignment of sequences of lengths i and j can have
+    fn worst_case_score(&self, i: i64, j: i64) -> i64 {
+        let gap_score = |len: i64| if len == 0 { 0 } else { (self.g as i64 + (len - 1) * self.e as i64).min(self.q as i64 + (len - 1) * self.c as i64) };
+        (-(self.m as i64 * i.min(j) + gap_score((i - j).abs()))).min(gap_score(i) + gap_score(j))
+    }
+
+    fn align_sisd(&self, sequence: &[u8], graph: &Graph) -> Result<(Alignment, i32), Exception> {
+        if sequence.len() > i32::MAX as usize {
+            return Err(invalid("[spoa::SisdAlignmentEngine::Align] error: too large sequence!"));
+        }
+        if graph.nodes.is_empty() || sequence.is_empty() {
+            return Ok((vec![], 0));
+        }
+        if self.worst_case_score(sequence.len() as i64, graph.nodes.len() as i64) < NEGATIVE_INFINITY as i64 {
+            return Err(invalid("[spoa::SisdAlignmentEngine::Align] error: possible overflow!"));
+        }
+
+        let mut matrices = Sisd::new(self, sequence, graph);
+        Ok(match self.subtype {
+            Subtype::Linear => matrices.linear(),
+            Subtype::Affine => matrices.affine(),
+            Subtype::Convex => matrices.convex(),
+        })
+    }
+
+    fn align_matrix(&self, sequence: &[u8], graph: &Graph, table: &[i32]) -> Result<(Alignment, i32), Exception> {
+        let rank_to_node = &graph.rank_to_node;
+        if rank_to_node.is_empty() || sequence.is_empty() {
+            return Ok((vec![], 0));
+        }
+        let max_magnitude = table.iter().map(|score| score.abs()).fold(self.g.abs().max(self.e.abs()), i32::max) as i64;
+        if (sequence.len() as i64 + rank_to_node.len() as i64 + 1) * max_magnitude >= 1 << 30 {
+            return Err(invalid("[poa_func] error: possible overflow!"));
+        }
+        let (g, e) = (self.g, self.e);
+        let (local, global) = (self.alignment_type == AlignmentType::Sw, self.alignment_type == AlignmentType::Nw);
+
+        let width = sequence.len() + 1;
+        let height = rank_to_node.len() + 1;
+        let mut h = vec![0; width * height];
+        let mut e_gaps = vec![NEGATIVE_INFINITY; width * height];
+        let mut f = vec![NEGATIVE_INFINITY; width * height];
+        let mut node_id_to_rank = vec![0; graph.nodes.len()];
+        for (rank, &id) in rank_to_node.iter().enumerate() {
+            node_id_to_rank[id as usize] = rank;
+        }
+        // the rows of the predecessors of the node in row i, or row 0 for a node without any
+        let predecessors = |i: usize| -> Vec<usize> {
+            let edges = &graph.nodes[rank_to_node[i - 1] as usize].inedges;
+            if edges.is_empty() {
+                return vec![0];
+            }
+            edges.iter().map(|&edge| node_id_to_rank[graph.edges[edge].tail as usize] + 1).collect()
+        };
+        let substitution = |i: usize, j: usize| table[graph.base(rank_to_node[i - 1]) as usize * 256 + sequence[j - 1] as usize];
+
+        // the gaps before the first base and the first node, as in the native engine
+        e_gaps[0] = 0;
+        f[0] = 0;
+        for j in 1..width {
+            e_gaps[j] = g + (j as i32 - 1) * e;
+            h[j] = if local { 0 } else { e_gaps[j] };
+        }
+        for i in 1..height {
+            let edges = &graph.nodes[rank_to_node[i - 1] as usize].inedges;
+            let mut penalty = if edges.is_empty() { g - e } else { NEGATIVE_INFINITY };
+            for &edge in edges {
+                penalty = penalty.max(f[(node_id_to_rank[graph.edges[edge].tail as usize] + 1) * width]);
+            }
+            f[i * width] = penalty + e;
+            h[i * width] = if global { f[i * width] } else { 0 };
+        }
+
+        let mut max_score = if local { 0 } else { NEGATIVE_INFINITY };
+        let (mut max_i, mut max_j) = (0, 0);
+        for i in 1..height {
+            let rows = predecessors(i);
+            let sink = graph.nodes[rank_to_node[i - 1] as usize].outedges.is_empty();
+            for j in 1..width {
+                let cell = i * width + j;
+                let mut diagonal = NEGATIVE_INFINITY;
+                for &p in &rows {
+                    diagonal = diagonal.max(h[p * width + j - 1] + substitution(i, j));
+                    f[cell] = f[cell].max((h[p * width + j] + g).max(f[p * width + j] + e));
+                }
+                e_gaps[cell] = (h[cell - 1] + g).max(e_gaps[cell - 1] + e);
+                h[cell] = diagonal.max(e_gaps[cell].max(f[cell]));
+                if local {
+                    h[cell] = h[cell].max(0);
+                }
+
+                let end = local || (sink && (self.alignment_type == AlignmentType::Ov || j == width - 1));
+                if end && h[cell] > max_score {
+                    max_score = h[cell];
+                    max_i = i;
+                    max_j = j;
+                }
+            }
+        }
+
+        if max_i == 0 && max_j == 0 {
+            return Ok((vec![], 0));
+        }
+
+        // the states of the traceback: a cell of H, or a gap in the sequence (F) or in the graph (E)
+        #[derive(PartialEq)]
+        enum State {
+            H,
+            E,
+            F,
+        }
+        let mut state = State::H;
+        let mut alignment = vec![];
+        let (mut i, mut j) = (max_i, max_j);
+        loop {
+            let cell = i * width + j;
+            match state {
+                State::H => {
+                    let done = if local { h[cell] == 0 } else if global { i == 0 && j == 0 } else { i == 0 || j == 0 };
+                    if done {
+                        break;
+                    }
+                    let mut matched = false;
+                    if i != 0 && j != 0 {
+                        if let Some(p) = predecessors(i).into_iter().find(|&p| h[cell] == h[p * width + j - 1] + substitution(i, j)) {
+                            alignment.push((rank_to_node[i - 1] as i32, j as i32 - 1));
+                            i = p;
+                            j -= 1;
+                            matched = true;
+                        }
+                    }
+                    if !matched {
+                        state = if i != 0 && h[cell] == f[cell] { State::F } else { State::E };
+                    }
+                }
+                State::F => {
+                    let mut extended = false;
+                    let mut next = 0;
+                    for p in predecessors(i) {
+                        if f[cell] == h[p * width + j] + g {
+                            next = p;
+                            break;
+                        }
+                        if f[cell] == f[p * width + j] + e {
+                            next = p;
+                            extended = true;
+                            break;
+                        }
+                    }
+                    alignment.push((rank_to_node[i - 1] as i32, -1));
+                    i = next;
+                    state = if extended { State::F } else { State::H };
+                }
+                State::E => {
+                    let extended = e_gaps[cell] != h[cell - 1] + g;
+                    alignment.push((-1, j as i32 - 1));
+                    j -= 1;
+                    state = if extended { State::E } else { State::H };
+                }
+            }
+        }
+
+        alignment.reverse();
+        Ok((alignment, max_score))
+    }
+}
+
+// the matrices of one alignment of spoa::SisdAlignmentEngine: H holds the best score of each cell,
+// F and E those ending in a gap in the sequence and in the graph, and O and Q those ending in a gap
+// of the second gap model of convex gaps
+struct Sisd<'a> {
+    engine: &'a Engine,
+    graph: &'a Graph,
+    width: usize,
+    // the score of each symbol code against each base, with a leading 0 for each code
+    profile: Vec<i32>,
+    node_id_to_rank: Vec<usize>,
+    h: Vec<i32>,
+    f: Vec<i32>,
+    e: Vec<i32>,
+    o: Vec<i32>,
+    q: Vec<i32>,
+}
+
+impl<'a> Sisd<'a> {
+    // fills the profile and the initial conditions, as Initialize does
+    fn new(engine: &'a Engine, sequence: &[u8], graph: &'a Graph) -> Sisd<'a> {
+        let width = sequence.len() + 1;
+        let height = graph.nodes.len() + 1;
+        let size = |subtypes: &[Subtype]| if subtypes.contains(&engine.subtype) { width * height } else { 0 };
+
+        let mut profile = vec![0; graph.num_codes() as usize * width];
+        for code in 0..graph.num_codes() {
+            let symbol = graph.decoder(code);
+            for (j, &base) in sequence.iter().enumerate() {
+                profile[code as usize * width + j + 1] = if symbol == base { engine.m } else { engine.n };
+            }
+        }
+        let mut node_id_to_rank = vec![0; graph.nodes.len()];
+        for (rank, &id) in graph.rank_to_node.iter().enumerate() {
+            node_id_to_rank[id as usize] = rank;
+        }
+
+        let mut matrices = Sisd {
+            engine,
+            graph,
+            width,
+            profile,
+            node_id_to_rank,
+            h: vec![0; width * height],
+            f: vec![0; size(&[Subtype::Affine, Subtype::Convex])],
+            e: vec![0; size(&[Subtype::Affine, Subtype::Convex])],
+            o: vec![0; size(&[Subtype::Convex])],
+            q: vec![0; size(&[Subtype::Convex])],
+        };
+        matrices.initialize(height);
+        matrices
+    }
+
+    // the rows of the predecessors of a node, in the order of its edges, or row 0 if it has none
+    fn predecessors(&self, node_id: u32) -> Vec<usize> {
+        let edges = &self.graph.nodes[node_id as usize].inedges;
+        if edges.is_empty() {
+            return vec![0];
+        }
+        self.edges_rows(node_id)
+    }
+
+    // the rows of the predecessors of a node, in the order of its edges
+    fn edges_rows(&self, node_id: u32) -> Vec<usize> {
+        self.graph.nodes[node_id as usize].inedges.iter().map(|&edge| self.node_id_to_rank[self.graph.edges[edge].tail as usize] + 1).collect()
+    }
+
+    // the best of the first column of the predecessors of the node in row i in matrix, or initial
+    // for a node without any
+    fn column_penalty(&self, matrix: &[i32], i: usize, initial: i32) -> i32 {
+        let node_id = self.graph.rank_to_node[i - 1];
+        let mut penalty = if self.graph.nodes[node_id as usize].inedges.is_empty() { initial } else { NEGATIVE_INFINITY };
+        for row in self.edges_rows(node_id) {
+            penalty = penalty.max(matrix[row * self.width]);
+        }
+        penalty
+    }
+
+    fn initialize(&mut self, height: usize) {
+        let Engine { alignment_type, subtype, g, e, q, c, .. } = *self.engine;
+        let width = self.width;
+
+        if subtype == Subtype::Convex {
+            self.o[0] = 0;
+            self.q[0] = 0;
+            for j in 1..width {
+                self.o[j] = NEGATIVE_INFINITY;
+                self.q[j] = q + (j as i32 - 1) * c;
+            }
+            for i in 1..height {
+                self.o[i * width] = self.column_penalty(&self.o, i, q - c) + c;
+                self.q[i * width] = NEGATIVE_INFINITY;
+            }
+        }
+        if subtype != Subtype::Linear {
+            self.f[0] = 0;
+            self.e[0] = 0;
+            for j in 1..width {
+                self.f[j] = NEGATIVE_INFINITY;
+                self.e[j] = g + (j as i32 - 1) * e;
+            }
+            for i in 1..height {
+                self.f[i * width] = self.column_penalty(&self.f, i, g - e) + e;
+                self.e[i * width] = NEGATIVE_INFINITY;
+            }
+        }
+        self.h[0] = 0;
+
+        // the first row
+        for j in 1..width {
+            self.h[j] = match (alignment_type, subtype) {
+                (AlignmentType::Sw, _) => 0,
+                (_, Subtype::Convex) => self.q[j].max(self.e[j]),
+                (_, Subtype::Affine) => self.e[j],
+                (_, Subtype::Linear) => j as i32 * g,
+            };
+        }
+        // the first column
+        for i in 1..height {
+            self.h[i * width] = match (alignment_type, subtype) {
+                (AlignmentType::Nw, Subtype::Convex) => self.o[i * width].max(self.f[i * width]),
+                (AlignmentType::Nw, Subtype::Affine) => self.f[i * width],
+                (AlignmentType::Nw, Subtype::Linear) => self.column_penalty(&self.h, i, 0) + g,
+                _ => 0,
+            };
+        }
+    }
+
+    // whether the cell is a candidate end of the alignment
+    fn is_end(&self, node_id: u32, j: usize) -> bool {
+        let sink = self.graph.nodes[node_id as usize].outedges.is_empty();
+        match self.engine.alignment_type {
+            AlignmentType::Sw => true,
+            AlignmentType::Nw => sink && j == self.width - 1,
+            AlignmentType::Ov => sink,
+        }
+    }
+
+    // whether the traceback goes on from the cell
+    fn traces_back(&self, i: usize, j: usize) -> bool {
+        match self.engine.alignment_type {
+            AlignmentType::Sw => self.h[i * self.width + j] != 0,
+            AlignmentType::Nw => i != 0 || j != 0,
+            AlignmentType::Ov => i != 0 && j != 0,
+        }
+    }
+
+    // the column of the alignment stepping from the cell (i, j) to the cell (prev_i, prev_j)
+    fn column(&self, i: usize, j: usize, prev_i: usize, prev_j: usize) -> (i32, i32) {
+        (if i == prev_i { -1 } else { self.graph.rank_to_node[i - 1] as i32 }, if j == prev_j { -1 } else { j as i32 - 1 })
+    }
+
+    // the score and cell at which the alignment ends, after the row of each node is filled by fill
+    fn fill<F: FnMut(&mut Sisd<'a>, u32, usize, &[usize])>(&mut self, mut fill: F) -> (i32, usize, usize) {
+        let mut max_score = if self.engine.alignment_type == AlignmentType::Sw { 0 } else { NEGATIVE_INFINITY };
+        let (mut max_i, mut max_j) = (0, 0);
+        for rank in 0..self.graph.rank_to_node.len() {
+            let node_id = self.graph.rank_to_node[rank];
+            let i = rank + 1;
+            let rows = self.predecessors(node_id);
+            fill(self, node_id, i, &rows);
+            for j in 1..self.width {
+                if self.is_end(node_id, j) && max_score < self.h[i * self.width + j] {
+                    max_score = self.h[i * self.width + j];
+                    max_i = i;
+                    max_j = j;
+                }
+            }
+        }
+        (max_score, max_i, max_j)
+    }
+
+    // the row of the diagonal predecessor of the cell (i, j), if any
+    fn diagonal(&self, i: usize, j: usize) -> Option<usize> {
+        if i == 0 || j == 0 {
+            return None;
+        }
+        let node_id = self.graph.rank_to_node[i - 1];
+        let match_cost = self.profile[self.graph.nodes[node_id as usize].code as usize * self.width + j];
+        let h_ij = self.h[i * self.width + j];
+        self.predecessors(node_id).into_iter().find(|&p| h_ij == self.h[p * self.width + j - 1] + match_cost)
+    }
+
+    fn linear(&mut self) -> (Alignment, i32) {
+        let (sw, g, width) = (self.engine.alignment_type == AlignmentType::Sw, self.engine.g, self.width);
+        let (max_score, max_i, max_j) = self.fill(|dp, node_id, i, rows| {
+            let char_profile = dp.graph.nodes[node_id as usize].code as usize * width;
+            for (p, &pred_i) in rows.iter().enumerate() {
+                for j in 1..width {
+                    let score = (dp.h[pred_i * width + j - 1] + dp.profile[char_profile + j]).max(dp.h[pred_i * width + j] + g);
+                    dp.h[i * width + j] = if p == 0 { score } else { score.max(dp.h[i * width + j]) };
+                }
+            }
+            for j in 1..width {
+                let mut score = (dp.h[i * width + j - 1] + g).max(dp.h[i * width + j]);
+                if sw {
+                    score = score.max(0);
+                }
+                dp.h[i * width + j] = score;
+            }
+        });
+
+        if max_i == 0 && max_j == 0 {
+            return (vec![], 0);
+        }
+
+        // backtrack
+        let mut alignment = vec![];
+        let (mut i, mut j) = (max_i, max_j);
+        let (mut prev_i, mut prev_j) = (0, 0);
+        while self.traces_back(i, j) {
+            let h_ij = self.h[i * width + j];
+            let mut predecessor_found = false;
+            if let Some(p) = self.diagonal(i, j) {
+                prev_i = p;
+                prev_j = j - 1;
+                predecessor_found = true;
+            }
+            if !predecessor_found && i != 0 {
+                if let Some(p) = self.predecessors(self.graph.rank_to_node[i - 1]).into_iter().find(|&p| h_ij == self.h[p * width + j] + g) {
+                    prev_i = p;
+                    prev_j = j;
+                    predecessor_found = true;
+                }
+            }
+            if !predecessor_found && j != 0 && h_ij == self.h[i * width + j - 1] + g {
+                prev_i = i;
+                prev_j = j - 1;
+            }
+
+            alignment.push(self.column(i, j, prev_i, prev_j));
+            i = prev_i;
+            j = prev_j;
+        }
+
+        alignment.reverse();
+        (alignment, max_score)
+    }
+
+    fn affine(&mut self) -> (Alignment, i32) {
+        let (sw, g, e, width) = (self.engine.alignment_type == AlignmentType::Sw, self.engine.g, self.engine.e, self.width);
+        let (max_score, max_i, max_j) = self.fill(|dp, node_id, i, rows| {
+            let char_profile = dp.graph.nodes[node_id as usize].code as usize * width;
+            // update F and H
+            for (p, &pred_i) in rows.iter().enumerate() {
+                for j in 1..width {
+                    let up = (dp.h[pred_i * width + j] + g).max(dp.f[pred_i * width + j] + e);
+                    let diagonal = dp.h[pred_i * width + j - 1] + dp.profile[char_profile + j];
+                    let cell = i * width + j;
+                    if p == 0 {
+                        dp.f[cell] = up;
+                        dp.h[cell] = diagonal;
+                    } else {
+                        dp.f[cell] = dp.f[cell].max(up);
+                        dp.h[cell] = dp.h[cell].max(diagonal);
+                    }
+                }
+            }
+            // update E and H
+            for j in 1..width {
+                let cell = i * width + j;
+                dp.e[cell] = (dp.h[cell - 1] + g).max(dp.e[cell - 1] + e);
+                let mut score = dp.h[cell].max(dp.f[cell].max(dp.e[cell]));
+                if sw {
+                    score = score.max(0);
+                }
+                dp.h[cell] = score;
+            }
+        });
+
+        if max_i == 0 && max_j == 0 {
+            return (vec![], 0);
+        }
+
+        // backtrack
+        let mut alignment = vec![];
+        let (mut i, mut j) = (max_i, max_j);
+        let (mut prev_i, mut prev_j) = (0, 0);
+        while self.traces_back(i, j) {
+            let h_ij = self.h[i * width + j];
+            let (mut predecessor_found, mut extend_left, mut extend_up) = (false, false, false);
+            if let Some(p) = self.diagonal(i, j) {
+                prev_i = p;
+                prev_j = j - 1;
+                predecessor_found = true;
+            }
+            if !predecessor_found && i != 0 {
+                for p in self.predecessors(self.graph.rank_to_node[i - 1]) {
+                    let extends = h_ij == self.f[p * width + j] + e;
+                    if extends || h_ij == self.h[p * width + j] + g {
+                        extend_up = extends;
+                        prev_i = p;
+                        prev_j = j;
+                        predecessor_found = true;
+                        break;
+                    }
+                }
+            }
+            if !predecessor_found && j != 0 {
+                let extends = h_ij == self.e[i * width + j - 1] + e;
+                if extends || h_ij == self.h[i * width + j - 1] + g {
+                    extend_left = extends;
+                    prev_i = i;
+                    prev_j = j - 1;
+                }
+            }
+
+            alignment.push(self.column(i, j, prev_i, prev_j));
+            i = prev_i;
+            j = prev_j;
+
+            if extend_left {
+                loop {
+                    alignment.push((-1, j as i32 - 1));
+                    j -= 1;
+                    if self.e[i * width + j] + e != self.e[i * width + j + 1] {
+                        break;
+                    }
+                }
+            } else if extend_up {
+                loop {
+                    let mut stop = false;
+                    prev_i = 0;
+                    let node_id = self.graph.rank_to_node[i - 1];
+                    for p in self.edges_rows(node_id) {
+                        let opens = self.f[i * width + j] == self.h[p * width + j] + g;
+                        if opens || self.f[i * width + j] == self.f[p * width + j] + e {
+                            stop = opens;
+                            prev_i = p;
+                            break;
+                        }
+                    }
+
+                    alignment.push((node_id as i32, -1));
+                    i = prev_i;
+                    if stop || i == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        alignment.reverse();
+        (alignment, max_score)
+    }
+
+    fn convex(&mut self) -> (Alignment, i32) {
+        let Engine { g, e, q, c, .. } = *self.engine;
+        let (sw, width) = (self.engine.alignment_type == AlignmentType::Sw, self.width);
+        let (max_score, max_i, max_j) = self.fill(|dp, node_id, i, rows| {
+            let char_profile = dp.graph.nodes[node_id as usize].code as usize * width;
+            // update F, O and H
+            for (p, &pred_i) in rows.iter().enumerate() {
+                for j in 1..width {
+                    let up = (dp.h[pred_i * width + j] + g).max(dp.f[pred_i * width + j] + e);
+                    let up2 = (dp.h[pred_i * width + j] + q).max(dp.o[pred_i * width + j] + c);
+                    let diagonal = dp.h[pred_i * width + j - 1] + dp.profile[char_profile + j];
+                    let cell = i * width + j;
+                    if p == 0 {
+                        dp.f[cell] = up;
+                        dp.o[cell] = up2;
+                        dp.h[cell] = diagonal;
+                    } else {
+                        dp.f[cell] = dp.f[cell].max(up);
+                        dp.o[cell] = dp.o[cell].max(up2);
+                        dp.h[cell] = dp.h[cell].max(diagonal);
+                    }
+                }
+            }
+            // update E, Q and H
+            for j in 1..width {
+                let cell = i * width + j;
+                dp.e[cell] = (dp.h[cell - 1] + g).max(dp.e[cell - 1] + e);
+                dp.q[cell] = (dp.h[cell - 1] + q).max(dp.q[cell - 1] + c);
+                let mut score = dp.h[cell].max(dp.f[cell].max(dp.e[cell]).max(dp.o[cell].max(dp.q[cell])));
+                if sw {
+                    score = score.max(0);
+                }
+                dp.h[cell] = score;
+            }
+        });
+
+        if max_i == 0 && max_j == 0 {
+            return (vec![], 0);
+        }
+
+        // backtrack
+        let mut alignment = vec![];
+        let (mut i, mut j) = (max_i, max_j);
+        let (mut prev_i, mut prev_j) = (0, 0);
+        while self.traces_back(i, j) {
+            let h_ij = self.h[i * width + j];
+            let (mut predecessor_found, mut extend_left, mut extend_up) = (false, false, false);
+            if let Some(p) = self.diagonal(i, j) {
+                prev_i = p;
+                prev_j = j - 1;
+                predecessor_found = true;
+            }
+            if !predecessor_found && i != 0 {
+                for p in self.predecessors(self.graph.rank_to_node[i - 1]) {
+                    let cell = p * width + j;
+                    if h_ij == self.f[cell] + e || (h_ij == self.o[cell] + c && h_ij != self.h[cell] + g) {
+                        extend_up = true;
+                    }
+                    if h_ij == self.f[cell] + e || h_ij == self.h[cell] + g || h_ij == self.o[cell] + c || h_ij == self.h[cell] + q {
+                        prev_i = p;
+                        prev_j = j;
+                        predecessor_found = true;
+                        break;
+                    }
+                }
+            }
+            if !predecessor_found && j != 0 {
+                let cell = i * width + j - 1;
+                if h_ij == self.e[cell] + e || (h_ij == self.q[cell] + c && h_ij != self.h[cell] + g) {
+                    extend_left = true;
+                }
+                if h_ij == self.e[cell] + e || h_ij == self.h[cell] + g || h_ij == self.q[cell] + c || h_ij == self.h[cell] + q {
+                    prev_i = i;
+                    prev_j = j - 1;
+                }
+            }
+
+            alignment.push(self.column(i, j, prev_i, prev_j));
+            i = prev_i;
+            j = prev_j;
+
+            if extend_left {
+                loop {
+                    alignment.push((-1, j as i32 - 1));
+                    j -= 1;
+                    let cell = i * width + j;
+                    if self.e[cell] + e != self.e[cell + 1] && self.q[cell] + c != self.q[cell + 1] {
+                        break;
+                    }
+                }
+            } else if extend_up {
+                loop {
+                    let mut stop = true;
+                    prev_i = 0;
+                    let node_id = self.graph.rank_to_node[i - 1];
+                    let cell = i * width + j;
+                    let rows = self.edges_rows(node_id);
+                    if let Some(&p) = rows.iter().find(|&&p| self.f[cell] == self.f[p * width + j] + e || self.o[cell] == self.o[p * width + j] + c) {
+                        prev_i = p;
+                        stop = false;
+                    }
+                    if stop {
+                        if let Some(&p) = rows.iter().find(|&&p| self.f[cell] == self.h[p * width + j] + g || self.o[cell] == self.h[p * width + j] + q) {
+                            prev_i = p;
+                        }
+                    }
+
+                    alignment.push((node_id as i32, -1));
+                    i = prev_i;
+                    if stop || i == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+
+        alignment.reverse();
+        (alignment, max_score)
+    }
+}
@@ -0,0 +1,528 @@
+//! A port of `spoa::Graph`, with nodes and edges referring to each other by index.
+
+use super::{invalid, Exception};
+
+// an alignment as spoa::Alignment: the node id and sequence position of each column, -1 for a gap
+pub(crate) type Alignment = Vec<(i32, i32)>;
+
+// a node of the graph, whose id is its index in Graph::nodes
+#[derive(Clone, Debug)]
+pub(crate) struct Node {
+    pub(crate) code: u32,
+    // the indices of the edges into and out of the node in Graph::edges
+    pub(crate) inedges: Vec<usize>,
+    pub(crate) outedges: Vec<usize>,
+    // the nodes of other letters aligned to the node
+    pub(crate) aligned_nodes: Vec<u32>,
+}
+
+// an edge between two consecutive bases, with the index of each sequence along it
+#[derive(Clone, Debug)]
+pub(crate) struct Edge {
+    pub(crate) tail: u32,
+    pub(crate) head: u32,
+    pub(crate) labels: Vec<u32>,
+    pub(crate) weight: i64,
+}
+
+// a partial order graph as spoa::Graph, numbering the symbols of the sequences by first use
+#[derive(Clone, Debug)]
+pub(crate) struct Graph {
+    num_codes: u32,
+    coder: [i32; 256],
+    decoder: [u8; 256],
+    // the first node of each sequence
+    pub(crate) sequences: Vec<u32>,
+    pub(crate) nodes: Vec<Node>,
+    pub(crate) edges: Vec<Edge>,
+    pub(crate) rank_to_node: Vec<u32>,
+    pub(crate) consensus: Vec<u32>,
+}
+
+impl Default for Graph {
+    fn default() -> Graph {
+        Graph {
+            num_codes: 0,
+            coder: [-1; 256],
+            decoder: [u8::MAX; 256],
+            sequences: vec![],
+            nodes: vec![],
+            edges: vec![],
+            rank_to_node: vec![],
+            consensus: vec![],
+        }
+    }
+}
+
+impl Graph {
+    pub(crate) fn num_codes(&self) -> u32 {
+        self.num_codes
+    }
+
+    pub(crate) fn coder(&self, symbol: u8) -> i32 {
+        self.coder[symbol as usize]
+    }
+
+    pub(crate) fn decoder(&self, code: u32) -> u8 {
+        self.decoder[code as usize]
+    }
+
+    // the letter of a node
+    pub(crate) fn base(&self, node_id: u32) -> u8 {
+        self.decoder(self.nodes[node_id as usize].code)
+    }
+
+    // the node following node_id along the sequence with the given label
+    pub(crate) fn successor(&self, node_id: u32, label: u32) -> Option<u32> {
+        self.nodes[node_id as usize]
+            .outedges
+            .iter()
+            .map(|&edge| &self.edges[edge])
+            .find(|edge| edge.labels.contains(&label))
+            .map(|edge| edge.head)
+    }
+
+    // the number of sequences passing through a node
+    pub(crate) fn coverage(&self, node_id: u32) -> u32 {
+        let node = &self.nodes[node_id as usize];
+        let mut labels: Vec<u32> = node.inedges.iter().chain(&node.outedges).flat_map(|&edge| self.edges[edge].labels.iter().cloned()).collect();
+        labels.sort_unstable();
+        labels.dedup();
+        labels.len() as u32
+    }
+
+    fn add_node(&mut self, code: u32) -> u32 {
+        self.nodes.push(Node { code, inedges: vec![], outedges: vec![], aligned_nodes: vec![] });
+        self.nodes.len() as u32 - 1
+    }
+
+    fn add_edge(&mut self, tail: u32, head: u32, weight: u32) {
+        let label = self.sequences.len() as u32;
+        for &edge in &self.nodes[tail as usize].outedges {
+            let edge = &mut self.edges[edge];
+            if edge.head == head {
+                edge.labels.push(label);
+                edge.weight += weight as i64;
+                return;
+            }
+        }
+        self.edges.push(Edge { tail, head, labels: vec![label], weight: weight as i64 });
+        let edge = self.edges.len() - 1;
+        self.nodes[tail as usize].outedges.push(edge);
+        self.nodes[head as usize].inedges.push(edge);
+    }
+
+    // adds the bases from begin to end as a chain of new nodes, returning the first of them
+    fn add_sequence(&mut self, sequence: &[u8], weights: &[u32], begin: usize, end: usize) -> Option<u32> {
+        if begin == end {
+            return None;
+        }
+        let mut prev = None;
+        for i in begin..end {
+            let curr = self.add_node(self.coder(sequence[i]) as u32);
+            if let Some(prev) = prev {
+                // both nodes contribute to the weight
+                self.add_edge(prev, curr, weights[i - 1].wrapping_add(weights[i]));
+            }
+            prev = Some(curr);
+        }
+        Some((self.nodes.len() - (end - begin)) as u32)
+    }
+
+    // adds a sequence along its alignment to the graph, with the weight of each base
+    pub(crate) fn add_alignment(&mut self, alignment: &[(i32, i32)], sequence: &[u8], weights: &[u32]) -> Result<(), Exception> {
+        if sequence.is_empty() {
+            return Ok(());
+        }
+        if sequence.len() != weights.len() {
+            return Err(invalid("[spoa::Graph::AddAlignment] error: sequence and weights are of unequal size!"));
+        }
+
+        for &symbol in sequence {
+            if self.coder[symbol as usize] == -1 {
+                self.coder[symbol as usize] = self.num_codes as i32;
+                self.decoder[self.num_codes as usize] = symbol;
+                self.num_codes += 1;
+            }
+        }
+
+        if alignment.is_empty() {
+            let begin = self.add_sequence(sequence, weights, 0, sequence.len());
+            self.sequences.extend(begin);
+            self.topological_sort();
+            return Ok(());
+        }
+
+        let mut valid = vec![];
+        for &(node_id, position) in alignment {
+            // spoa trusts the node ids, which index its nodes
+            if node_id < -1 || node_id >= self.nodes.len() as i32 {
+                return Err(invalid("[spoa::Graph::AddAlignment] error: invalid alignment"));
+            }
+            if position != -1 {
+                if position < 0 || position as usize >= sequence.len() {
+                    return Err(invalid("[spoa::Graph::AddAlignment] error: invalid alignment"));
+                }
+                valid.push(position as usize);
+            }
+        }
+        let (first, last) = match (valid.first(), valid.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return Err(invalid("[spoa::Graph::AddAlignment] error: missing sequence in alignment")),
+        };
+
+        // add unaligned bases
+        let mut begin = self.add_sequence(sequence, weights, 0, first);
+        let mut prev = begin.map(|_| self.nodes.len() as u32 - 1);
+        let unaligned = self.add_sequence(sequence, weights, last + 1, sequence.len());
+
+        // add aligned bases
+        for &(node_id, position) in alignment {
+            if position == -1 {
+                continue;
+            }
+            let position = position as usize;
+            let code = self.coder(sequence[position]) as u32;
+            let curr = if node_id == -1 {
+                self.add_node(code)
+            } else {
+                let aligned_to = node_id as u32;
+                let node = &self.nodes[aligned_to as usize];
+                let existing = node.aligned_nodes.iter().cloned().find(|&aligned| self.nodes[aligned as usize].code == code);
+                if node.code == code {
+                    aligned_to
+                } else if let Some(aligned) = existing {
+                    aligned
+                } else {
+                    let curr = self.add_node(code);
+                    for aligned in self.nodes[aligned_to as usize].aligned_nodes.clone() {
+                        self.nodes[aligned as usize].aligned_nodes.push(curr);
+                        self.nodes[curr as usize].aligned_nodes.push(aligned);
+                    }
+                    self.nodes[aligned_to as usize].aligned_nodes.push(curr);
+                    self.nodes[curr as usize].aligned_nodes.push(aligned_to);
+                    curr
+                }
+            };
+            if begin.is_none() {
+                begin = Some(curr);
+            }
+            if let Some(prev) = prev {
+                // both nodes contribute to the weight
+                self.add_edge(prev, curr, weights[position - 1].wrapping_add(weights[position]));
+            }
+            prev = Some(curr);
+        }
+        if let (Some(prev), Some(unaligned)) = (prev, unaligned) {
+            self.add_edge(prev, unaligned, weights[last].wrapping_add(weights[last + 1]));
+        }
+        self.sequences.extend(begin);
+
+        self.topological_sort();
+        Ok(())
+    }
+
+    // orders the nodes so that every node follows its predecessors, with the nodes aligned to each
+    // other next to each other
+    fn topological_sort(&mut self) {
+        self.rank_to_node.clear();
+
+        let mut marks = vec![0u8; self.nodes.len()];
+        let mut ignored = vec![false; self.nodes.len()];
+        let mut stack = vec![];
+        for id in 0..self.nodes.len() {
+            if marks[id] != 0 {
+                continue;
+            }
+            stack.push(id as u32);
+            while let Some(&curr) = stack.last() {
+                let node = &self.nodes[curr as usize];
+                let mut is_valid = true;
+                if marks[curr as usize] != 2 {
+                    for &edge in &node.inedges {
+                        let tail = self.edges[edge].tail;
+                        if marks[tail as usize] != 2 {
+                            stack.push(tail);
+                            is_valid = false;
+                        }
+                    }
+                    if !ignored[curr as usize] {
+                        for &aligned in &node.aligned_nodes {
+                            if marks[aligned as usize] != 2 {
+                                stack.push(aligned);
+                                ignored[aligned as usize] = true;
+                                is_valid = false;
+                            }
+                        }
+                    }
+
+                    debug_assert!(is_valid || marks[curr as usize] != 1, "graph is not a DAG");
+
+                    if is_valid {
+                        marks[curr as usize] = 2;
+                        if !ignored[curr as usize] {
+                            self.rank_to_node.push(curr);
+                            self.rank_to_node.extend(&node.aligned_nodes);
+                        }
+                    } else {
+                        marks[curr as usize] = 1;
+                    }
+                }
+
+                if is_valid {
+                    stack.pop();
+                }
+            }
+        }
+    }
+
+    // the column of each node in the multiple sequence alignment, and the number of columns
+    fn msa_columns(&self) -> (Vec<u32>, u32) {
+        let mut columns = vec![0; self.nodes.len()];
+        let mut column = 0;
+        let mut i = 0;
+        while i < self.rank_to_node.len() {
+            let id = self.rank_to_node[i];
+            columns[id as usize] = column;
+            for &aligned in &self.nodes[id as usize].aligned_nodes {
+                columns[aligned as usize] = column;
+                i += 1;
+            }
+            i += 1;
+            column += 1;
+        }
+        (columns, column)
+    }
+
+    // the row of each sequence in the multiple sequence alignment, and of the consensus if asked for
+    pub(crate) fn multiple_sequence_alignment(&mut self, include_consensus: bool) -> Vec<Vec<u8>> {
+        let (columns, row_size) = self.msa_columns();
+
+        let mut rows = vec![];
+        for (label, &start) in self.sequences.iter().enumerate() {
+            let mut row = vec![b'-'; row_size as usize];
+            let mut node = Some(start);
+            while let Some(id) = node {
+                row[columns[id as usize] as usize] = self.base(id);
+                node = self.successor(id, label as u32);
+            }
+            rows.push(row);
+        }
+        if include_consensus {
+            self.traverse_heaviest_bundle();
+            let mut row = vec![b'-'; row_size as usize];
+            for &id in &self.consensus {
+                row[columns[id as usize] as usize] = self.base(id);
+            }
+            rows.push(row);
+        }
+        rows
+    }
+
+    pub(crate) fn generate_consensus(&mut self) -> Vec<u8> {
+        self.traverse_heaviest_bundle();
+        self.consensus.iter().map(|&id| self.base(id)).collect()
+    }
+
+    // the consensus, with the number of sequences holding each symbol code at each consensus base,
+    // code by code, followed by the number of sequences with a deletion there
+    pub(crate) fn generate_consensus_summary(&mut self) -> (Vec<u8>, Vec<u32>) {
+        let consensus = self.generate_consensus();
+        let len = self.consensus.len();
+        let mut summary = vec![0; (self.num_codes as usize + 1) * len];
+        let (columns, _) = self.msa_columns();
+
+        for (label, &start) in self.sequences.iter().enumerate() {
+            let mut id = start;
+            let mut c = 0;
+            let mut p = 0;
+            let mut column = columns[id as usize];
+            let mut is_gap = false;
+            loop {
+                while c < len {
+                    let consensus_column = columns[self.consensus[c] as usize];
+                    if consensus_column < column {
+                        c += 1;
+                        continue;
+                    }
+                    if consensus_column == column {
+                        if is_gap {
+                            for j in p + 1..c {
+                                summary[self.num_codes as usize * len + j] += 1;
+                            }
+                        }
+                        is_gap = true;
+                        p = c;
+                        summary[self.nodes[id as usize].code as usize * len + c] += 1;
+                    }
+                    break;
+                }
+                if c == len {
+                    break;
+                }
+                match self.successor(id, label as u32) {
+                    Some(next) => id = next,
+                    None => break,
+                }
+                column = columns[id as usize];
+            }
+        }
+        (consensus, summary)
+    }
+
+    // the better of two ways into a node along an edge, as spoa breaks ties
+    fn better_edge(scores: &[i64], predecessors: &[Option<u32>], id: usize, tail: u32, weight: i64) -> bool {
+        scores[id] < weight
+            || (scores[id] == weight && scores[predecessors[id].expect("a scored node has a predecessor") as usize] <= scores[tail as usize])
+    }
+
+    fn traverse_heaviest_bundle(&mut self) {
+        if self.rank_to_node.is_empty() {
+            return;
+        }
+
+        let mut predecessors: Vec<Option<u32>> = vec![None; self.nodes.len()];
+        let mut scores: Vec<i64> = vec![-1; self.nodes.len()];
+        let mut max: Option<u32> = None;
+
+        for &id in &self.rank_to_node {
+            let i = id as usize;
+            for &edge in &self.nodes[i].inedges {
+                let edge = &self.edges[edge];
+                if Graph::better_edge(&scores, &predecessors, i, edge.tail, edge.weight) {
+                    scores[i] = edge.weight;
+                    predecessors[i] = Some(edge.tail);
+                }
+            }
+            if let Some(predecessor) = predecessors[i] {
+                scores[i] += scores[predecessor as usize];
+            }
+            if !matches!(max, Some(max) if scores[max as usize] >= scores[i]) {
+                max = Some(id);
+            }
+        }
+
+        let mut max = max.expect("a sorted graph has nodes");
+        if !self.nodes[max as usize].outedges.is_empty() {
+            let mut node_id_to_rank = vec![0; self.nodes.len()];
+            for (rank, &id) in self.rank_to_node.iter().enumerate() {
+                node_id_to_rank[id as usize] = rank;
+            }
+            while !self.nodes[max as usize].outedges.is_empty() {
+                max = self.branch_completion(node_id_to_rank[max as usize], &mut scores, &mut predecessors);
+            }
+        }
+
+        // traceback
+        self.consensus.clear();
+        while let Some(predecessor) = predecessors[max as usize] {
+            self.consensus.push(max);
+            max = predecessor;
+        }
+        self.consensus.push(max);
+        self.consensus.reverse();
+    }
+
+    fn branch_completion(&self, rank: usize, scores: &mut [i64], predecessors: &mut [Option<u32>]) -> u32 {
+        let start = self.rank_to_node[rank];
+        for &edge in &self.nodes[start as usize].outedges {
+            for &inedge in &self.nodes[self.edges[edge].head as usize].inedges {
+                let tail = self.edges[inedge].tail;
+                if tail != start {
+                    scores[tail as usize] = -1;
+                }
+            }
+        }
+
+        let mut max: Option<u32> = None;
+        for &id in &self.rank_to_node[rank + 1..] {
+            let i = id as usize;
+            scores[i] = -1;
+            predecessors[i] = None;
+
+            for &edge in &self.nodes[i].inedges {
+                let edge = &self.edges[edge];
+                if scores[edge.tail as usize] == -1 {
+                    continue;
+                }
+                if Graph::better_edge(scores, predecessors, i, edge.tail, edge.weight) {
+                    scores[i] = edge.weight;
+                    predecessors[i] = Some(edge.tail);
+                }
+            }
+            if let Some(predecessor) = predecessors[i] {
+                scores[i] += scores[predecessor as usize];
+            }
+            if !matches!(max, Some(max) if scores[max as usize] >= scores[i]) {
+                max = Some(id);
+            }
+        }
+        max.expect("a node with successors is followed by them")
+    }
+
+    // the nodes from begin back to end: those before begin along edges and aligned nodes whose id is
+    // not below that of end
+    fn extract_subgraph(&self, begin: u32, end: u32) -> Vec<bool> {
+        let mut in_subgraph = vec![false; self.nodes.len()];
+        let mut stack = vec![begin];
+        while let Some(curr) = stack.pop() {
+            if !in_subgraph[curr as usize] && curr >= end {
+                let node = &self.nodes[curr as usize];
+                stack.extend(node.inedges.iter().map(|&edge| self.edges[edge].tail));
+                stack.extend(&node.aligned_nodes);
+                in_subgraph[curr as usize] = true;
+            }
+        }
+        in_subgraph
+    }
+
+    // the part of the graph between the nodes begin and end, and the graph node of each of its nodes
+    pub(crate) fn subgraph(&self, begin: u32, end: u32) -> (Graph, Vec<u32>) {
+        let in_subgraph = self.extract_subgraph(end, begin);
+
+        let mut subgraph = Graph { num_codes: self.num_codes, coder: self.coder, decoder: self.decoder, ..Graph::default() };
+        let mut subgraph_to_graph = vec![];
+        let mut graph_to_subgraph: Vec<Option<u32>> = vec![None; self.nodes.len()];
+        for (id, node) in self.nodes.iter().enumerate() {
+            if in_subgraph[id] {
+                graph_to_subgraph[id] = Some(subgraph.add_node(node.code));
+                subgraph_to_graph.push(id as u32);
+            }
+        }
+
+        // connect nodes
+        for (id, node) in self.nodes.iter().enumerate() {
+            let head = match graph_to_subgraph[id] {
+                Some(head) => head,
+                None => continue,
+            };
+            for &edge in &node.inedges {
+                let edge = &self.edges[edge];
+                if let Some(tail) = graph_to_subgraph[edge.tail as usize] {
+                    subgraph.add_edge(tail, head, edge.weight as u32);
+                }
+            }
+            for &aligned in &node.aligned_nodes {
+                if let Some(aligned) = graph_to_subgraph[aligned as usize] {
+                    subgraph.nodes[head as usize].aligned_nodes.push(aligned);
+                }
+            }
+        }
+
+        subgraph.topological_sort();
+        (subgraph, subgraph_to_graph)
+    }
+
+    // maps the nodes of an alignment to a subgraph back to the graph
+    pub(crate) fn update_alignment(subgraph_to_graph: &[u32], alignment: &mut Alignment) {
+        for column in alignment.iter_mut() {
+            if column.0 != -1 {
+                column.0 = subgraph_to_graph[column.0 as usize] as i32;
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Graph::default();
+    }
+}
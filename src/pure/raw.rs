@@ -0,0 +1,172 @@
+//! The `poa_raw_` functions of poa_func.h: the graph and alignment engine on their own.
+
+use super::graph::{Alignment, Graph};
+use super::{bases, create_engine, guarded, Engine};
+
+// the links of a node poa_raw_graph_node_links lists, POA_RAW_ in poa_func.h
+const OUT_EDGES: i32 = 0;
+const ALIGNED: i32 = 2;
+
+// an alignment engine on its own, and the instruction set it uses
+pub(crate) struct RawEngine {
+    engine: Engine,
+    simd: i32,
+}
+
+// a graph on its own, without the read bookkeeping of ReadGraph
+pub(crate) struct RawGraph {
+    graph: Graph,
+}
+
+// spoa's classes on their own, for composing what poa_graph does not offer
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn poa_raw_engine_new(
+    alignment_type: i32,
+    match_score: i32,
+    mismatch_score: i32,
+    gap_open: i32,
+    gap_extend: i32,
+    gap_open2: i32,
+    gap_extend2: i32,
+    simd: i32,
+    ambiguity: i32,
+) -> *mut RawEngine {
+    guarded(std::ptr::null_mut(), || {
+        let (engine, simd) = create_engine(alignment_type, match_score, mismatch_score, gap_open, gap_extend, gap_open2, gap_extend2, simd, ambiguity)?;
+        Ok(Box::into_raw(Box::new(RawEngine { engine, simd })))
+    })
+}
+
+pub(crate) unsafe fn poa_raw_engine_free(e: *mut RawEngine) {
+    drop(Box::from_raw(e));
+}
+
+pub(crate) unsafe fn poa_raw_engine_simd(e: *const RawEngine) -> i32 {
+    (*e).simd
+}
+
+// the buffers are allocated for each alignment, so only the sequence length is checked
+pub(crate) unsafe fn poa_raw_engine_prealloc(e: *mut RawEngine, max_seq_len: u32, _alphabet_size: u32) {
+    guarded((), || (*e).engine.prealloc(max_seq_len))
+}
+
+pub(crate) unsafe fn poa_raw_engine_align(
+    e: *mut RawEngine,
+    g: *const RawGraph,
+    seq: *const u8,
+    seq_len: u32,
+    node_ids: *mut i32,
+    seq_positions: *mut i32,
+    score: *mut i32,
+) -> u32 {
+    guarded(0, || {
+        *score = 0;
+        let (alignment, alignment_score) = (*e).engine.align(bases(seq, seq_len), &(*g).graph)?;
+        *score = alignment_score;
+        for (i, &(node_id, position)) in alignment.iter().enumerate() {
+            *node_ids.add(i) = node_id;
+            *seq_positions.add(i) = position;
+        }
+        Ok(alignment.len() as u32)
+    })
+}
+
+pub(crate) unsafe fn poa_raw_graph_new() -> *mut RawGraph {
+    Box::into_raw(Box::new(RawGraph { graph: Graph::default() }))
+}
+
+pub(crate) unsafe fn poa_raw_graph_free(g: *mut RawGraph) {
+    drop(Box::from_raw(g));
+}
+
+pub(crate) unsafe fn poa_raw_graph_clear(g: *mut RawGraph) {
+    (*g).graph.clear();
+}
+
+pub(crate) unsafe fn poa_raw_graph_add_alignment(
+    g: *mut RawGraph,
+    seq: *const u8,
+    seq_len: u32,
+    weights: *const u32,
+    node_ids: *const i32,
+    seq_positions: *const i32,
+    alignment_len: u32,
+) {
+    guarded((), || {
+        let alignment: Alignment = (0..alignment_len as usize).map(|i| (*node_ids.add(i), *seq_positions.add(i))).collect();
+        let base_weights = if weights.is_null() { vec![1; seq_len as usize] } else { std::slice::from_raw_parts(weights, seq_len as usize).to_vec() };
+        (*g).graph.add_alignment(&alignment, bases(seq, seq_len), &base_weights)
+    })
+}
+
+pub(crate) unsafe fn poa_raw_graph_num_nodes(g: *const RawGraph) -> u32 {
+    (*g).graph.nodes.len() as u32
+}
+
+pub(crate) unsafe fn poa_raw_graph_num_edges(g: *const RawGraph) -> u32 {
+    (*g).graph.edges.len() as u32
+}
+
+pub(crate) unsafe fn poa_raw_graph_num_sequences(g: *const RawGraph) -> u32 {
+    (*g).graph.sequences.len() as u32
+}
+
+pub(crate) unsafe fn poa_raw_graph_sequence_start(g: *const RawGraph, seq_index: u32) -> u32 {
+    let graph = &(*g).graph;
+    graph.sequences[seq_index as usize]
+}
+
+pub(crate) unsafe fn poa_raw_graph_node_base(g: *const RawGraph, node_id: u32) -> u8 {
+    (*g).graph.base(node_id)
+}
+
+pub(crate) unsafe fn poa_raw_graph_node_coverage(g: *const RawGraph, node_id: u32) -> u32 {
+    (*g).graph.coverage(node_id)
+}
+
+pub(crate) unsafe fn poa_raw_graph_node_links(g: *const RawGraph, node_id: u32, kind: i32, ids: *mut u32, weights: *mut i64, len: u32) -> u32 {
+    let graph = &(*g).graph;
+    let node = &graph.nodes[node_id as usize];
+    if kind == ALIGNED {
+        for (i, &aligned) in node.aligned_nodes.iter().take(len as usize).enumerate() {
+            *ids.add(i) = aligned;
+        }
+        return node.aligned_nodes.len() as u32;
+    }
+    let edges = if kind == OUT_EDGES { &node.outedges } else { &node.inedges };
+    for (i, &edge) in edges.iter().take(len as usize).enumerate() {
+        let edge = &graph.edges[edge];
+        *ids.add(i) = if kind == OUT_EDGES { edge.head } else { edge.tail };
+        *weights.add(i) = edge.weight;
+    }
+    edges.len() as u32
+}
+
+pub(crate) unsafe fn poa_raw_graph_order(g: *const RawGraph, node_ids: *mut u32) {
+    let order = &(*g).graph.rank_to_node;
+    std::ptr::copy_nonoverlapping(order.as_ptr(), node_ids, order.len());
+}
+
+pub(crate) unsafe fn poa_raw_graph_consensus(g: *mut RawGraph, node_ids: *mut u32) -> u32 {
+    let graph = &mut (*g).graph;
+    graph.generate_consensus();
+    std::ptr::copy_nonoverlapping(graph.consensus.as_ptr(), node_ids, graph.consensus.len());
+    graph.consensus.len() as u32
+}
+
+pub(crate) unsafe fn poa_raw_graph_msa(g: *mut RawGraph, include_consensus: i32, mut rows: *mut u8, rows_len: usize) -> u32 {
+    let graph = &mut (*g).graph;
+    if graph.nodes.is_empty() {
+        return 0;
+    }
+    let msa = graph.multiple_sequence_alignment(include_consensus != 0);
+    let len = msa[0].len();
+    if rows_len >= len * msa.len() {
+        for row in &msa {
+            std::ptr::copy_nonoverlapping(row.as_ptr(), rows, len);
+            rows = rows.add(len);
+        }
+    }
+    len as u32
+}
@@ -0,0 +1,738 @@
+//! The `poa_graph_` functions of poa_func.h: a graph together with its engine, and the node path,
+//! weights, clips and score of each sequence.
+
+use std::collections::BTreeMap;
+use std::os::raw::c_void;
+
+use super::graph::{Alignment, Graph};
+use super::{bases, consensus_support, create_engine, guarded, matrix_table, apply_ambiguity, alignment_type, Engine, Exception, Subtype, SIMD_SCALAR, AMBIGUITY_LITERAL};
+use crate::ReserveSupport;
+
+// POA_NO_SCORE, the score of a sequence that was not aligned when it was added
+const NO_SCORE: i32 = i32::MIN;
+
+// POA_ADD_FAILED, returned by the add functions for an error
+const ADD_FAILED: i32 = -1;
+
+// a graph together with the engine aligning sequences to it, and the node path of each sequence
+pub(crate) struct ReadGraph {
+    engine: Engine,
+    // the instruction set of the engine, always the scalar one
+    simd: i32,
+    graph: Graph,
+    paths: Vec<Vec<u32>>,
+    weights: Vec<Vec<u32>>,
+    // the number of leading and trailing bases of each sequence not aligned to an existing node
+    clips: Vec<(u32, u32)>,
+    // the score of the alignment each sequence was added along, or NO_SCORE
+    scores: Vec<i32>,
+}
+
+impl ReadGraph {
+    fn new(engine: Engine, simd: i32) -> ReadGraph {
+        ReadGraph { engine, simd, graph: Graph::default(), paths: vec![], weights: vec![], clips: vec![], scores: vec![] }
+    }
+}
+
+// returns the id of the graph node representing each base of a sequence that was just added to
+// the graph with the given alignment, when the graph held num_nodes nodes before. this follows
+// the order in which Graph::add_alignment creates nodes: first the unaligned prefix, then the
+// unaligned suffix, then the aligned bases without an existing node for their letter.
+fn sequence_path(graph: &Graph, alignment: &[(i32, i32)], sequence: &[u8], num_nodes: u32) -> Vec<u32> {
+    let mut next_node_id = num_nodes;
+    if alignment.is_empty() {
+        return (next_node_id..next_node_id + sequence.len() as u32).collect();
+    }
+
+    let mut path = vec![0; sequence.len()];
+    let positions: Vec<usize> = alignment.iter().filter(|column| column.1 != -1).map(|column| column.1 as usize).collect();
+    let (first, last) = (positions[0], positions[positions.len() - 1]);
+    let (prefix, suffix) = path.split_at_mut(last + 1);
+    for id in prefix[..first].iter_mut().chain(suffix) {
+        *id = next_node_id;
+        next_node_id += 1;
+    }
+
+    for &(node_id, position) in alignment {
+        if position == -1 {
+            continue;
+        }
+        let letter = sequence[position as usize];
+        let id = if node_id == -1 {
+            next_node_id += 1;
+            next_node_id - 1
+        } else if graph.base(node_id as u32) == letter {
+            node_id as u32
+        } else {
+            // a mismatch joins (or creates) the node for its letter among the aligned nodes
+            let aligned = graph.nodes[node_id as usize].aligned_nodes.iter().cloned().find(|&aligned| graph.base(aligned) == letter).unwrap_or(0);
+            if aligned >= num_nodes {
+                next_node_id += 1;
+            }
+            aligned
+        };
+        path[position as usize] = id;
+    }
+    path
+}
+
+// sums the weights of the sequences passing along each edge, keyed by (tail, head)
+fn edge_weights(g: &ReadGraph) -> BTreeMap<(u32, u32), u64> {
+    let mut edges = BTreeMap::new();
+    for (path, weights) in g.paths.iter().zip(&g.weights) {
+        for i in 1..path.len() {
+            // both nodes contribute to edge weight, as in spoa
+            *edges.entry((path[i - 1], path[i])).or_insert(0) += weights[i - 1] as u64 + weights[i] as u64;
+        }
+    }
+    edges
+}
+
+// returns the complement of a nucleotide (including IUPAC ambiguity codes), or the symbol itself
+// for other symbols. this is an involution, so distinct symbols have distinct complements.
+fn complement(c: u8) -> u8 {
+    let complemented = match c.to_ascii_uppercase() {
+        b'A' => b'T',
+        b'T' => b'A',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        _ => return c,
+    };
+    if c.is_ascii_lowercase() {
+        complemented.to_ascii_lowercase()
+    } else {
+        complemented
+    }
+}
+
+// adds to dst one sequence per non-empty path of src nodes (per path if keep_empty), spelling the
+// bases of the nodes (or their complements) with the given weights. every base is aligned to the
+// dst node already representing its src node, or else to a dst node representing a src node
+// aligned to it, which recreates the src nodes along the paths one by one together with their
+// alignment groups.
+#[allow(clippy::too_many_arguments)]
+fn add_node_paths(
+    dst: &mut ReadGraph,
+    src: &ReadGraph,
+    paths: &[Vec<u32>],
+    weights: &[Vec<u32>],
+    clips: &[(u32, u32)],
+    scores: &[i32],
+    complemented: bool,
+    keep_empty: bool,
+) -> Result<(), Exception> {
+    let nodes = &src.graph.nodes;
+    let mut mapping: Vec<i64> = vec![-1; nodes.len()];
+
+    for (s, path) in paths.iter().enumerate() {
+        if path.is_empty() {
+            if keep_empty {
+                dst.paths.push(vec![]);
+                dst.weights.push(vec![]);
+                dst.clips.push(clips[s]);
+                dst.scores.push(scores[s]);
+            }
+            continue;
+        }
+
+        let mut sequence = vec![];
+        let mut alignment = Alignment::new();
+        for (i, &id) in path.iter().enumerate() {
+            let base = src.graph.base(id);
+            sequence.push(if complemented { complement(base) } else { base });
+
+            let mut node_id = mapping[id as usize];
+            if node_id == -1 {
+                if let Some(&aligned) = nodes[id as usize].aligned_nodes.iter().find(|&&aligned| mapping[aligned as usize] != -1) {
+                    node_id = mapping[aligned as usize];
+                }
+            }
+            alignment.push((node_id as i32, i as i32));
+        }
+
+        let num_nodes = dst.graph.nodes.len() as u32;
+        dst.graph.add_alignment(&alignment, &sequence, &weights[s])?;
+        let dst_path = sequence_path(&dst.graph, &alignment, &sequence, num_nodes);
+
+        for (&id, &dst_id) in path.iter().zip(&dst_path) {
+            mapping[id as usize] = dst_id as i64;
+        }
+        dst.paths.push(dst_path);
+        dst.weights.push(weights[s].clone());
+        dst.clips.push(clips[s]);
+        dst.scores.push(scores[s]);
+    }
+    Ok(())
+}
+
+// adds the sequence along an alignment with the given score to the graph, under the limits of
+// poa_graph_add_checked
+#[allow(clippy::too_many_arguments)]
+unsafe fn add_alignment_checked(
+    g: &mut ReadGraph,
+    alignment: &[(i32, i32)],
+    score: i32,
+    seq: &[u8],
+    weights: *const u32,
+    max_nodes: u32,
+    max_inserted: u32,
+    min_identity: f64,
+    identity: *mut f64,
+) -> Result<i32, Exception> {
+    let num_nodes = g.graph.nodes.len() as u32;
+    let nodes = &g.graph.nodes;
+    // the identity to the backbone is measured on this alignment: a base matches if it is aligned
+    // to a backbone node, or a node aligned to one, holding its letter. every alignment column and
+    // every base left out of a local alignment counts towards the length.
+    if num_nodes > 0 && !g.paths.is_empty() && (min_identity > 0.0 || !identity.is_null()) {
+        let mut backbone = vec![false; num_nodes as usize];
+        for &id in &g.paths[0] {
+            backbone[id as usize] = true;
+        }
+        let (mut matches, mut aligned_bases) = (0u64, 0u64);
+        for &(node_id, position) in alignment {
+            if position == -1 {
+                continue;
+            }
+            aligned_bases += 1;
+            if node_id == -1 {
+                continue;
+            }
+            let letter = seq[position as usize];
+            let holds = |id: u32| backbone[id as usize] && g.graph.base(id) == letter;
+            if holds(node_id as u32) || nodes[node_id as usize].aligned_nodes.iter().any(|&aligned| holds(aligned)) {
+                matches += 1;
+            }
+        }
+        let columns = alignment.len() as u64 + (seq.len() as u64 - aligned_bases);
+        let read_identity = if columns == 0 { 0.0 } else { matches as f64 / columns as f64 };
+        if !identity.is_null() {
+            *identity = read_identity;
+        }
+        if read_identity < min_identity {
+            return Ok(3);
+        }
+    } else if !identity.is_null() {
+        *identity = 1.0;
+    }
+
+    // the bases not aligned to any node are inserted as new nodes
+    let inserted = seq.len() as u64 - alignment.iter().filter(|&&(node_id, position)| node_id != -1 && position != -1).count() as u64;
+    // every base of the first sequence is new, so it is exempt from the insertion limit
+    if num_nodes > 0 && inserted > max_inserted as u64 {
+        return Ok(2);
+    }
+
+    // so does each mismatch without a node for its letter among the aligned nodes
+    let mut new_nodes = inserted;
+    for &(node_id, position) in alignment {
+        if node_id == -1 || position == -1 {
+            continue;
+        }
+        let letter = seq[position as usize];
+        let node = &nodes[node_id as usize];
+        if g.graph.base(node_id as u32) != letter && node.aligned_nodes.iter().all(|&aligned| g.graph.base(aligned) != letter) {
+            new_nodes += 1;
+        }
+    }
+    if num_nodes as u64 + new_nodes > max_nodes as u64 {
+        return Ok(1);
+    }
+
+    let base_weights = if weights.is_null() { vec![1; seq.len()] } else { std::slice::from_raw_parts(weights, seq.len()).to_vec() };
+    g.graph.add_alignment(alignment, seq, &base_weights)?;
+    g.paths.push(sequence_path(&g.graph, alignment, seq, num_nodes));
+    g.weights.push(base_weights);
+
+    // the first sequence defines the graph, so none of its bases are clipped
+    let (mut leading, mut trailing) = (0, 0);
+    if num_nodes > 0 {
+        let mut aligned = alignment.iter().filter(|&&(node_id, position)| node_id != -1 && position != -1).map(|column| column.1 as u32);
+        match (aligned.next(), aligned.next_back()) {
+            (Some(first), last) => {
+                leading = first;
+                trailing = seq.len() as u32 - 1 - last.unwrap_or(first);
+            }
+            (None, _) => leading = seq.len() as u32,
+        }
+    }
+    g.clips.push((leading, trailing));
+    // the first sequence is not aligned to anything
+    g.scores.push(if num_nodes > 0 { score } else { NO_SCORE });
+    Ok(0)
+}
+
+// see the C header file (poa_func.h) for detailed descriptions of each argument
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn poa_graph_new(
+    alignment_type: i32,
+    match_score: i32,
+    mismatch_score: i32,
+    gap_open: i32,
+    gap_extend: i32,
+    gap_open2: i32,
+    gap_extend2: i32,
+    simd: i32,
+    ambiguity: i32,
+) -> *mut ReadGraph {
+    guarded(std::ptr::null_mut(), || {
+        let (engine, simd) = create_engine(alignment_type, match_score, mismatch_score, gap_open, gap_extend, gap_open2, gap_extend2, simd, ambiguity)?;
+        Ok(Box::into_raw(Box::new(ReadGraph::new(engine, simd))))
+    })
+}
+
+pub(crate) unsafe fn poa_graph_new_matrix(
+    alignment_type: i32,
+    gap_open: i32,
+    gap_extend: i32,
+    alphabet: *const u8,
+    alphabet_len: u32,
+    scores: *const i32,
+    ambiguity: i32,
+) -> *mut ReadGraph {
+    guarded(std::ptr::null_mut(), || {
+        // validates the alignment type and gap scores as poa_graph_new does
+        create_engine(alignment_type, 0, 0, gap_open, gap_extend, gap_open, gap_extend, SIMD_SCALAR, AMBIGUITY_LITERAL)?;
+        let (open, mut extend) = (gap_open as i8, gap_extend as i8);
+        let subtype = if open >= extend { Subtype::Linear } else { Subtype::Affine };
+        if subtype == Subtype::Linear {
+            extend = open;
+        }
+
+        let len = alphabet_len as usize;
+        let mut table = matrix_table(bases(alphabet, alphabet_len), std::slice::from_raw_parts(scores, len * len));
+        apply_ambiguity(&mut table, ambiguity)?;
+        let engine = Engine::matrix(self::alignment_type(alignment_type)?, subtype, open, extend, table);
+        Ok(Box::into_raw(Box::new(ReadGraph::new(engine, SIMD_SCALAR))))
+    })
+}
+
+pub(crate) unsafe fn poa_graph_simd(g: *const ReadGraph) -> i32 {
+    (*g).simd
+}
+
+pub(crate) unsafe fn poa_graph_free(g: *mut ReadGraph) {
+    drop(Box::from_raw(g));
+}
+
+pub(crate) unsafe fn poa_graph_clear(g: *mut ReadGraph) {
+    let g = &mut *g;
+    g.graph.clear();
+    g.paths.clear();
+    g.weights.clear();
+    g.clips.clear();
+    g.scores.clear();
+}
+
+pub(crate) unsafe fn poa_graph_add(g: *mut ReadGraph, seq: *const u8, seq_len: u32) {
+    poa_graph_add_limited(g, seq, seq_len, u32::MAX, u32::MAX);
+}
+
+pub(crate) unsafe fn poa_graph_alignment_score(g: *mut ReadGraph, seq: *const u8, seq_len: u32) -> i32 {
+    let g = &mut *g;
+    guarded(0, || {
+        if g.graph.nodes.is_empty() {
+            return Ok(0);
+        }
+        Ok(g.engine.align(bases(seq, seq_len), &g.graph)?.1)
+    })
+}
+
+pub(crate) unsafe fn poa_graph_align(g: *mut ReadGraph, seq: *const u8, seq_len: u32, node_ids: *mut i32, seq_positions: *mut i32, score: *mut i32) -> u32 {
+    let g = &mut *g;
+    *score = 0;
+    guarded(0, || {
+        if g.graph.nodes.is_empty() {
+            return Ok(0);
+        }
+        let (alignment, alignment_score) = g.engine.align(bases(seq, seq_len), &g.graph)?;
+        *score = alignment_score;
+        for (i, &(node_id, position)) in alignment.iter().enumerate() {
+            *node_ids.add(i) = node_id;
+            *seq_positions.add(i) = position;
+        }
+        Ok(alignment.len() as u32)
+    })
+}
+
+pub(crate) unsafe fn poa_graph_add_limited(g: *mut ReadGraph, seq: *const u8, seq_len: u32, max_nodes: u32, max_inserted: u32) -> i32 {
+    poa_graph_add_weighted(g, seq, seq_len, std::ptr::null(), max_nodes, max_inserted)
+}
+
+pub(crate) unsafe fn poa_graph_add_weighted(g: *mut ReadGraph, seq: *const u8, seq_len: u32, weights: *const u32, max_nodes: u32, max_inserted: u32) -> i32 {
+    poa_graph_add_checked(g, seq, seq_len, weights, max_nodes, max_inserted, 0.0, std::ptr::null_mut())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn poa_graph_add_checked(
+    g: *mut ReadGraph,
+    seq: *const u8,
+    seq_len: u32,
+    weights: *const u32,
+    max_nodes: u32,
+    max_inserted: u32,
+    min_identity: f64,
+    identity: *mut f64,
+) -> i32 {
+    let g = &mut *g;
+    guarded(ADD_FAILED, || {
+        let seq = bases(seq, seq_len);
+        let (alignment, score) = g.engine.align(seq, &g.graph)?;
+        add_alignment_checked(g, &alignment, score, seq, weights, max_nodes, max_inserted, min_identity, identity)
+    })
+}
+
+pub(crate) unsafe fn poa_graph_add_anchored(g: *mut ReadGraph, seq: *const u8, seq_len: u32, weights: *const u32, begin: u32, end: u32) -> i32 {
+    let g = &mut *g;
+    guarded(ADD_FAILED, || {
+        if g.paths.is_empty() || begin >= end || begin as usize >= g.paths[0].len() {
+            return Ok(poa_graph_add_checked(g, seq, seq_len, weights, u32::MAX, u32::MAX, 0.0, std::ptr::null_mut()));
+        }
+        // the backbone is the first sequence, whose node ids increase along it as the subgraph
+        // extraction expects
+        let backbone = &g.paths[0];
+        let begin_node = backbone[begin as usize];
+        let end_node = backbone[(end as usize).min(backbone.len()) - 1];
+
+        let (subgraph, mapping) = g.graph.subgraph(begin_node, end_node);
+        let seq = bases(seq, seq_len);
+        let (mut alignment, score) = g.engine.align(seq, &subgraph)?;
+        Graph::update_alignment(&mapping, &mut alignment);
+        add_alignment_checked(g, &alignment, score, seq, weights, u32::MAX, u32::MAX, 0.0, std::ptr::null_mut())
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn poa_graph_add_alignment(
+    g: *mut ReadGraph,
+    seq: *const u8,
+    seq_len: u32,
+    weights: *const u32,
+    node_ids: *const i32,
+    seq_positions: *const i32,
+    alignment_len: u32,
+    score: i32,
+) {
+    poa_graph_add_alignment_checked(g, seq, seq_len, weights, node_ids, seq_positions, alignment_len, score, u32::MAX, u32::MAX, 0.0, std::ptr::null_mut());
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn poa_graph_add_alignment_checked(
+    g: *mut ReadGraph,
+    seq: *const u8,
+    seq_len: u32,
+    weights: *const u32,
+    node_ids: *const i32,
+    seq_positions: *const i32,
+    alignment_len: u32,
+    score: i32,
+    max_nodes: u32,
+    max_inserted: u32,
+    min_identity: f64,
+    identity: *mut f64,
+) -> i32 {
+    let g = &mut *g;
+    guarded(ADD_FAILED, || {
+        let alignment: Alignment = (0..alignment_len as usize).map(|i| (*node_ids.add(i), *seq_positions.add(i))).collect();
+        add_alignment_checked(g, &alignment, score, bases(seq, seq_len), weights, max_nodes, max_inserted, min_identity, identity)
+    })
+}
+
+pub(crate) unsafe fn poa_graph_add_subgraph(dst: *mut ReadGraph, src: *const ReadGraph, from_node: u32, to_node: u32) {
+    let (dst, src) = (&mut *dst, &*src);
+    guarded((), || {
+        // the region holds the nodes reachable from from_node that reach to_node
+        let num_nodes = src.graph.nodes.len();
+        let mut out_edges = vec![vec![]; num_nodes];
+        let mut in_edges = vec![vec![]; num_nodes];
+        for &(tail, head) in edge_weights(src).keys() {
+            out_edges[tail as usize].push(head);
+            in_edges[head as usize].push(tail);
+        }
+
+        let reachable = |start: u32, edges: &[Vec<u32>]| {
+            let mut visited = vec![false; num_nodes];
+            let mut stack = vec![start];
+            visited[start as usize] = true;
+            while let Some(id) = stack.pop() {
+                for &next in &edges[id as usize] {
+                    if !visited[next as usize] {
+                        visited[next as usize] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+            visited
+        };
+        let after_from = reachable(from_node, &out_edges);
+        let before_to = reachable(to_node, &in_edges);
+
+        // the nodes of a sequence path inside the region are consecutive, so each sequence adds at
+        // most one fragment
+        let mut fragments = vec![vec![]; src.paths.len()];
+        let mut weights = vec![vec![]; src.paths.len()];
+        for (s, path) in src.paths.iter().enumerate() {
+            for (i, &id) in path.iter().enumerate() {
+                if after_from[id as usize] && before_to[id as usize] {
+                    fragments[s].push(id);
+                    weights[s].push(src.weights[s][i]);
+                }
+            }
+        }
+
+        // the fragments are parts of the recreated graph, not clipped against it, and were never
+        // aligned on their own
+        let clips = vec![(0, 0); src.paths.len()];
+        let scores = vec![NO_SCORE; src.paths.len()];
+        add_node_paths(dst, src, &fragments, &weights, &clips, &scores, false, false)
+    })
+}
+
+pub(crate) unsafe fn poa_graph_add_reverse_complement(dst: *mut ReadGraph, src: *const ReadGraph) {
+    let (dst, src) = (&mut *dst, &*src);
+    guarded((), || {
+        let mut paths = src.paths.clone();
+        let mut weights = src.weights.clone();
+        let mut clips = src.clips.clone();
+        for s in 0..paths.len() {
+            paths[s].reverse();
+            weights[s].reverse();
+            clips[s] = (clips[s].1, clips[s].0);
+        }
+
+        // the reverse complement of an alignment scores as the alignment itself
+        add_node_paths(dst, src, &paths, &weights, &clips, &src.scores, true, false)
+    })
+}
+
+pub(crate) unsafe fn poa_graph_add_pruned(dst: *mut ReadGraph, src: *const ReadGraph, min_reads: u32) {
+    let (dst, src) = (&mut *dst, &*src);
+    guarded((), || {
+        // the number of sequences through each node and along each edge
+        let nodes = &src.graph.nodes;
+        let num_nodes = nodes.len();
+        let mut node_reads = vec![0u32; num_nodes];
+        let mut edge_reads: BTreeMap<(u32, u32), u32> = BTreeMap::new();
+        for path in &src.paths {
+            for i in 0..path.len() {
+                node_reads[path[i] as usize] += 1;
+                if i > 0 {
+                    *edge_reads.entry((path[i - 1], path[i])).or_insert(0) += 1;
+                }
+            }
+        }
+        let strong_edge = |from: u32, to: u32| matches!(edge_reads.get(&(from, to)), Some(&reads) if reads >= min_reads);
+
+        // a weak node folds into the kept node aligned to it with the most reads, the first among
+        // equals, and is dropped if there is none
+        let mut folded: Vec<Option<u32>> = vec![None; num_nodes];
+        for id in 0..num_nodes {
+            if node_reads[id] >= min_reads {
+                folded[id] = Some(id as u32);
+                continue;
+            }
+            for &aligned in &nodes[id].aligned_nodes {
+                let reads = node_reads[aligned as usize];
+                if reads >= min_reads && !matches!(folded[id], Some(kept) if reads <= node_reads[kept as usize]) {
+                    folded[id] = Some(aligned);
+                }
+            }
+        }
+
+        // the tail of the strong edge into each node along which the most sequences pass
+        let mut strongest_in: Vec<Option<u32>> = vec![None; num_nodes];
+        for (&(from, to), &reads) in &edge_reads {
+            if reads >= min_reads && !matches!(strongest_in[to as usize], Some(tail) if reads <= edge_reads[&(tail, to)]) {
+                strongest_in[to as usize] = Some(from);
+            }
+        }
+        let mut rank = vec![0; num_nodes];
+        for (r, &id) in src.graph.rank_to_node.iter().enumerate() {
+            rank[id as usize] = r;
+        }
+
+        let mut paths = vec![vec![]; src.paths.len()];
+        let mut weights = vec![vec![]; src.paths.len()];
+        for (s, src_path) in src.paths.iter().enumerate() {
+            let (path, path_weights) = (&mut paths[s], &mut weights[s]);
+            for (i, &id) in src_path.iter().enumerate() {
+                let node_id = match folded[id as usize] {
+                    Some(node_id) => node_id,
+                    None => continue,
+                };
+                let weight = src.weights[s][i];
+
+                // a sequence skipping kept nodes along a weak edge takes the strongest path of strong
+                // edges between its ends instead, with the weight of the base after it
+                if let Some(&last) = path.last() {
+                    if !strong_edge(last, node_id) {
+                        let mut detour = vec![];
+                        let mut prev = strongest_in[node_id as usize];
+                        while let Some(p) = prev {
+                            if rank[p as usize] <= rank[last as usize] {
+                                break;
+                            }
+                            detour.push(p);
+                            prev = strongest_in[p as usize];
+                        }
+                        if prev == Some(last) {
+                            path.extend(detour.iter().rev());
+                            path_weights.resize(path_weights.len() + detour.len(), weight);
+                        }
+                    }
+                }
+                path.push(node_id);
+                path_weights.push(weight);
+            }
+        }
+
+        // the pruned sequences are parts of the recreated graph, not clipped against it, and were
+        // never aligned as they are
+        let clips = vec![(0, 0); src.paths.len()];
+        let scores = vec![NO_SCORE; src.paths.len()];
+        add_node_paths(dst, src, &paths, &weights, &clips, &scores, false, true)
+    })
+}
+
+pub(crate) unsafe fn poa_graph_num_sequences(g: *const ReadGraph) -> u32 {
+    (*g).paths.len() as u32
+}
+
+pub(crate) unsafe fn poa_graph_consensus(g: *mut ReadGraph, consensus: *mut u8, consensus_len: u32) -> u32 {
+    let g = &mut *g;
+    if g.graph.nodes.is_empty() {
+        return 0;
+    }
+    let cns = g.graph.generate_consensus();
+    let len = cns.len().min(consensus_len as usize);
+    std::ptr::copy_nonoverlapping(cns.as_ptr(), consensus, len);
+    len as u32
+}
+
+pub(crate) unsafe fn poa_graph_consensus_support(g: *mut ReadGraph, buffers: *mut c_void, reserve: ReserveSupport) -> usize {
+    let g = &mut *g;
+    if g.graph.nodes.is_empty() {
+        return 0;
+    }
+    consensus_support(&mut g.graph, buffers, reserve)
+}
+
+pub(crate) unsafe fn poa_graph_consensus_path(g: *mut ReadGraph, node_ids: *mut u32, node_ids_len: u32) -> u32 {
+    let g = &mut *g;
+    if g.graph.nodes.is_empty() {
+        return 0;
+    }
+    g.graph.generate_consensus();
+    let len = g.graph.consensus.len().min(node_ids_len as usize);
+    std::ptr::copy_nonoverlapping(g.graph.consensus.as_ptr(), node_ids, len);
+    len as u32
+}
+
+pub(crate) unsafe fn poa_graph_msa_len(g: *mut ReadGraph) -> u32 {
+    let g = &mut *g;
+    if g.graph.nodes.is_empty() {
+        return 0;
+    }
+    g.graph.multiple_sequence_alignment(false).first().map_or(0, |row| row.len() as u32)
+}
+
+pub(crate) unsafe fn poa_graph_msa(g: *mut ReadGraph, mut rows: *mut u8, include_consensus: i32) {
+    let g = &mut *g;
+    if g.graph.nodes.is_empty() {
+        return;
+    }
+
+    // spoa leaves empty sequences out of the alignment, so they are written as rows of gaps
+    let msa = g.graph.multiple_sequence_alignment(include_consensus != 0);
+    let len = msa[0].len();
+    let gaps = vec![b'-'; len];
+    let mut next = 0;
+    for path in &g.paths {
+        let row = if path.is_empty() {
+            &gaps
+        } else {
+            next += 1;
+            &msa[next - 1]
+        };
+        std::ptr::copy_nonoverlapping(row.as_ptr(), rows, len);
+        rows = rows.add(len);
+    }
+    if include_consensus != 0 {
+        std::ptr::copy_nonoverlapping(msa[msa.len() - 1].as_ptr(), rows, len);
+    }
+}
+
+pub(crate) unsafe fn poa_graph_num_nodes(g: *const ReadGraph) -> u32 {
+    (*g).graph.nodes.len() as u32
+}
+
+pub(crate) unsafe fn poa_graph_nodes(g: *const ReadGraph, bases: *mut u8, weights: *mut u64) {
+    let g = &*g;
+    for id in 0..g.graph.nodes.len() {
+        *bases.add(id) = g.graph.base(id as u32);
+        *weights.add(id) = 0;
+    }
+    for (path, base_weights) in g.paths.iter().zip(&g.weights) {
+        for (&id, &weight) in path.iter().zip(base_weights) {
+            *weights.add(id as usize) += weight as u64;
+        }
+    }
+}
+
+pub(crate) unsafe fn poa_graph_num_edges(g: *const ReadGraph) -> u32 {
+    edge_weights(&*g).len() as u32
+}
+
+pub(crate) unsafe fn poa_graph_edges(g: *const ReadGraph, tails: *mut u32, heads: *mut u32, weights: *mut u64) {
+    for (i, (&(tail, head), &weight)) in edge_weights(&*g).iter().enumerate() {
+        *tails.add(i) = tail;
+        *heads.add(i) = head;
+        *weights.add(i) = weight;
+    }
+}
+
+pub(crate) unsafe fn poa_graph_sequence_len(g: *const ReadGraph, seq_index: u32) -> u32 {
+    let g = &*g;
+    g.paths[seq_index as usize].len() as u32
+}
+
+pub(crate) unsafe fn poa_graph_sequence_path(g: *const ReadGraph, seq_index: u32, node_ids: *mut u32) {
+    let path = &(&*g).paths[seq_index as usize];
+    std::ptr::copy_nonoverlapping(path.as_ptr(), node_ids, path.len());
+}
+
+pub(crate) unsafe fn poa_graph_sequence_weights(g: *const ReadGraph, seq_index: u32, weights: *mut u32) {
+    let base_weights = &(&*g).weights[seq_index as usize];
+    std::ptr::copy_nonoverlapping(base_weights.as_ptr(), weights, base_weights.len());
+}
+
+pub(crate) unsafe fn poa_graph_sequence_clips(g: *const ReadGraph, seq_index: u32, leading: *mut u32, trailing: *mut u32) {
+    let (first, second) = (&*g).clips[seq_index as usize];
+    *leading = first;
+    *trailing = second;
+}
+
+pub(crate) unsafe fn poa_graph_sequence_score(g: *const ReadGraph, seq_index: u32) -> i32 {
+    let g = &*g;
+    g.scores[seq_index as usize]
+}
+
+pub(crate) unsafe fn poa_graph_aligned_partners(g: *const ReadGraph, partners: *mut i32) {
+    for (id, node) in (*g).graph.nodes.iter().enumerate() {
+        *partners.add(id) = -1;
+        for &aligned in &node.aligned_nodes {
+            if (aligned as usize) < id {
+                *partners.add(id) = aligned as i32;
+            }
+        }
+    }
+}
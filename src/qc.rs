@@ -0,0 +1,158 @@
+//! Per-read quality control against the consensus, e.g. to flag contaminating reads of a UMI
+//! family without aligning them again.
+
+use crate::{PoaGraph, PoaParams, SoftClips};
+
+/// The quality control metrics of one read against the consensus of its graph.
+///
+/// The counts come from the alignment of the read to the consensus in the multiple sequence
+/// alignment, as by `PoaGraph::read_alignments`; bases the read was not aligned to the graph with
+/// have columns of their own there, so they count as insertions unless the consensus runs through
+/// them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReadQc {
+    /// the fraction of the alignment columns of the read that are matches, 0 for a read without bases
+    pub identity: f64,
+    /// the number of bases matching the consensus
+    pub matches: usize,
+    /// the number of bases differing from the consensus
+    pub mismatches: usize,
+    /// the number of bases missing from the consensus
+    pub insertions: usize,
+    /// the number of consensus bases missing from the read, between its first and last base
+    pub deletions: usize,
+    /// the number of bases of the read
+    pub length: usize,
+    /// the bases at either end of the read left unaligned when it was added
+    pub clips: SoftClips,
+}
+
+impl ReadQc {
+    /// Returns the number of bases of the read between its clips, which were aligned to the graph.
+    pub fn clipped_length(&self) -> usize {
+        self.length - self.clips.leading - self.clips.trailing
+    }
+
+    /// Returns the number of insertions and deletions.
+    pub fn indels(&self) -> usize {
+        self.insertions + self.deletions
+    }
+}
+
+impl PoaGraph {
+    /// Returns the quality control metrics of each sequence of the graph against the consensus,
+    /// in the order they were added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     for seq in ["AATGCCCGTT", "AATGCCCGTT", "AATGCCGTT", "AATCCCCGTT"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     let qc = graph.read_qc();
+    ///     assert_eq!(qc[0].identity, 1.0);
+    ///     assert_eq!((qc[2].deletions, qc[2].length), (1, 9));
+    ///     assert_eq!((qc[3].mismatches, qc[3].identity), (1, 0.9));
+    /// ```
+    pub fn read_qc(&self) -> Vec<ReadQc> {
+        self.read_alignments()
+            .iter()
+            .enumerate()
+            .map(|(i, alignment)| {
+                let (matches, mismatches, insertions, deletions) = alignment.counts();
+                ReadQc {
+                    identity: alignment.identity(),
+                    matches,
+                    mismatches,
+                    insertions,
+                    deletions,
+                    length: matches + mismatches + insertions,
+                    clips: self.soft_clips(i),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Generates a consensus sequence together with the quality control metrics of each read against it.
+///
+/// Trailing null terminators are ignored; empty reads are not aligned and get metrics of all 0.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus and the metrics of each read, in the order of `seqs`
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_read_qc, PoaParams};
+///
+///     let family = ["AATGCCCGTT", "AATGCCCGTT", "AATGCCCGTT", "", "GGCATTAAGC"];
+///     let (consensus, qc) = poa_consensus_with_read_qc(&family, &PoaParams::new(1, 5, -4, -8, -6));
+///
+///     assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(qc.len(), 5);
+///     assert_eq!(qc[3].length, 0);
+///     // the unrelated read is flagged by its identity
+///     assert!(qc.iter().take(3).all(|read| read.identity == 1.0));
+///     assert!(qc[4].identity < 0.5);
+/// ```
+pub fn poa_consensus_with_read_qc<T: AsRef<[u8]>>(seqs: &[T], params: &PoaParams) -> (Vec<u8>, Vec<ReadQc>) {
+    let mut graph = PoaGraph::new(params);
+    let mut indices = vec![];
+    for (i, seq) in seqs.iter().enumerate() {
+        let seq = seq.as_ref();
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        if !seq.is_empty() {
+            graph.add_sequence(seq);
+            indices.push(i);
+        }
+    }
+
+    let mut qc = vec![ReadQc::default(); seqs.len()];
+    for (read, metrics) in indices.into_iter().zip(graph.read_qc()) {
+        qc[read] = metrics;
+    }
+    (graph.consensus(), qc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clipped_reads() {
+        let params = PoaParams::new(0, 5, -4, -8, -6);
+        let reads = ["GATTACAGGCTTACCG", "GATTACAGGCTTACCG", "TTTTTTGATTACAGGCTTACCGAAAA", "GATTACAGGCTACCG\0"];
+        let (consensus, qc) = poa_consensus_with_read_qc(&reads, &params);
+        // the heaviest bundle runs on through the clipped bases, which only the third read has
+        assert_eq!(consensus, b"TTTTTTGATTACAGGCTTACCGAAAA".to_vec());
+
+        for (read, seq) in qc.iter().zip(reads.iter()) {
+            assert_eq!(read.length, seq.trim_end_matches('\0').len());
+            assert_eq!(read.matches + read.mismatches + read.insertions, read.length);
+        }
+        assert_eq!(qc[2].clips, SoftClips { leading: 6, trailing: 4 });
+        assert_eq!(qc[2].clipped_length(), 16);
+        assert_eq!((qc[2].matches, qc[2].identity), (26, 1.0));
+        assert_eq!((qc[0].matches, qc[0].deletions, qc[0].clips), (16, 0, SoftClips::default()));
+        assert_eq!((qc[3].deletions, qc[3].indels(), qc[3].clipped_length()), (1, 1, 15));
+        assert!((qc[3].identity - 15.0 / 16.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let (consensus, qc) = poa_consensus_with_read_qc(&["\0", ""], &params);
+        assert!(consensus.is_empty());
+        assert_eq!(qc, vec![ReadQc::default(); 2]);
+        assert!(PoaGraph::new(&params).read_qc().is_empty());
+    }
+}
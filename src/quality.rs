@@ -0,0 +1,357 @@
+//! Calibrated Phred qualities for consensus bases.
+//!
+//! A `QualityModel` maps the depth and the agreeing fraction of the reads covering a consensus
+//! base to the probability that the base is wrong. Untrained models derive this probability from
+//! the typical read error rate of the sequencing platform; trained models use the empirical error
+//! rates observed against a truth set, shrunk towards the platform prior where data is sparse.
+//! The agreement model skips both and takes the disagreeing fraction as the error probability.
+
+use crate::align::{align, AlignOp};
+use crate::msa::alignment_columns;
+use crate::{consensus_support, PoaGraph, PoaParams};
+
+/// The depths above this value share a calibration bin.
+const MAX_CALIBRATION_DEPTH: u32 = 30;
+/// The number of bins the agreeing fraction is divided into for calibration.
+const SUPPORT_BINS: usize = 10;
+/// The weight of the platform prior in a calibration bin, in pseudo-observations.
+const PRIOR_WEIGHT: f64 = 10.0;
+/// The highest quality written by Phred+33 as a printable character, `~`.
+const MAX_FASTQ_QUALITY: u8 = 93;
+
+/// A sequencing platform, determining the read error rate assumed by an untrained `QualityModel`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Platform {
+    Illumina,
+    Nanopore,
+    PacBioHifi,
+}
+
+impl Platform {
+    /// Returns the typical per-base error rate of a single read from this platform.
+    pub fn read_error_rate(&self) -> f64 {
+        match self {
+            Platform::Illumina => 0.005,
+            Platform::Nanopore => 0.05,
+            Platform::PacBioHifi => 0.002,
+        }
+    }
+}
+
+/// A consensus base with known correctness, used to train a `QualityModel`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CalibrationSample {
+    /// the number of reads covering the base
+    pub depth: u32,
+    /// the fraction of the covering reads agreeing with the base
+    pub support: f32,
+    /// whether the base matches the truth sequence
+    pub correct: bool,
+}
+
+/// Maps the depth and agreeing fraction of a consensus base to a calibrated Phred quality.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QualityModel {
+    read_error_rate: f64,
+    max_quality: u8,
+    // empirical error probabilities per (depth, support) bin, if trained
+    calibration: Option<Vec<f64>>,
+    // whether the error probability is the disagreeing fraction, for the agreement model
+    agreement: bool,
+}
+
+impl QualityModel {
+    /// Creates an untrained model for reads from `platform`, with qualities capped at 60.
+    pub fn new(platform: Platform) -> QualityModel {
+        QualityModel {
+            read_error_rate: platform.read_error_rate(),
+            max_quality: 60,
+            calibration: None,
+            agreement: false,
+        }
+    }
+
+    /// Creates a model taking the fraction of the covering reads disagreeing with a base as the
+    /// probability that it is wrong, whatever the depth, with qualities capped at 60.
+    ///
+    /// This is the plain agreement of the reads converted to Phred: a base with 9 of 10 reads
+    /// agreeing has quality 10. It needs no platform, but unlike `new` it gives a base covered by a
+    /// single read the highest quality.
+    pub fn agreement() -> QualityModel {
+        QualityModel {
+            agreement: true,
+            ..QualityModel::new(Platform::Illumina)
+        }
+    }
+
+    /// Trains a model on consensus bases of known correctness, e.g. from `calibration_samples`.
+    pub fn train<I>(platform: Platform, samples: I) -> QualityModel
+    where
+        I: IntoIterator<Item = CalibrationSample>,
+    {
+        let mut model = QualityModel::new(platform);
+
+        let num_bins = (MAX_CALIBRATION_DEPTH as usize + 1) * SUPPORT_BINS;
+        let mut observed = vec![0.0; num_bins];
+        let mut errors = vec![0.0; num_bins];
+        for sample in samples {
+            let bin = calibration_bin(sample.depth, sample.support);
+            observed[bin] += 1.0;
+            if !sample.correct {
+                errors[bin] += 1.0;
+            }
+        }
+
+        let mut calibration = vec![0.0; num_bins];
+        for depth in 0..=MAX_CALIBRATION_DEPTH {
+            for support_bin in 0..SUPPORT_BINS {
+                let bin = calibration_bin(depth, (support_bin as f32 + 0.5) / SUPPORT_BINS as f32);
+                let prior = model.prior_error_probability(depth, (support_bin as f32 + 0.5) / SUPPORT_BINS as f32);
+                calibration[bin] = (errors[bin] + PRIOR_WEIGHT * prior) / (observed[bin] + PRIOR_WEIGHT);
+            }
+        }
+
+        model.calibration = Some(calibration);
+        model
+    }
+
+    /// Sets the highest quality the model assigns; maxima above 93, the highest quality Phred+33
+    /// writes as a printable character (`~`), are clamped to 93.
+    pub fn max_quality(mut self, max_quality: u8) -> QualityModel {
+        self.max_quality = max_quality.min(MAX_FASTQ_QUALITY);
+        self
+    }
+
+    /// Returns the probability that a consensus base with the given depth and agreeing fraction is wrong.
+    pub fn error_probability(&self, depth: u32, support: f32) -> f64 {
+        let p = match &self.calibration {
+            Some(calibration) => calibration[calibration_bin(depth, support)],
+            None if self.agreement => if depth == 0 { 1.0 } else { 1.0 - support.clamp(0.0, 1.0) as f64 },
+            None => self.prior_error_probability(depth, support),
+        };
+
+        p.max(10f64.powf(-(self.max_quality as f64) / 10.0))
+    }
+
+    /// Returns the Phred quality of a consensus base with the given depth and agreeing fraction.
+    pub fn phred(&self, depth: u32, support: f32) -> u8 {
+        let q = -10.0 * self.error_probability(depth, support).log10();
+        q.round().clamp(0.0, self.max_quality as f64) as u8
+    }
+
+    /// Returns the FASTQ quality string (Phred+33) for consensus bases with the given depths and agreeing fractions.
+    pub fn qualities(&self, depth: &[u32], support: &[f32]) -> Vec<u8> {
+        depth.iter().zip(support).map(|(&d, &s)| self.phred(d, s) + 33).collect()
+    }
+
+    // the probability that the consensus base is wrong, if every disagreeing read supports the
+    // same alternative and read errors are spread evenly over the three other bases
+    fn prior_error_probability(&self, depth: u32, support: f32) -> f64 {
+        if depth == 0 {
+            return 0.75;
+        }
+
+        let e = self.read_error_rate;
+        let margin = depth as f64 * (2.0 * support.clamp(0.0, 1.0) as f64 - 1.0);
+        let log_likelihood_ratio = margin * ((1.0 - e) / (e / 3.0)).ln();
+
+        (1.0 / (1.0 + log_likelihood_ratio.exp())).min(0.75)
+    }
+}
+
+fn calibration_bin(depth: u32, support: f32) -> usize {
+    let depth = depth.min(MAX_CALIBRATION_DEPTH) as usize;
+    let support_bin = ((support.clamp(0.0, 1.0) * SUPPORT_BINS as f32) as usize).min(SUPPORT_BINS - 1);
+    depth * SUPPORT_BINS + support_bin
+}
+
+/// Compares a consensus with its known truth sequence, labelling each consensus base as correct or not.
+///
+/// The consensus is globally aligned to the truth; a base is correct if it is aligned to an
+/// identical truth base. `depth` and `support` hold the per-base values of the consensus.
+pub fn calibration_samples(consensus: &[u8], depth: &[u32], support: &[f32], truth: &[u8]) -> Vec<CalibrationSample> {
+    let mut samples = Vec::with_capacity(consensus.len());
+    let mut i = 0;
+
+    for op in align(consensus, truth) {
+        if op == AlignOp::Deletion {
+            continue;
+        }
+        samples.push(CalibrationSample {
+            depth: depth[i],
+            support: support[i],
+            correct: op == AlignOp::Match,
+        });
+        i += 1;
+    }
+
+    samples
+}
+
+/// Generates a consensus sequence together with its FASTQ quality string under the given quality model.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `model` - the quality model mapping per-base depth and agreement to Phred qualities
+///
+/// # Returns
+/// * returns the consensus and its Phred+33 quality string, of the same length
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_quality, Platform, PoaParams, QualityModel};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(); 5];
+///     let model = QualityModel::new(Platform::Nanopore);
+///     let (consensus, qual) = poa_consensus_with_quality(&seqs, 20, &PoaParams::new(1, 5, -4, -3, -1), &model);
+///
+///     assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(qual.len(), consensus.len());
+/// ```
+pub fn poa_consensus_with_quality<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    model: &QualityModel
+) -> (Vec<u8>, Vec<u8>) {
+
+    let (consensus, support, depth, _) = consensus_support(seqs, consensus_max_length, params);
+    let qualities = model.qualities(&depth, &support);
+
+    (consensus, qualities)
+}
+
+impl PoaGraph {
+    /// Returns the consensus of the graph together with its Phred+33 quality string under `model`.
+    ///
+    /// The depth of a consensus base is the number of reads spanning its column of the multiple
+    /// sequence alignment, and its support is the weight of those reads holding the base over the
+    /// weight of all of them, so bases weighted when added, e.g. by their qualities, count for
+    /// more. Bases are compared ignoring case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams, QualityModel};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -3, -1));
+    ///     for seq in ["AATGCCCGTT", "AATGCCCGTT", "AATGCCCGTT", "AATGCACGTT"].iter() {
+    ///         graph.add_sequence(seq.as_bytes());
+    ///     }
+    ///
+    ///     let (consensus, qual) = graph.consensus_with_quality(&QualityModel::agreement());
+    ///     assert_eq!(consensus, b"AATGCCCGTT".to_vec());
+    ///     // three reads in four agree with the sixth base, a quality of 6
+    ///     assert_eq!(qual, b"]]]]]']]]]".to_vec());
+    /// ```
+    pub fn consensus_with_quality(&self, model: &QualityModel) -> (Vec<u8>, Vec<u8>) {
+        let mut msa = self.multiple_sequence_alignment(true);
+        let consensus_row = msa.pop().unwrap_or_default();
+        let weights: Vec<Vec<f64>> =
+            (0..self.num_sequences()).map(|i| self.sequence_weights(i).into_iter().map(f64::from).collect()).collect();
+        let columns = alignment_columns(&msa, Some(&weights));
+
+        let mut consensus = Vec::new();
+        let (mut depth, mut support) = (Vec::new(), Vec::new());
+        for (column, &base) in columns.iter().zip(&consensus_row).filter(|&(_, &base)| base != b'-') {
+            let total: f64 = column.calls.iter().map(|&(_, _, weight)| weight).sum();
+            let agreeing: f64 =
+                column.calls.iter().filter(|&&(_, symbol, _)| symbol.eq_ignore_ascii_case(&base)).map(|&(_, _, weight)| weight).sum();
+            consensus.push(base);
+            depth.push(column.depth() as u32);
+            support.push(if total > 0.0 { (agreeing / total) as f32 } else { 0.0 });
+        }
+
+        let qualities = model.qualities(&depth, &support);
+        (consensus, qualities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prior_quality_increases_with_evidence() {
+        let model = QualityModel::new(Platform::Nanopore);
+
+        assert!(model.phred(2, 1.0) < model.phred(5, 1.0));
+        assert!(model.phred(10, 0.6) < model.phred(10, 0.9));
+        assert_eq!(model.phred(100, 1.0), 60);
+        assert_eq!(model.phred(0, 0.0), 1);
+        // a tie between two alleles is a coin flip
+        assert_eq!(model.phred(10, 0.5), 3);
+    }
+
+    #[test]
+    fn test_platform_error_rates_affect_quality() {
+        let illumina = QualityModel::new(Platform::Illumina);
+        let nanopore = QualityModel::new(Platform::Nanopore);
+
+        assert!(illumina.phred(3, 1.0) > nanopore.phred(3, 1.0));
+    }
+
+    #[test]
+    fn test_training_calibrates_bins() {
+        // bases at depth 5 with full agreement turn out to be wrong 10% of the time
+        let samples = (0..1000).map(|i| CalibrationSample { depth: 5, support: 1.0, correct: i % 10 != 0 });
+        let model = QualityModel::train(Platform::Nanopore, samples);
+
+        assert_eq!(model.phred(5, 1.0), 10);
+        // bins without observations keep the platform prior
+        assert_eq!(model.phred(20, 1.0), QualityModel::new(Platform::Nanopore).phred(20, 1.0));
+    }
+
+    #[test]
+    fn test_calibration_samples() {
+        let samples = calibration_samples(b"ACGGT", &[3, 4, 5, 6, 7], &[1.0, 1.0, 0.5, 1.0, 0.9], b"ACGT");
+
+        assert_eq!(samples.len(), 5);
+        assert_eq!(samples.iter().filter(|s| !s.correct).count(), 1);
+        assert_eq!(samples[1], CalibrationSample { depth: 4, support: 1.0, correct: true });
+    }
+
+    #[test]
+    fn test_agreement_model() {
+        let model = QualityModel::agreement();
+
+        assert_eq!(model.phred(10, 0.9), 10);
+        assert_eq!(model.phred(1, 1.0), 60);
+        assert_eq!(model.phred(3, 0.0), 0);
+        assert_eq!(model.phred(0, 0.0), 0);
+        assert_eq!(QualityModel::agreement().max_quality(30).phred(100, 1.0), 30);
+    }
+
+    #[test]
+    fn test_graph_qualities_use_weights() {
+        let mut graph = PoaGraph::new(&PoaParams::new(1, 5, -4, -8, -6));
+        graph.add_sequence_weighted(b"ACGTACGT", &[30; 8]);
+        graph.add_sequence_weighted(b"ACGTACGT", &[30; 8]);
+        graph.add_sequence_weighted(b"ACGAACGT", &[20; 8]);
+
+        let (consensus, qual) = graph.consensus_with_quality(&QualityModel::agreement());
+        assert_eq!(consensus, graph.consensus());
+        assert_eq!(qual.len(), consensus.len());
+        // a quarter of the weight disagrees at the fourth base, a quality of 6 rather than 5 by count
+        assert_eq!(qual[3], 6 + 33);
+        assert!(qual.iter().enumerate().all(|(i, &q)| i == 3 || q == 60 + 33));
+
+        let (consensus, qual) = PoaGraph::new(&PoaParams::dna()).consensus_with_quality(&QualityModel::agreement());
+        assert!(consensus.is_empty() && qual.is_empty());
+    }
+
+    #[test]
+    fn test_qualities_are_phred33() {
+        let model = QualityModel::new(Platform::Illumina).max_quality(40);
+
+        assert_eq!(model.qualities(&[50, 50], &[1.0, 1.0]), b"II".to_vec());
+
+        // a maximum past the printable qualities is clamped to `~`
+        let model = QualityModel::agreement().max_quality(255);
+        assert_eq!(model.phred(1000, 1.0), 93);
+        assert_eq!(model.qualities(&[1000, 0], &[1.0, 0.0]), b"~!".to_vec());
+    }
+}
@@ -0,0 +1,270 @@
+//! A read with its name and optional per-read inputs, accepted wherever reads are added to a graph.
+
+use crate::{GroupConsensus, PoaGraph, PoaParams};
+
+/// A named read with an optional quality string and an optional weight.
+///
+/// The weight of each base in the graph is its Phred quality (or 1 without a quality string)
+/// times the read weight (1 if unset), rounded to the nearest integer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SeqRecord {
+    /// the name of the read
+    pub name: String,
+    /// the bases of the read, without a null terminator
+    pub seq: Vec<u8>,
+    /// the Phred+33 quality of each base, if known
+    pub qual: Option<Vec<u8>>,
+    /// how much the read counts relative to other reads, if not 1
+    pub weight: Option<f32>,
+}
+
+impl SeqRecord {
+    /// Creates a record without qualities or weight.
+    pub fn new<S: Into<String>>(name: S, seq: Vec<u8>) -> SeqRecord {
+        SeqRecord { name: name.into(), seq, qual: None, weight: None }
+    }
+
+    /// Sets the Phred+33 quality string of the read.
+    pub fn qual(mut self, qual: Vec<u8>) -> SeqRecord {
+        self.qual = Some(qual);
+        self
+    }
+
+    /// Sets the weight of the read.
+    pub fn weight(mut self, weight: f32) -> SeqRecord {
+        self.weight = Some(weight);
+        self
+    }
+
+    /// Returns the weight of each base in the graph, or `None` if every base has weight 1.
+    ///
+    /// # Panics
+    /// Panics if the quality string does not have the length of the sequence.
+    pub fn base_weights(&self) -> Option<Vec<u32>> {
+        if self.qual.is_none() && self.weight.is_none() {
+            return None;
+        }
+        let weight = self.weight.unwrap_or(1.0).max(0.0);
+
+        Some(match &self.qual {
+            Some(qual) => {
+                assert_eq!(qual.len(), self.seq.len(), "quality string length differs from sequence length");
+                qual.iter().map(|&q| (f32::from(q.saturating_sub(33)) * weight).round() as u32).collect()
+            }
+            None => vec![weight.round() as u32; self.seq.len()],
+        })
+    }
+}
+
+impl From<Vec<u8>> for SeqRecord {
+    fn from(seq: Vec<u8>) -> SeqRecord {
+        SeqRecord::new(String::new(), seq)
+    }
+}
+
+impl From<&[u8]> for SeqRecord {
+    fn from(seq: &[u8]) -> SeqRecord {
+        SeqRecord::new(String::new(), seq.to_vec())
+    }
+}
+
+impl From<(String, Vec<u8>)> for SeqRecord {
+    fn from((name, seq): (String, Vec<u8>)) -> SeqRecord {
+        SeqRecord::new(name, seq)
+    }
+}
+
+impl From<(&str, &[u8])> for SeqRecord {
+    fn from((name, seq): (&str, &[u8])) -> SeqRecord {
+        SeqRecord::new(name, seq.to_vec())
+    }
+}
+
+impl From<GroupConsensus> for SeqRecord {
+    fn from(group: GroupConsensus) -> SeqRecord {
+        SeqRecord::new(group.name, group.consensus)
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl From<crate::FastaRecord<'_>> for SeqRecord {
+    fn from(record: crate::FastaRecord<'_>) -> SeqRecord {
+        SeqRecord::new(String::from_utf8_lossy(record.name).into_owned(), record.seq.into_owned())
+    }
+}
+
+/// Generates the consensus of a set of records, weighting their bases by quality and read weight.
+/// # Arguments
+///
+/// * `records` - the reads to form a consensus from; empty reads are left out
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the records
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_of_records, PoaParams, SeqRecord};
+///
+///     let records = vec![
+///         SeqRecord::new("r1", b"AATGCCCGTT".to_vec()),
+///         SeqRecord::new("r2", b"AATGCCGTT".to_vec()).weight(3.0),
+///     ];
+///     let consensus = poa_consensus_of_records(&records, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(consensus, b"AATGCCGTT".to_vec());
+/// ```
+pub fn poa_consensus_of_records(records: &[SeqRecord], params: &PoaParams) -> Vec<u8> {
+    let mut graph = PoaGraph::new(params);
+    for record in records.iter().filter(|record| !record.seq.is_empty()) {
+        graph.add_record(record);
+    }
+    graph.consensus()
+}
+
+/// Generates the consensus of a set of reads, weighting each base by its Phred quality.
+///
+/// This is `poa_consensus_of_records` for reads kept as separate sequence and quality vectors,
+/// e.g. as read from FASTQ. A trailing null terminator of a sequence is ignored.
+/// # Arguments
+///
+/// * `seqs` - the reads to form a consensus from; empty reads are left out
+/// * `quals` - the Phred+33 quality string of each read, of the length of the read without its null terminator
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the reads
+///
+/// # Panics
+/// Panics if `quals` does not hold one quality string per read, or a quality string does not
+/// have the length of its read.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_qualities, PoaParams};
+///
+///     let seqs = vec![b"AACGT".to_vec(), b"ACCGT".to_vec(), b"ACCGT".to_vec()];
+///     let quals = vec![b"IIIII".to_vec(), b"5!5II".to_vec(), b"5!5II".to_vec()];
+///
+///     assert_eq!(poa_consensus_with_qualities(&seqs, &quals, &PoaParams::new(1, 5, -4, -3, -1)), b"AACGT".to_vec());
+/// ```
+pub fn poa_consensus_with_qualities<T: AsRef<[u8]>>(seqs: &[T], quals: &[Vec<u8>], params: &PoaParams) -> Vec<u8> {
+    assert_eq!(seqs.len(), quals.len(), "one quality string is needed per read");
+
+    let mut graph = PoaGraph::new(params);
+    for (seq, qual) in seqs.iter().zip(quals) {
+        let seq = seq.as_ref();
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        assert_eq!(seq.len(), qual.len(), "quality string length differs from sequence length");
+        if !seq.is_empty() {
+            let weights: Vec<u32> = qual.iter().map(|&q| u32::from(q.saturating_sub(33))).collect();
+            graph.add_sequence_weighted(seq, &weights);
+        }
+    }
+    graph.consensus()
+}
+
+/// Generates the consensus of a set of reads, each weighing as much as the given number of reads.
+///
+/// This suits reads that are themselves consensus sequences, e.g. of the molecules sharing a UMI,
+/// weighted by the number of molecules behind them. A trailing null terminator of a sequence is
+/// ignored; `SeqRecord::weight` takes fractional read weights.
+/// # Arguments
+///
+/// * `seqs` - the reads to form a consensus from; empty reads are left out
+/// * `weights` - the weight of each read
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the reads
+///
+/// # Panics
+/// Panics if `weights` does not hold one weight per read.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_read_weights, PoaParams};
+///
+///     let seqs = vec!["AATGCCCGTT", "AATGCCCGTT", "AATGCCGTT"];
+///     let params = PoaParams::new(1, 5, -4, -3, -1);
+///
+///     assert_eq!(poa_consensus_with_read_weights(&seqs, &[1, 1, 1], &params), b"AATGCCCGTT".to_vec());
+///     assert_eq!(poa_consensus_with_read_weights(&seqs, &[1, 1, 5], &params), b"AATGCCGTT".to_vec());
+/// ```
+pub fn poa_consensus_with_read_weights<T: AsRef<[u8]>>(seqs: &[T], weights: &[u32], params: &PoaParams) -> Vec<u8> {
+    assert_eq!(seqs.len(), weights.len(), "one weight is needed per read");
+
+    let mut graph = PoaGraph::new(params);
+    for (seq, &weight) in seqs.iter().zip(weights) {
+        let seq = seq.as_ref();
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        if !seq.is_empty() {
+            graph.add_sequence_with_weight(seq, weight);
+        }
+    }
+    graph.consensus()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_weights() {
+        assert_eq!(SeqRecord::from(&b"ACGT"[..]).base_weights(), None);
+        assert_eq!(SeqRecord::new("r", b"ACG".to_vec()).weight(2.4).base_weights(), Some(vec![2, 2, 2]));
+        assert_eq!(
+            SeqRecord::new("r", b"ACG".to_vec()).qual(b"!+I".to_vec()).weight(0.5).base_weights(),
+            Some(vec![0, 5, 20])
+        );
+    }
+
+    #[test]
+    fn test_qualities_outvote_reads() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let records = vec![
+            SeqRecord::new("r1", b"AACGT".to_vec()).qual(b"IIIII".to_vec()),
+            SeqRecord::new("r2", b"ACCGT".to_vec()).qual(b"5!5II".to_vec()),
+            SeqRecord::new("r3", b"ACCGT".to_vec()).qual(b"5!5II".to_vec()),
+        ];
+
+        assert_eq!(poa_consensus_of_records(&records, &params), b"AACGT".to_vec());
+
+        let unweighted: Vec<SeqRecord> = records.into_iter().map(|r| SeqRecord::new(r.name, r.seq)).collect();
+        assert_eq!(poa_consensus_of_records(&unweighted, &params), b"ACCGT".to_vec());
+    }
+
+    #[test]
+    fn test_consensus_with_qualities() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let seqs = vec![b"AACGT\0".to_vec(), b"ACCGT".to_vec(), b"ACCGT".to_vec(), vec![]];
+        let quals = vec![b"IIIII".to_vec(), b"5!5II".to_vec(), b"5!5II".to_vec(), vec![]];
+        assert_eq!(poa_consensus_with_qualities(&seqs, &quals, &params), b"AACGT".to_vec());
+
+        // equal qualities leave the reads equally weighted
+        let flat = vec![b"IIIII".to_vec(); 3];
+        assert_eq!(poa_consensus_with_qualities(&seqs[..3], &flat, &params), b"ACCGT".to_vec());
+    }
+
+    #[test]
+    fn test_read_weights() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let seqs = vec![b"ACCGT\0".to_vec(), b"ACCGT".to_vec(), b"AACGT".to_vec(), vec![]];
+        assert_eq!(poa_consensus_with_read_weights(&seqs, &[1, 1, 1, 9], &params), b"ACCGT".to_vec());
+        assert_eq!(poa_consensus_with_read_weights(&seqs, &[1, 1, 3, 0], &params), b"AACGT".to_vec());
+
+        // a read of weight 3 counts as three reads along its edges, but covers its nodes once
+        let mut weighted = PoaGraph::new(&params);
+        weighted.add_sequence_with_weight(b"AACGT", 3);
+        let mut repeated = PoaGraph::new(&params);
+        for _ in 0..3 {
+            repeated.add_sequence(b"AACGT");
+        }
+        let weights = |graph: &PoaGraph| graph.edges().iter().map(|edge| edge.weight).collect::<Vec<_>>();
+        assert_eq!(weights(&weighted), weights(&repeated));
+        assert_eq!(weighted.nodes()[0].coverage, 1);
+    }
+}
@@ -0,0 +1,306 @@
+//! Reporting which reads were excluded from a consensus, and why.
+
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::{native_consensus, PoaParams};
+
+/// The reason a read was excluded from the consensus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum RejectReason {
+    /// the read contains no bases
+    Empty,
+    /// the read does not end with a null byte
+    NotNullTerminated,
+    /// the read contains a null byte before its end, which would silently truncate it
+    EmbeddedNull,
+    /// adding the read would make the graph hold more nodes than allowed
+    NodeLimit,
+    /// the read would insert more bases as new nodes than allowed for a single read
+    InsertionLimit,
+    /// the identity of the read to the backbone is below the minimum
+    LowIdentity,
+    /// aligning and adding the read would take more memory than allowed
+    MemoryLimit,
+    /// the read is shorter than the minimum length of the read filter
+    TooShort,
+    /// the read is longer than the maximum length of the read filter
+    TooLong,
+    /// the mean base quality of the read is below the minimum of the read filter
+    LowQuality,
+    /// the fraction of N bases in the read is above the maximum of the read filter
+    TooManyN,
+    /// the read mixes lower- and upper-case bases, which `CasePolicy::Reject` does not allow
+    MixedCase,
+}
+
+impl RejectReason {
+    /// Returns the short identifier used for this reason in TSV reports.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectReason::Empty => "empty",
+            RejectReason::NotNullTerminated => "not_null_terminated",
+            RejectReason::EmbeddedNull => "embedded_null",
+            RejectReason::NodeLimit => "node_limit",
+            RejectReason::InsertionLimit => "insertion_limit",
+            RejectReason::LowIdentity => "low_identity",
+            RejectReason::MemoryLimit => "memory_limit",
+            RejectReason::TooShort => "too_short",
+            RejectReason::TooLong => "too_long",
+            RejectReason::LowQuality => "low_quality",
+            RejectReason::TooManyN => "too_many_n",
+            RejectReason::MixedCase => "mixed_case",
+        }
+    }
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A read excluded from the consensus.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RejectedRead {
+    /// the index of the read in the input
+    pub index: usize,
+    /// why the read was excluded
+    pub reason: RejectReason,
+}
+
+/// A consensus together with an account of the reads that did not contribute to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsensusReport {
+    /// the consensus of the accepted reads
+    pub consensus: Vec<u8>,
+    /// the number of reads that were used to build the consensus
+    pub reads_used: usize,
+    /// the excluded reads, in input order
+    pub rejected: Vec<RejectedRead>,
+}
+
+impl ConsensusReport {
+    /// Returns how many reads were excluded for each reason, in the order each reason first occurs.
+    pub fn reason_counts(&self) -> Vec<(RejectReason, usize)> {
+        let mut counts: Vec<(RejectReason, usize)> = vec![];
+        for read in &self.rejected {
+            match counts.iter_mut().find(|(reason, _)| *reason == read.reason) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((read.reason, 1)),
+            }
+        }
+        counts
+    }
+
+    /// Classifies the report, telling a usable consensus apart from the two ways of getting none.
+    pub fn into_outcome(self) -> ConsensusOutcome {
+        if self.reads_used == 0 {
+            ConsensusOutcome::NoUsableReads { reasons: self.reason_counts() }
+        } else if self.consensus.is_empty() {
+            ConsensusOutcome::EmptyConsensus { reads_used: self.reads_used, reasons: self.reason_counts() }
+        } else {
+            ConsensusOutcome::Consensus(self)
+        }
+    }
+}
+
+/// Whether a consensus was built, and if not, why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConsensusOutcome {
+    /// a non-empty consensus was built from at least one read
+    Consensus(ConsensusReport),
+    /// every read was excluded, or there were no reads at all
+    NoUsableReads {
+        /// how many reads were excluded for each reason; empty if there were no reads
+        reasons: Vec<(RejectReason, usize)>,
+    },
+    /// reads were used, but the consensus came out empty; the used reads are never empty and a
+    /// maximum length keeps at least one base, so this points to a failure of the alignment engine
+    EmptyConsensus {
+        /// the number of reads that were used
+        reads_used: usize,
+        /// how many reads were excluded for each reason
+        reasons: Vec<(RejectReason, usize)>,
+    },
+}
+
+/// Checks a null-terminated input sequence, returning the reason it cannot be used, if any.
+pub(crate) fn validate_read(seq: &[u8]) -> Option<RejectReason> {
+    match seq.split_last() {
+        None => Some(RejectReason::Empty),
+        Some((&last, _)) if last != b'\0' => Some(RejectReason::NotNullTerminated),
+        Some((_, [])) => Some(RejectReason::Empty),
+        Some((_, bases)) if bases.contains(&b'\0') => Some(RejectReason::EmbeddedNull),
+        _ => None,
+    }
+}
+
+/// Generates a consensus sequence, excluding invalid reads instead of panicking and reporting why each was excluded.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the consensus of the valid reads, with the excluded reads and their reasons
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_with_report, PoaParams, RejectReason};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"AATGCCCGTT".to_vec(), b"AATGCCGTT\0".to_vec()];
+///     let report = poa_consensus_with_report(&seqs, 20, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(report.reads_used, 2);
+///     assert_eq!(report.rejected[0].index, 1);
+///     assert_eq!(report.rejected[0].reason, RejectReason::NotNullTerminated);
+/// ```
+pub fn poa_consensus_with_report<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams
+) -> ConsensusReport {
+
+    let mut accepted = Vec::with_capacity(seqs.len());
+    let mut rejected = vec![];
+
+    for (index, seq) in seqs.iter().enumerate() {
+        match validate_read(seq.as_ref()).map_or_else(|| params.case_policy.apply(seq.as_ref()).err(), Some) {
+            Some(reason) => rejected.push(RejectedRead { index, reason }),
+            None => accepted.push(seq.as_ref()),
+        }
+    }
+
+    let consensus = native_consensus(&accepted, consensus_max_length, params);
+
+    ConsensusReport { consensus, reads_used: accepted.len(), rejected }
+}
+
+/// Generates a consensus sequence like `poa_consensus_with_report`, reporting explicitly when there is none.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+///
+/// # Returns
+/// * returns the report of a non-empty consensus, or why no reads could be used, or that the consensus came out empty
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_outcome, ConsensusOutcome, PoaParams, RejectReason};
+///
+///     let seqs = vec![b"".to_vec(), b"AATGCCCGTT".to_vec(), b"\0".to_vec()];
+///     let outcome = poa_consensus_outcome(&seqs, 20, &PoaParams::new(1, 5, -4, -3, -1));
+///
+///     assert_eq!(outcome, ConsensusOutcome::NoUsableReads {
+///         reasons: vec![(RejectReason::Empty, 2), (RejectReason::NotNullTerminated, 1)],
+///     });
+/// ```
+pub fn poa_consensus_outcome<T: AsRef<[u8]>>(seqs: &[T], consensus_max_length: usize, params: &PoaParams) -> ConsensusOutcome {
+    poa_consensus_with_report(seqs, consensus_max_length, params).into_outcome()
+}
+
+/// Writes rejected reads as tab-separated rows of group name, read index and reason.
+///
+/// A header line is written when the log is created, so the reports of many groups can be
+/// collected in one file.
+pub struct RejectionLog<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> RejectionLog<W> {
+    /// Creates a log writing to `writer`, starting with the header line.
+    pub fn new(mut writer: W) -> io::Result<RejectionLog<W>> {
+        writeln!(writer, "group\tread_index\treason")?;
+        Ok(RejectionLog { writer })
+    }
+
+    /// Appends one row per rejected read of `group`.
+    pub fn record(&mut self, group: &str, rejected: &[RejectedRead]) -> io::Result<()> {
+        for read in rejected {
+            writeln!(self.writer, "{}\t{}\t{}", group, read.index, read.reason)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_read() {
+        assert_eq!(validate_read(b""), Some(RejectReason::Empty));
+        assert_eq!(validate_read(b"\0"), Some(RejectReason::Empty));
+        assert_eq!(validate_read(b"ACGT"), Some(RejectReason::NotNullTerminated));
+        assert_eq!(validate_read(b"AC\0GT\0"), Some(RejectReason::EmbeddedNull));
+        assert_eq!(validate_read(b"ACGT\0"), None);
+    }
+
+    #[test]
+    fn test_report_excludes_invalid_reads() {
+        let seqs = vec![
+            b"ATTGCCCGTT\0".to_vec(),
+            b"\0".to_vec(),
+            b"AATGCCGTT\0".to_vec(),
+            b"AATGCCCGAT\0".to_vec(),
+            b"GGGGGGGGGG".to_vec(),
+            b"AACGCCCGTC\0".to_vec(),
+            b"AGTGCTCGTT\0".to_vec(),
+            b"AATGCTCGTT\0".to_vec(),
+        ];
+
+        let report = poa_consensus_with_report(&seqs, 20, &PoaParams::new(1, 5, -4, -3, -1));
+
+        assert_eq!(report.consensus, b"AATGCCCGTT".to_vec());
+        assert_eq!(report.reads_used, 6);
+        assert_eq!(report.rejected, vec![
+            RejectedRead { index: 1, reason: RejectReason::Empty },
+            RejectedRead { index: 4, reason: RejectReason::NotNullTerminated },
+        ]);
+    }
+
+    #[test]
+    fn test_rejection_log() {
+        let mut log = RejectionLog::new(vec![]).unwrap();
+        log.record("umi1", &[RejectedRead { index: 4, reason: RejectReason::Empty }]).unwrap();
+        log.record("umi2", &[]).unwrap();
+        log.record("umi3", &[RejectedRead { index: 0, reason: RejectReason::EmbeddedNull }]).unwrap();
+
+        let tsv = String::from_utf8(log.into_inner()).unwrap();
+        assert_eq!(tsv, "group\tread_index\treason\numi1\t4\tempty\numi3\t0\tembedded_null\n");
+    }
+
+    #[test]
+    fn test_outcomes() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let seqs = vec![b"AATGCCCGTT\0".to_vec(), b"ACGT".to_vec(), b"AATGCCCGTT\0".to_vec()];
+
+        match poa_consensus_outcome(&seqs, 20, &params) {
+            ConsensusOutcome::Consensus(report) => assert_eq!(report.consensus, b"AATGCCCGTT".to_vec()),
+            outcome => panic!("unexpected outcome {:?}", outcome),
+        }
+        // the consensus of non-empty reads is never empty, whatever the maximum length, so an empty
+        // consensus is built by hand
+        let mut report = poa_consensus_with_report(&seqs, 0, &params);
+        assert_eq!(report.consensus, b"AATGCCCGTT".to_vec());
+        report.consensus.clear();
+        assert_eq!(
+            report.into_outcome(),
+            ConsensusOutcome::EmptyConsensus { reads_used: 2, reasons: vec![(RejectReason::NotNullTerminated, 1)] }
+        );
+        assert_eq!(poa_consensus_outcome(&Vec::<Vec<u8>>::new(), 20, &params), ConsensusOutcome::NoUsableReads { reasons: vec![] });
+    }
+}
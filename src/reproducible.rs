@@ -0,0 +1,104 @@
+//! Reproducible consensus, for pipelines validated against pinned outputs.
+
+use crate::{PoaParams, SimdBackend};
+
+impl PoaParams {
+    /// Pins the scalar alignment engine, so the consensus, alignments and graph of the same reads
+    /// in the same order are identical on every platform and CPU, at the cost of speed.
+    ///
+    /// Even without it, the output depends only on the reads, their order and the parameters, as
+    /// ties are broken by fixed rules rather than by hashing or timing:
+    ///
+    /// * aligning, the backtrack steps through equally scoring cells in a fixed order
+    /// * the heaviest bundle follows the heaviest edge into each node; among edges of equal weight it
+    ///   takes the one from the better-scoring node, and among those the edge added last, i.e. the
+    ///   one of the later read. It ends at the first node in topological order of the best score
+    /// * the maximum-weight path takes the path of the smaller node ids among paths of equal weight
+    /// * the column majority calls the symbol of the first read among symbols of equal count
+    /// * reads of a batch are aligned in input order whatever the number of threads
+    ///
+    /// The SIMD engines are built to give the alignments of the scalar engine, but which one runs
+    /// depends on the CPU; pinning the scalar engine keeps that out of the output, down to the
+    /// choice among equally good alignments.
+    ///
+    /// The setting is kept by the preset string of the parameters, as `simd=scalar`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{poa_consensus_unbounded, PoaParams};
+    ///
+    ///     let params = PoaParams::new(1, 5, -4, -3, -1).reproducible();
+    ///     assert!(params.is_reproducible());
+    ///     assert_eq!(params.to_string().parse::<PoaParams>().unwrap(), params);
+    ///
+    ///     // the reads tie at the SNP, so the consensus follows the later one
+    ///     let seqs = [b"AATGCCCGTT\0", b"AATGCTCGTT\0"];
+    ///     assert_eq!(poa_consensus_unbounded(&seqs, &params), b"AATGCTCGTT".to_vec());
+    /// ```
+    pub fn reproducible(self) -> PoaParams {
+        self.simd(SimdBackend::Scalar)
+    }
+
+    /// Returns whether the parameters pin the scalar engine, as `reproducible` does.
+    pub fn is_reproducible(&self) -> bool {
+        self.simd == SimdBackend::Scalar
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConsensusAlgorithm, PoaGraph};
+
+    // reads with a SNP, indels, and gaps that can be placed in several ways in runs of a base
+    const READS: [&str; 6] = ["GATTACAGGCTTACCG", "GATTACAGGCATACCG", "GATTAAAGGCTTACCG", "GATTACAGGCTACCG", "GATTCAGGCTTTACCG", "GATTACAGCTTACCGA"];
+
+    fn graph(params: &PoaParams) -> PoaGraph {
+        let mut graph = PoaGraph::new(params);
+        for read in READS.iter() {
+            graph.add_sequence(read.as_bytes());
+        }
+        graph
+    }
+
+    #[test]
+    fn test_pinned_outputs() {
+        let msa = ["GATTACAGGC-TTACCG-", "GATTACAGGC-ATACCG-", "GATTAAAGGC-TTACCG-", "GATTACAGGC--TACCG-", "GATT-CAGGCTTTACCG-", "GATTACA-GC-TTACCGA"];
+        for alignment_type in 0..3 {
+            let params = PoaParams::new(alignment_type, 5, -4, -8, -6).reproducible();
+            let graph = graph(&params);
+            let rows: Vec<Vec<u8>> = msa.iter().map(|row| row.as_bytes().to_vec()).collect();
+            assert_eq!(graph.multiple_sequence_alignment(false), rows, "{}", alignment_type);
+            for &algorithm in ConsensusAlgorithm::ALL.iter() {
+                assert_eq!(graph.consensus_by(algorithm), b"GATTACAGGCTTACCGA".to_vec(), "{} {}", alignment_type, algorithm);
+            }
+
+            // a tied SNP goes to the later read, a tied indel to the longer path in either order
+            for &(reads, consensus) in [
+                (["AATGCCCGTT", "AATGCTCGTT"], "AATGCTCGTT"),
+                (["AATGCTCGTT", "AATGCCCGTT"], "AATGCCCGTT"),
+                (["AATGCCGTT", "AATGCCCGTT"], "AATGCCCGTT"),
+                (["AATGCCCGTT", "AATGCCGTT"], "AATGCCCGTT"),
+            ]
+            .iter()
+            {
+                let mut pair = PoaGraph::new(&params);
+                for read in reads.iter() {
+                    pair.add_sequence(read.as_bytes());
+                }
+                assert_eq!(pair.consensus(), consensus.as_bytes().to_vec(), "{} {:?}", alignment_type, reads);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reproducible_engine() {
+        let params = PoaParams::dna().simd(SimdBackend::Avx2);
+        assert!(!params.is_reproducible());
+        let params = params.reproducible();
+        assert!(params.is_reproducible());
+        assert_eq!(graph(&params).simd(), SimdBackend::Scalar);
+        assert_eq!(graph(&params).multiple_sequence_alignment(false), graph(&params).multiple_sequence_alignment(false));
+    }
+}
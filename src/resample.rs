@@ -0,0 +1,220 @@
+//! Estimating the stability of a consensus by recomputing it from resampled reads.
+
+use crate::align::{align, edit_distance, identity, AlignOp};
+use crate::rng::Rng;
+use crate::{native_consensus, PoaParams};
+
+/// A consensus with the per-position stability estimated by bootstrapping.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BootstrapConsensus {
+    /// the consensus of all input sequences
+    pub consensus: Vec<u8>,
+    /// for each consensus base, the fraction of bootstrap replicates reproducing it
+    pub stability: Vec<f32>,
+    /// the number of bootstrap replicates computed
+    pub replicates: usize,
+}
+
+/// The agreement between the consensuses of two disjoint halves of a read group.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Concordance {
+    /// the consensus of the first half of the reads
+    pub first: Vec<u8>,
+    /// the consensus of the second half of the reads
+    pub second: Vec<u8>,
+    /// the edit distance between the two consensuses
+    pub edit_distance: usize,
+    /// the fraction of matching columns in the alignment of the two consensuses
+    pub identity: f64,
+}
+
+fn consensus_of<T: AsRef<[u8]>>(seqs: &[T], consensus_max_length: usize, params: &PoaParams) -> Vec<u8> {
+    native_consensus(seqs, consensus_max_length, params)
+}
+
+/// Generates a consensus sequence and estimates how stable each of its bases is under resampling of the reads.
+///
+/// Each replicate draws as many reads as the input holds, with replacement, and computes their
+/// consensus. The replicate consensus is globally aligned to the consensus of all reads, and a
+/// consensus base counts as reproduced if the replicate has the identical base aligned to it.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to form a consensus from
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `replicates` - the number of bootstrap replicates to compute
+/// * `seed` - the seed for drawing the replicates; equal seeds give equal results
+///
+/// # Returns
+/// * returns the consensus of all reads with the fraction of replicates reproducing each of its bases
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_bootstrap, PoaParams};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(); 4];
+///     let result = poa_consensus_bootstrap(&seqs, 20, &PoaParams::new(1, 5, -4, -3, -1), 10, 42);
+///
+///     assert_eq!(result.consensus, b"AATGCCCGTT".to_vec());
+///     assert!(result.stability.iter().all(|&s| s == 1.0));
+/// ```
+pub fn poa_consensus_bootstrap<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    replicates: usize,
+    seed: u64
+) -> BootstrapConsensus {
+
+    let consensus = consensus_of(seqs, consensus_max_length, params);
+    let mut reproduced = vec![0usize; consensus.len()];
+
+    let mut rng = Rng::new(seed);
+    let mut sample = Vec::with_capacity(seqs.len());
+
+    for _ in 0..replicates {
+        sample.clear();
+        for _ in 0..seqs.len() {
+            sample.push(seqs[rng.below(seqs.len())].as_ref());
+        }
+
+        let replicate = consensus_of(&sample, consensus_max_length, params);
+
+        // walk the alignment of the replicate (a) against the full consensus (b)
+        let mut position = 0;
+        for op in align(&replicate, &consensus) {
+            match op {
+                AlignOp::Match => {
+                    reproduced[position] += 1;
+                    position += 1;
+                }
+                AlignOp::Mismatch | AlignOp::Deletion => position += 1,
+                AlignOp::Insertion => {}
+            }
+        }
+    }
+
+    let stability = reproduced
+        .iter()
+        .map(|&count| if replicates == 0 { 0.0 } else { count as f32 / replicates as f32 })
+        .collect();
+
+    BootstrapConsensus { consensus, stability, replicates }
+}
+
+/// Splits the reads into two random halves and compares the consensuses of the halves.
+///
+/// This is a cheaper alternative to `poa_consensus_bootstrap` for quality control: a group whose
+/// halves agree is unlikely to have a consensus driven by a few reads. With an odd number of
+/// reads, the first half gets the extra read.
+/// # Arguments
+///
+/// * `seqs` - the sequences (each null terminated, as a `Vec<u8>`, a byte slice or any other `AsRef<[u8]>`) to split
+/// * `consensus_max_length` - the maximum length of the consensuses, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `seed` - the seed for assigning reads to halves; equal seeds give equal results
+///
+/// # Returns
+/// * returns both half consensuses with their edit distance and identity
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_concordance, PoaParams};
+///
+///     let seqs = vec![b"AATGCCCGTT\0".to_vec(); 6];
+///     let concordance = poa_concordance(&seqs, 20, &PoaParams::new(1, 5, -4, -3, -1), 1);
+///
+///     assert_eq!(concordance.edit_distance, 0);
+///     assert_eq!(concordance.identity, 1.0);
+/// ```
+pub fn poa_concordance<T: AsRef<[u8]>>(
+    seqs: &[T],
+    consensus_max_length: usize,
+    params: &PoaParams,
+    seed: u64
+) -> Concordance {
+
+    // Fisher-Yates shuffle of the read indices
+    let mut order: Vec<usize> = (0..seqs.len()).collect();
+    let mut rng = Rng::new(seed);
+    for i in (1..order.len()).rev() {
+        order.swap(i, rng.below(i + 1));
+    }
+
+    let split = seqs.len().div_ceil(2);
+    let first: Vec<&[u8]> = order[..split].iter().map(|&i| seqs[i].as_ref()).collect();
+    let second: Vec<&[u8]> = order[split..].iter().map(|&i| seqs[i].as_ref()).collect();
+
+    let first = consensus_of(&first, consensus_max_length, params);
+    let second = consensus_of(&second, consensus_max_length, params);
+
+    let ops = align(&first, &second);
+
+    Concordance {
+        edit_distance: edit_distance(&ops),
+        identity: identity(&ops),
+        first,
+        second,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dna_seqs() -> Vec<Vec<u8>> {
+        ["ATTGCCCGTT\0",
+            "AATGCCGTT\0",
+            "AATGCCCGAT\0",
+            "AACGCCCGTC\0",
+            "AGTGCTCGTT\0",
+            "AATGCTCGTT\0"].iter().map(|seq| seq.bytes().collect()).collect()
+    }
+
+    #[test]
+    fn test_bootstrap_stability() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let result = poa_consensus_bootstrap(&dna_seqs(), 20, &params, 50, 7);
+
+        assert_eq!(result.consensus, b"AATGCCCGTT".to_vec());
+        assert_eq!(result.replicates, 50);
+        assert_eq!(result.stability.len(), result.consensus.len());
+        assert!(result.stability.iter().all(|&s| (0.0..=1.0).contains(&s)));
+        // the first base is shared by all reads, the noisy positions are not always reproduced
+        assert_eq!(result.stability[0], 1.0);
+        assert!(result.stability.iter().any(|&s| s < 1.0));
+    }
+
+    #[test]
+    fn test_bootstrap_is_reproducible() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+
+        assert_eq!(
+            poa_consensus_bootstrap(&dna_seqs(), 20, &params, 20, 11),
+            poa_consensus_bootstrap(&dna_seqs(), 20, &params, 20, 11)
+        );
+    }
+
+    #[test]
+    fn test_concordance() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let concordance = poa_concordance(&dna_seqs(), 20, &params, 3);
+
+        assert!(!concordance.first.is_empty() && !concordance.second.is_empty());
+        assert_eq!(concordance.edit_distance, edit_distance(&align(&concordance.first, &concordance.second)));
+        assert!(concordance.identity > 0.5 && concordance.identity <= 1.0);
+        assert_eq!(concordance, poa_concordance(&dna_seqs(), 20, &params, 3));
+    }
+
+    #[test]
+    fn test_concordance_detects_mixed_groups() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let seqs = vec![b"AATGCCCGTTAATGCCCGTT\0".to_vec(), b"GGCATTACGAGGCATTACGA\0".to_vec()];
+
+        // each half holds one of two unrelated reads, so the halves cannot agree
+        let concordance = poa_concordance(&seqs, 40, &params, 0);
+        assert!(concordance.edit_distance > 5);
+    }
+}
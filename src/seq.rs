@@ -0,0 +1,32 @@
+//! Operations on nucleotide sequences.
+
+/// Returns the complement of a nucleotide, including IUPAC ambiguity codes, or the symbol itself
+/// for other symbols. As in the native graph code, this is an involution.
+pub(crate) fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T', b'T' => b'A', b'C' => b'G', b'G' => b'C',
+        b'a' => b't', b't' => b'a', b'c' => b'g', b'g' => b'c',
+        b'R' => b'Y', b'Y' => b'R', b'K' => b'M', b'M' => b'K',
+        b'B' => b'V', b'V' => b'B', b'D' => b'H', b'H' => b'D',
+        b'r' => b'y', b'y' => b'r', b'k' => b'm', b'm' => b'k',
+        b'b' => b'v', b'v' => b'b', b'd' => b'h', b'h' => b'd',
+        _ => base,
+    }
+}
+
+/// Returns the reverse complement of a nucleotide sequence.
+pub(crate) fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter().rev().map(|&base| complement(base)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reverse_complement() {
+        assert_eq!(reverse_complement(b"AACGTN"), b"NACGTT".to_vec());
+        assert_eq!(reverse_complement(b"acgRY-"), b"-RYcgt".to_vec());
+        assert!((0..=255u8).all(|c| complement(complement(c)) == c));
+    }
+}
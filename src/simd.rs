@@ -0,0 +1,207 @@
+//! The instruction sets the alignment engine can use, and which of them the CPU supports.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{PoaError, PoaParams};
+
+#[cfg(feature = "pure-rust")]
+use crate::pure::poa_simd_supported;
+
+#[cfg(not(feature = "pure-rust"))]
+extern "C" {
+    fn poa_simd_supported() -> i32;
+}
+
+/// The instruction set the native alignment engine aligns with.
+///
+/// The library is built for the baseline of the target, and on x86 the engine is compiled once
+/// more for SSE4.1 and AVX2, so one binary runs on any CPU and uses the fastest engine it can. The
+/// engines give the same alignments, so the choice only matters for speed, e.g. for benchmarking.
+/// An instruction set the CPU does not support is never used: the best supported one is chosen
+/// instead, as for `Auto`, so a binary cannot crash on an older CPU.
+///
+/// With the `system-spoa` feature, the installed spoa library picks the instruction set itself, and
+/// graphs report the scalar engine. With the `pure-rust` feature, the Rust port of the scalar
+/// engine is the only one, which the CPU always supports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SimdBackend {
+    /// the best instruction set the CPU supports, detected when an engine is created
+    #[default]
+    Auto,
+    /// the scalar engine, without SIMD instructions
+    Scalar,
+    /// SSE4.1, on x86
+    Sse41,
+    /// AVX2, on x86
+    Avx2,
+}
+
+impl SimdBackend {
+    /// Every instruction set other than `Auto`, from the slowest to the fastest.
+    pub const ALL: [SimdBackend; 3] = [SimdBackend::Scalar, SimdBackend::Sse41, SimdBackend::Avx2];
+
+    /// Returns the best instruction set the CPU supports, which `Auto` selects.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::SimdBackend;
+    ///
+    ///     let backend = SimdBackend::detect();
+    ///     assert!(backend != SimdBackend::Auto && backend.is_supported());
+    /// ```
+    pub fn detect() -> SimdBackend {
+        SimdBackend::from_code(unsafe { poa_simd_supported() }).unwrap_or(SimdBackend::Scalar)
+    }
+
+    /// Returns whether the CPU supports the instruction set; `Auto` and `Scalar` always are.
+    pub fn is_supported(self) -> bool {
+        self.code() <= SimdBackend::detect().code()
+    }
+
+    /// Returns the instruction set an engine asked to use this one uses on this CPU.
+    pub fn resolve(self) -> SimdBackend {
+        if self == SimdBackend::Auto || !self.is_supported() {
+            SimdBackend::detect()
+        } else {
+            self
+        }
+    }
+
+    /// Returns a short lower-case name for the instruction set, as `str::parse` reads it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SimdBackend::Auto => "auto",
+            SimdBackend::Scalar => "scalar",
+            SimdBackend::Sse41 => "sse4.1",
+            SimdBackend::Avx2 => "avx2",
+        }
+    }
+
+    // the POA_SIMD_ value of poa_func.h
+    pub(crate) fn code(self) -> i32 {
+        match self {
+            SimdBackend::Auto => 0,
+            SimdBackend::Scalar => 1,
+            SimdBackend::Sse41 => 2,
+            SimdBackend::Avx2 => 3,
+        }
+    }
+
+    pub(crate) fn from_code(code: i32) -> Option<SimdBackend> {
+        match code {
+            0 => Some(SimdBackend::Auto),
+            1 => Some(SimdBackend::Scalar),
+            2 => Some(SimdBackend::Sse41),
+            3 => Some(SimdBackend::Avx2),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SimdBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SimdBackend {
+    type Err = PoaError;
+
+    fn from_str(s: &str) -> Result<SimdBackend, PoaError> {
+        match s {
+            "auto" => Ok(SimdBackend::Auto),
+            "scalar" => Ok(SimdBackend::Scalar),
+            "sse4.1" => Ok(SimdBackend::Sse41),
+            "avx2" => Ok(SimdBackend::Avx2),
+            _ => Err(PoaError::InvalidPreset(format!("unknown instruction set {}", s))),
+        }
+    }
+}
+
+impl PoaParams {
+    /// Sets the instruction set of the alignment engine.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    ///     use rust_spoa::{PoaGraph, PoaParams, SimdBackend};
+    ///
+    ///     let mut graph = PoaGraph::new(&PoaParams::dna().simd(SimdBackend::Scalar));
+    ///     graph.add_sequence(b"AATGCCCGTT");
+    ///     assert_eq!(graph.simd(), SimdBackend::Scalar);
+    /// ```
+    pub fn simd(mut self, simd: SimdBackend) -> PoaParams {
+        self.simd = simd;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulate::{random_sequence, ReadSimulator};
+    use crate::PoaGraph;
+
+    #[test]
+    fn test_backends_align_alike() {
+        let reads = ["ATTGCCCGTT", "AATGCCGTT", "AATGCCCGAT", "AACGCCCGTC", "AGTGCTCGTT", "AATGCTCGTT"];
+        for alignment_type in 0..3 {
+            for &(gap_open, gap_extend) in [(-8, -6), (-4, -2)].iter() {
+                let params = PoaParams::new(alignment_type, 5, -4, gap_open, gap_extend);
+                let consensus: Vec<(SimdBackend, Vec<u8>)> = SimdBackend::ALL
+                    .iter()
+                    .map(|&backend| {
+                        let mut graph = PoaGraph::new(&params.simd(backend));
+                        for read in reads.iter() {
+                            graph.add_sequence(read.as_bytes());
+                        }
+                        assert_eq!(graph.simd(), backend.resolve());
+                        (backend, graph.consensus())
+                    })
+                    .collect();
+                for (backend, sequence) in &consensus {
+                    assert_eq!(sequence, &consensus[0].1, "{} {:?}", backend, params);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_backends_align_long_reads_alike() {
+        // reads long enough to fill several registers of each engine
+        let template = random_sequence(300, 7);
+        let reads = ReadSimulator::new(11).error_rates(0.05, 0.05, 0.05).reads(&template, 8);
+        for alignment_type in 0..3 {
+            let params = PoaParams::new(alignment_type, 5, -4, -8, -6);
+            let graphs: Vec<PoaGraph> = SimdBackend::ALL
+                .iter()
+                .map(|&backend| {
+                    let mut graph = PoaGraph::new(&params.simd(backend));
+                    for read in &reads {
+                        graph.add_sequence(read);
+                    }
+                    graph
+                })
+                .collect();
+            for (backend, graph) in SimdBackend::ALL.iter().zip(&graphs) {
+                assert_eq!(graph.multiple_sequence_alignment(false), graphs[0].multiple_sequence_alignment(false), "{} {}", backend, alignment_type);
+                assert_eq!(graph.consensus(), graphs[0].consensus(), "{} {}", backend, alignment_type);
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolution_and_names() {
+        let best = SimdBackend::detect();
+        assert_eq!(SimdBackend::Auto.resolve(), best);
+        assert_eq!(SimdBackend::Scalar.resolve(), SimdBackend::Scalar);
+        assert!(SimdBackend::ALL.iter().all(|&backend| backend.resolve().code() <= best.code()));
+
+        for &backend in [SimdBackend::Auto, SimdBackend::Scalar, SimdBackend::Sse41, SimdBackend::Avx2].iter() {
+            assert_eq!(backend.to_string().parse::<SimdBackend>(), Ok(backend));
+        }
+        assert!("neon".parse::<SimdBackend>().is_err());
+    }
+}
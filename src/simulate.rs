@@ -0,0 +1,223 @@
+//! Simulating noisy reads of a known sequence, for benchmarking parameters and testing consensus modes.
+//!
+//! All randomness comes from a seeded generator, so a simulation is reproducible from its seed.
+
+use crate::rng::Rng;
+use crate::seq::reverse_complement;
+use crate::SeqRecord;
+
+const BASES: &[u8] = b"ACGT";
+
+/// The distribution of the number of template bases each read covers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LengthDistribution {
+    /// every read covers the whole template
+    Full,
+    /// every read covers this many template bases
+    Fixed(usize),
+    /// read lengths are uniformly distributed between `min` and `max`, both included
+    Uniform {
+        /// the shortest length
+        min: usize,
+        /// the longest length
+        max: usize,
+    },
+    /// read lengths are normally distributed, rounded and at least 1
+    Normal {
+        /// the mean length
+        mean: f64,
+        /// the standard deviation of the length
+        sd: f64,
+    },
+}
+
+/// A simulated read, with the template region it was drawn from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulatedRead {
+    /// the bases of the read
+    pub seq: Vec<u8>,
+    /// the first template position covered (0-based)
+    pub start: usize,
+    /// the template position after the last one covered
+    pub end: usize,
+    /// whether the read is the reverse complement of the template region
+    pub reverse: bool,
+    /// the number of substitutions, insertions and deletions introduced
+    pub errors: usize,
+}
+
+impl SimulatedRead {
+    /// Converts the read into a record named `name`, without qualities.
+    pub fn into_record<S: Into<String>>(self, name: S) -> SeqRecord {
+        SeqRecord::new(name, self.seq)
+    }
+}
+
+/// Generates reads of a template with random substitutions, insertions and deletions.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::simulate::{random_sequence, ReadSimulator};
+///     use rust_spoa::{ConsensusExt, PoaParams};
+///
+///     let truth = random_sequence(200, 1);
+///     let reads = ReadSimulator::new(7).error_rates(0.02, 0.01, 0.01).reads(&truth, 10);
+///
+///     let consensus = reads.consensus(&PoaParams::new(1, 5, -4, -8, -6)).unwrap();
+///     assert_eq!(consensus, truth);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ReadSimulator {
+    substitution_rate: f64,
+    insertion_rate: f64,
+    deletion_rate: f64,
+    length: LengthDistribution,
+    reverse_fraction: f64,
+    rng: Rng,
+}
+
+impl ReadSimulator {
+    /// Creates a simulator drawing full-length forward reads with 1% of each error type.
+    pub fn new(seed: u64) -> ReadSimulator {
+        ReadSimulator {
+            substitution_rate: 0.01,
+            insertion_rate: 0.01,
+            deletion_rate: 0.01,
+            length: LengthDistribution::Full,
+            reverse_fraction: 0.0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Sets the per-base probabilities of a substitution, of an insertion after the base and of its deletion.
+    pub fn error_rates(mut self, substitution_rate: f64, insertion_rate: f64, deletion_rate: f64) -> ReadSimulator {
+        self.substitution_rate = substitution_rate;
+        self.insertion_rate = insertion_rate;
+        self.deletion_rate = deletion_rate;
+        self
+    }
+
+    /// Sets the distribution of the number of template bases each read covers.
+    pub fn length(mut self, length: LengthDistribution) -> ReadSimulator {
+        self.length = length;
+        self
+    }
+
+    /// Sets the fraction of reads drawn from the reverse strand.
+    pub fn reverse_fraction(mut self, reverse_fraction: f64) -> ReadSimulator {
+        self.reverse_fraction = reverse_fraction;
+        self
+    }
+
+    fn sample_length(&mut self, template_len: usize) -> usize {
+        let len = match self.length {
+            LengthDistribution::Full => template_len,
+            LengthDistribution::Fixed(len) => len,
+            LengthDistribution::Uniform { min, max } => min + self.rng.below(max.saturating_sub(min) + 1),
+            LengthDistribution::Normal { mean, sd } => {
+                // Box-Muller transform
+                let (u, v) = (1.0 - self.rng.unit(), self.rng.unit());
+                let z = (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos();
+                (mean + sd * z).round().max(1.0) as usize
+            }
+        };
+        len.min(template_len)
+    }
+
+    fn other_base(&mut self, base: u8) -> u8 {
+        let others: Vec<u8> = BASES.iter().cloned().filter(|&b| b != base.to_ascii_uppercase()).collect();
+        others[self.rng.below(others.len())]
+    }
+
+    /// Draws one read of `template`.
+    pub fn simulate(&mut self, template: &[u8]) -> SimulatedRead {
+        let len = self.sample_length(template.len());
+        let start = self.rng.below(template.len() - len + 1);
+        let end = start + len;
+
+        let mut seq = Vec::with_capacity(len + len / 10);
+        let mut errors = 0;
+        for &base in &template[start..end] {
+            let roll = self.rng.unit();
+            if roll < self.deletion_rate {
+                errors += 1;
+            } else if roll < self.deletion_rate + self.substitution_rate {
+                seq.push(self.other_base(base));
+                errors += 1;
+            } else {
+                seq.push(base);
+            }
+            if self.rng.unit() < self.insertion_rate {
+                seq.push(BASES[self.rng.below(4)]);
+                errors += 1;
+            }
+        }
+
+        let reverse = self.rng.unit() < self.reverse_fraction;
+        if reverse {
+            seq = reverse_complement(&seq);
+        }
+        SimulatedRead { seq, start, end, reverse, errors }
+    }
+
+    /// Draws `n` reads of `template`, returning just their bases.
+    pub fn reads(&mut self, template: &[u8], n: usize) -> Vec<Vec<u8>> {
+        (0..n).map(|_| self.simulate(template).seq).collect()
+    }
+}
+
+/// Returns a uniformly random sequence of A, C, G and T.
+pub fn random_sequence(len: usize, seed: u64) -> Vec<u8> {
+    let mut rng = Rng::new(seed);
+    (0..len).map(|_| BASES[rng.below(4)]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::align::{align, edit_distance};
+
+    #[test]
+    fn test_simulation_is_reproducible() {
+        let template = random_sequence(100, 3);
+        assert_eq!(template, random_sequence(100, 3));
+
+        let a = ReadSimulator::new(5).reads(&template, 4);
+        assert_eq!(a, ReadSimulator::new(5).reads(&template, 4));
+        assert_ne!(a, ReadSimulator::new(6).reads(&template, 4));
+    }
+
+    #[test]
+    fn test_error_rates() {
+        let template = random_sequence(1000, 3);
+        let mut exact = ReadSimulator::new(1).error_rates(0.0, 0.0, 0.0);
+        assert_eq!(exact.simulate(&template).seq, template);
+
+        let read = ReadSimulator::new(1).error_rates(0.05, 0.05, 0.05).simulate(&template);
+        let distance = edit_distance(&align(&read.seq, &template));
+        assert!(read.errors > 100 && read.errors < 200, "{} errors", read.errors);
+        assert!(distance <= read.errors);
+    }
+
+    #[test]
+    fn test_lengths_and_strands() {
+        let template = random_sequence(500, 3);
+        let mut simulator = ReadSimulator::new(2)
+            .error_rates(0.0, 0.0, 0.0)
+            .length(LengthDistribution::Uniform { min: 50, max: 80 })
+            .reverse_fraction(1.0);
+
+        for _ in 0..20 {
+            let read = simulator.simulate(&template);
+            assert!(read.reverse);
+            assert!((50..=80).contains(&(read.end - read.start)));
+            assert_eq!(reverse_complement(&read.seq), template[read.start..read.end].to_vec());
+        }
+
+        let mut normal = ReadSimulator::new(2).length(LengthDistribution::Normal { mean: 200.0, sd: 20.0 });
+        let mean = (0..200).map(|_| normal.simulate(&template)).map(|read| (read.end - read.start) as f64).sum::<f64>() / 200.0;
+        assert!((mean - 200.0).abs() < 10.0, "mean length {}", mean);
+        assert_eq!(ReadSimulator::new(2).length(LengthDistribution::Fixed(900)).simulate(&template).end, 500);
+    }
+}
@@ -0,0 +1,253 @@
+//! Snapshots of the consensus of a graph while reads are streamed into it, and stopping once it
+//! no longer changes.
+
+use crate::{PoaError, PoaGraph, PoaParams};
+
+/// A graph that reads are streamed into, keeping the consensus of the reads added so far.
+///
+/// The consensus is generated at most once for each read added, however often it is asked for.
+/// With `stop_when_stable(n)`, the consensus is checked after every read, and once it did not
+/// change for `n` reads in a row the stream is converged: later reads are skipped, without being
+/// aligned. Deep groups, such as large UMI families, reach their consensus long before their last
+/// read, so this saves most of their alignments.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{PoaParams, StreamingConsensus};
+///
+///     let mut stream = StreamingConsensus::new(&PoaParams::new(1, 5, -4, -3, -1)).stop_when_stable(2);
+///     for seq in ["AATGCCCGTT", "AATGCCGTT", "AATGCCCGTT", "AATGCCCGTT", "AATGCCCGTT", "AACGCCCGTC"].iter() {
+///         stream.add_sequence(seq.as_bytes());
+///     }
+///
+///     assert!(stream.is_converged());
+///     assert_eq!((stream.num_sequences(), stream.num_skipped()), (3, 3));
+///     assert_eq!(stream.current_consensus(), b"AATGCCCGTT");
+/// ```
+pub struct StreamingConsensus {
+    graph: PoaGraph,
+    stable_reads: Option<usize>,
+    // the consensus of the graph, or None if a read was added since it was generated
+    consensus: Option<Vec<u8>>,
+    unchanged: usize,
+    skipped: usize,
+}
+
+impl StreamingConsensus {
+    /// Creates an empty stream aligning reads with the given parameters, never converging.
+    ///
+    /// # Panics
+    /// Panics if the alignment engine rejects the parameters, as `PoaGraph::new` does.
+    pub fn new(params: &PoaParams) -> StreamingConsensus {
+        StreamingConsensus::from_graph(PoaGraph::new(params))
+    }
+
+    /// Streams reads into an existing graph, e.g. one built from a substitution matrix or holding
+    /// reads already.
+    pub fn from_graph(graph: PoaGraph) -> StreamingConsensus {
+        StreamingConsensus { graph, stable_reads: None, consensus: None, unchanged: 0, skipped: 0 }
+    }
+
+    /// Stops adding reads once the consensus did not change for `reads` reads in a row; 0 never
+    /// stops.
+    pub fn stop_when_stable(mut self, reads: usize) -> StreamingConsensus {
+        self.stable_reads = if reads == 0 { None } else { Some(reads) };
+        self
+    }
+
+    /// Aligns a read, without a null terminator, to the graph and adds it, unless it is empty or
+    /// the stream has converged. An empty read is left out without counting towards the reads
+    /// the consensus did not change for, or towards the reads skipped.
+    ///
+    /// # Returns
+    /// * returns whether the read was added; `false` for an empty read and once the stream has
+    ///   converged
+    ///
+    /// # Panics
+    /// Panics if the native library fails, as `PoaGraph::add_sequence` does.
+    pub fn add_sequence(&mut self, seq: &[u8]) -> bool {
+        self.try_add_sequence(seq).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Adds a read as `add_sequence` does, returning `PoaError::Native` if the native library
+    /// fails.
+    pub fn try_add_sequence(&mut self, seq: &[u8]) -> Result<bool, PoaError> {
+        if seq.is_empty() {
+            return Ok(false);
+        }
+        if self.is_converged() {
+            self.skipped += 1;
+            return Ok(false);
+        }
+        self.graph.try_add_sequence(seq)?;
+        match (self.stable_reads, self.consensus.take()) {
+            (Some(_), Some(previous)) => {
+                let consensus = self.graph.try_consensus()?;
+                self.unchanged = if consensus == previous { self.unchanged + 1 } else { 0 };
+                self.consensus = Some(consensus);
+            }
+            (Some(_), None) => self.consensus = Some(self.graph.try_consensus()?),
+            (None, _) => {}
+        }
+        Ok(true)
+    }
+
+    /// Returns the consensus of the reads added so far, generating it only if a read was added
+    /// since it was last generated.
+    pub fn current_consensus(&mut self) -> &[u8] {
+        if self.consensus.is_none() {
+            self.consensus = Some(self.graph.consensus());
+        }
+        self.consensus.as_deref().unwrap_or_default()
+    }
+
+    /// Returns whether the consensus did not change for the number of reads `stop_when_stable`
+    /// asks for, so that further reads are skipped.
+    pub fn is_converged(&self) -> bool {
+        matches!(self.stable_reads, Some(reads) if self.unchanged >= reads)
+    }
+
+    /// Returns the number of reads in a row the consensus did not change for, counted only with
+    /// `stop_when_stable`.
+    pub fn num_unchanged(&self) -> usize {
+        self.unchanged
+    }
+
+    /// Returns the number of reads added to the graph.
+    pub fn num_sequences(&self) -> usize {
+        self.graph.num_sequences()
+    }
+
+    /// Returns the number of reads skipped since the stream converged.
+    pub fn num_skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Returns the graph of the reads added so far, e.g. for their multiple sequence alignment.
+    pub fn graph(&self) -> &PoaGraph {
+        &self.graph
+    }
+
+    /// Returns the graph of the reads added so far, ending the stream.
+    pub fn into_graph(self) -> PoaGraph {
+        self.graph
+    }
+}
+
+/// A consensus of reads added until it stopped changing, with the number of reads that took.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StableConsensus {
+    /// the consensus
+    pub consensus: Vec<u8>,
+    /// the number of non-empty reads aligned and added, in input order
+    pub reads_added: usize,
+    /// whether the consensus stopped changing for the reads asked for, so that any later reads
+    /// were left out
+    pub converged: bool,
+}
+
+/// Generates the consensus of reads added in order until it did not change for `stable_reads`
+/// reads in a row, leaving the rest of the reads out.
+///
+/// Empty reads and trailing null terminators are ignored, as by `poa_consensus_oriented`.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `consensus_max_length` - the maximum length of the consensus, or 0 for no maximum
+/// * `params` - the alignment and scoring parameters
+/// * `stable_reads` - the number of reads in a row the consensus must not change for, or 0 to add
+///   every read
+///
+/// # Returns
+/// * returns the consensus, the number of reads added and whether it converged
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{poa_consensus_until_stable, PoaParams};
+///
+///     let seqs = vec!["AATGCCCGTT"; 50];
+///     let stable = poa_consensus_until_stable(&seqs, 0, &PoaParams::new(1, 5, -4, -3, -1), 3);
+///
+///     assert_eq!(stable.consensus, b"AATGCCCGTT".to_vec());
+///     assert_eq!(stable.reads_added, 4);
+///     assert!(stable.converged);
+/// ```
+pub fn poa_consensus_until_stable<T: AsRef<[u8]>>(seqs: &[T], consensus_max_length: usize, params: &PoaParams, stable_reads: usize) -> StableConsensus {
+    let mut stream = StreamingConsensus::new(params).stop_when_stable(stable_reads);
+    for seq in seqs {
+        let seq = seq.as_ref();
+        let seq = seq.strip_suffix(b"\0").unwrap_or(seq);
+        if !seq.is_empty() && !stream.add_sequence(seq) {
+            break;
+        }
+    }
+
+    let converged = stream.is_converged();
+    let reads_added = stream.num_sequences();
+    let mut consensus = stream.current_consensus().to_vec();
+    if consensus_max_length > 0 {
+        consensus.truncate(consensus_max_length);
+    }
+    StableConsensus { consensus, reads_added, converged }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streaming_consensus() {
+        let params = PoaParams::new(1, 5, -4, -3, -1);
+        let seqs = ["AATGCCCGTT", "AATGCCGTT", "AATGCCCGAT", "AATGCCCGTT", "AAGCCCGTT"];
+
+        // without a stability window every read is added, and snapshots follow the graph
+        let mut stream = StreamingConsensus::new(&params);
+        let mut graph = PoaGraph::new(&params);
+        for seq in seqs.iter() {
+            assert!(stream.add_sequence(seq.as_bytes()));
+            graph.add_sequence(seq.as_bytes());
+            assert_eq!(stream.current_consensus(), &graph.consensus()[..]);
+        }
+        assert!(!stream.is_converged());
+        assert_eq!((stream.num_sequences(), stream.num_skipped(), stream.num_unchanged()), (5, 0, 0));
+        assert_eq!(stream.into_graph().consensus(), graph.consensus());
+
+        // the window restarts whenever the consensus changes
+        let seqs = ["AATG", "AATGCCGTT", "AATGCCCGTT", "AATGCCCGTT", "AATGCCCGTT", "AATGCCCGTT", "AATGCCCGTT", "AATG"];
+        let mut snapshots = StreamingConsensus::new(&params);
+        let mut stream = StreamingConsensus::new(&params).stop_when_stable(2);
+        let (mut previous, mut unchanged) = (None, 0);
+        for seq in seqs.iter() {
+            let converged = stream.is_converged();
+            assert_eq!(stream.add_sequence(seq.as_bytes()), !converged);
+            snapshots.add_sequence(seq.as_bytes());
+            if !converged {
+                let consensus = snapshots.current_consensus().to_vec();
+                unchanged = if previous.as_ref() == Some(&consensus) { unchanged + 1 } else { 0 };
+                previous = Some(consensus);
+                assert_eq!(stream.num_unchanged(), unchanged);
+                assert_eq!(stream.current_consensus(), &previous.as_ref().unwrap()[..]);
+            }
+        }
+        assert!(stream.is_converged());
+        assert_eq!(stream.graph().num_sequences() + stream.num_skipped(), seqs.len());
+        assert!(stream.num_skipped() >= 1);
+
+        // empty reads are neither added nor count as reads leaving the consensus unchanged
+        let mut stream = StreamingConsensus::new(&params).stop_when_stable(1);
+        assert!(stream.add_sequence(b"AATGCCCGTT"));
+        for _ in 0..3 {
+            assert!(!stream.add_sequence(b""));
+        }
+        assert!(!stream.is_converged());
+        assert_eq!((stream.num_sequences(), stream.num_unchanged(), stream.num_skipped()), (1, 0, 0));
+
+        let empty: [&str; 0] = [];
+        let stable = poa_consensus_until_stable(&empty, 0, &params, 3);
+        assert_eq!(stable, StableConsensus { consensus: vec![], reads_added: 0, converged: false });
+        let stable = poa_consensus_until_stable(&["AATGCCCGTT\0", "", "AATGCCCGTT", "AATGCCCGTT"], 4, &params, 0);
+        assert_eq!(stable, StableConsensus { consensus: b"AATG".to_vec(), reads_added: 3, converged: false });
+    }
+}
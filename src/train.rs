@@ -0,0 +1,240 @@
+//! Choosing alignment and scoring parameters from read groups with known true sequences.
+
+use std::collections::HashMap;
+
+use crate::align::{align, identity};
+use crate::{PoaGraph, PoaParams};
+
+/// A group of reads of one molecule, together with the true sequence of that molecule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrainingGroup {
+    /// the reads, without null terminators
+    pub reads: Vec<Vec<u8>>,
+    /// the true sequence the consensus of the reads should reproduce
+    pub truth: Vec<u8>,
+}
+
+/// The candidate values of each parameter.
+///
+/// Combinations with a positive gap score, which the alignment engine rejects, are never tried.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParameterGrid {
+    /// the candidate alignment modes: 0 = local, 1 = global, 2 = gapped
+    pub alignment_types: Vec<i32>,
+    /// the candidate match scores
+    pub match_scores: Vec<i32>,
+    /// the candidate mismatch scores
+    pub mismatch_scores: Vec<i32>,
+    /// the candidate gap open scores
+    pub gap_opens: Vec<i32>,
+    /// the candidate gap extend scores
+    pub gap_extends: Vec<i32>,
+}
+
+impl Default for ParameterGrid {
+    fn default() -> ParameterGrid {
+        ParameterGrid {
+            alignment_types: vec![0, 1, 2],
+            match_scores: vec![1, 2, 3, 5],
+            mismatch_scores: vec![-1, -2, -4, -6],
+            gap_opens: vec![-2, -3, -4, -6, -8],
+            gap_extends: vec![-1, -2, -4, -6],
+        }
+    }
+}
+
+impl ParameterGrid {
+    fn dimension(&self, d: usize) -> &[i32] {
+        match d {
+            0 => &self.alignment_types,
+            1 => &self.match_scores,
+            2 => &self.mismatch_scores,
+            3 => &self.gap_opens,
+            _ => &self.gap_extends,
+        }
+    }
+}
+
+/// How the parameter space is searched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParameterSearch {
+    /// evaluate every combination of the grid
+    Grid,
+    /// starting from `start`, repeatedly optimize one parameter at a time over its grid values
+    /// while the others are held fixed, until a round changes nothing or `max_rounds` is reached
+    CoordinateDescent {
+        /// the parameters to start from
+        start: PoaParams,
+        /// the maximum number of rounds over all five parameters
+        max_rounds: usize,
+    },
+}
+
+/// The outcome of `train_params`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrainedParams {
+    /// the best parameters found; their `Display` form can be stored and parsed back as a preset
+    pub params: PoaParams,
+    /// the mean identity of the consensuses to the true sequences with `params`
+    pub accuracy: f64,
+    /// the number of parameter sets evaluated
+    pub evaluated: usize,
+}
+
+/// Returns the mean identity of the consensus of each group to its true sequence, or 0 without groups.
+///
+/// Empty reads are left out; a group without reads has an empty consensus.
+pub fn consensus_accuracy(groups: &[TrainingGroup], params: &PoaParams) -> f64 {
+    if groups.is_empty() {
+        return 0.0;
+    }
+
+    let mut graph = PoaGraph::new(params);
+    let total: f64 = groups
+        .iter()
+        .map(|group| {
+            graph.clear();
+            for read in group.reads.iter().filter(|read| !read.is_empty()) {
+                graph.add_sequence(read);
+            }
+            identity(&align(&graph.consensus(), &group.truth))
+        })
+        .sum();
+
+    total / groups.len() as f64
+}
+
+fn from_values(values: [i32; 5]) -> PoaParams {
+    PoaParams::new(values[0], values[1], values[2], values[3], values[4])
+}
+
+fn values(params: &PoaParams) -> [i32; 5] {
+    [params.alignment_type, params.match_score, params.mismatch_score, params.gap_open, params.gap_extend]
+}
+
+/// Searches for the parameters whose consensuses best reproduce the true sequences of the groups.
+/// # Arguments
+///
+/// * `groups` - the read groups with their true sequences
+/// * `grid` - the candidate values of each parameter
+/// * `search` - whether to try every combination or to optimize one parameter at a time
+///
+/// # Returns
+/// * returns the parameters with the highest `consensus_accuracy`, the first one evaluated among equals
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::{train_params, ParameterGrid, ParameterSearch, PoaParams, TrainingGroup};
+///
+///     let groups = vec![TrainingGroup {
+///         reads: vec![b"AATGCCCGTT".to_vec(), b"AATGCCGTT".to_vec(), b"AATGCCCGTT".to_vec()],
+///         truth: b"AATGCCCGTT".to_vec(),
+///     }];
+///     let search = ParameterSearch::CoordinateDescent { start: PoaParams::new(1, 5, -4, -3, -1), max_rounds: 3 };
+///     let trained = train_params(&groups, &ParameterGrid::default(), search);
+///
+///     assert_eq!(trained.accuracy, 1.0);
+///     let preset: PoaParams = trained.params.to_string().parse().unwrap();
+///     assert_eq!(preset, trained.params);
+/// ```
+pub fn train_params(groups: &[TrainingGroup], grid: &ParameterGrid, search: ParameterSearch) -> TrainedParams {
+    let mut accuracies: HashMap<[i32; 5], f64> = HashMap::new();
+    let mut best: Option<([i32; 5], f64)> = None;
+
+    let mut evaluate = |candidate: [i32; 5], best: &mut Option<([i32; 5], f64)>| {
+        if candidate[3] > 0 || candidate[4] > 0 {
+            return;
+        }
+        let accuracy = *accuracies
+            .entry(candidate)
+            .or_insert_with(|| consensus_accuracy(groups, &from_values(candidate)));
+        if !matches!(best, Some((_, best_accuracy)) if *best_accuracy >= accuracy) {
+            *best = Some((candidate, accuracy));
+        }
+    };
+
+    match search {
+        ParameterSearch::Grid => {
+            for &alignment_type in &grid.alignment_types {
+                for &match_score in &grid.match_scores {
+                    for &mismatch_score in &grid.mismatch_scores {
+                        for &gap_open in &grid.gap_opens {
+                            for &gap_extend in &grid.gap_extends {
+                                evaluate([alignment_type, match_score, mismatch_score, gap_open, gap_extend], &mut best);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        ParameterSearch::CoordinateDescent { start, max_rounds } => {
+            evaluate(values(&start), &mut best);
+            for _ in 0..max_rounds {
+                let before = best.map(|(candidate, _)| candidate);
+                for d in 0..5 {
+                    for &value in grid.dimension(d) {
+                        if let Some((mut candidate, _)) = best {
+                            candidate[d] = value;
+                            evaluate(candidate, &mut best);
+                        }
+                    }
+                }
+                if best.map(|(candidate, _)| candidate) == before {
+                    break;
+                }
+            }
+        }
+    }
+
+    let evaluated = accuracies.len();
+    let (params, accuracy) = match best {
+        Some((candidate, accuracy)) => (from_values(candidate), accuracy),
+        None => (PoaParams::new(1, 5, -4, -3, -1), 0.0),
+    };
+    TrainedParams { params, accuracy, evaluated }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn groups() -> Vec<TrainingGroup> {
+        let reads = ["ATTGCCCGTT", "AATGCCGTT", "AATGCCCGAT", "AACGCCCGTC", "AGTGCTCGTT", "AATGCTCGTT"];
+        vec![TrainingGroup { reads: reads.iter().map(|read| read.bytes().collect()).collect(), truth: b"AATGCCCGTT".to_vec() }]
+    }
+
+    #[test]
+    fn test_consensus_accuracy() {
+        assert_eq!(consensus_accuracy(&groups(), &PoaParams::new(1, 5, -4, -3, -1)), 1.0);
+        assert_eq!(consensus_accuracy(&[], &PoaParams::new(1, 5, -4, -3, -1)), 0.0);
+    }
+
+    #[test]
+    fn test_grid_search() {
+        let grid = ParameterGrid {
+            alignment_types: vec![1],
+            match_scores: vec![1, 5],
+            mismatch_scores: vec![-4],
+            gap_opens: vec![-3, 2],
+            gap_extends: vec![-1],
+        };
+        let trained = train_params(&groups(), &grid, ParameterSearch::Grid);
+
+        // the combinations with a positive gap open score are skipped
+        assert_eq!(trained.evaluated, 2);
+        assert_eq!(trained.accuracy, 1.0);
+        assert_eq!(trained.params, PoaParams::new(1, 1, -4, -3, -1));
+    }
+
+    #[test]
+    fn test_coordinate_descent_keeps_best_start() {
+        let start = PoaParams::new(1, 5, -4, -3, -1);
+        let search = ParameterSearch::CoordinateDescent { start, max_rounds: 2 };
+        let trained = train_params(&groups(), &ParameterGrid::default(), search);
+
+        assert_eq!(trained.params, start);
+        assert_eq!(trained.accuracy, 1.0);
+        assert!(trained.evaluated < 20);
+    }
+}
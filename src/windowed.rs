@@ -0,0 +1,339 @@
+//! Consensus of very long reads window by window, as racon polishes contigs.
+//!
+//! Aligning whole reads of tens of kilobases to a graph takes time and memory quadratic in their
+//! length. Instead, one read is taken as the backbone and every other read is anchored on it by
+//! the k-mers the two share once each; the backbone is cut into windows overlapping their
+//! neighbours, each window is the consensus of the backbone window and the parts of the reads the
+//! anchors place in it, and the window consensuses are stitched at the middle of their overlaps.
+
+use std::collections::HashMap;
+
+use crate::align::{align, AlignOp};
+use crate::seq::reverse_complement;
+use crate::{PoaGraph, PoaParams};
+
+/// The settings of `poa_consensus_windowed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowedOptions {
+    /// the number of backbone bases of each window
+    pub window_length: usize,
+    /// the number of backbone bases each window extends into each of its neighbours
+    pub overlap: usize,
+    /// the length of the k-mers anchoring reads on the backbone
+    pub kmer_length: usize,
+    /// the index of the backbone read, or `None` for the longest read
+    pub backbone: Option<usize>,
+    /// the alignment and scoring parameters of the window consensus
+    pub params: PoaParams,
+}
+
+impl Default for WindowedOptions {
+    /// Returns windows of 500 bases overlapping by 50, anchored by 15-mers on the longest read,
+    /// with semi-global alignment, as the fragments at read ends do not span their window.
+    fn default() -> WindowedOptions {
+        WindowedOptions { window_length: 500, overlap: 50, kmer_length: 15, backbone: None, params: PoaParams::new(2, 5, -4, -8, -6) }
+    }
+}
+
+impl WindowedOptions {
+    /// Sets the number of backbone bases of each window.
+    pub fn window_length(mut self, window_length: usize) -> WindowedOptions {
+        self.window_length = window_length;
+        self
+    }
+
+    /// Sets the number of backbone bases each window extends into each of its neighbours.
+    pub fn overlap(mut self, overlap: usize) -> WindowedOptions {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Sets the length of the k-mers anchoring reads on the backbone.
+    pub fn kmer_length(mut self, kmer_length: usize) -> WindowedOptions {
+        self.kmer_length = kmer_length;
+        self
+    }
+
+    /// Sets the index of the backbone read in the input.
+    pub fn backbone(mut self, backbone: usize) -> WindowedOptions {
+        self.backbone = Some(backbone);
+        self
+    }
+
+    /// Sets the alignment and scoring parameters of the window consensus.
+    pub fn params(mut self, params: PoaParams) -> WindowedOptions {
+        self.params = params;
+        self
+    }
+}
+
+// the number of anchors a read needs to be placed on the backbone, so a chance match of a k-mer
+// does not place it
+const MIN_ANCHORS: usize = 3;
+// the number of window bases on each side of a fragment it may be aligned to beyond its placement
+const FRAGMENT_SLACK: usize = 20;
+
+// the position of each k-mer occurring once in seq
+fn unique_kmers(seq: &[u8], k: usize) -> HashMap<&[u8], usize> {
+    let mut positions: HashMap<&[u8], Option<usize>> = HashMap::new();
+    for (i, kmer) in seq.windows(k).enumerate() {
+        positions.entry(kmer).and_modify(|position| *position = None).or_insert(Some(i));
+    }
+    positions.into_iter().filter_map(|(kmer, position)| position.map(|position| (kmer, position))).collect()
+}
+
+// the longest chain of (backbone, read) positions of k-mers unique in both, increasing in both
+fn anchor_chain(backbone_kmers: &HashMap<&[u8], usize>, read: &[u8], k: usize) -> Vec<(usize, usize)> {
+    let mut anchors: Vec<(usize, usize)> =
+        unique_kmers(read, k).into_iter().filter_map(|(kmer, j)| backbone_kmers.get(kmer).map(|&i| (i, j))).collect();
+    anchors.sort_unstable();
+
+    // the longest increasing subsequence of the read positions, tails[n] ending the least one of
+    // length n + 1
+    let mut tails: Vec<usize> = vec![];
+    let mut previous: Vec<Option<usize>> = vec![None; anchors.len()];
+    for (a, &(_, j)) in anchors.iter().enumerate() {
+        let length = tails.partition_point(|&t| anchors[t].1 < j);
+        if length > 0 {
+            previous[a] = Some(tails[length - 1]);
+        }
+        if length == tails.len() {
+            tails.push(a);
+        } else {
+            tails[length] = a;
+        }
+    }
+
+    let mut chain = vec![];
+    let mut next = tails.last().copied();
+    while let Some(a) = next {
+        chain.push(anchors[a]);
+        next = previous[a];
+    }
+    chain.reverse();
+    chain
+}
+
+// a read oriented along the backbone, with the chain of anchors placing it
+struct AnchoredRead {
+    seq: Vec<u8>,
+    chain: Vec<(usize, usize)>,
+}
+
+impl AnchoredRead {
+    // the read oriented as it shares the longer chain of anchors with the backbone, if that chain
+    // is long enough
+    fn new(read: &[u8], backbone_kmers: &HashMap<&[u8], usize>, k: usize) -> Option<AnchoredRead> {
+        let forward = anchor_chain(backbone_kmers, read, k);
+        let reverse_read = reverse_complement(read);
+        let reverse = anchor_chain(backbone_kmers, &reverse_read, k);
+        let read = if reverse.len() > forward.len() {
+            AnchoredRead { seq: reverse_read, chain: reverse }
+        } else {
+            AnchoredRead { seq: read.to_vec(), chain: forward }
+        };
+        if read.chain.len() >= MIN_ANCHORS { Some(read) } else { None }
+    }
+
+    // the read position of backbone position t, interpolated between the anchors around it, or
+    // along the diagonal of the first or last anchor beyond them
+    fn position(&self, t: usize) -> usize {
+        let after = self.chain.partition_point(|&(i, _)| i < t);
+        let q = match (after.checked_sub(1).map(|a| self.chain[a]), self.chain.get(after)) {
+            (Some((i0, j0)), Some(&(i1, j1))) => (j0 + (t - i0) * (j1 - j0) / (i1 - i0)) as isize,
+            (None, Some(&(i, j))) | (Some((i, j)), None) => j as isize + t as isize - i as isize,
+            (None, None) => unreachable!("anchored reads have anchors"),
+        };
+        q.clamp(0, self.seq.len() as isize) as usize
+    }
+
+    // the part of the read placed in [start, end) of the backbone, with the backbone position of
+    // its first base
+    fn fragment(&self, start: usize, end: usize) -> (usize, &[u8]) {
+        let (i, j) = self.chain[0];
+        let read_start = (i as isize - j as isize).max(0) as usize;
+        (read_start.max(start), &self.seq[self.position(start)..self.position(end)])
+    }
+}
+
+// appends the consensus of a window to that of the windows before it, cutting both at the middle
+// of the alignment of the 2 * overlap bases they share
+fn stitch(consensus: &mut Vec<u8>, window: &[u8], overlap: usize) {
+    let tail_start = consensus.len() - consensus.len().min(2 * overlap);
+    let head = &window[..window.len().min(2 * overlap)];
+    let middle = (consensus.len() - tail_start) / 2;
+
+    let (mut a, mut b) = (0, 0);
+    for op in align(&consensus[tail_start..], head) {
+        if a == middle {
+            break;
+        }
+        match op {
+            AlignOp::Match | AlignOp::Mismatch => {
+                a += 1;
+                b += 1;
+            }
+            AlignOp::Insertion => a += 1,
+            AlignOp::Deletion => b += 1,
+        }
+    }
+    consensus.truncate(tail_start + a);
+    consensus.extend_from_slice(&window[b..]);
+}
+
+/// Generates the consensus of very long reads window by window, so time and memory grow with the
+/// read length rather than its square.
+///
+/// The backbone read is cut into windows of `window_length` bases, each extended by `overlap`
+/// bases into its neighbours. Every other read is oriented and placed on the backbone by the
+/// longest chain of k-mers it shares with it, each occurring once in both, and reads sharing
+/// fewer than 3 are left out. Each window is the consensus of the backbone window, added first,
+/// and the parts of the reads placed in it, each aligned around its placement as by
+/// `PoaGraph::add_sequence_anchored`; consecutive windows are stitched at the middle of the
+/// alignment of their overlap. The consensus therefore spans the backbone, along its strand.
+/// Empty reads and trailing null terminators are ignored, as by `poa_consensus_with_matrix`.
+/// # Arguments
+///
+/// * `seqs` - the reads, with or without null terminators
+/// * `options` - the windows, anchors, backbone and consensus parameters
+///
+/// # Returns
+/// * returns the consensus, empty if there are no reads
+///
+/// # Panics
+/// Panics if `options.backbone` is not less than `seqs.len()`.
+///
+/// # Examples
+///
+/// ```
+///     use rust_spoa::simulate::{random_sequence, ReadSimulator};
+///     use rust_spoa::{poa_consensus_windowed, WindowedOptions};
+///
+///     let truth = random_sequence(2000, 4);
+///     let reads = ReadSimulator::new(7).error_rates(0.02, 0.01, 0.01).reads(&truth, 10);
+///     let consensus = poa_consensus_windowed(&reads, &WindowedOptions::default().window_length(400).backbone(0));
+///
+///     assert_eq!(consensus, truth);
+/// ```
+pub fn poa_consensus_windowed<T: AsRef<[u8]>>(seqs: &[T], options: &WindowedOptions) -> Vec<u8> {
+    let reads: Vec<(usize, &[u8])> = seqs
+        .iter()
+        .map(|seq| {
+            let seq = seq.as_ref();
+            seq.strip_suffix(b"\0").unwrap_or(seq)
+        })
+        .enumerate()
+        .filter(|(_, seq)| !seq.is_empty())
+        .collect();
+    let backbone_index = match options.backbone {
+        Some(backbone) => {
+            assert!(backbone < seqs.len(), "backbone {} is not one of the {} reads", backbone, seqs.len());
+            backbone
+        }
+        None => match reads.iter().max_by_key(|&&(i, seq)| (seq.len(), std::cmp::Reverse(i))) {
+            Some(&(i, _)) => i,
+            None => return vec![],
+        },
+    };
+    let backbone = match reads.iter().find(|&&(i, _)| i == backbone_index) {
+        Some(&(_, backbone)) => backbone,
+        None => return vec![],
+    };
+
+    let k = options.kmer_length.max(1);
+    let backbone_kmers = unique_kmers(backbone, k);
+    let anchored: Vec<AnchoredRead> = reads
+        .iter()
+        .filter(|&&(i, _)| i != backbone_index)
+        .filter_map(|&(_, read)| AnchoredRead::new(read, &backbone_kmers, k))
+        .collect();
+
+    let window_length = options.window_length.max(1);
+    let mut consensus = Vec::with_capacity(backbone.len());
+    let mut graph = PoaGraph::new(&options.params);
+    for window_start in (0..backbone.len()).step_by(window_length) {
+        let start = window_start.saturating_sub(options.overlap);
+        let end = (window_start + window_length + options.overlap).min(backbone.len());
+
+        graph.clear();
+        graph.add_sequence(&backbone[start..end]);
+        for read in &anchored {
+            // fragments are aligned only around their placement, as semi-global alignment would
+            // rather let a fragment ending inside the window overhang its end
+            let (fragment_start, fragment) = read.fragment(start, end);
+            if !fragment.is_empty() {
+                graph.add_sequence_anchored(fragment, fragment_start - start, FRAGMENT_SLACK);
+            }
+        }
+        let window = graph.consensus();
+        if window_start == 0 {
+            consensus = window;
+        } else {
+            stitch(&mut consensus, &window, options.overlap);
+        }
+    }
+    consensus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::align::identity;
+    use crate::simulate::{random_sequence, LengthDistribution, ReadSimulator};
+
+    #[test]
+    fn test_anchor_chain() {
+        let backbone = b"ACGTTGCAAGGCTTAGC";
+        let kmers = unique_kmers(backbone, 4);
+        // every 4-mer of the backbone occurs once
+        assert_eq!(kmers.len(), backbone.len() - 3);
+        assert!(unique_kmers(b"AAAAA", 2).is_empty());
+
+        // a read of the backbone with a base deleted, and a 4-mer of the backbone out of order
+        let chain = anchor_chain(&kmers, b"ACGTTGAAGGCTTAGCGCAA", 4);
+        assert_eq!(&chain[..3], &[(0, 0), (1, 1), (2, 2)]);
+        assert!(chain.windows(2).all(|pair| pair[0].0 < pair[1].0 && pair[0].1 < pair[1].1));
+        assert_eq!(chain.last(), Some(&(13, 12)));
+
+        let read = AnchoredRead { seq: b"TTACGTTGAAGG".to_vec(), chain: vec![(0, 2), (4, 6)] };
+        assert_eq!((read.position(0), read.position(2), read.position(10), read.position(100)), (2, 4, 12, 12));
+        assert_eq!(read.fragment(0, 4), (0, &b"ACGT"[..]));
+        let read = AnchoredRead { seq: b"GTTG".to_vec(), chain: vec![(2, 0)] };
+        assert_eq!(read.fragment(0, 4), (2, &b"GT"[..]));
+    }
+
+    #[test]
+    fn test_stitch() {
+        let mut consensus = b"AAAACCCCGG".to_vec();
+        // the window repeats the last 4 bases with an error in the half that is cut away
+        stitch(&mut consensus, b"CAGGTTTT", 2);
+        assert_eq!(consensus, b"AAAACCCCGGTTTT".to_vec());
+
+        let mut consensus = b"ACGT".to_vec();
+        stitch(&mut consensus, b"TTGCA", 0);
+        assert_eq!(consensus, b"ACGTTTGCA".to_vec());
+    }
+
+    #[test]
+    fn test_windowed_consensus() {
+        let truth = random_sequence(3000, 11);
+        let mut simulator = ReadSimulator::new(3).error_rates(0.03, 0.02, 0.02).reverse_fraction(0.5);
+        let mut reads: Vec<Vec<u8>> = (0..11).map(|_| simulator.simulate(&truth).seq).collect();
+        let mut simulator = simulator.length(LengthDistribution::Fixed(1200));
+        reads.extend((0..4).map(|_| simulator.simulate(&truth).seq));
+        reads.push(vec![]);
+        reads.push(random_sequence(500, 12));
+
+        for options in [WindowedOptions::default().window_length(300), WindowedOptions::default().window_length(700).overlap(0)].iter() {
+            let consensus = poa_consensus_windowed(&reads, options);
+            let forward = identity(&align(&consensus, &truth));
+            let reverse = identity(&align(&consensus, &reverse_complement(&truth)));
+            assert!(forward.max(reverse) > 0.995, "identity {} {} with {:?}", forward, reverse, options);
+        }
+
+        assert!(poa_consensus_windowed(&Vec::<Vec<u8>>::new(), &WindowedOptions::default()).is_empty());
+        assert!(poa_consensus_windowed(&["", "ACGT"], &WindowedOptions::default().backbone(0)).is_empty());
+        // reads without anchors leave the backbone as it is
+        assert_eq!(poa_consensus_windowed(&["ACGTTGCA\0", "GGGGGGG"], &WindowedOptions::default()), b"ACGTTGCA".to_vec());
+    }
+}